@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use prost::Message as _;
+
+use eloquentlog_console_api::model::message::proto::NewMessage;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = NewMessage::decode(data);
+});
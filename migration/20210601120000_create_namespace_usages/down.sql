@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS namespace_usages_namespace_id_date_idx;
+
+DROP TABLE IF EXISTS namespace_usages;
+DROP SEQUENCE IF EXISTS namespace_usages_id_seq;
@@ -0,0 +1,25 @@
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE namespace_usages_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+CREATE TABLE namespace_usages (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('namespace_usages_id_seq'),
+  namespace_id BIGINT REFERENCES namespaces (id) MATCH FULL NOT NULL,
+  date DATE NOT NULL,
+  messages_count BIGINT NOT NULL DEFAULT 0,
+  bytes_count BIGINT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE namespace_usages_id_seq OWNED BY namespace_usages.id;
+
+CREATE UNIQUE INDEX namespace_usages_namespace_id_date_idx ON
+  namespace_usages(namespace_id, date);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS waitlist_entries_email_idx;
+
+DROP TABLE IF EXISTS waitlist_entries;
+DROP SEQUENCE IF EXISTS waitlist_entries_id_seq;
@@ -0,0 +1,22 @@
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE waitlist_entries_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+CREATE TABLE waitlist_entries (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('waitlist_entries_id_seq'),
+  email CHARACTER VARYING(128) NOT NULL,
+  confirmed_at TIMESTAMP WITHOUT TIME ZONE NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE waitlist_entries_id_seq OWNED BY waitlist_entries.id;
+
+CREATE UNIQUE INDEX waitlist_entries_email_idx ON waitlist_entries(email);
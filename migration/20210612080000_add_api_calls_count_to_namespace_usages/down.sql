@@ -0,0 +1 @@
+ALTER TABLE namespace_usages DROP COLUMN api_calls_count;
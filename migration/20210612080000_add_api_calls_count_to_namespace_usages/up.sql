@@ -0,0 +1,2 @@
+ALTER TABLE namespace_usages ADD COLUMN api_calls_count BIGINT NOT NULL
+  DEFAULT 0;
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS usage_records_namespace_id_month_idx;
+
+DROP TABLE IF EXISTS usage_records;
+DROP SEQUENCE IF EXISTS usage_records_id_seq;
@@ -0,0 +1,26 @@
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE usage_records_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+CREATE TABLE usage_records (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('usage_records_id_seq'),
+  namespace_id BIGINT REFERENCES namespaces (id) MATCH FULL NOT NULL,
+  month DATE NOT NULL,
+  api_calls_count BIGINT NOT NULL DEFAULT 0,
+  messages_count BIGINT NOT NULL DEFAULT 0,
+  bytes_count BIGINT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE usage_records_id_seq OWNED BY usage_records.id;
+
+CREATE UNIQUE INDEX usage_records_namespace_id_month_idx ON
+  usage_records(namespace_id, month);
@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS identities;
+DROP SEQUENCE IF EXISTS identities_id_seq;
+
+DROP TYPE IF EXISTS e_identity_provider;
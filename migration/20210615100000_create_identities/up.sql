@@ -0,0 +1,30 @@
+CREATE TYPE e_identity_provider AS ENUM (
+  'github',
+  'google'
+);
+
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE identities_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+CREATE TABLE identities (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('identities_id_seq'),
+  user_id BIGINT REFERENCES users (id) MATCH FULL NOT NULL,
+  provider e_identity_provider NOT NULL,
+  uid CHARACTER VARYING(255) NOT NULL,
+  email CHARACTER VARYING(128) NOT NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE identities_id_seq OWNED BY identities.id;
+
+CREATE UNIQUE INDEX identities_provider_uid_idx ON identities(provider, uid);
+CREATE INDEX identities_user_id_idx ON identities(user_id);
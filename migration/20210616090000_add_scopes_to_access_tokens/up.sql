@@ -0,0 +1,6 @@
+ALTER TABLE access_tokens ADD COLUMN scopes CHARACTER VARYING(32)[] NOT NULL
+  DEFAULT '{}';
+
+-- existing tokens keep working as before
+UPDATE access_tokens SET scopes =
+  ARRAY['ingest:write', 'messages:read', 'namespace:admin'];
@@ -0,0 +1,11 @@
+-- a value can't be removed from an enum type
+DELETE FROM identities WHERE provider = 'ldap';
+
+CREATE TYPE e_identity_provider_old AS ENUM (
+  'github',
+  'google'
+);
+ALTER TABLE identities ALTER COLUMN provider TYPE e_identity_provider_old
+  USING provider::text::e_identity_provider_old;
+DROP TYPE e_identity_provider;
+ALTER TYPE e_identity_provider_old RENAME TO e_identity_provider;
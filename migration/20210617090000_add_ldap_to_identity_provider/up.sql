@@ -0,0 +1,10 @@
+-- ALTER TYPE ... ADD VALUE can't run in a transaction (PostgreSQL < 12)
+CREATE TYPE e_identity_provider_new AS ENUM (
+  'github',
+  'google',
+  'ldap'
+);
+ALTER TABLE identities ALTER COLUMN provider TYPE e_identity_provider_new
+  USING provider::text::e_identity_provider_new;
+DROP TYPE e_identity_provider;
+ALTER TYPE e_identity_provider_new RENAME TO e_identity_provider;
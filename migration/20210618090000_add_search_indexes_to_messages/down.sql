@@ -0,0 +1,9 @@
+DROP INDEX IF EXISTS messages_content_unaccent_trgm_idx;
+DROP INDEX IF EXISTS messages_title_unaccent_trgm_idx;
+DROP INDEX IF EXISTS messages_content_trgm_idx;
+DROP INDEX IF EXISTS messages_title_trgm_idx;
+
+DROP FUNCTION IF EXISTS f_unaccent(text);
+
+DROP EXTENSION IF EXISTS unaccent;
+DROP EXTENSION IF EXISTS pg_trgm;
@@ -0,0 +1,20 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE EXTENSION IF NOT EXISTS unaccent;
+
+-- unaccent() is only STABLE (it depends on the dictionary), so wrap it as an
+-- IMMUTABLE function with the fixed dictionary to use it in indexes
+CREATE OR REPLACE FUNCTION f_unaccent(text) RETURNS text AS $$
+  SELECT public.unaccent('public.unaccent'::regdictionary, $1)
+$$ LANGUAGE sql IMMUTABLE PARALLEL SAFE STRICT;
+
+-- case sensitive (LIKE) and insensitive (ILIKE)
+CREATE INDEX messages_title_trgm_idx ON messages
+  USING gin (title gin_trgm_ops);
+CREATE INDEX messages_content_trgm_idx ON messages
+  USING gin (coalesce(content, '') gin_trgm_ops);
+
+-- unaccent (case insensitive)
+CREATE INDEX messages_title_unaccent_trgm_idx ON messages
+  USING gin (lower(f_unaccent(title)) gin_trgm_ops);
+CREATE INDEX messages_content_unaccent_trgm_idx ON messages
+  USING gin (lower(f_unaccent(coalesce(content, ''))) gin_trgm_ops);
@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS recent_views_user_id_viewed_at_idx;
+DROP INDEX IF EXISTS recent_views_user_id_kind_target_idx;
+
+DROP TABLE IF EXISTS recent_views;
+DROP SEQUENCE IF EXISTS recent_views_id_seq;
+
+DROP TYPE IF EXISTS e_recent_view_kind;
@@ -0,0 +1,32 @@
+CREATE TYPE e_recent_view_kind AS ENUM (
+  'message',
+  'search'
+);
+
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE recent_views_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+CREATE TABLE recent_views (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('recent_views_id_seq'),
+  user_id BIGINT REFERENCES users (id) MATCH FULL NOT NULL,
+  kind e_recent_view_kind NOT NULL,
+  target CHARACTER VARYING(512) NOT NULL,
+  viewed_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE recent_views_id_seq OWNED BY recent_views.id;
+
+CREATE UNIQUE INDEX recent_views_user_id_kind_target_idx ON
+  recent_views(user_id, kind, target);
+CREATE INDEX recent_views_user_id_viewed_at_idx ON
+  recent_views(user_id, viewed_at DESC);
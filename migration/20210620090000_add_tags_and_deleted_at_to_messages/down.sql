@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS messages_deleted_at_idx;
+DROP INDEX IF EXISTS messages_incident_id_idx;
+DROP INDEX IF EXISTS messages_tags_idx;
+
+ALTER TABLE messages DROP COLUMN deleted_at;
+ALTER TABLE messages DROP COLUMN incident_id;
+ALTER TABLE messages DROP COLUMN tags;
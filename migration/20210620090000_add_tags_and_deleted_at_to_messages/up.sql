@@ -0,0 +1,9 @@
+ALTER TABLE messages ADD COLUMN tags CHARACTER VARYING(64)[] NOT NULL
+  DEFAULT '{}';
+-- incidents are managed outside of this database for now
+ALTER TABLE messages ADD COLUMN incident_id BIGINT NULL;
+ALTER TABLE messages ADD COLUMN deleted_at TIMESTAMP WITHOUT TIME ZONE NULL;
+
+CREATE INDEX messages_tags_idx ON messages USING GIN (tags);
+CREATE INDEX messages_incident_id_idx ON messages(incident_id);
+CREATE INDEX messages_deleted_at_idx ON messages(deleted_at);
@@ -0,0 +1,8 @@
+DROP INDEX IF EXISTS bulk_operations_user_id_idx;
+DROP INDEX IF EXISTS bulk_operations_uuid_idx;
+
+DROP TABLE IF EXISTS bulk_operations;
+DROP SEQUENCE IF EXISTS bulk_operations_id_seq;
+
+DROP TYPE IF EXISTS e_bulk_operation_state;
+DROP TYPE IF EXISTS e_bulk_operation_action;
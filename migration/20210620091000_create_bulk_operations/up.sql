@@ -0,0 +1,44 @@
+CREATE TYPE e_bulk_operation_action AS ENUM (
+  'add_tag',
+  'assign_incident',
+  'soft_delete'
+);
+
+CREATE TYPE e_bulk_operation_state AS ENUM (
+  'pending',
+  'running',
+  'finished',
+  'failed'
+);
+
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE bulk_operations_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+CREATE TABLE bulk_operations (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('bulk_operations_id_seq'),
+  uuid UUID NOT NULL DEFAULT uuid_generate_v4(),
+  user_id BIGINT REFERENCES users (id) MATCH FULL NOT NULL,
+  stream_id BIGINT REFERENCES streams (id) MATCH FULL NOT NULL,
+  action e_bulk_operation_action NOT NULL,
+  argument CHARACTER VARYING(128) NULL,
+  filter TEXT NOT NULL,
+  state e_bulk_operation_state NOT NULL DEFAULT 'pending',
+  matched_count BIGINT NOT NULL DEFAULT 0,
+  affected_count BIGINT NOT NULL DEFAULT 0,
+  finished_at TIMESTAMP WITHOUT TIME ZONE NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE bulk_operations_id_seq OWNED BY bulk_operations.id;
+
+CREATE UNIQUE INDEX bulk_operations_uuid_idx ON bulk_operations(uuid);
+CREATE INDEX bulk_operations_user_id_idx ON bulk_operations(user_id);
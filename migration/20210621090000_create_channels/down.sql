@@ -0,0 +1,7 @@
+DROP INDEX IF EXISTS channels_namespace_id_idx;
+DROP INDEX IF EXISTS channels_uuid_idx;
+
+DROP TABLE IF EXISTS channels;
+DROP SEQUENCE IF EXISTS channels_id_seq;
+
+DROP TYPE IF EXISTS e_channel_kind;
@@ -0,0 +1,32 @@
+CREATE TYPE e_channel_kind AS ENUM (
+  'webhook',
+  'slack'
+);
+
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE channels_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+CREATE TABLE channels (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('channels_id_seq'),
+  uuid UUID NOT NULL DEFAULT uuid_generate_v4(),
+  namespace_id BIGINT REFERENCES namespaces (id) MATCH FULL NOT NULL,
+  kind e_channel_kind NOT NULL,
+  name CHARACTER VARYING(64) NOT NULL,
+  url CHARACTER VARYING(2048) NOT NULL,
+  template TEXT NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE channels_id_seq OWNED BY channels.id;
+
+CREATE UNIQUE INDEX channels_uuid_idx ON channels(uuid);
+CREATE INDEX channels_namespace_id_idx ON channels(namespace_id);
@@ -0,0 +1,2 @@
+-- in seconds (0 means every alert is delivered)
+ALTER TABLE channels ADD COLUMN rollup_window INTEGER NOT NULL DEFAULT 0;
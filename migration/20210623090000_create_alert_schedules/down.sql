@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS alert_schedules_user_id_idx;
+
+DROP TABLE IF EXISTS alert_schedules;
+DROP SEQUENCE IF EXISTS alert_schedules_id_seq;
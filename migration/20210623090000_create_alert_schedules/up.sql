@@ -0,0 +1,26 @@
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE alert_schedules_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- quiet hours are the local time in the timezone (IANA name), and may wrap
+-- around midnight (e.g. 22:00 - 07:00)
+CREATE TABLE alert_schedules (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('alert_schedules_id_seq'),
+  user_id BIGINT REFERENCES users (id) MATCH FULL NOT NULL,
+  timezone CHARACTER VARYING(64) NOT NULL DEFAULT 'UTC',
+  quiet_hours_start TIME WITHOUT TIME ZONE NULL,
+  quiet_hours_end TIME WITHOUT TIME ZONE NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE alert_schedules_id_seq OWNED BY alert_schedules.id;
+
+CREATE UNIQUE INDEX alert_schedules_user_id_idx ON alert_schedules(user_id);
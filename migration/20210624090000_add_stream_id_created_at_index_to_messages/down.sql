@@ -0,0 +1 @@
+DROP INDEX IF EXISTS messages_stream_id_created_at_idx;
@@ -0,0 +1,3 @@
+-- for aggregations by the age of messages (e.g. a retention preview)
+CREATE INDEX messages_stream_id_created_at_idx ON
+  messages(stream_id, created_at);
@@ -0,0 +1,7 @@
+-- the tokens can't be restored from the digests (pending ones are expired)
+UPDATE user_emails SET
+  identification_token = NULL,
+  identification_token_expires_at = NULL,
+  identification_token_granted_at = NULL
+WHERE
+  identification_state = 'pending';
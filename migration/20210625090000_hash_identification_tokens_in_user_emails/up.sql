@@ -0,0 +1,5 @@
+-- only the SHA-256 digest (hex) of an identification token is stored
+UPDATE user_emails SET
+  identification_token = encode(sha256(identification_token::bytea), 'hex')
+WHERE
+  identification_token IS NOT NULL AND identification_token <> '';
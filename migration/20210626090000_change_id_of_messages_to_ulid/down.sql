@@ -0,0 +1,23 @@
+-- the ids are numbered again in the order of ULIDs
+CREATE SEQUENCE messages_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+ALTER TABLE messages ADD COLUMN serial_id BIGINT NULL;
+UPDATE messages SET serial_id = m.n FROM (
+  SELECT id, row_number() OVER (ORDER BY id) AS n FROM messages
+) m WHERE messages.id = m.id;
+SELECT setval('messages_id_seq', (SELECT count(*) FROM messages) + 1, false);
+
+ALTER TABLE messages DROP CONSTRAINT messages_pkey;
+ALTER TABLE messages DROP COLUMN id;
+ALTER TABLE messages RENAME COLUMN serial_id TO id;
+ALTER TABLE messages ALTER COLUMN id SET NOT NULL;
+ALTER TABLE messages ALTER COLUMN id SET DEFAULT nextval('messages_id_seq');
+ALTER TABLE messages ADD PRIMARY KEY (id);
+
+ALTER SEQUENCE messages_id_seq OWNED BY messages.id;
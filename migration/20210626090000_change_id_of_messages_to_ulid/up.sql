@@ -0,0 +1,25 @@
+-- encodes the value into the number of characters of Crockford's Base32
+CREATE FUNCTION f_crockford_base32(value BIGINT, length INTEGER)
+  RETURNS TEXT AS $$
+DECLARE
+  alphabet CONSTANT TEXT := '0123456789ABCDEFGHJKMNPQRSTVWXYZ';
+  result TEXT := '';
+BEGIN
+  FOR i IN 1..length LOOP
+    result := substr(alphabet, (value % 32)::INTEGER + 1, 1) || result;
+    value := value / 32;
+  END LOOP;
+  RETURN result;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+-- ULIDs are generated on the application side. The existing ids are kept in
+-- the random part of them (after the time of creation).
+ALTER TABLE messages ALTER COLUMN id DROP DEFAULT;
+ALTER TABLE messages ALTER COLUMN id TYPE CHARACTER VARYING(26) COLLATE "C"
+  USING f_crockford_base32(
+    (extract(epoch FROM created_at) * 1000)::BIGINT, 10) ||
+    f_crockford_base32(id, 16);
+DROP SEQUENCE IF EXISTS messages_id_seq;
+
+DROP FUNCTION f_crockford_base32(BIGINT, INTEGER);
@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS stream_tokens_namespace_id_idx;
+DROP INDEX IF EXISTS stream_tokens_token_idx;
+DROP INDEX IF EXISTS stream_tokens_uuid_idx;
+
+DROP TABLE IF EXISTS stream_tokens;
+DROP SEQUENCE IF EXISTS stream_tokens_id_seq;
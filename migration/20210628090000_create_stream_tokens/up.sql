@@ -0,0 +1,28 @@
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE stream_tokens_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- token is the hash (SHA-256) of the raw value, which is shown only once
+CREATE TABLE stream_tokens (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('stream_tokens_id_seq'),
+  uuid UUID NOT NULL DEFAULT uuid_generate_v4(),
+  namespace_id BIGINT REFERENCES namespaces (id) MATCH FULL NOT NULL,
+  name CHARACTER VARYING(64) NOT NULL,
+  token CHARACTER VARYING(64) NOT NULL,
+  revoked_at TIMESTAMP WITHOUT TIME ZONE NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE stream_tokens_id_seq OWNED BY stream_tokens.id;
+
+CREATE UNIQUE INDEX stream_tokens_uuid_idx ON stream_tokens(uuid);
+CREATE UNIQUE INDEX stream_tokens_token_idx ON stream_tokens(token);
+CREATE INDEX stream_tokens_namespace_id_idx ON stream_tokens(namespace_id);
@@ -0,0 +1,2 @@
+ALTER TABLE messages DROP COLUMN resolved_at;
+ALTER TABLE messages DROP COLUMN acknowledged_at;
@@ -0,0 +1,4 @@
+-- annotations by users (see PATCH /v1/message/.../hset/...)
+ALTER TABLE messages ADD COLUMN acknowledged_at TIMESTAMP WITHOUT TIME ZONE
+  NULL;
+ALTER TABLE messages ADD COLUMN resolved_at TIMESTAMP WITHOUT TIME ZONE NULL;
@@ -0,0 +1,13 @@
+ALTER TABLE namespaces DROP COLUMN deduplicates_messages;
+
+-- moves the bodies back into the messages which refer to them
+UPDATE messages m SET content = b.content
+  FROM message_bodies b
+  WHERE m.body_id = b.id;
+
+DROP INDEX messages_body_id_idx;
+ALTER TABLE messages DROP COLUMN body_id;
+
+DROP INDEX message_bodies_references_count_idx;
+DROP INDEX message_bodies_digest_idx;
+DROP TABLE message_bodies;
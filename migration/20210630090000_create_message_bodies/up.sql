@@ -0,0 +1,36 @@
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE message_bodies_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- content-addressed bodies of messages in namespaces which deduplicate them.
+-- digest is the hash (SHA-256) of the normalized content, and
+-- references_count is the number of messages which refer to the body.
+CREATE TABLE message_bodies (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('message_bodies_id_seq'),
+  digest CHARACTER VARYING(64) NOT NULL,
+  content TEXT NOT NULL,
+  references_count BIGINT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE message_bodies_id_seq OWNED BY message_bodies.id;
+
+CREATE UNIQUE INDEX message_bodies_digest_idx ON message_bodies(digest);
+CREATE INDEX message_bodies_references_count_idx ON message_bodies(
+  references_count);
+
+-- content is NULL if the message refers to a body
+ALTER TABLE messages ADD COLUMN body_id BIGINT
+  REFERENCES message_bodies (id) NULL;
+CREATE INDEX messages_body_id_idx ON messages(body_id);
+
+ALTER TABLE namespaces ADD COLUMN deduplicates_messages BOOLEAN NOT NULL
+  DEFAULT FALSE;
@@ -0,0 +1,5 @@
+DROP INDEX messages_created_at_idx;
+
+DROP INDEX message_counts_hour_idx;
+DROP INDEX message_counts_namespace_id_hour_level_idx;
+DROP TABLE message_counts;
@@ -0,0 +1,30 @@
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE message_counts_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- hourly counts of messages by the level in a namespace, which are
+-- aggregated by RollupMessageCounts job (for stats over long ranges)
+CREATE TABLE message_counts (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('message_counts_id_seq'),
+  namespace_id BIGINT REFERENCES namespaces (id) MATCH FULL NOT NULL,
+  hour TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+  level e_log_level NOT NULL,
+  count BIGINT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE message_counts_id_seq OWNED BY message_counts.id;
+
+CREATE UNIQUE INDEX message_counts_namespace_id_hour_level_idx ON
+  message_counts(namespace_id, hour, level);
+CREATE INDEX message_counts_hour_idx ON message_counts(hour);
+
+CREATE INDEX messages_created_at_idx ON messages(created_at);
@@ -0,0 +1,3 @@
+-- the number of the identical messages coalesced into the message within the
+-- deduplication window of ingestion
+ALTER TABLE messages ADD COLUMN occurrences_count INTEGER NOT NULL DEFAULT 1;
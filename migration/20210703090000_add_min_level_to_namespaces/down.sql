@@ -0,0 +1,3 @@
+ALTER TABLE namespace_usages DROP COLUMN dropped_messages_count;
+
+ALTER TABLE namespaces DROP COLUMN min_level;
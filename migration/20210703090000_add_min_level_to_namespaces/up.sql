@@ -0,0 +1,6 @@
+-- messages below the level are counted but not saved on ingestion (NULL
+-- accepts all of the levels)
+ALTER TABLE namespaces ADD COLUMN min_level e_log_level NULL;
+
+ALTER TABLE namespace_usages
+  ADD COLUMN dropped_messages_count BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+DROP INDEX messages_trace_id_idx;
+
+ALTER TABLE messages DROP COLUMN span_id;
+ALTER TABLE messages DROP COLUMN trace_id;
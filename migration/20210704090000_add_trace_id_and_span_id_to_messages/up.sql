@@ -0,0 +1,7 @@
+-- the trace context of the span where the message is logged (see
+-- `tracecontext`), for the logs of a distributed trace
+ALTER TABLE messages ADD COLUMN trace_id VARCHAR(32) NULL;
+ALTER TABLE messages ADD COLUMN span_id VARCHAR(16) NULL;
+
+CREATE INDEX messages_trace_id_idx ON messages(trace_id)
+  WHERE trace_id IS NOT NULL;
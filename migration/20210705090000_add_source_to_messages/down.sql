@@ -0,0 +1,9 @@
+DROP INDEX messages_stream_id_service_idx;
+
+ALTER TABLE stream_tokens DROP COLUMN environment;
+ALTER TABLE stream_tokens DROP COLUMN service;
+ALTER TABLE stream_tokens DROP COLUMN hostname;
+
+ALTER TABLE messages DROP COLUMN environment;
+ALTER TABLE messages DROP COLUMN service;
+ALTER TABLE messages DROP COLUMN hostname;
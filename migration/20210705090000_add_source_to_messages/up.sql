@@ -0,0 +1,12 @@
+-- where the message comes from. They are given on ingestion, or they are
+-- the defaults of the stream token.
+ALTER TABLE messages ADD COLUMN hostname VARCHAR(255) NULL;
+ALTER TABLE messages ADD COLUMN service VARCHAR(128) NULL;
+ALTER TABLE messages ADD COLUMN environment VARCHAR(64) NULL;
+
+ALTER TABLE stream_tokens ADD COLUMN hostname VARCHAR(255) NULL;
+ALTER TABLE stream_tokens ADD COLUMN service VARCHAR(128) NULL;
+ALTER TABLE stream_tokens ADD COLUMN environment VARCHAR(64) NULL;
+
+CREATE INDEX messages_stream_id_service_idx ON messages(stream_id, service)
+  WHERE service IS NOT NULL;
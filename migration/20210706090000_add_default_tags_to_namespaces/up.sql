@@ -0,0 +1,4 @@
+-- merged into the tags of every message ingested into the namespace (e.g.
+-- `environment=staging`)
+ALTER TABLE namespaces ADD COLUMN default_tags CHARACTER VARYING(64)[] NOT NULL
+  DEFAULT '{}';
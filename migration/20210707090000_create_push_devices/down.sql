@@ -0,0 +1,13 @@
+DROP INDEX IF EXISTS push_deliveries_push_device_id_idx;
+
+DROP TABLE IF EXISTS push_deliveries;
+DROP SEQUENCE IF EXISTS push_deliveries_id_seq;
+
+DROP INDEX IF EXISTS push_devices_user_id_idx;
+DROP INDEX IF EXISTS push_devices_platform_token_idx;
+DROP INDEX IF EXISTS push_devices_uuid_idx;
+
+DROP TABLE IF EXISTS push_devices;
+DROP SEQUENCE IF EXISTS push_devices_id_seq;
+
+DROP TYPE IF EXISTS e_push_platform;
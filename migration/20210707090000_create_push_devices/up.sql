@@ -0,0 +1,63 @@
+CREATE TYPE e_push_platform AS ENUM (
+  'apns',
+  'fcm'
+);
+
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE push_devices_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- token is the one issued by the platform for the app on the device
+CREATE TABLE push_devices (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('push_devices_id_seq'),
+  uuid UUID NOT NULL DEFAULT uuid_generate_v4(),
+  user_id BIGINT REFERENCES users (id) MATCH FULL NOT NULL,
+  platform e_push_platform NOT NULL,
+  token CHARACTER VARYING(4096) NOT NULL,
+  name CHARACTER VARYING(64) NOT NULL,
+  last_delivered_at TIMESTAMP WITHOUT TIME ZONE NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE push_devices_id_seq OWNED BY push_devices.id;
+
+CREATE UNIQUE INDEX push_devices_uuid_idx ON push_devices(uuid);
+CREATE UNIQUE INDEX push_devices_platform_token_idx
+  ON push_devices(platform, token);
+CREATE INDEX push_devices_user_id_idx ON push_devices(user_id);
+
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE push_deliveries_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- receipts of notifications, provider_id is the one returned by the platform
+-- (apns-id or the message id of FCM)
+CREATE TABLE push_deliveries (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('push_deliveries_id_seq'),
+  push_device_id BIGINT REFERENCES push_devices (id) ON DELETE CASCADE
+    NOT NULL,
+  message_id CHARACTER VARYING(26) NOT NULL,
+  delivered BOOLEAN NOT NULL,
+  provider_id CHARACTER VARYING(255) NULL,
+  error CHARACTER VARYING(255) NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE push_deliveries_id_seq OWNED BY push_deliveries.id;
+
+CREATE INDEX push_deliveries_push_device_id_idx
+  ON push_deliveries(push_device_id);
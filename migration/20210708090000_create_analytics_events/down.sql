@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS analytics_events_name_occurred_at_idx;
+
+DROP TABLE IF EXISTS analytics_events;
+DROP SEQUENCE IF EXISTS analytics_events_id_seq;
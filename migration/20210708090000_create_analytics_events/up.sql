@@ -0,0 +1,25 @@
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE analytics_events_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- anonymized usage events; actor is a salted hash of the user (if any), and
+-- occurred_at is truncated to the hour
+CREATE TABLE analytics_events (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('analytics_events_id_seq'),
+  name CHARACTER VARYING(64) NOT NULL,
+  actor CHARACTER VARYING(64) NULL,
+  properties TEXT NOT NULL DEFAULT '{}',
+  occurred_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE analytics_events_id_seq OWNED BY analytics_events.id;
+
+CREATE INDEX analytics_events_name_occurred_at_idx
+  ON analytics_events(name, occurred_at);
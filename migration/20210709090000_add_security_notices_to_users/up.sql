@@ -0,0 +1,3 @@
+-- the non-critical security notices (e.g. a new sign-in) are sent only if it's
+-- true. the critical ones are always sent (see `mailer::security`)
+ALTER TABLE users ADD COLUMN security_notices BOOLEAN NOT NULL DEFAULT TRUE;
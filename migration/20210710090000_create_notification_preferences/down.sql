@@ -0,0 +1,13 @@
+ALTER TABLE users ADD COLUMN security_notices BOOLEAN NOT NULL DEFAULT TRUE;
+
+UPDATE users SET security_notices = FALSE
+  FROM notification_preferences p
+  WHERE p.user_id = users.id AND p.event = 'security' AND p.channel = 'none';
+
+DROP INDEX IF EXISTS notification_preferences_user_id_event_idx;
+
+DROP TABLE IF EXISTS notification_preferences;
+DROP SEQUENCE IF EXISTS notification_preferences_id_seq;
+
+DROP TYPE IF EXISTS e_notification_channel;
+DROP TYPE IF EXISTS e_notification_event;
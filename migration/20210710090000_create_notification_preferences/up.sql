@@ -0,0 +1,47 @@
+CREATE TYPE e_notification_event AS ENUM (
+  'alert',
+  'quota',
+  'security'
+);
+
+CREATE TYPE e_notification_channel AS ENUM (
+  'email',
+  'webhook',
+  'none'
+);
+
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE notification_preferences_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- the channel of notifications on the event for the user (email if there is
+-- no row), webhook_url is only for webhook
+CREATE TABLE notification_preferences (
+  id BIGINT NOT NULL PRIMARY KEY
+    DEFAULT nextval('notification_preferences_id_seq'),
+  user_id BIGINT REFERENCES users (id) ON DELETE CASCADE NOT NULL,
+  event e_notification_event NOT NULL,
+  channel e_notification_channel NOT NULL,
+  webhook_url CHARACTER VARYING(2048) NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE notification_preferences_id_seq
+  OWNED BY notification_preferences.id;
+
+CREATE UNIQUE INDEX notification_preferences_user_id_event_idx
+  ON notification_preferences(user_id, event);
+
+-- the opt-out of security notices moves into the preferences
+INSERT INTO notification_preferences (user_id, event, channel)
+  SELECT id, 'security', 'none' FROM users WHERE security_notices = FALSE;
+
+ALTER TABLE users DROP COLUMN security_notices;
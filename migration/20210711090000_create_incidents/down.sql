@@ -0,0 +1,14 @@
+DROP INDEX IF EXISTS incident_messages_message_id_idx;
+DROP INDEX IF EXISTS incident_messages_incident_id_message_id_idx;
+
+DROP TABLE IF EXISTS incident_messages;
+DROP SEQUENCE IF EXISTS incident_messages_id_seq;
+
+DROP INDEX IF EXISTS incidents_namespace_id_rule_idx;
+DROP INDEX IF EXISTS incidents_namespace_id_idx;
+DROP INDEX IF EXISTS incidents_uuid_idx;
+
+DROP TABLE IF EXISTS incidents;
+DROP SEQUENCE IF EXISTS incidents_id_seq;
+
+DROP TYPE IF EXISTS e_incident_state;
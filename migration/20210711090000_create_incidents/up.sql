@@ -0,0 +1,63 @@
+CREATE TYPE e_incident_state AS ENUM (
+  'open',
+  'resolved'
+);
+
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE incidents_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- rule is the alert rule which has opened the incident (NULL if it's created
+-- via API), an open incident is reused for the alerts of the rule
+CREATE TABLE incidents (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('incidents_id_seq'),
+  uuid UUID NOT NULL DEFAULT uuid_generate_v4(),
+  namespace_id BIGINT REFERENCES namespaces (id) MATCH FULL NOT NULL,
+  title CHARACTER VARYING(255) NOT NULL,
+  state e_incident_state NOT NULL DEFAULT 'open',
+  rule CHARACTER VARYING(255) NULL,
+  resolved_at TIMESTAMP WITHOUT TIME ZONE NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc'),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE incidents_id_seq OWNED BY incidents.id;
+
+CREATE UNIQUE INDEX incidents_uuid_idx ON incidents(uuid);
+CREATE INDEX incidents_namespace_id_idx ON incidents(namespace_id);
+CREATE UNIQUE INDEX incidents_namespace_id_rule_idx
+  ON incidents(namespace_id, rule) WHERE state = 'open' AND rule IS NOT NULL;
+
+-- equivalent to use of SERIAL or BIGSERIAL
+CREATE SEQUENCE incident_messages_id_seq
+  START WITH 1
+  INCREMENT BY 1
+  NO MAXVALUE
+  NO MINVALUE
+  CACHE 1
+;
+
+-- messages attached to incidents (a message can be in some incidents),
+-- created_at is the time of the attachment
+CREATE TABLE incident_messages (
+  id BIGINT NOT NULL PRIMARY KEY DEFAULT nextval('incident_messages_id_seq'),
+  incident_id BIGINT REFERENCES incidents (id) ON DELETE CASCADE NOT NULL,
+  message_id CHARACTER VARYING(26) COLLATE "C"
+    REFERENCES messages (id) ON DELETE CASCADE NOT NULL,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+ALTER SEQUENCE incident_messages_id_seq OWNED BY incident_messages.id;
+
+CREATE UNIQUE INDEX incident_messages_incident_id_message_id_idx
+  ON incident_messages(incident_id, message_id);
+CREATE INDEX incident_messages_message_id_idx
+  ON incident_messages(message_id);
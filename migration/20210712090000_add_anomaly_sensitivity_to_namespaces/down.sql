@@ -0,0 +1,3 @@
+ALTER TABLE namespaces DROP COLUMN anomaly_sensitivity;
+
+DROP TYPE IF EXISTS e_anomaly_sensitivity;
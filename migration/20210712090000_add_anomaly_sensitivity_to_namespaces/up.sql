@@ -0,0 +1,11 @@
+CREATE TYPE e_anomaly_sensitivity AS ENUM (
+  'off',
+  'low',
+  'medium',
+  'high'
+);
+
+-- how significant a spike of errors must be to be alerted by DetectAnomalies
+-- job (off disables it)
+ALTER TABLE namespaces ADD COLUMN anomaly_sensitivity e_anomaly_sensitivity
+  NOT NULL DEFAULT 'medium';
@@ -0,0 +1,2 @@
+-- the status badge of the namespace can be shown without the signed token
+ALTER TABLE namespaces ADD COLUMN badge_public BOOLEAN NOT NULL DEFAULT FALSE;
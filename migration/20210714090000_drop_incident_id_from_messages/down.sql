@@ -0,0 +1,11 @@
+ALTER TABLE messages ADD COLUMN incident_id BIGINT NULL;
+
+CREATE INDEX messages_incident_id_idx ON messages(incident_id);
+
+-- the first incident of the message
+UPDATE messages m SET incident_id = im.incident_id
+  FROM (
+    SELECT message_id, MIN(incident_id) AS incident_id
+      FROM incident_messages GROUP BY message_id
+  ) im
+  WHERE im.message_id = m.id;
@@ -0,0 +1,12 @@
+-- messages are attached to incidents only via incident_messages (the ids of
+-- the ones which don't exist in the namespace are dropped)
+INSERT INTO incident_messages (incident_id, message_id)
+  SELECT m.incident_id, m.id FROM messages m
+    INNER JOIN streams s ON s.id = m.stream_id
+    INNER JOIN incidents i
+      ON i.id = m.incident_id AND i.namespace_id = s.namespace_id
+  ON CONFLICT (incident_id, message_id) DO NOTHING;
+
+DROP INDEX IF EXISTS messages_incident_id_idx;
+
+ALTER TABLE messages DROP COLUMN incident_id;
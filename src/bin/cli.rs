@@ -0,0 +1,438 @@
+//! A CLI for operators (serve, worker, migrate and management tasks).
+#![feature(rustc_private)]
+
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use dotenv::dotenv;
+use proctitle::set_title;
+use redis::Client;
+
+use eloquentlog_console_api::cli;
+use eloquentlog_console_api::clock::SystemClock;
+use eloquentlog_console_api::config::Config;
+use eloquentlog_console_api::db::establish_connection;
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::logger::{get_logger, get_stderr_logger};
+use eloquentlog_console_api::metadata::metadata;
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("eloquentlog-console-api")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Runs and manages the console API of Eloquentlog")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("serve").about("Runs the server"))
+        .subcommand(SubCommand::with_name("worker").about("Runs the worker"))
+        .subcommand(
+            SubCommand::with_name("migrate")
+                .about("Runs pending database migrations"),
+        )
+        .subcommand(
+            SubCommand::with_name("create-admin")
+                .about("Creates an activated user")
+                .arg(
+                    Arg::with_name("email")
+                        .long("email")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("username")
+                        .long("username")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .long("password")
+                        .takes_value(true)
+                        .help("Reads it from stdin if omitted"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("deprecations").about(
+                "Reports clients which use deprecated routes and fields",
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Writes a namespace as NDJSON into stdout")
+                .arg(
+                    Arg::with_name("namespace")
+                        .long("namespace")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The uuid of the namespace"),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .help("Skips messages before it (RFC 3339 or epoch)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Restores a NDJSON export in stdin as a new namespace"),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-archives")
+                .about("Verifies backup directories with their manifests")
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .takes_value(true)
+                        .help("BACKUP_DIRECTORY by default"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("deduplicate")
+                .about("Stores message bodies of a namespace only once")
+                .arg(
+                    Arg::with_name("namespace")
+                        .long("namespace")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The uuid of the namespace"),
+                )
+                .arg(
+                    Arg::with_name("disable")
+                        .long("disable")
+                        .help("Stores new messages as is again"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("enqueue-job")
+                .about("Enqueues a job into the default queue")
+                .arg(
+                    Arg::with_name("kind")
+                        .required(true)
+                        .help("e.g. FlushNamespaceUsages"),
+                )
+                .arg(Arg::with_name("args").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("seed")
+                .about("Populates a development database with sample data")
+                .arg(
+                    Arg::with_name("users")
+                        .long("users")
+                        .takes_value(true)
+                        .help("4 by default"),
+                )
+                .arg(
+                    Arg::with_name("namespaces")
+                        .long("namespaces")
+                        .takes_value(true)
+                        .help("2 by default"),
+                )
+                .arg(
+                    Arg::with_name("messages")
+                        .long("messages")
+                        .takes_value(true)
+                        .help("In total, 3000 by default"),
+                )
+                .arg(
+                    Arg::with_name("days")
+                        .long("days")
+                        .takes_value(true)
+                        .help("The time range of messages, 30 by default"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("metadata").about(
+                "Writes route and type metadata as JSON into stdout",
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Inspects the config")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("check")
+                        .about("Checks the config and the connections"),
+                ),
+        )
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
+
+fn read_password() -> String {
+    eprint!("password: ");
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line).is_err() {
+        exit_with("failed to read password");
+    }
+    line.trim_end_matches(&['\r', '\n'][..]).to_string()
+}
+
+fn create_admin(config: &Config, matches: &ArgMatches) {
+    let password = match matches.value_of("password") {
+        Some(v) => v.to_string(),
+        None => read_password(),
+    };
+    let conn = establish_connection(config);
+    let logger = get_logger(config);
+    match cli::admin::create(
+        matches.value_of("email").unwrap(),
+        matches.value_of("username").unwrap(),
+        &password,
+        config,
+        &conn,
+        &logger,
+    ) {
+        Ok(user) => println!("created: {}", user.uuid),
+        Err(e) => exit_with(&e),
+    }
+}
+
+fn deduplicate(config: &Config, matches: &ArgMatches) {
+    let conn = establish_connection(config);
+    let logger = get_logger(config);
+    match cli::namespace::deduplicate(
+        matches.value_of("namespace").unwrap(),
+        !matches.is_present("disable"),
+        &SystemClock,
+        &conn,
+        &logger,
+    ) {
+        Ok(n) if n.deduplicates_messages => println!("enabled: {}", n.uuid),
+        Ok(n) => println!("disabled: {}", n.uuid),
+        Err(e) => exit_with(&e),
+    }
+}
+
+fn enqueue_job(config: &Config, matches: &ArgMatches) {
+    let args: Vec<String> = matches
+        .values_of("args")
+        .map(|v| v.map(|s| s.to_string()).collect())
+        .unwrap_or_else(Vec::new);
+    let mut conn = Client::open(config.message_queue_connection_url().as_str())
+        .and_then(|c| c.get_connection())
+        .unwrap_or_else(|e| exit_with(&e.to_string()));
+    match cli::job::enqueue(matches.value_of("kind").unwrap(), args, &mut conn)
+    {
+        Ok(_) => println!("enqueued"),
+        Err(e) => exit_with(&e),
+    }
+}
+
+fn export(config: &Config, matches: &ArgMatches) {
+    let since = matches.value_of("since").map(|s| {
+        cli::backup::parse_since(s).unwrap_or_else(|e| exit_with(&e))
+    });
+    let conn = establish_connection(config);
+    // stdout is for the records
+    let logger = get_stderr_logger(config);
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    if let Err(e) = cli::backup::export(
+        matches.value_of("namespace").unwrap(),
+        since,
+        &mut out,
+        &conn,
+        &logger,
+    ) {
+        exit_with(&e);
+    }
+}
+
+fn import(config: &Config) {
+    let conn = establish_connection(config);
+    let logger = get_stderr_logger(config);
+    let stdin = io::stdin();
+    match cli::backup::import(stdin.lock(), &conn, &logger) {
+        Ok(namespace) => println!("imported: {}", namespace.uuid),
+        Err(e) => exit_with(&e),
+    }
+}
+
+fn verify_archives(config: &Config, matches: &ArgMatches) {
+    let root = matches.value_of("dir").unwrap_or(&config.backup_directory);
+    match cli::backup::verify(Path::new(root)) {
+        Ok((lines, valid)) => {
+            lines.iter().for_each(|l| println!("{}", l));
+            if !valid {
+                process::exit(1);
+            }
+        },
+        Err(e) => exit_with(&e),
+    }
+}
+
+fn seed(config: &Config, matches: &ArgMatches) {
+    let defaults = cli::seed::Options::default();
+    let number = |name: &str, default: usize| -> usize {
+        matches.value_of(name).map_or(default, |v| {
+            v.parse()
+                .unwrap_or_else(|_| exit_with(&format!("invalid {}", name)))
+        })
+    };
+    let options = cli::seed::Options {
+        users: number("users", defaults.users),
+        namespaces: number("namespaces", defaults.namespaces),
+        messages: number("messages", defaults.messages),
+        days: number("days", defaults.days as usize) as i64,
+    };
+    let conn = establish_connection(config);
+    match cli::seed::run(&options, config, &SystemClock, &conn) {
+        Ok(summary) => {
+            for user in &summary.users {
+                println!("user: {} ({})", user.email, user.uuid);
+            }
+            for namespace in &summary.namespaces {
+                println!("namespace: {} ({})", namespace.name, namespace.uuid);
+            }
+            println!("messages: {}", summary.messages);
+            println!("password: {}", factory::PASSWORD);
+        },
+        Err(e) => exit_with(&e),
+    }
+}
+
+fn report_deprecations(config: &Config) {
+    let mut conn = Client::open(config.session_store_connection_url().as_str())
+        .and_then(|c| c.get_connection())
+        .unwrap_or_else(|e| exit_with(&e.to_string()));
+    match cli::deprecation::report(&mut conn) {
+        Ok(lines) => lines.iter().for_each(|l| println!("{}", l)),
+        Err(e) => exit_with(&e),
+    }
+}
+
+fn print_metadata() {
+    match serde_json::to_string_pretty(&metadata()) {
+        Ok(s) => println!("{}", s),
+        Err(e) => exit_with(&e.to_string()),
+    }
+}
+
+fn check_config(name: &str) {
+    let config = match Config::from(name) {
+        Ok(c) => c,
+        Err(e) => {
+            println!("config: {}", name);
+            for error in e.errors {
+                println!("  {}", error);
+            }
+            process::exit(1);
+        },
+    };
+    println!("config: {} ok", name);
+
+    let mut failed = false;
+    for (target, result) in cli::config::check(&config) {
+        match result {
+            Ok(_) => println!("{}: ok", target),
+            Err(e) => {
+                failed = true;
+                println!("{}: {}", target, e);
+            },
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    set_title("eloquentlog: cli");
+    let name = cli::get_env();
+
+    dotenv().ok();
+    let matches = app().get_matches();
+
+    // it reports all of the invalid variables
+    if let ("config", Some(_)) = matches.subcommand() {
+        return check_config(name.as_str());
+    }
+    // it doesn't depend on the environment
+    if let ("metadata", Some(_)) = matches.subcommand() {
+        return print_metadata();
+    }
+
+    let config = Config::from(name.as_str())
+        .unwrap_or_else(|e| exit_with(&e.to_string()));
+    match matches.subcommand() {
+        ("serve", _) => {
+            set_title("eloquentlog: server");
+            cli::serve::run(config);
+        },
+        ("worker", _) => {
+            set_title("eloquentlog: worker");
+            cli::work::run(config);
+        },
+        ("migrate", _) => {
+            let conn = establish_connection(&config);
+            if let Err(e) = cli::migrate::run(&conn) {
+                exit_with(&e);
+            }
+        },
+        ("create-admin", Some(m)) => create_admin(&config, m),
+        ("deduplicate", Some(m)) => deduplicate(&config, m),
+        ("deprecations", _) => report_deprecations(&config),
+        ("export", Some(m)) => export(&config, m),
+        ("import", _) => import(&config),
+        ("verify-archives", Some(m)) => verify_archives(&config, m),
+        ("seed", Some(m)) => seed(&config, m),
+        ("enqueue-job", Some(m)) => enqueue_job(&config, m),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_app() {
+        let matches = app()
+            .get_matches_from_safe(vec![
+                "cli",
+                "enqueue-job",
+                "ApplyBulkOperation",
+                "a",
+                "b",
+            ])
+            .unwrap();
+        let (name, m) = matches.subcommand();
+        assert_eq!(name, "enqueue-job");
+
+        let m = m.unwrap();
+        assert_eq!(m.value_of("kind"), Some("ApplyBulkOperation"));
+        let args: Vec<&str> = m.values_of("args").unwrap().collect();
+        assert_eq!(args, vec!["a", "b"]);
+
+        assert!(app()
+            .get_matches_from_safe(vec!["cli", "create-admin"])
+            .is_err());
+
+        let matches = app()
+            .get_matches_from_safe(vec![
+                "cli",
+                "export",
+                "--namespace",
+                "uuid",
+                "--since",
+                "1622505600",
+            ])
+            .unwrap();
+        let (name, m) = matches.subcommand();
+        assert_eq!(name, "export");
+        assert_eq!(m.unwrap().value_of("since"), Some("1622505600"));
+
+        assert!(app().get_matches_from_safe(vec!["cli", "export"]).is_err());
+
+        let matches = app()
+            .get_matches_from_safe(vec!["cli", "seed", "--messages", "500"])
+            .unwrap();
+        let (name, m) = matches.subcommand();
+        assert_eq!(name, "seed");
+        assert_eq!(m.unwrap().value_of("messages"), Some("500"));
+    }
+}
@@ -0,0 +1,89 @@
+//! Creates an activated user without the signup (and the activation email).
+//!
+//! The user gets a default namespace with a stream like the one signed up.
+use diesel::pg::PgConnection;
+use diesel::result::Error;
+use rocket_contrib::json::Json;
+
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::model::Activatable;
+use crate::model::membership::{Membership, MembershipRole, NewMembership};
+use crate::model::namespace::{Namespace, NewNamespace};
+use crate::model::password_hash::PasswordHashParams;
+use crate::model::stream::{NewStream, Stream};
+use crate::model::user::{NewUser, User};
+use crate::model::user_email::{NewUserEmail, UserEmail};
+use crate::request::user::registration::UserRegistration;
+use crate::validation::email::{EmailPolicy, normalize};
+use crate::validation::user::Validator;
+
+pub fn create(
+    email: &str,
+    username: &str,
+    password: &str,
+    config: &Config,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Result<User, String> {
+    let params = &PasswordHashParams::from(config);
+    let policy = EmailPolicy::from(config);
+    let data = Json(UserRegistration {
+        email: normalize(email, &policy),
+        name: None,
+        username: username.to_string(),
+        password: password.to_string(),
+    });
+
+    let v = Validator::new(conn, &data, &policy, logger);
+    if let Err(errors) = v.validate() {
+        let messages: Vec<String> = errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.messages.join(", ")))
+            .collect();
+        return Err(messages.join("\n"));
+    }
+
+    conn.build_transaction()
+        .serializable()
+        .deferrable()
+        .read_write()
+        .run::<User, Error, _>(|| {
+            let mut u = NewUser::from(&data.0);
+            u.set_password(&data.password, params);
+            let user = User::insert(&u, conn, logger)
+                .ok_or(Error::RollbackTransaction)?;
+            let ue = NewUserEmail::from(&user);
+            UserEmail::insert(&ue, conn, logger)
+                .ok_or(Error::RollbackTransaction)?;
+
+            let ns = NewNamespace {
+                name: format!("{}'s default namespace", u.username),
+                description: None,
+                streams_count: 0,
+            };
+            let namespace = Namespace::insert(&ns, conn, logger)
+                .ok_or(Error::RollbackTransaction)?;
+            let s = NewStream {
+                namespace_id: namespace.id,
+                name: "main".to_string(),
+                description: None,
+            };
+            Stream::insert(&s, conn, logger)
+                .ok_or(Error::RollbackTransaction)?;
+            let m = NewMembership {
+                namespace_id: namespace.id,
+                user_id: user.id,
+                role: MembershipRole::PrimaryOwner,
+            };
+            Membership::insert(&m, conn, logger)
+                .ok_or(Error::RollbackTransaction)?;
+
+            user.activate(conn, logger).map_err(|e| {
+                error!(logger, "error: {}", e);
+                Error::RollbackTransaction
+            })?;
+            Ok(user)
+        })
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,126 @@
+//! Exports a namespace as NDJSON (into stdout) and imports it, for offsite
+//! backups by cron without pg_dump (see `service::namespace_backup`).
+//!
+//! `verify` checks the backup directories written by the export job with
+//! their manifests.
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime};
+use diesel::pg::PgConnection;
+
+use crate::logger::Logger;
+use crate::model::namespace::Namespace;
+use crate::service::namespace_backup::{NamespaceBackup, verify_all};
+
+/// Parses `--since` as RFC 3339 (e.g. `2021-06-01T00:00:00Z`) or seconds
+/// since the epoch.
+pub fn parse_since(s: &str) -> Result<NaiveDateTime, String> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.naive_utc());
+    }
+    match s.parse::<i64>() {
+        Ok(n) if n >= 0 => Ok(NaiveDateTime::from_timestamp(n, 0)),
+        _ => Err(format!("invalid timestamp: {}", s)),
+    }
+}
+
+/// Writes the namespace (the key) into the writer and returns the number of
+/// the lines.
+pub fn export<W: Write>(
+    namespace_key: &str,
+    since: Option<NaiveDateTime>,
+    writer: &mut W,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Result<usize, String> {
+    let namespace = Namespace::find_by_key(namespace_key, conn, logger)
+        .ok_or_else(|| format!("namespace not found: {}", namespace_key))?;
+    NamespaceBackup::new(conn, logger)
+        .export_to(&namespace, since, writer)
+        .map_err(|e| e.to_string())
+}
+
+/// Restores the backup in the reader as a new namespace.
+pub fn import<R: BufRead>(
+    reader: R,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Result<Namespace, String> {
+    NamespaceBackup::new(conn, logger)
+        .import_from(reader)
+        .map_err(|e| e.to_string())
+}
+
+/// Returns lines of `directory ok (N records)` or `directory error`, and
+/// whether all of them are valid.
+pub fn verify(root: &Path) -> Result<(Vec<String>, bool), String> {
+    let results = verify_all(root).map_err(|e| e.to_string())?;
+    let valid = results.iter().all(|(_, r)| r.is_ok());
+    let lines = results
+        .iter()
+        .map(|(dir, result)| match result {
+            Ok(n) => format!("{}: ok ({} records)", dir.display(), n),
+            Err(e) => format!("{}: {}", dir.display(), e),
+        })
+        .collect();
+    Ok((lines, valid))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+    use diesel::prelude::*;
+
+    use crate::model::namespace::{NewNamespace, namespaces};
+    use crate::model::test::run;
+
+    #[test]
+    fn test_parse_since() {
+        let t = Utc.ymd(2021, 6, 1).and_hms(0, 0, 0).naive_utc();
+        assert_eq!(parse_since("2021-06-01T00:00:00Z"), Ok(t));
+        assert_eq!(parse_since("2021-06-01T09:00:00+09:00"), Ok(t));
+        assert_eq!(parse_since("1622505600"), Ok(t));
+
+        assert!(parse_since("").is_err());
+        assert!(parse_since("-1").is_err());
+        assert!(parse_since("2021-06-01").is_err());
+    }
+
+    #[test]
+    fn test_export_unknown_namespace() {
+        run(|conn, _, logger| {
+            let mut out: Vec<u8> = vec![];
+            let key = "00000000-0000-0000-0000-000000000000";
+            assert!(export(key, None, &mut out, conn, logger).is_err());
+            assert!(out.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        run(|conn, _, logger| {
+            // by the sequence, as the imported one is (the fixture has id 1)
+            let ns = NewNamespace {
+                name: "piano".to_string(),
+
+                ..Default::default()
+            };
+            let namespace = Namespace::insert(&ns, conn, logger).unwrap();
+
+            let key = namespace.uuid.to_string();
+            let mut out: Vec<u8> = vec![];
+            assert_eq!(export(&key, None, &mut out, conn, logger), Ok(2));
+
+            // names are unique
+            diesel::update(namespaces::table.find(namespace.id))
+                .set(namespaces::name.eq("exported"))
+                .execute(conn)
+                .unwrap();
+            let imported = import(&out[..], conn, logger).unwrap();
+            assert_eq!(imported.name, namespace.name);
+        });
+    }
+}
@@ -0,0 +1,45 @@
+//! Checks the config and the connections to the backends.
+use diesel::Connection;
+use diesel::pg::PgConnection;
+use redis::Client;
+
+use crate::config::Config;
+
+fn check_database(url: &str) -> Result<(), String> {
+    PgConnection::establish(url)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn check_redis(url: &str) -> Result<(), String> {
+    let mut conn = Client::open(url)
+        .and_then(|c| c.get_connection())
+        .map_err(|e| e.to_string())?;
+    redis::cmd("PING")
+        .query::<String>(&mut conn)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Returns the results of the checks with their names.
+pub fn check(config: &Config) -> Vec<(&'static str, Result<(), String>)> {
+    let mut results = vec![(
+        "database",
+        check_database(&config.database_connection_url()),
+    )];
+    if !config.database_replica_url.is_empty() {
+        results.push((
+            "database replica",
+            check_database(&config.database_replica_connection_url()),
+        ));
+    }
+    results.push((
+        "message queue",
+        check_redis(&config.message_queue_connection_url()),
+    ));
+    results.push((
+        "session store",
+        check_redis(&config.session_store_connection_url()),
+    ));
+    results
+}
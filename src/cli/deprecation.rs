@@ -0,0 +1,65 @@
+//! Reports the clients which still use deprecated routes and fields.
+use chrono::NaiveDateTime;
+use redis::Connection;
+
+use crate::service::deprecation::{
+    DEPRECATIONS, Deprecation, DeprecationUsage, Usage, find_by_name,
+};
+
+fn format_usage(usage: &Usage, deprecation: Option<&Deprecation>) -> String {
+    let last_used_at = usage
+        .last_used_at
+        .map(|t| NaiveDateTime::from_timestamp(t, 0).to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let sunset = deprecation.and_then(|d| d.sunset).unwrap_or("-");
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        usage.name, usage.client, usage.count, last_used_at, sunset
+    )
+}
+
+/// Returns lines of `name client count last_used_at sunset` (tab separated).
+pub fn report(conn: &mut Connection) -> Result<Vec<String>, String> {
+    let usages = DeprecationUsage::new(conn)
+        .report(DEPRECATIONS)
+        .map_err(|e| e.to_string())?;
+    Ok(usages
+        .iter()
+        .map(|u| format_usage(u, find_by_name(DEPRECATIONS, &u.name)))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_usage() {
+        let usage = Usage {
+            name: "legacy-id".to_string(),
+            client: "personal:uuid".to_string(),
+            count: 3,
+            last_used_at: Some(1_561_447_541),
+        };
+        assert_eq!(
+            format_usage(&usage, None),
+            "legacy-id\tpersonal:uuid\t3\t2019-06-25 07:25:41\t-"
+        );
+
+        let d = Deprecation {
+            name: "legacy-id",
+            route: None,
+            since: "2021-06-27",
+            sunset: Some("2022-01-01"),
+            link: None,
+        };
+        let usage = Usage {
+            last_used_at: None,
+            ..usage
+        };
+        assert_eq!(
+            format_usage(&usage, Some(&d)),
+            "legacy-id\tpersonal:uuid\t3\t-\t2022-01-01"
+        );
+    }
+}
@@ -0,0 +1,41 @@
+//! Enqueues a job by hand (e.g. to flush usages or to retry a delivery).
+use fourche::queue::Queue;
+use redis::Connection;
+use serde_json::Value;
+
+use crate::job::{Job, JobKind};
+
+/// Parses the name of a job kind (e.g. `FlushNamespaceUsages`).
+pub fn parse_kind(name: &str) -> Option<JobKind> {
+    serde_json::from_value(Value::String(name.to_string())).ok()
+}
+
+pub fn enqueue(
+    name: &str,
+    args: Vec<String>,
+    conn: &mut Connection,
+) -> Result<(), String> {
+    let kind = parse_kind(name)
+        .ok_or_else(|| format!("unknown job kind: {}", name))?;
+    let job = Job::<String> { kind, args };
+    let mut queue = Queue::new("default", conn);
+    queue
+        .enqueue::<Job<String>>(job)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_kind() {
+        assert_eq!(
+            parse_kind("FlushNamespaceUsages"),
+            Some(JobKind::FlushNamespaceUsages)
+        );
+        assert_eq!(parse_kind("flush_namespace_usages"), None);
+        assert_eq!(parse_kind(""), None);
+    }
+}
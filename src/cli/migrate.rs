@@ -0,0 +1,14 @@
+//! Runs pending migrations.
+//!
+//! The migrations in `migration` are embedded at build time, so that the
+//! binary can migrate the database without diesel_cli.
+use std::io;
+
+use diesel::pg::PgConnection;
+
+embed_migrations!("migration");
+
+pub fn run(conn: &PgConnection) -> Result<(), String> {
+    embedded_migrations::run_with_output(conn, &mut io::stdout())
+        .map_err(|e| e.to_string())
+}
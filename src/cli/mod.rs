@@ -0,0 +1,25 @@
+//! Subcommands of the management CLI (see `src/bin/cli.rs`).
+//!
+//! The server and the worker binaries are thin wrappers of `serve` and
+//! `work`, so that every way to run them shares the same setup.
+pub mod admin;
+pub mod backup;
+pub mod config;
+pub mod deprecation;
+pub mod job;
+pub mod migrate;
+pub mod namespace;
+pub mod seed;
+pub mod serve;
+pub mod work;
+
+use std::env;
+
+/// Returns the name of the config by `ENV` (development by default).
+pub fn get_env() -> String {
+    match env::var("ENV") {
+        Ok(ref v) if v == &"test".to_string() => String::from("testing"),
+        Ok(v) => v.to_lowercase(),
+        Err(_) => String::from("development"),
+    }
+}
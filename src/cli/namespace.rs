@@ -0,0 +1,59 @@
+//! Changes settings of a namespace which are only for operators.
+use diesel::pg::PgConnection;
+
+use crate::clock::Clock;
+use crate::logger::Logger;
+use crate::model::namespace::Namespace;
+
+/// Enables (or disables) the deduplication of message bodies of the
+/// namespace (the key). See `model::message_body`.
+pub fn deduplicate(
+    namespace_key: &str,
+    enabled: bool,
+    clock: &dyn Clock,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Result<Namespace, String> {
+    let namespace = Namespace::find_by_key(namespace_key, conn, logger)
+        .ok_or_else(|| format!("namespace not found: {}", namespace_key))?;
+    let now = clock.now().naive_utc();
+    namespace
+        .set_deduplicates_messages(enabled, now, conn, logger)
+        .ok_or_else(|| "failed to update namespace".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use diesel::prelude::*;
+
+    use crate::clock::SystemClock;
+    use crate::model::namespace::namespaces;
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_deduplicate() {
+        run(|conn, _, logger| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+            assert!(!namespace.deduplicates_messages);
+
+            let key = namespace.uuid.to_string();
+            let result =
+                deduplicate(&key, true, &SystemClock, conn, logger).unwrap();
+            assert!(result.deduplicates_messages);
+
+            let result =
+                deduplicate(&key, false, &SystemClock, conn, logger).unwrap();
+            assert!(!result.deduplicates_messages);
+
+            let key = "00000000-0000-0000-0000-000000000000";
+            let result = deduplicate(key, true, &SystemClock, conn, logger);
+            assert!(result.is_err());
+        });
+    }
+}
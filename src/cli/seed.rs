@@ -0,0 +1,206 @@
+//! Seeds a development database with realistic data (see `factory`).
+//!
+//! Users get the factory password, and each namespace is owned by one of
+//! them with the others as members. Messages are spread over the levels (by
+//! their usual shares) and over the last days in the streams of the
+//! namespaces. It's refused in production.
+use chrono::Duration;
+use diesel::pg::PgConnection;
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::model::message::LogLevel;
+use crate::model::namespace::Namespace;
+use crate::model::user::User;
+use crate::factory;
+
+const NAMES: &[&str] = &[
+    "Alice Johnson",
+    "Bob Martin",
+    "Carol Wu",
+    "Dave Becker",
+    "Erin Novak",
+    "Frank Ito",
+];
+
+const NAMESPACES: &[&str] = &["web shop", "payment service", "mobile app"];
+
+const STREAMS: &[&str] = &["api", "worker", "web"];
+
+// level, share (percent), title and content
+const SAMPLES: &[(LogLevel, usize, &str, &str)] = &[
+    (LogLevel::Debug, 20, "Cache miss", "key: session:4f2a, ttl: 3600s"),
+    (LogLevel::Information, 48, "Request completed", "GET /orders 200 42ms"),
+    (LogLevel::Information, 2, "Deployed", "version v1.4.2 (9c1e0d7)"),
+    (LogLevel::Warning, 18, "Slow query", "SELECT * FROM orders took 3.2s"),
+    (LogLevel::Error, 10, "Connection timeout", "<db> is not reachable"),
+    (LogLevel::Critical, 2, "Out of memory", "worker was killed (OOM)"),
+];
+
+/// Options
+#[derive(Debug)]
+pub struct Options {
+    pub users: usize,
+    pub namespaces: usize,
+    /// In total
+    pub messages: usize,
+    /// Messages are created over the days until now
+    pub days: i64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            users: 4,
+            namespaces: 2,
+            messages: 3000,
+            days: 30,
+        }
+    }
+}
+
+/// What has been seeded.
+#[derive(Debug)]
+pub struct Summary {
+    pub users: Vec<User>,
+    pub namespaces: Vec<Namespace>,
+    pub messages: usize,
+}
+
+// splits the count by the shares of the samples (the remainder goes into the
+// first one)
+fn split(count: usize) -> Vec<usize> {
+    let total: usize = SAMPLES.iter().map(|s| s.1).sum();
+    let mut counts: Vec<usize> =
+        SAMPLES.iter().map(|s| count * s.1 / total).collect();
+    counts[0] += count - counts.iter().sum::<usize>();
+    counts
+}
+
+pub fn run(
+    options: &Options,
+    config: &Config,
+    clock: &dyn Clock,
+    conn: &PgConnection,
+) -> Result<Summary, String> {
+    if config.env_name == "production" {
+        return Err("seed is not allowed in production".to_string());
+    }
+    if options.users == 0 || options.namespaces == 0 {
+        return Err("users and namespaces must be at least 1".to_string());
+    }
+    if options.days < 1 {
+        return Err("days must be at least 1".to_string());
+    }
+
+    let users: Vec<User> = (0..options.users)
+        .map(|i| factory::user().name(NAMES[i % NAMES.len()]).insert(conn))
+        .collect();
+
+    let namespaces: Vec<Namespace> = (0..options.namespaces)
+        .map(|i| {
+            let owner = &users[i % users.len()];
+            users
+                .iter()
+                .filter(|u| u.id != owner.id)
+                .fold(
+                    factory::namespace()
+                        .description(NAMESPACES[i % NAMESPACES.len()])
+                        .with_owner(owner),
+                    |f, u| f.with_member(u),
+                )
+                .insert(conn)
+        })
+        .collect();
+
+    let streams: Vec<_> = namespaces
+        .iter()
+        .flat_map(|ns| {
+            STREAMS
+                .iter()
+                .map(move |name| factory::stream().namespace(ns).name(name))
+        })
+        .map(|f| f.insert(conn))
+        .collect();
+
+    // the messages of each stream
+    let to = clock.now();
+    let from = to - Duration::days(options.days);
+    let mut messages = 0;
+    for (i, stream) in streams.iter().enumerate() {
+        let count = options.messages / streams.len()
+            + usize::from(i < options.messages % streams.len());
+        let agent = &users[i % users.len()];
+        for (sample, n) in SAMPLES.iter().zip(split(count)) {
+            let (level, _, title, content) = sample;
+            messages += factory::message()
+                .stream(stream)
+                .agent(agent)
+                .level(level.clone())
+                .title(title)
+                .content(content)
+                .between(from, to)
+                .count(n)
+                .insert(conn)
+                .len();
+        }
+    }
+
+    Ok(Summary {
+        users,
+        namespaces,
+        messages,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use diesel::prelude::*;
+
+    use crate::clock::SystemClock;
+    use crate::model::membership::memberships;
+    use crate::model::message::messages;
+    use crate::model::test::run as run_test;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(split(100), vec![20, 48, 2, 18, 10, 2]);
+        assert_eq!(split(7).iter().sum::<usize>(), 7);
+        assert_eq!(split(0), vec![0; SAMPLES.len()]);
+    }
+
+    #[test]
+    fn test_run() {
+        run_test(|conn, config, _| {
+            let options = Options {
+                users: 3,
+                namespaces: 2,
+                messages: 100,
+                days: 7,
+            };
+            let summary = run(&options, config, &SystemClock, conn).unwrap();
+            assert_eq!(summary.users.len(), 3);
+            assert_eq!(summary.namespaces.len(), 2);
+            assert_eq!(summary.messages, 100);
+
+            let count: i64 = messages::table.count().get_result(conn).unwrap();
+            assert_eq!(count, 100);
+
+            // an owner and the others as members
+            let count: i64 = memberships::table
+                .filter(memberships::namespace_id.eq(summary.namespaces[0].id))
+                .count()
+                .get_result(conn)
+                .unwrap();
+            assert_eq!(count, 3);
+
+            let options = Options {
+                users: 0,
+                ..Default::default()
+            };
+            assert!(run(&options, config, &SystemClock, conn).is_err());
+        })
+    }
+}
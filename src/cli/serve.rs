@@ -0,0 +1,85 @@
+//! Runs the API server.
+use std::sync::RwLock;
+
+use rocket_slog::SlogFairing;
+
+use crate::clock::SystemClock;
+use crate::config::{Config, DynamicConfig};
+use crate::db::{
+    init_pool_holder as init_db_pool_holder,
+    init_replica_pool_holder as init_db_replica_pool_holder,
+};
+use crate::id::RandomIdGenerator;
+use crate::license::License;
+use crate::logger;
+use crate::mq::init_pool_holder as init_mq_pool_holder;
+use crate::server;
+use crate::service::secrets_provider::Refresher;
+use crate::ss::init_pool_holder as init_ss_pool_holder;
+use crate::telemetry::{Exporter, Tracing};
+
+pub fn run(config: Config) {
+    #[cfg(feature = "error-tracking")]
+    let _guard = crate::service::error_tracking::init(&config);
+    app(config).launch();
+}
+
+/// Builds the whole app (with the pools and the states) without launching
+/// it.
+pub fn app(config: Config) -> rocket::Rocket {
+    let logger = logger::get_logger(&config);
+    let license = License::load(&config).expect("failed to load license");
+
+    // connection pool holders
+    let db_pool_holder = init_db_pool_holder(
+        &config.database_connection_url(),
+        &config.database_pool(),
+    );
+    let db_replica_pool_holder = init_db_replica_pool_holder(
+        &config.database_replica_connection_url(),
+        &config.database_pool(),
+    );
+    let mq_pool_holder = init_mq_pool_holder(
+        &config.message_queue_connection_url(),
+        &config.message_queue_pool(),
+    );
+    let ss_pool_holder = init_ss_pool_holder(
+        &config.session_store_connection_url(),
+        config.session_store_max_pool_size,
+    );
+
+    // reconnects to the database with rotated credentials
+    let db = db_pool_holder.clone();
+    let db_replica = db_replica_pool_holder.clone();
+    let refresher_logger = logger.clone();
+    Refresher::new(&config, &logger).spawn(move |current, c| {
+        let options = c.database_pool();
+        if c.database_url != current.database_url {
+            let url = c.database_connection_url();
+            if let Err(e) = db.reconnect(&url, &options) {
+                error!(refresher_logger, "err: {}", e);
+            }
+        }
+        if c.database_replica_url != current.database_replica_url {
+            let url = c.database_replica_connection_url();
+            if let Err(e) = db_replica.reconnect(&url, &options) {
+                error!(refresher_logger, "err: {}", e);
+            }
+        }
+    });
+
+    let exporter = Exporter::from_config(&config, &logger);
+
+    server(&config)
+        .attach(Tracing::new(exporter))
+        .attach(SlogFairing::new(logger))
+        .manage(db_pool_holder)
+        .manage(db_replica_pool_holder)
+        .manage(mq_pool_holder)
+        .manage(ss_pool_holder)
+        .manage(RwLock::new(DynamicConfig::from(&config)))
+        .manage(SystemClock::shared())
+        .manage(RandomIdGenerator::shared())
+        .manage(config)
+        .manage(license)
+}
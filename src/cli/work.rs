@@ -0,0 +1,129 @@
+//! Runs the job worker.
+use std::env;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use fourche::queue::Queue;
+use redis::Client;
+
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::db::establish_connection;
+use crate::job::{Job, enqueue_deferred};
+use crate::logger::get_logger;
+use crate::service::secrets_provider::Refresher;
+use crate::service::worker_heartbeat::WorkerHeartbeat;
+use crate::telemetry::{self, Exporter, SpanKind};
+
+// e.g. `worker-1-42` (hostname and pid)
+fn worker_id() -> String {
+    let host = env::var("HOSTNAME").unwrap_or_else(|_| "worker".to_string());
+    format!("{}-{}", host, process::id())
+}
+
+pub fn run(mut config: Config) {
+    #[cfg(feature = "error-tracking")]
+    let _guard = crate::service::error_tracking::init(&config);
+
+    // redis
+    let url = config.message_queue_connection_url();
+    let client = Client::open(url.as_str()).unwrap();
+    let mut mq_conn = client.get_connection().unwrap();
+    // the queue holds the other one while it's alive
+    let mut heartbeat_conn = client.get_connection().unwrap();
+
+    // postgresql
+    let mut db_conn = establish_connection(&config);
+
+    let logger = get_logger(&config);
+    let exporter = Exporter::from_config(&config, &logger);
+
+    // the config with rotated credentials is taken before the next job
+    let (tx, rx) = mpsc::channel::<Config>();
+    Refresher::new(&config, &logger).spawn(move |_, c| {
+        let _ = tx.send(c.clone());
+    });
+
+    // the start of the running job (0 while it waits)
+    let busy_since = Arc::new(AtomicI64::new(0));
+
+    // moves deferred jobs into the queue when they are due, and beats unless
+    // the running job is stuck
+    let timeout = config.worker_heartbeat_timeout as i64;
+    let deferred_logger = logger.clone();
+    let running = Arc::clone(&busy_since);
+    thread::spawn(move || {
+        let id = worker_id();
+        let client = Client::open(url.as_str()).unwrap();
+        let mut conn = client.get_connection().unwrap();
+        loop {
+            let now = SystemClock.now();
+            match enqueue_deferred(now, &mut conn) {
+                Ok(0) => (),
+                Ok(n) => info!(deferred_logger, "enqueued deferred: {}", n),
+                Err(e) => error!(deferred_logger, "err: {}", e),
+            }
+
+            let started_at = running.load(Ordering::SeqCst);
+            if started_at == 0 || now.timestamp() - started_at < timeout {
+                let result =
+                    WorkerHeartbeat::new(&mut conn).beat(&id, now.timestamp());
+                if let Err(e) = result {
+                    error!(deferred_logger, "err: {}", e);
+                }
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+
+    let clock = SystemClock;
+    let mut queue = Queue::new("default", &mut mq_conn);
+    loop {
+        match queue.dequeue::<Job<String>>() {
+            Ok(job) => {
+                info!(
+                    logger,
+                    "kind: {}, args: {:?}",
+                    job.kind,
+                    job.args.as_slice()
+                );
+                if let Some(c) = rx.try_iter().last() {
+                    if c.database_url != config.database_url {
+                        db_conn = establish_connection(&c);
+                    }
+                    config = c;
+                }
+
+                busy_since.store(clock.now().timestamp(), Ordering::SeqCst);
+                if exporter.is_some() {
+                    let name = format!("job {}", job.kind);
+                    telemetry::start(&name, SpanKind::Consumer, None);
+                }
+                job.invoke(&db_conn, &config, &clock, &logger);
+                if let Some(ref e) = exporter {
+                    let kind = job.kind.to_string();
+                    let attributes = vec![("job.kind".to_string(), kind)];
+                    let spans = telemetry::finish(None, attributes, false);
+                    if let Some(spans) = spans {
+                        e.export(spans);
+                    }
+                }
+                busy_since.store(0, Ordering::SeqCst);
+
+                let result = WorkerHeartbeat::new(&mut heartbeat_conn)
+                    .finish(&job.kind.to_string(), clock.now().timestamp());
+                if let Err(e) = result {
+                    error!(logger, "err: {}", e);
+                }
+            },
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                break;
+            },
+        }
+    }
+}
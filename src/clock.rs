@@ -0,0 +1,100 @@
+//! Clock
+//!
+//! The code which depends on the current time (token granting, retention,
+//! rate limiting and scheduling) takes it from a `Clock` instead of calling
+//! `Utc::now()` directly, so that tests can freeze and advance it.
+//!
+//! The server manages a `SharedClock` as state (`State<SharedClock>`).
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The actual clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+impl SystemClock {
+    pub fn shared() -> SharedClock {
+        Arc::new(Self)
+    }
+}
+
+/// A clock which stays at the time until it's set or advanced (for tests).
+#[derive(Debug)]
+pub struct FrozenClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl Clock for FrozenClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock()
+    }
+}
+
+impl FrozenClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock();
+        *now = *now + duration;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_system_clock() {
+        let before = Utc::now();
+        let now = SystemClock.now();
+        assert!(before <= now && now <= Utc::now());
+    }
+
+    #[test]
+    fn test_frozen_clock() {
+        let t = Utc.ymd(2019, 7, 7).and_hms(7, 20, 15);
+        let clock = FrozenClock::new(t);
+        assert_eq!(clock.now(), t);
+        assert_eq!(clock.now(), t);
+
+        clock.advance(Duration::minutes(3));
+        assert_eq!(clock.now(), Utc.ymd(2019, 7, 7).and_hms(7, 23, 15));
+
+        clock.set(t);
+        assert_eq!(clock.now(), t);
+    }
+
+    #[test]
+    fn test_shared_clock() {
+        let t = Utc.ymd(2019, 7, 7).and_hms(7, 20, 15);
+        let frozen = Arc::new(FrozenClock::new(t));
+        let clock: SharedClock = frozen.clone();
+
+        frozen.advance(Duration::seconds(1));
+        assert_eq!(clock.now(), t + Duration::seconds(1));
+    }
+}
@@ -1,274 +1,1249 @@
+//! Config
+//!
+//! The values are read from these sources (the former wins):
+//!
+//! 1. environment variables (with `TEST_` prefix for testing)
+//! 2. files named by `<NAME>_FILE` variables (Docker secrets style, e.g.
+//!    `DATABASE_URL_FILE=/run/secrets/database_url`)
+//! 3. the secrets provider by `SECRETS_PROVIDER` (vault or aws, see
+//!    `service::secrets_provider`)
+//! 4. the section of the environment in the TOML file by `CONFIG_FILE`
+//! 5. the `[default]` section of the file
+//!
+//! The keys in the file are lowercase names without the prefix like below.
+//! Arrays are joined with commas.
+//!
+//! ```toml
+//! [default]
+//! mailer_smtp_port = 465
+//! quota_notification_thresholds = [80, 100]
+//!
+//! [production]
+//! database_max_pool_size = 24
+//! ```
+//!
+//! The config of Rocket is also built from it (`Config::to_rocket_config`),
+//! so `Rocket.toml` and `ROCKET_*` variables are not used.
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rocket::config::{
+    Config as RocketConfig, Environment, Limits, LoggingLevel,
+};
+use url::Url;
+
+use crate::clock::SystemClock;
+use crate::logger::{LOG_FORMATS, LOG_LEVELS, LOG_SYSLOG_FACILITIES};
+use crate::service::secrets_provider::{
+    AwsSecretsManager, SecretsProvider, Vault,
+};
+
+const EMAIL_SUGGESTION_DOMAINS: &str = "gmail.com,googlemail.com,yahoo.com,\
+hotmail.com,outlook.com,live.com,icloud.com,aol.com,protonmail.com,gmx.de,\
+gmx.net,web.de,mail.ru,yandex.ru";
+
+const AUTHENTICATION_BACKENDS: &[&str] = &["local", "ldap"];
+
+const DATABASE_SSL_MODES: &[&str] =
+    &["disable", "allow", "prefer", "require", "verify-ca", "verify-full"];
+const DATABASE_URL_SCHEMES: &[&str] = &["postgres", "postgresql"];
+const LDAP_URL_SCHEMES: &[&str] = &["ldap", "ldaps"];
+const REDIS_URL_SCHEMES: &[&str] = &["redis", "rediss", "redis+unix"];
+const SECRETS_PROVIDERS: &[&str] = &["none", "vault", "aws"];
+const SERVER_SECRET_KEY_LENGTH: usize = 32; // bytes
+const WEB_URL_SCHEMES: &[&str] = &["http", "https"];
+
+/// How the mailer secures the connection to the SMTP server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MailerSecurity {
+    /// TLS from the start (SMTPS, usually on 465)
+    Tls,
+    /// STARTTLS is required (usually on 587)
+    StartTls,
+    /// plain text (only for a local relay)
+    None,
+}
+
+impl FromStr for MailerSecurity {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_ref() {
+            "tls" => Ok(MailerSecurity::Tls),
+            "starttls" => Ok(MailerSecurity::StartTls),
+            "none" => Ok(MailerSecurity::None),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where the mailer transports emails.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MailerTransport {
+    Smtp,
+    /// kept in the process (see `mailer::memory`)
+    Memory,
+}
+
+impl FromStr for MailerTransport {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_ref() {
+            "smtp" => Ok(MailerTransport::Smtp),
+            "memory" => Ok(MailerTransport::Memory),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where jobs are enqueued.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageQueueBackend {
+    Redis,
+    /// kept in the process (see `mq::MemoryQueue`)
+    Memory,
+}
+
+impl FromStr for MessageQueueBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_ref() {
+            "redis" => Ok(MessageQueueBackend::Redis),
+            "memory" => Ok(MessageQueueBackend::Memory),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The settings of a connection pool (see `db` and `mq`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolConfig {
+    pub max_size: u32,
+    /// None keeps as many as `max_size` (the default of r2d2)
+    pub min_idle: Option<u32>,
+    /// seconds to wait for a connection
+    pub connection_timeout: u64,
+    /// seconds (0 means never)
+    pub idle_timeout: u64,
+    /// seconds (0 means never)
+    pub max_lifetime: u64,
+}
+
+fn seconds(value: u64) -> Option<Duration> {
+    if value == 0 {
+        None
+    } else {
+        Some(Duration::from_secs(value))
+    }
+}
+
+impl PoolConfig {
+    pub fn connection_timeout(&self) -> Duration {
+        Duration::from_secs(self.connection_timeout)
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        seconds(self.idle_timeout)
+    }
+
+    pub fn max_lifetime(&self) -> Option<Duration> {
+        seconds(self.max_lifetime)
+    }
+}
+
+// libpq takes the TLS options in the query of the URL. The ones given in it are
+// kept as they are, and `prefer` is not added as it's the default.
+fn postgres_url(url: &str, ssl_mode: &str, root_cert: &str) -> String {
+    let mut u = match Url::parse(url) {
+        Ok(u) => u,
+        Err(_) => return url.to_string(),
+    };
+    let has = |key: &str| u.query_pairs().any(|(k, _)| k == key);
+    let mut options = vec![];
+    if ssl_mode != "prefer" && !has("sslmode") {
+        options.push(("sslmode", ssl_mode));
+    }
+    if !root_cert.is_empty() && !has("sslrootcert") {
+        options.push(("sslrootcert", root_cert));
+    }
+    if options.is_empty() {
+        return url.to_string();
+    }
+    u.query_pairs_mut().extend_pairs(options);
+    u.to_string()
+}
+
+// `#insecure` makes the client skip the verification of the certificate (and
+// the hostname) of a rediss server. The CA certificates of the system are
+// used to verify it otherwise.
+fn redis_url(url: &str, tls_verify: bool) -> String {
+    if tls_verify || !url.starts_with("rediss://") || url.contains('#') {
+        return url.to_string();
+    }
+    format!("{}#insecure", url)
+}
+
+/// ConfigError lists all of the invalid variables, not only the first one.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid config: {}", self.errors.join(", "))
+    }
+}
+
+// returns the values of the sections for the environment in the file
+fn read_file(
+    path: &str,
+    env_name: &str,
+) -> Result<HashMap<String, String>, String> {
+    fn to_string(value: &toml::Value) -> String {
+        match value {
+            toml::Value::String(s) => s.to_string(),
+            toml::Value::Array(a) => {
+                a.iter().map(to_string).collect::<Vec<String>>().join(",")
+            },
+            v => v.to_string(),
+        }
+    }
+
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let table = toml::from_str::<toml::value::Table>(&content)
+        .map_err(|e| e.to_string())?;
+
+    let mut values = HashMap::new();
+    for section in &["default", env_name] {
+        if let Some(toml::Value::Table(t)) = table.get(*section) {
+            for (key, value) in t {
+                values.insert(key.to_ascii_uppercase(), to_string(value));
+            }
+        }
+    }
+    Ok(values)
+}
+
+// reads environment variables with the prefix (e.g. `TEST_`), and collects
+// the errors instead of panicking at the first one
+struct Vars {
+    prefix: &'static str,
+    file: HashMap<String, String>,
+    secrets: HashMap<String, String>,
+    secrets_provider: String,
+    errors: Vec<String>,
+}
+
+impl Vars {
+    fn new(prefix: &'static str, env_name: &str) -> Self {
+        let mut vars = Self {
+            prefix,
+            file: HashMap::new(),
+            secrets: HashMap::new(),
+            secrets_provider: "none".to_string(),
+            errors: vec![],
+        };
+        if let Ok(path) = env::var(format!("{}CONFIG_FILE", prefix)) {
+            match read_file(&path, env_name) {
+                Ok(values) => vars.file = values,
+                Err(e) => {
+                    let message = format!("is invalid: {}", e);
+                    vars.invalid("CONFIG_FILE", &message);
+                },
+            }
+        }
+
+        // the settings of the provider itself can't be in the secrets
+        vars.secrets_provider =
+            vars.one_of("SECRETS_PROVIDER", "none", SECRETS_PROVIDERS);
+        let provider: Box<dyn SecretsProvider> =
+            match vars.secrets_provider.as_ref() {
+                "vault" => Box::new(Vault {
+                    addr: vars.url("SECRETS_VAULT_ADDR", true, WEB_URL_SCHEMES),
+                    token: vars.required("SECRETS_VAULT_TOKEN"),
+                    path: vars.required("SECRETS_VAULT_PATH"),
+                }),
+                "aws" => Box::new(AwsSecretsManager {
+                    region: vars.required("SECRETS_AWS_REGION"),
+                    secret_id: vars.required("SECRETS_AWS_SECRET_ID"),
+                    access_key_id: vars.required("SECRETS_AWS_ACCESS_KEY_ID"),
+                    secret_access_key: vars
+                        .required("SECRETS_AWS_SECRET_ACCESS_KEY"),
+                    session_token: vars.string("SECRETS_AWS_SESSION_TOKEN", ""),
+                }),
+                _ => return vars,
+            };
+        if vars.errors.is_empty() {
+            match provider.fetch(&SystemClock) {
+                Ok(values) => vars.secrets = values,
+                Err(e) => {
+                    let message = format!("failed to fetch: {}", e);
+                    vars.invalid("SECRETS_PROVIDER", &message);
+                },
+            }
+        }
+        vars
+    }
+
+    fn get(&mut self, name: &str) -> Option<String> {
+        let key = format!("{}{}", self.prefix, name);
+        if let Ok(v) = env::var(&key) {
+            return Some(v);
+        }
+        if let Ok(path) = env::var(format!("{}_FILE", key)) {
+            match fs::read_to_string(&path) {
+                Ok(v) => {
+                    return Some(v.trim_end_matches(&['\r', '\n'][..]).into());
+                },
+                Err(e) => {
+                    let name = format!("{}_FILE", name);
+                    self.invalid(&name, &format!("is unreadable: {}", e));
+                },
+            }
+        }
+        self.secrets
+            .get(name)
+            .or_else(|| self.file.get(name))
+            .cloned()
+    }
+
+    fn invalid(&mut self, name: &str, message: &str) {
+        self.errors.push(format!("{}{} {}", self.prefix, name, message));
+    }
+
+    fn required(&mut self, name: &str) -> String {
+        self.get(name).unwrap_or_else(|| {
+            self.invalid(name, "is not set");
+            "".to_string()
+        })
+    }
+
+    fn string(&mut self, name: &str, default: &str) -> String {
+        self.get(name).unwrap_or_else(|| default.to_string())
+    }
+
+    fn one_of(&mut self, name: &str, default: &str, values: &[&str]) -> String {
+        let value = self.string(name, default).to_ascii_lowercase();
+        if !values.contains(&value.as_str()) {
+            let message = format!("must be one of {}", values.join(", "));
+            self.invalid(name, &message);
+        }
+        value
+    }
+
+    // an empty value is allowed if it's not set
+    fn optional_one_of(&mut self, name: &str, values: &[&str]) -> String {
+        match self.get(name) {
+            None => "".to_string(),
+            Some(_) => self.one_of(name, "", values),
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, default: T) -> T {
+        match self.get(name) {
+            None => default,
+            Some(v) => v.trim().parse::<T>().unwrap_or_else(|_| {
+                self.invalid(name, &format!("is invalid: '{}'", v));
+                default
+            }),
+        }
+    }
+
+    fn range<T>(&mut self, name: &str, default: T, min: T, max: T) -> T
+    where T: FromStr + PartialOrd + fmt::Display + Copy {
+        let value = self.parse(name, default);
+        if value < min || value > max {
+            let message = format!("must be between {} and {}", min, max);
+            self.invalid(name, &message);
+        }
+        value
+    }
+
+    // an empty value means None
+    fn optional_range<T>(&mut self, name: &str, min: T, max: T) -> Option<T>
+    where T: FromStr + PartialOrd + fmt::Display + Copy {
+        match self.get(name) {
+            Some(ref v) if !v.trim().is_empty() => {
+                Some(self.range(name, min, min, max))
+            },
+            _ => None,
+        }
+    }
+
+    // an empty value is allowed
+    fn base64(&mut self, name: &str, length: usize) -> String {
+        let value = self.string(name, "");
+        if !value.is_empty() &&
+            base64::decode(&value).map(|v| v.len()) != Ok(length)
+        {
+            let message = format!("must be {} bytes in base64", length);
+            self.invalid(name, &message);
+        }
+        value
+    }
+
+    fn minutes(&mut self, name: &str, default: u64) -> Duration {
+        self.minutes_range(name, default, 0, 1440)
+    }
+
+    fn minutes_range(
+        &mut self,
+        name: &str,
+        default: u64,
+        min: u64,
+        max: u64,
+    ) -> Duration {
+        Duration::from_secs(self.range(name, default, min, max) * 60)
+    }
+
+    fn list_range<T>(
+        &mut self,
+        name: &str,
+        default: &str,
+        min: T,
+        max: T,
+    ) -> Vec<T>
+    where T: FromStr + PartialOrd + fmt::Display + Copy {
+        let values = self.list::<T>(name, default);
+        if values.iter().any(|v| *v < min || *v > max) {
+            let message = format!("must be between {} and {}", min, max);
+            self.invalid(name, &message);
+        }
+        values
+    }
+
+    fn list<T: FromStr>(&mut self, name: &str, default: &str) -> Vec<T> {
+        let value = self.string(name, default);
+        let mut values = vec![];
+        for s in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match s.parse::<T>() {
+                Ok(v) => values.push(v),
+                Err(_) => self.invalid(name, &format!("is invalid: '{}'", s)),
+            }
+        }
+        values
+    }
+
+    // an empty value is allowed only for an optional one
+    fn url(&mut self, name: &str, required: bool, schemes: &[&str]) -> String {
+        let value = if required {
+            self.required(name)
+        } else {
+            self.string(name, "")
+        };
+        if value.is_empty() {
+            if required && self.get(name).is_some() {
+                self.invalid(name, "must not be empty");
+            }
+            return value;
+        }
+        match Url::parse(&value) {
+            Ok(ref u) if schemes.contains(&u.scheme()) => (),
+            _ => self.invalid(
+                name,
+                &format!("must be a URL of {}", schemes.join(", ")),
+            ),
+        }
+        value
+    }
+
+    // all of the placeholders must be in it, and `{application_url}` may be
+    // there. It must be a URL after they are replaced.
+    fn url_template(
+        &mut self,
+        name: &str,
+        default: &str,
+        placeholders: &[&str],
+    ) -> String {
+        let value = self.string(name, default);
+        let mut url = value.replace("{application_url}", "http://localhost");
+        for p in placeholders {
+            let placeholder = format!("{{{}}}", p);
+            if !value.contains(&placeholder) {
+                self.invalid(name, &format!("must contain {}", placeholder));
+                return value;
+            }
+            url = url.replace(&placeholder, "x");
+        }
+        if let Some(i) = url.find('{') {
+            let rest = &url[i..];
+            let end = rest.find('}').map_or(rest.len(), |j| j + 1);
+            let message =
+                format!("has an unknown placeholder: '{}'", &rest[..end]);
+            self.invalid(name, &message);
+            return value;
+        }
+        match Url::parse(&url) {
+            Ok(ref u) if WEB_URL_SCHEMES.contains(&u.scheme()) => (),
+            _ => self.invalid(
+                name,
+                &format!("must be a URL of {}", WEB_URL_SCHEMES.join(", ")),
+            ),
+        }
+        value
+    }
+}
+
+// defaults which differ by environment
+struct Defaults {
+    backup_directory: &'static str,
+    database_max_pool_size: u32,
+    ingestion_max_decompressed_size: u64,
+    message_queue_max_pool_size: u32,
+    server_address: &'static str,
+    server_port: u16,
+    session_store_max_pool_size: u32,
+}
+
+/// The values which can be reloaded at runtime without restart (see
+/// `route::config::reload`). It's managed as `RwLock<DynamicConfig>`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DynamicConfig {
+    pub log_level: String,
+    pub rate_limit_api_per_minute: u32,
+    pub rate_limit_ingestion_per_minute: u32,
+    pub rate_limit_login_per_minute: u32,
+    pub rate_limit_waitlist_per_minute: u32,
+    pub read_only: bool,
+}
+
+impl From<&Config> for DynamicConfig {
+    fn from(c: &Config) -> Self {
+        Self {
+            log_level: c.log_level.clone(),
+            rate_limit_api_per_minute: c.rate_limit_api_per_minute,
+            rate_limit_ingestion_per_minute: c.rate_limit_ingestion_per_minute,
+            rate_limit_login_per_minute: c.rate_limit_login_per_minute,
+            rate_limit_waitlist_per_minute: c.rate_limit_waitlist_per_minute,
+            read_only: c.read_only,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Config {
+    pub analytics_salt: String,
+    pub analytics_sample_rate: u32,
     pub application_url: String,
+    pub authentication_backend: String,
     pub authentication_token_issuer: String,
     pub authentication_token_key_id: String,
     pub authentication_token_secret: String,
+    pub backup_directory: String,
+    pub concurrency_export_limit: u32,
+    pub concurrency_retry_after: u64,
+    pub concurrency_search_limit: u32,
     pub cookie_domain: String,
     pub cookie_secure: bool,
     pub database_url: String,
     pub database_max_pool_size: u32,
+    pub database_pool_connection_timeout: u64,
+    pub database_pool_idle_timeout: u64,
+    pub database_pool_max_lifetime: u64,
+    pub database_pool_min_idle: Option<u32>,
+    pub database_replica_url: String,
+    pub database_ssl_mode: String,
+    pub database_ssl_root_cert: String,
+    pub database_statement_timeout: u64,
+    pub email_disposable_domains: Vec<String>,
+    pub email_mx_check_cache_ttl: u64,
+    pub email_mx_check_url: String,
+    pub email_normalize_gmail_dots: bool,
+    pub email_suggestion_distance: usize,
+    pub email_suggestion_domains: Vec<String>,
     pub env_name: &'static str,
+    pub fault_injection: bool,
+    pub ingestion_deduplication_window: u64,
+    pub ingestion_max_decompressed_size: u64,
+    pub ldap_attribute_email: String,
+    pub ldap_attribute_name: String,
+    pub ldap_base_dn: String,
+    pub ldap_bind_dn: String,
+    pub ldap_bind_password: String,
+    pub ldap_group_roles: Vec<String>,
+    pub ldap_namespace: String,
+    pub ldap_url: String,
+    pub ldap_user_filter: String,
+    pub license_file: String,
+    pub log_file: String,
+    pub log_format: String,
+    pub log_level: String,
+    pub log_syslog_facility: String,
     pub mailer_domain: String,
     pub mailer_from_email: String,
     pub mailer_from_alias: String,
     pub mailer_smtp_host: String,
     pub mailer_smtp_port: u16,
+    pub mailer_smtp_security: MailerSecurity,
     pub mailer_smtp_username: String,
     pub mailer_smtp_password: String,
+    pub mailer_transport: MailerTransport,
+    pub message_queue_backend: MessageQueueBackend,
     pub message_queue_url: String,
     pub message_queue_max_pool_size: u32,
+    pub message_queue_pool_connection_timeout: u64,
+    pub message_queue_pool_idle_timeout: u64,
+    pub message_queue_pool_max_lifetime: u64,
+    pub message_queue_pool_min_idle: Option<u32>,
+    pub message_queue_tls_verify: bool,
+    pub namespace_purge_grace_period: u64,
+    pub oauth_github_client_id: String,
+    pub oauth_github_client_secret: String,
+    pub oauth_google_client_id: String,
+    pub oauth_google_client_secret: String,
+    pub otlp_endpoint: String,
+    pub otlp_service_name: String,
+    pub password_hash_iterations: u32,
+    pub password_hash_memory_cost: u32,
+    pub password_hash_parallelism: u32,
+    pub push_apns_endpoint: String,
+    pub push_apns_key_file: String,
+    pub push_apns_key_id: String,
+    pub push_apns_team_id: String,
+    pub push_apns_topic: String,
+    pub push_fcm_endpoint: String,
+    pub push_fcm_server_key: String,
+    pub quota_bytes_per_day: u64,
+    pub quota_flush_interval: u64,
+    pub quota_grace_period: u64,
+    pub quota_messages_per_day: u64,
+    pub quota_notification_thresholds: Vec<u64>,
+    pub recent_view_flush_interval: u64,
+    pub recent_view_limit: u64,
+    pub rate_limit_api_per_minute: u32,
+    pub rate_limit_ingestion_per_minute: u32,
+    pub rate_limit_login_per_minute: u32,
+    pub rate_limit_waitlist_per_minute: u32,
+    pub read_only: bool,
+    pub secrets_provider: String,
+    pub secrets_refresh_interval: u64,
+    pub sentry_dsn: String,
+    pub server_address: String,
+    pub server_admin_token: String,
+    pub server_keep_alive: u32,
+    pub server_limit_json: u64,
+    pub server_limit_json_auth: u64,
+    pub server_limit_json_batch: u64,
+    pub server_limit_protobuf: u64,
+    pub server_port: u16,
+    pub server_secret_key: String,
+    pub server_trusted_proxies: Vec<IpAddr>,
+    pub server_workers: u16,
     pub session_store_url: String,
     pub session_store_max_pool_size: u32,
+    pub session_store_tls_verify: bool,
+    pub slo_alert_burn_rate: f64,
+    pub slo_alert_webhook_url: String,
+    pub slo_availability_target: f64,
+    pub slo_latency_target: f64,
+    pub slo_latency_threshold: u64,
+    pub slo_windows: Vec<u64>,
+    pub stream_buffer_flush_interval: u64,
+    pub sudo_mode_duration: Duration,
+    pub token_duration_confirmation: Duration,
+    pub token_duration_namespace_transfer: Duration,
+    pub token_duration_password_reset: Duration,
+    pub token_duration_user_activation: Duration,
+    pub url_template_message: String,
+    pub url_template_namespace_transfer: String,
+    pub url_template_password_reset: String,
+    pub url_template_user_activation: String,
+    pub url_template_waitlist_confirmation: String,
+    pub user_agent_sample_rate: u32,
     pub verification_token_issuer: String,
     pub verification_token_key_id: String,
     pub verification_token_secret: String,
-}
-
-impl Default for Config {
-    fn default() -> Config {
-        Config {
-            application_url: env::var("APPLICATION_URL")
-                .expect("APPLICATION_URL is not set"),
-
-            authentication_token_issuer: env::var(
-                "AUTHENTICATION_TOKEN_ISSUER",
-            )
-            .expect("AUTHENTICATION_TOKEN_ISSUER is not set"),
-            authentication_token_key_id: env::var(
-                "AUTHENTICATION_TOKEN_KEY_ID",
-            )
-            .expect("AUTHENTICATION_TOKEN_KEY_ID is not set"),
-            authentication_token_secret: env::var(
-                "AUTHENTICATION_TOKEN_SECRET",
-            )
-            .expect("AUTHENTICATION_TOKEN_SECRET is not set"),
-
-            cookie_domain: env::var("COOKIE_DOMAIN")
-                .expect("COOKIE_DOMAIN is not set"),
-            cookie_secure: env::var("COOKIE_SECURE")
-                .unwrap_or_else(|_| "false".to_string()) ==
-                "true",
-
-            database_max_pool_size: 0,
-            database_url: env::var("DATABASE_URL")
-                .expect("DATABASE_URL is not set"),
-
-            env_name: &"undefined",
-
-            mailer_domain: env::var("MAILER_DOMAIN")
-                .expect("MAILER_DOMAIN is not set"),
-            mailer_from_email: env::var("MAILER_FROM_EMAIL")
-                .expect("MAILER_FROM_EMAIL is not set"),
-            mailer_from_alias: env::var("MAILER_FROM_ALIAS")
-                .expect("MAILER_FROM_ALIAS is not set"),
-            mailer_smtp_host: env::var("MAILER_SMTP_HOST")
-                .expect("MAILER_SMTP_HOST is not set"),
-            mailer_smtp_port: 587,
-            mailer_smtp_username: env::var("MAILER_SMTP_USERNAME")
-                .expect("MAILER_SMTP_USERNAME is not set"),
-            mailer_smtp_password: env::var("MAILER_SMTP_PASSWORD")
-                .expect("MAILER_SMTP_PASSWORD is not set"),
-
-            message_queue_max_pool_size: 0,
-            message_queue_url: env::var("MESSAGE_QUEUE_URL")
-                .expect("MESSAGE_QUEUE_URL is not set"),
-
-            session_store_max_pool_size: 0,
-            session_store_url: env::var("SESSION_STORE_URL")
-                .expect("SESSION_STORE_URL is not set"),
-
-            verification_token_issuer: env::var("VERIFICATION_TOKEN_ISSUER")
-                .expect("VERIFICATION_TOKEN_ISSUER is not set"),
-            verification_token_key_id: env::var("VERIFICATION_TOKEN_KEY_ID")
-                .expect("VERIFICATION_TOKEN_KEY_ID is not set"),
-            verification_token_secret: env::var("VERIFICATION_TOKEN_SECRET")
-                .expect("VERIFICATION_TOKEN_SECRET is not set"),
-        }
-    }
+    pub worker_heartbeat_timeout: u64,
 }
 
 impl Config {
     pub const CSRF_HASH_DURATION: i64 = 10; // minutes
-    pub const CSRF_HASH_LENGTH: i32 = 32;
+    pub const CSRF_HASH_LENGTH: usize = 32;
     pub const CSRF_HASH_SOURCE: &'static [u8] =
         b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz01234567890-_";
 
-    pub fn from(config_name: &str) -> Result<Config, String> {
+    pub fn from(config_name: &str) -> Result<Config, ConfigError> {
         match config_name {
-            "production" => Ok(Config::production_config()),
-            "testing" => Ok(Config::testing_config()),
-            "development" => Ok(Config::development_config()),
-            _ => Err(format!("Invalid config_name: '{}'", &config_name)),
+            "production" => Config::production_config(),
+            "testing" => Config::testing_config(),
+            "development" => Config::development_config(),
+            _ => Err(ConfigError {
+                errors: vec![format!("Invalid config_name: '{}'", config_name)],
+            }),
         }
     }
 
-    fn production_config() -> Config {
-        let database_max_pool_size: u32 =
-            match env::var("DATABASE_MAX_POOL_SIZE") {
-                Ok(v) => v.parse::<u32>().unwrap(),
-                Err(_) => 12,
-            };
-
-        let mailer_smtp_port: u16 = match env::var("MAILER_SMTP_PORT") {
-            Ok(v) => v.parse::<u16>().unwrap(),
-            Err(_) => 587,
-        };
-
-        let message_queue_max_pool_size: u32 =
-            match env::var("MESSAGE_QUEUE_MAX_POOL_SIZE") {
-                Ok(v) => v.parse::<u32>().unwrap(),
-                Err(_) => 8,
-            };
-
-        let session_store_max_pool_size: u32 =
-            match env::var("SESSION_STORE_MAX_POOL_SIZE") {
-                Ok(v) => v.parse::<u32>().unwrap(),
-                Err(_) => 8,
-            };
-
-        Config {
-            env_name: &"production",
-            cookie_secure: true,
-            database_max_pool_size,
-            mailer_smtp_port,
-            message_queue_max_pool_size,
-            session_store_max_pool_size,
-
-            ..Default::default()
-        }
+    fn production_config() -> Result<Config, ConfigError> {
+        let mut c = Config::load("production", "", Defaults {
+            backup_directory: "tmp/backup",
+            database_max_pool_size: 12,
+            ingestion_max_decompressed_size: 52_428_800, // 50MB
+            message_queue_max_pool_size: 8,
+            server_address: "0.0.0.0",
+            server_port: 80,
+            session_store_max_pool_size: 8,
+        })?;
+        c.cookie_secure = true;
+        Ok(c)
     }
 
     // NOTE:
     // xxx_max_pool_size both must be >= 2 for integration tests.
     // Because the pool will be shared between the server and a client for the
     // instance.
-    fn testing_config() -> Config {
-        let database_max_pool_size: u32 =
-            match env::var("TEST_DATABASE_MAX_POOL_SIZE") {
-                Ok(v) => v.parse::<u32>().unwrap(),
-                Err(_) => 2,
-            };
+    fn testing_config() -> Result<Config, ConfigError> {
+        Config::load("testing", "TEST_", Defaults {
+            backup_directory: "tmp/test/backup",
+            database_max_pool_size: 2,
+            ingestion_max_decompressed_size: 1_048_576, // 1MB
+            message_queue_max_pool_size: 2,
+            server_address: "127.0.0.1",
+            server_port: 0, // random
+            session_store_max_pool_size: 2,
+        })
+    }
 
-        let mailer_smtp_port: u16 = match env::var("TEST_MAILER_SMTP_PORT") {
-            Ok(v) => v.parse::<u16>().unwrap(),
-            Err(_) => 587,
-        };
+    fn development_config() -> Result<Config, ConfigError> {
+        Config::load("development", "", Defaults {
+            backup_directory: "tmp/backup",
+            database_max_pool_size: 4,
+            ingestion_max_decompressed_size: 52_428_800, // 50MB
+            message_queue_max_pool_size: 4,
+            server_address: "127.0.0.1",
+            server_port: 8000,
+            session_store_max_pool_size: 4,
+        })
+    }
 
-        let message_queue_max_pool_size: u32 =
-            match env::var("TEST_MESSAGE_QUEUE_MAX_POOL_SIZE") {
-                Ok(v) => v.parse::<u32>().unwrap(),
-                Err(_) => 2,
-            };
+    fn load(
+        env_name: &'static str,
+        prefix: &'static str,
+        defaults: Defaults,
+    ) -> Result<Config, ConfigError> {
+        let mut v = Vars::new(prefix, env_name);
 
-        let session_store_max_pool_size: u32 =
-            match env::var("TEST_SESSION_STORE_MAX_POOL_SIZE") {
-                Ok(v) => v.parse::<u32>().unwrap(),
-                Err(_) => 2,
-            };
+        let config = Config {
+            // percent of usage events recorded with `analytics` feature (0
+            // disables it), actors are hashed with the salt (omitted if empty)
+            analytics_salt: v.string("ANALYTICS_SALT", ""),
+            analytics_sample_rate: v.range("ANALYTICS_SAMPLE_RATE", 0, 0, 100),
 
-        Config {
-            application_url: env::var("TEST_APPLICATION_URL")
-                .expect("TEST_APPLICATION_URL is not set"),
-
-            authentication_token_issuer: env::var(
-                "TEST_AUTHENTICATION_TOKEN_ISSUER",
-            )
-            .expect("TEST_AUTHENTICATION_TOKEN_ISSUER is not set"),
-            authentication_token_key_id: env::var(
-                "TEST_AUTHENTICATION_TOKEN_KEY_ID",
-            )
-            .expect("TEST_AUTHENTICATION_TOKEN_KEY_ID is not set"),
-            authentication_token_secret: env::var(
-                "TEST_AUTHENTICATION_TOKEN_SECRET",
-            )
-            .expect("TEST_AUTHENTICATION_TOKEN_SECRET is not set"),
-
-            cookie_domain: env::var("TEST_COOKIE_DOMAIN")
-                .expect("TEST_COOKIE_DOMAIN is not set"),
-            cookie_secure: env::var("TEST_COOKIE_SECURE")
-                .unwrap_or_else(|_| "false".to_string()) ==
-                "true",
-
-            database_max_pool_size,
-            database_url: env::var("TEST_DATABASE_URL")
-                .expect("TEST_DATABASE_URL is not set"),
-
-            env_name: &"testing",
-
-            mailer_domain: env::var("TEST_MAILER_DOMAIN")
-                .expect("TEST_MAILER_DOMAIN is not set"),
-            mailer_from_email: env::var("TEST_MAILER_FROM_EMAIL")
-                .expect("TEST_MAILER_FROM_EMAIL is not set"),
-            mailer_from_alias: env::var("TEST_MAILER_FROM_ALIAS")
-                .expect("TEST_MAILER_FROM_ALIAS is not set"),
-            mailer_smtp_host: env::var("TEST_MAILER_SMTP_HOST")
-                .expect("TEST_MAILER_SMTP_HOST is not set"),
-            mailer_smtp_port,
-            mailer_smtp_username: env::var("TEST_MAILER_SMTP_USERNAME")
-                .expect("TEST_MAILER_SMTP_USERNAME is not set"),
-            mailer_smtp_password: env::var("TEST_MAILER_SMTP_PASSWORD")
-                .expect("TEST_MAILER_SMTP_PASSWORD is not set"),
-
-            message_queue_max_pool_size,
-            message_queue_url: env::var("TEST_MESSAGE_QUEUE_URL")
-                .expect("TEST_MESSAGE_QUEUE_URL is not set"),
-
-            session_store_max_pool_size,
-            session_store_url: env::var("TEST_SESSION_STORE_URL")
-                .expect("TEST_SESSION_STORE_URL is not set"),
-
-            verification_token_issuer: env::var(
-                "TEST_VERIFICATION_TOKEN_ISSUER",
-            )
-            .expect("TEST_VERIFICATION_TOKEN_ISSUER is not set"),
-            verification_token_key_id: env::var(
-                "TEST_VERIFICATION_TOKEN_KEY_ID",
-            )
-            .expect("TEST_VERIFICATION_TOKEN_KEY_ID is not set"),
-            verification_token_secret: env::var(
-                "TEST_VERIFICATION_TOKEN_SECRET",
-            )
-            .expect("TEST_VERIFICATION_TOKEN_SECRET is not set"),
-        }
-    }
-
-    fn development_config() -> Config {
-        let database_max_pool_size: u32 =
-            match env::var("DATABASE_MAX_POOL_SIZE") {
-                Ok(v) => v.parse::<u32>().unwrap(),
-                Err(_) => 4,
-            };
+            application_url: v.url("APPLICATION_URL", true, WEB_URL_SCHEMES),
+
+            // local (password) or ldap
+            authentication_backend: v.one_of(
+                "AUTHENTICATION_BACKEND",
+                "local",
+                AUTHENTICATION_BACKENDS,
+            ),
+            authentication_token_issuer: v
+                .required("AUTHENTICATION_TOKEN_ISSUER"),
+            authentication_token_key_id: v
+                .required("AUTHENTICATION_TOKEN_KEY_ID"),
+            authentication_token_secret: v
+                .required("AUTHENTICATION_TOKEN_SECRET"),
+
+            backup_directory: v
+                .string("BACKUP_DIRECTORY", defaults.backup_directory),
+
+            // in-flight requests per server process (0 means unlimited)
+            concurrency_export_limit: v.parse("CONCURRENCY_EXPORT_LIMIT", 2),
+            concurrency_retry_after: v.parse("CONCURRENCY_RETRY_AFTER", 5),
+            concurrency_search_limit: v.parse("CONCURRENCY_SEARCH_LIMIT", 8),
+
+            cookie_domain: v.required("COOKIE_DOMAIN"),
+            cookie_secure: v.parse("COOKIE_SECURE", false),
+
+            database_max_pool_size: v.range(
+                "DATABASE_MAX_POOL_SIZE",
+                defaults.database_max_pool_size,
+                1,
+                1024,
+            ),
+            // seconds (0 means never for the timeout and the lifetime of idle
+            // connections, and an empty min idle keeps all of them)
+            database_pool_connection_timeout: v
+                .range("DATABASE_POOL_CONNECTION_TIMEOUT", 30, 1, 300),
+            database_pool_idle_timeout: v
+                .range("DATABASE_POOL_IDLE_TIMEOUT", 600, 0, 86400),
+            database_pool_max_lifetime: v
+                .range("DATABASE_POOL_MAX_LIFETIME", 1800, 0, 86400),
+            database_pool_min_idle: v
+                .optional_range("DATABASE_POOL_MIN_IDLE", 0, 1024),
+            database_url: v.url("DATABASE_URL", true, DATABASE_URL_SCHEMES),
+            // read-only queries go to the primary if it's empty
+            database_replica_url: v.url(
+                "DATABASE_REPLICA_URL",
+                false,
+                DATABASE_URL_SCHEMES,
+            ),
+            // sslmode of libpq for the primary and the replica (the ones in
+            // their URLs are prior to these), and the file of the CA
+            // certificates to verify the server (empty means the default one
+            // of libpq, `~/.postgresql/root.crt`)
+            database_ssl_mode: v.one_of(
+                "DATABASE_SSL_MODE",
+                "prefer",
+                DATABASE_SSL_MODES,
+            ),
+            database_ssl_root_cert: v.string("DATABASE_SSL_ROOT_CERT", ""),
+            // milliseconds for slow queries like search (0 means no timeout)
+            database_statement_timeout: v
+                .parse("DATABASE_STATEMENT_TIMEOUT", 30000),
+
+            // registrations with these domains (and subdomains) are rejected
+            email_disposable_domains: v
+                .string("EMAIL_DISPOSABLE_DOMAINS", "")
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            // seconds to keep the result of an MX check in the session store
+            email_mx_check_cache_ttl: v
+                .range("EMAIL_MX_CHECK_CACHE_TTL", 86_400, 1, 2_592_000),
+            // DNS over HTTPS (JSON) resolver for the MX checks on
+            // registration, e.g. `https://cloudflare-dns.com/dns-query` (empty
+            // disables them)
+            email_mx_check_url: v.url("EMAIL_MX_CHECK_URL", false, &["https"]),
+            // e.g. `o.s.wald@gmail.com` is saved as `oswald@gmail.com`
+            email_normalize_gmail_dots: v
+                .parse("EMAIL_NORMALIZE_GMAIL_DOTS", false),
+            email_suggestion_distance: v
+                .range("EMAIL_SUGGESTION_DISTANCE", 2, 0, 8),
+            email_suggestion_domains: v
+                .string("EMAIL_SUGGESTION_DOMAINS", EMAIL_SUGGESTION_DOMAINS)
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+
+            env_name,
+
+            // hooks for resilience tests (see `service::fault_injection`)
+            fault_injection: v.parse("FAULT_INJECTION", false) &&
+                env_name == "testing",
 
-        let mailer_smtp_port: u16 = match env::var("MAILER_SMTP_PORT") {
-            Ok(v) => v.parse::<u16>().unwrap(),
-            Err(_) => 587,
+            // seconds to coalesce identical messages into the first one (0
+            // disables the deduplication)
+            ingestion_deduplication_window: v
+                .range("INGESTION_DEDUPLICATION_WINDOW", 0, 0, 86_400),
+
+            // limit of the size after Content-Encoding (gzip, zstd) is decoded
+            ingestion_max_decompressed_size: v.range(
+                "INGESTION_MAX_DECOMPRESSED_SIZE",
+                defaults.ingestion_max_decompressed_size,
+                1,
+                u64::MAX,
+            ),
+
+            // these are used only with the ldap authentication backend
+            ldap_attribute_email: v.string("LDAP_ATTRIBUTE_EMAIL", "mail"),
+            ldap_attribute_name: v.string("LDAP_ATTRIBUTE_NAME", "cn"),
+            ldap_base_dn: v.string("LDAP_BASE_DN", ""),
+            ldap_bind_dn: v.string("LDAP_BIND_DN", ""),
+            ldap_bind_password: v.string("LDAP_BIND_PASSWORD", ""),
+            // e.g. `owner=cn=admins,ou=groups,dc=example,dc=org;member=...`
+            ldap_group_roles: v
+                .string("LDAP_GROUP_ROLES", "")
+                .split(';')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            ldap_namespace: v.string("LDAP_NAMESPACE", ""),
+            ldap_url: v.url("LDAP_URL", false, LDAP_URL_SCHEMES),
+            ldap_user_filter: v.string("LDAP_USER_FILTER", "(uid={username})"),
+
+            // the community edition runs without any license
+            license_file: v.string("LICENSE_FILE", ""),
+
+            // appended in addition to stdout
+            log_file: v.string("LOG_FILE", ""),
+            log_format: v.one_of("LOG_FORMAT", "terminal", LOG_FORMATS),
+            // the default depends on the environment (see `logger`)
+            log_level: v.optional_one_of("LOG_LEVEL", LOG_LEVELS),
+            // syslog is not used if it's empty
+            log_syslog_facility: v
+                .optional_one_of("LOG_SYSLOG_FACILITY", LOG_SYSLOG_FACILITIES),
+
+            mailer_domain: v.required("MAILER_DOMAIN"),
+            mailer_from_email: v.required("MAILER_FROM_EMAIL"),
+            mailer_from_alias: v.required("MAILER_FROM_ALIAS"),
+            mailer_smtp_host: v.required("MAILER_SMTP_HOST"),
+            mailer_smtp_port: v.range("MAILER_SMTP_PORT", 587, 1, u16::MAX),
+            // tls, starttls or none
+            mailer_smtp_security: v
+                .parse("MAILER_SMTP_SECURITY", MailerSecurity::Tls),
+            mailer_smtp_username: v.required("MAILER_SMTP_USERNAME"),
+            mailer_smtp_password: v.required("MAILER_SMTP_PASSWORD"),
+            // smtp or memory (not in production)
+            mailer_transport: v
+                .parse("MAILER_TRANSPORT", MailerTransport::Smtp),
+
+            // redis or memory (not in production, nobody runs the jobs)
+            message_queue_backend: v
+                .parse("MESSAGE_QUEUE_BACKEND", MessageQueueBackend::Redis),
+
+            message_queue_max_pool_size: v.range(
+                "MESSAGE_QUEUE_MAX_POOL_SIZE",
+                defaults.message_queue_max_pool_size,
+                1,
+                1024,
+            ),
+            // same as the ones of the database
+            message_queue_pool_connection_timeout: v
+                .range("MESSAGE_QUEUE_POOL_CONNECTION_TIMEOUT", 30, 1, 300),
+            message_queue_pool_idle_timeout: v
+                .range("MESSAGE_QUEUE_POOL_IDLE_TIMEOUT", 600, 0, 86400),
+            message_queue_pool_max_lifetime: v
+                .range("MESSAGE_QUEUE_POOL_MAX_LIFETIME", 1800, 0, 86400),
+            message_queue_pool_min_idle: v
+                .optional_range("MESSAGE_QUEUE_POOL_MIN_IDLE", 0, 1024),
+            // false skips the verification of the certificate for rediss
+            message_queue_tls_verify: v
+                .parse("MESSAGE_QUEUE_TLS_VERIFY", true),
+            message_queue_url: v.url(
+                "MESSAGE_QUEUE_URL",
+                true,
+                REDIS_URL_SCHEMES,
+            ),
+
+            // deleted namespaces can be restored until it's passed
+            namespace_purge_grace_period: v
+                .parse("NAMESPACE_PURGE_GRACE_PERIOD", 2_592_000), // sec
+
+            // an empty client id disables the provider
+            oauth_github_client_id: v.string("OAUTH_GITHUB_CLIENT_ID", ""),
+            oauth_github_client_secret: v
+                .string("OAUTH_GITHUB_CLIENT_SECRET", ""),
+            oauth_google_client_id: v.string("OAUTH_GOOGLE_CLIENT_ID", ""),
+            oauth_google_client_secret: v
+                .string("OAUTH_GOOGLE_CLIENT_SECRET", ""),
+
+            // an empty endpoint disables tracing (see `telemetry`)
+            otlp_endpoint: v.url("OTLP_ENDPOINT", false, WEB_URL_SCHEMES),
+            otlp_service_name: v
+                .string("OTLP_SERVICE_NAME", "eloquentlog-console-api"),
+
+            // Argon2id (the memory cost is in KiB)
+            password_hash_iterations: v
+                .range("PASSWORD_HASH_ITERATIONS", 2, 1, 64),
+            password_hash_memory_cost: v
+                .range("PASSWORD_HASH_MEMORY_COST", 19_456, 128, 4_194_304),
+            password_hash_parallelism: v
+                .range("PASSWORD_HASH_PARALLELISM", 1, 1, 16),
+
+            // an empty endpoint disables the platform (see
+            // `service::push_notifier`), the key of APNs is a .p8 file
+            push_apns_endpoint: v.url(
+                "PUSH_APNS_ENDPOINT",
+                false,
+                WEB_URL_SCHEMES,
+            ),
+            push_apns_key_file: v.string("PUSH_APNS_KEY_FILE", ""),
+            push_apns_key_id: v.string("PUSH_APNS_KEY_ID", ""),
+            push_apns_team_id: v.string("PUSH_APNS_TEAM_ID", ""),
+            // the bundle id of the app
+            push_apns_topic: v.string("PUSH_APNS_TOPIC", ""),
+            push_fcm_endpoint: v.url(
+                "PUSH_FCM_ENDPOINT",
+                false,
+                WEB_URL_SCHEMES,
+            ),
+            push_fcm_server_key: v.string("PUSH_FCM_SERVER_KEY", ""),
+
+            quota_bytes_per_day: v
+                .parse("QUOTA_BYTES_PER_DAY", 104_857_600), // 100MB
+            quota_flush_interval: v.parse("QUOTA_FLUSH_INTERVAL", 300), // sec
+            quota_grace_period: v.parse("QUOTA_GRACE_PERIOD", 3600), // sec
+            quota_messages_per_day: v.parse("QUOTA_MESSAGES_PER_DAY", 100_000),
+            quota_notification_thresholds: v
+                .list("QUOTA_NOTIFICATION_THRESHOLDS", "80,100"), // percent
+
+            recent_view_flush_interval: v
+                .parse("RECENT_VIEW_FLUSH_INTERVAL", 300), // seconds
+            recent_view_limit: v.parse("RECENT_VIEW_LIMIT", 50), // per user
+
+            rate_limit_api_per_minute: v
+                .parse("RATE_LIMIT_API_PER_MINUTE", 120),
+            rate_limit_ingestion_per_minute: v
+                .parse("RATE_LIMIT_INGESTION_PER_MINUTE", 600),
+            rate_limit_login_per_minute: v
+                .parse("RATE_LIMIT_LOGIN_PER_MINUTE", 10),
+            rate_limit_waitlist_per_minute: v
+                .parse("RATE_LIMIT_WAITLIST_PER_MINUTE", 5),
+
+            // mutations fail with 503 (e.g. during failovers of the primary
+            // database, see `service::read_only`)
+            read_only: v.parse("READ_ONLY", false),
+
+            // none, vault or aws (the settings are read in `Vars::new`)
+            secrets_provider: v.secrets_provider.clone(),
+            // seconds until the secrets are fetched again (0 disables it)
+            secrets_refresh_interval: v
+                .range("SECRETS_REFRESH_INTERVAL", 300, 0, 86400),
+
+            // errors are reported only with `error-tracking` feature
+            sentry_dsn: v.url("SENTRY_DSN", false, WEB_URL_SCHEMES),
+
+            server_address: v.string("SERVER_ADDRESS", defaults.server_address),
+            // X-Admin-Token of the local-only endpoints (they are disabled if
+            // it's empty, see `request::local_only`)
+            server_admin_token: v.string("SERVER_ADMIN_TOKEN", ""),
+            // seconds (0 disables it)
+            server_keep_alive: v.parse("SERVER_KEEP_ALIVE", 0),
+            // bytes of request bodies
+            server_limit_json: v.parse("SERVER_LIMIT_JSON", 5_242_880), // 5MB
+            // login, registration, sudo mode and password reset
+            server_limit_json_auth: v.parse("SERVER_LIMIT_JSON_AUTH", 4_096),
+            // messages sent by shippers (before the decompression)
+            server_limit_json_batch: v
+                .parse("SERVER_LIMIT_JSON_BATCH", 20_971_520), // 20MB
+            server_limit_protobuf: v
+                .parse("SERVER_LIMIT_PROTOBUF", 5_242_880), // 5MB
+            server_port: v.parse("SERVER_PORT", defaults.server_port),
+            // an empty key is generated on start (private cookies are lost
+            // by restarts)
+            server_secret_key: v
+                .base64("SERVER_SECRET_KEY", SERVER_SECRET_KEY_LENGTH),
+            // the proxies whose `X-Real-IP` header is trusted (it's ignored
+            // if empty)
+            server_trusted_proxies: v.list("SERVER_TRUSTED_PROXIES", ""),
+            // 0 means the default of Rocket (cores * 2)
+            server_workers: v.range("SERVER_WORKERS", 0, 0, 1024),
+
+            session_store_max_pool_size: v.range(
+                "SESSION_STORE_MAX_POOL_SIZE",
+                defaults.session_store_max_pool_size,
+                1,
+                1024,
+            ),
+            // same as the one of the message queue
+            session_store_tls_verify: v
+                .parse("SESSION_STORE_TLS_VERIFY", true),
+            session_store_url: v.url(
+                "SESSION_STORE_URL",
+                true,
+                REDIS_URL_SCHEMES,
+            ),
+
+            // burn rate of the error budget which is alerted (0 disables it,
+            // see `service::slo`)
+            slo_alert_burn_rate: v
+                .range("SLO_ALERT_BURN_RATE", 0.0, 0.0, 1000.0),
+            slo_alert_webhook_url: v.url(
+                "SLO_ALERT_WEBHOOK_URL",
+                false,
+                &["http", "https"],
+            ),
+            // percent of the requests which don't fail (5xx)
+            slo_availability_target: v
+                .range("SLO_AVAILABILITY_TARGET", 99.9, 50.0, 99.999),
+            // percent of the requests which are faster than the threshold
+            // (milliseconds)
+            slo_latency_target: v
+                .range("SLO_LATENCY_TARGET", 99.0, 50.0, 99.999),
+            slo_latency_threshold: v
+                .range("SLO_LATENCY_THRESHOLD", 500, 1, 60_000),
+            // minutes of the windows of burn rates (empty disables the
+            // request metrics)
+            slo_windows: v.list_range("SLO_WINDOWS", "", 1, 1440),
+
+            // seconds between flushes of ingested messages (0 disables the
+            // buffer, they are saved on the ingestion)
+            stream_buffer_flush_interval: v
+                .range("STREAM_BUFFER_FLUSH_INTERVAL", 0, 0, 3600),
+
+            // minutes after re-authentication (0 disables sudo mode)
+            sudo_mode_duration: v.minutes("SUDO_MODE_DURATION", 15),
+
+            // minutes until tokens expire: the ones for destructive calls
+            // (see `service::confirmation`), the one sent to the new owner of
+            // a namespace, and the ones in emails for password reset and user
+            // activation
+            token_duration_confirmation: v
+                .minutes_range("TOKEN_DURATION_CONFIRMATION", 5, 1, 60),
+            token_duration_namespace_transfer: v.minutes_range(
+                "TOKEN_DURATION_NAMESPACE_TRANSFER",
+                1440,
+                1,
+                10080,
+            ),
+            token_duration_password_reset: v
+                .minutes_range("TOKEN_DURATION_PASSWORD_RESET", 60, 1, 1440),
+            token_duration_user_activation: v
+                .minutes_range("TOKEN_DURATION_USER_ACTIVATION", 60, 1, 1440),
+
+            // links in emails and previews to the frontend, which may run on
+            // another domain or path than `APPLICATION_URL` (see
+            // `mailer::user` and `service::unfurl`)
+            url_template_message: v.url_template(
+                "URL_TEMPLATE_MESSAGE",
+                "{application_url}/message/{namespace}/{stream}/{id}",
+                &["namespace", "stream", "id"],
+            ),
+            url_template_namespace_transfer: v.url_template(
+                "URL_TEMPLATE_NAMESPACE_TRANSFER",
+                "{application_url}/namespace/{namespace}/transfer/accept?t={t}",
+                &["namespace", "t"],
+            ),
+            url_template_password_reset: v.url_template(
+                "URL_TEMPLATE_PASSWORD_RESET",
+                "{application_url}/password/reset?s={s}&t={t}",
+                &["s", "t"],
+            ),
+            url_template_user_activation: v.url_template(
+                "URL_TEMPLATE_USER_ACTIVATION",
+                "{application_url}/user/activate?s={s}&t={t}",
+                &["s", "t"],
+            ),
+            url_template_waitlist_confirmation: v.url_template(
+                "URL_TEMPLATE_WAITLIST_CONFIRMATION",
+                "{application_url}/waitlist/confirm?t={t}",
+                &["t"],
+            ),
+
+            // percent of requests with access tokens (0 disables it)
+            user_agent_sample_rate: v
+                .range("USER_AGENT_SAMPLE_RATE", 10, 0, 100),
+
+            verification_token_issuer: v.required("VERIFICATION_TOKEN_ISSUER"),
+            verification_token_key_id: v.required("VERIFICATION_TOKEN_KEY_ID"),
+            verification_token_secret: v.required("VERIFICATION_TOKEN_SECRET"),
+
+            // seconds without any heartbeat until workers are not ready
+            worker_heartbeat_timeout: v
+                .range("WORKER_HEARTBEAT_TIMEOUT", 30, 1, 3600),
         };
 
-        let message_queue_max_pool_size: u32 =
-            match env::var("MESSAGE_QUEUE_MAX_POOL_SIZE") {
-                Ok(v) => v.parse::<u32>().unwrap(),
-                Err(_) => 4,
-            };
+        // r2d2 doesn't accept more idle connections than the size
+        let pools = [
+            ("DATABASE_POOL_MIN_IDLE", config.database_pool()),
+            ("MESSAGE_QUEUE_POOL_MIN_IDLE", config.message_queue_pool()),
+        ];
+        for (name, pool) in pools.iter() {
+            if pool.min_idle.map_or(false, |n| n > pool.max_size) {
+                v.invalid(name, "must not be larger than the max pool size");
+            }
+        }
 
-        let session_store_max_pool_size: u32 =
-            match env::var("SESSION_STORE_MAX_POOL_SIZE") {
-                Ok(v) => v.parse::<u32>().unwrap(),
-                Err(_) => 4,
-            };
+        // the ones in memory are lost with the process
+        if env_name == "production" {
+            if config.mailer_transport == MailerTransport::Memory {
+                v.invalid("MAILER_TRANSPORT", "must not be memory");
+            }
+            if config.message_queue_backend == MessageQueueBackend::Memory {
+                v.invalid("MESSAGE_QUEUE_BACKEND", "must not be memory");
+            }
+        }
+
+        let root_cert = &config.database_ssl_root_cert;
+        if !root_cert.is_empty() && !Path::new(root_cert).is_file() {
+            v.invalid("DATABASE_SSL_ROOT_CERT", "must be a file");
+        }
 
-        Config {
-            env_name: &"development",
-            database_max_pool_size,
-            mailer_smtp_port,
-            message_queue_max_pool_size,
-            session_store_max_pool_size,
+        let redis_tls = [
+            (
+                "MESSAGE_QUEUE_TLS_VERIFY",
+                &config.message_queue_url,
+                config.message_queue_tls_verify,
+            ),
+            (
+                "SESSION_STORE_TLS_VERIFY",
+                &config.session_store_url,
+                config.session_store_tls_verify,
+            ),
+        ];
+        for (name, url, verify) in redis_tls.iter() {
+            if !verify && !url.starts_with("rediss://") {
+                v.invalid(name, "must be true unless the URL is rediss");
+            }
+        }
 
-            ..Default::default()
+        if v.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError { errors: v.errors })
         }
     }
+
+    pub fn database_pool(&self) -> PoolConfig {
+        PoolConfig {
+            max_size: self.database_max_pool_size,
+            min_idle: self.database_pool_min_idle,
+            connection_timeout: self.database_pool_connection_timeout,
+            idle_timeout: self.database_pool_idle_timeout,
+            max_lifetime: self.database_pool_max_lifetime,
+        }
+    }
+
+    /// Returns the URL to connect to the database with the TLS options.
+    pub fn database_connection_url(&self) -> String {
+        self.postgres_url(&self.database_url)
+    }
+
+    /// Returns the URL to connect to the replica with the TLS options (empty
+    /// if it's not configured).
+    pub fn database_replica_connection_url(&self) -> String {
+        self.postgres_url(&self.database_replica_url)
+    }
+
+    fn postgres_url(&self, url: &str) -> String {
+        postgres_url(url, &self.database_ssl_mode, &self.database_ssl_root_cert)
+    }
+
+    pub fn message_queue_pool(&self) -> PoolConfig {
+        PoolConfig {
+            max_size: self.message_queue_max_pool_size,
+            min_idle: self.message_queue_pool_min_idle,
+            connection_timeout: self.message_queue_pool_connection_timeout,
+            idle_timeout: self.message_queue_pool_idle_timeout,
+            max_lifetime: self.message_queue_pool_max_lifetime,
+        }
+    }
+
+    /// Returns the URL to connect to the message queue with the TLS options.
+    pub fn message_queue_connection_url(&self) -> String {
+        redis_url(&self.message_queue_url, self.message_queue_tls_verify)
+    }
+
+    /// Returns the URL to connect to the session store with the TLS options.
+    pub fn session_store_connection_url(&self) -> String {
+        redis_url(&self.session_store_url, self.session_store_tls_verify)
+    }
+
+    /// Builds the config of Rocket (the environment is by `env_name`).
+    pub fn to_rocket_config(&self) -> Result<RocketConfig, String> {
+        let (environment, log_level) = match self.env_name {
+            "production" => (Environment::Production, LoggingLevel::Critical),
+            "testing" => (Environment::Development, LoggingLevel::Critical),
+            _ => (Environment::Development, LoggingLevel::Debug),
+        };
+        let limits = Limits::default()
+            .limit("json", self.server_limit_json)
+            .limit("protobuf", self.server_limit_protobuf);
+        let mut builder = RocketConfig::build(environment)
+            .address(self.server_address.as_str())
+            .port(self.server_port)
+            .keep_alive(self.server_keep_alive)
+            .limits(limits)
+            .log_level(log_level);
+        if self.server_workers > 0 {
+            builder = builder.workers(self.server_workers);
+        }
+        if !self.server_secret_key.is_empty() {
+            builder = builder.secret_key(self.server_secret_key.as_str());
+        }
+        builder.finalize().map_err(|e| e.to_string())
+    }
 }
 
 #[cfg(test)]
@@ -276,7 +1251,11 @@ mod test {
     use super::*;
 
     use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
     use std::panic::{self, AssertUnwindSafe};
+    use std::process;
+    use std::thread;
 
     use parking_lot::Mutex;
 
@@ -362,6 +1341,35 @@ mod test {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_postgres_url() {
+        let url = "postgresql://u:p@db.example.org:5432/dbname";
+        assert_eq!(postgres_url(url, "prefer", ""), url);
+        assert_eq!(
+            postgres_url(url, "verify-full", "/etc/ssl/root.crt"),
+            "postgresql://u:p@db.example.org:5432/dbname?sslmode=verify-full\
+             &sslrootcert=%2Fetc%2Fssl%2Froot.crt"
+        );
+
+        // the ones in the url are kept
+        let url = "postgresql://localhost/dbname?sslmode=disable";
+        assert_eq!(postgres_url(url, "require", ""), url);
+        assert_eq!(postgres_url("", "require", ""), "");
+    }
+
+    #[test]
+    fn test_redis_url() {
+        let url = "rediss://u:p@redis.example.org:6380/0";
+        assert_eq!(redis_url(url, true), url);
+        let insecure = "rediss://u:p@redis.example.org:6380/0#insecure";
+        assert_eq!(redis_url(url, false), insecure);
+        assert_eq!(redis_url(insecure, false), insecure);
+        assert_eq!(
+            redis_url("redis://localhost:6379/0", false),
+            "redis://localhost:6379/0"
+        );
+    }
+
     #[test]
     fn test_from_unknown_without_env_vars() {
         let c = Config::from("unknown");
@@ -391,11 +1399,241 @@ TEST_VERIFICATION_TOKEN_ISSUER
 TEST_VERIFICATION_TOKEN_KEY_ID
 TEST_VERIFICATION_TOKEN_SECRET
 "#, || {
-                let result = panic::catch_unwind(|| {
-                    let c = Config::from("production");
-                    assert!(c.is_ok());
+                let c = Config::from("production");
+                assert!(c.is_err());
+
+                // lists all of the missing variables
+                let errors = c.err().unwrap().errors;
+                assert_eq!(errors.len(), 17);
+                assert_eq!(errors[0], "APPLICATION_URL is not set");
+            })
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_from_production_with_invalid_values() {
+            with(r#"
+APPLICATION_URL
+AUTHENTICATION_TOKEN_ISSUER
+AUTHENTICATION_TOKEN_KEY_ID
+AUTHENTICATION_TOKEN_SECRET
+COOKIE_DOMAIN
+COOKIE_SECURE
+DATABASE_URL
+MAILER_DOMAIN
+MAILER_FROM_EMAIL
+MAILER_FROM_ALIAS
+MAILER_SMTP_HOST
+MAILER_SMTP_PASSWORD
+MAILER_SMTP_USERNAME
+MESSAGE_QUEUE_URL
+SESSION_STORE_URL
+VERIFICATION_TOKEN_ISSUER
+VERIFICATION_TOKEN_KEY_ID
+VERIFICATION_TOKEN_SECRET
+"#, || {
+                env::set_var("SECRETS_PROVIDER", "consul");
+                env::set_var("ANALYTICS_SAMPLE_RATE", "101");
+                env::set_var("AUTHENTICATION_BACKEND", "kerberos");
+                env::set_var("DATABASE_URL", "mysql://localhost/dbname");
+                env::set_var("DATABASE_POOL_MIN_IDLE", "20");
+                env::set_var("DATABASE_SSL_MODE", "verify");
+                env::set_var("DATABASE_SSL_ROOT_CERT", "/nonexistent.crt");
+                env::set_var("MAILER_SMTP_PORT", "0");
+                env::set_var("MAILER_SMTP_SECURITY", "ssl");
+                env::set_var("MAILER_TRANSPORT", "memory");
+                env::set_var("LOG_FORMAT", "xml");
+                env::set_var("LOG_LEVEL", "verbose");
+                env::set_var("PUSH_FCM_ENDPOINT", "fcm.googleapis.com");
+                env::set_var("QUOTA_NOTIFICATION_THRESHOLDS", "80,x");
+                env::set_var("SERVER_SECRET_KEY", "c2hvcnQ=");
+                env::set_var("SESSION_STORE_TLS_VERIFY", "false");
+                env::set_var("SLO_WINDOWS", "60,0");
+                env::set_var("SUDO_MODE_DURATION", "-1");
+                env::set_var("TOKEN_DURATION_CONFIRMATION", "0");
+                env::set_var(
+                    "URL_TEMPLATE_PASSWORD_RESET",
+                    "https://example.org/reset?t={t}",
+                );
+                env::set_var(
+                    "URL_TEMPLATE_USER_ACTIVATION",
+                    "{base}/activate?s={s}&t={t}",
+                );
+
+                let errors = Config::from("production").err().unwrap().errors;
+                assert_eq!(
+                    errors,
+                    vec![
+                        "SECRETS_PROVIDER must be one of none, vault, aws",
+                        "ANALYTICS_SAMPLE_RATE must be between 0 and 100",
+                        "AUTHENTICATION_BACKEND must be one of local, ldap",
+                        "DATABASE_URL must be a URL of postgres, postgresql",
+                        "DATABASE_SSL_MODE must be one of disable, allow, \
+                         prefer, require, verify-ca, verify-full",
+                        "LOG_FORMAT must be one of terminal, json",
+                        "LOG_LEVEL must be one of trace, debug, info, \
+                         warning, error, critical",
+                        "MAILER_SMTP_PORT must be between 1 and 65535",
+                        "MAILER_SMTP_SECURITY is invalid: 'ssl'",
+                        "PUSH_FCM_ENDPOINT must be a URL of http, https",
+                        "QUOTA_NOTIFICATION_THRESHOLDS is invalid: 'x'",
+                        "SERVER_SECRET_KEY must be 32 bytes in base64",
+                        "SLO_WINDOWS must be between 1 and 1440",
+                        "SUDO_MODE_DURATION is invalid: '-1'",
+                        "TOKEN_DURATION_CONFIRMATION must be between 1 and 60",
+                        "URL_TEMPLATE_PASSWORD_RESET must contain {s}",
+                        "URL_TEMPLATE_USER_ACTIVATION has an unknown \
+                         placeholder: '{base}'",
+                        "DATABASE_POOL_MIN_IDLE must not be larger than the \
+                         max pool size",
+                        "MAILER_TRANSPORT must not be memory",
+                        "DATABASE_SSL_ROOT_CERT must be a file",
+                        "SESSION_STORE_TLS_VERIFY must be true unless the \
+                         URL is rediss",
+                    ]
+                );
+            })
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_from_production_with_files() {
+            with(r#"
+APPLICATION_URL
+AUTHENTICATION_TOKEN_ISSUER
+AUTHENTICATION_TOKEN_KEY_ID
+AUTHENTICATION_TOKEN_SECRET
+COOKIE_DOMAIN
+COOKIE_SECURE
+DATABASE_URL
+MAILER_DOMAIN
+MAILER_FROM_EMAIL
+MAILER_FROM_ALIAS
+MAILER_SMTP_HOST
+MAILER_SMTP_USERNAME
+MESSAGE_QUEUE_URL
+SESSION_STORE_URL
+VERIFICATION_TOKEN_ISSUER
+VERIFICATION_TOKEN_KEY_ID
+VERIFICATION_TOKEN_SECRET
+"#, || {
+                let dir = env::temp_dir();
+                let file = dir.join(format!("config-{}.toml", process::id()));
+                fs::write(
+                    &file,
+                    r#"
+[default]
+database_max_pool_size = 4
+mailer_smtp_port = 465
+rate_limit_api_per_minute = 60
+
+[production]
+database_max_pool_size = 24
+quota_notification_thresholds = [50, 90]
+
+[development]
+rate_limit_api_per_minute = 1
+"#,
+                )
+                .unwrap();
+                let secret = dir.join(format!("secret-{}", process::id()));
+                fs::write(&secret, "s3cret\n").unwrap();
+
+                env::set_var("CONFIG_FILE", &file);
+                env::set_var("MAILER_SMTP_PASSWORD_FILE", &secret);
+                env::set_var("MAILER_SMTP_PORT", "2525");
+
+                let c = Config::from("production").unwrap();
+                assert_eq!(c.database_max_pool_size, 24);
+                assert_eq!(c.mailer_smtp_password, "s3cret");
+                assert_eq!(c.mailer_smtp_port, 2525);
+                assert_eq!(c.quota_notification_thresholds, vec![50, 90]);
+                assert_eq!(c.rate_limit_api_per_minute, 60);
+
+                fs::remove_file(&file).unwrap();
+                fs::remove_file(&secret).unwrap();
+
+                let errors = Config::from("production").err().unwrap().errors;
+                assert!(errors[0].starts_with("CONFIG_FILE is invalid"));
+                assert!(errors[1]
+                    .starts_with("MAILER_SMTP_PASSWORD_FILE is unreadable"));
+            })
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_from_production_with_secrets_provider() {
+            with(r#"
+APPLICATION_URL
+AUTHENTICATION_TOKEN_ISSUER
+AUTHENTICATION_TOKEN_KEY_ID
+AUTHENTICATION_TOKEN_SECRET
+COOKIE_DOMAIN
+COOKIE_SECURE
+MAILER_DOMAIN
+MAILER_FROM_EMAIL
+MAILER_FROM_ALIAS
+MAILER_SMTP_HOST
+MAILER_SMTP_USERNAME
+MESSAGE_QUEUE_URL
+SESSION_STORE_URL
+VERIFICATION_TOKEN_ISSUER
+VERIFICATION_TOKEN_KEY_ID
+VERIFICATION_TOKEN_SECRET
+"#, || {
+                // a Vault server (KV version 2) which responds only once
+                let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+                let addr = listener.local_addr().unwrap();
+                let server = thread::spawn(move || {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut buf = [0; 1024];
+                    let n = stream.read(&mut buf).unwrap();
+                    let body = r#"{"data": {
+                        "data": {
+                            "database_url": "postgresql://localhost/vault",
+                            "MAILER_SMTP_PASSWORD": "s3cret"
+                        },
+                        "metadata": {"version": 2}
+                    }}"#;
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\n\
+                         Content-Type: application/json\r\n\
+                         Content-Length: {}\r\n\
+                         Connection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                    String::from_utf8_lossy(&buf[..n]).to_lowercase()
                 });
-                assert!(result.is_err());
+
+                env::set_var("SECRETS_PROVIDER", "vault");
+                env::set_var("SECRETS_VAULT_ADDR", format!("http://{}", addr));
+                env::set_var("SECRETS_VAULT_PATH", "secret/data/eloquentlog");
+                env::set_var("SECRETS_VAULT_TOKEN", "t0ken");
+                // the environment variable still wins
+                env::set_var("MAILER_SMTP_USERNAME", "user");
+
+                let c = Config::from("production").unwrap();
+                assert_eq!(c.database_url, "postgresql://localhost/vault");
+                assert_eq!(c.mailer_smtp_password, "s3cret");
+                assert_eq!(c.mailer_smtp_username, "user");
+                assert_eq!(c.secrets_provider, "vault");
+                assert_eq!(c.secrets_refresh_interval, 300);
+
+                let req = server.join().unwrap();
+                assert!(req.starts_with("get /v1/secret/data/eloquentlog "));
+                assert!(req.contains("x-vault-token: t0ken"));
+
+                // the server has gone
+                let errors = Config::from("production").err().unwrap().errors;
+                assert!(
+                    errors[0].starts_with("SECRETS_PROVIDER failed to fetch")
+                );
             })
         }
     }
@@ -423,11 +1661,13 @@ VERIFICATION_TOKEN_ISSUER
 VERIFICATION_TOKEN_KEY_ID
 VERIFICATION_TOKEN_SECRET
 "#, || {
-                let result = panic::catch_unwind(|| {
-                    let c = Config::from("testing");
-                    assert!(c.is_ok());
-                });
-                assert!(result.is_err());
+                let c = Config::from("testing");
+                assert!(c.is_err());
+
+                // lists all of the missing variables
+                let errors = c.err().unwrap().errors;
+                assert_eq!(errors.len(), 17);
+                assert_eq!(errors[0], "TEST_APPLICATION_URL is not set");
             })
         }
     }
@@ -455,11 +1695,13 @@ TEST_VERIFICATION_TOKEN_ISSUER
 TEST_VERIFICATION_TOKEN_KEY_ID
 TEST_VERIFICATION_TOKEN_SECRET
 "#, || {
-                let result = panic::catch_unwind(|| {
-                    let c = Config::from("development");
-                    assert!(c.is_ok());
-                });
-                assert!(result.is_err());
+                let c = Config::from("development");
+                assert!(c.is_err());
+
+                // lists all of the missing variables
+                let errors = c.err().unwrap().errors;
+                assert_eq!(errors.len(), 17);
+                assert_eq!(errors[0], "APPLICATION_URL is not set");
             })
         }
     }
@@ -489,10 +1731,137 @@ VERIFICATION_TOKEN_SECRET
 "#, || {
                 let c = Config::from("production").unwrap();
                 assert_eq!(c.env_name, "production");
+                assert!(!c.fault_injection);
                 assert!(c.cookie_secure);
                 assert_eq!(c.database_max_pool_size, 12);
+                assert_eq!(
+                    c.database_pool(),
+                    PoolConfig {
+                        max_size: 12,
+                        min_idle: None,
+                        connection_timeout: 30,
+                        idle_timeout: 600,
+                        max_lifetime: 1800,
+                    }
+                );
+                assert_eq!(
+                    c.message_queue_pool().idle_timeout(),
+                    Some(Duration::from_secs(600))
+                );
+                assert_eq!(c.database_replica_url, "");
+                assert_eq!(c.database_ssl_mode, "prefer");
+                assert_eq!(c.database_ssl_root_cert, "");
+                assert_eq!(c.database_connection_url(), c.database_url);
+                assert_eq!(c.database_statement_timeout, 30000);
                 assert_eq!(c.message_queue_max_pool_size, 8);
                 assert_eq!(c.session_store_max_pool_size, 8);
+                assert!(c.message_queue_tls_verify);
+                assert!(c.session_store_tls_verify);
+                assert_eq!(
+                    c.session_store_connection_url(),
+                    c.session_store_url
+                );
+                assert_eq!(c.analytics_salt, "");
+                assert_eq!(c.analytics_sample_rate, 0);
+                assert_eq!(c.authentication_backend, "local");
+                assert_eq!(c.backup_directory, "tmp/backup");
+                assert_eq!(c.concurrency_export_limit, 2);
+                assert_eq!(c.concurrency_retry_after, 5);
+                assert_eq!(c.concurrency_search_limit, 8);
+                assert_eq!(c.ingestion_deduplication_window, 0);
+                assert_eq!(c.ingestion_max_decompressed_size, 52_428_800);
+                assert_eq!(c.ldap_attribute_email, "mail");
+                assert!(c.ldap_group_roles.is_empty());
+                assert_eq!(c.ldap_user_filter, "(uid={username})");
+                assert_eq!(c.license_file, "");
+                assert_eq!(c.log_file, "");
+                assert_eq!(c.log_format, "terminal");
+                assert_eq!(c.log_level, "");
+                assert_eq!(c.log_syslog_facility, "");
+                assert_eq!(c.namespace_purge_grace_period, 2_592_000);
+                assert_eq!(c.oauth_github_client_id, "");
+                assert_eq!(c.oauth_google_client_id, "");
+                assert_eq!(c.otlp_endpoint, "");
+                assert_eq!(c.otlp_service_name, "eloquentlog-console-api");
+                assert_eq!(c.password_hash_iterations, 2);
+                assert_eq!(c.password_hash_memory_cost, 19_456);
+                assert_eq!(c.password_hash_parallelism, 1);
+                assert_eq!(c.push_apns_endpoint, "");
+                assert_eq!(c.push_fcm_endpoint, "");
+                assert!(c.email_disposable_domains.is_empty());
+                assert_eq!(c.email_mx_check_cache_ttl, 86_400);
+                assert_eq!(c.email_mx_check_url, "");
+                assert!(!c.email_normalize_gmail_dots);
+                assert_eq!(c.email_suggestion_distance, 2);
+                assert!(c
+                    .email_suggestion_domains
+                    .contains(&"gmail.com".to_string()));
+                assert_eq!(c.quota_bytes_per_day, 104_857_600);
+                assert_eq!(c.quota_flush_interval, 300);
+                assert_eq!(c.quota_grace_period, 3600);
+                assert_eq!(c.quota_messages_per_day, 100_000);
+                assert_eq!(c.quota_notification_thresholds, vec![80, 100]);
+                assert_eq!(c.recent_view_flush_interval, 300);
+                assert_eq!(c.recent_view_limit, 50);
+                assert_eq!(c.rate_limit_api_per_minute, 120);
+                assert_eq!(c.rate_limit_ingestion_per_minute, 600);
+                assert_eq!(c.rate_limit_login_per_minute, 10);
+                assert_eq!(c.rate_limit_waitlist_per_minute, 5);
+                assert!(!c.read_only);
+                assert_eq!(c.mailer_smtp_port, 587);
+                assert_eq!(c.mailer_smtp_security, MailerSecurity::Tls);
+                assert_eq!(c.mailer_transport, MailerTransport::Smtp);
+                assert_eq!(
+                    c.message_queue_backend,
+                    MessageQueueBackend::Redis
+                );
+                assert_eq!(c.secrets_provider, "none");
+                assert_eq!(c.secrets_refresh_interval, 300);
+                assert_eq!(c.sentry_dsn, "");
+                assert_eq!(c.server_address, "0.0.0.0");
+                assert_eq!(c.server_admin_token, "");
+                assert_eq!(c.server_keep_alive, 0);
+                assert_eq!(c.server_limit_json, 5_242_880);
+                assert_eq!(c.server_limit_json_auth, 4_096);
+                assert_eq!(c.server_limit_json_batch, 20_971_520);
+                assert_eq!(c.server_port, 80);
+                assert_eq!(c.server_secret_key, "");
+                assert!(c.server_trusted_proxies.is_empty());
+                assert_eq!(c.server_workers, 0);
+                assert!(c.slo_alert_burn_rate.abs() < f64::EPSILON);
+                assert_eq!(c.slo_alert_webhook_url, "");
+                assert!((c.slo_availability_target - 99.9).abs() < 1e-9);
+                assert!((c.slo_latency_target - 99.0).abs() < 1e-9);
+                assert_eq!(c.slo_latency_threshold, 500);
+                assert!(c.slo_windows.is_empty());
+                assert_eq!(c.stream_buffer_flush_interval, 0);
+                assert_eq!(c.sudo_mode_duration, Duration::from_secs(900));
+                assert_eq!(
+                    c.token_duration_confirmation,
+                    Duration::from_secs(300)
+                );
+                assert_eq!(
+                    c.token_duration_namespace_transfer,
+                    Duration::from_secs(86_400)
+                );
+                assert_eq!(
+                    c.token_duration_password_reset,
+                    Duration::from_secs(3600)
+                );
+                assert_eq!(
+                    c.token_duration_user_activation,
+                    Duration::from_secs(3600)
+                );
+                assert_eq!(
+                    c.url_template_message,
+                    "{application_url}/message/{namespace}/{stream}/{id}"
+                );
+                assert_eq!(
+                    c.url_template_user_activation,
+                    "{application_url}/user/activate?s={s}&t={t}"
+                );
+                assert_eq!(c.user_agent_sample_rate, 10);
+                assert_eq!(c.worker_heartbeat_timeout, 30);
             });
         }
     }
@@ -522,10 +1891,18 @@ TEST_VERIFICATION_TOKEN_SECRET
 "#, || {
                 let c = Config::from("testing").unwrap();
                 assert_eq!(c.env_name, "testing");
+                assert!(!c.fault_injection);
                 assert!(!c.cookie_secure);
                 assert_eq!(c.database_max_pool_size, 2);
                 assert_eq!(c.message_queue_max_pool_size, 2);
                 assert_eq!(c.session_store_max_pool_size, 2);
+                assert_eq!(c.server_port, 0);
+
+                let r = c.to_rocket_config().unwrap();
+                assert_eq!(r.address, "127.0.0.1");
+                assert_eq!(r.port, 0);
+                assert_eq!(r.limits.get("json"), Some(5_242_880));
+                assert_eq!(r.limits.get("protobuf"), Some(5_242_880));
             });
         }
     }
@@ -559,6 +1936,8 @@ VERIFICATION_TOKEN_SECRET
                 assert_eq!(c.database_max_pool_size, 4);
                 assert_eq!(c.message_queue_max_pool_size, 4);
                 assert_eq!(c.session_store_max_pool_size, 4);
+                assert_eq!(c.server_address, "127.0.0.1");
+                assert_eq!(c.server_port, 8000);
             });
         }
     }
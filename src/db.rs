@@ -1,19 +1,38 @@
 //! The database connection and its manager.
 use std::ops::Deref;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
 
 use rocket::http::Status;
 use rocket::request::{self, FromRequest};
 use rocket::{Request, State, Outcome};
 use diesel::{PgConnection, prelude::*};
-use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
+use diesel::result::{DatabaseErrorKind, Error};
 
-use crate::config::Config;
+use crate::config::{Config, PoolConfig};
+use crate::logger::Logger;
+use crate::service::pool_metrics::{PoolState, PoolStats};
+use crate::telemetry::{SpanKind, span};
+
+// attempts of a query in `with_retry` (including the first one)
+const RETRY_ATTEMPTS: u32 = 3;
+// milliseconds (doubled on each retry)
+const RETRY_BACKOFF: u64 = 50;
 
 pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 pub type DbPooledConn = PooledConnection<ConnectionManager<PgConnection>>;
 
 pub struct DbConn(pub DbPooledConn);
 
+/// A connection for read-only queries.
+///
+/// It's taken from the replica if configured, otherwise from the primary.
+/// Note that the replica may lag behind the primary, so it should not be used
+/// to read what has just been written in the same request.
+pub struct DbReadConn(pub DbPooledConn);
+
 // NOTE:
 // The type alias is not acceptable in this case because we have mulitple
 // connection pools in same type and `rocket::Rocket::manage` can handle state
@@ -22,14 +41,75 @@ pub struct DbConn(pub DbPooledConn);
 //
 // See also:
 // https://github.com/SergioBenitez/Rocket/issues/1053
+//
+// The pool can be replaced (e.g. for rotated credentials), and the clones of
+// the holder share it.
 #[derive(Clone)]
 pub struct DbPoolHolder {
-    pool: DbPool,
+    pool: Arc<RwLock<DbPool>>,
+    stats: PoolStats,
 }
 
 impl DbPoolHolder {
     pub fn get(&self) -> Option<DbPooledConn> {
-        self.pool.get().ok()
+        // doesn't hold the lock while waiting for a connection
+        let pool = self.pool.read().unwrap().clone();
+        get_traced(&pool, &self.stats)
+    }
+
+    pub fn state(&self) -> PoolState {
+        state_of(&self.pool.read().unwrap(), &self.stats)
+    }
+
+    /// Replaces the pool with new one for the url. The connections in use
+    /// are still valid until they are returned.
+    pub fn reconnect(
+        &self,
+        database_url: &str,
+        options: &PoolConfig,
+    ) -> Result<(), PoolError> {
+        let pool = build_pool(database_url, options)?;
+        *self.pool.write().unwrap() = pool;
+        Ok(())
+    }
+}
+
+// The pool of the replica (None if it's not configured).
+#[derive(Clone)]
+pub struct DbReplicaPoolHolder {
+    pool: Arc<RwLock<Option<DbPool>>>,
+    stats: PoolStats,
+}
+
+impl DbReplicaPoolHolder {
+    pub fn get(&self) -> Option<DbPooledConn> {
+        let pool = self.pool.read().unwrap().clone();
+        pool.and_then(|p| get_traced(&p, &self.stats))
+    }
+
+    pub fn state(&self) -> Option<PoolState> {
+        let pool = self.pool.read().unwrap();
+        pool.as_ref().map(|p| state_of(p, &self.stats))
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.pool.read().unwrap().is_some()
+    }
+
+    /// Replaces the pool like `DbPoolHolder::reconnect` (an empty url removes
+    /// the replica).
+    pub fn reconnect(
+        &self,
+        database_url: &str,
+        options: &PoolConfig,
+    ) -> Result<(), PoolError> {
+        let pool = if database_url.is_empty() {
+            None
+        } else {
+            Some(build_pool(database_url, options)?)
+        };
+        *self.pool.write().unwrap() = pool;
+        Ok(())
     }
 }
 
@@ -53,20 +133,223 @@ impl Deref for DbConn {
     }
 }
 
+impl<'a, 'r> FromRequest<'a, 'r> for DbReadConn {
+    type Error = ();
+
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<DbReadConn, ()> {
+        if let Outcome::Success(holder) =
+            req.guard::<State<DbReplicaPoolHolder>>()
+        {
+            if holder.is_configured() {
+                return match holder.get() {
+                    Some(conn) => Outcome::Success(DbReadConn(conn)),
+                    None => Outcome::Failure((Status::ServiceUnavailable, ())),
+                };
+            }
+        }
+        // falls back to the primary
+        let holder = req.guard::<State<DbPoolHolder>>()?;
+        match holder.get() {
+            Some(conn) => Outcome::Success(DbReadConn(conn)),
+            None => Outcome::Failure((Status::ServiceUnavailable, ())),
+        }
+    }
+}
+
+impl Deref for DbReadConn {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// waits for a connection in a span (see `telemetry`)
+fn get_traced(pool: &DbPool, stats: &PoolStats) -> Option<DbPooledConn> {
+    let s = span("db.pool.get", SpanKind::Client);
+    s.set("db.system", "postgresql");
+    let conn = stats.wait(|| pool.get().ok());
+    if conn.is_none() {
+        s.fail();
+    }
+    conn
+}
+
 // Returns a single connection.
 pub fn establish_connection(config: &Config) -> PgConnection {
-    PgConnection::establish(&config.database_url).unwrap_or_else(|_| {
+    let url = config.database_connection_url();
+    PgConnection::establish(&url).unwrap_or_else(|_| {
         panic!("Error connecting to : {}", &config.database_url)
     })
 }
 
-// Initializes db connection pool holder.
-pub fn init_pool_holder(database_url: &str, max_size: u32) -> DbPoolHolder {
+fn state_of(pool: &DbPool, stats: &PoolStats) -> PoolState {
+    let state = pool.state();
+    PoolState::new(
+        pool.max_size(),
+        state.connections,
+        state.idle_connections,
+        stats,
+    )
+}
+
+fn build_pool(
+    database_url: &str,
+    options: &PoolConfig,
+) -> Result<DbPool, PoolError> {
     let connection_manager =
         ConnectionManager::<PgConnection>::new(database_url);
-    let pool = Pool::builder()
-        .max_size(max_size)
+    Pool::builder()
+        .max_size(options.max_size)
+        .min_idle(options.min_idle)
+        .connection_timeout(options.connection_timeout())
+        .idle_timeout(options.idle_timeout())
+        .max_lifetime(options.max_lifetime())
         .build(connection_manager)
-        .expect("database pool");
-    DbPoolHolder { pool }
+}
+
+// Initializes db connection pool holder (the URL has the TLS options, see
+// `Config::database_connection_url`).
+pub fn init_pool_holder(
+    database_url: &str,
+    options: &PoolConfig,
+) -> DbPoolHolder {
+    let pool = build_pool(database_url, options).expect("database pool");
+    DbPoolHolder {
+        pool: Arc::new(RwLock::new(pool)),
+        stats: PoolStats::default(),
+    }
+}
+
+// Initializes db connection pool holder for the replica. The pool is not
+// created if the url is empty.
+pub fn init_replica_pool_holder(
+    database_url: &str,
+    options: &PoolConfig,
+) -> DbReplicaPoolHolder {
+    let pool = if database_url.is_empty() {
+        None
+    } else {
+        Some(build_pool(database_url, options).expect("database pool"))
+    };
+    DbReplicaPoolHolder {
+        pool: Arc::new(RwLock::new(pool)),
+        stats: PoolStats::default(),
+    }
+}
+
+/// Runs the queries in a transaction with `SET LOCAL statement_timeout`, so
+/// that a slow query can't hold the connection indefinitely. The timeout is in
+/// milliseconds (0 means no timeout).
+pub fn with_statement_timeout<T, F>(
+    conn: &PgConnection,
+    timeout: u64,
+    f: F,
+) -> QueryResult<T>
+where F: FnOnce() -> QueryResult<T> {
+    if timeout == 0 {
+        return traced(f);
+    }
+    traced(|| {
+        conn.transaction(|| {
+            diesel::sql_query(format!(
+                "SET LOCAL statement_timeout = {}",
+                timeout
+            ))
+            .execute(conn)?;
+            f()
+        })
+    })
+}
+
+/// Runs the query, and retries it a few times on serialization failures and
+/// deadlocks. This must not be used in a transaction, because the whole
+/// transaction fails in that case.
+pub fn with_retry<T, F>(logger: &Logger, mut f: F) -> QueryResult<T>
+where F: FnMut() -> QueryResult<T> {
+    let mut attempt = 1;
+    loop {
+        match traced(&mut f) {
+            Err(ref e) if attempt < RETRY_ATTEMPTS && is_transient(e) => {
+                warn!(logger, "retry ({}): {}", attempt, e);
+                let backoff = RETRY_BACKOFF * 2u64.pow(attempt - 1);
+                thread::sleep(Duration::from_millis(backoff));
+                attempt += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
+// runs the query in a span (see `telemetry`). Not found is not a failure.
+fn traced<T, F>(f: F) -> QueryResult<T>
+where F: FnOnce() -> QueryResult<T> {
+    let s = span("db.query", SpanKind::Client);
+    s.set("db.system", "postgresql");
+    let result = f();
+    match result {
+        Ok(_) | Err(Error::NotFound) => (),
+        Err(_) => s.fail(),
+    }
+    result
+}
+
+// the deadlock (40P01) has no kind in diesel
+fn is_transient(e: &Error) -> bool {
+    match e {
+        Error::DatabaseError(DatabaseErrorKind::SerializationFailure, _) => {
+            true
+        },
+        Error::DatabaseError(_, info) => {
+            info.message().starts_with("deadlock detected")
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::cell::Cell;
+
+    use crate::model::test::{CONFIG, run};
+
+    #[test]
+    fn test_with_retry() {
+        run(|_, _, logger| {
+            let count = Cell::new(0);
+            let result: QueryResult<i32> = with_retry(logger, || {
+                count.set(count.get() + 1);
+                Err(Error::DatabaseError(
+                    DatabaseErrorKind::SerializationFailure,
+                    Box::new("could not serialize access".to_string()),
+                ))
+            });
+            assert!(result.is_err());
+            assert_eq!(count.get(), RETRY_ATTEMPTS);
+
+            count.set(0);
+            let result: QueryResult<i32> = with_retry(logger, || {
+                count.set(count.get() + 1);
+                Err(Error::NotFound)
+            });
+            assert!(result.is_err());
+            assert_eq!(count.get(), 1);
+        })
+    }
+
+    #[test]
+    fn test_with_statement_timeout() {
+        let conn = establish_connection(&CONFIG);
+        let result = with_statement_timeout(&conn, 10, || {
+            diesel::sql_query("SELECT pg_sleep(1)").execute(&conn)
+        });
+        assert!(result.is_err());
+
+        let result = with_statement_timeout(&conn, 1000, || {
+            diesel::sql_query("SELECT 1").execute(&conn)
+        });
+        assert_eq!(result.ok(), Some(1));
+    }
 }
@@ -0,0 +1,419 @@
+//! Data factories for tests and seeds (see `cli::seed`).
+//!
+//! Each call makes a new valid record with unique values (e.g. the username
+//! and the email of a user), so that tests don't collide on fixed ones.
+//! The records are inserted directly (the validations and the jobs are
+//! skipped), and an error is a panic.
+//!
+//! ```rust,ignore
+//! let user = factory::user().insert(conn);
+//! let namespace = factory::namespace().with_owner(&user).insert(conn);
+//! let stream = factory::stream().namespace(&namespace).insert(conn);
+//! let ids = factory::message().stream(&stream).count(100).insert(conn);
+//! ```
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::pg::PgConnection;
+use uuid::Uuid;
+
+use crate::id::{IdGenerator, RandomIdGenerator};
+use crate::model::membership::{MembershipRole, memberships};
+use crate::model::message::{
+    AgentType, LogFormat, LogLevel, NewMessage, messages,
+};
+use crate::model::namespace::{Namespace, namespaces};
+use crate::model::password_hash::{PasswordHashParams, hash};
+use crate::model::stream::{Stream, streams};
+use crate::model::user::{User, UserResetPasswordState, UserState, users};
+use crate::model::user_email::{
+    UserEmailIdentificationState, UserEmailRole, user_emails,
+};
+
+/// The raw password of users by `user` (unless it's given).
+pub const PASSWORD: &str = "Pa$$w0rd";
+
+// the same ones as `TEST_PASSWORD_HASH_*` (cheap)
+const PASSWORD_HASH_PARAMS: PasswordHashParams = PasswordHashParams {
+    memory_cost: 128,
+    iterations: 1,
+    parallelism: 1,
+};
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(1);
+
+// unique also over the processes which share the database
+fn next() -> String {
+    let n = SEQUENCE.fetch_add(1, Ordering::SeqCst);
+    format!("{}_{}", process::id(), n)
+}
+
+/// Returns a factory of an active user with a primary (identified) email.
+pub fn user() -> UserFactory {
+    let n = next();
+    UserFactory {
+        name: Some(format!("User {}", n)),
+        username: format!("user_{}", n),
+        email: format!("user_{}@example.org", n),
+        password: PASSWORD.to_string(),
+        state: UserState::Active,
+    }
+}
+
+/// Returns a factory of a namespace (without any member).
+pub fn namespace() -> NamespaceFactory {
+    NamespaceFactory {
+        name: format!("namespace {}", next()),
+        description: None,
+        owner_id: None,
+        member_ids: vec![],
+    }
+}
+
+/// Returns a factory of a stream. The namespace must be given.
+pub fn stream() -> StreamFactory {
+    StreamFactory {
+        namespace_id: None,
+        name: format!("stream {}", next()),
+        description: None,
+    }
+}
+
+/// Returns a factory of a message. The stream must be given.
+pub fn message() -> MessageFactory {
+    MessageFactory {
+        stream_id: None,
+        agent_id: 0,
+        level: LogLevel::Information,
+        title: None,
+        content: None,
+        range: None,
+        count: 1,
+    }
+}
+
+pub struct UserFactory {
+    name: Option<String>,
+    username: String,
+    email: String,
+    password: String,
+    state: UserState,
+}
+
+impl UserFactory {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = password.to_string();
+        self
+    }
+
+    pub fn state(mut self, state: UserState) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn insert(self, conn: &PgConnection) -> User {
+        let password = hash(&self.password, &PASSWORD_HASH_PARAMS)
+            .expect("failed to hash the password");
+        conn.transaction::<_, diesel::result::Error, _>(|| {
+            let user = diesel::insert_into(users::table)
+                .values((
+                    users::name.eq(&self.name),
+                    users::username.eq(&self.username),
+                    users::email.eq(&self.email),
+                    users::password.eq(&password),
+                    users::state.eq(&self.state),
+                    users::reset_password_state
+                        .eq(UserResetPasswordState::Never),
+                ))
+                .get_result::<User>(conn)?;
+            diesel::insert_into(user_emails::table)
+                .values((
+                    user_emails::user_id.eq(user.id),
+                    user_emails::email.eq(&user.email),
+                    user_emails::role.eq(UserEmailRole::Primary),
+                    user_emails::identification_state
+                        .eq(UserEmailIdentificationState::Done),
+                ))
+                .execute(conn)?;
+            Ok(user)
+        })
+        .unwrap_or_else(|e| panic!("failed to insert a user: {}", e))
+    }
+}
+
+pub struct NamespaceFactory {
+    name: String,
+    description: Option<String>,
+    owner_id: Option<i64>,
+    member_ids: Vec<i64>,
+}
+
+impl NamespaceFactory {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Makes the user the primary owner of the namespace.
+    pub fn with_owner(mut self, user: &User) -> Self {
+        self.owner_id = Some(user.id);
+        self
+    }
+
+    /// Adds the user as a member of the namespace.
+    pub fn with_member(mut self, user: &User) -> Self {
+        self.member_ids.push(user.id);
+        self
+    }
+
+    pub fn insert(self, conn: &PgConnection) -> Namespace {
+        conn.transaction::<_, diesel::result::Error, _>(|| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values((
+                    namespaces::uuid.eq(Uuid::new_v4()),
+                    namespaces::name.eq(&self.name),
+                    namespaces::description.eq(&self.description),
+                ))
+                .get_result::<Namespace>(conn)?;
+            let mut roles = vec![];
+            if let Some(id) = self.owner_id {
+                roles.push((id, MembershipRole::PrimaryOwner));
+            }
+            for id in &self.member_ids {
+                roles.push((*id, MembershipRole::Member));
+            }
+            for (user_id, role) in roles {
+                diesel::insert_into(memberships::table)
+                    .values((
+                        memberships::namespace_id.eq(namespace.id),
+                        memberships::user_id.eq(user_id),
+                        memberships::role.eq(role),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(namespace)
+        })
+        .unwrap_or_else(|e| panic!("failed to insert a namespace: {}", e))
+    }
+}
+
+pub struct StreamFactory {
+    namespace_id: Option<i64>,
+    name: String,
+    description: Option<String>,
+}
+
+impl StreamFactory {
+    pub fn namespace(mut self, namespace: &Namespace) -> Self {
+        self.namespace_id = Some(namespace.id);
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn insert(self, conn: &PgConnection) -> Stream {
+        let namespace_id = self.namespace_id.expect("namespace is not given");
+        diesel::insert_into(streams::table)
+            .values((
+                streams::uuid.eq(Uuid::new_v4()),
+                streams::namespace_id.eq(namespace_id),
+                streams::name.eq(&self.name),
+                streams::description.eq(&self.description),
+            ))
+            .get_result::<Stream>(conn)
+            .unwrap_or_else(|e| panic!("failed to insert a stream: {}", e))
+    }
+}
+
+pub struct MessageFactory {
+    stream_id: Option<i64>,
+    agent_id: i64,
+    level: LogLevel,
+    title: Option<String>,
+    content: Option<String>,
+    range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    count: usize,
+}
+
+impl MessageFactory {
+    pub fn stream(mut self, stream: &Stream) -> Self {
+        self.stream_id = Some(stream.id);
+        self
+    }
+
+    /// Makes the user the agent of the messages.
+    pub fn agent(mut self, user: &User) -> Self {
+        self.agent_id = user.id;
+        self
+    }
+
+    pub fn level(mut self, level: LogLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// The title of the messages (a unique one by default).
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn content(mut self, content: &str) -> Self {
+        self.content = Some(content.to_string());
+        self
+    }
+
+    /// Spreads the messages over the time range at even intervals (they are
+    /// created now by default).
+    pub fn between(mut self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        self.range = Some((from, to));
+        self
+    }
+
+    /// The number of messages to insert (1 by default).
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Inserts the messages, and returns their ids.
+    pub fn insert(self, conn: &PgConnection) -> Vec<String> {
+        let stream_id = self.stream_id.expect("stream is not given");
+        conn.transaction::<_, diesel::result::Error, _>(|| {
+            (0..self.count)
+                .map(|i| {
+                    let n = next();
+                    let at = self.time_of(i);
+                    let m = NewMessage {
+                        agent_id: self.agent_id,
+                        agent_type: AgentType::Person,
+                        stream_id,
+                        code: None,
+                        lang: "en".to_string(),
+                        level: self.level.clone(),
+                        format: LogFormat::TOML,
+                        title: Some(self.title.clone().unwrap_or_else(
+                            || format!("message {}", n),
+                        )),
+                        content: Some(self.content.clone().unwrap_or_else(
+                            || format!("content of message {}", n),
+                        )),
+                        trace_id: None,
+                        span_id: None,
+                        hostname: None,
+                        service: None,
+                        environment: None,
+                        tags: vec![],
+                    };
+                    let id = RandomIdGenerator.ulid(at);
+                    diesel::insert_into(messages::table)
+                        .values((
+                            messages::id.eq(&id),
+                            &m,
+                            messages::created_at.eq(at.naive_utc()),
+                            messages::updated_at.eq(at.naive_utc()),
+                        ))
+                        .returning(messages::id)
+                        .get_result::<String>(conn)
+                })
+                .collect()
+        })
+        .unwrap_or_else(|e| panic!("failed to insert messages: {}", e))
+    }
+
+    fn time_of(&self, i: usize) -> DateTime<Utc> {
+        match self.range {
+            Some((from, to)) => {
+                let step = (to - from) / self.count.max(1) as i32;
+                from + step * i as i32
+            },
+            None => Utc::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::membership::Membership;
+    use crate::model::password_hash::verify;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_user() {
+        run(|conn, _, _| {
+            let a = user().insert(conn);
+            let b = user().password("secret").insert(conn);
+            assert_ne!(a.username, b.username);
+            assert_ne!(a.email, b.email);
+            assert_eq!(a.state, UserState::Active);
+            assert!(verify(PASSWORD, &a.password));
+            assert!(verify("secret", &b.password));
+
+            let emails: i64 = user_emails::table
+                .filter(user_emails::user_id.eq(a.id))
+                .count()
+                .get_result(conn)
+                .unwrap();
+            assert_eq!(emails, 1);
+        })
+    }
+
+    #[test]
+    fn test_namespace_with_owner() {
+        run(|conn, _, _| {
+            let u = user().insert(conn);
+            let a = namespace().with_owner(&u).insert(conn);
+            let b = namespace().insert(conn);
+            assert_ne!(a.name, b.name);
+
+            let ms = memberships::table
+                .filter(memberships::namespace_id.eq(a.id))
+                .load::<Membership>(conn)
+                .unwrap();
+            assert_eq!(ms.len(), 1);
+            assert_eq!(ms[0].user_id, u.id);
+            assert_eq!(ms[0].role, MembershipRole::PrimaryOwner);
+
+            let count: i64 = memberships::table
+                .filter(memberships::namespace_id.eq(b.id))
+                .count()
+                .get_result(conn)
+                .unwrap();
+            assert_eq!(count, 0);
+        })
+    }
+
+    #[test]
+    fn test_message_count() {
+        run(|conn, _, _| {
+            let ns = namespace().insert(conn);
+            let s = stream().namespace(&ns).insert(conn);
+            let ids = message().stream(&s).count(100).insert(conn);
+            assert_eq!(ids.len(), 100);
+
+            let count: i64 = messages::table
+                .filter(messages::stream_id.eq(s.id))
+                .count()
+                .get_result(conn)
+                .unwrap();
+            assert_eq!(count, 100);
+        })
+    }
+}
@@ -0,0 +1,292 @@
+//! # GraphQL schema
+//!
+//! A read-only schema over users, namespaces, memberships, streams and
+//! messages. The resolvers are thin wrappers of the Diesel models, and every
+//! query starts from the current user, so the visibility is the same as the
+//! REST API.
+use chrono::NaiveDateTime;
+use juniper::{
+    EmptyMutation, EmptySubscription, FieldResult, ID, RootNode,
+    graphql_object,
+};
+use parking_lot::Mutex;
+
+use crate::db::DbConn;
+use crate::logger::Logger;
+use crate::model::membership::Membership;
+use crate::model::message::{LogLevel, Message};
+use crate::model::namespace::Namespace;
+use crate::model::stream::Stream;
+use crate::model::user::User;
+
+const DEFAULT_PAGE_SIZE: i32 = 25;
+const MAX_PAGE_SIZE: i32 = 100;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+pub type Schema = RootNode<
+    'static,
+    Query,
+    EmptyMutation<Context>,
+    EmptySubscription<Context>,
+>;
+
+pub fn schema() -> Schema {
+    Schema::new(Query, EmptyMutation::new(), EmptySubscription::new())
+}
+
+/// Context is created per request.
+///
+/// The connection is wrapped with a mutex because the context must be Sync.
+pub struct Context {
+    pub conn: Mutex<DbConn>,
+    pub logger: Logger,
+    pub user: User,
+}
+
+impl juniper::Context for Context {}
+
+// returns (offset, limit) within the range
+fn paginate(first: Option<i32>, offset: Option<i32>) -> (i64, i64) {
+    let limit = first.unwrap_or(DEFAULT_PAGE_SIZE).max(0).min(MAX_PAGE_SIZE);
+    let offset = offset.unwrap_or(0).max(0);
+    (offset as i64, limit as i64)
+}
+
+fn format_timestamp(t: &NaiveDateTime) -> String {
+    t.format(TIMESTAMP_FORMAT).to_string()
+}
+
+pub struct Query;
+
+#[graphql_object(context = Context)]
+impl Query {
+    /// The current user
+    fn me(context: &Context) -> UserNode {
+        UserNode(context.user.clone())
+    }
+
+    /// Namespaces which the current user belongs to
+    fn namespaces(
+        context: &Context,
+        first: Option<i32>,
+        offset: Option<i32>,
+    ) -> FieldResult<Vec<NamespaceNode>> {
+        let (offset, limit) = paginate(first, offset);
+        let conn = context.conn.lock();
+        let namespaces =
+            Namespace::find_all(&context.user, &conn, &context.logger)
+                .ok_or("namespaces are not available")?;
+        Ok(namespaces
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(NamespaceNode)
+            .collect())
+    }
+
+    fn namespace(context: &Context, uuid: String) -> Option<NamespaceNode> {
+        let conn = context.conn.lock();
+        Namespace::find_by_uuid(&uuid, &context.user, &conn, &context.logger)
+            .map(NamespaceNode)
+    }
+}
+
+pub struct UserNode(User);
+
+#[graphql_object(name = "User", context = Context)]
+impl UserNode {
+    fn uuid(&self) -> String {
+        self.0.uuid.to_string()
+    }
+
+    fn name(&self) -> Option<String> {
+        self.0.name.clone()
+    }
+
+    fn username(&self) -> String {
+        self.0.username.clone()
+    }
+
+    fn email(&self) -> String {
+        self.0.email.clone()
+    }
+}
+
+pub struct NamespaceNode(Namespace);
+
+#[graphql_object(name = "Namespace", context = Context)]
+impl NamespaceNode {
+    fn uuid(&self) -> String {
+        self.0.uuid.to_string()
+    }
+
+    fn name(&self) -> String {
+        self.0.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.0.description.clone()
+    }
+
+    fn streams_count(&self) -> i32 {
+        self.0.streams_count as i32
+    }
+
+    fn created_at(&self) -> String {
+        format_timestamp(&self.0.created_at)
+    }
+
+    fn updated_at(&self) -> String {
+        format_timestamp(&self.0.updated_at)
+    }
+
+    fn memberships(
+        &self,
+        context: &Context,
+    ) -> FieldResult<Vec<MembershipNode>> {
+        let conn = context.conn.lock();
+        let memberships = Membership::find_all_by_namespace_id(
+            self.0.id,
+            &conn,
+            &context.logger,
+        )
+        .ok_or("memberships are not available")?;
+        Ok(memberships.into_iter().map(MembershipNode).collect())
+    }
+
+    fn streams(&self, context: &Context) -> FieldResult<Vec<StreamNode>> {
+        let conn = context.conn.lock();
+        let streams =
+            Stream::find_all_by_namespace_id(self.0.id, &conn, &context.logger)
+                .ok_or("streams are not available")?;
+        Ok(streams.into_iter().map(StreamNode).collect())
+    }
+}
+
+pub struct MembershipNode(Membership);
+
+#[graphql_object(name = "Membership", context = Context)]
+impl MembershipNode {
+    fn role(&self) -> String {
+        self.0.role.to_string()
+    }
+
+    fn created_at(&self) -> String {
+        format_timestamp(&self.0.created_at)
+    }
+
+    fn user(&self, context: &Context) -> Option<UserNode> {
+        let conn = context.conn.lock();
+        User::find_by_id(self.0.user_id, &conn, &context.logger).map(UserNode)
+    }
+}
+
+pub struct StreamNode(Stream);
+
+#[graphql_object(name = "Stream", context = Context)]
+impl StreamNode {
+    fn uuid(&self) -> String {
+        self.0.uuid.to_string()
+    }
+
+    fn name(&self) -> String {
+        self.0.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.0.description.clone()
+    }
+
+    fn created_at(&self) -> String {
+        format_timestamp(&self.0.created_at)
+    }
+
+    /// Messages on the stream (newest first)
+    ///
+    /// The level is like "debug", "information", "warning", "error" or
+    /// "critical".
+    fn messages(
+        &self,
+        context: &Context,
+        level: Option<String>,
+        first: Option<i32>,
+        offset: Option<i32>,
+    ) -> FieldResult<Vec<MessageNode>> {
+        let level = match level {
+            None => None,
+            Some(l) => Some(
+                LogLevel::iter()
+                    .find(|v| v.to_string() == l.to_lowercase())
+                    .cloned()
+                    .ok_or("unknown level")?,
+            ),
+        };
+        let (offset, limit) = paginate(first, offset);
+        if limit < 1 {
+            return Ok(vec![]);
+        }
+
+        let conn = context.conn.lock();
+        let messages = Message::find_all_by_stream_id(
+            self.0.id,
+            level,
+            offset,
+            limit,
+            &conn,
+            &context.logger,
+        )
+        .ok_or("messages are not available")?;
+        Ok(messages.into_iter().map(MessageNode).collect())
+    }
+}
+
+pub struct MessageNode(Message);
+
+#[graphql_object(name = "Message", context = Context)]
+impl MessageNode {
+    fn id(&self) -> ID {
+        ID::new(self.0.id.to_string())
+    }
+
+    fn code(&self) -> Option<String> {
+        self.0.code.clone()
+    }
+
+    fn lang(&self) -> String {
+        self.0.lang.clone()
+    }
+
+    fn level(&self) -> String {
+        self.0.level.to_string()
+    }
+
+    fn format(&self) -> String {
+        self.0.format.to_string()
+    }
+
+    fn title(&self) -> String {
+        self.0.title.clone()
+    }
+
+    fn content(&self) -> Option<String> {
+        self.0.content.clone()
+    }
+
+    fn created_at(&self) -> String {
+        format_timestamp(&self.0.created_at)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_paginate() {
+        assert_eq!(paginate(None, None), (0, 25));
+        assert_eq!(paginate(Some(10), Some(20)), (20, 10));
+        assert_eq!(paginate(Some(1000), None), (0, 100));
+        assert_eq!(paginate(Some(-1), Some(-1)), (0, 0));
+    }
+}
@@ -0,0 +1,180 @@
+//! Id generation
+//!
+//! Random hashes (tokens, session ids) and ULIDs (message ids) are generated
+//! through an `IdGenerator`, so that tests can use a seeded one.
+//!
+//! A ULID is 48 bits of the time in milliseconds and 80 random bits, encoded
+//! in 26 characters of Crockford's Base32 (e.g. `01F8MECHZX3TBDSZ7XRADM79XE`).
+//! They are sorted by the time also as strings, and are generated on the
+//! application side (no sequence on PostgreSQL).
+//!
+//! The server manages a `SharedIdGenerator` as state
+//! (`State<SharedIdGenerator>`).
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::rngs::StdRng;
+
+const CROCKFORD_BASE32: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+pub const ULID_LENGTH: usize = 26;
+
+pub trait IdGenerator: Send + Sync {
+    /// Returns a random string of the length from the source characters.
+    fn random_hash(&self, source: &[u8], length: usize) -> String;
+
+    /// Returns a new ULID at the time.
+    fn ulid(&self, now: DateTime<Utc>) -> String;
+}
+
+pub type SharedIdGenerator = Arc<dyn IdGenerator>;
+
+fn random_hash<R: Rng>(rng: &mut R, source: &[u8], length: usize) -> String {
+    (0..length)
+        .map(|_| char::from(source[rng.gen_range(0..source.len())]))
+        .collect()
+}
+
+fn ulid<R: Rng>(rng: &mut R, now: DateTime<Utc>) -> String {
+    let time = now.timestamp_millis().max(0) as u128 & 0xffff_ffff_ffff;
+    let randomness = rng.gen::<u128>() & ((1 << 80) - 1);
+    encode_ulid((time << 80) | randomness)
+}
+
+// 5 bits per character from the most significant ones (the first character
+// has only 3 bits)
+fn encode_ulid(value: u128) -> String {
+    (0..ULID_LENGTH)
+        .rev()
+        .map(|i| {
+            let idx = (value >> (i * 5)) & 0x1f;
+            char::from(CROCKFORD_BASE32[idx as usize])
+        })
+        .collect()
+}
+
+/// Returns true if the value looks like a ULID.
+pub fn is_ulid(value: &str) -> bool {
+    value.len() == ULID_LENGTH &&
+        value.as_bytes()[0] <= b'7' &&
+        value.bytes().all(|c| CROCKFORD_BASE32.contains(&c))
+}
+
+/// The generator with the thread-local random number generator.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomIdGenerator;
+
+impl IdGenerator for RandomIdGenerator {
+    fn random_hash(&self, source: &[u8], length: usize) -> String {
+        random_hash(&mut thread_rng(), source, length)
+    }
+
+    fn ulid(&self, now: DateTime<Utc>) -> String {
+        ulid(&mut thread_rng(), now)
+    }
+}
+
+impl RandomIdGenerator {
+    pub fn shared() -> SharedIdGenerator {
+        Arc::new(Self)
+    }
+}
+
+/// A generator which returns the same values for the seed (for tests).
+#[derive(Debug)]
+pub struct SeededIdGenerator {
+    rng: Mutex<StdRng>,
+}
+
+impl IdGenerator for SeededIdGenerator {
+    fn random_hash(&self, source: &[u8], length: usize) -> String {
+        random_hash(&mut *self.rng.lock(), source, length)
+    }
+
+    fn ulid(&self, now: DateTime<Utc>) -> String {
+        ulid(&mut *self.rng.lock(), now)
+    }
+}
+
+impl SeededIdGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_encode_ulid() {
+        assert_eq!(encode_ulid(0), "00000000000000000000000000");
+        assert_eq!(encode_ulid(u128::MAX), "7ZZZZZZZZZZZZZZZZZZZZZZZZZ");
+
+        // the example in the spec (1469918176385 ms)
+        let time = 1_469_918_176_385u128 << 80;
+        assert!(encode_ulid(time).starts_with("01ARYZ6S41"));
+    }
+
+    #[test]
+    fn test_ulid() {
+        let t = Utc.ymd(2019, 7, 7).and_hms(7, 20, 15);
+        let ids = RandomIdGenerator;
+
+        let a = ids.ulid(t);
+        assert!(is_ulid(&a));
+        assert_eq!(a[..10], ids.ulid(t)[..10]);
+        assert_ne!(a, ids.ulid(t));
+
+        // sortable by the time
+        assert!(a < ids.ulid(t + Duration::milliseconds(1)));
+        assert!(a > ids.ulid(t - Duration::milliseconds(1)));
+    }
+
+    #[test]
+    fn test_is_ulid() {
+        assert!(is_ulid("01ARYZ6S41TSV4RRFFQ69G5FAV"));
+
+        assert!(!is_ulid(""));
+        assert!(!is_ulid("01ARYZ6S41TSV4RRFFQ69G5FA"));
+        assert!(!is_ulid("81ARYZ6S41TSV4RRFFQ69G5FAV"));
+        assert!(!is_ulid("01ARYZ6S41TSV4RRFFQ69G5FAU"));
+        assert!(!is_ulid("01aryz6s41tsv4rrffq69g5fav"));
+    }
+
+    #[test]
+    fn test_random_hash_length() {
+        let s = b".";
+        assert_eq!(RandomIdGenerator.random_hash(s, 0), "".to_string());
+        assert_eq!(RandomIdGenerator.random_hash(s, 1), ".".to_string());
+        assert_eq!(RandomIdGenerator.random_hash(s, 6), "......".to_string());
+    }
+
+    #[test]
+    fn test_random_hash_source() {
+        let s = b"abcdefghijklmnopqrstuvwxyz";
+        let t = String::from_utf8_lossy(s);
+        let value = RandomIdGenerator.random_hash(s, 128);
+
+        for v in value.chars() {
+            assert!(t.contains(v));
+        }
+    }
+
+    #[test]
+    fn test_seeded_id_generator() {
+        let t = Utc.ymd(2019, 7, 7).and_hms(7, 20, 15);
+        let a = SeededIdGenerator::new(7);
+        let b = SeededIdGenerator::new(7);
+
+        assert_eq!(a.ulid(t), b.ulid(t));
+        assert_eq!(a.random_hash(b"abc", 9), b.random_hash(b"abc", 9));
+        assert_ne!(a.ulid(t), SeededIdGenerator::new(8).ulid(t));
+    }
+}
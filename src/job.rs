@@ -1,19 +1,93 @@
+use std::collections::BTreeSet;
 use std::convert::Into;
 use std::fmt;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
 
 use diesel::PgConnection;
 use diesel::result::Error;
+use fourche::queue::Queue;
+use postgres::{Client as PgClient, NoTls};
+use redis::{Client, Commands, Connection};
 use slog::Logger;
 
+use crate::clock::Clock;
 use crate::config::Config;
+use crate::id::is_ulid;
+use crate::model::alert_schedule::AlertSchedule;
+use crate::model::analytics_event::AnalyticsEvent;
+use crate::model::bulk_operation::{
+    BATCH_SIZE, BulkOperation, BulkOperationAction, BulkOperationState,
+};
+use crate::model::channel::Channel;
+use crate::model::incident::Incident;
+use crate::model::incident_message::IncidentMessage;
+use crate::model::membership::Membership;
+use crate::model::message::{
+    LogLevel, Message, MessageFilter, StatsInterval,
+};
+use crate::model::message_count::HourlyMessageCount;
+use crate::model::namespace::Namespace;
+use crate::model::namespace_usage::NamespaceUsage;
+use crate::model::notification_preference::NotificationEvent;
+use crate::model::push_delivery::{NewPushDelivery, PushDelivery};
+use crate::model::push_device::PushDevice;
+use crate::model::recent_view::RecentView;
+use crate::model::stream::Stream;
+use crate::model::usage_record::UsageRecord;
 use crate::model::user::User;
 use crate::model::user_email::UserEmail;
+use crate::model::waitlist_entry::WaitlistEntry;
+use crate::mailer::security::{SecurityMailer, SecurityNotice};
 use crate::mailer::user::UserMailer;
+use crate::request::analytics::{KEY as ANALYTICS_KEY, PendingEvent};
+use crate::request::quota::{KEY_PREFIX, parse_counter_key};
+use crate::request::recent_view::{
+    KEY_PREFIX as VIEW_KEY_PREFIX, parse_view_key, parse_view_member,
+    score_to_datetime,
+};
+use crate::service::alert_rollup::AlertRollup;
+use crate::service::anomaly_detector::{
+    AnomalyDetector, RULE as ANOMALY_RULE,
+};
+use crate::service::channel_notifier::ChannelNotifier;
+use crate::service::namespace_backup::{NamespaceBackup, verify_all};
+use crate::service::namespace_purger::NamespacePurger;
+use crate::service::notifier::{Delivery, Notifier};
+use crate::service::payload_template::PayloadContext;
+use crate::service::push_notifier::{Outcome, PushNotification, PushNotifier};
+use crate::service::quiet_hours::QuietHours;
+use crate::service::slo::Slo;
+use crate::service::stream_buffer::StreamBuffer;
+
+/// The sorted set of the jobs deferred to later (the score is the unix time to
+/// run them).
+pub const DEFERRED_KEY: &str = "deferred";
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum JobKind {
     SendUserActivationEmail,
     SendPasswordResetEmail,
+    FlushNamespaceUsages,
+    SendWaitlistConfirmationEmail,
+    SendQuotaNotificationEmail,
+    ExportNamespaceBackup,
+    ImportNamespaceBackup,
+    FlushRecentViews,
+    ApplyBulkOperation,
+    DeliverAlert,
+    SendAlertEmail,
+    PurgeNamespaces,
+    SendNamespaceTransferEmail,
+    VerifyArchives,
+    FlushStreamBuffers,
+    RollupMessageCounts,
+    CheckSloBurnRates,
+    SendPushNotification,
+    FlushAnalyticsEvents,
+    SendSecurityNotificationEmail,
+    DetectAnomalies,
 }
 
 impl fmt::Display for JobKind {
@@ -35,6 +109,7 @@ where T: Clone + fmt::Debug + Into<String>
         &self,
         db_conn: &PgConnection,
         config: &Config,
+        clock: &dyn Clock,
         logger: &Logger,
     ) {
         match self.kind {
@@ -44,6 +119,63 @@ where T: Clone + fmt::Debug + Into<String>
             JobKind::SendPasswordResetEmail => {
                 self.send_password_reset_email(db_conn, config, logger);
             },
+            JobKind::FlushNamespaceUsages => {
+                self.flush_namespace_usages(db_conn, config, logger);
+            },
+            JobKind::SendWaitlistConfirmationEmail => {
+                self.send_waitlist_confirmation_email(db_conn, config, logger);
+            },
+            JobKind::SendQuotaNotificationEmail => {
+                self.send_quota_notification_email(db_conn, config, logger);
+            },
+            JobKind::ExportNamespaceBackup => {
+                self.export_namespace_backup(db_conn, config, clock, logger);
+            },
+            JobKind::ImportNamespaceBackup => {
+                self.import_namespace_backup(db_conn, config, logger);
+            },
+            JobKind::FlushRecentViews => {
+                self.flush_recent_views(db_conn, config, logger);
+            },
+            JobKind::ApplyBulkOperation => {
+                self.apply_bulk_operation(db_conn, config, clock, logger);
+            },
+            JobKind::DeliverAlert => {
+                self.deliver_alert(db_conn, config, logger);
+            },
+            JobKind::SendAlertEmail => {
+                self.send_alert_email(db_conn, config, clock, logger);
+            },
+            JobKind::PurgeNamespaces => {
+                self.purge_namespaces(db_conn, config, clock, logger);
+            },
+            JobKind::SendNamespaceTransferEmail => {
+                self.send_namespace_transfer_email(db_conn, config, logger);
+            },
+            JobKind::VerifyArchives => {
+                self.verify_archives(config, logger);
+            },
+            JobKind::FlushStreamBuffers => {
+                self.flush_stream_buffers(db_conn, config, clock, logger);
+            },
+            JobKind::RollupMessageCounts => {
+                self.rollup_message_counts(db_conn, clock, logger);
+            },
+            JobKind::CheckSloBurnRates => {
+                self.check_slo_burn_rates(config, clock, logger);
+            },
+            JobKind::SendPushNotification => {
+                self.send_push_notification(db_conn, config, clock, logger);
+            },
+            JobKind::FlushAnalyticsEvents => {
+                self.flush_analytics_events(db_conn, config, logger);
+            },
+            JobKind::SendSecurityNotificationEmail => {
+                self.send_security_notification_email(db_conn, config, logger);
+            },
+            JobKind::DetectAnomalies => {
+                self.detect_anomalies(db_conn, config, clock, logger);
+            },
         }
     }
 
@@ -147,4 +279,1300 @@ where T: Clone + fmt::Debug + Into<String>
             }
         });
     }
+
+    fn send_waitlist_confirmation_email(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.len() < 2 {
+            return;
+        }
+
+        let entry_id = args[0].clone().into().parse::<i64>().unwrap();
+        let token = args[1].clone().into();
+
+        match WaitlistEntry::find_by_id(entry_id, db_conn, &logger) {
+            Some(ref entry) if !entry.is_confirmed() => {
+                let mut mailer = UserMailer::new(config, logger);
+                mailer
+                    .to((&entry.email, ""))
+                    .send_waitlist_confirmation_email(&token);
+            },
+            Some(_) => info!(logger, "already confirmed"),
+            None => error!(logger, "not found :'("),
+        }
+    }
+
+    fn send_quota_notification_email(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.len() < 2 {
+            return;
+        }
+
+        let namespace_key: String = args[0].clone().into();
+        let percent = args[1].clone().into().parse::<u64>().unwrap();
+
+        let namespace =
+            match Namespace::find_by_key(&namespace_key, db_conn, logger) {
+                Some(n) => n,
+                None => {
+                    error!(logger, "not found :'(");
+                    return;
+                },
+            };
+        let owners =
+            User::find_all_owners_by_namespace_id(namespace.id, db_conn, logger)
+                .unwrap_or_else(Vec::new);
+        let notifier = Notifier::new(db_conn, logger);
+        let mut recipients: Vec<(&str, &str)> = vec![];
+        for u in owners.iter() {
+            match notifier.delivery(u, NotificationEvent::Quota) {
+                Delivery::Email => recipients
+                    .push((u.email.as_str(), u.name.as_deref().unwrap_or(""))),
+                Delivery::Webhook(ref url) => {
+                    let payload = serde_json::json!({
+                        "event": NotificationEvent::Quota.to_string(),
+                        "namespace": namespace.name,
+                        "percent": percent,
+                    });
+                    if let Err(e) = notifier.post(url, &payload) {
+                        error!(logger, "err: {}", e);
+                    }
+                },
+                Delivery::Skip => info!(logger, "opted out: {}", u.uuid),
+            }
+        }
+        // a message per owner (with the name) in a batch
+        let mut mailer = UserMailer::new(config, logger);
+        let sent = mailer.send_quota_notification_emails(
+            &recipients,
+            &namespace.name,
+            percent,
+        );
+        if sent < recipients.len() {
+            error!(logger, "err: sent {}/{}", sent, recipients.len());
+        }
+    }
+
+    // Writes a backup of the namespace into the backup directory as
+    // `<uuid>-<timestamp>/`.
+    fn export_namespace_backup(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        clock: &dyn Clock,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.is_empty() {
+            return;
+        }
+
+        let namespace_key: String = args[0].clone().into();
+        let namespace =
+            match Namespace::find_by_key(&namespace_key, db_conn, logger) {
+                Some(n) => n,
+                None => {
+                    error!(logger, "not found :'(");
+                    return;
+                },
+            };
+
+        let now = clock.now().naive_utc();
+        let dir = Path::new(&config.backup_directory).join(format!(
+            "{}-{}",
+            namespace.uuid,
+            now.format("%Y%m%d%H%M%S")
+        ));
+        let backup = NamespaceBackup::new(db_conn, logger);
+        match backup.export(&namespace, &dir, now) {
+            Ok(files) => info!(logger, "files: {:#?}", files),
+            Err(e) => error!(logger, "err: {}", e),
+        }
+    }
+
+    // Restores a backup directory (the path) as a new namespace. The messages
+    // are saved using COPY, or INSERTs if another connection is not available.
+    fn import_namespace_backup(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.is_empty() {
+            return;
+        }
+
+        let path: String = args[0].clone().into();
+        let dir = Path::new(&path);
+        let backup = NamespaceBackup::new(db_conn, logger);
+        // the client has no TLS (`DATABASE_SSL_MODE` is only for libpq), so
+        // it falls back to inserts if the server requires TLS
+        let result = match PgClient::connect(&config.database_url, NoTls) {
+            Ok(mut client) => backup.import_by_copy(dir, &mut client),
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                backup.import(dir)
+            },
+        };
+        match result {
+            Ok(namespace) => info!(logger, "namespace: {}", namespace.uuid),
+            Err(e) => error!(logger, "err: {}", e),
+        }
+    }
+
+    // Saves the daily counters in the session store into namespace_usages,
+    // then updates usage_records of the months.
+    fn flush_namespace_usages(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        logger: &Logger,
+    ) {
+        let url = config.session_store_connection_url();
+        let client = match Client::open(url.as_str()) {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let mut ss_conn = match client.get_connection() {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+
+        let keys: Vec<String> =
+            match ss_conn.scan_match::<_, String>(format!("{}*", KEY_PREFIX)) {
+                Ok(iter) => iter.collect(),
+                Err(e) => {
+                    error!(logger, "err: {}", e);
+                    return;
+                },
+            };
+
+        let mut months = BTreeSet::new();
+        for key in keys {
+            let (namespace_key, date) = match parse_counter_key(&key) {
+                Some(v) => v,
+                None => continue,
+            };
+            let namespace =
+                match Namespace::find_by_key(&namespace_key, db_conn, logger) {
+                    Some(n) => n,
+                    None => continue,
+                };
+
+            let (calls, messages, bytes): (
+                Option<i64>,
+                Option<i64>,
+                Option<i64>,
+            ) = match ss_conn.hget(&key, &["calls", "messages", "bytes"]) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(logger, "err: {}", e);
+                    continue;
+                },
+            };
+
+            if NamespaceUsage::upsert(
+                namespace.id,
+                date,
+                calls.unwrap_or(0),
+                messages.unwrap_or(0),
+                bytes.unwrap_or(0),
+                db_conn,
+                logger,
+            )
+            .is_none()
+            {
+                error!(logger, "err: failed to flush {}", key);
+                continue;
+            }
+            months.insert(UsageRecord::month_of(date));
+        }
+
+        for month in months {
+            if let Err(e) = UsageRecord::aggregate(month, db_conn, logger) {
+                error!(logger, "err: {}", e);
+            }
+        }
+    }
+
+    fn flush_recent_views(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        logger: &Logger,
+    ) {
+        let url = config.session_store_connection_url();
+        let client = match Client::open(url.as_str()) {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let mut ss_conn = match client.get_connection() {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+
+        let keys: Vec<String> = match ss_conn
+            .scan_match::<_, String>(format!("{}*", VIEW_KEY_PREFIX))
+        {
+            Ok(iter) => iter.collect(),
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+
+        for key in keys {
+            let user_id = match parse_view_key(&key) {
+                Some(v) => v,
+                None => continue,
+            };
+            let entries: Vec<(String, i64)> =
+                match ss_conn.zrange_withscores(&key, 0, -1) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!(logger, "err: {}", e);
+                        continue;
+                    },
+                };
+            let max_score = match entries.iter().map(|(_, s)| *s).max() {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let flushed = db_conn.build_transaction().run::<_, Error, _>(|| {
+                for (member, score) in entries.iter() {
+                    let (kind, target) = match parse_view_member(member) {
+                        Some(v) => v,
+                        None => continue,
+                    };
+                    let viewed_at = score_to_datetime(*score);
+                    RecentView::upsert(
+                        user_id, kind, &target, viewed_at, db_conn, logger,
+                    )
+                    .ok_or(Error::RollbackTransaction)?;
+                }
+                RecentView::truncate(
+                    user_id,
+                    config.recent_view_limit as i64,
+                    db_conn,
+                    logger,
+                )
+                .ok_or(Error::RollbackTransaction)?;
+                Ok(())
+            });
+            if flushed.is_err() {
+                error!(logger, "err: failed to flush {}", key);
+                continue;
+            }
+
+            // views added during the flush remain in the store
+            let _: Result<i64, _> =
+                ss_conn.zrembyscore(&key, "-inf", max_score);
+        }
+    }
+
+    fn apply_bulk_operation(
+        &self,
+        db_conn: &PgConnection,
+        _config: &Config,
+        clock: &dyn Clock,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.is_empty() {
+            return;
+        }
+        let uuid: String = args[0].clone().into();
+
+        let found = BulkOperation::find_by_uuid(&uuid, db_conn, logger);
+        let operation = match found {
+            Some(o) if o.state == BulkOperationState::Pending => o,
+            Some(o) => {
+                warn!(logger, "already started: {}", o);
+                return;
+            },
+            None => return,
+        };
+        // validated on the request
+        let filter = match MessageFilter::parse(&operation.filter) {
+            Ok(f) => f,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                let _ = operation.update_state(
+                    BulkOperationState::Failed,
+                    operation.matched_count,
+                    0,
+                    clock,
+                    db_conn,
+                    logger,
+                );
+                return;
+            },
+        };
+        let argument = operation.argument.clone().unwrap_or_default();
+
+        // the incident must be in the namespace of the stream
+        let incident = match operation.action {
+            BulkOperationAction::AssignIncident => {
+                match Incident::find_by_uuid_and_stream_id(
+                    &argument,
+                    operation.stream_id,
+                    db_conn,
+                    logger,
+                ) {
+                    Some(i) => Some(i),
+                    None => {
+                        warn!(logger, "incident not found: {}", argument);
+                        let _ = operation.update_state(
+                            BulkOperationState::Failed,
+                            operation.matched_count,
+                            0,
+                            clock,
+                            db_conn,
+                            logger,
+                        );
+                        return;
+                    },
+                }
+            },
+            _ => None,
+        };
+
+        // the count may be changed since the preview
+        let matched_count = Message::count_by_filter(
+            operation.stream_id,
+            &filter,
+            db_conn,
+            logger,
+        )
+        .unwrap_or(operation.matched_count);
+        let operation = match operation.update_state(
+            BulkOperationState::Running,
+            matched_count,
+            0,
+            clock,
+            db_conn,
+            logger,
+        ) {
+            Some(o) => o,
+            None => return,
+        };
+
+        let mut affected_count = 0;
+        let mut last_id = "".to_string();
+        let state = loop {
+            let ids = match Message::find_ids_by_filter(
+                operation.stream_id,
+                &filter,
+                &last_id,
+                BATCH_SIZE,
+                db_conn,
+                logger,
+            ) {
+                Some(v) if v.is_empty() => break BulkOperationState::Finished,
+                Some(v) => v,
+                None => break BulkOperationState::Failed,
+            };
+            last_id = ids[ids.len() - 1].to_string();
+
+            let result = match operation.action {
+                BulkOperationAction::AddTag => {
+                    Message::add_tag_by_ids(
+                        &ids,
+                        &argument,
+                        clock,
+                        db_conn,
+                        logger,
+                    )
+                },
+                BulkOperationAction::AssignIncident => {
+                    incident.as_ref().and_then(|i| {
+                        IncidentMessage::attach(i.id, &ids, db_conn, logger)
+                    })
+                },
+                BulkOperationAction::SoftDelete => {
+                    Message::soft_delete_by_ids(&ids, clock, db_conn, logger)
+                },
+            };
+            match result {
+                Some(n) => affected_count += n as i64,
+                None => break BulkOperationState::Failed,
+            }
+
+            // progress
+            let _ = operation.update_state(
+                BulkOperationState::Running,
+                matched_count,
+                affected_count,
+                clock,
+                db_conn,
+                logger,
+            );
+        };
+
+        info!(logger, "{} {}: {}", operation, state, affected_count);
+        let _ = operation.update_state(
+            state,
+            matched_count,
+            affected_count,
+            clock,
+            db_conn,
+            logger,
+        );
+    }
+
+    // Posts an alert for the message to the channel. The message is attached
+    // to the open incident of the rule (a new one is opened if there is not),
+    // even if the alert is rolled up.
+    //
+    // The args are the channel, the stream, the message id and the rule
+    // (optional; defaults to the stream) which is the unit of the rollup and
+    // the incident.
+    fn deliver_alert(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.len() < 3 {
+            return;
+        }
+
+        let channel_uuid: String = args[0].clone().into();
+        let stream_uuid: String = args[1].clone().into();
+        let message_id: String = args[2].clone().into();
+        if !is_ulid(&message_id) {
+            return;
+        }
+        let rule: String = match args.get(3) {
+            Some(v) => v.clone().into(),
+            None => stream_uuid.to_string(),
+        };
+
+        let channel =
+            match Channel::find_by_uuid(&channel_uuid, db_conn, logger) {
+                Some(c) => c,
+                None => {
+                    error!(logger, "not found :'(");
+                    return;
+                },
+            };
+        let stream = match Stream::find_by_uuid(&stream_uuid, db_conn, logger) {
+            Some(s) if s.namespace_id == channel.namespace_id => s,
+            _ => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+        let namespace =
+            Namespace::find_by_id(stream.namespace_id, db_conn, logger);
+        let message = Message::first_by_stream_id(
+            &message_id,
+            stream.id,
+            db_conn,
+            logger,
+        );
+        let (namespace, message) = match (namespace, message) {
+            (Some(n), Some(m)) => (n, m),
+            _ => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+        let mut context = PayloadContext::new(&namespace, &stream, &message);
+
+        match Incident::find_or_open_by_rule(
+            namespace.id,
+            &rule,
+            &message.title,
+            db_conn,
+            logger,
+        ) {
+            Some(incident) => {
+                let ids = [message.id.to_string()];
+                let _ = IncidentMessage::attach(
+                    incident.id,
+                    &ids,
+                    db_conn,
+                    logger,
+                );
+            },
+            None => error!(logger, "err: no incident for rule: {}", rule),
+        }
+
+        // it's also for the render metrics (see `service::render_metrics`)
+        let url = config.session_store_connection_url();
+        let mut ss_conn = Client::open(url.as_str())
+            .and_then(|c| c.get_connection())
+            .map_err(|e| error!(logger, "err: {}", e))
+            .ok();
+        if channel.rollup_window > 0 {
+            let conn = match ss_conn {
+                Some(ref mut c) => c,
+                None => return,
+            };
+            let window = channel.rollup_window as usize;
+            let mut rollup = AlertRollup::new(conn, window);
+            match rollup.admit(&channel_uuid, &rule) {
+                Ok(Some(n)) => context.rollup.suppressed_count = n,
+                Ok(None) => {
+                    info!(logger, "suppressed: {} {}", channel, rule);
+                    return;
+                },
+                // delivers it anyway
+                Err(e) => error!(logger, "err: {}", e),
+            }
+        }
+
+        let notifier = ChannelNotifier::new(&channel, logger);
+        if let Err(e) = notifier.notify(&context, ss_conn.as_mut()) {
+            error!(logger, "err: {}", e);
+        }
+    }
+
+    // Sends an alert email for the message to the user, or posts it to the
+    // webhook of the user (see `service::notifier`). In the quiet hours of the
+    // user, an email is deferred to the end of them instead.
+    //
+    // The args are the user, the stream and the message id.
+    fn send_alert_email(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        clock: &dyn Clock,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.len() < 3 {
+            return;
+        }
+
+        let user_uuid: String = args[0].clone().into();
+        let stream_uuid: String = args[1].clone().into();
+        let message_id: String = args[2].clone().into();
+        if !is_ulid(&message_id) {
+            return;
+        }
+
+        let user = match User::find_by_uuid(&user_uuid, db_conn, logger) {
+            Some(u) => u,
+            None => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+        let stream = match Stream::find_by_uuid(&stream_uuid, db_conn, logger) {
+            Some(s) => s,
+            None => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+        // the user may have left the namespace after the alert
+        if Membership::find_by_namespace_id_and_user_id(
+            stream.namespace_id,
+            user.id,
+            db_conn,
+            logger,
+        )
+        .is_none()
+        {
+            info!(logger, "not a member: {}", user.uuid);
+            return;
+        }
+
+        let notifier = Notifier::new(db_conn, logger);
+        let delivery = notifier.delivery(&user, NotificationEvent::Alert);
+        if delivery == Delivery::Skip {
+            info!(logger, "opted out: {}", user.uuid);
+            return;
+        }
+
+        // the quiet hours are only for emails
+        let schedule = match delivery {
+            Delivery::Email => {
+                AlertSchedule::find_by_user_id(user.id, db_conn, logger)
+            },
+            _ => None,
+        };
+        let quiet_hours = schedule.and_then(|s| {
+            match (s.quiet_hours_start, s.quiet_hours_end) {
+                (Some(start), Some(end)) => {
+                    QuietHours::new(&s.timezone, start, end).ok()
+                },
+                _ => None,
+            }
+        });
+        if let Some(until) = quiet_hours.and_then(|q| q.ends_at(clock.now())) {
+            let url = config.message_queue_connection_url();
+            let client = match Client::open(url.as_str()) {
+                Ok(c) => c,
+                Err(e) => {
+                    error!(logger, "err: {}", e);
+                    return;
+                },
+            };
+            let mut mq_conn = match client.get_connection() {
+                Ok(c) => c,
+                Err(e) => {
+                    error!(logger, "err: {}", e);
+                    return;
+                },
+            };
+            let job = Job::<String> {
+                kind: self.kind.clone(),
+                args: self.args.iter().map(|a| a.clone().into()).collect(),
+            };
+            match defer(&job, until, &mut mq_conn) {
+                Ok(_) => info!(logger, "deferred until: {}", until),
+                Err(e) => error!(logger, "err: {}", e),
+            }
+            return;
+        }
+
+        let namespace =
+            Namespace::find_by_id(stream.namespace_id, db_conn, logger);
+        let message = Message::first_by_stream_id(
+            &message_id,
+            stream.id,
+            db_conn,
+            logger,
+        );
+        let (namespace, message) = match (namespace, message) {
+            (Some(n), Some(m)) => (n, m),
+            _ => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+
+        if let Delivery::Webhook(ref url) = delivery {
+            let payload = serde_json::json!({
+                "event": NotificationEvent::Alert.to_string(),
+                "namespace": namespace.name,
+                "stream": stream.name,
+                "title": message.title,
+            });
+            if let Err(e) = notifier.post(url, &payload) {
+                error!(logger, "err: {}", e);
+            }
+            return;
+        }
+
+        let mut mailer = UserMailer::new(config, logger);
+        let name = user.name.as_deref().unwrap_or("");
+        mailer.to((&user.email, name)).send_alert_email(
+            &namespace.name,
+            &stream.name,
+            &message.title,
+        );
+    }
+
+    // Sends a push notification of the critical message to the devices of
+    // the user, and saves the receipts. Unlike alert emails, it's not
+    // deferred in the quiet hours, and it's skipped only if the user has
+    // opted out of alerts. The devices rejected by the platform are removed.
+    //
+    // The args are the user, the stream and the message id.
+    fn send_push_notification(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        clock: &dyn Clock,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.len() < 3 {
+            return;
+        }
+
+        let user_uuid: String = args[0].clone().into();
+        let stream_uuid: String = args[1].clone().into();
+        let message_id: String = args[2].clone().into();
+        if !is_ulid(&message_id) {
+            return;
+        }
+
+        let user = match User::find_by_uuid(&user_uuid, db_conn, logger) {
+            Some(u) => u,
+            None => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+        let stream = match Stream::find_by_uuid(&stream_uuid, db_conn, logger) {
+            Some(s) => s,
+            None => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+        // the user may have left the namespace after the alert
+        if Membership::find_by_namespace_id_and_user_id(
+            stream.namespace_id,
+            user.id,
+            db_conn,
+            logger,
+        )
+        .is_none()
+        {
+            info!(logger, "not a member: {}", user.uuid);
+            return;
+        }
+
+        let delivery = Notifier::new(db_conn, logger)
+            .delivery(&user, NotificationEvent::Alert);
+        if delivery == Delivery::Skip {
+            info!(logger, "opted out: {}", user.uuid);
+            return;
+        }
+
+        let namespace =
+            Namespace::find_by_id(stream.namespace_id, db_conn, logger);
+        let message = Message::first_by_stream_id(
+            &message_id,
+            stream.id,
+            db_conn,
+            logger,
+        );
+        let (namespace, message) = match (namespace, message) {
+            (Some(n), Some(m)) => (n, m),
+            _ => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+        if message.level != LogLevel::Critical {
+            info!(logger, "not critical: {}", message.id);
+            return;
+        }
+
+        let devices = PushDevice::find_all_by_user_id(user.id, db_conn, logger)
+            .unwrap_or_else(Vec::new);
+        let notification =
+            PushNotification::new(config, &namespace, &stream, &message);
+        let notifier = PushNotifier::new(config, clock, logger);
+        for device in devices.iter() {
+            let outcome = notifier.notify(device, &notification);
+            let (delivered, provider_id, error) = match outcome {
+                Outcome::Delivered(ref id) => (true, id.clone(), None),
+                Outcome::Unregistered(ref e) | Outcome::Failed(ref e) => {
+                    (false, None, Some(e.to_string()))
+                },
+            };
+            let delivery = NewPushDelivery {
+                push_device_id: device.id,
+                message_id: message.id.to_string(),
+                delivered,
+                provider_id,
+                error,
+            };
+            let _ = PushDelivery::insert(&delivery, db_conn, logger);
+
+            let now = clock.now().naive_utc();
+            let result = match outcome {
+                Outcome::Delivered(_) => {
+                    device.mark_as_delivered(now, db_conn, logger).map(|_| ())
+                },
+                Outcome::Unregistered(_) => {
+                    info!(logger, "unregistered: {}", device);
+                    device.delete(db_conn, logger)
+                },
+                Outcome::Failed(_) => Ok(()),
+            };
+            if let Err(e) = result {
+                error!(logger, "err: {}", e);
+            }
+        }
+    }
+
+    // Saves the sampled analytics events in the session store (see
+    // `request::analytics`). It's expected to be enqueued periodically (e.g.
+    // `enqueue-job FlushAnalyticsEvents` by cron).
+    fn flush_analytics_events(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        logger: &Logger,
+    ) {
+        // events per insert
+        const BATCH: isize = 500;
+
+        let url = config.session_store_connection_url();
+        let client = match Client::open(url.as_str()) {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let mut ss_conn = match client.get_connection() {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+
+        let mut count = 0;
+        loop {
+            // a batch is popped at once, as the pushers trim the list too
+            // (see `request::analytics`)
+            let popped: Result<(Vec<String>,), _> = redis::pipe()
+                .atomic()
+                .lrange(ANALYTICS_KEY, 0, BATCH - 1)
+                .ltrim(ANALYTICS_KEY, BATCH, -1)
+                .ignore()
+                .query(&mut ss_conn);
+            let values = match popped {
+                Ok((v,)) => v,
+                Err(e) => {
+                    error!(logger, "err: {}", e);
+                    break;
+                },
+            };
+            if values.is_empty() {
+                break;
+            }
+            // broken ones are dropped
+            let events: Vec<_> = values
+                .iter()
+                .filter_map(|v| serde_json::from_str::<PendingEvent>(v).ok())
+                .map(PendingEvent::into_new)
+                .collect();
+            if events.is_empty() {
+                continue;
+            }
+            match AnalyticsEvent::insert_all(&events, db_conn, logger) {
+                Some(n) => count += n,
+                None => {
+                    // they are retried by the next flush
+                    let pushed: Result<i64, _> =
+                        ss_conn.rpush(ANALYTICS_KEY, values);
+                    if let Err(e) = pushed {
+                        error!(logger, "err: {}", e);
+                    }
+                    break;
+                },
+            }
+        }
+        info!(logger, "flushed: {} events", count);
+    }
+
+    // Removes namespaces deleted before the grace period with their records.
+    // It's deferred on deletions, and can be enqueued also by hand.
+    fn purge_namespaces(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        clock: &dyn Clock,
+        logger: &Logger,
+    ) {
+        let period = config.namespace_purge_grace_period as i64;
+        let before = (clock.now() - Duration::seconds(period)).naive_utc();
+        let namespaces =
+            match Namespace::find_all_archived_before(before, db_conn, logger) {
+                Some(v) => v,
+                None => return,
+            };
+
+        let purger = NamespacePurger::new(db_conn, clock, logger);
+        for namespace in namespaces.iter() {
+            if let Err(e) = purger.purge(namespace) {
+                error!(logger, "err: {} {}", namespace, e);
+            }
+        }
+    }
+
+    // Verifies the backup directories in the backup directory with their
+    // manifests. It's for cron (or by hand), failures are logged as errors.
+    fn verify_archives(&self, config: &Config, logger: &Logger) {
+        let root = Path::new(&config.backup_directory);
+        let results = match verify_all(root) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        for (dir, result) in results {
+            match result {
+                Ok(n) => info!(logger, "verified: {:?} ({} records)", dir, n),
+                Err(e) => error!(logger, "err: {:?} {}", dir, e),
+            }
+        }
+    }
+
+    // Saves the messages in the stream buffers (see `service::stream_buffer`).
+    fn flush_stream_buffers(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        clock: &dyn Clock,
+        logger: &Logger,
+    ) {
+        let url = config.session_store_connection_url();
+        let client = match Client::open(url.as_str()) {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let mut ss_conn = match client.get_connection() {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let mut buffer = StreamBuffer::new(&mut ss_conn, logger);
+        match buffer.flush_all(clock, db_conn) {
+            Ok(n) => info!(logger, "flushed: {} messages", n),
+            Err(e) => error!(logger, "err: {}", e),
+        }
+    }
+
+    // Aggregates the hourly counts of messages for stats (see
+    // `model::message_count`). It's expected to be enqueued every hour (e.g.
+    // `enqueue-job RollupMessageCounts` by cron).
+    fn rollup_message_counts(
+        &self,
+        db_conn: &PgConnection,
+        clock: &dyn Clock,
+        logger: &Logger,
+    ) {
+        let now = clock.now().naive_utc();
+        match HourlyMessageCount::roll_up(now, db_conn, logger) {
+            Ok(n) => info!(logger, "rolled up: {} counts", n),
+            Err(e) => error!(logger, "err: {}", e),
+        }
+    }
+
+    // Alerts spikes of errors in the last hour via the channels of the
+    // namespaces, and opens (or keeps) an incident of them (see
+    // `service::anomaly_detector`). It's expected to be enqueued every hour
+    // after `RollupMessageCounts` (e.g. `enqueue-job DetectAnomalies` by
+    // cron). The alerts are rolled up like the ones of messages.
+    fn detect_anomalies(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        clock: &dyn Clock,
+        logger: &Logger,
+    ) {
+        let end = match HourlyMessageCount::rolled_up_until(db_conn, logger) {
+            Ok(Some(t)) => t.min(clock.now().naive_utc()),
+            Ok(None) => return,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let end = StatsInterval::Hour.truncate(end);
+        let namespaces =
+            match Namespace::find_all_with_anomaly_detection(db_conn, logger) {
+                Some(v) => v,
+                None => return,
+            };
+
+        let mut ss_conn: Option<Connection> = None;
+        let detector = AnomalyDetector::new(db_conn, logger);
+        for namespace in namespaces.iter() {
+            let anomaly = match detector.detect(namespace, end) {
+                Ok(Some(a)) => a,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!(logger, "err: {}", e);
+                    continue;
+                },
+            };
+            info!(logger, "anomaly: {} {:?}", namespace.id, anomaly);
+
+            let title = anomaly.title();
+            if Incident::find_or_open_by_rule(
+                namespace.id,
+                ANOMALY_RULE,
+                &title,
+                db_conn,
+                logger,
+            )
+            .is_none()
+            {
+                error!(logger, "err: no incident for rule: {}", ANOMALY_RULE);
+            }
+
+            let channels =
+                Channel::find_all_by_namespace_id(namespace.id, db_conn, logger)
+                    .unwrap_or_default();
+            for channel in channels.iter() {
+                let mut context = PayloadContext::anomaly(namespace, &anomaly);
+                // it's also for the render metrics
+                if ss_conn.is_none() {
+                    let url = config.session_store_connection_url();
+                    ss_conn = Client::open(url.as_str())
+                        .and_then(|c| c.get_connection())
+                        .map_err(|e| error!(logger, "err: {}", e))
+                        .ok();
+                }
+                if channel.rollup_window > 0 {
+                    if let Some(ref mut conn) = ss_conn {
+                        let window = channel.rollup_window as usize;
+                        let mut rollup = AlertRollup::new(conn, window);
+                        let uuid = channel.uuid.to_string();
+                        match rollup.admit(&uuid, ANOMALY_RULE) {
+                            Ok(Some(n)) => context.rollup.suppressed_count = n,
+                            Ok(None) => {
+                                info!(logger, "suppressed: {}", channel);
+                                continue;
+                            },
+                            // delivers it anyway
+                            Err(e) => error!(logger, "err: {}", e),
+                        }
+                    }
+                }
+
+                let notifier = ChannelNotifier::new(channel, logger);
+                if let Err(e) = notifier.notify(&context, ss_conn.as_mut()) {
+                    error!(logger, "err: {}", e);
+                }
+            }
+        }
+    }
+
+    // Alerts if the error budgets are burning too fast (see `service::slo`).
+    // It's expected to be enqueued every minute (e.g. `enqueue-job
+    // CheckSloBurnRates` by cron).
+    fn check_slo_burn_rates(
+        &self,
+        config: &Config,
+        clock: &dyn Clock,
+        logger: &Logger,
+    ) {
+        let slo = Slo::new(config, logger);
+        if !slo.is_enabled() {
+            return;
+        }
+        let url = config.session_store_connection_url();
+        let client = match Client::open(url.as_str()) {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let mut ss_conn = match client.get_connection() {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let minute = clock.now().timestamp() / 60;
+        match slo.burn_rates(&mut ss_conn, minute) {
+            Ok(rates) => {
+                if !slo.check(&rates) {
+                    info!(logger, "slo: ok");
+                }
+            },
+            Err(e) => error!(logger, "err: {}", e),
+        }
+    }
+
+    // Sends the token to accept the transfer of the namespace to the new
+    // owner.
+    //
+    // The args are the user, the namespace and the token.
+    fn send_namespace_transfer_email(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.len() < 3 {
+            return;
+        }
+
+        let user_uuid: String = args[0].clone().into();
+        let namespace_key: String = args[1].clone().into();
+        let token: String = args[2].clone().into();
+
+        let user = match User::find_by_uuid(&user_uuid, db_conn, logger) {
+            Some(u) => u,
+            None => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+        // the user must be still a member
+        let found =
+            Namespace::find_by_uuid(&namespace_key, &user, db_conn, logger);
+        let namespace = match found {
+            Some(n) => n,
+            None => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+
+        let mut mailer = UserMailer::new(config, logger);
+        let name = user.name.as_deref().unwrap_or("");
+        let uuid = namespace.uuid.to_string();
+        // TODO: check result (should be Result instead of bool?)
+        mailer.to((&user.email, name)).send_namespace_transfer_email(
+            &namespace.name,
+            &uuid,
+            &token,
+        );
+    }
+
+    // Sends the security notice to the user (see `mailer::security`), or
+    // posts it to the webhook of the user. The critical ones are always sent
+    // by email, and the others are skipped if the user has opted out of them.
+    //
+    // The args are the user, the notice, the time (RFC 3339) and the IP
+    // address (only for a new sign-in).
+    fn send_security_notification_email(
+        &self,
+        db_conn: &PgConnection,
+        config: &Config,
+        logger: &Logger,
+    ) {
+        info!(logger, "args: {:#?}", self.args.as_slice());
+        let args = self.args.as_slice();
+        if args.len() < 3 {
+            return;
+        }
+
+        let user_uuid: String = args[0].clone().into();
+        let name: String = args[1].clone().into();
+        let notice = match SecurityNotice::from_name(&name) {
+            Some(n) => n,
+            None => {
+                error!(logger, "unknown notice: {}", name);
+                return;
+            },
+        };
+        let at: String = args[2].clone().into();
+        let at = match DateTime::parse_from_rfc3339(&at) {
+            Ok(t) => t.with_timezone(&Utc),
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let ip: String =
+            args.get(3).map(|v| v.clone().into()).unwrap_or_default();
+
+        let user = match User::find_by_uuid(&user_uuid, db_conn, logger) {
+            Some(u) => u,
+            None => {
+                error!(logger, "not found :'(");
+                return;
+            },
+        };
+        let notifier = Notifier::new(db_conn, logger);
+        let delivery = notifier.delivery(&user, NotificationEvent::Security);
+        if let Delivery::Webhook(ref url) = delivery {
+            let payload = serde_json::json!({
+                "event": NotificationEvent::Security.to_string(),
+                "notice": notice.as_str(),
+                "at": at.to_rfc3339(),
+                "ip": ip,
+            });
+            if let Err(e) = notifier.post(url, &payload) {
+                error!(logger, "err: {}", e);
+            }
+        }
+        if delivery != Delivery::Email && !notice.is_critical() {
+            info!(logger, "no email: {}", notice.as_str());
+            return;
+        }
+
+        let mut mailer = SecurityMailer::new(config, logger);
+        let name = user.name.as_deref().unwrap_or("");
+        // TODO: check result (should be Result instead of bool?)
+        mailer
+            .to((&user.email, name))
+            .send_security_notification_email(notice, &at, &ip);
+    }
+}
+
+/// Defers the job until the time. It's moved into the queue by
+/// `enqueue_deferred` when it's due.
+pub fn defer(
+    job: &Job<String>,
+    until: DateTime<Utc>,
+    mq_conn: &mut Connection,
+) -> Result<(), String> {
+    let member = serde_json::to_string(job).map_err(|e| e.to_string())?;
+    mq_conn
+        .zadd::<_, _, _, i64>(DEFERRED_KEY, member, until.timestamp())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Moves the deferred jobs which are due into the queue, and returns the
+/// number of them.
+pub fn enqueue_deferred(
+    now: DateTime<Utc>,
+    mq_conn: &mut Connection,
+) -> Result<usize, String> {
+    let members: Vec<String> = mq_conn
+        .zrangebyscore(DEFERRED_KEY, "-inf", now.timestamp())
+        .map_err(|e| e.to_string())?;
+
+    let mut count = 0;
+    for member in members {
+        // only one of workers can take it
+        let removed: i64 = mq_conn
+            .zrem(DEFERRED_KEY, &member)
+            .map_err(|e| e.to_string())?;
+        if removed == 0 {
+            continue;
+        }
+        let job = match serde_json::from_str::<Job<String>>(&member) {
+            Ok(j) => j,
+            Err(_) => continue,
+        };
+        let mut queue = Queue::new("default", &mut *mq_conn);
+        queue
+            .enqueue::<Job<String>>(job)
+            .map_err(|e| e.to_string())?;
+        count += 1;
+    }
+    Ok(count)
 }
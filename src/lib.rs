@@ -8,6 +8,8 @@ extern crate accord;
 #[macro_use]
 extern crate diesel;
 #[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
 extern crate rocket;
 #[macro_use]
 extern crate rocket_contrib;
@@ -27,6 +29,16 @@ extern crate rusty_fork;
 
 use std::collections::HashMap;
 
+use crate::config::Config;
+use crate::request::api_version;
+use crate::request::concurrency::Bulkheads;
+use crate::service::deprecation::Deprecations;
+use crate::service::fault_injection::RouteFaults;
+use crate::service::maintenance::MaintenanceMode;
+use crate::service::read_only::ReadOnlyMode;
+use crate::service::request_metrics::RequestCounting;
+use crate::service::user_agent::UserAgentSampling;
+
 mod response;
 mod validation;
 mod service;
@@ -37,13 +49,25 @@ pub mod db;
 pub mod mq;
 pub mod ss;
 
+pub mod cli;
+pub mod clock;
 pub mod config;
+pub mod factory;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod id;
 pub mod job;
+pub mod license;
 pub mod logger;
 pub mod mailer;
+pub mod metadata;
 pub mod model;
 pub mod request;
 pub mod route;
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tracecontext;
 
 // macros
 
@@ -69,6 +93,15 @@ macro_rules! fnvhashmap(
     };
 );
 
+#[cfg(feature = "graphql")]
+fn graphql_routes() -> Vec<rocket::Route> {
+    routes![
+        route::graphql::preflight::query,
+        route::graphql::get,
+        route::graphql::post,
+    ]
+}
+
 // returns a sorted vec by namespace.
 pub fn routes() -> Vec<(&'static str, Vec<rocket::Route>)> {
     let mut r = vec![
@@ -77,11 +110,25 @@ pub fn routes() -> Vec<(&'static str, Vec<rocket::Route>)> {
             routes![
                 route::activation::preflight::activate,
                 route::activation::activate,
+                route::authentication::preflight::exchange,
                 route::authentication::preflight::login,
                 route::authentication::preflight::logout,
+                route::authentication::preflight::sudo,
                 route::authentication::preignition::login,
+                route::authentication::exchange,
                 route::authentication::login,
                 route::authentication::logout,
+                route::authentication::sudo,
+                route::config::reload,
+                route::fault::clear,
+                route::fault::inject,
+                route::maintenance::hget,
+                route::maintenance::hset,
+                route::metrics::pools,
+                route::oauth::preflight::authorize,
+                route::oauth::preflight::callback,
+                route::oauth::authorize,
+                route::oauth::callback,
                 route::password_reset::preflight::request,
                 route::password_reset::preflight::verify_update,
                 route::password_reset::preignition::request,
@@ -94,50 +141,178 @@ pub fn routes() -> Vec<(&'static str, Vec<rocket::Route>)> {
                 route::registration::preignition::register,
                 route::registration::deregister,
                 route::registration::register,
+                route::slo::burn_rates,
+                route::stream::buffer,
+                route::stream::flush,
+                route::waitlist::preflight::confirm,
+                route::waitlist::preflight::join,
+                route::waitlist::confirm,
+                route::waitlist::join,
                 route::health::check,
+                route::health::ready,
             ],
         ),
         (
             "/v1", // public console api
             routes![
                 route::access_token::preflight::del,
+                route::access_token::preflight::del_confirm,
                 route::access_token::preflight::dump,
                 route::access_token::preflight::hset_state,
+                route::access_token::preflight::hset_scopes,
                 route::access_token::preflight::append,
                 route::access_token::preflight::lrange,
                 route::access_token::del,
+                route::access_token::del_confirm,
                 route::access_token::dump,
                 route::access_token::hset_state,
+                route::access_token::hset_scopes,
                 route::access_token::append,
                 route::access_token::lrange,
+                route::alert_schedule::preflight::hget,
+                route::alert_schedule::preflight::hset,
+                route::alert_schedule::hget,
+                route::alert_schedule::hset,
+                route::badge::show,
+                route::bulk_operation::preflight::append,
+                route::bulk_operation::preflight::count,
+                route::bulk_operation::preflight::hget,
+                route::bulk_operation::append,
+                route::bulk_operation::count,
+                route::bulk_operation::hget,
+                route::channel::preflight::append,
+                route::channel::preflight::hgetall,
+                route::channel::preflight::preview,
+                route::channel::append,
+                route::channel::hgetall,
+                route::channel::preview,
+                route::incident::preflight::append,
+                route::incident::preflight::attach,
+                route::incident::preflight::hget,
+                route::incident::preflight::hgetall,
+                route::incident::preflight::hset,
+                route::incident::preflight::timeline,
+                route::incident::append,
+                route::incident::attach,
+                route::incident::hget,
+                route::incident::hgetall,
+                route::incident::hset,
+                route::incident::timeline,
                 route::message::preflight::append,
+                route::message::preflight::hget,
+                route::message::preflight::hset,
+                route::message::preflight::ingest,
                 route::message::preflight::lrange,
+                route::message::preflight::search,
+                route::message::preflight::share,
+                route::message::preflight::trace,
+                route::message::preflight::unfurl,
                 route::message::append,
+                route::message::append_protobuf,
+                route::message::hget,
+                route::message::hset,
+                route::message::ingest_json,
+                route::message::ingest_protobuf,
                 route::message::lrange,
+                route::message::search,
+                route::message::share,
+                route::message::shared,
+                route::message::trace,
+                route::message::unfurl,
+                route::namespace::preflight::badge,
+                route::namespace::preflight::del,
+                route::namespace::preflight::del_confirm,
                 route::namespace::preflight::hget,
                 route::namespace::preflight::hgetall,
                 route::namespace::preflight::hset,
+                route::namespace::preflight::memberships,
+                route::namespace::preflight::restore,
+                route::namespace::preflight::retention_preview,
+                route::namespace::preflight::stats,
+                route::namespace::preflight::transfer,
+                route::namespace::preflight::transfer_confirm,
+                route::namespace::preflight::transfer_accept,
+                route::namespace::preflight::update,
+                route::namespace::preflight::usage,
+                route::namespace::badge,
+                route::namespace::del,
+                route::namespace::del_confirm,
                 route::namespace::hget,
                 route::namespace::hgetall,
                 route::namespace::hset,
+                route::namespace::memberships,
+                route::namespace::restore,
+                route::namespace::retention_preview,
+                route::namespace::stats,
+                route::namespace::transfer,
+                route::namespace::transfer_confirm,
+                route::namespace::transfer_accept,
+                route::namespace::update,
+                route::namespace::usage,
+                route::push_device::preflight::append,
+                route::push_device::preflight::del,
+                route::push_device::preflight::deliveries,
+                route::push_device::preflight::hgetall,
+                route::push_device::append,
+                route::push_device::del,
+                route::push_device::deliveries,
+                route::push_device::hgetall,
+                route::recent_view::preflight::lrange,
+                route::recent_view::lrange,
+                route::stream_token::preflight::append,
+                route::stream_token::preflight::del,
+                route::stream_token::preflight::dump,
+                route::stream_token::preflight::hgetall,
+                route::stream_token::append,
+                route::stream_token::del,
+                route::stream_token::dump,
+                route::stream_token::hgetall,
+                route::user::preflight::notifications,
+                route::user::hget_notifications,
+                route::user::hset_notifications,
                 route::health::check,
             ],
         ),
     ];
+    // optional api (see features)
+    #[cfg(feature = "graphql")]
+    r[1].1.extend(graphql_routes());
     r.sort_by(|a, b| a.0.cmp(b.0));
     r
 }
 
-pub fn server() -> rocket::Rocket {
+pub fn server(config: &Config) -> rocket::Rocket {
     let r: HashMap<&str, Vec<_>> = routes().iter().cloned().collect();
-    rocket::ignite()
+    let rocket_config = config
+        .to_rocket_config()
+        .expect("failed to build rocket config");
+    let server = rocket::custom(rocket_config)
+        .attach(MaintenanceMode)
+        .attach(ReadOnlyMode)
+        .attach(RouteFaults)
+        .attach(Deprecations)
+        .attach(UserAgentSampling)
+        .attach(RequestCounting)
+        .manage(Bulkheads::default());
+    #[cfg(feature = "graphql")]
+    let server = server.manage(graphql::schema());
+    #[cfg(feature = "error-tracking")]
+    let server = server.attach(service::error_tracking::ErrorReporting);
+    server
         .mount("/_", r["/_"].clone())
-        .mount("/v1", r["/v1"].clone())
+        .mount(&api_version::base(api_version::CURRENT), r["/v1"].clone())
+        .mount("/v1", r["/v1"].clone()) // compatibility with shippers
         .register(catchers![
             route::error::bad_request,
+            route::error::forbidden,
             route::error::internal_server_error,
+            route::error::not_acceptable,
             route::error::not_found,
+            route::error::payload_too_large,
+            route::error::service_unavailable,
+            route::error::too_many_requests,
             route::error::unauthorized,
             route::error::unprocessable_entity,
+            route::error::unsupported_media_type,
         ])
 }
@@ -0,0 +1,321 @@
+//! Edition and license.
+//!
+//! The server runs as the community edition unless a license file is given.
+//! A license is a JWT signed (RS256) by the vendor, and it enables enterprise
+//! features listed in its claims until it expires. The license is loaded at
+//! launch and managed as a state of the server.
+//!
+//! The public key of the vendor is embedded in the binary (not configurable),
+//! otherwise anyone could sign a license for themselves.
+use std::fmt;
+use std::fs;
+
+use chrono::NaiveDateTime;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode as decode_token};
+
+use crate::clock::Clock;
+use crate::config::Config;
+
+const ISSUER: &str = "com.eloquentlog";
+
+const PUBLIC_KEY: &[u8] = include_bytes!("license/vendor.pem");
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edition {
+    Community,
+    Enterprise,
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Community => write!(f, "community"),
+            Self::Enterprise => write!(f, "enterprise"),
+        }
+    }
+}
+
+/// Features only available in the enterprise edition.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feature {
+    AuditExport,
+    RlsMode,
+    Sso,
+}
+
+const FEATURES: [Feature; 3] =
+    [Feature::AuditExport, Feature::RlsMode, Feature::Sso];
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::AuditExport => write!(f, "audit_export"),
+            Self::RlsMode => write!(f, "rls_mode"),
+            Self::Sso => write!(f, "sso"),
+        }
+    }
+}
+
+impl Feature {
+    pub fn from_name(s: &str) -> Option<Self> {
+        FEATURES.iter().find(|f| f.to_string() == s).copied()
+    }
+
+    fn label(&self) -> &'static str {
+        match *self {
+            Self::AuditExport => "Audit log export",
+            Self::RlsMode => "Row level security mode",
+            Self::Sso => "Single sign-on",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LicenseError {
+    Expired,
+    Invalid,
+    NotAvailable(Feature),
+    NotFound,
+}
+
+impl fmt::Display for LicenseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Expired => write!(f, "The license has been expired."),
+            Self::Invalid => write!(f, "The license is invalid."),
+            Self::NotAvailable(feature) => write!(
+                f,
+                "{} is only available in the enterprise edition.",
+                feature.label()
+            ),
+            Self::NotFound => write!(f, "The license file is not found."),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LicenseClaims {
+    pub sub: String, // licensee
+    pub iss: String,
+    pub iat: usize,
+    pub exp: usize,
+    pub features: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct License {
+    pub edition: Edition,
+    pub licensee: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    features: Vec<Feature>,
+}
+
+impl Default for License {
+    fn default() -> Self {
+        Self {
+            edition: Edition::Community,
+            licensee: None,
+            expires_at: None,
+            features: vec![],
+        }
+    }
+}
+
+impl fmt::Display for License {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<License {edition}>", edition = &self.edition)
+    }
+}
+
+impl License {
+    /// Loads the license file in config (community edition if not given).
+    pub fn load(config: &Config) -> Result<Self, LicenseError> {
+        if config.license_file.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let token = fs::read_to_string(&config.license_file)
+            .map_err(|_| LicenseError::NotFound)?;
+        Self::decode(token.trim(), PUBLIC_KEY)
+    }
+
+    /// Verifies the signature of the token with the public key (PEM).
+    pub fn decode(token: &str, key: &[u8]) -> Result<Self, LicenseError> {
+        let key =
+            DecodingKey::from_rsa_pem(key).map_err(|_| LicenseError::Invalid)?;
+        let v = Validation {
+            algorithms: vec![Algorithm::RS256],
+            iss: Some(ISSUER.to_string()),
+            validate_exp: true,
+
+            ..Validation::default()
+        };
+
+        let claims = decode_token::<LicenseClaims>(token, &key, &v)
+            .map(|t| t.claims)
+            .map_err(|e| match *e.kind() {
+                jsonwebtoken::errors::ErrorKind::ExpiredSignature => {
+                    LicenseError::Expired
+                },
+                _ => LicenseError::Invalid,
+            })?;
+
+        Ok(Self {
+            edition: Edition::Enterprise,
+            licensee: Some(claims.sub),
+            expires_at: Some(NaiveDateTime::from_timestamp(
+                claims.exp as i64,
+                0,
+            )),
+            // unknown features (for newer versions) are ignored
+            features: claims
+                .features
+                .iter()
+                .filter_map(|f| Feature::from_name(f))
+                .collect(),
+        })
+    }
+
+    pub fn is_expired(&self, clock: &dyn Clock) -> bool {
+        match self.expires_at {
+            Some(t) => t <= clock.now().naive_utc(),
+            None => false,
+        }
+    }
+
+    pub fn is_enabled(&self, feature: Feature, clock: &dyn Clock) -> bool {
+        self.require(feature, clock).is_ok()
+    }
+
+    /// Returns an error which explains why the feature is not available.
+    pub fn require(
+        &self,
+        feature: Feature,
+        clock: &dyn Clock,
+    ) -> Result<(), LicenseError> {
+        if self.edition == Edition::Community ||
+            !self.features.contains(&feature)
+        {
+            return Err(LicenseError::NotAvailable(feature));
+        }
+        if self.is_expired(clock) {
+            return Err(LicenseError::Expired);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{Duration, Utc};
+    use jsonwebtoken::{EncodingKey, Header, encode};
+
+    use crate::clock::{FrozenClock, SystemClock};
+
+    const PRIVATE_KEY: &[u8] =
+        include_bytes!("../test/fixture/license/private.pem");
+    // not the one of the vendor
+    const TEST_PUBLIC_KEY: &[u8] =
+        include_bytes!("../test/fixture/license/public.pem");
+
+    fn encode_license(features: &[&str], exp: i64) -> String {
+        let claims = LicenseClaims {
+            sub: "Eloquentlog".to_string(),
+            iss: ISSUER.to_string(),
+            iat: Utc::now().timestamp() as usize,
+            exp: exp as usize,
+            features: features.iter().map(|f| f.to_string()).collect(),
+        };
+        encode(
+            &Header::new(Algorithm::RS256),
+            &claims,
+            &EncodingKey::from_rsa_pem(PRIVATE_KEY).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_default() {
+        let license = License::default();
+        assert_eq!(license.edition, Edition::Community);
+        assert!(!license.is_enabled(Feature::Sso, &SystemClock));
+        assert_eq!(
+            license.require(Feature::Sso, &SystemClock),
+            Err(LicenseError::NotAvailable(Feature::Sso))
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let exp = (Utc::now() + Duration::days(1)).timestamp();
+        let token = encode_license(&["sso", "unknown"], exp);
+
+        let license = License::decode(&token, TEST_PUBLIC_KEY).unwrap();
+        assert_eq!(license.edition, Edition::Enterprise);
+        assert_eq!(license.licensee, Some("Eloquentlog".to_string()));
+        assert!(license.is_enabled(Feature::Sso, &SystemClock));
+        assert!(!license.is_enabled(Feature::AuditExport, &SystemClock));
+        assert!(!license.is_enabled(Feature::RlsMode, &SystemClock));
+    }
+
+    #[test]
+    fn test_require_after_expiration() {
+        let now = Utc::now();
+        let exp = (now + Duration::days(1)).timestamp();
+        let token = encode_license(&["sso"], exp);
+
+        let license = License::decode(&token, TEST_PUBLIC_KEY).unwrap();
+        let clock = FrozenClock::new(now);
+        assert!(!license.is_expired(&clock));
+        assert_eq!(license.require(Feature::Sso, &clock), Ok(()));
+
+        clock.advance(Duration::days(2));
+        assert!(license.is_expired(&clock));
+        assert_eq!(
+            license.require(Feature::Sso, &clock),
+            Err(LicenseError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_decode_expired_license() {
+        let exp = (Utc::now() - Duration::days(1)).timestamp();
+        let token = encode_license(&["sso"], exp);
+
+        let result = License::decode(&token, TEST_PUBLIC_KEY);
+        assert_eq!(result.err(), Some(LicenseError::Expired));
+    }
+
+    #[test]
+    fn test_decode_tampered_license() {
+        let exp = (Utc::now() + Duration::days(1)).timestamp();
+        let token = encode_license(&["sso"], exp);
+
+        let mut parts: Vec<&str> = token.split('.').collect();
+        let other = encode_license(&["sso", "audit_export"], exp);
+        let payload = other.split('.').nth(1).unwrap().to_string();
+        parts[1] = &payload;
+
+        let result = License::decode(&parts.join("."), TEST_PUBLIC_KEY);
+        assert_eq!(result.err(), Some(LicenseError::Invalid));
+    }
+
+    #[test]
+    fn test_decode_license_signed_by_another_key() {
+        let exp = (Utc::now() + Duration::days(1)).timestamp();
+        let token = encode_license(&["sso"], exp);
+
+        let result = License::decode(&token, PUBLIC_KEY);
+        assert_eq!(result.err(), Some(LicenseError::Invalid));
+    }
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            LicenseError::NotAvailable(Feature::Sso).to_string(),
+            "Single sign-on is only available in the enterprise edition."
+        );
+    }
+}
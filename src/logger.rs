@@ -1,5 +1,19 @@
+//! Loggers of the server and the commands.
+//!
+//! The format is either `terminal` (human readable) or `json` (a JSON object
+//! per line, e.g. to ship the logs into a stream by an agent). The records are
+//! written into stdout (or stderr) and also into `LOG_FILE` (appended) and
+//! syslog with the `LOG_SYSLOG_FACILITY` if they are set.
+use std::fs::OpenOptions;
+use std::io;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use slog::{o, Drain, Duplicate, Level, Never, OwnedKVList, Record};
 use sloggers::{
     Build,
+    file::FileLoggerBuilder,
+    syslog::{Facility, SyslogBuilder},
     terminal::{TerminalLoggerBuilder, Destination},
     types::Severity,
 };
@@ -8,25 +22,173 @@ use crate::config::Config;
 
 pub type Logger = slog::Logger;
 
+pub const LOG_FORMATS: &[&str] = &["terminal", "json"];
+
+pub const LOG_LEVELS: &[&str] =
+    &["trace", "debug", "info", "warning", "error", "critical"];
+
+pub const LOG_SYSLOG_FACILITIES: &[&str] = &[
+    "user", "daemon", "local0", "local1", "local2", "local3", "local4",
+    "local5", "local6", "local7",
+];
+
+// the level set at runtime for all loggers (0 means not set)
+static LEVEL: AtomicUsize = AtomicUsize::new(0);
+
+pub fn parse_level(s: &str) -> Option<Level> {
+    match s {
+        "trace" => Some(Level::Trace),
+        "debug" => Some(Level::Debug),
+        "info" => Some(Level::Info),
+        "warning" => Some(Level::Warning),
+        "error" => Some(Level::Error),
+        "critical" => Some(Level::Critical),
+        _ => None,
+    }
+}
+
+/// Changes the level of all loggers (None resets it to their own one).
+pub fn set_level(level: Option<Level>) {
+    LEVEL.store(level.map(|l| l.as_usize()).unwrap_or(0), Ordering::SeqCst);
+}
+
+// filters the records by the level at runtime if it's set
+struct LevelFilter {
+    drain: Logger,
+    level: Level,
+}
+
+impl Drain for LevelFilter {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(
+        &self,
+        record: &Record,
+        values: &OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        if self.is_enabled(record.level()) {
+            Drain::log(&self.drain, record, values)?;
+        }
+        Ok(())
+    }
+
+    fn is_enabled(&self, level: Level) -> bool {
+        let current = LEVEL.load(Ordering::SeqCst);
+        level.is_at_least(Level::from_usize(current).unwrap_or(self.level))
+    }
+}
+
 pub fn get_logger(config: &Config) -> Logger {
+    build(config, Destination::Stdout)
+}
+
+/// Returns a logger which writes into stderr, for commands whose output is
+/// data (e.g. `export`).
+pub fn get_stderr_logger(config: &Config) -> Logger {
+    build(config, Destination::Stderr)
+}
+
+fn parse_facility(s: &str) -> Option<Facility> {
+    match s {
+        "user" => Some(Facility::User),
+        "daemon" => Some(Facility::Daemon),
+        "local0" => Some(Facility::Local0),
+        "local1" => Some(Facility::Local1),
+        "local2" => Some(Facility::Local2),
+        "local3" => Some(Facility::Local3),
+        "local4" => Some(Facility::Local4),
+        "local5" => Some(Facility::Local5),
+        "local6" => Some(Facility::Local6),
+        "local7" => Some(Facility::Local7),
+        _ => None,
+    }
+}
+
+// writes a JSON object per line (with `ts`, `level` and `msg`)
+fn json<W>(writer: W) -> Logger
+where W: io::Write + Send + 'static {
+    let drain = slog_json::Json::new(writer)
+        .set_flush(true)
+        .add_default_keys()
+        .build();
+    slog::Logger::root(Mutex::new(drain).fuse(), o!())
+}
+
+fn terminal(config: &Config, destination: Destination) -> Logger {
+    if config.log_format == "json" {
+        return match destination {
+            Destination::Stderr => json(io::stderr()),
+            _ => json(io::stdout()),
+        };
+    }
     let mut builder = TerminalLoggerBuilder::new();
+    // the filter below decides it
+    builder.level(Severity::Trace);
+    builder.destination(destination);
+    builder.build().unwrap()
+}
 
-    let level = match config.env_name {
-        "development" => Severity::Debug,
-        "production" => Severity::Error,
-        "testing" => Severity::Warning,
-        _ => Severity::Trace,
-    };
+fn file(config: &Config, path: &str) -> Logger {
+    if config.log_format == "json" {
+        let f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap_or_else(|e| panic!("LOG_FILE {}: {}", path, e));
+        return json(f);
+    }
+    let mut builder = FileLoggerBuilder::new(path);
+    builder.level(Severity::Trace);
+    builder.build().unwrap()
+}
 
-    builder.level(level);
-    builder.destination(Destination::Stdout);
+fn syslog(facility: Facility) -> Logger {
+    let mut builder = SyslogBuilder::new();
+    builder.level(Severity::Trace);
+    builder.facility(facility);
     builder.build().unwrap()
 }
 
+fn build(config: &Config, destination: Destination) -> Logger {
+    let mut drain = terminal(config, destination);
+    if !config.log_file.is_empty() {
+        let f = file(config, &config.log_file);
+        drain = slog::Logger::root(Duplicate::new(drain, f).fuse(), o!());
+    }
+    if let Some(facility) = parse_facility(&config.log_syslog_facility) {
+        let s = syslog(facility);
+        drain = slog::Logger::root(Duplicate::new(drain, s).fuse(), o!());
+    }
+    // errors are sent to Sentry (nothing is sent unless it's initialized)
+    #[cfg(feature = "error-tracking")]
+    {
+        use crate::service::error_tracking::ErrorDrain;
+        let errors = Duplicate::new(drain, ErrorDrain).fuse();
+        drain = slog::Logger::root(errors, o!());
+    }
+
+    let level = match parse_level(&config.log_level) {
+        Some(l) => l,
+        None => match config.env_name {
+            "development" => Level::Debug,
+            "production" => Level::Error,
+            "testing" => Level::Warning,
+            _ => Level::Trace,
+        },
+    };
+
+    slog::Logger::root(LevelFilter { drain, level }, o!())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use std::env;
+    use std::fs;
+
+    use serde_json::Value;
     use slog::Drain;
 
     use crate::model::test::run;
@@ -76,4 +238,71 @@ mod test {
             assert!(!logger.is_trace_enabled());
         })
     }
+
+    #[test]
+    fn test_get_logger_with_log_level() {
+        run(|_, config, _| {
+            let mut c = config.clone();
+            c.log_level = "info".to_string();
+            let logger = get_logger(&c);
+
+            assert!(logger.is_info_enabled());
+            assert!(!logger.is_debug_enabled());
+        })
+    }
+
+    #[test]
+    fn test_get_logger_json_with_log_file() {
+        run(|_, config, _| {
+            let path = env::temp_dir().join(format!(
+                "eloquentlog-test-{}.log",
+                uuid::Uuid::new_v4().to_simple()
+            ));
+            let mut c = config.clone();
+            c.log_format = "json".to_string();
+            c.log_file = path.to_string_lossy().to_string();
+            let logger = get_logger(&c);
+
+            warn!(logger, "hello"; "user_id" => 1);
+            info!(logger, "ignored");
+
+            let s = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            let lines: Vec<&str> = s.lines().collect();
+            assert_eq!(lines.len(), 1);
+            let record: Value = serde_json::from_str(lines[0]).unwrap();
+            assert_eq!(record["msg"], "hello");
+            assert_eq!(record["level"], "WARN");
+            assert_eq!(record["user_id"], 1);
+            assert!(record["ts"].is_string());
+        })
+    }
+
+    #[test]
+    fn test_parse_facility() {
+        for s in LOG_SYSLOG_FACILITIES {
+            assert!(parse_facility(s).is_some(), "{}", s);
+        }
+        assert!(parse_facility("").is_none());
+        assert!(parse_facility("kern").is_none());
+    }
+
+    // the level is shared by all loggers in the process
+    rusty_fork_test! {
+        #[test]
+        fn test_set_level() {
+            run(|_, config, _| {
+                let logger = get_logger(config);
+                assert!(!logger.is_info_enabled());
+
+                set_level(parse_level("debug"));
+                assert!(logger.is_debug_enabled());
+                assert!(!logger.is_trace_enabled());
+
+                set_level(None);
+                assert!(!logger.is_info_enabled());
+            })
+        }
+    }
 }
@@ -0,0 +1,68 @@
+//! The transport which keeps emails in the process (`MAILER_TRANSPORT=memory`).
+//!
+//! Nothing is sent, so that tests and local development don't need any SMTP
+//! server. The emails can be read by `sent` (e.g. to take a token in them).
+use lazy_static::lazy_static;
+use lettre::{SendableEmail, Transport};
+use lettre::smtp::error::SmtpResult;
+use lettre::smtp::response::{Category, Code, Detail, Response, Severity};
+use parking_lot::Mutex;
+
+// the oldest ones are dropped
+const MAX_EMAILS: usize = 1000;
+
+lazy_static! {
+    static ref OUTBOX: Mutex<Vec<SentEmail>> = Mutex::new(vec![]);
+}
+
+/// SentEmail
+#[derive(Clone, Debug, PartialEq)]
+pub struct SentEmail {
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub message_id: String,
+    /// The whole message including the headers
+    pub message: String,
+}
+
+/// Returns the emails transported until now (the oldest first).
+pub fn sent() -> Vec<SentEmail> {
+    OUTBOX.lock().clone()
+}
+
+/// Removes all of the emails.
+pub fn clear() {
+    OUTBOX.lock().clear();
+}
+
+pub struct MemoryTransport;
+
+impl<'a> Transport<'a> for MemoryTransport {
+    type Result = SmtpResult;
+
+    fn send(&mut self, email: SendableEmail) -> SmtpResult {
+        let envelope = email.envelope().clone();
+        let message_id = email.message_id().to_string();
+        let message = email.message_to_string()?;
+
+        let mut outbox = OUTBOX.lock();
+        if outbox.len() >= MAX_EMAILS {
+            outbox.remove(0);
+        }
+        outbox.push(SentEmail {
+            from: envelope.from().map(|a| a.to_string()),
+            to: envelope.to().iter().map(|a| a.to_string()).collect(),
+            message_id,
+            message,
+        });
+
+        Ok(Response {
+            code: Code::new(
+                Severity::PositiveCompletion,
+                Category::MailSystem,
+                Detail::Zero,
+            ),
+            message: vec![],
+        })
+    }
+}
@@ -1,7 +1,11 @@
 //! Mailer sends email.
 
+pub mod memory;
+pub mod security;
 pub mod user;
 
+use std::thread;
+
 use lettre::{
     ClientSecurity, ClientTlsParameters, Transport, SendableEmail, SmtpClient,
 };
@@ -13,18 +17,26 @@ use lettre::smtp::client::net::DEFAULT_TLS_PROTOCOLS;
 use native_tls::TlsConnector;
 use slog::Logger;
 
-use crate::config::Config;
+use crate::config::{Config, MailerSecurity, MailerTransport};
+use crate::mailer::memory::MemoryTransport;
+use crate::service::fault_injection::smtp_delay;
+use crate::telemetry::{SpanKind, span};
 
+// (email, name) pairs
 struct Header<'a> {
     from: (&'a str, &'a str),
-    to: (&'a str, &'a str),
+    to: Vec<(&'a str, &'a str)>,
+    cc: Vec<(&'a str, &'a str)>,
+    bcc: Vec<(&'a str, &'a str)>,
 }
 
 impl<'a> Default for Header<'a> {
     fn default() -> Self {
         Self {
             from: ("", ""),
-            to: ("", ""),
+            to: vec![],
+            cc: vec![],
+            bcc: vec![],
         }
     }
 }
@@ -40,9 +52,13 @@ pub struct Mailer<'a> {
 impl<'a> Mailer<'a> {
     // TODO: connection manager (r2d2)
     pub fn build_client(config: &Config) -> Client<'a> {
+        if config.mailer_transport == MailerTransport::Memory {
+            return Box::new(MemoryTransport);
+        }
+
         // NOTE:
-        // This TlsConnectors uses SSL/TLS.
-        // Thus, you may want to use 25/465 than 587.
+        // `tls` (default) uses SSL/TLS from the start, thus you may want to
+        // use 465 than 587. Use `starttls` for 587.
         let mut tls_builder = TlsConnector::builder();
         tls_builder.min_protocol_version(Some(DEFAULT_TLS_PROTOCOLS[0]));
         let tls_parameters = ClientTlsParameters::new(
@@ -50,9 +66,17 @@ impl<'a> Mailer<'a> {
             tls_builder.build().unwrap(),
         );
 
+        let security = match config.mailer_smtp_security {
+            MailerSecurity::Tls => ClientSecurity::Wrapper(tls_parameters),
+            MailerSecurity::StartTls => {
+                ClientSecurity::Required(tls_parameters)
+            },
+            MailerSecurity::None => ClientSecurity::None,
+        };
+
         let client = SmtpClient::new(
             (config.mailer_smtp_host.as_str(), config.mailer_smtp_port),
-            ClientSecurity::Wrapper(tls_parameters),
+            security,
         )
         .unwrap()
         .hello_name(ClientId::Domain(config.mailer_domain.to_string()))
@@ -83,17 +107,25 @@ impl<'a> Mailer<'a> {
 
     /// Transports an email.
     ///
-    /// `lettre_email::Email` implements Into<lettre::SenderableEmail>.
+    /// `lettre_email::Email` implements Into<lettre::SenderableEmail>. The
+    /// client is built at the first time, and its connection is reused for
+    /// the following emails (e.g. a batch to many recipients).
     pub fn send(&mut self, email: SendableEmail) -> bool {
-        let result;
-        if let Some(ref mut c) = self.client {
-            result = c.send(email);
-        } else {
-            let mut client = Self::build_client(self.config);
-            result = client.send(email);
+        let s = span("mailer.send", SpanKind::Client);
+        let delay = smtp_delay(self.config);
+        if delay.as_millis() > 0 {
+            warn!(self.logger, "fault: smtp is delayed {:?}", delay);
+            thread::sleep(delay);
         }
+
+        let config = self.config;
+        let client = self
+            .client
+            .get_or_insert_with(|| Self::build_client(config));
+        let result = client.send(email);
         if let Err(ref e) = result {
             error!(self.logger, "err: {}", e);
+            s.fail();
         }
         result.is_ok()
     }
@@ -170,4 +202,36 @@ mod test {
             assert!(mailer.send(email));
         })
     }
+
+    #[test]
+    fn test_email_send_in_memory() {
+        run(|_, config, logger| {
+            let mut config = config.clone();
+            config.mailer_transport = MailerTransport::Memory;
+            let mut mailer = Mailer::new(&config, logger);
+
+            let u = USERS.get("oswald").unwrap();
+            let email = SendableEmail::new(
+                Envelope::new(
+                    Some(
+                        EmailAddress::new(config.mailer_from_email.to_string())
+                            .unwrap(),
+                    ),
+                    vec![EmailAddress::new(u.email.to_string()).unwrap()],
+                )
+                .unwrap(),
+                "in-memory".to_string(),
+                b"Hello, world!".to_vec(),
+            );
+            assert!(mailer.send(email));
+
+            // other tests may also send emails
+            let sent = memory::sent()
+                .into_iter()
+                .find(|e| e.message_id == "in-memory")
+                .unwrap();
+            assert_eq!(sent.to, vec![u.email.to_string()]);
+            assert_eq!(sent.message, "Hello, world!");
+        })
+    }
 }
@@ -0,0 +1,239 @@
+//! SecurityMailer
+//!
+//! Notices on the security of the account (e.g. a password change). The
+//! critical ones are always sent, and the others only to the users who have
+//! not opted out of them (see `model::notification_preference`).
+
+use chrono::{DateTime, Utc};
+use slog::Logger;
+
+use crate::config::Config;
+use crate::mailer::Client;
+use crate::mailer::user::{UserMailer, greeting};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SecurityNotice {
+    PasswordChanged,
+    NewSignIn,
+    // two-factor authentication is not available yet, so nothing sends it
+    // for now
+    TwoFactorDisabled,
+}
+
+impl SecurityNotice {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityNotice::PasswordChanged => "password_changed",
+            SecurityNotice::NewSignIn => "new_sign_in",
+            SecurityNotice::TwoFactorDisabled => "two_factor_disabled",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "password_changed" => Some(SecurityNotice::PasswordChanged),
+            "new_sign_in" => Some(SecurityNotice::NewSignIn),
+            "two_factor_disabled" => Some(SecurityNotice::TwoFactorDisabled),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the user can't opt out of it.
+    pub fn is_critical(&self) -> bool {
+        *self != SecurityNotice::NewSignIn
+    }
+}
+
+/// SecurityMailer sends the security notices to a user.
+pub struct SecurityMailer<'a> {
+    config: &'a Config,
+    /// The name of the recipient (for the greeting).
+    name: &'a str,
+    mailer: UserMailer<'a>,
+}
+
+impl<'a> SecurityMailer<'a> {
+    pub fn new(config: &'a Config, logger: &'a Logger) -> Self {
+        Self {
+            config,
+            name: "",
+            mailer: UserMailer::new(config, logger),
+        }
+    }
+
+    /// Sets the recipient (email, name) and returns mailer itself.
+    pub fn to(&mut self, to: (&'a str, &'a str)) -> &mut Self {
+        self.name = to.1;
+        self.mailer.to(to);
+        self
+    }
+
+    pub fn inject(&mut self, client: Option<Client<'a>>) {
+        self.mailer.inject(client);
+    }
+
+    /// Builds a message of the notice and send it via actual mailer. The IP
+    /// address is only for a new sign-in.
+    pub fn send_security_notification_email(
+        &mut self,
+        notice: SecurityNotice,
+        at: &DateTime<Utc>,
+        ip: &str,
+    ) -> bool {
+        let (subject, message) =
+            self.security_notification_email(notice, at, ip);
+        self.mailer.send(&subject, message)
+    }
+
+    fn security_notification_email(
+        &self,
+        notice: SecurityNotice,
+        at: &DateTime<Utc>,
+        ip: &str,
+    ) -> (String, String) {
+        let url = self.config.application_url.to_string();
+        let at = at.format("%Y-%m-%d %H:%M UTC").to_string();
+
+        let (subject, body) = match notice {
+            SecurityNotice::PasswordChanged => (
+                "Your password has been changed",
+                format!(
+                    r#"The password of your Eloquentlog account has been changed at {}.
+
+If you did this, you can disregard this email. Otherwise, reset your password
+right away and let us know."#,
+                    at,
+                ),
+            ),
+            SecurityNotice::NewSignIn => (
+                "New sign-in to your account",
+                format!(
+                    r#"Your Eloquentlog account has been signed in from a new IP address.
+
+IP address: {}
+Time: {}
+
+If this was you, you can disregard this email. Otherwise, reset your password
+right away.
+
+You can turn off these notices in your settings."#,
+                    ip, at,
+                ),
+            ),
+            SecurityNotice::TwoFactorDisabled => (
+                "Two-factor authentication has been disabled",
+                format!(
+                    r#"Two-factor authentication of your Eloquentlog account has been disabled at {}.
+
+If you did this, you can disregard this email. Otherwise, reset your password
+and enable it again right away."#,
+                    at,
+                ),
+            ),
+        };
+        // TODO: use template file
+        let message = format!(
+            r#"
+{}
+
+{}
+
+--
+Eloquentlog
+{}
+"#,
+            greeting(self.name),
+            body,
+            url,
+        );
+        (subject.to_string(), message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::TimeZone;
+    use dotenv::dotenv;
+    use insta::assert_snapshot;
+
+    use crate::logger::get_logger;
+
+    // the values which appear in messages are fixed (not from .env)
+    fn run<T>(test: T)
+    where T: FnOnce(&SecurityMailer) {
+        dotenv().ok();
+        let mut config = Config::from("testing").unwrap();
+        config.application_url = "https://eloquentlog.com".to_string();
+        let logger = get_logger(&config);
+
+        let mut mailer = SecurityMailer::new(&config, &logger);
+        mailer.to(("postmaster@eloquentlog.com", "Name"));
+        test(&mailer)
+    }
+
+    fn render((subject, message): (String, String)) -> String {
+        format!("Subject: {}\n\n{}", subject, message.trim())
+    }
+
+    fn at() -> DateTime<Utc> {
+        Utc.ymd(2021, 7, 9).and_hms(12, 34, 56)
+    }
+
+    #[test]
+    fn test_security_notice_name() {
+        for notice in &[
+            SecurityNotice::PasswordChanged,
+            SecurityNotice::NewSignIn,
+            SecurityNotice::TwoFactorDisabled,
+        ] {
+            let name = notice.as_str();
+            assert_eq!(SecurityNotice::from_name(name), Some(*notice));
+        }
+        assert_eq!(SecurityNotice::from_name("unknown"), None);
+    }
+
+    #[test]
+    fn test_security_notice_is_critical() {
+        assert!(SecurityNotice::PasswordChanged.is_critical());
+        assert!(SecurityNotice::TwoFactorDisabled.is_critical());
+        assert!(!SecurityNotice::NewSignIn.is_critical());
+    }
+
+    #[test]
+    fn test_password_changed_email() {
+        run(|mailer| {
+            let email = mailer.security_notification_email(
+                SecurityNotice::PasswordChanged,
+                &at(),
+                "",
+            );
+            assert_snapshot!("password_changed_email", render(email));
+        })
+    }
+
+    #[test]
+    fn test_new_sign_in_email() {
+        run(|mailer| {
+            let email = mailer.security_notification_email(
+                SecurityNotice::NewSignIn,
+                &at(),
+                "192.0.2.1",
+            );
+            assert_snapshot!("new_sign_in_email", render(email));
+        })
+    }
+
+    #[test]
+    fn test_two_factor_disabled_email() {
+        run(|mailer| {
+            let email = mailer.security_notification_email(
+                SecurityNotice::TwoFactorDisabled,
+                &at(),
+                "",
+            );
+            assert_snapshot!("two_factor_disabled_email", render(email));
+        })
+    }
+}
@@ -1,6 +1,7 @@
 #![allow(clippy::needless_doctest_main)]
 //! UserMailer
 
+use lettre::SendableEmail;
 use lettre_email::Email;
 use slog::Logger;
 
@@ -56,6 +57,15 @@ pub struct UserMailer<'a> {
     mailer: Mailer<'a>,
 }
 
+// e.g. `Hi Oswald,` (or `Hi,` without the name)
+pub(super) fn greeting(name: &str) -> String {
+    if name.is_empty() {
+        "Hi,".to_string()
+    } else {
+        format!("Hi {},", name)
+    }
+}
+
 impl<'a> UserMailer<'a> {
     /// Creates a new UserMailer.
     pub fn new(config: &'a Config, logger: &'a Logger) -> Self {
@@ -73,9 +83,22 @@ impl<'a> UserMailer<'a> {
         }
     }
 
-    /// Sets to and returns mailer itself.
+    /// Adds a recipient (email, name) and returns mailer itself.
     pub fn to(&mut self, to: (&'a str, &'a str)) -> &mut Self {
-        self.header.to = to;
+        self.header.to.push(to);
+        self
+    }
+
+    /// Adds a carbon copy recipient and returns mailer itself.
+    pub fn cc(&mut self, cc: (&'a str, &'a str)) -> &mut Self {
+        self.header.cc.push(cc);
+        self
+    }
+
+    /// Adds a blind carbon copy recipient and returns mailer itself. It's
+    /// only in the envelope (not in the headers).
+    pub fn bcc(&mut self, bcc: (&'a str, &'a str)) -> &mut Self {
+        self.header.bcc.push(bcc);
         self
     }
 
@@ -83,11 +106,81 @@ impl<'a> UserMailer<'a> {
         self.mailer.client = client;
     }
 
+    // renders a url template of the config (e.g. `url_template_password_reset`)
+    // with the values, which must not contain any placeholder like tokens
+    fn url(&self, template: &str, values: &[(&str, &str)]) -> String {
+        let application_url = self.config.application_url.trim_end_matches('/');
+        values.iter().fold(
+            template.replace("{application_url}", application_url),
+            |url, (name, value)| url.replace(&format!("{{{}}}", name), value),
+        )
+    }
+
+    fn build(&self, subject: &str, message: String) -> Option<SendableEmail> {
+        let mut builder = Email::builder()
+            .from(self.header.from)
+            .subject(subject)
+            .text(message);
+        for to in self.header.to.iter() {
+            builder = builder.to(*to);
+        }
+        for cc in self.header.cc.iter() {
+            builder = builder.cc(*cc);
+        }
+        for bcc in self.header.bcc.iter() {
+            builder = builder.bcc(*bcc);
+        }
+        match builder.build() {
+            Ok(email) => Some(email.into()),
+            Err(e) => {
+                error!(self.mailer.logger, "err: {}", e);
+                None
+            },
+        }
+    }
+
+    pub(super) fn send(&mut self, subject: &str, message: String) -> bool {
+        match self.build(subject, message) {
+            Some(email) => self.mailer.send(email),
+            None => false,
+        }
+    }
+
+    // sends a message rendered for each of the recipients (email, name)
+    // separately over the same connection, and returns the number of the sent
+    // ones. The cc and bcc recipients are on every message.
+    fn send_each<F>(
+        &mut self,
+        recipients: &[(&'a str, &'a str)],
+        render: F,
+    ) -> usize
+    where
+        F: Fn(&Self, &str) -> (String, String),
+    {
+        let mut sent = 0;
+        for recipient in recipients {
+            self.header.to = vec![*recipient];
+            let (subject, message) = render(self, recipient.1);
+            if self.send(&subject, message) {
+                sent += 1;
+            }
+        }
+        self.header.to.clear();
+        sent
+    }
+
     /// Builds an user activation message and send it via actual mailer.
     pub fn send_user_activation_email(&mut self, s: &str, t: &str) -> bool {
+        let (subject, message) = self.user_activation_email(s, t);
+        self.send(&subject, message)
+    }
+
+    fn user_activation_email(&self, s: &str, t: &str) -> (String, String) {
         let url = self.config.application_url.to_string();
-        // TODO: build it with rocket::http::uri::Origin?
-        let activation_url = format!("{}/user/activate?s={}&t={}", url, s, t);
+        let activation_url = self.url(
+            &self.config.url_template_user_activation,
+            &[("s", s), ("t", t)],
+        );
 
         let subject = "Activate your account";
         // TODO: use template file
@@ -108,21 +201,21 @@ Eloquentlog
 "#,
             activation_url, url,
         );
-        let email = Email::builder()
-            .to(self.header.to)
-            .from(self.header.from)
-            .subject(subject)
-            .text(message)
-            .build()
-            .unwrap();
-        self.mailer.send(email.into())
+        (subject.to_string(), message)
     }
 
     /// Builds a password reset message and send it via actual mailer.
     pub fn send_password_reset_email(&mut self, s: &str, t: &str) -> bool {
+        let (subject, message) = self.password_reset_email(s, t);
+        self.send(&subject, message)
+    }
+
+    fn password_reset_email(&self, s: &str, t: &str) -> (String, String) {
         let url = self.config.application_url.to_string();
-        // TODO: build it with rocket::http::uri::Origin?
-        let reset_url = format!("{}/password/reset?s={}&t={}", url, s, t);
+        let reset_url = self.url(
+            &self.config.url_template_password_reset,
+            &[("s", s), ("t", t)],
+        );
 
         let subject = "Reset your password";
         // TODO: use template file
@@ -145,13 +238,355 @@ Eloquentlog
 "#,
             reset_url, url,
         );
-        let email = Email::builder()
-            .to(self.header.to)
-            .from(self.header.from)
-            .subject(subject)
-            .text(message)
-            .build()
-            .unwrap();
-        self.mailer.send(email.into())
+        (subject.to_string(), message)
+    }
+
+    /// Builds a waitlist confirmation (double opt-in) message and send it via
+    /// actual mailer.
+    pub fn send_waitlist_confirmation_email(&mut self, t: &str) -> bool {
+        let (subject, message) = self.waitlist_confirmation_email(t);
+        self.send(&subject, message)
+    }
+
+    fn waitlist_confirmation_email(&self, t: &str) -> (String, String) {
+        let url = self.config.application_url.to_string();
+        let confirmation_url = self
+            .url(&self.config.url_template_waitlist_confirmation, &[("t", t)]);
+
+        let subject = "Confirm your email address";
+        // TODO: use template file
+        let message = format!(
+            r#"
+Hi,
+
+Thank you for your interest in Eloquentlog!
+To confirm your email address for the waitlist, just follow the link below
+
+{}
+
+If you did not sign up for the waitlist, disregard this email.
+
+--
+Eloquentlog
+{}
+"#,
+            confirmation_url, url,
+        );
+        (subject.to_string(), message)
+    }
+
+    /// Builds a message for the new owner of the namespace to accept the
+    /// transfer, and send it via actual mailer.
+    pub fn send_namespace_transfer_email(
+        &mut self,
+        namespace_name: &str,
+        namespace_uuid: &str,
+        t: &str,
+    ) -> bool {
+        let (subject, message) =
+            self.namespace_transfer_email(namespace_name, namespace_uuid, t);
+        self.send(&subject, message)
+    }
+
+    fn namespace_transfer_email(
+        &self,
+        namespace_name: &str,
+        namespace_uuid: &str,
+        t: &str,
+    ) -> (String, String) {
+        let url = self.config.application_url.to_string();
+        let accept_url = self.url(
+            &self.config.url_template_namespace_transfer,
+            &[("namespace", namespace_uuid), ("t", t)],
+        );
+
+        let subject =
+            format!("Transfer of the namespace \"{}\"", namespace_name);
+        // TODO: use template file
+        let message = format!(
+            r#"
+Hi,
+
+You've been asked to become the primary owner of the namespace "{}".
+To accept the transfer, just follow the link below within 24 hours
+
+{}
+
+The current primary owner will become an owner. If you don't want to take it
+over, disregard this email.
+
+--
+Eloquentlog
+{}
+"#,
+            namespace_name, accept_url, url,
+        );
+        (subject, message)
+    }
+
+    /// Builds a quota notification message for the namespace owner and send
+    /// it via actual mailer.
+    pub fn send_quota_notification_email(
+        &mut self,
+        namespace_name: &str,
+        percent: u64,
+    ) -> bool {
+        let (subject, message) =
+            self.quota_notification_email("", namespace_name, percent);
+        self.send(&subject, message)
+    }
+
+    /// Sends the quota notification to each of the owners (email, name) with
+    /// their names, and returns the number of the sent messages.
+    pub fn send_quota_notification_emails(
+        &mut self,
+        recipients: &[(&'a str, &'a str)],
+        namespace_name: &str,
+        percent: u64,
+    ) -> usize {
+        self.send_each(recipients, |mailer, name| {
+            mailer.quota_notification_email(name, namespace_name, percent)
+        })
+    }
+
+    fn quota_notification_email(
+        &self,
+        name: &str,
+        namespace_name: &str,
+        percent: u64,
+    ) -> (String, String) {
+        let url = self.config.application_url.to_string();
+
+        let subject = format!("{}% of today's quota used", percent);
+        let grace = if percent >= 100 {
+            format!(
+                "Ingestion continues for {} minutes of the grace period, then \
+new messages will be rejected until the quota is reset at midnight (UTC).",
+                self.config.quota_grace_period / 60
+            )
+        } else {
+            "New messages will be rejected after the grace period once the \
+quota is used up."
+                .to_string()
+        };
+        // TODO: use template file
+        let message = format!(
+            r#"
+{}
+
+Your namespace "{}" has used {}% of today's quota.
+{}
+
+--
+Eloquentlog
+{}
+"#,
+            greeting(name),
+            namespace_name,
+            percent,
+            grace,
+            url,
+        );
+        (subject, message)
+    }
+
+    /// Builds an alert message on the stream and send it via actual mailer.
+    pub fn send_alert_email(
+        &mut self,
+        namespace_name: &str,
+        stream_name: &str,
+        title: &str,
+    ) -> bool {
+        let (subject, message) =
+            self.alert_email(namespace_name, stream_name, title);
+        self.send(&subject, message)
+    }
+
+    fn alert_email(
+        &self,
+        namespace_name: &str,
+        stream_name: &str,
+        title: &str,
+    ) -> (String, String) {
+        let url = self.config.application_url.to_string();
+
+        let subject = format!("[{}/{}] {}", namespace_name, stream_name, title);
+        // TODO: use template file
+        let message = format!(
+            r#"
+Hi,
+
+A new alert has been raised on the stream "{}" of the namespace "{}".
+
+{}
+
+You can change when you receive alerts in your settings.
+
+--
+Eloquentlog
+{}
+"#,
+            stream_name, namespace_name, title, url,
+        );
+        (subject, message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use dotenv::dotenv;
+    use insta::assert_snapshot;
+    use lettre::smtp::response::{Category, Code, Detail, Severity};
+
+    use crate::logger::get_logger;
+
+    include!("./mock_transport.rs");
+
+    // the values which appear in messages are fixed (not from .env)
+    fn run<T>(test: T)
+    where T: FnOnce(&UserMailer) {
+        dotenv().ok();
+        let mut config = Config::from("testing").unwrap();
+        config.application_url = "https://eloquentlog.com".to_string();
+        config.quota_grace_period = 3600;
+        let logger = get_logger(&config);
+
+        let mut mailer = UserMailer::new(&config, &logger);
+        mailer.to(("postmaster@eloquentlog.com", "Name"));
+        test(&mailer)
+    }
+
+    fn render((subject, message): (String, String)) -> String {
+        format!("Subject: {}\n\n{}", subject, message.trim())
+    }
+
+    #[test]
+    fn test_user_activation_email() {
+        run(|mailer| {
+            let email = mailer.user_activation_email("session", "token");
+            assert_snapshot!("user_activation_email", render(email));
+        })
+    }
+
+    #[test]
+    fn test_url() {
+        run(|mailer| {
+            let url = mailer.url(
+                "{application_url}/activate/{t}?s={s}",
+                &[("s", "session"), ("t", "token")],
+            );
+            assert_eq!(url, "https://eloquentlog.com/activate/token?s=session");
+
+            // the frontend on another domain
+            let template = "https://app.example.org/#/w?t={t}";
+            let url = mailer.url(template, &[("t", "token")]);
+            assert_eq!(url, "https://app.example.org/#/w?t=token");
+        })
+    }
+
+    #[test]
+    fn test_password_reset_email() {
+        run(|mailer| {
+            let email = mailer.password_reset_email("session", "token");
+            assert_snapshot!("password_reset_email", render(email));
+        })
+    }
+
+    #[test]
+    fn test_waitlist_confirmation_email() {
+        run(|mailer| {
+            let email = mailer.waitlist_confirmation_email("token");
+            assert_snapshot!("waitlist_confirmation_email", render(email));
+        })
+    }
+
+    #[test]
+    fn test_namespace_transfer_email() {
+        run(|mailer| {
+            let email =
+                mailer.namespace_transfer_email("piano", "uuid", "token");
+            assert_snapshot!("namespace_transfer_email", render(email));
+        })
+    }
+
+    #[test]
+    fn test_quota_notification_email() {
+        run(|mailer| {
+            let email = mailer.quota_notification_email("", "piano", 80);
+            assert_snapshot!("quota_notification_email", render(email));
+
+            let email = mailer.quota_notification_email("", "piano", 100);
+            assert_snapshot!(
+                "quota_notification_email_exceeded",
+                render(email)
+            );
+        })
+    }
+
+    #[test]
+    fn test_quota_notification_email_with_name() {
+        run(|mailer| {
+            let (_, message) =
+                mailer.quota_notification_email("Oswald", "piano", 80);
+            assert!(message.trim().starts_with("Hi Oswald,"));
+        })
+    }
+
+    #[test]
+    fn test_build_with_cc_and_bcc() {
+        dotenv().ok();
+        let config = Config::from("testing").unwrap();
+        let logger = get_logger(&config);
+
+        let mut mailer = UserMailer::new(&config, &logger);
+        mailer
+            .to(("oswald@example.org", "Oswald"))
+            .to(("weenie@example.org", "Weenie"))
+            .cc(("henry@example.org", ""))
+            .bcc(("audit@example.org", ""));
+
+        let email = mailer.build("Subject", "Hello".to_string()).unwrap();
+        let to: Vec<String> =
+            email.envelope().to().iter().map(|a| a.to_string()).collect();
+        assert_eq!(to.len(), 4);
+        assert!(to.contains(&"audit@example.org".to_string()));
+
+        let message = email.message_to_string().unwrap();
+        assert!(message.contains("weenie@example.org"));
+        assert!(message.contains("Cc: "));
+        assert!(message.contains("henry@example.org"));
+        assert!(!message.contains("audit@example.org"));
+    }
+
+    #[test]
+    fn test_send_quota_notification_emails() {
+        dotenv().ok();
+        let config = Config::from("testing").unwrap();
+        let logger = get_logger(&config);
+
+        let code = Code::new(
+            Severity::PositiveCompletion,
+            Category::MailSystem,
+            Detail::Zero,
+        );
+        let mut mailer = UserMailer::new(&config, &logger);
+        mailer.inject(Some(Box::new(MockTransport::new(code, vec![]))));
+
+        let recipients =
+            [("oswald@example.org", "Oswald"), ("weenie@example.org", "")];
+        let sent =
+            mailer.send_quota_notification_emails(&recipients, "piano", 80);
+        assert_eq!(sent, 2);
+        assert!(mailer.header.to.is_empty());
+    }
+
+    #[test]
+    fn test_alert_email() {
+        run(|mailer| {
+            let email = mailer.alert_email("piano", "api", "Error rate > 5%");
+            assert_snapshot!("alert_email", render(email));
+        })
     }
 }
@@ -0,0 +1,258 @@
+//! Machine-readable metadata of the API for client code generation.
+//!
+//! It lists the mounted routes and the JSON fields of the models in
+//! responses, as they are serialized by serde (skipped fields are not
+//! included). `cli metadata` writes it into stdout.
+//!
+//! The official client crate keeps a copy of it which its types are generated
+//! from. If `CLIENT_METADATA_FILE` is set to the copy, the test fails when it
+//! differs from this crate (e.g. a field is added without regeneration).
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::model::message::{AgentType, LogFormat, LogLevel, Message};
+use crate::model::namespace::{AnomalySensitivity, Namespace};
+use crate::routes;
+use crate::service::deprecation::{DEPRECATIONS, find_by_route};
+
+/// The version of the format of the metadata.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Metadata {
+    pub version: u32,
+    pub routes: Vec<RouteMetadata>,
+    pub types: Vec<TypeMetadata>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct RouteMetadata {
+    /// The name of the handler (e.g. `append`)
+    pub name: String,
+    pub method: String,
+    /// The mounted uri with the query (e.g. `/v1/message/<a>/append/<b>`)
+    pub path: String,
+    pub format: Option<String>,
+    pub rank: isize,
+    pub deprecated: bool,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct TypeMetadata {
+    pub name: String,
+    pub fields: Vec<FieldMetadata>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct FieldMetadata {
+    pub name: String,
+    /// The type in JSON (string, number, boolean, array or object)
+    pub kind: String,
+    pub nullable: bool,
+}
+
+fn kind_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// describes the fields by two values of the type, which have all the optional
+// fields (`full`) and none of them (`empty`)
+fn describe<T>(name: &str, full: &T, empty: &T) -> TypeMetadata
+where T: serde::Serialize {
+    let full = serde_json::to_value(full).unwrap_or(Value::Null);
+    let empty = serde_json::to_value(empty).unwrap_or(Value::Null);
+    let fields = match full {
+        Value::Object(m) => m
+            .iter()
+            .map(|(k, v)| FieldMetadata {
+                name: k.to_string(),
+                kind: kind_of(v).to_string(),
+                nullable: empty.get(k).map_or(true, |v| v.is_null()),
+            })
+            .collect(),
+        _ => vec![],
+    };
+    TypeMetadata {
+        name: name.to_string(),
+        fields,
+    }
+}
+
+fn some_if<T>(full: bool, value: T) -> Option<T> {
+    if full {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn sample_time() -> NaiveDateTime {
+    Utc.ymd(2021, 7, 1).and_hms(0, 0, 0).naive_utc()
+}
+
+fn sample_message(full: bool) -> Message {
+    let t = sample_time();
+    Message {
+        id: "01F9DKJ8M00000000000000001".to_string(),
+        agent_id: 1,
+        agent_type: AgentType::Client,
+        stream_id: 1,
+        code: some_if(full, "E001".to_string()),
+        lang: "en".to_string(),
+        level: LogLevel::Error,
+        format: LogFormat::TOML,
+        title: "title".to_string(),
+        content: some_if(full, "content".to_string()),
+        created_at: t,
+        updated_at: t,
+        tags: vec!["tag".to_string()],
+        deleted_at: some_if(full, t),
+        acknowledged_at: some_if(full, t),
+        resolved_at: some_if(full, t),
+        body_id: some_if(full, 1),
+        occurrences_count: 1,
+        trace_id: some_if(full, "4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+        span_id: some_if(full, "00f067aa0ba902b7".to_string()),
+        hostname: some_if(full, "web-1".to_string()),
+        service: some_if(full, "api".to_string()),
+        environment: some_if(full, "production".to_string()),
+    }
+}
+
+fn sample_namespace(full: bool) -> Namespace {
+    let t = sample_time();
+    Namespace {
+        id: 1,
+        uuid: Uuid::nil(),
+        name: "name".to_string(),
+        description: some_if(full, "description".to_string()),
+        streams_count: 1,
+        archived_at: some_if(full, t),
+        created_at: t,
+        updated_at: t,
+        deduplicates_messages: false,
+        min_level: some_if(full, LogLevel::Warning),
+        default_tags: vec!["environment=production".to_string()],
+        anomaly_sensitivity: AnomalySensitivity::Medium,
+        badge_public: false,
+    }
+}
+
+fn types() -> Vec<TypeMetadata> {
+    vec![
+        describe("Message", &sample_message(true), &sample_message(false)),
+        describe(
+            "Namespace",
+            &sample_namespace(true),
+            &sample_namespace(false),
+        ),
+    ]
+}
+
+/// Returns the metadata of the routes and the types.
+pub fn metadata() -> Metadata {
+    let mut routes: Vec<RouteMetadata> = routes()
+        .iter()
+        .flat_map(|(base, routes)| {
+            routes.iter().map(move |r| {
+                let method = r.method.to_string();
+                let path = format!("{}{}", base, r.uri);
+                let deprecated =
+                    find_by_route(DEPRECATIONS, &method, &path).is_some();
+                RouteMetadata {
+                    name: r.name.unwrap_or("").to_string(),
+                    method,
+                    path,
+                    format: r.format.as_ref().map(|f| f.to_string()),
+                    rank: r.rank,
+                    deprecated,
+                }
+            })
+        })
+        .collect();
+    routes.sort_by(|a, b| {
+        (&a.path, &a.method, a.rank).cmp(&(&b.path, &b.method, b.rank))
+    });
+
+    Metadata {
+        version: VERSION,
+        routes,
+        types: types(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::HashSet;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_routes() {
+        let m = metadata();
+        assert!(!m.routes.is_empty());
+
+        let mut keys = HashSet::new();
+        for r in m.routes.iter() {
+            assert!(!r.name.is_empty(), "no name: {}", r.path);
+            assert!(r.path.starts_with("/_/") || r.path.starts_with("/v1/"));
+
+            let key = (&r.method, &r.path, &r.format, r.rank);
+            assert!(keys.insert(key), "duplicated: {} {}", r.method, r.path);
+        }
+
+        let path = "/v1/message/<namespace_key>/append/<stream_slug>";
+        assert!(m
+            .routes
+            .iter()
+            .any(|r| r.method == "POST" && r.path == path));
+    }
+
+    #[test]
+    fn test_types() {
+        let m = metadata();
+        let message = m.types.iter().find(|t| t.name == "Message").unwrap();
+        let field = |name: &str| message.fields.iter().find(|f| f.name == name);
+
+        assert_eq!(
+            field("content"),
+            Some(&FieldMetadata {
+                name: "content".to_string(),
+                kind: "string".to_string(),
+                nullable: true,
+            })
+        );
+        assert_eq!(field("title").map(|f| f.nullable), Some(false));
+        assert_eq!(field("tags").map(|f| f.kind.as_str()), Some("array"));
+
+        // skipped by serde
+        assert_eq!(field("deleted_at"), None);
+        assert_eq!(field("body_id"), None);
+    }
+
+    #[test]
+    fn test_client_metadata() {
+        let path = match env::var("CLIENT_METADATA_FILE") {
+            Ok(v) if !v.is_empty() => v,
+            _ => return,
+        };
+        let s = fs::read_to_string(&path).expect("client metadata");
+        let client: Metadata = serde_json::from_str(&s).expect("invalid json");
+        assert_eq!(
+            client,
+            metadata(),
+            "regenerate the client by `cli metadata > {}`",
+            path
+        );
+    }
+}
@@ -4,22 +4,24 @@
 use std::fmt;
 use std::str;
 
-use chrono::{NaiveDateTime, Utc};
+use chrono::NaiveDateTime;
 use diesel::{Identifiable, Queryable, debug_query, prelude::*};
 use diesel::dsl;
 use diesel::pg::{Pg, PgConnection};
 use uuid::Uuid;
 
+pub use crate::model::access_token_scope::*;
 pub use crate::model::access_token_state::*;
 pub use crate::model::agent_type::*;
 pub use crate::model::token::Claims;
 pub use crate::schema::access_tokens;
 
+use crate::clock::Clock;
+use crate::id::IdGenerator;
 use crate::logger::Logger;
 use crate::model::user::User;
-use crate::util::generate_random_hash;
 
-const HASH_LENGTH: i32 = 128;
+const HASH_LENGTH: usize = 128;
 const HASH_SOURCE: &[u8] =
     b"+/ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
 
@@ -29,6 +31,7 @@ pub struct NewAccessToken {
     pub agent_id: i64,
     pub agent_type: AgentType,
     pub name: String,
+    pub scopes: Vec<Scope>,
 }
 
 impl Default for NewAccessToken {
@@ -37,6 +40,7 @@ impl Default for NewAccessToken {
             agent_id: 0, // validation error
             agent_type: AgentType::Client,
             name: "".to_string(), // validation error
+            scopes: vec![],
         }
     }
 }
@@ -63,6 +67,7 @@ type AllColumns = (
     access_tokens::revoked_at,
     access_tokens::created_at,
     access_tokens::updated_at,
+    access_tokens::scopes,
 );
 
 const ALL_COLUMNS: AllColumns = (
@@ -76,6 +81,7 @@ const ALL_COLUMNS: AllColumns = (
     access_tokens::revoked_at,
     access_tokens::created_at,
     access_tokens::updated_at,
+    access_tokens::scopes,
 );
 
 /// AccessToken
@@ -102,6 +108,7 @@ pub struct AccessToken {
     pub revoked_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub scopes: Vec<String>,
 }
 
 type All = dsl::Select<access_tokens::table, AllColumns>;
@@ -138,6 +145,7 @@ impl AccessToken {
             access_tokens::agent_id.eq(access_token.agent_id),
             access_tokens::agent_type.eq(&access_token.agent_type),
             access_tokens::name.eq(&access_token.name),
+            access_tokens::scopes.eq(to_names(&access_token.scopes)),
             // default
             access_tokens::state.eq(AccessTokenState::Disabled),
         ));
@@ -202,8 +210,57 @@ impl AccessToken {
         }
     }
 
-    pub fn generate_token() -> String {
-        generate_random_hash(HASH_SOURCE, HASH_LENGTH)
+    /// Finds an enabled (and not revoked) token by its raw value.
+    pub fn find_by_token(
+        token: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        if token.is_empty() {
+            return None;
+        }
+
+        let q = Self::all()
+            .filter(access_tokens::token.eq(token.as_bytes()))
+            .filter(access_tokens::state.eq(AccessTokenState::Enabled))
+            .filter(Self::visible())
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        let name = scope.to_string();
+        self.scopes.iter().any(|s| *s == name)
+    }
+
+    pub fn update_scopes(
+        &self,
+        scopes: &[Scope],
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<Self, &'static str> {
+        let q = diesel::update(self)
+            .set(access_tokens::scopes.eq(to_names(scopes)));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to change scopes")
+            },
+            Ok(access_token) => Ok(access_token),
+        }
+    }
+
+    pub fn generate_token(ids: &dyn IdGenerator) -> String {
+        ids.random_hash(HASH_SOURCE, HASH_LENGTH)
     }
 
     pub fn update_token(
@@ -251,10 +308,11 @@ impl AccessToken {
 
     pub fn revoke(
         &self,
+        clock: &dyn Clock,
         conn: &PgConnection,
         logger: &Logger,
     ) -> Result<Self, &'static str> {
-        let now = Utc::now().naive_utc();
+        let now = clock.now().naive_utc();
 
         // TODO: refactor
         let a = &Self {
@@ -268,6 +326,7 @@ impl AccessToken {
             revoked_at: Some(now),
             created_at: self.created_at,
             updated_at: self.updated_at,
+            scopes: self.scopes.to_owned(),
         };
         let q = diesel::update(self).set(a);
 
@@ -304,6 +363,13 @@ impl AccessToken {
     }
 }
 
+fn to_names(scopes: &[Scope]) -> Vec<String> {
+    let mut names: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
 #[cfg(test)]
 pub mod data {
     use super::*;
@@ -329,6 +395,7 @@ pub mod data {
                 revoked_at: None,
                 created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
                 updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
+                scopes: vec!["messages:read".to_string()],
             },
             "weenie's personal token" => AccessToken {
                 id: 2,
@@ -341,6 +408,7 @@ pub mod data {
                 revoked_at: None,
                 created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
                 updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
+                scopes: vec!["messages:read".to_string()],
             },
             "hennry's personal token" => AccessToken {
                 id: 3,
@@ -353,6 +421,7 @@ pub mod data {
                 revoked_at: None,
                 created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
                 updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
+                scopes: vec!["messages:read".to_string()],
             }
         };
     }
@@ -488,6 +557,7 @@ mod test {
                 agent_id: user.id,
                 agent_type: AgentType::Person,
                 name: "".to_string(),
+                scopes: vec![Scope::MessagesRead, Scope::MessagesRead],
             };
 
             let result = AccessToken::insert(&at, conn, logger);
@@ -502,6 +572,67 @@ mod test {
 
             assert!(result.token.is_none());
             assert_eq!(result.state, AccessTokenState::Disabled);
+            assert_eq!(result.scopes, vec!["messages:read".to_string()]);
+        })
+    }
+
+    #[test]
+    fn test_find_by_token() {
+        run(|conn, _, logger| {
+            let u = USERS.get("oswald").unwrap();
+            let user = diesel::insert_into(users::table)
+                .values(u)
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let access_token = diesel::insert_into(access_tokens::table)
+                .values((
+                    access_tokens::agent_id.eq(user.id),
+                    access_tokens::agent_type.eq(AgentType::Person),
+                    access_tokens::name.eq("name"),
+                    access_tokens::state.eq(AccessTokenState::Enabled),
+                    access_tokens::token.eq(Some(b"token".to_vec())),
+                ))
+                .get_result::<AccessToken>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let result = AccessToken::find_by_token("token", conn, logger);
+            assert_eq!(result.map(|t| t.id), Some(access_token.id));
+
+            access_token
+                .mark_as(AccessTokenState::Disabled, conn, logger)
+                .unwrap();
+            let result = AccessToken::find_by_token("token", conn, logger);
+            assert!(result.is_none());
+            assert!(AccessToken::find_by_token("", conn, logger).is_none());
+        })
+    }
+
+    #[test]
+    fn test_update_scopes() {
+        run(|conn, _, logger| {
+            let u = USERS.get("oswald").unwrap();
+            let user = diesel::insert_into(users::table)
+                .values(u)
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let at = NewAccessToken::from(&user);
+            let access_token = AccessToken::insert(&at, conn, logger).unwrap();
+            assert!(access_token.scopes.is_empty());
+            assert!(!access_token.has_scope(Scope::IngestWrite));
+
+            let scopes = [Scope::NamespaceAdmin, Scope::IngestWrite];
+            let result = access_token.update_scopes(&scopes, conn, logger);
+            assert!(result.is_ok());
+
+            let access_token = result.unwrap();
+            assert_eq!(
+                access_token.scopes,
+                vec!["ingest:write".to_string(), "namespace:admin".to_string()]
+            );
+            assert!(access_token.has_scope(Scope::IngestWrite));
+            assert!(!access_token.has_scope(Scope::MessagesRead));
         })
     }
 }
@@ -0,0 +1,74 @@
+//! # A type Scope for AccessToken in access_token.rs
+//!
+//! Scopes are saved as an array of strings in `access_tokens.scopes`, so
+//! this is not an SQL type.
+use std::fmt;
+use std::slice::Iter;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    IngestWrite,
+    MessagesRead,
+    MessagesWrite,
+    NamespaceAdmin,
+}
+
+const SCOPES: [Scope; 4] = [
+    Scope::IngestWrite,
+    Scope::MessagesRead,
+    Scope::MessagesWrite,
+    Scope::NamespaceAdmin,
+];
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::IngestWrite => write!(f, "ingest:write"),
+            Self::MessagesRead => write!(f, "messages:read"),
+            Self::MessagesWrite => write!(f, "messages:write"),
+            Self::NamespaceAdmin => write!(f, "namespace:admin"),
+        }
+    }
+}
+
+impl Scope {
+    pub fn iter() -> Iter<'static, Scope> {
+        SCOPES.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|v| v.to_string() == s).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Scope::from_name("ingest:write"), Some(Scope::IngestWrite));
+        assert_eq!(
+            Scope::from_name("messages:read"),
+            Some(Scope::MessagesRead)
+        );
+        assert_eq!(
+            Scope::from_name("messages:write"),
+            Some(Scope::MessagesWrite)
+        );
+        assert_eq!(
+            Scope::from_name("namespace:admin"),
+            Some(Scope::NamespaceAdmin)
+        );
+        assert_eq!(Scope::from_name("namespace:write"), None);
+        assert_eq!(Scope::from_name(""), None);
+    }
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", Scope::IngestWrite), "ingest:write");
+        assert_eq!(format!("{}", Scope::MessagesRead), "messages:read");
+        assert_eq!(format!("{}", Scope::MessagesWrite), "messages:write");
+        assert_eq!(format!("{}", Scope::NamespaceAdmin), "namespace:admin");
+    }
+}
@@ -0,0 +1,143 @@
+//! # AlertSchedule
+//!
+//! When a user receives alert emails. Alerts in the quiet hours are deferred
+//! to the end of them (see `service::quiet_hours`).
+use std::fmt;
+
+use chrono::{NaiveDateTime, NaiveTime};
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use diesel::pg::upsert::excluded;
+
+pub use crate::schema::alert_schedules;
+
+use crate::db::with_retry;
+use crate::logger::Logger;
+use crate::model::user::User;
+
+/// NewAlertSchedule
+#[derive(Debug, Insertable)]
+#[table_name = "alert_schedules"]
+pub struct NewAlertSchedule {
+    pub user_id: i64,
+    pub timezone: String,
+    pub quiet_hours_start: Option<NaiveTime>,
+    pub quiet_hours_end: Option<NaiveTime>,
+}
+
+/// AlertSchedule
+#[derive(Associations, Debug, Identifiable, Queryable)]
+#[belongs_to(User)]
+#[table_name = "alert_schedules"]
+pub struct AlertSchedule {
+    pub id: i64,
+    pub user_id: i64,
+    pub timezone: String,
+    pub quiet_hours_start: Option<NaiveTime>,
+    pub quiet_hours_end: Option<NaiveTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for AlertSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<AlertSchedule {id}>", id = &self.id)
+    }
+}
+
+impl AlertSchedule {
+    pub fn find_by_user_id(
+        user_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = alert_schedules::table
+            .filter(alert_schedules::user_id.eq(user_id))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Saves the schedule of the user (a user has only one).
+    pub fn upsert(
+        schedule: &NewAlertSchedule,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(alert_schedules::table)
+                .values(schedule)
+                .on_conflict(alert_schedules::user_id)
+                .do_update()
+                .set((
+                    alert_schedules::timezone
+                        .eq(excluded(alert_schedules::timezone)),
+                    alert_schedules::quiet_hours_start
+                        .eq(excluded(alert_schedules::quiet_hours_start)),
+                    alert_schedules::quiet_hours_end
+                        .eq(excluded(alert_schedules::quiet_hours_end)),
+                    alert_schedules::updated_at.eq(diesel::dsl::now),
+                ));
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::test::run;
+    use crate::model::user::users;
+    use crate::model::user::data::USERS;
+
+    #[test]
+    fn test_upsert() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            assert!(AlertSchedule::find_by_user_id(user.id, conn, logger)
+                .is_none());
+
+            let mut schedule = NewAlertSchedule {
+                user_id: user.id,
+                timezone: "Europe/Zurich".to_string(),
+                quiet_hours_start: Some(NaiveTime::from_hms(22, 0, 0)),
+                quiet_hours_end: Some(NaiveTime::from_hms(7, 0, 0)),
+            };
+            let s = AlertSchedule::upsert(&schedule, conn, logger).unwrap();
+
+            schedule.quiet_hours_start = None;
+            schedule.quiet_hours_end = None;
+            let result =
+                AlertSchedule::upsert(&schedule, conn, logger).unwrap();
+            assert_eq!(result.id, s.id);
+            assert!(result.quiet_hours_start.is_none());
+
+            let result =
+                AlertSchedule::find_by_user_id(user.id, conn, logger).unwrap();
+            assert_eq!(result.timezone, "Europe/Zurich");
+        })
+    }
+}
@@ -0,0 +1,113 @@
+//! # AnalyticsEvent
+//!
+//! An anonymized usage event for the product analytics (see
+//! `request::analytics`). It has no reference to users or namespaces, the
+//! actor is only a salted hash to count the distinct ones.
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+
+pub use crate::schema::analytics_events;
+
+use crate::logger::Logger;
+
+/// NewAnalyticsEvent
+#[derive(Debug, Insertable)]
+#[table_name = "analytics_events"]
+pub struct NewAnalyticsEvent {
+    pub name: String,
+    pub actor: Option<String>,
+    /// JSON object of strings
+    pub properties: String,
+    pub occurred_at: NaiveDateTime,
+}
+
+/// AnalyticsEvent
+#[derive(Debug, Identifiable, Queryable)]
+#[table_name = "analytics_events"]
+pub struct AnalyticsEvent {
+    pub id: i64,
+    pub name: String,
+    pub actor: Option<String>,
+    pub properties: String,
+    pub occurred_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+impl fmt::Display for AnalyticsEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<AnalyticsEvent {id}>", id = &self.id)
+    }
+}
+
+impl AnalyticsEvent {
+    pub fn insert_all(
+        events: &[NewAnalyticsEvent],
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<usize> {
+        let q = diesel::insert_into(analytics_events::table).values(events);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.execute(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(n) => Some(n),
+        }
+    }
+
+    pub fn count_by_name(
+        name: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<i64> {
+        let q = analytics_events::table
+            .filter(analytics_events::name.eq(name))
+            .count();
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<i64>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(n) => Some(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::model::test::run;
+
+    #[test]
+    fn test_insert_all() {
+        run(|conn, _, logger| {
+            let t = Utc.ymd(2021, 7, 8).and_hms(9, 0, 0).naive_utc();
+            let events: Vec<NewAnalyticsEvent> = ["search", "search", "other"]
+                .iter()
+                .map(|name| NewAnalyticsEvent {
+                    name: name.to_string(),
+                    actor: None,
+                    properties: "{}".to_string(),
+                    occurred_at: t,
+                })
+                .collect();
+            let result = AnalyticsEvent::insert_all(&events, conn, logger);
+            assert_eq!(result, Some(3));
+
+            let result = AnalyticsEvent::count_by_name("search", conn, logger);
+            assert_eq!(result, Some(2));
+        })
+    }
+}
@@ -0,0 +1,121 @@
+//! # A type AnomalySensitivity for Namespace in namespace.rs
+//!
+//! EAnomalySensitivity represents SQL type value `e_anomaly_sensitivity` and
+//! AnomalySensitivity is an Enum holds all the values.
+use std::fmt;
+use std::io::Write;
+use std::slice::Iter;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_anomaly_sensitivity")]
+pub struct EAnomalySensitivity;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "EAnomalySensitivity"]
+pub enum AnomalySensitivity {
+    Off,
+    Low,
+    Medium, // default
+    High,
+}
+
+const ANOMALY_SENSITIVITIES: [AnomalySensitivity; 4] = [
+    AnomalySensitivity::Off,
+    AnomalySensitivity::Low,
+    AnomalySensitivity::Medium,
+    AnomalySensitivity::High,
+];
+
+impl fmt::Display for AnomalySensitivity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Off => write!(f, "off"),
+            Self::Low => write!(f, "low"),
+            Self::Medium => write!(f, "medium"),
+            Self::High => write!(f, "high"),
+        }
+    }
+}
+
+impl ToSql<EAnomalySensitivity, Pg> for AnomalySensitivity {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::Off => out.write_all(b"off")?,
+            Self::Low => out.write_all(b"low")?,
+            Self::Medium => out.write_all(b"medium")?,
+            Self::High => out.write_all(b"high")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<EAnomalySensitivity, Pg> for AnomalySensitivity {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"off" => Ok(Self::Off),
+            b"low" => Ok(Self::Low),
+            b"medium" => Ok(Self::Medium),
+            b"high" => Ok(Self::High),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl AnomalySensitivity {
+    pub fn iter() -> Iter<'static, AnomalySensitivity> {
+        ANOMALY_SENSITIVITIES.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|v| v.to_string() == s).copied()
+    }
+
+    /// Returns the number of the standard deviations from the baseline over
+    /// which a count is a spike (None if it's off). The higher sensitivity
+    /// alerts the smaller ones.
+    pub fn threshold(self) -> Option<f64> {
+        match self {
+            Self::Off => None,
+            Self::Low => Some(4.0),
+            Self::Medium => Some(3.0),
+            Self::High => Some(2.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", AnomalySensitivity::Off), "off");
+        assert_eq!(format!("{}", AnomalySensitivity::Low), "low");
+        assert_eq!(format!("{}", AnomalySensitivity::Medium), "medium");
+        assert_eq!(format!("{}", AnomalySensitivity::High), "high");
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            AnomalySensitivity::from_name("high"),
+            Some(AnomalySensitivity::High)
+        );
+        assert_eq!(AnomalySensitivity::from_name("max"), None);
+    }
+
+    #[test]
+    fn test_threshold() {
+        assert_eq!(AnomalySensitivity::Off.threshold(), None);
+        let high = AnomalySensitivity::High.threshold().unwrap();
+        let low = AnomalySensitivity::Low.threshold().unwrap();
+        assert!(high < low);
+    }
+}
@@ -0,0 +1,241 @@
+//! # BulkOperation
+//!
+//! An action applied to all the messages which match a filter expression
+//! (see `model::message_filter`). It's run by `ApplyBulkOperation` job, and
+//! the progress is tracked in this table.
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use uuid::Uuid;
+
+pub use crate::model::bulk_operation_action::*;
+pub use crate::model::bulk_operation_state::*;
+pub use crate::schema::bulk_operations;
+
+use crate::clock::Clock;
+use crate::db::with_retry;
+use crate::logger::Logger;
+use crate::model::stream::Stream;
+use crate::model::user::User;
+
+/// The number of messages updated at once.
+pub const BATCH_SIZE: i64 = 1_000;
+
+/// NewBulkOperation
+#[derive(Debug, Insertable)]
+#[table_name = "bulk_operations"]
+pub struct NewBulkOperation {
+    pub user_id: i64,
+    pub stream_id: i64,
+    pub action: BulkOperationAction,
+    pub argument: Option<String>,
+    pub filter: String,
+    pub matched_count: i64,
+}
+
+/// BulkOperation
+#[derive(Associations, Debug, Identifiable, Queryable)]
+#[belongs_to(Stream)]
+#[belongs_to(User)]
+#[table_name = "bulk_operations"]
+pub struct BulkOperation {
+    pub id: i64,
+    pub uuid: Uuid,
+    pub user_id: i64,
+    pub stream_id: i64,
+    pub action: BulkOperationAction,
+    pub argument: Option<String>,
+    pub filter: String,
+    pub state: BulkOperationState,
+    pub matched_count: i64,
+    pub affected_count: i64,
+    pub finished_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for BulkOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<BulkOperation {uuid}>", uuid = &self.uuid.to_string())
+    }
+}
+
+impl BulkOperation {
+    pub fn insert(
+        operation: &NewBulkOperation,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::insert_into(bulk_operations::table).values(operation);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn find_by_uuid(
+        uuid: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let uuid = Uuid::parse_str(uuid).ok()?;
+        let q = bulk_operations::table
+            .filter(bulk_operations::uuid.eq(uuid))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn owned_by_uuid(
+        user: &User,
+        uuid: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let uuid = Uuid::parse_str(uuid).ok()?;
+        let q = bulk_operations::table
+            .filter(bulk_operations::uuid.eq(uuid))
+            .filter(bulk_operations::user_id.eq(user.id))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Updates the state and the counts. `finished_at` is set if the
+    /// operation has been finished or failed.
+    pub fn update_state(
+        &self,
+        state: BulkOperationState,
+        matched_count: i64,
+        affected_count: i64,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let now = clock.now().naive_utc();
+        let finished_at = match state {
+            BulkOperationState::Finished | BulkOperationState::Failed => {
+                Some(now)
+            },
+            _ => None,
+        };
+        let result = with_retry(logger, || {
+            let q = diesel::update(self).set((
+                bulk_operations::state.eq(state),
+                bulk_operations::matched_count.eq(matched_count),
+                bulk_operations::affected_count.eq(affected_count),
+                bulk_operations::finished_at.eq(finished_at),
+                bulk_operations::updated_at.eq(now),
+            ));
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::FrozenClock;
+    use crate::model::namespace::{Namespace, namespaces};
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::stream::streams;
+    use crate::model::stream::data::STREAMS;
+    use crate::model::test::run;
+    use crate::model::user::users;
+    use crate::model::user::data::USERS;
+
+    #[test]
+    fn test_insert_and_update_state() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let new_operation = NewBulkOperation {
+                user_id: user.id,
+                stream_id: stream.id,
+                action: BulkOperationAction::AddTag,
+                argument: Some("db".to_string()),
+                filter: "level:error".to_string(),
+                matched_count: 3,
+            };
+            let operation =
+                BulkOperation::insert(&new_operation, conn, logger).unwrap();
+            assert_eq!(operation.state, BulkOperationState::Pending);
+            assert_eq!(operation.finished_at, None);
+
+            let uuid = operation.uuid.to_string();
+            let result =
+                BulkOperation::owned_by_uuid(&user, &uuid, conn, logger);
+            assert_eq!(result.map(|o| o.id), Some(operation.id));
+            assert!(BulkOperation::find_by_uuid("x", conn, logger).is_none());
+
+            let now = Utc.ymd(2019, 7, 7).and_hms(7, 20, 15);
+            let clock = FrozenClock::new(now);
+            let result = operation
+                .update_state(
+                    BulkOperationState::Finished,
+                    3,
+                    2,
+                    &clock,
+                    conn,
+                    logger,
+                )
+                .unwrap();
+            assert_eq!(result.state, BulkOperationState::Finished);
+            assert_eq!(result.affected_count, 2);
+            assert_eq!(result.finished_at, Some(now.naive_utc()));
+        })
+    }
+}
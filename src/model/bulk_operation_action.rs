@@ -0,0 +1,109 @@
+//! # A type BulkOperationAction for BulkOperation in bulk_operation.rs
+//!
+//! EBulkOperationAction represents SQL type value
+//! `e_bulk_operation_action` and BulkOperationAction is an Enum
+//! holds all the values.
+use std::fmt;
+use std::io::Write;
+use std::slice::Iter;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_bulk_operation_action")]
+pub struct EBulkOperationAction;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "EBulkOperationAction"]
+pub enum BulkOperationAction {
+    AddTag,
+    AssignIncident,
+    SoftDelete,
+}
+
+const BULK_OPERATION_ACTIONS: [BulkOperationAction; 3] = [
+    BulkOperationAction::AddTag,
+    BulkOperationAction::AssignIncident,
+    BulkOperationAction::SoftDelete,
+];
+
+impl fmt::Display for BulkOperationAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::AddTag => write!(f, "add_tag"),
+            Self::AssignIncident => write!(f, "assign_incident"),
+            Self::SoftDelete => write!(f, "soft_delete"),
+        }
+    }
+}
+
+impl ToSql<EBulkOperationAction, Pg> for BulkOperationAction {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::AddTag => out.write_all(b"add_tag")?,
+            Self::AssignIncident => out.write_all(b"assign_incident")?,
+            Self::SoftDelete => out.write_all(b"soft_delete")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<EBulkOperationAction, Pg> for BulkOperationAction {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"add_tag" => Ok(Self::AddTag),
+            b"assign_incident" => Ok(Self::AssignIncident),
+            b"soft_delete" => Ok(Self::SoftDelete),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl BulkOperationAction {
+    pub fn iter() -> Iter<'static, BulkOperationAction> {
+        BULK_OPERATION_ACTIONS.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|a| a.to_string() == s).copied()
+    }
+
+    /// Returns true if the action needs an argument (tag or incident uuid).
+    pub fn requires_argument(&self) -> bool {
+        !matches!(*self, Self::SoftDelete)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            BulkOperationAction::from_name("add_tag"),
+            Some(BulkOperationAction::AddTag)
+        );
+        assert_eq!(
+            BulkOperationAction::from_name("assign_incident"),
+            Some(BulkOperationAction::AssignIncident)
+        );
+        assert_eq!(
+            BulkOperationAction::from_name("soft_delete"),
+            Some(BulkOperationAction::SoftDelete)
+        );
+        assert_eq!(BulkOperationAction::from_name("delete"), None);
+    }
+
+    #[test]
+    fn test_requires_argument() {
+        assert!(BulkOperationAction::AddTag.requires_argument());
+        assert!(BulkOperationAction::AssignIncident.requires_argument());
+        assert!(!BulkOperationAction::SoftDelete.requires_argument());
+    }
+}
@@ -0,0 +1,75 @@
+//! # A type BulkOperationState for BulkOperation in bulk_operation.rs
+//!
+//! EBulkOperationState represents SQL type value
+//! `e_bulk_operation_state` and BulkOperationState is an Enum
+//! holds all the values.
+use std::fmt;
+use std::io::Write;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_bulk_operation_state")]
+pub struct EBulkOperationState;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "EBulkOperationState"]
+pub enum BulkOperationState {
+    Pending, // default
+    Running,
+    Finished,
+    Failed,
+}
+
+impl fmt::Display for BulkOperationState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Pending => write!(f, "pending"),
+            Self::Running => write!(f, "running"),
+            Self::Finished => write!(f, "finished"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl ToSql<EBulkOperationState, Pg> for BulkOperationState {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::Pending => out.write_all(b"pending")?,
+            Self::Running => out.write_all(b"running")?,
+            Self::Finished => out.write_all(b"finished")?,
+            Self::Failed => out.write_all(b"failed")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<EBulkOperationState, Pg> for BulkOperationState {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"pending" => Ok(Self::Pending),
+            b"running" => Ok(Self::Running),
+            b"finished" => Ok(Self::Finished),
+            b"failed" => Ok(Self::Failed),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", BulkOperationState::Pending), "pending");
+        assert_eq!(format!("{}", BulkOperationState::Running), "running");
+        assert_eq!(format!("{}", BulkOperationState::Finished), "finished");
+        assert_eq!(format!("{}", BulkOperationState::Failed), "failed");
+    }
+}
@@ -0,0 +1,157 @@
+//! # Channel
+//!
+//! A destination of alerts in a namespace (an outgoing webhook or Slack).
+//! The payload can be customized with a template (see
+//! `service::payload_template`), and alerts can be rolled up into one per
+//! `rollup_window` seconds (see `service::alert_rollup`).
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use uuid::Uuid;
+
+pub use crate::model::channel_kind::*;
+pub use crate::schema::channels;
+
+use crate::db::with_retry;
+use crate::logger::Logger;
+use crate::model::namespace::Namespace;
+
+/// NewChannel
+#[derive(Debug, Insertable)]
+#[table_name = "channels"]
+pub struct NewChannel {
+    pub namespace_id: i64,
+    pub kind: ChannelKind,
+    pub name: String,
+    pub url: String,
+    pub template: Option<String>,
+    pub rollup_window: i32,
+}
+
+/// Channel
+#[derive(Associations, Debug, Identifiable, Queryable)]
+#[belongs_to(Namespace)]
+#[table_name = "channels"]
+pub struct Channel {
+    pub id: i64,
+    pub uuid: Uuid,
+    pub namespace_id: i64,
+    pub kind: ChannelKind,
+    pub name: String,
+    pub url: String,
+    pub template: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub rollup_window: i32,
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Channel {uuid}>", uuid = &self.uuid.to_string())
+    }
+}
+
+impl Channel {
+    pub fn insert(
+        channel: &NewChannel,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(channels::table).values(channel);
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn find_by_uuid(
+        uuid: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let uuid = Uuid::parse_str(uuid).ok()?;
+        let q = channels::table.filter(channels::uuid.eq(uuid)).limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn find_all_by_namespace_id(
+        namespace_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = channels::table
+            .filter(channels::namespace_id.eq(namespace_id))
+            .order(channels::id.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::namespace::namespaces;
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_insert_and_find() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let new_channel = NewChannel {
+                namespace_id: namespace.id,
+                kind: ChannelKind::Slack,
+                name: "alerts".to_string(),
+                url: "https://hooks.slack.com/services/T0/B0/X".to_string(),
+                template: None,
+                rollup_window: 300,
+            };
+            let channel = Channel::insert(&new_channel, conn, logger).unwrap();
+            assert_eq!(channel.kind, ChannelKind::Slack);
+
+            let uuid = channel.uuid.to_string();
+            let result = Channel::find_by_uuid(&uuid, conn, logger);
+            assert_eq!(result.map(|c| c.id), Some(channel.id));
+            assert!(Channel::find_by_uuid("x", conn, logger).is_none());
+
+            let result =
+                Channel::find_all_by_namespace_id(namespace.id, conn, logger)
+                    .unwrap();
+            assert_eq!(result.len(), 1);
+        })
+    }
+}
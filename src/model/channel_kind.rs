@@ -0,0 +1,84 @@
+//! # A type ChannelKind for Channel in channel.rs
+//!
+//! EChannelKind represents SQL type value `e_channel_kind` and ChannelKind
+//! is an Enum holds all the values.
+use std::fmt;
+use std::io::Write;
+use std::slice::Iter;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_channel_kind")]
+pub struct EChannelKind;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "EChannelKind"]
+pub enum ChannelKind {
+    Webhook,
+    Slack,
+}
+
+const CHANNEL_KINDS: [ChannelKind; 2] =
+    [ChannelKind::Webhook, ChannelKind::Slack];
+
+impl fmt::Display for ChannelKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Webhook => write!(f, "webhook"),
+            Self::Slack => write!(f, "slack"),
+        }
+    }
+}
+
+impl ToSql<EChannelKind, Pg> for ChannelKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::Webhook => out.write_all(b"webhook")?,
+            Self::Slack => out.write_all(b"slack")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<EChannelKind, Pg> for ChannelKind {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"webhook" => Ok(Self::Webhook),
+            b"slack" => Ok(Self::Slack),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl ChannelKind {
+    pub fn iter() -> Iter<'static, ChannelKind> {
+        CHANNEL_KINDS.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|k| k.to_string() == s).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", ChannelKind::Webhook), "webhook");
+        assert_eq!(format!("{}", ChannelKind::Slack), "slack");
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(ChannelKind::from_name("slack"), Some(ChannelKind::Slack));
+        assert_eq!(ChannelKind::from_name("email"), None);
+    }
+}
@@ -0,0 +1,104 @@
+//! External identifiers of users and namespaces.
+//!
+//! The API refers to users and namespaces by their `uuid` columns. The
+//! sequential ids (`id`) are internal, and they were accepted as keys by
+//! older clients. Those lookups still work during the deprecation window,
+//! but they are logged as deprecated and will be removed.
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::logger::Logger;
+
+/// ExternalId
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExternalId {
+    Uuid(Uuid),
+    /// Deprecated
+    Legacy(i64),
+}
+
+impl fmt::Display for ExternalId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Uuid(v) => write!(f, "{}", v),
+            Self::Legacy(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl ExternalId {
+    /// Parses the key as an uuid, or as a (positive) sequential id.
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Ok(v) = Uuid::parse_str(s) {
+            return Some(Self::Uuid(v));
+        }
+        match s.parse::<i64>() {
+            Ok(v) if v > 0 && !s.starts_with('+') => Some(Self::Legacy(v)),
+            _ => None,
+        }
+    }
+
+    /// Parses the key like `parse`, and logs the lookup of a legacy one
+    /// (e.g. `"namespace"`).
+    pub fn parse_for(kind: &str, s: &str, logger: &Logger) -> Option<Self> {
+        let key = Self::parse(s)?;
+        if key.is_legacy() {
+            warn!(logger, "deprecated: {} is looked up by id {}", kind, key);
+        }
+        Some(key)
+    }
+
+    pub fn is_legacy(self) -> bool {
+        matches!(self, Self::Legacy(_))
+    }
+
+    /// Returns the pair of values for filters on both columns ((uuid, id)),
+    /// the one unused never matches (nil, 0).
+    pub fn to_pair(self) -> (Uuid, i64) {
+        match self {
+            Self::Uuid(v) => (v, 0),
+            Self::Legacy(v) => (Uuid::nil(), v),
+        }
+    }
+}
+
+/// Returns true if the key is a sequential id.
+pub fn is_legacy(key: &str) -> bool {
+    ExternalId::parse(key).map_or(false, ExternalId::is_legacy)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let uuid = Uuid::new_v4();
+        assert_eq!(
+            ExternalId::parse(&uuid.to_string()),
+            Some(ExternalId::Uuid(uuid))
+        );
+        assert_eq!(ExternalId::parse("42"), Some(ExternalId::Legacy(42)));
+
+        assert_eq!(ExternalId::parse(""), None);
+        assert_eq!(ExternalId::parse("0"), None);
+        assert_eq!(ExternalId::parse("-1"), None);
+        assert_eq!(ExternalId::parse("+1"), None);
+        assert_eq!(ExternalId::parse("unknown"), None);
+    }
+
+    #[test]
+    fn test_to_pair() {
+        let uuid = Uuid::new_v4();
+        assert_eq!(ExternalId::Uuid(uuid).to_pair(), (uuid, 0));
+        assert_eq!(ExternalId::Legacy(3).to_pair(), (Uuid::nil(), 3));
+
+        assert!(ExternalId::Legacy(3).is_legacy());
+        assert!(!ExternalId::Uuid(uuid).is_legacy());
+
+        assert!(is_legacy("3"));
+        assert!(!is_legacy(&uuid.to_string()));
+        assert!(!is_legacy("unknown"));
+    }
+}
@@ -0,0 +1,161 @@
+//! # Identity
+//!
+//! An account on an external (OAuth2/OpenID Connect) provider which is linked
+//! to a user. A user may have an identity for each provider.
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+
+pub use crate::model::identity_provider::*;
+pub use crate::schema::identities;
+
+use crate::logger::Logger;
+use crate::model::user::User;
+
+/// NewIdentity
+#[derive(Debug)]
+pub struct NewIdentity {
+    pub user_id: i64,
+    pub provider: IdentityProvider,
+    pub uid: String,
+    pub email: String,
+}
+
+/// Identity
+#[derive(Associations, Debug, Identifiable, Insertable, Queryable)]
+#[belongs_to(User)]
+#[table_name = "identities"]
+pub struct Identity {
+    pub id: i64,
+    pub user_id: i64,
+    pub provider: IdentityProvider,
+    pub uid: String,
+    pub email: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Identity {provider}>", provider = &self.provider)
+    }
+}
+
+impl Identity {
+    pub fn find_by_provider_and_uid(
+        provider: IdentityProvider,
+        uid: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        if uid.is_empty() {
+            return None;
+        }
+
+        let q = identities::table
+            .filter(identities::provider.eq(provider))
+            .filter(identities::uid.eq(uid))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn insert(
+        identity: &NewIdentity,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::insert_into(identities::table).values((
+            identities::user_id.eq(identity.user_id),
+            identities::provider.eq(identity.provider),
+            identities::uid.eq(&identity.uid),
+            identities::email.eq(&identity.email),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::test::run;
+    use crate::model::user::users;
+    use crate::model::user::data::USERS;
+
+    #[test]
+    fn test_insert() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let identity = NewIdentity {
+                user_id: user.id,
+                provider: IdentityProvider::GitHub,
+                uid: "1234".to_string(),
+                email: user.email,
+            };
+            let result = Identity::insert(&identity, conn, logger);
+            assert!(result.is_some());
+
+            // same uid on the provider (in a savepoint, as it's aborted)
+            let result = conn.transaction::<_, diesel::result::Error, _>(|| {
+                Identity::insert(&identity, conn, logger)
+                    .ok_or(diesel::result::Error::RollbackTransaction)
+            });
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    fn test_find_by_provider_and_uid() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let identity = NewIdentity {
+                user_id: user.id,
+                provider: IdentityProvider::Google,
+                uid: "1234".to_string(),
+                email: user.email.to_string(),
+            };
+            let _ = Identity::insert(&identity, conn, logger).unwrap();
+
+            let result = Identity::find_by_provider_and_uid(
+                IdentityProvider::Google,
+                "1234",
+                conn,
+                logger,
+            );
+            assert_eq!(result.unwrap().user_id, user.id);
+
+            assert!(Identity::find_by_provider_and_uid(
+                IdentityProvider::GitHub,
+                "1234",
+                conn,
+                logger,
+            )
+            .is_none());
+        });
+    }
+}
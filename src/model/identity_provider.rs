@@ -0,0 +1,106 @@
+//! # A type IdentityProvider for Identity in identity.rs
+//!
+//! EIdentityProvider represents SQL type value
+//! `e_identity_provider` and IdentityProvider is an Enum
+//! holds all the values.
+use std::fmt;
+use std::io::Write;
+use std::slice::Iter;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_identity_provider")]
+pub struct EIdentityProvider;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "EIdentityProvider"]
+pub enum IdentityProvider {
+    GitHub,
+    Google,
+    Ldap,
+}
+
+const IDENTITY_PROVIDERS: [IdentityProvider; 3] = [
+    IdentityProvider::GitHub,
+    IdentityProvider::Google,
+    IdentityProvider::Ldap,
+];
+
+impl fmt::Display for IdentityProvider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::GitHub => write!(f, "github"),
+            Self::Google => write!(f, "google"),
+            Self::Ldap => write!(f, "ldap"),
+        }
+    }
+}
+
+impl ToSql<EIdentityProvider, Pg> for IdentityProvider {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::GitHub => out.write_all(b"github")?,
+            Self::Google => out.write_all(b"google")?,
+            Self::Ldap => out.write_all(b"ldap")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<EIdentityProvider, Pg> for IdentityProvider {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"github" => Ok(Self::GitHub),
+            b"google" => Ok(Self::Google),
+            b"ldap" => Ok(Self::Ldap),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl IdentityProvider {
+    pub fn iter() -> Iter<'static, IdentityProvider> {
+        IDENTITY_PROVIDERS.iter()
+    }
+
+    /// Returns the provider for the name in path (there is no default).
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|p| p.to_string() == s).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            IdentityProvider::from_name("github"),
+            Some(IdentityProvider::GitHub)
+        );
+        assert_eq!(
+            IdentityProvider::from_name("google"),
+            Some(IdentityProvider::Google)
+        );
+        assert_eq!(
+            IdentityProvider::from_name("ldap"),
+            Some(IdentityProvider::Ldap)
+        );
+        assert_eq!(IdentityProvider::from_name("GitHub"), None);
+        assert_eq!(IdentityProvider::from_name("unknown"), None);
+    }
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", IdentityProvider::GitHub), "github");
+        assert_eq!(format!("{}", IdentityProvider::Google), "google");
+        assert_eq!(format!("{}", IdentityProvider::Ldap), "ldap");
+    }
+}
@@ -0,0 +1,312 @@
+//! # Incident
+//!
+//! A group of related messages in a namespace, which is open until it's
+//! resolved. An incident is created via API, or opened by an alert rule (see
+//! `DeliverAlert` job). The alerts of a rule are grouped into its open
+//! incident, and the next one opens a new incident once it's resolved.
+//!
+//! Messages are attached via `incident_messages` (see
+//! `model::incident_message`), also by `assign_incident` of bulk operations.
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use uuid::Uuid;
+
+pub use crate::model::incident_state::*;
+pub use crate::schema::incidents;
+
+use crate::db::with_retry;
+use crate::logger::Logger;
+use crate::model::namespace::Namespace;
+use crate::model::stream::streams;
+
+/// NewIncident
+#[derive(Debug, Insertable)]
+#[table_name = "incidents"]
+pub struct NewIncident {
+    pub namespace_id: i64,
+    pub title: String,
+    pub rule: Option<String>,
+}
+
+/// Incident
+#[derive(Associations, Debug, Identifiable, Queryable)]
+#[belongs_to(Namespace)]
+#[table_name = "incidents"]
+pub struct Incident {
+    pub id: i64,
+    pub uuid: Uuid,
+    pub namespace_id: i64,
+    pub title: String,
+    pub state: IncidentState,
+    pub rule: Option<String>,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for Incident {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Incident {uuid}>", uuid = &self.uuid.to_string())
+    }
+}
+
+impl Incident {
+    pub fn insert(
+        incident: &NewIncident,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(incidents::table).values(incident);
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn find_by_uuid(
+        uuid: &str,
+        namespace_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let uuid = Uuid::parse_str(uuid).ok()?;
+        let q = incidents::table
+            .filter(incidents::uuid.eq(uuid))
+            .filter(incidents::namespace_id.eq(namespace_id))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Returns the incident of the namespace which the stream belongs to.
+    pub fn find_by_uuid_and_stream_id(
+        uuid: &str,
+        stream_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let uuid = Uuid::parse_str(uuid).ok()?;
+        let q = incidents::table
+            .filter(incidents::uuid.eq(uuid))
+            .filter(
+                incidents::namespace_id.eq_any(
+                    streams::table
+                        .select(streams::namespace_id)
+                        .filter(streams::id.eq(stream_id)),
+                ),
+            )
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Returns incidents of the namespace (newest first).
+    pub fn find_all_by_namespace_id(
+        namespace_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = incidents::table
+            .filter(incidents::namespace_id.eq(namespace_id))
+            .order(incidents::id.desc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    fn find_open_by_rule(
+        namespace_id: i64,
+        rule: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = incidents::table
+            .filter(incidents::namespace_id.eq(namespace_id))
+            .filter(incidents::rule.eq(rule))
+            .filter(incidents::state.eq(IncidentState::Open))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        q.first::<Self>(conn).ok()
+    }
+
+    /// Returns the open incident of the rule, or opens a new one with the
+    /// title.
+    pub fn find_or_open_by_rule(
+        namespace_id: i64,
+        rule: &str,
+        title: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let found = Self::find_open_by_rule(namespace_id, rule, conn, logger);
+        if found.is_some() {
+            return found;
+        }
+        let incident = NewIncident {
+            namespace_id,
+            title: title.to_string(),
+            rule: Some(rule.to_string()),
+        };
+        // it may have been opened by another alert of the rule in the meantime
+        Self::insert(&incident, conn, logger).or_else(|| {
+            Self::find_open_by_rule(namespace_id, rule, conn, logger)
+        })
+    }
+
+    /// Changes the state (the time of resolution is cleared on reopen).
+    ///
+    /// It fails with "conflict" if it's an incident of a rule which has
+    /// another open one.
+    pub fn update_state(
+        &mut self,
+        state: IncidentState,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<(), &'static str> {
+        if self.state == state {
+            return Ok(());
+        }
+        let resolved_at = match state {
+            IncidentState::Resolved => Some(now),
+            IncidentState::Open => None,
+        };
+        let q = diesel::update(incidents::table.find(self.id)).set((
+            incidents::state.eq(state),
+            incidents::resolved_at.eq(resolved_at),
+            incidents::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Ok(v) => {
+                *self = v;
+                Ok(())
+            },
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => Err("conflict"),
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to update incident")
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::Utc;
+
+    use crate::model::namespace::namespaces;
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_find_or_open_by_rule() {
+        run(|conn, _, logger| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut incident = Incident::find_or_open_by_rule(
+                namespace.id,
+                "db",
+                "timeout",
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(incident.state, IncidentState::Open);
+            assert_eq!(incident.title, "timeout");
+
+            let result = Incident::find_or_open_by_rule(
+                namespace.id,
+                "db",
+                "deadlock",
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|i| i.id), Some(incident.id));
+
+            let now = Utc::now().naive_utc();
+            let state = IncidentState::Resolved;
+            assert!(incident.update_state(state, now, conn, logger).is_ok());
+            assert_eq!(incident.state, IncidentState::Resolved);
+            assert!(incident.resolved_at.is_some());
+
+            let mut result = Incident::find_or_open_by_rule(
+                namespace.id,
+                "db",
+                "deadlock",
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_ne!(result.id, incident.id);
+            assert_eq!(result.title, "deadlock");
+
+            let uuid = result.uuid.to_string();
+            let found =
+                Incident::find_by_uuid(&uuid, namespace.id, conn, logger);
+            assert_eq!(found.map(|i| i.id), Some(result.id));
+            assert!(Incident::find_by_uuid(&uuid, 0, conn, logger).is_none());
+
+            let incidents =
+                Incident::find_all_by_namespace_id(namespace.id, conn, logger)
+                    .unwrap();
+            assert_eq!(incidents.len(), 2);
+            assert_eq!(incidents[0].id, result.id);
+
+            let state = IncidentState::Resolved;
+            assert!(result.update_state(state, now, conn, logger).is_ok());
+
+            let state = IncidentState::Open;
+            assert!(incident.update_state(state, now, conn, logger).is_ok());
+            assert_eq!(incident.state, IncidentState::Open);
+            assert!(incident.resolved_at.is_none());
+        })
+    }
+}
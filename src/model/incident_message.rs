@@ -0,0 +1,186 @@
+//! # IncidentMessage
+//!
+//! A link between an incident and a message (see `model::incident`). A
+//! message can be attached to some incidents, and the links are deleted with
+//! either of them.
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+
+pub use crate::schema::incident_messages;
+
+use crate::logger::Logger;
+use crate::model::incident::Incident;
+
+/// NewIncidentMessage
+#[derive(Debug, Insertable)]
+#[table_name = "incident_messages"]
+pub struct NewIncidentMessage {
+    pub incident_id: i64,
+    pub message_id: String,
+}
+
+/// IncidentMessage
+///
+/// `created_at` is the time of the attachment.
+#[derive(Associations, Debug, Identifiable, Queryable)]
+#[belongs_to(Incident)]
+#[table_name = "incident_messages"]
+pub struct IncidentMessage {
+    pub id: i64,
+    pub incident_id: i64,
+    pub message_id: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl fmt::Display for IncidentMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<IncidentMessage {id}>", id = &self.id)
+    }
+}
+
+impl IncidentMessage {
+    /// Attaches the messages to the incident, and returns the number of the
+    /// newly attached ones (the attached ones are skipped).
+    pub fn attach(
+        incident_id: i64,
+        message_ids: &[String],
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<usize> {
+        let values: Vec<NewIncidentMessage> = message_ids
+            .iter()
+            .map(|id| NewIncidentMessage {
+                incident_id,
+                message_id: id.to_string(),
+            })
+            .collect();
+        let q = diesel::insert_into(incident_messages::table)
+            .values(&values)
+            .on_conflict((
+                incident_messages::incident_id,
+                incident_messages::message_id,
+            ))
+            .do_nothing();
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.execute(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(n) => Some(n),
+        }
+    }
+
+    /// Returns the links of the incident (in the order of the attachment).
+    pub fn find_all_by_incident_id(
+        incident_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = incident_messages::table
+            .filter(incident_messages::incident_id.eq(incident_id))
+            .order(incident_messages::id.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::Utc;
+
+    use crate::id::{IdGenerator, RandomIdGenerator};
+    use crate::model::incident::NewIncident;
+    use crate::model::message::{AgentType, Message, NewMessage};
+    use crate::model::namespace::{Namespace, namespaces};
+    use crate::model::stream::{Stream, streams};
+    use crate::model::test::run;
+    use crate::model::user::{User, users};
+
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::stream::data::STREAMS;
+    use crate::model::user::data::USERS;
+
+    #[test]
+    fn test_attach() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let stream = diesel::insert_into(streams::table)
+                .values(STREAMS.get("oswald's stream").unwrap())
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let m = NewMessage {
+                agent_id: user.id,
+                agent_type: AgentType::Person,
+                stream_id: stream.id,
+                title: Some("title".to_string()),
+
+                ..Default::default()
+            };
+            let ids: Vec<String> = (0..2)
+                .map(|_| {
+                    let id = RandomIdGenerator.ulid(Utc::now());
+                    Message::insert(&m, &id, conn, logger).unwrap()
+                })
+                .collect();
+
+            let incident = NewIncident {
+                namespace_id: namespace.id,
+                title: "outage".to_string(),
+                rule: None,
+            };
+            let incident = Incident::insert(&incident, conn, logger).unwrap();
+
+            let result =
+                IncidentMessage::attach(incident.id, &ids[..1], conn, logger);
+            assert_eq!(result, Some(1));
+
+            // the first one is skipped
+            let result =
+                IncidentMessage::attach(incident.id, &ids, conn, logger);
+            assert_eq!(result, Some(1));
+
+            let links = IncidentMessage::find_all_by_incident_id(
+                incident.id,
+                conn,
+                logger,
+            )
+            .unwrap();
+            let message_ids: Vec<String> =
+                links.into_iter().map(|l| l.message_id).collect();
+            assert_eq!(message_ids, ids);
+
+            let messages =
+                Message::find_all_by_ids(namespace.id, &ids, conn, logger)
+                    .unwrap();
+            assert_eq!(messages.len(), 2);
+            let messages = Message::find_all_by_ids(0, &ids, conn, logger);
+            assert!(messages.unwrap().is_empty());
+        })
+    }
+}
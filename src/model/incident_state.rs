@@ -0,0 +1,87 @@
+//! # A type IncidentState for Incident in incident.rs
+//!
+//! EIncidentState represents SQL type value `e_incident_state` and
+//! IncidentState is an Enum holds all the values.
+use std::fmt;
+use std::io::Write;
+use std::slice::Iter;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_incident_state")]
+pub struct EIncidentState;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "EIncidentState"]
+pub enum IncidentState {
+    Open,
+    Resolved,
+}
+
+const INCIDENT_STATES: [IncidentState; 2] =
+    [IncidentState::Open, IncidentState::Resolved];
+
+impl fmt::Display for IncidentState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Open => write!(f, "open"),
+            Self::Resolved => write!(f, "resolved"),
+        }
+    }
+}
+
+impl ToSql<EIncidentState, Pg> for IncidentState {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::Open => out.write_all(b"open")?,
+            Self::Resolved => out.write_all(b"resolved")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<EIncidentState, Pg> for IncidentState {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"open" => Ok(Self::Open),
+            b"resolved" => Ok(Self::Resolved),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl IncidentState {
+    pub fn iter() -> Iter<'static, IncidentState> {
+        INCIDENT_STATES.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|v| v.to_string() == s).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", IncidentState::Open), "open");
+        assert_eq!(format!("{}", IncidentState::Resolved), "resolved");
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            IncidentState::from_name("resolved"),
+            Some(IncidentState::Resolved)
+        );
+        assert_eq!(IncidentState::from_name("closed"), None);
+    }
+}
@@ -15,7 +15,10 @@ use serde::Serialize;
 #[postgres(type_name = "e_log_level")]
 pub struct ELogLevel;
 
-#[derive(AsExpression, Clone, Debug, FromSqlRow, PartialEq, Serialize)]
+// the order is by severity (e.g. `Debug < Warning`)
+#[derive(
+    AsExpression, Clone, Debug, FromSqlRow, PartialEq, PartialOrd, Serialize,
+)]
 #[sql_type = "ELogLevel"]
 pub enum LogLevel {
     Debug,
@@ -9,7 +9,7 @@ pub use crate::model::membership_role::*;
 pub use crate::schema::memberships;
 
 use crate::logger::Logger;
-use crate::model::user::User;
+use crate::model::user::{User, users};
 use crate::model::namespace::Namespace;
 
 /// NewMembership
@@ -85,6 +85,80 @@ impl Membership {
         }
     }
 
+    /// Returns memberships in the namespace which are not revoked.
+    pub fn find_all_by_namespace_id(
+        namespace_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = memberships::table
+            .filter(memberships::namespace_id.eq(namespace_id))
+            .filter(memberships::revoked_at.is_null())
+            .order(memberships::id.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Membership>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Returns memberships in the namespace which are not revoked with their
+    /// users.
+    pub fn find_all_with_users_by_namespace_id(
+        namespace_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<(Self, User)>> {
+        let q = memberships::table
+            .inner_join(users::table)
+            .filter(memberships::namespace_id.eq(namespace_id))
+            .filter(memberships::revoked_at.is_null())
+            .order(memberships::id.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<(Membership, User)>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Finds a membership which is not revoked.
+    pub fn find_by_namespace_id_and_user_id(
+        namespace_id: i64,
+        user_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = memberships::table
+            .filter(memberships::namespace_id.eq(namespace_id))
+            .filter(memberships::user_id.eq(user_id))
+            .filter(memberships::revoked_at.is_null())
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Membership>(conn) {
+            Ok(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn is_owner(&self) -> bool {
+        match self.role {
+            MembershipRole::PrimaryOwner | MembershipRole::Owner => true,
+            MembershipRole::Member => false,
+        }
+    }
+
     pub fn insert(
         membership: &NewMembership,
         conn: &PgConnection,
@@ -107,6 +181,25 @@ impl Membership {
         }
     }
 
+    pub fn update_role(
+        &self,
+        role: MembershipRole,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<Self, &'static str> {
+        let q = diesel::update(self).set(memberships::role.eq(role));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to change role")
+            },
+            Ok(m) => Ok(m),
+        }
+    }
+
     pub fn with_user(user: &User) -> WithUser {
         memberships::user_id.eq(user.id)
     }
@@ -5,25 +5,138 @@
 //! ## Note
 //!
 //! See diesel_tests' custom_types.rs.
+use std::collections::HashMap;
 use std::fmt;
+use std::io::Write;
 
-use chrono::{NaiveDateTime, Utc};
+use chrono::NaiveDateTime;
 use diesel::{self, Insertable, prelude::*};
 use diesel::debug_query;
 use diesel::dsl;
 use diesel::pg::{Pg, PgConnection};
+use diesel::sql_types::{
+    Array, BigInt, Bool, Nullable, Text, Timestamp, Varchar,
+};
+use postgres::Transaction;
 use serde::Serialize;
 
+use crate::clock::Clock;
+use crate::db::with_retry;
 use crate::logger::Logger;
+use crate::model::message_body::{MessageBody, message_bodies};
 use crate::request::message::Message as RequestData;
+use crate::tracecontext::TraceParent;
 
 pub use crate::model::agent_type::*;
 pub use crate::model::log_level::*;
 pub use crate::model::log_format::*;
+pub use crate::model::message_filter::*;
+pub use crate::model::stats_interval::*;
 pub use crate::model::stream::{Stream, streams};
 use crate::model::user::User;
 pub use crate::schema::messages;
 
+/// Protocol Buffers (see proto/message.proto)
+pub mod proto {
+    pub use crate::model::message_proto::*;
+}
+
+sql_function!(fn coalesce(x: Nullable<Text>, y: Text) -> Text);
+sql_function!(fn lower(x: Text) -> Text);
+// immutable wrapper of unaccent (see migration)
+sql_function!(fn f_unaccent(x: Text) -> Text);
+sql_function!(fn array_append(x: Array<Varchar>, y: Varchar) -> Array<Varchar>);
+
+// `messages.tags` is a `varchar(64)[]`, which can't be compared with a bind of
+// `text[]` by `@>` (diesel binds a `Varchar` as a `text`)
+fn has_tags(
+    tags: Vec<String>,
+) -> Box<dyn BoxableExpression<messages::table, Pg, SqlType = Bool>> {
+    Box::new(
+        dsl::sql::<Bool>("messages.tags @> ")
+            .bind::<Array<Text>, _>(tags)
+            .sql("::varchar[]"),
+    )
+}
+
+/// SearchOptions
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub unaccent: bool,
+}
+
+/// SourceFilter
+///
+/// Messages from the hostname, the service and the environment (any of them
+/// if omitted).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceFilter {
+    pub hostname: Option<String>,
+    pub service: Option<String>,
+    pub environment: Option<String>,
+}
+
+/// FacetCount
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: i64,
+}
+
+/// Facets
+///
+/// The numbers of the messages by their sources (the top ones of each, except
+/// the messages without it).
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Facets {
+    pub hostname: Vec<FacetCount>,
+    pub service: Vec<FacetCount>,
+    pub environment: Vec<FacetCount>,
+}
+
+// values per facet
+const FACET_LIMIT: usize = 10;
+
+// the most frequent values (in alphabetical order if the counts are same)
+fn top_facets(counts: HashMap<String, i64>) -> Vec<FacetCount> {
+    let mut facets: Vec<FacetCount> = counts
+        .into_iter()
+        .map(|(value, count)| FacetCount { value, count })
+        .collect();
+    facets.sort_by(|a, b| b.count.cmp(&a.count).then(a.value.cmp(&b.value)));
+    facets.truncate(FACET_LIMIT);
+    facets
+}
+
+/// RetentionPreview
+///
+/// What a retention would delete (the messages created before the time), and
+/// the oldest message which would survive.
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct RetentionPreview {
+    #[sql_type = "BigInt"]
+    pub messages_count: i64,
+    #[sql_type = "BigInt"]
+    pub bytes_count: i64,
+    #[sql_type = "Nullable<Timestamp>"]
+    pub oldest_created_at: Option<NaiveDateTime>,
+}
+
+/// MessageCount
+///
+/// The number of the messages of a level in a time bucket (see
+/// `count_by_level_and_time`).
+#[derive(Debug, PartialEq, QueryableByName)]
+pub struct MessageCount {
+    #[sql_type = "Timestamp"]
+    pub time: NaiveDateTime,
+    #[sql_type = "Text"]
+    pub level: String,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
 /// NewMessage
 #[derive(Debug, Insertable)]
 #[table_name = "messages"]
@@ -37,6 +150,12 @@ pub struct NewMessage {
     pub format: LogFormat,
     pub title: Option<String>,
     pub content: Option<String>,
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub hostname: Option<String>,
+    pub service: Option<String>,
+    pub environment: Option<String>,
+    pub tags: Vec<String>,
 }
 
 impl fmt::Display for NewMessage {
@@ -61,12 +180,75 @@ impl Default for NewMessage {
             format: LogFormat::TOML,
             title: None,
             content: None,
+            trace_id: None,
+            span_id: None,
+            hostname: None,
+            service: None,
+            environment: None,
+            tags: vec![],
         }
     }
 }
 
+/// MessageRow
+///
+/// A new message with the id and the time of creation for
+/// `Message::copy_insert` (e.g. on imports).
+#[derive(Debug)]
+pub struct MessageRow {
+    pub id: String,
+    pub message: NewMessage,
+    pub created_at: NaiveDateTime,
+}
+
+// the columns in a row of COPY (in this order)
+const COPY_COLUMNS: [&str; 11] = [
+    "id",
+    "agent_id",
+    "agent_type",
+    "stream_id",
+    "code",
+    "lang",
+    "level",
+    "format",
+    "title",
+    "content",
+    "created_at",
+];
+
+// quotes the value for CSV (an unquoted empty field is NULL)
+fn csv_field(value: Option<&str>) -> String {
+    match value {
+        None => "".to_string(),
+        Some(v) => format!("\"{}\"", v.replace('"', "\"\"")),
+    }
+}
+
+impl MessageRow {
+    /// Formats the row as a line of CSV for COPY.
+    pub fn to_csv(&self) -> String {
+        let m = &self.message;
+        let fields = [
+            self.id.to_string(),
+            m.agent_id.to_string(),
+            m.agent_type.to_string(),
+            m.stream_id.to_string(),
+            csv_field(m.code.as_deref()),
+            csv_field(Some(&m.lang)),
+            m.level.to_string(),
+            m.format.to_string(),
+            csv_field(Some(m.title.as_deref().unwrap_or(""))),
+            csv_field(m.content.as_deref()),
+            self.created_at.format("%Y-%m-%d %H:%M:%S%.f").to_string(),
+        ];
+        format!("{}\n", fields.join(","))
+    }
+}
+
 impl From<RequestData> for NewMessage {
     fn from(data: RequestData) -> Self {
+        // an invalid one is ignored here (see validation)
+        let trace = data.traceparent.as_deref().and_then(TraceParent::parse);
         // TODO: get stream_id from data
         Self {
             agent_id: data.agent_id,
@@ -84,6 +266,12 @@ impl From<RequestData> for NewMessage {
             ),
             title: data.title,
             content: data.content,
+            trace_id: trace.as_ref().map(|t| t.trace_id.to_string()),
+            span_id: trace.map(|t| t.span_id),
+            hostname: data.hostname,
+            service: data.service,
+            environment: data.environment,
+            tags: vec![],
         }
     }
 }
@@ -92,6 +280,7 @@ type AllColumns = (
     messages::id,
     messages::agent_id,
     messages::agent_type,
+    messages::stream_id,
     messages::code,
     messages::lang,
     messages::level,
@@ -100,12 +289,24 @@ type AllColumns = (
     messages::content,
     messages::created_at,
     messages::updated_at,
+    messages::tags,
+    messages::deleted_at,
+    messages::acknowledged_at,
+    messages::resolved_at,
+    messages::body_id,
+    messages::occurrences_count,
+    messages::trace_id,
+    messages::span_id,
+    messages::hostname,
+    messages::service,
+    messages::environment,
 );
 
 const ALL_COLUMNS: AllColumns = (
     messages::id,
     messages::agent_id,
     messages::agent_type,
+    messages::stream_id,
     messages::code,
     messages::lang,
     messages::level,
@@ -114,6 +315,17 @@ const ALL_COLUMNS: AllColumns = (
     messages::content,
     messages::created_at,
     messages::updated_at,
+    messages::tags,
+    messages::deleted_at,
+    messages::acknowledged_at,
+    messages::resolved_at,
+    messages::body_id,
+    messages::occurrences_count,
+    messages::trace_id,
+    messages::span_id,
+    messages::hostname,
+    messages::service,
+    messages::environment,
 );
 
 /// Message
@@ -128,7 +340,8 @@ const ALL_COLUMNS: AllColumns = (
 )]
 #[table_name = "messages"]
 pub struct Message {
-    pub id: i64,
+    /// ULID
+    pub id: String,
     pub agent_id: i64,
     pub agent_type: AgentType,
     pub stream_id: i64,
@@ -140,6 +353,24 @@ pub struct Message {
     pub content: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub tags: Vec<String>,
+    #[serde(skip)]
+    pub deleted_at: Option<NaiveDateTime>,
+    pub acknowledged_at: Option<NaiveDateTime>,
+    pub resolved_at: Option<NaiveDateTime>,
+    /// The body which has the content (see `model::message_body`).
+    #[serde(skip)]
+    pub body_id: Option<i64>,
+    /// The number of the identical ones ingested within the deduplication
+    /// window (see `request::duplicate_window`).
+    pub occurrences_count: i32,
+    /// The trace and the span where it's logged (see `tracecontext`).
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    /// Where it comes from (see `request::message::Message`).
+    pub hostname: Option<String>,
+    pub service: Option<String>,
+    pub environment: Option<String>,
 }
 
 impl Clone for Message {
@@ -149,6 +380,7 @@ impl Clone for Message {
         let format = format!("{}", self.format);
 
         Self {
+            id: self.id.clone(),
             agent_id: self.agent_id,
             agent_type: AgentType::from(agent_type),
             stream_id: self.stream_id,
@@ -158,6 +390,12 @@ impl Clone for Message {
             format: LogFormat::from(format),
             title: self.title.clone(),
             content: self.content.clone(),
+            tags: self.tags.clone(),
+            trace_id: self.trace_id.clone(),
+            span_id: self.span_id.clone(),
+            hostname: self.hostname.clone(),
+            service: self.service.clone(),
+            environment: self.environment.clone(),
 
             ..*self
         }
@@ -176,7 +414,10 @@ type WithUser = dsl::And<
     dsl::Eq<messages::agent_id, i64>,
     dsl::Eq<messages::agent_type, AgentType>,
 >;
-type Visible = dsl::IsNotNull<messages::content>;
+type Visible = dsl::Or<
+    dsl::IsNotNull<messages::content>,
+    dsl::IsNotNull<messages::body_id>,
+>;
 type ByUser = dsl::Filter<All, WithUser>;
 type VisibleTo = dsl::Filter<All, dsl::And<WithUser, Visible>>;
 
@@ -189,22 +430,131 @@ impl Message {
         Self::all().filter(Self::with_user(user))
     }
 
-    pub fn fetch_by_stream_slug(
-        stream_slug: String,
+    /// Returns the latest updated_at and the number of messages in the
+    /// stream. It's used as an entity tag for the list.
+    pub fn version_by_stream_id(
+        stream_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<(Option<NaiveDateTime>, i64)> {
+        let q = messages::table
+            .filter(messages::stream_id.eq(stream_id))
+            .filter(messages::deleted_at.is_null())
+            // aggregates can't be mixed in a tuple (diesel 1.4)
+            .select(dsl::sql::<(Nullable<Timestamp>, BigInt)>(
+                "max(messages.updated_at), count(*)",
+            ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<(Option<NaiveDateTime>, i64)>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Returns what a retention of the namespace would delete. It's computed
+    /// in one scan of the messages (which are not deleted yet).
+    pub fn preview_retention(
+        namespace_id: i64,
+        before: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<RetentionPreview> {
+        let q = diesel::sql_query(
+            r#"
+SELECT
+  COUNT(*) FILTER (WHERE m.created_at < $2) AS messages_count,
+  COALESCE(SUM(
+    OCTET_LENGTH(m.title) + COALESCE(OCTET_LENGTH(m.content), 0)
+  ) FILTER (WHERE m.created_at < $2), 0)::BIGINT AS bytes_count,
+  MIN(m.created_at) FILTER (WHERE m.created_at >= $2) AS oldest_created_at
+FROM messages m
+INNER JOIN streams s ON s.id = m.stream_id
+WHERE s.namespace_id = $1 AND m.deleted_at IS NULL
+"#,
+        )
+        .bind::<BigInt, _>(namespace_id)
+        .bind::<Timestamp, _>(before);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<RetentionPreview>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Returns the number of the messages in the namespace by the level and
+    /// the time bucket of the interval, in `[from, to)`. Buckets without any
+    /// message are not included.
+    pub fn count_by_level_and_time(
+        namespace_id: i64,
+        interval: StatsInterval,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<MessageCount>> {
+        let q = diesel::sql_query(
+            r#"
+SELECT
+  DATE_TRUNC($2, m.created_at) AS time,
+  m.level::TEXT AS level,
+  COUNT(*) AS count
+FROM messages m
+INNER JOIN streams s ON s.id = m.stream_id
+WHERE s.namespace_id = $1 AND m.deleted_at IS NULL AND
+  m.created_at >= $3 AND m.created_at < $4
+GROUP BY 1, 2
+ORDER BY 1, 2
+"#,
+        )
+        .bind::<BigInt, _>(namespace_id)
+        .bind::<Text, _>(interval.to_string())
+        .bind::<Timestamp, _>(from)
+        .bind::<Timestamp, _>(to);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<MessageCount>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn fetch_by_stream_id(
+        stream_id: i64,
+        source: &SourceFilter,
         offset: i64,
         limit: i64,
         conn: &PgConnection,
         logger: &Logger,
     ) -> Option<Vec<Self>> {
-        if stream_slug.is_empty() {
-            return None;
-        }
-
-        // TODO: Fix clause id = slug
-        let stream_id = 1;
-        let q = messages::table
+        let mut q = messages::table
             .inner_join(streams::table)
             .filter(streams::id.eq(stream_id))
+            .filter(messages::deleted_at.is_null())
+            .into_boxed();
+        if let Some(ref v) = source.hostname {
+            q = q.filter(messages::hostname.eq(v.to_string()));
+        }
+        if let Some(ref v) = source.service {
+            q = q.filter(messages::service.eq(v.to_string()));
+        }
+        if let Some(ref v) = source.environment {
+            q = q.filter(messages::environment.eq(v.to_string()));
+        }
+        let q = q
             .order(messages::created_at.desc())
             .offset(offset)
             .limit(limit);
@@ -212,7 +562,10 @@ impl Message {
         info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
         match q.load::<(Self, Stream)>(conn) {
-            Ok(r) => Some(r.into_iter().map(|(m, _)| m).collect::<Vec<Self>>()),
+            Ok(r) => {
+                let v = r.into_iter().map(|(m, _)| m).collect::<Vec<Self>>();
+                Self::with_bodies(v, conn, logger)
+            },
             Err(e) => {
                 println!("err: {}", e);
                 None
@@ -220,174 +573,1528 @@ impl Message {
         }
     }
 
-    pub fn first_by_stream_id(
-        id: i64,
+    /// Returns messages which contain all the terms in the query in their
+    /// title or content.
+    ///
+    /// The matching is case-insensitive by default. With `unaccent`, the
+    /// diacritics are ignored on both sides (e.g. "ete" matches "été").
+    pub fn search_by_stream_id(
         stream_id: i64,
+        query: &str,
+        options: &SearchOptions,
+        offset: i64,
+        limit: i64,
         conn: &PgConnection,
         logger: &Logger,
-    ) -> Option<Self> {
-        let q = messages::table
-            .filter(messages::stream_id.eq(stream_id))
-            .find(id);
+    ) -> Option<Vec<Self>> {
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return None;
+        }
+
+        let q = Self::searched(stream_id, &terms, options)
+            .order(messages::created_at.desc())
+            .offset(offset)
+            .limit(limit);
+
         info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
-        match q.first::<Message>(conn) {
+        match q.load::<Self>(conn) {
             Err(e) => {
-                println!("err: {}", e);
+                error!(logger, "err: {}", e);
                 None
             },
-            Ok(m) => Some(m),
+            Ok(v) => Self::with_bodies(v, conn, logger),
         }
     }
 
-    /// Save new message.
-    ///
-    /// `created_at` and `updated_at` will be filled on PostgreSQL side
-    /// using timezone('utc'::text, now()).
-    pub fn insert(
-        message: &NewMessage,
+    /// Counts all the messages which match the query of a search (see
+    /// `search_by_stream_id`) by their sources.
+    pub fn count_facets_by_search(
+        stream_id: i64,
+        query: &str,
+        options: &SearchOptions,
         conn: &PgConnection,
         logger: &Logger,
-    ) -> Option<i64> {
-        let q = diesel::insert_into(messages::table)
-            .values(message)
-            .returning(messages::id);
+    ) -> Option<Facets> {
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return None;
+        }
+
+        let q = Self::searched(stream_id, &terms, options).select((
+            messages::hostname,
+            messages::service,
+            messages::environment,
+        ));
+
         info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
-        match q.get_result::<i64>(conn) {
+        type Source = (Option<String>, Option<String>, Option<String>);
+        let sources = match q.load::<Source>(conn) {
             Err(e) => {
-                println!("err: {}", e);
-                None
+                error!(logger, "err: {}", e);
+                return None;
             },
-            Ok(id) => Some(id),
+            Ok(v) => v,
+        };
+
+        let mut counts: [HashMap<String, i64>; 3] = Default::default();
+        for (hostname, service, environment) in sources {
+            for (i, v) in [hostname, service, environment].iter().enumerate() {
+                if let Some(v) = v {
+                    *counts[i].entry(v.to_string()).or_insert(0) += 1;
+                }
+            }
         }
+        let [hostname, service, environment] = counts;
+        Some(Facets {
+            hostname: top_facets(hostname),
+            service: top_facets(service),
+            environment: top_facets(environment),
+        })
     }
 
-    /// Update a message.
-    pub fn update(
-        message: &mut Message,
+    // messages on the stream which contain all the terms in their title or
+    // content (see `search_by_stream_id`)
+    fn searched<'a>(
+        stream_id: i64,
+        terms: &[&str],
+        options: &SearchOptions,
+    ) -> messages::BoxedQuery<'a, Pg> {
+        let mut q = messages::table
+            .filter(messages::stream_id.eq(stream_id))
+            .filter(messages::deleted_at.is_null())
+            .into_boxed();
+        // NOTE:
+        // These expressions must be same as the indexes in the migration
+        // (add_search_indexes_to_messages).
+        //
+        // The content of a deduplicated message is matched on its body.
+        for term in terms {
+            let p = format!("%{}%", escape_like(term));
+            let content = || coalesce(messages::content, "");
+            let body = message_bodies::content;
+            let bodies =
+                || message_bodies::table.select(message_bodies::id.nullable());
+            q = match (options.case_sensitive, options.unaccent) {
+                (true, false) => q.filter(
+                    messages::title
+                        .like(p.clone())
+                        .or(content().like(p.clone()))
+                        .or(messages::body_id
+                            .eq_any(bodies().filter(body.like(p)))),
+                ),
+                (false, false) => q.filter(
+                    messages::title
+                        .ilike(p.clone())
+                        .or(content().ilike(p.clone()))
+                        .or(messages::body_id
+                            .eq_any(bodies().filter(body.ilike(p)))),
+                ),
+                (true, true) => q.filter(
+                    f_unaccent(messages::title)
+                        .like(f_unaccent(p.clone()))
+                        .or(f_unaccent(content()).like(f_unaccent(p.clone())))
+                        .or(messages::body_id.eq_any(bodies().filter(
+                            f_unaccent(body).like(f_unaccent(p)),
+                        ))),
+                ),
+                (false, true) => q.filter(
+                    lower(f_unaccent(messages::title))
+                        .like(lower(f_unaccent(p.clone())))
+                        .or(lower(f_unaccent(content()))
+                            .like(lower(f_unaccent(p.clone()))))
+                        .or(messages::body_id.eq_any(bodies().filter(
+                            lower(f_unaccent(body)).like(lower(f_unaccent(p))),
+                        ))),
+                ),
+            };
+        }
+        q
+    }
+
+    /// Returns messages on the stream (newest first), optionally filtered
+    /// by level.
+    pub fn find_all_by_stream_id(
+        stream_id: i64,
+        level: Option<LogLevel>,
+        offset: i64,
+        limit: i64,
         conn: &PgConnection,
         logger: &Logger,
-    ) -> Option<i64> {
-        message.updated_at = Utc::now().naive_utc();
-        let q = diesel::update(messages::table)
-            .set(&*message)
-            .filter(messages::id.eq(message.id))
-            .returning(messages::id);
+    ) -> Option<Vec<Self>> {
+        if limit < 1 {
+            return None;
+        }
+
+        let mut q = Self::all()
+            .filter(messages::stream_id.eq(stream_id))
+            .filter(messages::deleted_at.is_null())
+            .into_boxed();
+        if let Some(level) = level {
+            q = q.filter(messages::level.eq(level));
+        }
+        let q = q
+            .order(messages::created_at.desc())
+            .offset(offset)
+            .limit(limit);
+
         info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
-        match q.get_result::<i64>(conn) {
+        match q.load::<Self>(conn) {
             Err(e) => {
-                println!("err: {}", e);
+                error!(logger, "err: {}", e);
                 None
             },
-            Ok(id) => Some(id),
+            Ok(v) => Self::with_bodies(v, conn, logger),
         }
     }
 
-    // FIXME: scope
-    pub fn visible() -> Visible {
-        messages::content.is_not_null()
-    }
-
-    // FIXME: scope
-    pub fn visible_to(user: &User) -> VisibleTo {
-        Self::all().filter(Self::with_user(user).and(Self::visible()))
-    }
+    /// Returns the messages of the ids in all the streams of the namespace
+    /// (oldest first). The ones which are not found are just omitted.
+    pub fn find_all_by_ids(
+        namespace_id: i64,
+        ids: &[String],
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let streams = streams::table
+            .select(streams::id)
+            .filter(streams::namespace_id.eq(namespace_id));
+        let q = Self::all()
+            .filter(messages::id.eq_any(ids))
+            .filter(messages::stream_id.eq_any(streams))
+            .filter(messages::deleted_at.is_null())
+            .order((messages::created_at.asc(), messages::id.asc()));
 
-    pub fn with_type(agent_type: AgentType) -> WithType {
-        messages::agent_type.eq(agent_type)
-    }
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
-    pub fn with_user(user: &User) -> WithUser {
-        messages::agent_id
-            .eq(user.id)
-            .and(Self::with_type(AgentType::Person))
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Self::with_bodies(v, conn, logger),
+        }
     }
-}
-
-#[cfg(test)]
-mod data {
-    use super::*;
 
-    use chrono::{Utc, TimeZone};
-    use fnv::FnvHashMap;
+    /// Returns messages of the trace in all the streams of the namespace
+    /// (oldest first), which are the logs of a distributed trace.
+    pub fn find_all_by_trace_id(
+        namespace_id: i64,
+        trace_id: &str,
+        limit: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        if limit < 1 {
+            return None;
+        }
 
-    use crate::fnvhashmap;
-    use crate::model::stream::data::STREAMS;
+        let streams = streams::table
+            .select(streams::id)
+            .filter(streams::namespace_id.eq(namespace_id));
+        let q = Self::all()
+            .filter(messages::trace_id.eq(trace_id))
+            .filter(messages::stream_id.eq_any(streams))
+            .filter(messages::deleted_at.is_null())
+            .order((messages::created_at.asc(), messages::id.asc()))
+            .limit(limit);
 
-    type MessageFixture = FnvHashMap<&'static str, Message>;
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
-    lazy_static! {
-        pub static ref MESSAGES: MessageFixture = fnvhashmap! {
-            "blank message" => Message {
-                id: 1,
-                agent_id: 0,
-                agent_type: AgentType::Person,
-                stream_id: STREAMS.clone().get("weenie's stream").unwrap().id,
-                code: None,
-                lang: "en".to_string(),
-                level: LogLevel::Information,
-                format: LogFormat::TOML,
-                title: "title".to_string(),
-                content: None,
-                created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
-                updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
-            }
-        };
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Self::with_bodies(v, conn, logger),
+        }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    /// Returns the messages on the stream of the message just before and
+    /// after it (oldest first), at most the limit of each.
+    pub fn find_all_around(
+        message: &Self,
+        limit: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<(Vec<Self>, Vec<Self>)> {
+        if limit < 1 {
+            return Some((vec![], vec![]));
+        }
 
-    use crate::model::message::data::MESSAGES;
-    use crate::model::namespace::{Namespace, namespaces};
-    use crate::model::namespace::data::NAMESPACES;
-    use crate::model::stream::{Stream, streams};
-    use crate::model::stream::data::STREAMS;
-    use crate::model::test::run;
+        let t = message.created_at;
+        let earlier = messages::created_at
+            .lt(t)
+            .or(messages::created_at.eq(t).and(messages::id.lt(&message.id)));
+        let q = Self::all()
+            .filter(messages::stream_id.eq(message.stream_id))
+            .filter(messages::deleted_at.is_null())
+            .filter(earlier)
+            .order((messages::created_at.desc(), messages::id.desc()))
+            .limit(limit);
 
-    #[test]
-    fn test_insert() {
-        run(|conn, _, logger| {
-            let ns = NAMESPACES.get("ball").unwrap();
-            let namespace = diesel::insert_into(namespaces::table)
-                .values(ns)
-                .get_result::<Namespace>(conn)
-                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
-            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
-            s.namespace_id = namespace.id;
-            let stream = diesel::insert_into(streams::table)
-                .values(&s)
-                .get_result::<Stream>(conn)
-                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+        let mut before = match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return None;
+            },
+            Ok(v) => v,
+        };
+        before.reverse();
 
-            let m = NewMessage {
-                agent_id: 1,
-                agent_type: AgentType::Person,
-                stream_id: stream.id,
-                code: None,
-                lang: "en".to_string(),
-                level: LogLevel::Information,
-                format: LogFormat::TOML,
-                title: Some("title".to_string()),
-                content: None,
-            };
-            let result = Message::insert(&m, conn, logger);
-            assert!(result.is_some());
+        let later = messages::created_at
+            .gt(t)
+            .or(messages::created_at.eq(t).and(messages::id.gt(&message.id)));
+        let q = Self::all()
+            .filter(messages::stream_id.eq(message.stream_id))
+            .filter(messages::deleted_at.is_null())
+            .filter(later)
+            .order((messages::created_at.asc(), messages::id.asc()))
+            .limit(limit);
 
-            let rows_count: i64 = messages::table
-                .count()
-                .first(conn)
-                .expect("Failed to count rows");
-            assert_eq!(1, rows_count);
-        })
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        let after = match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return None;
+            },
+            Ok(v) => v,
+        };
+        Some((
+            Self::with_bodies(before, conn, logger)?,
+            Self::with_bodies(after, conn, logger)?,
+        ))
     }
 
-    #[test]
+    pub fn first_by_stream_id(
+        id: &str,
+        stream_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = messages::table
+            .filter(messages::stream_id.eq(stream_id))
+            .filter(messages::deleted_at.is_null())
+            .find(id);
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Message>(conn) {
+            Err(e) => {
+                println!("err: {}", e);
+                None
+            },
+            Ok(m) => Self::with_bodies(vec![m], conn, logger)?.pop(),
+        }
+    }
+
+    // fills the content of the messages which refer to a body
+    fn with_bodies(
+        mut messages: Vec<Self>,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        match MessageBody::fill(&mut messages, conn, logger) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(_) => Some(messages),
+        }
+    }
+
+    // messages on the stream which match the filter (except deleted ones)
+    fn filtered(
+        stream_id: i64,
+        filter: &MessageFilter,
+    ) -> messages::BoxedQuery<'_, Pg> {
+        let mut q = messages::table
+            .filter(messages::stream_id.eq(stream_id))
+            .filter(messages::deleted_at.is_null())
+            .into_boxed();
+        for term in filter.terms.iter() {
+            let p = format!("%{}%", escape_like(term));
+            q = q.filter(
+                messages::title
+                    .ilike(p.clone())
+                    .or(coalesce(messages::content, "").ilike(p.clone()))
+                    .or(messages::body_id.eq_any(
+                        message_bodies::table
+                            .select(message_bodies::id.nullable())
+                            .filter(message_bodies::content.ilike(p)),
+                    )),
+            );
+        }
+        if !filter.levels.is_empty() {
+            q = q.filter(messages::level.eq_any(filter.levels.clone()));
+        }
+        if !filter.codes.is_empty() {
+            q = q.filter(messages::code.eq_any(filter.codes.clone()));
+        }
+        if !filter.tags.is_empty() {
+            q = q.filter(has_tags(filter.tags.clone()));
+        }
+        if !filter.trace_ids.is_empty() {
+            q = q.filter(messages::trace_id.eq_any(filter.trace_ids.clone()));
+        }
+        if let Some(after) = filter.after {
+            q = q.filter(messages::created_at.ge(after));
+        }
+        if let Some(before) = filter.before {
+            q = q.filter(messages::created_at.lt(before));
+        }
+        q
+    }
+
+    /// Counts messages which match the filter.
+    pub fn count_by_filter(
+        stream_id: i64,
+        filter: &MessageFilter,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<i64> {
+        let q = Self::filtered(stream_id, filter).count();
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<i64>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(n) => Some(n),
+        }
+    }
+
+    /// Returns ids of messages which match the filter in batches (ordered by
+    /// id, after the last id of the previous batch). The first batch is after
+    /// an empty id.
+    pub fn find_ids_by_filter(
+        stream_id: i64,
+        filter: &MessageFilter,
+        after_id: &str,
+        limit: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<String>> {
+        let q = Self::filtered(stream_id, filter)
+            .select(messages::id)
+            .filter(messages::id.gt(after_id))
+            .order(messages::id.asc())
+            .limit(limit);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<String>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Adds the tag to messages (if they don't have it yet).
+    pub fn add_tag_by_ids(
+        ids: &[String],
+        tag: &str,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<usize> {
+        let has_tag = has_tags(vec![tag.to_string()]);
+        let q = diesel::update(
+            messages::table
+                .filter(messages::id.eq_any(ids))
+                .filter(dsl::not(has_tag)),
+        )
+        .set((
+            messages::tags.eq(array_append(messages::tags, tag.to_string())),
+            messages::updated_at.eq(clock.now().naive_utc()),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.execute(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(n) => Some(n),
+        }
+    }
+
+    /// Marks messages as deleted. They are not returned anymore, but the rows
+    /// are kept.
+    pub fn soft_delete_by_ids(
+        ids: &[String],
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<usize> {
+        let now = clock.now().naive_utc();
+        let q = diesel::update(
+            messages::table
+                .filter(messages::id.eq_any(ids))
+                .filter(messages::deleted_at.is_null()),
+        )
+        .set((
+            messages::deleted_at.eq(Some(now)),
+            messages::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.execute(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(n) => Some(n),
+        }
+    }
+
+    /// Save new message with the id (ULID, see `id::IdGenerator`).
+    ///
+    /// `created_at` and `updated_at` will be filled on PostgreSQL side
+    /// using timezone('utc'::text, now()).
+    pub fn insert(
+        message: &NewMessage,
+        id: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<String> {
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(messages::table)
+                .values((messages::id.eq(id), message))
+                .returning(messages::id);
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<String>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                println!("err: {}", e);
+                None
+            },
+            Ok(id) => Some(id),
+        }
+    }
+
+    /// Saves new message like `insert`, but its content is stored as a body
+    /// shared with the other messages of the same content (see
+    /// `model::message_body`).
+    pub fn insert_deduplicated(
+        mut message: NewMessage,
+        id: &str,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<String> {
+        let content = match message.content.take() {
+            Some(c) => c,
+            None => return Self::insert(&message, id, conn, logger),
+        };
+        let result = with_retry(logger, || {
+            conn.transaction::<_, diesel::result::Error, _>(|| {
+                let body_id =
+                    MessageBody::acquire(&content, clock, conn, logger)
+                        .ok_or(diesel::result::Error::RollbackTransaction)?;
+                let q = diesel::insert_into(messages::table)
+                    .values((
+                        messages::id.eq(id),
+                        messages::body_id.eq(body_id),
+                        &message,
+                    ))
+                    .returning(messages::id);
+                info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+                q.get_result::<String>(conn)
+            })
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(id) => Some(id),
+        }
+    }
+
+    /// Saves the row (e.g. from `service::stream_buffer`) unless a message of
+    /// the id exists already, and returns the number of the saved ones (0 or
+    /// 1). If `deduplicates` is true, its content is stored as a body like
+    /// `insert_deduplicated`.
+    pub fn insert_row(
+        mut row: MessageRow,
+        deduplicates: bool,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> QueryResult<usize> {
+        let content = if deduplicates {
+            row.message.content.take()
+        } else {
+            None
+        };
+        conn.transaction::<_, diesel::result::Error, _>(|| {
+            let q = diesel::insert_into(messages::table)
+                .values((
+                    messages::id.eq(&row.id),
+                    messages::created_at.eq(row.created_at),
+                    messages::updated_at.eq(row.created_at),
+                    &row.message,
+                ))
+                .on_conflict(messages::id)
+                .do_nothing();
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            let n = q.execute(conn)?;
+
+            if let (1, Some(c)) = (n, content) {
+                let body_id = MessageBody::acquire(&c, clock, conn, logger)
+                    .ok_or(diesel::result::Error::RollbackTransaction)?;
+                let q = diesel::update(messages::table.find(&row.id))
+                    .set(messages::body_id.eq(body_id));
+                info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+                q.execute(conn)?;
+            }
+            Ok(n)
+        })
+    }
+
+    /// Counts another occurrence of the message (e.g. a duplicate within the
+    /// deduplication window), and returns the number of them. This changes
+    /// `updated_at` too, so that an annotation based on the old one conflicts.
+    pub fn increment_occurrences(
+        id: &str,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<i32> {
+        let q = diesel::update(
+            messages::table
+                .filter(messages::id.eq(id))
+                .filter(messages::deleted_at.is_null()),
+        )
+        .set((
+            messages::occurrences_count.eq(messages::occurrences_count + 1),
+            messages::updated_at.eq(clock.now().naive_utc()),
+        ))
+        .returning(messages::occurrences_count);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<i32>(conn) {
+            Err(diesel::result::Error::NotFound) => None,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(n) => Some(n),
+        }
+    }
+
+    /// Saves new messages using COPY FROM STDIN (as CSV), which is much faster
+    /// than INSERTs for a large number of rows. Returns the number of them.
+    ///
+    /// diesel doesn't support COPY, so it needs a transaction of another
+    /// connection (`postgres` crate). The rows are saved on its commit.
+    pub fn copy_insert(
+        rows: &[MessageRow],
+        tx: &mut Transaction,
+        logger: &Logger,
+    ) -> Option<u64> {
+        if rows.is_empty() {
+            return Some(0);
+        }
+        let q = format!(
+            "COPY messages ({}) FROM STDIN WITH (FORMAT csv)",
+            COPY_COLUMNS.join(", ")
+        );
+        info!(logger, "{} -- rows: {}", q, rows.len());
+
+        let result = tx
+            .copy_in(q.as_str())
+            .map_err(|e| e.to_string())
+            .and_then(|mut writer| {
+                for row in rows {
+                    // the copy is aborted if the writer is dropped before
+                    // finish()
+                    writer
+                        .write_all(row.to_csv().as_bytes())
+                        .map_err(|e| e.to_string())?;
+                }
+                writer.finish().map_err(|e| e.to_string())
+            });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(n) => Some(n),
+        }
+    }
+
+    /// Update a message, only if it has not been changed since it was loaded
+    /// (optimistic concurrency by `updated_at`). The message gets the saved
+    /// values.
+    ///
+    /// It fails with "conflict" if the message has been updated (or removed)
+    /// in the meantime.
+    pub fn update(
+        message: &mut Message,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<(), &'static str> {
+        let version = message.updated_at;
+        message.updated_at = clock.now().naive_utc();
+        // the content of a body is not saved into the message (None is
+        // skipped in the changeset)
+        let content = match message.body_id {
+            Some(_) => message.content.take(),
+            None => None,
+        };
+        let result = with_retry(logger, || {
+            let q = diesel::update(
+                messages::table
+                    .filter(messages::id.eq(&message.id))
+                    .filter(messages::updated_at.eq(version))
+                    .filter(messages::deleted_at.is_null()),
+            )
+            .set(&*message);
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        let result = match result {
+            Ok(m) => {
+                *message = m;
+                Ok(())
+            },
+            Err(diesel::result::Error::NotFound) => {
+                message.updated_at = version;
+                Err("conflict")
+            },
+            Err(e) => {
+                message.updated_at = version;
+                error!(logger, "err: {}", e);
+                Err("failed to update message")
+            },
+        };
+        if content.is_some() {
+            message.content = content;
+        }
+        result
+    }
+
+    // FIXME: scope
+    pub fn visible() -> Visible {
+        messages::content
+            .is_not_null()
+            .or(messages::body_id.is_not_null())
+    }
+
+    // FIXME: scope
+    pub fn visible_to(user: &User) -> VisibleTo {
+        Self::all().filter(Self::with_user(user).and(Self::visible()))
+    }
+
+    pub fn with_type(agent_type: AgentType) -> WithType {
+        messages::agent_type.eq(agent_type)
+    }
+
+    pub fn with_user(user: &User) -> WithUser {
+        messages::agent_id
+            .eq(user.id)
+            .and(Self::with_type(AgentType::Person))
+    }
+}
+
+// escapes wildcards for LIKE (the default escape character is backslash)
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod data {
+    use super::*;
+
+    use chrono::{Utc, TimeZone};
+    use fnv::FnvHashMap;
+
+    use crate::fnvhashmap;
+    use crate::model::stream::data::STREAMS;
+
+    type MessageFixture = FnvHashMap<&'static str, Message>;
+
+    lazy_static! {
+        pub static ref MESSAGES: MessageFixture = fnvhashmap! {
+            "blank message" => Message {
+                id: "01DF5MVZWR0000000000000001".to_string(),
+                agent_id: 0,
+                agent_type: AgentType::Person,
+                stream_id: STREAMS.clone().get("weenie's stream").unwrap().id,
+                code: None,
+                lang: "en".to_string(),
+                level: LogLevel::Information,
+                format: LogFormat::TOML,
+                title: "title".to_string(),
+                content: None,
+                created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
+                updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
+                tags: vec![],
+                deleted_at: None,
+                acknowledged_at: None,
+                resolved_at: None,
+                body_id: None,
+                occurrences_count: 1,
+                trace_id: None,
+                span_id: None,
+                hostname: None,
+                service: None,
+                environment: None,
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::SystemClock;
+    use crate::id::{IdGenerator, RandomIdGenerator};
+    use crate::model::message::data::MESSAGES;
+    use crate::model::namespace::{Namespace, namespaces};
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::stream::{Stream, streams};
+    use crate::model::stream::data::STREAMS;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_insert() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(&s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let m = NewMessage {
+                agent_id: 1,
+                agent_type: AgentType::Person,
+                stream_id: stream.id,
+                code: None,
+                lang: "en".to_string(),
+                level: LogLevel::Information,
+                format: LogFormat::TOML,
+                title: Some("title".to_string()),
+                content: None,
+                trace_id: None,
+                span_id: None,
+                hostname: None,
+                service: None,
+                environment: None,
+                tags: vec![],
+            };
+            let id = RandomIdGenerator.ulid(Utc::now());
+            let result = Message::insert(&m, &id, conn, logger);
+            assert_eq!(result, Some(id));
+
+            let rows_count: i64 = messages::table
+                .count()
+                .first(conn)
+                .expect("Failed to count rows");
+            assert_eq!(1, rows_count);
+        })
+    }
+
+    #[test]
+    fn test_insert_deduplicated() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(&s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut ids = vec![];
+            for content in &["connection refused\r\n", "connection refused"] {
+                let m = NewMessage {
+                    agent_id: 1,
+                    stream_id: stream.id,
+                    title: Some("title".to_string()),
+                    content: Some(content.to_string()),
+
+                    ..Default::default()
+                };
+                let id = RandomIdGenerator.ulid(Utc::now());
+                let result = Message::insert_deduplicated(
+                    m,
+                    &id,
+                    &SystemClock,
+                    conn,
+                    logger,
+                );
+                assert_eq!(result, Some(id.clone()));
+                ids.push(id);
+            }
+
+            let references_counts = message_bodies::table
+                .select(message_bodies::references_count)
+                .load::<i64>(conn)
+                .expect("Failed to load");
+            assert_eq!(references_counts, vec![2]);
+
+            let count: i64 = messages::table
+                .filter(messages::content.is_null())
+                .filter(messages::body_id.is_not_null())
+                .count()
+                .first(conn)
+                .expect("Failed to count rows");
+            assert_eq!(count, 2);
+
+            let mut m =
+                Message::first_by_stream_id(&ids[0], stream.id, conn, logger)
+                    .unwrap();
+            assert_eq!(m.content, Some("connection refused".to_string()));
+
+            let options = SearchOptions::default();
+            let result = Message::search_by_stream_id(
+                stream.id,
+                "REFUSED",
+                &options,
+                0,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(2));
+
+            // the content stays in the body
+            m.title = "updated".to_string();
+            let result = Message::update(&mut m, &SystemClock, conn, logger);
+            assert!(result.is_ok());
+            assert_eq!(m.content, Some("connection refused".to_string()));
+
+            let content = messages::table
+                .select(messages::content)
+                .filter(messages::id.eq(&m.id))
+                .get_result::<Option<String>>(conn)
+                .expect("Failed to load");
+            assert_eq!(content, None);
+        })
+    }
+
+    #[test]
+    fn test_increment_occurrences() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(&s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let m = NewMessage {
+                agent_id: 1,
+                stream_id: stream.id,
+                title: Some("title".to_string()),
+
+                ..Default::default()
+            };
+            let id = RandomIdGenerator.ulid(Utc::now());
+            let _ = Message::insert(&m, &id, conn, logger).unwrap();
+
+            let n =
+                Message::increment_occurrences(&id, &SystemClock, conn, logger);
+            assert_eq!(n, Some(2));
+            let n =
+                Message::increment_occurrences(&id, &SystemClock, conn, logger);
+            assert_eq!(n, Some(3));
+
+            let unknown = RandomIdGenerator.ulid(Utc::now());
+            let n = Message::increment_occurrences(
+                &unknown,
+                &SystemClock,
+                conn,
+                logger,
+            );
+            assert_eq!(n, None);
+        })
+    }
+
+    #[test]
+    fn test_insert_row() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(&s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let created_at = Utc.ymd(2021, 6, 30).and_hms(9, 0, 0).naive_utc();
+            let id = RandomIdGenerator.ulid(Utc::now());
+            let row = || MessageRow {
+                id: id.clone(),
+                message: NewMessage {
+                    agent_id: 1,
+                    stream_id: stream.id,
+                    title: Some("title".to_string()),
+                    content: Some("content".to_string()),
+
+                    ..Default::default()
+                },
+                created_at,
+            };
+            let n =
+                Message::insert_row(row(), true, &SystemClock, conn, logger);
+            assert_eq!(n.unwrap(), 1);
+
+            // the same id is ignored (without another reference)
+            let n =
+                Message::insert_row(row(), true, &SystemClock, conn, logger);
+            assert_eq!(n.unwrap(), 0);
+
+            let references_counts = message_bodies::table
+                .select(message_bodies::references_count)
+                .load::<i64>(conn)
+                .expect("Failed to load");
+            assert_eq!(references_counts, vec![1]);
+
+            let m = Message::first_by_stream_id(&id, stream.id, conn, logger)
+                .unwrap();
+            assert_eq!(m.content, Some("content".to_string()));
+            assert_eq!(m.created_at, created_at);
+        })
+    }
+
+    #[test]
+    fn test_message_row_to_csv() {
+        let row = MessageRow {
+            id: "01F8HQZ3M00000000000000001".to_string(),
+            message: NewMessage {
+                agent_id: 1,
+                stream_id: 2,
+                title: Some(r#"a "quoted", title"#.to_string()),
+                content: Some("line\nbreak".to_string()),
+
+                ..Default::default()
+            },
+            created_at: Utc
+                .ymd(2021, 6, 1)
+                .and_hms_milli(1, 2, 3, 4)
+                .naive_utc(),
+        };
+        assert_eq!(
+            row.to_csv(),
+            concat!(
+                "01F8HQZ3M00000000000000001,",
+                r#"1,person,2,,"en",information,toml,"a ""quoted"", title","#,
+                "\"line\nbreak\",2021-06-01 01:02:03.004\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_find_all_by_stream_id() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut m = MESSAGES.get("blank message").unwrap().clone();
+            m.stream_id = stream.id;
+            let _ = diesel::insert_into(messages::table)
+                .values(m)
+                .execute(conn)
+                .unwrap_or_else(|e| panic!("Error inserting: {}", e));
+
+            let result = Message::find_all_by_stream_id(
+                stream.id, None, 0, 10, conn, logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(1));
+
+            let result = Message::find_all_by_stream_id(
+                stream.id,
+                Some(LogLevel::Debug),
+                0,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(0));
+
+            let result = Message::find_all_by_stream_id(
+                stream.id, None, 1, 10, conn, logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(0));
+        })
+    }
+
+    #[test]
+    fn test_find_all_by_trace_id() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+            for (i, t) in [Some(trace_id), None].iter().enumerate() {
+                let mut m = MESSAGES.get("blank message").unwrap().clone();
+                m.id = format!("01DF5MVZWR000000000000001{}", i);
+                m.stream_id = stream.id;
+                m.trace_id = t.map(|v| v.to_string());
+                m.span_id = t.map(|_| "00f067aa0ba902b7".to_string());
+                let _ = diesel::insert_into(messages::table)
+                    .values(m)
+                    .execute(conn)
+                    .unwrap_or_else(|e| panic!("Error inserting: {}", e));
+            }
+
+            let result = Message::find_all_by_trace_id(
+                namespace.id,
+                trace_id,
+                10,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(result.len(), 1);
+            assert_eq!(result[0].span_id.as_deref(), Some("00f067aa0ba902b7"));
+
+            // other namespaces
+            let result = Message::find_all_by_trace_id(
+                namespace.id + 1,
+                trace_id,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(0));
+        })
+    }
+
+    #[test]
+    fn test_fetch_by_stream_id_and_count_facets_by_search() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let sources = [
+                (Some("web-1"), Some("api"), Some("production")),
+                (Some("web-2"), Some("api"), Some("production")),
+                (Some("worker-1"), Some("worker"), Some("staging")),
+                (None, None, None),
+            ];
+            for (i, (hostname, service, environment)) in
+                sources.iter().enumerate()
+            {
+                let mut m = MESSAGES.get("blank message").unwrap().clone();
+                m.id = format!("01DF5MVZWR000000000000001{}", i);
+                m.stream_id = stream.id;
+                m.title = "Timeout".to_string();
+                m.hostname = hostname.map(|v| v.to_string());
+                m.service = service.map(|v| v.to_string());
+                m.environment = environment.map(|v| v.to_string());
+                let _ = diesel::insert_into(messages::table)
+                    .values(m)
+                    .execute(conn)
+                    .unwrap_or_else(|e| panic!("Error inserting: {}", e));
+            }
+
+            let fetch = |source: &SourceFilter| {
+                Message::fetch_by_stream_id(
+                    stream.id,
+                    source,
+                    0,
+                    10,
+                    conn,
+                    logger,
+                )
+                .map(|v| v.len())
+            };
+            assert_eq!(fetch(&SourceFilter::default()), Some(4));
+            let source = SourceFilter {
+                service: Some("api".to_string()),
+
+                ..Default::default()
+            };
+            assert_eq!(fetch(&source), Some(2));
+            let source = SourceFilter {
+                service: Some("api".to_string()),
+                environment: Some("staging".to_string()),
+
+                ..Default::default()
+            };
+            assert_eq!(fetch(&source), Some(0));
+
+            let options = SearchOptions::default();
+            let facets = Message::count_facets_by_search(
+                stream.id,
+                "timeout",
+                &options,
+                conn,
+                logger,
+            )
+            .unwrap();
+            let count = |value: &str, count: i64| FacetCount {
+                value: value.to_string(),
+                count,
+            };
+            assert_eq!(facets.hostname.len(), 3);
+            assert_eq!(
+                facets.service,
+                vec![count("api", 2), count("worker", 1)]
+            );
+            assert_eq!(
+                facets.environment,
+                vec![count("production", 2), count("staging", 1)]
+            );
+
+            let facets = Message::count_facets_by_search(
+                stream.id,
+                "unknown",
+                &options,
+                conn,
+                logger,
+            );
+            assert_eq!(facets, Some(Facets::default()));
+        })
+    }
+
+    #[test]
+    fn test_search_by_stream_id() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut m = MESSAGES.get("blank message").unwrap().clone();
+            m.stream_id = stream.id;
+            m.title = "Summer été".to_string();
+            m.content = Some("100% done".to_string());
+            let _ = diesel::insert_into(messages::table)
+                .values(m)
+                .execute(conn)
+                .unwrap_or_else(|e| panic!("Error inserting: {}", e));
+
+            let options = SearchOptions::default();
+            let result = Message::search_by_stream_id(
+                stream.id,
+                "SUMMER done",
+                &options,
+                0,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(1));
+
+            let result = Message::search_by_stream_id(
+                stream.id,
+                "été unknown",
+                &options,
+                0,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(0));
+
+            // wildcards are escaped
+            let result = Message::search_by_stream_id(
+                stream.id,
+                "0%",
+                &options,
+                0,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(1));
+
+            let result = Message::search_by_stream_id(
+                stream.id,
+                "_t_",
+                &options,
+                0,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(0));
+
+            // case sensitive
+            let options = SearchOptions {
+                case_sensitive: true,
+                unaccent: false,
+            };
+            let result = Message::search_by_stream_id(
+                stream.id,
+                "SUMMER",
+                &options,
+                0,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(0));
+
+            // unaccent
+            let options = SearchOptions {
+                case_sensitive: false,
+                unaccent: true,
+            };
+            let result = Message::search_by_stream_id(
+                stream.id,
+                "ETE",
+                &options,
+                0,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(1));
+
+            let options = SearchOptions {
+                case_sensitive: true,
+                unaccent: true,
+            };
+            let result = Message::search_by_stream_id(
+                stream.id,
+                "ETE",
+                &options,
+                0,
+                10,
+                conn,
+                logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(0));
+
+            let result = Message::search_by_stream_id(
+                stream.id, "ete", &options, 0, 10, conn, logger,
+            );
+            assert_eq!(result.map(|v| v.len()), Some(1));
+        })
+    }
+
+    #[test]
+    fn test_preview_retention() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let now = Utc::now().naive_utc();
+            let m = MESSAGES.get("blank message").unwrap().clone();
+            let messages = vec![
+                ("1", None, "2019-07-07T07:20:15", None),
+                ("2", Some("äb"), "2019-07-08T07:20:15", None),
+                ("3", None, "2019-07-09T07:20:15", None),
+                ("4", None, "2019-07-06T07:20:15", Some(now)),
+            ];
+            for (id, content, created_at, deleted_at) in messages {
+                let created_at = created_at.parse::<NaiveDateTime>().unwrap();
+                let m = Message {
+                    id: id.to_string(),
+                    stream_id: stream.id,
+                    content: content.map(|c| c.to_string()),
+                    created_at,
+                    deleted_at,
+
+                    ..m.clone()
+                };
+                let _ = diesel::insert_into(messages::table)
+                    .values(m)
+                    .execute(conn)
+                    .unwrap_or_else(|e| panic!("Error inserting: {}", e));
+            }
+
+            let before =
+                "2019-07-09T00:00:00".parse::<NaiveDateTime>().unwrap();
+            let result =
+                Message::preview_retention(namespace.id, before, conn, logger)
+                    .unwrap();
+            assert_eq!(result.messages_count, 2);
+            // "title" * 2 + "äb"
+            assert_eq!(result.bytes_count, 13);
+            assert_eq!(
+                result.oldest_created_at.map(|t| t.to_string()),
+                Some("2019-07-09 07:20:15".to_string())
+            );
+        })
+    }
+
+    #[test]
+    fn test_count_by_level_and_time() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("ball").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("weenie's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let now = Utc::now().naive_utc();
+            let m = MESSAGES.get("blank message").unwrap().clone();
+            let messages = vec![
+                ("1", LogLevel::Error, "2019-07-07T07:20:15", None),
+                ("2", LogLevel::Error, "2019-07-07T07:40:15", None),
+                ("3", LogLevel::Warning, "2019-07-07T07:50:15", None),
+                ("4", LogLevel::Error, "2019-07-07T09:20:15", None),
+                ("5", LogLevel::Error, "2019-07-07T09:30:15", Some(now)),
+                ("6", LogLevel::Error, "2019-07-08T00:00:00", None),
+            ];
+            for (id, level, created_at, deleted_at) in messages {
+                let created_at = created_at.parse::<NaiveDateTime>().unwrap();
+                let m = Message {
+                    id: id.to_string(),
+                    stream_id: stream.id,
+                    level,
+                    created_at,
+                    deleted_at,
+
+                    ..m.clone()
+                };
+                let _ = diesel::insert_into(messages::table)
+                    .values(m)
+                    .execute(conn)
+                    .unwrap_or_else(|e| panic!("Error inserting: {}", e));
+            }
+
+            let t = |s: &str| s.parse::<NaiveDateTime>().unwrap();
+            let from = t("2019-07-07T00:00:00");
+            let to = t("2019-07-08T00:00:00");
+
+            let result = Message::count_by_level_and_time(
+                namespace.id,
+                StatsInterval::Hour,
+                from,
+                to,
+                conn,
+                logger,
+            )
+            .unwrap();
+            let counts: Vec<(String, String, i64)> = result
+                .into_iter()
+                .map(|c| (c.time.to_string(), c.level, c.count))
+                .collect();
+            let expected: Vec<(String, String, i64)> = [
+                ("2019-07-07 07:00:00", "error", 2),
+                ("2019-07-07 07:00:00", "warning", 1),
+                ("2019-07-07 09:00:00", "error", 1),
+            ]
+            .iter()
+            .map(|(t, l, n)| (t.to_string(), l.to_string(), *n))
+            .collect();
+            assert_eq!(counts, expected);
+
+            let result = Message::count_by_level_and_time(
+                namespace.id,
+                StatsInterval::Day,
+                from,
+                to,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(result.len(), 2);
+            assert_eq!(result[0].count, 3);
+        })
+    }
+
+    #[test]
     fn test_update() {
         run(|conn, _, logger| {
             let ns = NAMESPACES.get("ball").unwrap();
@@ -416,17 +2123,28 @@ mod test {
                 title: "updated".to_string(),
                 content: Some("content".to_string()),
 
-                ..message
+                ..message.clone()
             };
-            let result = Message::update(&mut m, conn, logger);
-            assert!(result.is_some());
+            let result = Message::update(&mut m, &SystemClock, conn, logger);
+            assert!(result.is_ok());
+            assert_ne!(m.updated_at, message.updated_at);
 
             let title = messages::table
                 .select(messages::title)
-                .filter(messages::id.eq(m.id))
+                .filter(messages::id.eq(&m.id))
                 .get_result::<String>(conn)
                 .expect("Failed to load");
             assert_eq!(title, "updated");
+
+            // based on the old version
+            let mut m = Message {
+                title: "conflict".to_string(),
+
+                ..message
+            };
+            let result = Message::update(&mut m, &SystemClock, conn, logger);
+            assert_eq!(result, Err("conflict"));
+            assert_eq!(m.updated_at, message.updated_at);
         })
     }
 }
@@ -0,0 +1,199 @@
+//! # Message Body
+//!
+//! The content of messages in a namespace which deduplicates them
+//! (`namespaces.deduplicates_messages`). A normalized content is stored once
+//! by its digest (SHA-256), and messages refer to it by `body_id` (their own
+//! `content` is NULL). `references_count` is the number of messages which
+//! refer to the body, it's decremented when the messages are removed and the
+//! unreferenced bodies are removed too (see `service::namespace_purger`).
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+
+pub use crate::schema::message_bodies;
+
+use crate::clock::Clock;
+use crate::logger::Logger;
+use crate::model::message::Message;
+use crate::util::hash_token;
+
+/// MessageBody
+#[derive(Debug, Identifiable, PartialEq, Queryable)]
+#[table_name = "message_bodies"]
+pub struct MessageBody {
+    pub id: i64,
+    pub digest: String,
+    pub content: String,
+    pub references_count: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Normalizes the content for the deduplication. The line endings are LF and
+/// the trailing whitespace is removed.
+pub fn normalize(content: &str) -> String {
+    content.replace("\r\n", "\n").trim_end().to_string()
+}
+
+impl MessageBody {
+    /// Saves the (normalized) content, or increments the references of the
+    /// body of the same one. Returns the id of the body.
+    pub fn acquire(
+        content: &str,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<i64> {
+        let content = normalize(content);
+        let q = diesel::insert_into(message_bodies::table)
+            .values((
+                message_bodies::digest.eq(hash_token(&content)),
+                message_bodies::content.eq(&content),
+                message_bodies::references_count.eq(1),
+            ))
+            .on_conflict(message_bodies::digest)
+            .do_update()
+            .set((
+                message_bodies::references_count
+                    .eq(message_bodies::references_count + 1),
+                message_bodies::updated_at.eq(clock.now().naive_utc()),
+            ))
+            .returning(message_bodies::id);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<i64>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(id) => Some(id),
+        }
+    }
+
+    /// Decrements the references of the bodies by the ids (once for each
+    /// occurrence, e.g. the body ids of removed messages).
+    pub fn release(
+        ids: &[i64],
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> QueryResult<usize> {
+        let mut counts: HashMap<i64, i64> = HashMap::new();
+        for id in ids {
+            *counts.entry(*id).or_insert(0) += 1;
+        }
+        let now = clock.now().naive_utc();
+        let mut updated = 0;
+        for (id, n) in counts {
+            let q = diesel::update(message_bodies::table.find(id)).set((
+                message_bodies::references_count
+                    .eq(message_bodies::references_count - n),
+                message_bodies::updated_at.eq(now),
+            ));
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+            updated += q.execute(conn)?;
+        }
+        Ok(updated)
+    }
+
+    /// Removes the bodies which no message refers to, and returns the number
+    /// of them.
+    pub fn delete_unreferenced(
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> QueryResult<usize> {
+        let q = diesel::delete(
+            message_bodies::table
+                .filter(message_bodies::references_count.le(0)),
+        );
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        q.execute(conn)
+    }
+
+    /// Fills the content of the messages which refer to a body.
+    pub fn fill(
+        messages: &mut [Message],
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> QueryResult<()> {
+        let mut ids: Vec<i64> =
+            messages.iter().filter_map(|m| m.body_id).collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        ids.sort_unstable();
+        ids.dedup();
+
+        let q = message_bodies::table
+            .filter(message_bodies::id.eq_any(ids))
+            .select((message_bodies::id, message_bodies::content));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        let contents: HashMap<i64, String> =
+            q.load::<(i64, String)>(conn)?.into_iter().collect();
+        for m in messages.iter_mut() {
+            if let Some(id) = m.body_id {
+                m.content = contents.get(&id).cloned();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::clock::SystemClock;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("a\r\nb\r\n"), "a\nb");
+        assert_eq!(normalize("a \n\t"), "a");
+        assert_eq!(normalize(" a"), " a");
+    }
+
+    #[test]
+    fn test_acquire_and_release() {
+        run(|conn, _, logger| {
+            let clock = SystemClock;
+            let id =
+                MessageBody::acquire("text\r\n", &clock, conn, logger).unwrap();
+            let same = MessageBody::acquire("text", &clock, conn, logger);
+            assert_eq!(same, Some(id));
+            let other =
+                MessageBody::acquire("other", &clock, conn, logger).unwrap();
+            assert_ne!(other, id);
+
+            let body = message_bodies::table
+                .find(id)
+                .first::<MessageBody>(conn)
+                .unwrap();
+            assert_eq!(body.content, "text");
+            assert_eq!(body.references_count, 2);
+
+            let n = MessageBody::release(&[id, other], &clock, conn, logger);
+            assert_eq!(n.unwrap(), 2);
+            let n = MessageBody::delete_unreferenced(conn, logger);
+            assert_eq!(n.unwrap(), 1);
+
+            let n = MessageBody::release(&[id], &clock, conn, logger);
+            assert_eq!(n.unwrap(), 1);
+            let n = MessageBody::delete_unreferenced(conn, logger);
+            assert_eq!(n.unwrap(), 1);
+
+            let count: i64 =
+                message_bodies::table.count().get_result(conn).unwrap();
+            assert_eq!(count, 0);
+        });
+    }
+}
@@ -0,0 +1,405 @@
+//! # HourlyMessageCount
+//!
+//! Hourly counts of the messages in a namespace by the level, for stats over
+//! long ranges without GROUP BY on `messages`. They are aggregated from the
+//! messages of the past (whole) hours by `RollupMessageCounts` job
+//! incrementally, and the recent hours are aggregated again on each run for
+//! the late ones (e.g. from `service::stream_buffer`).
+//!
+//! Messages which are deleted or imported into the hours later are not
+//! reflected until the hours are aggregated again (see `aggregate`).
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::{Duration, NaiveDateTime};
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::dsl;
+use diesel::pg::{Pg, PgConnection};
+use diesel::result::Error;
+use diesel::sql_types::{BigInt, Text, Timestamp};
+
+pub use crate::schema::message_counts;
+
+use crate::logger::Logger;
+use crate::model::message::{
+    LogLevel, Message, MessageCount, StatsInterval, messages,
+};
+use crate::model::namespace::Namespace;
+
+// the hours which are aggregated again on each run
+const LOOKBACK_HOURS: i64 = 2;
+
+// hours per transaction
+const BATCH_HOURS: i64 = 24;
+
+/// HourlyMessageCount
+#[derive(Associations, Debug, Identifiable, Queryable)]
+#[belongs_to(Namespace)]
+#[table_name = "message_counts"]
+pub struct HourlyMessageCount {
+    pub id: i64,
+    pub namespace_id: i64,
+    pub hour: NaiveDateTime,
+    pub level: LogLevel,
+    pub count: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for HourlyMessageCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<HourlyMessageCount {hour}>", hour = &self.hour)
+    }
+}
+
+// the start of the first whole hour at or after the time
+fn ceil_hour(t: NaiveDateTime) -> NaiveDateTime {
+    let hour = StatsInterval::Hour.truncate(t);
+    if hour < t {
+        hour + Duration::hours(1)
+    } else {
+        hour
+    }
+}
+
+impl HourlyMessageCount {
+    /// Returns the end of the last hour which has been aggregated (or None if
+    /// nothing has been aggregated yet).
+    pub fn rolled_up_until(
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<Option<NaiveDateTime>, &'static str> {
+        let q = message_counts::table.select(dsl::max(message_counts::hour));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        q.first::<Option<NaiveDateTime>>(conn)
+            .map(|v| v.map(|t| t + Duration::hours(1)))
+            .map_err(|e| {
+                error!(logger, "err: {}", e);
+                "failed to load message counts"
+            })
+    }
+
+    /// Counts the messages in the hours `[from, to)` of all namespaces again,
+    /// and returns the number of the saved counts.
+    pub fn aggregate(
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<usize, &'static str> {
+        let (from, to) = (
+            StatsInterval::Hour.truncate(from),
+            StatsInterval::Hour.truncate(to),
+        );
+        conn.transaction::<_, Error, _>(|| {
+            let q = diesel::delete(
+                message_counts::table
+                    .filter(message_counts::hour.ge(from))
+                    .filter(message_counts::hour.lt(to)),
+            );
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.execute(conn)?;
+
+            let q = diesel::sql_query(
+                r#"
+INSERT INTO message_counts (namespace_id, hour, level, count)
+SELECT
+  s.namespace_id,
+  DATE_TRUNC('hour', m.created_at),
+  m.level,
+  COUNT(*)
+FROM messages m
+INNER JOIN streams s ON s.id = m.stream_id
+WHERE m.deleted_at IS NULL AND m.created_at >= $1 AND m.created_at < $2
+GROUP BY 1, 2, 3
+"#,
+            )
+            .bind::<Timestamp, _>(from)
+            .bind::<Timestamp, _>(to);
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.execute(conn)
+        })
+        .map_err(|e| {
+            error!(logger, "err: {}", e);
+            "failed to aggregate message counts"
+        })
+    }
+
+    /// Aggregates the hours after the last run (and the recent ones again)
+    /// until the hour of now, from the oldest message at the first time. This
+    /// returns the number of the saved counts.
+    pub fn roll_up(
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<usize, &'static str> {
+        let end = StatsInterval::Hour.truncate(now);
+        let start = match Self::rolled_up_until(conn, logger)? {
+            Some(t) => t.min(end) - Duration::hours(LOOKBACK_HOURS),
+            None => {
+                let q = messages::table.select(dsl::min(messages::created_at));
+                info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+                match q.first::<Option<NaiveDateTime>>(conn) {
+                    Ok(Some(t)) => StatsInterval::Hour.truncate(t),
+                    Ok(None) => return Ok(0),
+                    Err(e) => {
+                        error!(logger, "err: {}", e);
+                        return Err("failed to load messages");
+                    },
+                }
+            },
+        };
+
+        let mut count = 0;
+        let mut t = start;
+        while t < end {
+            let next = (t + Duration::hours(BATCH_HOURS)).min(end);
+            count += Self::aggregate(t, next, conn, logger)?;
+            t = next;
+        }
+        Ok(count)
+    }
+
+    /// Returns the number of the messages in the namespace like
+    /// `Message::count_by_level_and_time`, but the whole hours which have
+    /// been aggregated are read from the counts.
+    ///
+    /// The same bucket and level may appear more than once in the result.
+    pub fn count_by_level_and_time(
+        namespace_id: i64,
+        interval: StatsInterval,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<MessageCount>> {
+        let until = Self::rolled_up_until(conn, logger).ok()?;
+        let start = ceil_hour(from);
+        let end = until
+            .map_or(start, |t| t.min(StatsInterval::Hour.truncate(to)));
+        if start >= end {
+            return Message::count_by_level_and_time(
+                namespace_id,
+                interval,
+                from,
+                to,
+                conn,
+                logger,
+            );
+        }
+
+        let mut counts = vec![];
+        if from < start {
+            counts.extend(Message::count_by_level_and_time(
+                namespace_id,
+                interval,
+                from,
+                start,
+                conn,
+                logger,
+            )?);
+        }
+        counts.extend(Self::sum_by_level_and_time(
+            namespace_id,
+            interval,
+            start,
+            end,
+            conn,
+            logger,
+        )?);
+        if end < to {
+            counts.extend(Message::count_by_level_and_time(
+                namespace_id,
+                interval,
+                end,
+                to,
+                conn,
+                logger,
+            )?);
+        }
+        Some(counts)
+    }
+
+    /// Returns the number of the errors (`error` and `critical` messages) in
+    /// the namespace by the hour in `[from, to)`. Only the hours which have
+    /// been aggregated are read (see `service::anomaly_detector`), and the
+    /// ones without errors are not in the result.
+    pub fn count_errors_by_hour(
+        namespace_id: i64,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<BTreeMap<NaiveDateTime, i64>> {
+        let counts = Self::sum_by_level_and_time(
+            namespace_id,
+            StatsInterval::Hour,
+            from,
+            to,
+            conn,
+            logger,
+        )?;
+        let levels =
+            [LogLevel::Error.to_string(), LogLevel::Critical.to_string()];
+        let mut data = BTreeMap::new();
+        for c in counts.iter().filter(|c| levels.contains(&c.level)) {
+            *data.entry(c.time).or_insert(0) += c.count;
+        }
+        Some(data)
+    }
+
+    // sums up the counts in the hours `[from, to)` by the time bucket
+    fn sum_by_level_and_time(
+        namespace_id: i64,
+        interval: StatsInterval,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<MessageCount>> {
+        let q = diesel::sql_query(
+            r#"
+SELECT
+  DATE_TRUNC($2, c.hour) AS time,
+  c.level::TEXT AS level,
+  SUM(c.count)::BIGINT AS count
+FROM message_counts c
+WHERE c.namespace_id = $1 AND c.hour >= $3 AND c.hour < $4
+GROUP BY 1, 2
+ORDER BY 1, 2
+"#,
+        )
+        .bind::<BigInt, _>(namespace_id)
+        .bind::<Text, _>(interval.to_string())
+        .bind::<Timestamp, _>(from)
+        .bind::<Timestamp, _>(to);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<MessageCount>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::SystemClock;
+    use crate::model::message::{MessageRow, NewMessage};
+    use crate::model::namespace::{Namespace, namespaces};
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::stream::{Stream, streams};
+    use crate::model::stream::data::STREAMS;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_ceil_hour() {
+        let t = Utc.ymd(2021, 6, 30).and_hms(9, 0, 0).naive_utc();
+        assert_eq!(ceil_hour(t), t);
+        assert_eq!(
+            ceil_hour(t + Duration::seconds(1)),
+            t + Duration::hours(1)
+        );
+    }
+
+    #[test]
+    fn test_roll_up() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("piano").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+            s.namespace_id = namespace.id;
+            let stream = diesel::insert_into(streams::table)
+                .values(&s)
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let now = Utc.ymd(2021, 6, 30).and_hms(12, 30, 0).naive_utc();
+            assert_eq!(HourlyMessageCount::roll_up(now, conn, logger), Ok(0));
+
+            let times = [
+                (Duration::hours(50), LogLevel::Error),
+                (Duration::hours(50), LogLevel::Error),
+                (Duration::hours(26), LogLevel::Warning),
+                (Duration::hours(1), LogLevel::Error),
+                (Duration::minutes(10), LogLevel::Error),
+            ];
+            for (i, (ago, level)) in times.iter().enumerate() {
+                let row = MessageRow {
+                    id: format!("01F9DKJ8M0000000000000000{}", i),
+                    message: NewMessage {
+                        agent_id: 1,
+                        stream_id: stream.id,
+                        level: level.clone(),
+                        title: Some("title".to_string()),
+
+                        ..Default::default()
+                    },
+                    created_at: now - *ago,
+                };
+                let _ =
+                    Message::insert_row(row, false, &SystemClock, conn, logger);
+            }
+
+            // the current hour is not aggregated
+            assert_eq!(HourlyMessageCount::roll_up(now, conn, logger), Ok(3));
+            let until = HourlyMessageCount::rolled_up_until(conn, logger);
+            assert_eq!(
+                until,
+                Ok(Some(Utc.ymd(2021, 6, 30).and_hms(12, 0, 0).naive_utc()))
+            );
+
+            let counts = message_counts::table
+                .select(message_counts::count)
+                .order(message_counts::hour)
+                .load::<i64>(conn)
+                .expect("Failed to load");
+            assert_eq!(counts, vec![2, 1, 1]);
+
+            let from = now - Duration::days(3);
+            let sum = |counts: Vec<MessageCount>| -> i64 {
+                counts.iter().map(|c| c.count).sum()
+            };
+            let counts = HourlyMessageCount::count_by_level_and_time(
+                namespace.id,
+                StatsInterval::Day,
+                from,
+                now,
+                conn,
+                logger,
+            );
+            assert_eq!(counts.map(sum), Some(5));
+
+            // the warning is not an error
+            let errors = HourlyMessageCount::count_errors_by_hour(
+                namespace.id,
+                from,
+                now,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(
+                errors.into_iter().collect::<Vec<_>>(),
+                vec![
+                    (Utc.ymd(2021, 6, 28).and_hms(10, 0, 0).naive_utc(), 2),
+                    (Utc.ymd(2021, 6, 30).and_hms(11, 0, 0).naive_utc(), 1),
+                ]
+            );
+        });
+    }
+}
@@ -0,0 +1,256 @@
+//! # Filter expression for messages
+//!
+//! A filter is a list of conditions separated by whitespace, and messages
+//! must match all of them. A condition is `<field>:<value>` or a term which
+//! is matched against the title and content (case-insensitive). Double quotes
+//! can be used for a term or a value which contains whitespace.
+//!
+//! | field    | value                                    |
+//! |----------|------------------------------------------|
+//! | `level`  | log level (any of them if repeated)      |
+//! | `code`   | code (any of them if repeated)           |
+//! | `tag`    | tag (all of them if repeated)            |
+//! | `trace`  | trace id (any of them if repeated)       |
+//! | `after`  | `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SS`    |
+//! | `before` | `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SS`    |
+//!
+//! e.g. `level:error tag:db "connection timeout" after:2021-06-01`
+use std::fmt;
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::model::log_level::LogLevel;
+use crate::tracecontext::is_trace_id;
+
+#[derive(Debug, PartialEq)]
+pub enum FilterError {
+    Empty,
+    InvalidValue(String),
+    UnclosedQuote,
+    UnknownField(String),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Must not be empty"),
+            Self::InvalidValue(s) => write!(f, "Invalid value: {}", s),
+            Self::UnclosedQuote => write!(f, "Quote is not closed"),
+            Self::UnknownField(s) => write!(f, "Unknown field: {}", s),
+        }
+    }
+}
+
+/// MessageFilter
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessageFilter {
+    pub terms: Vec<String>,
+    pub levels: Vec<LogLevel>,
+    pub codes: Vec<String>,
+    pub tags: Vec<String>,
+    pub trace_ids: Vec<String>,
+    pub after: Option<NaiveDateTime>,
+    pub before: Option<NaiveDateTime>,
+}
+
+// splits the expression by whitespace (except in double quotes)
+fn tokenize(s: &str) -> Result<Vec<String>, FilterError> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(token);
+                    token = String::new();
+                }
+            },
+            c => token.push(c),
+        }
+    }
+    if quoted {
+        return Err(FilterError::UnclosedQuote);
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_datetime(s: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .map(|d| d.and_hms(0, 0, 0))
+        })
+}
+
+impl MessageFilter {
+    pub fn parse(s: &str) -> Result<Self, FilterError> {
+        let mut filter = Self::default();
+        for token in tokenize(s)? {
+            let (field, value) = match token.find(':') {
+                Some(i) => (&token[..i], &token[i + 1..]),
+                None => {
+                    filter.terms.push(token.to_string());
+                    continue;
+                },
+            };
+            let invalid = || FilterError::InvalidValue(token.to_string());
+            if value.is_empty() {
+                return Err(invalid());
+            }
+            match field {
+                "level" => {
+                    let level = LogLevel::iter()
+                        .find(|l| l.to_string() == value)
+                        .cloned()
+                        .ok_or_else(invalid)?;
+                    filter.levels.push(level);
+                },
+                "code" => filter.codes.push(value.to_string()),
+                "tag" => filter.tags.push(value.to_string()),
+                "trace" if is_trace_id(value) => {
+                    filter.trace_ids.push(value.to_string())
+                },
+                "trace" => return Err(invalid()),
+                "after" => {
+                    filter.after =
+                        Some(parse_datetime(value).ok_or_else(invalid)?)
+                },
+                "before" => {
+                    filter.before =
+                        Some(parse_datetime(value).ok_or_else(invalid)?)
+                },
+                _ => return Err(FilterError::UnknownField(field.to_string())),
+            }
+        }
+        if filter == Self::default() {
+            return Err(FilterError::Empty);
+        }
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse() {
+        let filter = MessageFilter::parse(
+            r#"level:error level:critical tag:db "connection timeout" pool"#,
+        )
+        .unwrap();
+        assert_eq!(filter.levels, vec![LogLevel::Error, LogLevel::Critical]);
+        assert_eq!(filter.tags, vec!["db".to_string()]);
+        assert_eq!(
+            filter.terms,
+            vec!["connection timeout".to_string(), "pool".to_string()]
+        );
+        assert!(filter.codes.is_empty());
+
+        let filter = MessageFilter::parse(
+            "code:E001 after:2021-06-01 before:2021-06-02T03:04:05",
+        )
+        .unwrap();
+        assert_eq!(filter.codes, vec!["E001".to_string()]);
+        assert_eq!(
+            filter.after,
+            Some(NaiveDate::from_ymd(2021, 6, 1).and_hms(0, 0, 0))
+        );
+        assert_eq!(
+            filter.before,
+            Some(NaiveDate::from_ymd(2021, 6, 2).and_hms(3, 4, 5))
+        );
+
+        let filter = MessageFilter::parse(r#"tag:"on call""#).unwrap();
+        assert_eq!(filter.tags, vec!["on call".to_string()]);
+
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let filter =
+            MessageFilter::parse(&format!("trace:{} timeout", trace_id))
+                .unwrap();
+        assert_eq!(filter.trace_ids, vec![trace_id.to_string()]);
+        assert_eq!(filter.terms, vec!["timeout".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(MessageFilter::parse(""), Err(FilterError::Empty));
+        assert_eq!(MessageFilter::parse(r#" "" "#), Err(FilterError::Empty));
+        assert_eq!(
+            MessageFilter::parse("level:unknown"),
+            Err(FilterError::InvalidValue("level:unknown".to_string()))
+        );
+        assert_eq!(
+            MessageFilter::parse("tag:"),
+            Err(FilterError::InvalidValue("tag:".to_string()))
+        );
+        assert_eq!(
+            MessageFilter::parse("trace:00f067aa0ba902b7"),
+            Err(FilterError::InvalidValue(
+                "trace:00f067aa0ba902b7".to_string()
+            ))
+        );
+        assert_eq!(
+            MessageFilter::parse("after:yesterday"),
+            Err(FilterError::InvalidValue("after:yesterday".to_string()))
+        );
+        assert_eq!(
+            MessageFilter::parse("agent:1"),
+            Err(FilterError::UnknownField("agent".to_string()))
+        );
+        assert_eq!(
+            MessageFilter::parse(r#""timeout"#),
+            Err(FilterError::UnclosedQuote)
+        );
+    }
+
+    // an alphanumeric value which may contain whitespace
+    const VALUE: &str = "[A-Za-z0-9][A-Za-z0-9 ]{0,15}";
+
+    // the filter comes from users as is (query string), so any input must be
+    // rejected with an error instead of panics
+    proptest! {
+        #[test]
+        fn test_parse_does_not_panic(s in "\\PC*") {
+            let _ = MessageFilter::parse(&s);
+        }
+
+        #[test]
+        fn test_parse_does_not_panic_with_fields(
+            s in r#"((level|code|tag|trace|after|before|"|:| |[0-9T-])+)"#,
+        ) {
+            let _ = MessageFilter::parse(&s);
+        }
+
+        #[test]
+        fn test_parse_conditions(
+            levels in prop::collection::vec(0..5usize, 0..3),
+            tags in prop::collection::vec(VALUE, 0..3),
+            terms in prop::collection::vec(VALUE, 1..3),
+        ) {
+            let levels: Vec<LogLevel> = levels
+                .iter()
+                .map(|i| LogLevel::iter().nth(*i).cloned().unwrap())
+                .collect();
+            let mut conditions: Vec<String> =
+                levels.iter().map(|l| format!("level:{}", l)).collect();
+            conditions.extend(tags.iter().map(|t| format!("tag:\"{}\"", t)));
+            conditions.extend(terms.iter().map(|t| format!("\"{}\"", t)));
+
+            let filter = MessageFilter::parse(&conditions.join(" ")).unwrap();
+            // whitespace in quotes is kept as is
+            prop_assert_eq!(filter.levels, levels);
+            prop_assert_eq!(filter.tags, tags);
+            prop_assert_eq!(filter.terms, terms);
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! # Protocol Buffers representation of messages
+//!
+//! These are the same as proto/message.proto, with prost derives instead of
+//! generated code. The default content type for ingestion is still JSON, but
+//! `application/x-protobuf` costs less at high ingest rates.
+use prost::Message as _;
+use serde::{Deserialize, Serialize};
+
+use crate::model::message::Message as MessageModel;
+use crate::request::message::Message as RequestData;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+#[derive(Clone, Deserialize, PartialEq, Serialize, prost::Message)]
+pub struct NewMessage {
+    #[prost(int64, tag = "1")]
+    pub agent_id: i64,
+    #[prost(string, optional, tag = "2")]
+    pub agent_type: Option<String>,
+    #[prost(int64, tag = "3")]
+    pub stream_id: i64,
+    #[prost(string, optional, tag = "4")]
+    pub code: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub lang: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub level: Option<String>,
+    #[prost(string, optional, tag = "7")]
+    pub format: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    pub title: Option<String>,
+    #[prost(string, optional, tag = "9")]
+    pub content: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub fingerprint: Option<String>,
+    #[prost(string, optional, tag = "11")]
+    pub traceparent: Option<String>,
+    #[prost(string, optional, tag = "12")]
+    pub hostname: Option<String>,
+    #[prost(string, optional, tag = "13")]
+    pub service: Option<String>,
+    #[prost(string, optional, tag = "14")]
+    pub environment: Option<String>,
+}
+
+#[derive(Clone, Deserialize, PartialEq, Serialize, prost::Message)]
+pub struct Message {
+    /// ULID
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(int64, tag = "2")]
+    pub agent_id: i64,
+    #[prost(string, tag = "3")]
+    pub agent_type: String,
+    #[prost(int64, tag = "4")]
+    pub stream_id: i64,
+    #[prost(string, optional, tag = "5")]
+    pub code: Option<String>,
+    #[prost(string, tag = "6")]
+    pub lang: String,
+    #[prost(string, tag = "7")]
+    pub level: String,
+    #[prost(string, tag = "8")]
+    pub format: String,
+    #[prost(string, tag = "9")]
+    pub title: String,
+    #[prost(string, optional, tag = "10")]
+    pub content: Option<String>,
+    #[prost(string, tag = "11")]
+    pub created_at: String,
+    #[prost(string, tag = "12")]
+    pub updated_at: String,
+    #[prost(int32, tag = "13")]
+    pub occurrences_count: i32,
+    #[prost(string, optional, tag = "14")]
+    pub trace_id: Option<String>,
+    #[prost(string, optional, tag = "15")]
+    pub span_id: Option<String>,
+    #[prost(string, optional, tag = "16")]
+    pub hostname: Option<String>,
+    #[prost(string, optional, tag = "17")]
+    pub service: Option<String>,
+    #[prost(string, optional, tag = "18")]
+    pub environment: Option<String>,
+}
+
+impl Message {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+}
+
+// into the request data for validation (same as JSON)
+impl From<NewMessage> for RequestData {
+    fn from(m: NewMessage) -> Self {
+        Self {
+            agent_id: m.agent_id,
+            agent_type: m.agent_type,
+            stream_id: m.stream_id,
+            code: m.code,
+            lang: m.lang,
+            level: m.level,
+            format: m.format,
+            title: m.title,
+            content: m.content,
+            fingerprint: m.fingerprint,
+            traceparent: m.traceparent,
+            hostname: m.hostname,
+            service: m.service,
+            environment: m.environment,
+        }
+    }
+}
+
+impl From<&MessageModel> for Message {
+    fn from(m: &MessageModel) -> Self {
+        Self {
+            id: m.id.clone(),
+            agent_id: m.agent_id,
+            agent_type: m.agent_type.to_string(),
+            stream_id: m.stream_id,
+            code: m.code.clone(),
+            lang: m.lang.clone(),
+            level: m.level.to_string(),
+            format: m.format.to_string(),
+            title: m.title.clone(),
+            content: m.content.clone(),
+            created_at: m.created_at.format(TIMESTAMP_FORMAT).to_string(),
+            updated_at: m.updated_at.format(TIMESTAMP_FORMAT).to_string(),
+            occurrences_count: m.occurrences_count,
+            trace_id: m.trace_id.clone(),
+            span_id: m.span_id.clone(),
+            hostname: m.hostname.clone(),
+            service: m.service.clone(),
+            environment: m.environment.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+    use proptest::prelude::*;
+
+    use crate::model::message::{AgentType, LogFormat, LogLevel};
+
+    #[test]
+    fn test_new_message_decode() {
+        let m = NewMessage {
+            agent_id: 1,
+            agent_type: Some("person".to_string()),
+            stream_id: 2,
+            code: None,
+            lang: Some("en".to_string()),
+            level: Some("error".to_string()),
+            format: None,
+            title: Some("title".to_string()),
+            content: Some("content".to_string()),
+            fingerprint: None,
+            traceparent: None,
+            hostname: None,
+            service: None,
+            environment: None,
+        };
+        let buf = m.encode_to_vec();
+        let decoded = NewMessage::decode(&buf[..]).unwrap();
+        assert_eq!(decoded, m);
+
+        let data = RequestData::from(decoded);
+        assert_eq!(data.agent_id, 1);
+        assert_eq!(data.stream_id, 2);
+        assert_eq!(data.code, None);
+        assert_eq!(data.level, Some("error".to_string()));
+        assert_eq!(data.title, Some("title".to_string()));
+    }
+
+    #[test]
+    fn test_new_message_decode_invalid_bytes() {
+        assert!(NewMessage::decode(&b"\xff\xff\xff"[..]).is_err());
+    }
+
+    #[test]
+    fn test_message_from_model() {
+        let dt = Utc.ymd(2019, 8, 7).and_hms_milli(6, 5, 4, 333).naive_utc();
+        let model = MessageModel {
+            id: "01DHNAWKYD0000000000000003".to_string(),
+            agent_id: 1,
+            agent_type: AgentType::Person,
+            stream_id: 2,
+            code: Some("E001".to_string()),
+            lang: "en".to_string(),
+            level: LogLevel::Warning,
+            format: LogFormat::TOML,
+            title: "title".to_string(),
+            content: None,
+            created_at: dt,
+            updated_at: dt,
+            tags: vec![],
+            deleted_at: None,
+            acknowledged_at: None,
+            resolved_at: None,
+            body_id: None,
+            occurrences_count: 1,
+            trace_id: None,
+            span_id: None,
+            hostname: None,
+            service: None,
+            environment: None,
+        };
+
+        let m = Message::from(&model);
+        assert_eq!(m.id, "01DHNAWKYD0000000000000003");
+        assert_eq!(m.agent_type, "person");
+        assert_eq!(m.level, "warning");
+        assert_eq!(m.format, "toml");
+        assert_eq!(m.created_at, "2019-08-07T06:05:04.333");
+
+        let decoded = Message::decode(&m.to_bytes()[..]).unwrap();
+        assert_eq!(decoded, m);
+
+        // serde
+        let json = serde_json::to_string(&m).unwrap();
+        let m: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(m, decoded);
+    }
+
+    proptest! {
+        #[test]
+        fn test_new_message_decode_any_bytes(
+            buf in prop::collection::vec(any::<u8>(), 0..512),
+        ) {
+            if let Ok(m) = NewMessage::decode(&buf[..]) {
+                let _ = RequestData::from(m);
+            }
+        }
+
+        #[test]
+        fn test_new_message_decode_roundtrip(
+            agent_id in any::<i64>(),
+            stream_id in any::<i64>(),
+            title in proptest::option::of("\\PC{0,64}"),
+            content in proptest::option::of("\\PC{0,256}"),
+        ) {
+            let m = NewMessage {
+                agent_id,
+                stream_id,
+                title,
+                content,
+
+                ..Default::default()
+            };
+            let buf = m.encode_to_vec();
+            prop_assert_eq!(NewMessage::decode(&buf[..]).unwrap(), m);
+        }
+    }
+}
@@ -3,31 +3,65 @@
 //! SQL types are imported publicly in each model entities.
 
 // sql types
+mod access_token_scope;
 mod access_token_state;
 mod agent_type;
+mod anomaly_sensitivity;
+mod bulk_operation_action;
+mod bulk_operation_state;
+mod channel_kind;
+mod identity_provider;
+mod incident_state;
 mod log_level;
 mod log_format;
 mod membership_role;
+mod message_filter;
+mod message_proto;
+mod notification_channel;
+mod notification_event;
+mod push_platform;
+mod recent_view_kind;
+mod stats_interval;
 mod user_email_identification_state;
 mod user_email_role;
 mod user_reset_password_state;
 mod user_state;
 
 // non-persistent (deciduous) entities
+pub mod password_hash;
 pub mod token;
 
 // models
 pub mod access_token;
+pub mod alert_schedule;
+pub mod analytics_event;
+pub mod bulk_operation;
+pub mod channel;
+pub mod external_id;
+pub mod identity;
+pub mod incident;
+pub mod incident_message;
 pub mod message;
+pub mod message_body;
+pub mod message_count;
 pub mod membership;
 pub mod namespace;
+pub mod namespace_usage;
+pub mod notification_preference;
+pub mod push_delivery;
+pub mod push_device;
+pub mod recent_view;
 pub mod stream;
+pub mod stream_token;
+pub mod usage_record;
 pub mod user;
 pub mod user_email;
+pub mod waitlist_entry;
 
 use diesel::pg::PgConnection;
 
 use crate::logger::Logger;
+use crate::model::password_hash::PasswordHashParams;
 
 // Note
 //
@@ -61,6 +95,7 @@ pub trait Authenticatable {
     fn update_password(
         &mut self,
         new_password: &str,
+        params: &PasswordHashParams,
         conn: &PgConnection,
         logger: &Logger,
     ) -> Result<(), &'static str>;
@@ -105,8 +140,8 @@ pub mod test {
     lazy_static! {
         static ref DB_POOL_HOLDER: DbPoolHolder = {
             init_pool_holder(
-                &CONFIG.database_url,
-                CONFIG.database_max_pool_size,
+                &CONFIG.database_connection_url(),
+                &CONFIG.database_pool(),
             )
         };
     }
@@ -150,9 +185,26 @@ pub mod test {
             "users",
             "user_emails",
             "access_tokens",
+            "alert_schedules",
+            "analytics_events",
+            "bulk_operations",
+            "channels",
+            "identities",
+            "incidents",
+            "incident_messages",
             "messages",
+            "message_bodies",
+            "message_counts",
             "namespaces",
+            "namespace_usages",
+            "notification_preferences",
+            "push_deliveries",
+            "push_devices",
+            "recent_views",
             "streams",
+            "stream_tokens",
+            "usage_records",
+            "waitlist_entries",
         ]
         .join(", ");
         let q = format!("TRUNCATE TABLE {} RESTART IDENTITY CASCADE;", tables);
@@ -6,14 +6,20 @@ use chrono::NaiveDateTime;
 use diesel::{Identifiable, Insertable, Queryable, debug_query, prelude::*};
 use diesel::dsl;
 use diesel::pg::{Pg, PgConnection};
+use diesel::sql_types::{BigInt, Nullable, Timestamp};
 use serde::Serialize;
 use uuid::Uuid;
 
+use crate::db::with_retry;
 use crate::logger::Logger;
 use crate::request::namespace::Namespace as RequestData;
+use crate::model::external_id::ExternalId;
+use crate::model::log_level::LogLevel;
 use crate::model::membership::{Membership, memberships};
+use crate::model::stream::streams;
 use crate::model::user::User;
 
+pub use crate::model::anomaly_sensitivity::*;
 pub use crate::schema::namespaces;
 
 /// NewNamespace
@@ -60,6 +66,11 @@ type AllColumns = (
     namespaces::archived_at,
     namespaces::created_at,
     namespaces::updated_at,
+    namespaces::deduplicates_messages,
+    namespaces::min_level,
+    namespaces::default_tags,
+    namespaces::anomaly_sensitivity,
+    namespaces::badge_public,
 );
 
 const ALL_COLUMNS: AllColumns = (
@@ -71,6 +82,11 @@ const ALL_COLUMNS: AllColumns = (
     namespaces::archived_at,
     namespaces::created_at,
     namespaces::updated_at,
+    namespaces::deduplicates_messages,
+    namespaces::min_level,
+    namespaces::default_tags,
+    namespaces::anomaly_sensitivity,
+    namespaces::badge_public,
 );
 
 /// Namespace
@@ -97,6 +113,22 @@ pub struct Namespace {
     pub archived_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// Whether the bodies of messages are stored once (see
+    /// `model::message_body`).
+    #[serde(skip)]
+    pub deduplicates_messages: bool,
+    /// Messages below the level are not saved on ingestion (see
+    /// `accepts`).
+    pub min_level: Option<LogLevel>,
+    /// Tags merged into every message ingested into the namespace (see
+    /// `merge_default_tags`).
+    pub default_tags: Vec<String>,
+    /// How significant a spike of errors must be to be alerted (see
+    /// `service::anomaly_detector`).
+    pub anomaly_sensitivity: AnomalySensitivity,
+    /// Whether the status badge is shown without the token (see
+    /// `service::badge`).
+    pub badge_public: bool,
 }
 
 mod uuid_as_string {
@@ -117,6 +149,8 @@ impl Clone for Namespace {
             description: self.description.clone(),
             streams_count: self.streams_count,
             archived_at: None,
+            min_level: self.min_level.clone(),
+            default_tags: self.default_tags.clone(),
 
             ..*self
         }
@@ -135,7 +169,10 @@ type VisibleTo = dsl::Filter<
     dsl::InnerJoin<All, memberships::table>,
     dsl::And<crate::model::membership::WithUser, Visible>,
 >;
-type WithUuid = dsl::Eq<namespaces::uuid, Uuid>;
+type WithKey = dsl::Or<
+    dsl::Eq<namespaces::uuid, Uuid>,
+    dsl::Eq<namespaces::id, i64>,
+>;
 
 impl Namespace {
     pub fn all() -> All {
@@ -164,6 +201,36 @@ impl Namespace {
         }
     }
 
+    /// Returns the latest updated_at and the number of namespaces visible to
+    /// the user. It's used as an entity tag for the list.
+    pub fn version(
+        user: &User,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<(Option<NaiveDateTime>, i64)> {
+        if user.id < 1 {
+            return None;
+        }
+
+        let q = namespaces::table
+            .inner_join(memberships::table)
+            .filter(Membership::with_user(user).and(Self::visible()))
+            // aggregates can't be mixed in a tuple (diesel 1.4)
+            .select(dsl::sql::<(Nullable<Timestamp>, BigInt)>(
+                "max(namespaces.updated_at), count(*)",
+            ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<(Option<NaiveDateTime>, i64)>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
     pub fn find_by_uuid(
         uuid: &str,
         user: &User,
@@ -173,9 +240,53 @@ impl Namespace {
         if user.id < 1 {
             return None;
         }
+        let key = ExternalId::parse_for("namespace", uuid, logger)?;
 
         let q = Self::visible_to(&user)
-            .filter(Self::with_uuid(uuid))
+            .filter(Self::with_key(key))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+        }
+    }
+
+    /// Finds a namespace by its key (uuid) regardless of memberships.
+    ///
+    /// This is only for internal use (e.g. jobs).
+    pub fn find_by_id(
+        id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = Self::all().filter(namespaces::id.eq(id)).limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+        }
+    }
+
+    /// Finds the namespace of the stream (for internal use).
+    pub fn find_by_stream_id(
+        stream_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = Self::all()
+            .inner_join(streams::table)
+            .filter(streams::id.eq(stream_id))
             .limit(1);
 
         info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
@@ -189,16 +300,112 @@ impl Namespace {
         }
     }
 
+    pub fn find_by_key(
+        key: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let key = ExternalId::parse_for("namespace", key, logger)?;
+        let q = Self::all().filter(Self::with_key(key)).limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+        }
+    }
+
+    /// Finds a deleted namespace which the user is a member of.
+    pub fn find_archived_by_uuid(
+        uuid: &str,
+        user: &User,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        if user.id < 1 {
+            return None;
+        }
+        let key = ExternalId::parse_for("namespace", uuid, logger)?;
+
+        let q = Self::all()
+            .inner_join(memberships::table)
+            .filter(Membership::with_user(user))
+            .filter(namespaces::archived_at.is_not_null())
+            .filter(Self::with_key(key))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+        }
+    }
+
+    /// Returns namespaces deleted before the time (to be purged).
+    pub fn find_all_archived_before(
+        before: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = Self::all()
+            .filter(namespaces::archived_at.lt(before))
+            .order(namespaces::archived_at.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
     pub fn insert(
         namespace: &NewNamespace,
         conn: &PgConnection,
         logger: &Logger,
     ) -> Option<Self> {
         let uuid = Uuid::new_v4();
-        let q = diesel::insert_into(namespaces::table).values((
-            namespaces::uuid.eq(uuid),
-            namespaces::name.eq(&namespace.name),
-            namespaces::description.eq(&namespace.description),
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(namespaces::table).values((
+                namespaces::uuid.eq(uuid),
+                namespaces::name.eq(&namespace.name),
+                namespaces::description.eq(&namespace.description),
+            ));
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(u) => Some(u),
+        }
+    }
+
+    /// Deletes the namespace softly. It's hidden until it's restored.
+    pub fn archive(
+        &self,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::update(self).set((
+            namespaces::archived_at.eq(Some(now)),
+            namespaces::updated_at.eq(now),
         ));
 
         info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
@@ -208,13 +415,197 @@ impl Namespace {
                 error!(logger, "err: {}", e);
                 None
             },
-            Ok(u) => Some(u),
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn restore(
+        &self,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::update(self).set((
+            namespaces::archived_at.eq(None::<NaiveDateTime>),
+            namespaces::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Enables (or disables) the deduplication of message bodies. It applies
+    /// only to messages saved after the change.
+    pub fn set_deduplicates_messages(
+        &self,
+        value: bool,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::update(self).set((
+            namespaces::deduplicates_messages.eq(value),
+            namespaces::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Sets (or removes) the minimum level of messages to be saved. It
+    /// applies only to messages ingested after the change.
+    pub fn set_min_level(
+        &self,
+        value: Option<LogLevel>,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::update(self).set((
+            namespaces::min_level.eq(value),
+            namespaces::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Whether a message of the level is saved on ingestion.
+    pub fn accepts(&self, level: &LogLevel) -> bool {
+        self.min_level.as_ref().map_or(true, |min| level >= min)
+    }
+
+    /// Replaces the default tags of messages. It applies only to messages
+    /// ingested after the change.
+    pub fn set_default_tags(
+        &self,
+        value: Vec<String>,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::update(self).set((
+            namespaces::default_tags.eq(value),
+            namespaces::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Changes the sensitivity of the anomaly detection. It applies from the
+    /// next run of `DetectAnomalies` job.
+    pub fn set_anomaly_sensitivity(
+        &self,
+        value: AnomalySensitivity,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::update(self).set((
+            namespaces::anomaly_sensitivity.eq(value),
+            namespaces::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Changes whether the status badge is shown without the token.
+    pub fn set_badge_public(
+        &self,
+        value: bool,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::update(self).set((
+            namespaces::badge_public.eq(value),
+            namespaces::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Returns the namespaces (not deleted) where anomalies are detected.
+    ///
+    /// This is only for internal use (e.g. jobs).
+    pub fn find_all_with_anomaly_detection(
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = Self::all()
+            .filter(namespaces::archived_at.is_null())
+            .filter(namespaces::anomaly_sensitivity.ne(AnomalySensitivity::Off))
+            .order(namespaces::id.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
         }
     }
 
-    pub fn with_uuid(s: &str) -> WithUuid {
-        let uuid = Uuid::parse_str(s).unwrap_or_else(|_| Uuid::nil());
-        namespaces::uuid.eq(uuid)
+    /// Adds the default tags which the message doesn't have yet.
+    pub fn merge_default_tags(&self, tags: &mut Vec<String>) {
+        for tag in &self.default_tags {
+            if !tags.contains(tag) {
+                tags.push(tag.to_string());
+            }
+        }
+    }
+
+    /// Matches the uuid (or the id, deprecated) of the key.
+    pub fn with_key(key: ExternalId) -> WithKey {
+        let (uuid, id) = key.to_pair();
+        namespaces::uuid.eq(uuid).or(namespaces::id.eq(id))
     }
 
     pub fn visible() -> Visible {
@@ -250,6 +641,11 @@ pub mod data {
                 archived_at: None,
                 created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
                 updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
+                deduplicates_messages: false,
+                min_level: None,
+                default_tags: vec![],
+                anomaly_sensitivity: AnomalySensitivity::Medium,
+                badge_public: false,
             },
             "ball" => Namespace {
                 id: 2,
@@ -260,6 +656,11 @@ pub mod data {
                 archived_at: None,
                 created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
                 updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
+                deduplicates_messages: false,
+                min_level: None,
+                default_tags: vec![],
+                anomaly_sensitivity: AnomalySensitivity::Medium,
+                badge_public: false,
             },
             "fish" => Namespace {
                 id: 3,
@@ -270,6 +671,11 @@ pub mod data {
                 archived_at: None,
                 created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
                 updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
+                deduplicates_messages: false,
+                min_level: None,
+                default_tags: vec![],
+                anomaly_sensitivity: AnomalySensitivity::Medium,
+                badge_public: false,
             }
         };
     }
@@ -279,6 +685,8 @@ pub mod data {
 mod test {
     use super::*;
 
+    use chrono::{Duration, TimeZone, Utc};
+
     use crate::model::membership::{Membership, memberships};
     use crate::model::user::{User, users};
 
@@ -342,6 +750,35 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_version() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("piano").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let u = USERS.get("oswald").unwrap();
+            let user = diesel::insert_into(users::table)
+                .values(u)
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let result = Namespace::version(&user, conn, logger);
+            assert_eq!(result, Some((None, 0)));
+
+            let m = MEMBERSHIPS.get("oswald as a primary owner").unwrap();
+            let _ = diesel::insert_into(memberships::table)
+                .values(m)
+                .get_result::<Membership>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let result = Namespace::version(&user, conn, logger);
+            assert_eq!(result, Some((Some(namespace.updated_at), 1)));
+        });
+    }
+
     #[test]
     fn test_find_by_uuid() {
         run(|conn, _, logger| {
@@ -376,7 +813,42 @@ mod test {
                 conn,
                 logger,
             );
-            assert_eq!(result, Some(namespace));
+            assert_eq!(result.as_ref(), Some(&namespace));
+
+            // deprecated
+            let result = Namespace::find_by_uuid(
+                &namespace.id.to_string(),
+                &user,
+                conn,
+                logger,
+            );
+            assert_eq!(result.as_ref(), Some(&namespace));
+
+            let result =
+                Namespace::find_by_uuid("unknown", &user, conn, logger);
+            assert_eq!(result, None);
+        });
+    }
+
+    #[test]
+    fn test_find_by_key() {
+        run(|conn, _, logger| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values((namespaces::name.eq("name"),))
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let key = namespace.uuid.to_string();
+            let result = Namespace::find_by_key(&key, conn, logger);
+            assert_eq!(result.as_ref(), Some(&namespace));
+
+            let key = namespace.id.to_string();
+            let result = Namespace::find_by_key(&key, conn, logger);
+            assert_eq!(result.as_ref(), Some(&namespace));
+
+            let key = Uuid::nil().to_string();
+            let result = Namespace::find_by_key(&key, conn, logger);
+            assert_eq!(result, None);
         });
     }
 
@@ -402,4 +874,134 @@ mod test {
             assert_eq!(result.streams_count, 0);
         })
     }
+    #[test]
+    fn test_archive_and_restore() {
+        run(|conn, _, logger| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let _ = diesel::insert_into(memberships::table)
+                .values(MEMBERSHIPS.get("oswald as a primary owner").unwrap())
+                .get_result::<Membership>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let uuid = namespace.uuid.to_string();
+            let result =
+                Namespace::find_archived_by_uuid(&uuid, &user, conn, logger);
+            assert!(result.is_none());
+
+            let now = Utc.ymd(2021, 6, 28).and_hms(0, 0, 0).naive_utc();
+            let archived = namespace.archive(now, conn, logger).unwrap();
+            assert_eq!(archived.archived_at, Some(now));
+
+            let result = Namespace::find_by_uuid(&uuid, &user, conn, logger);
+            assert!(result.is_none());
+            let result =
+                Namespace::find_archived_by_uuid(&uuid, &user, conn, logger);
+            assert_eq!(result.as_ref(), Some(&archived));
+
+            let result = Namespace::find_all_archived_before(now, conn, logger);
+            assert_eq!(result, Some(vec![]));
+            let result = Namespace::find_all_archived_before(
+                now + Duration::seconds(1),
+                conn,
+                logger,
+            );
+            assert_eq!(result, Some(vec![archived]));
+
+            let namespace = namespace.restore(now, conn, logger).unwrap();
+            assert_eq!(namespace.archived_at, None);
+
+            let result = Namespace::find_by_uuid(&uuid, &user, conn, logger);
+            assert_eq!(result, Some(namespace));
+        })
+    }
+
+    #[test]
+    fn test_set_min_level() {
+        run(|conn, _, logger| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+            assert!(namespace.accepts(&LogLevel::Debug));
+
+            let now = Utc.ymd(2021, 7, 3).and_hms(0, 0, 0).naive_utc();
+            let namespace = namespace
+                .set_min_level(Some(LogLevel::Warning), now, conn, logger)
+                .unwrap();
+            assert_eq!(namespace.min_level, Some(LogLevel::Warning));
+            assert_eq!(namespace.updated_at, now);
+            assert!(!namespace.accepts(&LogLevel::Debug));
+            assert!(!namespace.accepts(&LogLevel::Information));
+            assert!(namespace.accepts(&LogLevel::Warning));
+            assert!(namespace.accepts(&LogLevel::Critical));
+
+            let namespace =
+                namespace.set_min_level(None, now, conn, logger).unwrap();
+            assert_eq!(namespace.min_level, None);
+            assert!(namespace.accepts(&LogLevel::Debug));
+        })
+    }
+
+    #[test]
+    fn test_set_anomaly_sensitivity() {
+        run(|conn, _, logger| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let result =
+                Namespace::find_all_with_anomaly_detection(conn, logger);
+            assert_eq!(result.map(|v| v.len()), Some(1));
+
+            let now = Utc.ymd(2021, 7, 12).and_hms(0, 0, 0).naive_utc();
+            let namespace = namespace
+                .set_anomaly_sensitivity(
+                    AnomalySensitivity::Off,
+                    now,
+                    conn,
+                    logger,
+                )
+                .unwrap();
+            assert_eq!(namespace.anomaly_sensitivity, AnomalySensitivity::Off);
+            assert_eq!(namespace.updated_at, now);
+
+            let result =
+                Namespace::find_all_with_anomaly_detection(conn, logger);
+            assert_eq!(result.map(|v| v.len()), Some(0));
+        })
+    }
+
+    #[test]
+    fn test_set_default_tags() {
+        run(|conn, _, logger| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+            assert!(namespace.default_tags.is_empty());
+
+            let now = Utc.ymd(2021, 7, 6).and_hms(0, 0, 0).naive_utc();
+            let tags =
+                vec!["environment=staging".to_string(), "db".to_string()];
+            let namespace = namespace
+                .set_default_tags(tags.clone(), now, conn, logger)
+                .unwrap();
+            assert_eq!(namespace.default_tags, tags);
+            assert_eq!(namespace.updated_at, now);
+
+            let mut tags = vec!["db".to_string(), "outage".to_string()];
+            namespace.merge_default_tags(&mut tags);
+            assert_eq!(tags, vec!["db", "outage", "environment=staging"]);
+        })
+    }
 }
@@ -0,0 +1,259 @@
+//! # NamespaceUsage
+//!
+//! Daily ingestion usage per namespace. The counters are kept in the session
+//! store during the day and flushed into this table periodically.
+use std::fmt;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use diesel::pg::upsert::excluded;
+
+pub use crate::schema::namespace_usages;
+
+use crate::db::with_retry;
+use crate::logger::Logger;
+use crate::model::namespace::Namespace;
+
+/// NamespaceUsage
+#[derive(Associations, Debug, Identifiable, Insertable, Queryable)]
+#[belongs_to(Namespace)]
+#[table_name = "namespace_usages"]
+pub struct NamespaceUsage {
+    pub id: i64,
+    pub namespace_id: i64,
+    pub date: NaiveDate,
+    pub messages_count: i64,
+    pub bytes_count: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub api_calls_count: i64,
+    /// Messages below the minimum level of the namespace (not saved).
+    pub dropped_messages_count: i64,
+}
+
+impl fmt::Display for NamespaceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<NamespaceUsage {date}>", date = &self.date)
+    }
+}
+
+impl NamespaceUsage {
+    pub fn find_by_namespace_id_and_date(
+        namespace_id: i64,
+        date: NaiveDate,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = namespace_usages::table
+            .filter(namespace_usages::namespace_id.eq(namespace_id))
+            .filter(namespace_usages::date.eq(date))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+        }
+    }
+
+    /// Saves the counts of the date.
+    ///
+    /// The values are totals of the date (not increments), so this can be
+    /// called repeatedly with the latest counters.
+    pub fn upsert(
+        namespace_id: i64,
+        date: NaiveDate,
+        api_calls_count: i64,
+        messages_count: i64,
+        bytes_count: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(namespace_usages::table)
+                .values((
+                    namespace_usages::namespace_id.eq(namespace_id),
+                    namespace_usages::date.eq(date),
+                    namespace_usages::api_calls_count.eq(api_calls_count),
+                    namespace_usages::messages_count.eq(messages_count),
+                    namespace_usages::bytes_count.eq(bytes_count),
+                ))
+                .on_conflict((
+                    namespace_usages::namespace_id,
+                    namespace_usages::date,
+                ))
+                .do_update()
+                .set((
+                    namespace_usages::api_calls_count
+                        .eq(excluded(namespace_usages::api_calls_count)),
+                    namespace_usages::messages_count
+                        .eq(excluded(namespace_usages::messages_count)),
+                    namespace_usages::bytes_count
+                        .eq(excluded(namespace_usages::bytes_count)),
+                    namespace_usages::updated_at.eq(diesel::dsl::now),
+                ));
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Counts a message which is dropped on ingestion (see
+    /// `Namespace::accepts`), and returns the count of the date.
+    ///
+    /// It's not in the counters of the session store, so `upsert` doesn't
+    /// overwrite it.
+    pub fn increment_dropped(
+        namespace_id: i64,
+        date: NaiveDate,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<i64> {
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(namespace_usages::table)
+                .values((
+                    namespace_usages::namespace_id.eq(namespace_id),
+                    namespace_usages::date.eq(date),
+                    namespace_usages::dropped_messages_count.eq(1),
+                ))
+                .on_conflict((
+                    namespace_usages::namespace_id,
+                    namespace_usages::date,
+                ))
+                .do_update()
+                .set((
+                    namespace_usages::dropped_messages_count
+                        .eq(namespace_usages::dropped_messages_count + 1),
+                    namespace_usages::updated_at.eq(diesel::dsl::now),
+                ))
+                .returning(namespace_usages::dropped_messages_count);
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<i64>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::namespace::{Namespace, namespaces};
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_upsert() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("piano").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let date = NaiveDate::from_ymd(2021, 6, 1);
+            let result = NamespaceUsage::upsert(
+                namespace.id,
+                date,
+                7,
+                3,
+                42,
+                conn,
+                logger,
+            );
+            assert!(result.is_some());
+
+            let usage = result.unwrap();
+            assert_eq!(usage.api_calls_count, 7);
+            assert_eq!(usage.messages_count, 3);
+            assert_eq!(usage.bytes_count, 42);
+
+            // overwrites the totals
+            let result = NamespaceUsage::upsert(
+                namespace.id,
+                date,
+                9,
+                5,
+                64,
+                conn,
+                logger,
+            );
+            assert!(result.is_some());
+
+            let usage = NamespaceUsage::find_by_namespace_id_and_date(
+                namespace.id,
+                date,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(usage.api_calls_count, 9);
+            assert_eq!(usage.messages_count, 5);
+            assert_eq!(usage.bytes_count, 64);
+
+            let count: i64 = namespace_usages::table
+                .count()
+                .get_result(conn)
+                .expect("Failed to count rows");
+            assert_eq!(count, 1);
+        });
+    }
+
+    #[test]
+    fn test_increment_dropped() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("piano").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let date = NaiveDate::from_ymd(2021, 7, 3);
+            let inc = || {
+                NamespaceUsage::increment_dropped(
+                    namespace.id,
+                    date,
+                    conn,
+                    logger,
+                )
+            };
+            assert_eq!(inc(), Some(1));
+            assert_eq!(inc(), Some(2));
+
+            // the counters are flushed after that
+            let usage = NamespaceUsage::upsert(
+                namespace.id,
+                date,
+                1,
+                3,
+                42,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(usage.messages_count, 3);
+            assert_eq!(usage.dropped_messages_count, 2);
+        });
+    }
+}
@@ -0,0 +1,96 @@
+//! # A type NotificationChannel for NotificationPreference in
+//! notification_preference.rs
+//!
+//! ENotificationChannel represents SQL type value `e_notification_channel`
+//! and NotificationChannel is an Enum holds all the values.
+use std::fmt;
+use std::io::Write;
+use std::slice::Iter;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_notification_channel")]
+pub struct ENotificationChannel;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "ENotificationChannel"]
+pub enum NotificationChannel {
+    Email,
+    Webhook,
+    None,
+}
+
+const NOTIFICATION_CHANNELS: [NotificationChannel; 3] = [
+    NotificationChannel::Email,
+    NotificationChannel::Webhook,
+    NotificationChannel::None,
+];
+
+impl fmt::Display for NotificationChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Email => write!(f, "email"),
+            Self::Webhook => write!(f, "webhook"),
+            Self::None => write!(f, "none"),
+        }
+    }
+}
+
+impl ToSql<ENotificationChannel, Pg> for NotificationChannel {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::Email => out.write_all(b"email")?,
+            Self::Webhook => out.write_all(b"webhook")?,
+            Self::None => out.write_all(b"none")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<ENotificationChannel, Pg> for NotificationChannel {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"email" => Ok(Self::Email),
+            b"webhook" => Ok(Self::Webhook),
+            b"none" => Ok(Self::None),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl NotificationChannel {
+    pub fn iter() -> Iter<'static, NotificationChannel> {
+        NOTIFICATION_CHANNELS.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|c| c.to_string() == s).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", NotificationChannel::Email), "email");
+        assert_eq!(format!("{}", NotificationChannel::Webhook), "webhook");
+        assert_eq!(format!("{}", NotificationChannel::None), "none");
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            NotificationChannel::from_name("none"),
+            Some(NotificationChannel::None)
+        );
+        assert_eq!(NotificationChannel::from_name("sms"), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! # A type NotificationEvent for NotificationPreference in
+//! notification_preference.rs
+//!
+//! ENotificationEvent represents SQL type value `e_notification_event` and
+//! NotificationEvent is an Enum holds all the values.
+use std::fmt;
+use std::io::Write;
+use std::slice::Iter;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_notification_event")]
+pub struct ENotificationEvent;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "ENotificationEvent"]
+pub enum NotificationEvent {
+    Alert,
+    Quota,
+    Security,
+}
+
+const NOTIFICATION_EVENTS: [NotificationEvent; 3] = [
+    NotificationEvent::Alert,
+    NotificationEvent::Quota,
+    NotificationEvent::Security,
+];
+
+impl fmt::Display for NotificationEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Alert => write!(f, "alert"),
+            Self::Quota => write!(f, "quota"),
+            Self::Security => write!(f, "security"),
+        }
+    }
+}
+
+impl ToSql<ENotificationEvent, Pg> for NotificationEvent {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::Alert => out.write_all(b"alert")?,
+            Self::Quota => out.write_all(b"quota")?,
+            Self::Security => out.write_all(b"security")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<ENotificationEvent, Pg> for NotificationEvent {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"alert" => Ok(Self::Alert),
+            b"quota" => Ok(Self::Quota),
+            b"security" => Ok(Self::Security),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl NotificationEvent {
+    pub fn iter() -> Iter<'static, NotificationEvent> {
+        NOTIFICATION_EVENTS.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|e| e.to_string() == s).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", NotificationEvent::Alert), "alert");
+        assert_eq!(format!("{}", NotificationEvent::Quota), "quota");
+        assert_eq!(format!("{}", NotificationEvent::Security), "security");
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            NotificationEvent::from_name("quota"),
+            Some(NotificationEvent::Quota)
+        );
+        assert_eq!(NotificationEvent::from_name("transfer"), None);
+    }
+}
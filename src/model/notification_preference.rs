@@ -0,0 +1,189 @@
+//! # NotificationPreference
+//!
+//! The channel via which a user receives notifications on an event (e.g.
+//! alerts). A user without the preference of an event receives them by email
+//! (see `service::notifier`).
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use diesel::pg::upsert::excluded;
+
+pub use crate::model::notification_channel::*;
+pub use crate::model::notification_event::*;
+pub use crate::schema::notification_preferences;
+
+use crate::db::with_retry;
+use crate::logger::Logger;
+use crate::model::user::User;
+
+/// NewNotificationPreference
+#[derive(Debug, Insertable)]
+#[table_name = "notification_preferences"]
+pub struct NewNotificationPreference {
+    pub user_id: i64,
+    pub event: NotificationEvent,
+    pub channel: NotificationChannel,
+    pub webhook_url: Option<String>,
+}
+
+/// NotificationPreference
+#[derive(Associations, Debug, Identifiable, Queryable)]
+#[belongs_to(User)]
+#[table_name = "notification_preferences"]
+pub struct NotificationPreference {
+    pub id: i64,
+    pub user_id: i64,
+    pub event: NotificationEvent,
+    pub channel: NotificationChannel,
+    pub webhook_url: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for NotificationPreference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<NotificationPreference {id}>", id = &self.id)
+    }
+}
+
+impl NotificationPreference {
+    pub fn find_all_by_user_id(
+        user_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = notification_preferences::table
+            .filter(notification_preferences::user_id.eq(user_id))
+            .order(notification_preferences::id.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn find_by_user_id_and_event(
+        user_id: i64,
+        event: NotificationEvent,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = notification_preferences::table
+            .filter(notification_preferences::user_id.eq(user_id))
+            .filter(notification_preferences::event.eq(event))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Saves the preference of the user on the event (a user has only one
+    /// for each event).
+    pub fn upsert(
+        preference: &NewNotificationPreference,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(notification_preferences::table)
+                .values(preference)
+                .on_conflict((
+                    notification_preferences::user_id,
+                    notification_preferences::event,
+                ))
+                .do_update()
+                .set((
+                    notification_preferences::channel
+                        .eq(excluded(notification_preferences::channel)),
+                    notification_preferences::webhook_url
+                        .eq(excluded(notification_preferences::webhook_url)),
+                    notification_preferences::updated_at.eq(diesel::dsl::now),
+                ));
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::test::run;
+    use crate::model::user::users;
+    use crate::model::user::data::USERS;
+
+    #[test]
+    fn test_upsert() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let event = NotificationEvent::Alert;
+            assert!(NotificationPreference::find_by_user_id_and_event(
+                user.id, event, conn, logger,
+            )
+            .is_none());
+
+            let mut preference = NewNotificationPreference {
+                user_id: user.id,
+                event,
+                channel: NotificationChannel::Webhook,
+                webhook_url: Some("https://example.org/hook".to_string()),
+            };
+            let p = NotificationPreference::upsert(&preference, conn, logger)
+                .unwrap();
+
+            preference.channel = NotificationChannel::None;
+            preference.webhook_url = None;
+            let result =
+                NotificationPreference::upsert(&preference, conn, logger)
+                    .unwrap();
+            assert_eq!(result.id, p.id);
+            assert_eq!(result.channel, NotificationChannel::None);
+            assert!(result.webhook_url.is_none());
+
+            let result = NotificationPreference::find_by_user_id_and_event(
+                user.id, event, conn, logger,
+            )
+            .unwrap();
+            assert_eq!(result.channel, NotificationChannel::None);
+
+            let result = NotificationPreference::find_by_user_id_and_event(
+                user.id,
+                NotificationEvent::Quota,
+                conn,
+                logger,
+            );
+            assert!(result.is_none());
+
+            let preferences = NotificationPreference::find_all_by_user_id(
+                user.id, conn, logger,
+            )
+            .unwrap();
+            assert_eq!(preferences.len(), 1);
+        });
+    }
+}
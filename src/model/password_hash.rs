@@ -0,0 +1,156 @@
+//! Password hashes.
+//!
+//! New hashes are Argon2id in the PHC string format (e.g.
+//! `$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>`), which holds the algorithm,
+//! its version and the parameters. Legacy hashes (bcrypt, `$2b$12$...`) can
+//! still be verified, and they are replaced at the next login (see
+//! `needs_rehash`), as well as Argon2id ones with outdated parameters.
+use std::str;
+
+use argon2::{self, ThreadMode, Variant, Version};
+use rand::{Rng, thread_rng};
+
+use crate::config::Config;
+
+const ARGON2ID_PREFIX: &str = "$argon2id$";
+const BCRYPT_PREFIXES: &[&str] = &["$2a$", "$2b$", "$2x$", "$2y$"];
+const HASH_LENGTH: u32 = 32;
+const SALT_LENGTH: usize = 16;
+
+/// The parameters of Argon2id (see `PASSWORD_HASH_*` in config).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PasswordHashParams {
+    /// KiB
+    pub memory_cost: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashParams {
+    fn default() -> Self {
+        Self {
+            memory_cost: 19_456,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl From<&Config> for PasswordHashParams {
+    fn from(c: &Config) -> Self {
+        Self {
+            memory_cost: c.password_hash_memory_cost,
+            iterations: c.password_hash_iterations,
+            parallelism: c.password_hash_parallelism,
+        }
+    }
+}
+
+impl PasswordHashParams {
+    // the part of parameters in the PHC string
+    fn to_phc(self) -> String {
+        format!(
+            "m={},t={},p={}",
+            self.memory_cost, self.iterations, self.parallelism
+        )
+    }
+}
+
+/// Returns an Argon2id hash of the password as bytes.
+pub fn hash(password: &str, params: &PasswordHashParams) -> Option<Vec<u8>> {
+    let salt: [u8; SALT_LENGTH] = thread_rng().gen();
+    let config = argon2::Config {
+        hash_length: HASH_LENGTH,
+        lanes: params.parallelism,
+        mem_cost: params.memory_cost,
+        thread_mode: ThreadMode::Sequential,
+        time_cost: params.iterations,
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+
+        ..Default::default()
+    };
+    match argon2::hash_encoded(password.as_bytes(), &salt, &config) {
+        Ok(v) => Some(v.into_bytes()),
+        Err(e) => {
+            println!("err: {:?}", e);
+            None
+        },
+    }
+}
+
+/// Checks the password against the hash (Argon2id or legacy bcrypt).
+pub fn verify(password: &str, hash: &[u8]) -> bool {
+    let hash = match str::from_utf8(hash) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    if hash.starts_with(ARGON2ID_PREFIX) {
+        argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+    } else if BCRYPT_PREFIXES.iter().any(|p| hash.starts_with(p)) {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    } else {
+        false
+    }
+}
+
+/// Returns true if the hash isn't an Argon2id one with the parameters.
+pub fn needs_rehash(hash: &[u8], params: &PasswordHashParams) -> bool {
+    let hash = match str::from_utf8(hash) {
+        Ok(s) if s.starts_with(ARGON2ID_PREFIX) => s,
+        _ => return true,
+    };
+    // ["", "argon2id", "v=19", "m=...,t=...,p=...", salt, hash]
+    let parts: Vec<&str> = hash.split('$').collect();
+    parts.len() != 6 || parts[2] != "v=19" || parts[3] != params.to_phc()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PARAMS: PasswordHashParams = PasswordHashParams {
+        memory_cost: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_hash() {
+        let h = hash("Pa$$w0rd", &PARAMS).unwrap();
+        let s = str::from_utf8(&h).unwrap();
+        assert!(s.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+
+        // salted
+        assert_ne!(hash("Pa$$w0rd", &PARAMS).unwrap(), h);
+    }
+
+    #[test]
+    fn test_verify() {
+        let h = hash("Pa$$w0rd", &PARAMS).unwrap();
+        assert!(verify("Pa$$w0rd", &h));
+        assert!(!verify("password", &h));
+
+        let legacy = bcrypt::hash("Pa$$w0rd", 4).unwrap().into_bytes();
+        assert!(verify("Pa$$w0rd", &legacy));
+        assert!(!verify("password", &legacy));
+
+        assert!(!verify("Pa$$w0rd", b"Pa$$w0rd"));
+        assert!(!verify("Pa$$w0rd", b""));
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        let h = hash("Pa$$w0rd", &PARAMS).unwrap();
+        assert!(!needs_rehash(&h, &PARAMS));
+
+        let params = PasswordHashParams {
+            iterations: 2,
+            ..PARAMS
+        };
+        assert!(needs_rehash(&h, &params));
+
+        let legacy = bcrypt::hash("Pa$$w0rd", 4).unwrap().into_bytes();
+        assert!(needs_rehash(&legacy, &PARAMS));
+    }
+}
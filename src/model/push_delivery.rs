@@ -0,0 +1,147 @@
+//! # PushDelivery
+//!
+//! A receipt of a push notification to a device (see `service::push_notifier`).
+//! The receipts are deleted with the device.
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+
+pub use crate::schema::push_deliveries;
+
+use crate::logger::Logger;
+use crate::model::push_device::PushDevice;
+
+/// NewPushDelivery
+#[derive(Debug, Insertable)]
+#[table_name = "push_deliveries"]
+pub struct NewPushDelivery {
+    pub push_device_id: i64,
+    pub message_id: String,
+    pub delivered: bool,
+    pub provider_id: Option<String>,
+    pub error: Option<String>,
+}
+
+/// PushDelivery
+#[derive(Associations, Debug, Identifiable, Queryable)]
+#[belongs_to(PushDevice)]
+#[table_name = "push_deliveries"]
+pub struct PushDelivery {
+    pub id: i64,
+    pub push_device_id: i64,
+    pub message_id: String,
+    pub delivered: bool,
+    pub provider_id: Option<String>,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl fmt::Display for PushDelivery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<PushDelivery {id}>", id = &self.id)
+    }
+}
+
+impl PushDelivery {
+    pub fn insert(
+        delivery: &NewPushDelivery,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::insert_into(push_deliveries::table).values(delivery);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Returns the recent receipts of the device (the newest first).
+    pub fn find_all_by_push_device_id(
+        push_device_id: i64,
+        limit: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = push_deliveries::table
+            .filter(push_deliveries::push_device_id.eq(push_device_id))
+            .order(push_deliveries::id.desc())
+            .limit(limit);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::push_device::{NewPushDevice, PushPlatform};
+    use crate::model::test::run;
+    use crate::model::user::{User, users};
+    use crate::model::user::data::USERS;
+
+    #[test]
+    fn test_insert_and_find() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+            let new_device = NewPushDevice {
+                user_id: user.id,
+                platform: PushPlatform::Fcm,
+                token: "fcm-token".to_string(),
+                name: "Pixel".to_string(),
+            };
+            let device = PushDevice::upsert(&new_device, conn, logger).unwrap();
+
+            for (i, delivered) in [true, false].iter().enumerate() {
+                let delivery = NewPushDelivery {
+                    push_device_id: device.id,
+                    message_id: format!("01F9DKJ8M0000000000000000{}", i),
+                    delivered: *delivered,
+                    provider_id: None,
+                    error: None,
+                };
+                let result = PushDelivery::insert(&delivery, conn, logger);
+                assert!(result.is_some());
+            }
+
+            let result = PushDelivery::find_all_by_push_device_id(
+                device.id,
+                1,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(result.len(), 1);
+            assert!(!result[0].delivered);
+
+            assert!(device.delete(conn, logger).is_ok());
+            let result = PushDelivery::find_all_by_push_device_id(
+                device.id,
+                10,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert!(result.is_empty());
+        })
+    }
+}
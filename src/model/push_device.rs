@@ -0,0 +1,228 @@
+//! # PushDevice
+//!
+//! A device of a user which receives push notifications of critical alerts
+//! in the mobile app. The token is the one issued by the platform (APNs or
+//! FCM) to the app, and it's unique in the platform. A token registered again
+//! (e.g. after signing in with another account) moves to the user.
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use diesel::pg::upsert::excluded;
+use uuid::Uuid;
+
+pub use crate::model::push_platform::*;
+pub use crate::schema::push_devices;
+
+use crate::db::with_retry;
+use crate::logger::Logger;
+use crate::model::user::User;
+
+/// NewPushDevice
+#[derive(Debug, Insertable)]
+#[table_name = "push_devices"]
+pub struct NewPushDevice {
+    pub user_id: i64,
+    pub platform: PushPlatform,
+    pub token: String,
+    pub name: String,
+}
+
+/// PushDevice
+#[derive(Associations, Debug, Identifiable, Queryable)]
+#[belongs_to(User)]
+#[table_name = "push_devices"]
+pub struct PushDevice {
+    pub id: i64,
+    pub uuid: Uuid,
+    pub user_id: i64,
+    pub platform: PushPlatform,
+    pub token: String,
+    pub name: String,
+    pub last_delivered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for PushDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<PushDevice {uuid}>", uuid = &self.uuid.to_string())
+    }
+}
+
+impl PushDevice {
+    /// Saves the device (the user and the name of a known token are
+    /// updated).
+    pub fn upsert(
+        device: &NewPushDevice,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(push_devices::table)
+                .values(device)
+                .on_conflict((push_devices::platform, push_devices::token))
+                .do_update()
+                .set((
+                    push_devices::user_id.eq(excluded(push_devices::user_id)),
+                    push_devices::name.eq(excluded(push_devices::name)),
+                    push_devices::updated_at.eq(diesel::dsl::now),
+                ));
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn find_all_by_user_id(
+        user_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = push_devices::table
+            .filter(push_devices::user_id.eq(user_id))
+            .order(push_devices::id.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn find_by_user_id_and_uuid(
+        user_id: i64,
+        uuid: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let uuid = Uuid::parse_str(uuid).ok()?;
+        let q = push_devices::table
+            .filter(push_devices::user_id.eq(user_id))
+            .filter(push_devices::uuid.eq(uuid))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn mark_as_delivered(
+        &self,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<Self, &'static str> {
+        let q = diesel::update(self).set((
+            push_devices::last_delivered_at.eq(Some(now)),
+            push_devices::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to mark device as delivered")
+            },
+            Ok(v) => Ok(v),
+        }
+    }
+
+    /// Deletes the device with its receipts.
+    pub fn delete(
+        &self,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<(), &'static str> {
+        let q = diesel::delete(self);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.execute(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to delete device")
+            },
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::model::test::run;
+    use crate::model::user::users;
+    use crate::model::user::data::USERS;
+
+    #[test]
+    fn test_upsert_and_delete() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut new_device = NewPushDevice {
+                user_id: user.id,
+                platform: PushPlatform::Apns,
+                token: "a1b2c3".to_string(),
+                name: "iPhone".to_string(),
+            };
+            let device = PushDevice::upsert(&new_device, conn, logger).unwrap();
+            assert_eq!(device.platform, PushPlatform::Apns);
+            assert!(device.last_delivered_at.is_none());
+
+            new_device.name = "iPhone 12".to_string();
+            let result = PushDevice::upsert(&new_device, conn, logger).unwrap();
+            assert_eq!(result.id, device.id);
+            assert_eq!(result.name, "iPhone 12");
+
+            let uuid = device.uuid.to_string();
+            let result = PushDevice::find_by_user_id_and_uuid(
+                user.id,
+                &uuid,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(result.id, device.id);
+            assert!(PushDevice::find_by_user_id_and_uuid(
+                user.id + 1,
+                &uuid,
+                conn,
+                logger
+            )
+            .is_none());
+
+            let now = Utc.ymd(2021, 7, 7).and_hms(9, 0, 0).naive_utc();
+            let result = device.mark_as_delivered(now, conn, logger).unwrap();
+            assert_eq!(result.last_delivered_at, Some(now));
+
+            assert!(device.delete(conn, logger).is_ok());
+            let result =
+                PushDevice::find_all_by_user_id(user.id, conn, logger).unwrap();
+            assert!(result.is_empty());
+        })
+    }
+}
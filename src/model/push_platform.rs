@@ -0,0 +1,84 @@
+//! # A type PushPlatform for PushDevice in push_device.rs
+//!
+//! EPushPlatform represents SQL type value `e_push_platform` and PushPlatform
+//! is an Enum holds all the values.
+use std::fmt;
+use std::io::Write;
+use std::slice::Iter;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_push_platform")]
+pub struct EPushPlatform;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "EPushPlatform"]
+pub enum PushPlatform {
+    Apns,
+    Fcm,
+}
+
+const PUSH_PLATFORMS: [PushPlatform; 2] =
+    [PushPlatform::Apns, PushPlatform::Fcm];
+
+impl fmt::Display for PushPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Apns => write!(f, "apns"),
+            Self::Fcm => write!(f, "fcm"),
+        }
+    }
+}
+
+impl ToSql<EPushPlatform, Pg> for PushPlatform {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::Apns => out.write_all(b"apns")?,
+            Self::Fcm => out.write_all(b"fcm")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<EPushPlatform, Pg> for PushPlatform {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"apns" => Ok(Self::Apns),
+            b"fcm" => Ok(Self::Fcm),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl PushPlatform {
+    pub fn iter() -> Iter<'static, PushPlatform> {
+        PUSH_PLATFORMS.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|p| p.to_string() == s).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", PushPlatform::Apns), "apns");
+        assert_eq!(format!("{}", PushPlatform::Fcm), "fcm");
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(PushPlatform::from_name("fcm"), Some(PushPlatform::Fcm));
+        assert_eq!(PushPlatform::from_name("webpush"), None);
+    }
+}
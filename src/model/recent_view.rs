@@ -0,0 +1,239 @@
+//! # RecentView
+//!
+//! Messages and searches which a user opened recently. The views are kept in
+//! the session store first and flushed into this table periodically (see
+//! `request::recent_view`).
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use diesel::pg::upsert::excluded;
+use serde::Serialize;
+
+pub use crate::model::recent_view_kind::*;
+pub use crate::schema::recent_views;
+
+use crate::db::with_retry;
+use crate::logger::Logger;
+use crate::model::user::User;
+
+/// RecentView
+#[derive(Associations, Debug, Identifiable, Insertable, Queryable, Serialize)]
+#[belongs_to(User)]
+#[table_name = "recent_views"]
+pub struct RecentView {
+    #[serde(skip)]
+    pub id: i64,
+    #[serde(skip)]
+    pub user_id: i64,
+    pub kind: RecentViewKind,
+    pub target: String,
+    pub viewed_at: NaiveDateTime,
+    #[serde(skip)]
+    pub created_at: NaiveDateTime,
+    #[serde(skip)]
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for RecentView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<RecentView {kind}>", kind = &self.kind)
+    }
+}
+
+impl RecentView {
+    /// Returns views of the user (the latest first).
+    pub fn find_all_by_user_id(
+        user_id: i64,
+        offset: i64,
+        limit: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = recent_views::table
+            .filter(recent_views::user_id.eq(user_id))
+            .order(recent_views::viewed_at.desc())
+            .offset(offset)
+            .limit(limit);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Saves a view, or updates the time if it has been viewed before.
+    pub fn upsert(
+        user_id: i64,
+        kind: RecentViewKind,
+        target: &str,
+        viewed_at: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let result = with_retry(logger, || {
+            let q = diesel::insert_into(recent_views::table)
+                .values((
+                    recent_views::user_id.eq(user_id),
+                    recent_views::kind.eq(kind),
+                    recent_views::target.eq(target),
+                    recent_views::viewed_at.eq(viewed_at),
+                ))
+                .on_conflict((
+                    recent_views::user_id,
+                    recent_views::kind,
+                    recent_views::target,
+                ))
+                .do_update()
+                .set((
+                    recent_views::viewed_at
+                        .eq(excluded(recent_views::viewed_at)),
+                    recent_views::updated_at.eq(diesel::dsl::now),
+                ));
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.get_result::<Self>(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Deletes views of the user except the latest ones.
+    pub fn truncate(
+        user_id: i64,
+        keep: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<usize> {
+        // a subselect from the same table can't be the target (diesel 1.4)
+        let latest = recent_views::table
+            .select(recent_views::id)
+            .filter(recent_views::user_id.eq(user_id))
+            .order(recent_views::viewed_at.desc())
+            .limit(keep)
+            .load::<i64>(conn)
+            .map_err(|e| error!(logger, "err: {}", e))
+            .ok()?;
+        let q = diesel::delete(
+            recent_views::table
+                .filter(recent_views::user_id.eq(user_id))
+                .filter(recent_views::id.ne_all(latest)),
+        );
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.execute(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(n) => Some(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::model::test::run;
+    use crate::model::user::users;
+    use crate::model::user::data::USERS;
+
+    #[test]
+    fn test_upsert() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let now = Utc.ymd(2021, 6, 19).and_hms(9, 0, 0).naive_utc();
+            let v = RecentView::upsert(
+                user.id,
+                RecentViewKind::Search,
+                "timeout",
+                now - Duration::minutes(1),
+                conn,
+                logger,
+            )
+            .unwrap();
+
+            let result = RecentView::upsert(
+                user.id,
+                RecentViewKind::Search,
+                "timeout",
+                now,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(result.id, v.id);
+            assert_eq!(result.viewed_at, now);
+
+            let result = RecentView::upsert(
+                user.id,
+                RecentViewKind::Message,
+                "timeout",
+                now,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_ne!(result.id, v.id);
+        })
+    }
+
+    #[test]
+    fn test_find_all_by_user_id_and_truncate() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let now = Utc::now().naive_utc();
+            for i in 0..3 {
+                let _ = RecentView::upsert(
+                    user.id,
+                    RecentViewKind::Message,
+                    &i.to_string(),
+                    now + Duration::seconds(i),
+                    conn,
+                    logger,
+                );
+            }
+
+            let result =
+                RecentView::find_all_by_user_id(user.id, 0, 10, conn, logger)
+                    .unwrap();
+            let targets: Vec<&str> =
+                result.iter().map(|v| v.target.as_str()).collect();
+            assert_eq!(targets, vec!["2", "1", "0"]);
+
+            let result = RecentView::truncate(user.id, 2, conn, logger);
+            assert_eq!(result, Some(1));
+
+            let result =
+                RecentView::find_all_by_user_id(user.id, 0, 10, conn, logger)
+                    .unwrap();
+            let targets: Vec<&str> =
+                result.iter().map(|v| v.target.as_str()).collect();
+            assert_eq!(targets, vec!["2", "1"]);
+        })
+    }
+}
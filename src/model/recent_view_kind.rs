@@ -0,0 +1,93 @@
+//! # A type RecentViewKind for RecentView in recent_view.rs
+//!
+//! ERecentViewKind represents SQL type value
+//! `e_recent_view_kind` and RecentViewKind is an Enum
+//! holds all the values.
+use std::fmt;
+use std::io::Write;
+use std::slice::Iter;
+
+use serde::Serialize;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+
+#[derive(QueryId, SqlType)]
+#[postgres(type_name = "e_recent_view_kind")]
+pub struct ERecentViewKind;
+
+#[derive(
+    AsExpression, Clone, Copy, Debug, FromSqlRow, PartialEq, Serialize,
+)]
+#[sql_type = "ERecentViewKind"]
+pub enum RecentViewKind {
+    Message,
+    Search,
+}
+
+const RECENT_VIEW_KINDS: [RecentViewKind; 2] =
+    [RecentViewKind::Message, RecentViewKind::Search];
+
+impl fmt::Display for RecentViewKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Message => write!(f, "message"),
+            Self::Search => write!(f, "search"),
+        }
+    }
+}
+
+impl ToSql<ERecentViewKind, Pg> for RecentViewKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            Self::Message => out.write_all(b"message")?,
+            Self::Search => out.write_all(b"search")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<ERecentViewKind, Pg> for RecentViewKind {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"message" => Ok(Self::Message),
+            b"search" => Ok(Self::Search),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl RecentViewKind {
+    pub fn iter() -> Iter<'static, RecentViewKind> {
+        RECENT_VIEW_KINDS.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|k| k.to_string() == s).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            RecentViewKind::from_name("message"),
+            Some(RecentViewKind::Message)
+        );
+        assert_eq!(
+            RecentViewKind::from_name("search"),
+            Some(RecentViewKind::Search)
+        );
+        assert_eq!(RecentViewKind::from_name("Message"), None);
+        assert_eq!(RecentViewKind::from_name("unknown"), None);
+    }
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(format!("{}", RecentViewKind::Message), "message");
+        assert_eq!(format!("{}", RecentViewKind::Search), "search");
+    }
+}
@@ -0,0 +1,80 @@
+//! # A type StatsInterval for the stats of messages in message.rs
+//!
+//! StatsInterval is the width of the time buckets, which is the field of
+//! `date_trunc` in SQL.
+use std::fmt;
+use std::slice::Iter;
+
+use chrono::{Duration, NaiveDateTime, Timelike};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatsInterval {
+    Hour,
+    Day,
+}
+
+const STATS_INTERVALS: [StatsInterval; 2] =
+    [StatsInterval::Hour, StatsInterval::Day];
+
+impl fmt::Display for StatsInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Hour => write!(f, "hour"),
+            Self::Day => write!(f, "day"),
+        }
+    }
+}
+
+impl StatsInterval {
+    pub fn iter() -> Iter<'static, StatsInterval> {
+        STATS_INTERVALS.iter()
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Self::iter().find(|v| v.to_string() == s).copied()
+    }
+
+    pub fn duration(self) -> Duration {
+        match self {
+            Self::Hour => Duration::hours(1),
+            Self::Day => Duration::days(1),
+        }
+    }
+
+    /// Returns the start of the bucket which contains the time (same as
+    /// `date_trunc`).
+    pub fn truncate(self, t: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Self::Hour => t.date().and_hms(t.hour(), 0, 0),
+            Self::Day => t.date().and_hms(0, 0, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(StatsInterval::from_name("hour"), Some(StatsInterval::Hour));
+        assert_eq!(StatsInterval::from_name("day"), Some(StatsInterval::Day));
+        assert_eq!(StatsInterval::from_name("week"), None);
+        assert_eq!(StatsInterval::from_name(""), None);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let t = Utc.ymd(2021, 6, 30).and_hms_milli(9, 45, 30, 123).naive_utc();
+        assert_eq!(
+            StatsInterval::Hour.truncate(t),
+            Utc.ymd(2021, 6, 30).and_hms(9, 0, 0).naive_utc()
+        );
+        assert_eq!(
+            StatsInterval::Day.truncate(t),
+            Utc.ymd(2021, 6, 30).and_hms(0, 0, 0).naive_utc()
+        );
+    }
+}
@@ -110,6 +110,28 @@ impl Stream {
         Self::all().filter(Self::with_uuid(uuid))
     }
 
+    /// Returns streams in the namespace which are not archived.
+    pub fn find_all_by_namespace_id(
+        namespace_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = Self::all()
+            .filter(streams::namespace_id.eq(namespace_id))
+            .filter(Self::visible())
+            .order(streams::id.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
     pub fn find_by_uuid(
         uuid: &str,
         conn: &PgConnection,
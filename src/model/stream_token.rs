@@ -0,0 +1,361 @@
+//! # Stream Token
+//!
+//! An ingestion token of a namespace, used by log shippers instead of the
+//! credentials of a user. It can only append messages to the streams of the
+//! namespace (`ingest:write`). Only the hash of the raw value is saved, the
+//! raw one is shown once when it's issued (or rotated).
+//!
+//! The source (hostname, service and environment) of a token is the default
+//! one of the messages which are ingested with it (see `fill_source`).
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use uuid::Uuid;
+
+pub use crate::schema::stream_tokens;
+
+use crate::id::IdGenerator;
+use crate::logger::Logger;
+use crate::model::namespace::Namespace;
+use crate::request::message::Message as RequestData;
+use crate::util::{constant_time_eq, hash_token};
+
+const HASH_LENGTH: usize = 48;
+const HASH_SOURCE: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// NewStreamToken
+#[derive(Debug)]
+pub struct NewStreamToken {
+    pub namespace_id: i64,
+    pub name: String,
+    pub hostname: Option<String>,
+    pub service: Option<String>,
+    pub environment: Option<String>,
+}
+
+/// StreamToken
+#[derive(Associations, Debug, Identifiable, PartialEq, Queryable)]
+#[belongs_to(Namespace)]
+#[table_name = "stream_tokens"]
+pub struct StreamToken {
+    pub id: i64,
+    pub uuid: Uuid,
+    pub namespace_id: i64,
+    pub name: String,
+    pub token: String, // hash
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub hostname: Option<String>,
+    pub service: Option<String>,
+    pub environment: Option<String>,
+}
+
+impl fmt::Display for StreamToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<StreamToken {uuid}>", uuid = &self.uuid.to_string())
+    }
+}
+
+impl StreamToken {
+    pub fn generate_token(ids: &dyn IdGenerator) -> String {
+        ids.random_hash(HASH_SOURCE, HASH_LENGTH)
+    }
+
+    /// Sets the source of the token to the message, except the ones which
+    /// are given in the payload.
+    pub fn fill_source(&self, data: &mut RequestData) {
+        if data.hostname.is_none() {
+            data.hostname = self.hostname.clone();
+        }
+        if data.service.is_none() {
+            data.service = self.service.clone();
+        }
+        if data.environment.is_none() {
+            data.environment = self.environment.clone();
+        }
+    }
+
+    /// Saves a new token with the hash of the raw value.
+    pub fn insert(
+        stream_token: &NewStreamToken,
+        raw: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::insert_into(stream_tokens::table).values((
+            stream_tokens::namespace_id.eq(stream_token.namespace_id),
+            stream_tokens::name.eq(&stream_token.name),
+            stream_tokens::token.eq(hash_token(raw)),
+            stream_tokens::hostname.eq(&stream_token.hostname),
+            stream_tokens::service.eq(&stream_token.service),
+            stream_tokens::environment.eq(&stream_token.environment),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Returns tokens of the namespace which are not revoked.
+    pub fn find_all_by_namespace_id(
+        namespace_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = stream_tokens::table
+            .filter(stream_tokens::namespace_id.eq(namespace_id))
+            .filter(stream_tokens::revoked_at.is_null())
+            .order(stream_tokens::id.asc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Finds a token of the namespace which is not revoked.
+    pub fn find_by_namespace_id_and_uuid(
+        namespace_id: i64,
+        uuid: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let uuid = Uuid::parse_str(uuid).ok()?;
+        let q = stream_tokens::table
+            .filter(stream_tokens::namespace_id.eq(namespace_id))
+            .filter(stream_tokens::uuid.eq(uuid))
+            .filter(stream_tokens::revoked_at.is_null())
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Finds a token (not revoked) by its raw value.
+    pub fn find_by_token(
+        raw: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        if raw.is_empty() {
+            return None;
+        }
+
+        let hash = hash_token(raw);
+        let q = stream_tokens::table
+            .filter(stream_tokens::token.eq(&hash))
+            .filter(stream_tokens::revoked_at.is_null())
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) if constant_time_eq(v.token.as_bytes(), hash.as_bytes()) => {
+                Some(v)
+            },
+            _ => None,
+        }
+    }
+
+    /// Replaces the token with a new raw value. The old one can't be used
+    /// anymore.
+    pub fn rotate(
+        &self,
+        raw: &str,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<Self, &'static str> {
+        let q = diesel::update(
+            stream_tokens::table
+                .filter(stream_tokens::id.eq(self.id))
+                .filter(stream_tokens::revoked_at.is_null()),
+        )
+        .set((
+            stream_tokens::token.eq(hash_token(raw)),
+            stream_tokens::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to rotate token")
+            },
+            Ok(v) => Ok(v),
+        }
+    }
+
+    pub fn revoke(
+        &self,
+        now: NaiveDateTime,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<Self, &'static str> {
+        let q = diesel::update(self).set((
+            stream_tokens::revoked_at.eq(Some(now)),
+            stream_tokens::updated_at.eq(now),
+        ));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to revoke token")
+            },
+            Ok(v) => Ok(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::id::{RandomIdGenerator, SeededIdGenerator};
+    use crate::model::namespace::namespaces;
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_generate_token() {
+        let token = StreamToken::generate_token(&RandomIdGenerator);
+        assert_eq!(token.len(), HASH_LENGTH);
+        assert!(token.chars().all(|c| c.is_ascii_alphanumeric()));
+
+        // the generator is injected
+        let a = StreamToken::generate_token(&SeededIdGenerator::new(1));
+        let b = StreamToken::generate_token(&SeededIdGenerator::new(1));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_fill_source() {
+        let t = Utc.ymd(2021, 7, 5).and_hms(9, 0, 0).naive_utc();
+        let stream_token = StreamToken {
+            id: 1,
+            uuid: Uuid::nil(),
+            namespace_id: 1,
+            name: "fluent-bit".to_string(),
+            token: "".to_string(),
+            revoked_at: None,
+            created_at: t,
+            updated_at: t,
+            hostname: Some("web-1".to_string()),
+            service: Some("api".to_string()),
+            environment: None,
+        };
+
+        let mut data = RequestData {
+            service: Some("worker".to_string()),
+            environment: Some("staging".to_string()),
+
+            ..Default::default()
+        };
+        stream_token.fill_source(&mut data);
+        assert_eq!(data.hostname, Some("web-1".to_string()));
+        assert_eq!(data.service, Some("worker".to_string()));
+        assert_eq!(data.environment, Some("staging".to_string()));
+    }
+
+    #[test]
+    fn test_insert_and_find_by_token() {
+        run(|conn, _, logger| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let t = NewStreamToken {
+                namespace_id: namespace.id,
+                name: "fluent-bit".to_string(),
+                hostname: None,
+                service: Some("api".to_string()),
+                environment: Some("production".to_string()),
+            };
+            let raw = StreamToken::generate_token(&RandomIdGenerator);
+            let stream_token =
+                StreamToken::insert(&t, &raw, conn, logger).unwrap();
+            assert_ne!(stream_token.token, raw);
+
+            let result = StreamToken::find_by_token(&raw, conn, logger);
+            assert_eq!(stream_token.service, Some("api".to_string()));
+            assert_eq!(result, Some(stream_token));
+
+            assert!(StreamToken::find_by_token("", conn, logger).is_none());
+            assert!(StreamToken::find_by_token("unknown", conn, logger)
+                .is_none());
+        });
+    }
+
+    #[test]
+    fn test_rotate_and_revoke() {
+        run(|conn, _, logger| {
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let t = NewStreamToken {
+                namespace_id: namespace.id,
+                name: "vector".to_string(),
+                hostname: None,
+                service: None,
+                environment: None,
+            };
+            let old = StreamToken::generate_token(&RandomIdGenerator);
+            let stream_token =
+                StreamToken::insert(&t, &old, conn, logger).unwrap();
+
+            let now = Utc.ymd(2021, 6, 28).and_hms(9, 0, 0).naive_utc();
+            let new = StreamToken::generate_token(&RandomIdGenerator);
+            let stream_token =
+                stream_token.rotate(&new, now, conn, logger).unwrap();
+            assert!(StreamToken::find_by_token(&old, conn, logger).is_none());
+            assert!(StreamToken::find_by_token(&new, conn, logger).is_some());
+
+            let uuid = stream_token.uuid.to_string();
+            let stream_token = stream_token.revoke(now, conn, logger).unwrap();
+            assert_eq!(stream_token.revoked_at, Some(now));
+            assert!(StreamToken::find_by_token(&new, conn, logger).is_none());
+            assert!(StreamToken::find_by_namespace_id_and_uuid(
+                namespace.id,
+                &uuid,
+                conn,
+                logger
+            )
+            .is_none());
+            assert_eq!(
+                StreamToken::find_all_by_namespace_id(
+                    namespace.id,
+                    conn,
+                    logger
+                ),
+                Some(vec![])
+            );
+        });
+    }
+}
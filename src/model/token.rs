@@ -1,15 +1,15 @@
 //! Token handles encoding/decoding of raw token using Claims.
 use std::fmt;
 
-use chrono::{NaiveDateTime, Utc};
+use chrono::NaiveDateTime;
 
 use jsonwebtoken::{
     Algorithm, EncodingKey, DecodingKey, Header, Validation,
     decode as decode_token, decode_header, encode as encode_data,
 };
 
+use crate::clock::Clock;
 use crate::model::user::User;
-use crate::model::user_email::UserEmail;
 
 #[derive(Clone)]
 pub struct TokenData {
@@ -27,32 +27,18 @@ impl fmt::Display for TokenData {
     }
 }
 
-impl From<&User> for TokenData {
-    fn from(item: &User) -> Self {
+impl TokenData {
+    /// Returns the data of a token granted to the user at the time of the
+    /// clock.
+    pub fn granted_to(user: &User, clock: &dyn Clock) -> Self {
         Self {
-            value: item.uuid.to_urn().to_string(),
-            granted_at: Utc::now().timestamp(),
+            value: user.uuid.to_urn().to_string(),
+            granted_at: clock.now().timestamp(),
             expires_at: 0,
         }
     }
 }
 
-impl From<&UserEmail> for TokenData {
-    fn from(item: &UserEmail) -> Self {
-        Self {
-            value: item.identification_token.as_ref().unwrap().to_string(),
-            granted_at: item
-                .identification_token_granted_at
-                .unwrap()
-                .timestamp(),
-            expires_at: item
-                .identification_token_expires_at
-                .unwrap()
-                .timestamp(),
-        }
-    }
-}
-
 pub trait Claims
 where Self: std::marker::Sized
 {
@@ -257,6 +243,7 @@ impl Claims for AuthenticationClaims {
 }
 
 pub type BrowserCookieTokenClaims = AuthenticationClaims;
+pub type ExchangedTokenClaims = AuthenticationClaims;
 pub type PersonalAccessTokenClaims = AuthenticationClaims;
 
 #[cfg(test)]
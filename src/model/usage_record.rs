@@ -0,0 +1,181 @@
+//! # UsageRecord
+//!
+//! Monthly usage per namespace for billing. The record is an aggregation of
+//! the daily rows in `namespace_usages` and it is updated until the month
+//! passes.
+use std::fmt;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use diesel::{Associations, Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use diesel::sql_types::Date;
+use serde::Serialize;
+
+pub use crate::schema::usage_records;
+
+use crate::logger::Logger;
+use crate::model::namespace::Namespace;
+
+/// UsageRecord
+#[derive(
+    Associations, Debug, Identifiable, Insertable, Queryable, Serialize,
+)]
+#[belongs_to(Namespace)]
+#[table_name = "usage_records"]
+pub struct UsageRecord {
+    #[serde(skip)]
+    pub id: i64,
+    #[serde(skip)]
+    pub namespace_id: i64,
+    pub month: NaiveDate,
+    pub api_calls_count: i64,
+    pub messages_count: i64,
+    pub bytes_count: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for UsageRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<UsageRecord {month}>", month = &self.month)
+    }
+}
+
+impl UsageRecord {
+    /// Returns the first day of the month of the date.
+    pub fn month_of(date: NaiveDate) -> NaiveDate {
+        NaiveDate::from_ymd(date.year(), date.month(), 1)
+    }
+
+    pub fn find_all_by_namespace_id(
+        namespace_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = usage_records::table
+            .filter(usage_records::namespace_id.eq(namespace_id))
+            .order(usage_records::month.desc());
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Sums up the daily usages in the month (of the given date) for all
+    /// namespaces, and saves them as the records of the month.
+    ///
+    /// This returns the number of the saved records.
+    pub fn aggregate(
+        date: NaiveDate,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<usize, &'static str> {
+        let q = diesel::sql_query(
+            r#"
+INSERT INTO usage_records (
+  namespace_id, month, api_calls_count, messages_count, bytes_count
+)
+SELECT
+  namespace_id,
+  $1,
+  SUM(api_calls_count)::BIGINT,
+  SUM(messages_count)::BIGINT,
+  SUM(bytes_count)::BIGINT
+FROM namespace_usages
+WHERE date >= $1 AND date < ($1 + INTERVAL '1 month')
+GROUP BY namespace_id
+ON CONFLICT (namespace_id, month) DO UPDATE SET
+  api_calls_count = EXCLUDED.api_calls_count,
+  messages_count = EXCLUDED.messages_count,
+  bytes_count = EXCLUDED.bytes_count,
+  updated_at = (now() AT TIME ZONE 'utc')
+"#,
+        )
+        .bind::<Date, _>(Self::month_of(date));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        q.execute(conn).map_err(|e| {
+            error!(logger, "err: {}", e);
+            "failed to aggregate usage records"
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::namespace::{Namespace, namespaces};
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::namespace_usage::NamespaceUsage;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_month_of() {
+        assert_eq!(
+            UsageRecord::month_of(NaiveDate::from_ymd(2021, 6, 30)),
+            NaiveDate::from_ymd(2021, 6, 1)
+        );
+        assert_eq!(
+            UsageRecord::month_of(NaiveDate::from_ymd(2021, 6, 1)),
+            NaiveDate::from_ymd(2021, 6, 1)
+        );
+    }
+
+    #[test]
+    fn test_aggregate() {
+        run(|conn, _, logger| {
+            let ns = NAMESPACES.get("piano").unwrap();
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(ns)
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let days = [
+                (NaiveDate::from_ymd(2021, 5, 31), 100, 100, 100),
+                (NaiveDate::from_ymd(2021, 6, 1), 1, 2, 3),
+                (NaiveDate::from_ymd(2021, 6, 30), 4, 5, 6),
+                (NaiveDate::from_ymd(2021, 7, 1), 100, 100, 100),
+            ];
+            for (date, calls, messages, bytes) in days.iter() {
+                let _ = NamespaceUsage::upsert(
+                    namespace.id,
+                    *date,
+                    *calls,
+                    *messages,
+                    *bytes,
+                    conn,
+                    logger,
+                )
+                .unwrap();
+            }
+
+            let date = NaiveDate::from_ymd(2021, 6, 15);
+            assert_eq!(UsageRecord::aggregate(date, conn, logger), Ok(1));
+
+            // it can be run repeatedly
+            assert_eq!(UsageRecord::aggregate(date, conn, logger), Ok(1));
+
+            let records = UsageRecord::find_all_by_namespace_id(
+                namespace.id,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(records.len(), 1);
+
+            let record = &records[0];
+            assert_eq!(record.month, NaiveDate::from_ymd(2021, 6, 1));
+            assert_eq!(record.api_calls_count, 5);
+            assert_eq!(record.messages_count, 7);
+            assert_eq!(record.bytes_count, 9);
+        });
+    }
+}
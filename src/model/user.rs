@@ -1,10 +1,9 @@
 use std::any::Any;
 use std::fmt;
-use std::str;
 
-use bcrypt::{hash, verify};
-use chrono::{Duration, NaiveDateTime, Utc};
+use chrono::{Duration, NaiveDateTime};
 use diesel::{Identifiable, Queryable, debug_query, prelude::*};
+use diesel::connection::TransactionManager;
 use diesel::pg::{Pg, PgConnection};
 use diesel::result::Error;
 use uuid::Uuid;
@@ -12,7 +11,8 @@ use uuid::Uuid;
 pub use crate::model::user_state::*;
 pub use crate::model::user_reset_password_state::*;
 pub use crate::model::access_token::{
-    AccessToken, AccessTokenState, AgentType, NewAccessToken, access_tokens,
+    AccessToken, AccessTokenState, AgentType, NewAccessToken, Scope,
+    access_tokens,
 };
 pub use crate::model::token::{
     BrowserCookieTokenClaims, PersonalAccessTokenClaims, Claims,
@@ -21,28 +21,30 @@ pub use crate::model::token::{
 pub use crate::schema::users;
 pub use crate::schema::user_emails;
 
+use crate::db::with_retry;
+use crate::id::IdGenerator;
 use crate::model::{Activatable, Authenticatable, Verifiable};
+use crate::model::external_id::ExternalId;
+use crate::model::membership::{MembershipRole, memberships};
+use crate::model::password_hash::{self, PasswordHashParams};
 use crate::model::user_email::{
     UserEmail, UserEmailRole, UserEmailIdentificationState,
 };
 use crate::logger::Logger;
 use crate::request::user::registration::UserRegistration as RequestData;
-use crate::util::generate_random_hash;
+use crate::util::hash_token;
+use crate::validation::username;
 
-const BCRYPT_COST: u32 = 12;
-const RESET_PASSWORD_HASH_LENGTH: i32 = 128;
+const RESET_PASSWORD_HASH_LENGTH: usize = 128;
 const RESET_PASSWORD_HASH_SOURCE: &[u8] =
     b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
 
-/// Returns encrypted password hash as bytes using bcrypt.
-pub fn encrypt_password(password: &str) -> Option<Vec<u8>> {
-    match hash(password, BCRYPT_COST) {
-        Ok(v) => Some(v.into_bytes()),
-        Err(e) => {
-            println!("err: {:?}", e);
-            None
-        },
-    }
+/// Returns encrypted password hash as bytes using Argon2id.
+pub fn encrypt_password(
+    password: &str,
+    params: &PasswordHashParams,
+) -> Option<Vec<u8>> {
+    password_hash::hash(password, params)
 }
 
 /// NewUser
@@ -92,8 +94,12 @@ impl<'a> From<&'a RequestData> for NewUser {
 impl NewUser {
     // NOTE:
     // run asynchronously? It (encrypt_password) may slow.
-    pub fn set_password(&mut self, password: &str) {
-        self.password = encrypt_password(password).unwrap();
+    pub fn set_password(
+        &mut self,
+        password: &str,
+        params: &PasswordHashParams,
+    ) {
+        self.password = encrypt_password(password, params).unwrap();
     }
 }
 
@@ -172,8 +178,40 @@ impl User {
         }
     }
 
+    /// Returns active users who own the namespace.
+    pub fn find_all_owners_by_namespace_id(
+        namespace_id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Vec<Self>> {
+        let q = users::table
+            .inner_join(memberships::table)
+            .filter(memberships::namespace_id.eq(namespace_id))
+            .filter(
+                memberships::role
+                    .eq(MembershipRole::PrimaryOwner)
+                    .or(memberships::role.eq(MembershipRole::Owner)),
+            )
+            .filter(memberships::revoked_at.is_null())
+            .filter(users::state.eq(UserState::Active))
+            .select(users::all_columns);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.load::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    /// Returns the user unless a token has been granted in the last 3
+    /// minutes (from `now`).
     pub fn find_by_email_only_in_available_to_reset(
         s: &str,
+        now: NaiveDateTime,
         conn: &PgConnection,
         logger: &Logger,
     ) -> Option<Self> {
@@ -181,7 +219,7 @@ impl User {
             return None;
         }
 
-        let in_3_minutes = Utc::now().naive_utc() - Duration::minutes(3);
+        let in_3_minutes = now - Duration::minutes(3);
         let q =
             users::table
                 .inner_join(user_emails::table)
@@ -258,13 +296,9 @@ impl User {
         conn: &PgConnection,
         logger: &Logger,
     ) -> Option<Self> {
-        if s.is_empty() {
-            return None;
-        }
-
-        let u = Uuid::parse_str(s).unwrap();
+        let (uuid, id) = ExternalId::parse_for("user", s, logger)?.to_pair();
         let q = users::table
-            .filter(users::uuid.eq(u))
+            .filter(users::uuid.eq(uuid).or(users::id.eq(id)))
             .filter(users::state.eq(UserState::Active))
             .limit(1);
 
@@ -355,15 +389,79 @@ impl User {
         }
     }
 
-    pub fn generate_password_reset_token() -> String {
-        generate_random_hash(
-            RESET_PASSWORD_HASH_SOURCE,
-            RESET_PASSWORD_HASH_LENGTH,
-        )
+    pub fn generate_password_reset_token(ids: &dyn IdGenerator) -> String {
+        ids.random_hash(RESET_PASSWORD_HASH_SOURCE, RESET_PASSWORD_HASH_LENGTH)
+    }
+
+    pub fn change_password(
+        &mut self,
+        password: &str,
+        params: &PasswordHashParams,
+    ) {
+        self.password = encrypt_password(password, params).unwrap();
+    }
+
+    /// Replaces the password hash if it's a legacy one or has outdated
+    /// parameters. The password must have been verified.
+    pub fn rehash_password(
+        &mut self,
+        password: &str,
+        params: &PasswordHashParams,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<bool, &'static str> {
+        if !password_hash::needs_rehash(&self.password, params) {
+            return Ok(false);
+        }
+        self.change_password(password, params);
+
+        let result = with_retry(logger, || {
+            let q = diesel::update(users::table.filter(users::id.eq(self.id)))
+                .set(users::password.eq(&self.password));
+
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+            q.execute(conn)
+        });
+
+        match result {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to rehash password")
+            },
+            Ok(_) => Ok(true),
+        }
     }
 
-    pub fn change_password(&mut self, password: &str) {
-        self.password = encrypt_password(password).unwrap();
+    /// Changes the username. It must satisfy the policy (see
+    /// `validation::username`) and must not be taken.
+    pub fn update_username(
+        &mut self,
+        username: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<(), &'static str> {
+        if username::validate(username).is_err() {
+            return Err("invalid username");
+        }
+        if !Self::check_username_uniqueness(username, conn, logger) {
+            return Err("username is already taken");
+        }
+
+        let q = diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set(users::username.eq(username));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.execute(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to update username")
+            },
+            Ok(_) => {
+                self.username = username.to_string();
+                Ok(())
+            },
+        }
     }
 
     pub fn grant_token<T: Claims>(
@@ -403,54 +501,66 @@ impl Activatable for User {
         conn: &PgConnection,
         logger: &Logger,
     ) -> Result<(), &'static str> {
-        conn.build_transaction()
-            .serializable()
-            .deferrable()
-            .read_write()
-            .run::<_, diesel::result::Error, _>(|| {
-                let q = users::table
-                    .inner_join(user_emails::table)
-                    .filter(user_emails::user_id.eq(self.id))
-                    .filter(user_emails::role.eq(UserEmailRole::Primary))
-                    .filter(
-                        user_emails::identification_state
-                            .eq(UserEmailIdentificationState::Pending),
-                    )
-                    .limit(1);
+        let activate = || {
+            let q = users::table
+                .inner_join(user_emails::table)
+                .filter(user_emails::user_id.eq(self.id))
+                .filter(user_emails::role.eq(UserEmailRole::Primary))
+                .filter(
+                    user_emails::identification_state
+                        .eq(UserEmailIdentificationState::Pending),
+                )
+                .limit(1);
+            info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+            let user_email = q
+                .load::<(Self, UserEmail)>(conn)
+                .map(|mut v| v.pop().unwrap().1)
+                .map_err(|e| {
+                    error!(logger, "error: {}", e);
+                    e
+                })
+                .unwrap();
+
+            if user_email.activate(conn, logger).is_ok() {
+                let q = diesel::update(self)
+                    .set(users::state.eq(UserState::Active));
                 info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
-                let user_email = q
-                    .load::<(Self, UserEmail)>(conn)
-                    .map(|mut v| v.pop().unwrap().1)
-                    .map_err(|e| {
-                        error!(logger, "error: {}", e);
-                        e
-                    })
-                    .unwrap();
-
-                if user_email.activate(conn, logger).is_ok() {
-                    let q = diesel::update(self)
-                        .set(users::state.eq(UserState::Active));
-                    info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
-
-                    match q.get_result::<Self>(conn) {
-                        Err(e) => {
-                            error!(logger, "err: {}", e);
-                        },
-                        Ok(u) => {
-                            // create (disabled) personal access token
-                            let mut t = NewAccessToken::from(&u);
-                            t.name = "Personal Access Token".to_string();
-                            let result = AccessToken::insert(&t, conn, logger);
-                            if result.is_some() {
-                                return Ok(());
-                            }
-                        },
-                    };
-                }
-                Err(Error::RollbackTransaction)
-            })
-            .map_err(|_| "activation failed")
+                match q.get_result::<Self>(conn) {
+                    Err(e) => {
+                        error!(logger, "err: {}", e);
+                    },
+                    Ok(u) => {
+                        // create (disabled) personal access token
+                        let mut t = NewAccessToken::from(&u);
+                        t.name = "Personal Access Token".to_string();
+                        t.scopes = Scope::iter().copied().collect();
+                        let result = AccessToken::insert(&t, conn, logger);
+                        if result.is_some() {
+                            return Ok(());
+                        }
+                    },
+                };
+            }
+            Err(Error::RollbackTransaction)
+        };
+        // it's a savepoint in a transaction of the caller (e.g. a sign-in by
+        // `service::oauth`), which can't have its own isolation level
+        let depth = TransactionManager::<PgConnection>::get_transaction_depth(
+            conn.transaction_manager(),
+        );
+        let nested = depth > 0;
+        let result = if nested {
+            conn.transaction::<_, Error, _>(activate)
+        } else {
+            conn.build_transaction()
+                .serializable()
+                .deferrable()
+                .read_write()
+                .run::<_, Error, _>(activate)
+        };
+        result.map_err(|_| "activation failed")
     }
 }
 
@@ -458,10 +568,11 @@ impl Authenticatable for User {
     fn update_password(
         &mut self,
         new_password: &str,
+        params: &PasswordHashParams,
         conn: &PgConnection,
         logger: &Logger,
     ) -> Result<(), &'static str> {
-        self.change_password(new_password);
+        self.change_password(new_password, params);
 
         let q = diesel::update(
             users::table
@@ -487,7 +598,7 @@ impl Authenticatable for User {
     /// Checks whether the password given as an argument is valid or not.
     /// This takes a bit long til returning the result.
     fn verify_password(&self, password: &str) -> bool {
-        verify(password, &str::from_utf8(&self.password).unwrap()).unwrap()
+        password_hash::verify(password, &self.password)
     }
 }
 
@@ -510,10 +621,11 @@ impl Verifiable<(Self, UserEmail)> for User {
         conn: &PgConnection,
         logger: &Logger,
     ) -> Result<(Self, UserEmail), &'static str> {
+        let hash = hash_token(concrete_token);
         let q = users::table
             .filter(users::state.eq(UserState::Pending))
             .inner_join(user_emails::table)
-            .filter(user_emails::identification_token.eq(concrete_token))
+            .filter(user_emails::identification_token.eq(&hash))
             .filter(
                 user_emails::identification_state
                     .eq(UserEmailIdentificationState::Pending),
@@ -524,7 +636,9 @@ impl Verifiable<(Self, UserEmail)> for User {
         info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
         match q.load::<(Self, UserEmail)>(conn) {
-            Ok(ref mut v) if v.len() == 1 => v.pop().ok_or("unexpected :'("),
+            Ok(ref mut v) if v.len() == 1 && v[0].1.has_token_hash(&hash) => {
+                v.pop().ok_or("unexpected :'(")
+            },
             _ => Err("not found"),
         }
     }
@@ -638,6 +752,10 @@ pub mod data {
 mod test {
     use super::*;
 
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::{Clock, FrozenClock};
+    use crate::id::RandomIdGenerator;
     use crate::model::test::run;
     use crate::model::token::{
         AuthenticationClaims, BrowserCookieTokenClaims, Claims, TokenData,
@@ -731,6 +849,39 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_update_username() {
+        run(|conn, _, logger| {
+            let u = USERS.get("hennry").unwrap();
+            let _ = diesel::insert_into(users::table)
+                .values(u)
+                .execute(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let u = USERS.get("oswald").unwrap();
+            let mut user = diesel::insert_into(users::table)
+                .values(u)
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let result = user.update_username("admin", conn, logger);
+            assert_eq!(result, Err("invalid username"));
+
+            let result = user.update_username("1oswald", conn, logger);
+            assert_eq!(result, Err("invalid username"));
+
+            let result = user.update_username("hennry", conn, logger);
+            assert_eq!(result, Err("username is already taken"));
+
+            let result = user.update_username("oswald_2", conn, logger);
+            assert!(result.is_ok());
+            assert_eq!(user.username, "oswald_2");
+
+            let saved = User::find_by_id(user.id, conn, logger).unwrap();
+            assert_eq!(saved.username, "oswald_2");
+        });
+    }
+
     #[test]
     fn test_find_by_id_not_found() {
         run(|conn, _, logger| {
@@ -938,6 +1089,46 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_find_by_email_only_in_available_to_reset() {
+        run(|conn, _, logger| {
+            let mut u = USERS.get("oswald").unwrap().clone();
+            let t = Utc.ymd(2019, 7, 7).and_hms(7, 20, 15);
+            let clock = FrozenClock::new(t);
+            u.reset_password_token_granted_at = Some(clock.now().naive_utc());
+            let user = diesel::insert_into(users::table)
+                .values(&u)
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut ue =
+                USER_EMAILS.get("oswald's primary address").unwrap().clone();
+            ue.user_id = user.id;
+            diesel::insert_into(user_emails::table)
+                .values(&ue)
+                .execute(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let find = |now: NaiveDateTime| {
+                User::find_by_email_only_in_available_to_reset(
+                    &user.email,
+                    now,
+                    conn,
+                    logger,
+                )
+            };
+
+            // a token has been granted just now
+            assert!(find(clock.now().naive_utc()).is_none());
+
+            clock.advance(Duration::minutes(3));
+            assert!(find(clock.now().naive_utc()).is_none());
+
+            clock.advance(Duration::seconds(1));
+            assert!(find(clock.now().naive_utc()).is_some());
+        });
+    }
+
     #[test]
     fn test_find_by_token_with_authentication_claims() {
         run(|conn, config, logger| {
@@ -983,7 +1174,7 @@ mod test {
             assert_eq!(u.state, UserState::Active);
 
             let granted_at = Utc::now();
-            let raw_token = User::generate_password_reset_token();
+            let raw_token = User::generate_password_reset_token(&RandomIdGenerator);
             u.reset_password_token = Some(raw_token.clone());
             u.reset_password_token_granted_at = Some(granted_at.naive_utc());
 
@@ -1058,12 +1249,19 @@ mod test {
 
             let user = result.unwrap();
             assert_eq!(user.uuid, uuid);
+
+            // deprecated
+            let result = User::find_by_uuid(&user.id.to_string(), conn, logger);
+            assert_eq!(result.map(|u| u.uuid), Some(uuid));
+
+            assert!(User::find_by_uuid("", conn, logger).is_none());
+            assert!(User::find_by_uuid("unknown", conn, logger).is_none());
         });
     }
 
     #[test]
     fn test_insert() {
-        run(|conn, _, logger| {
+        run(|conn, config, logger| {
             let mut u = NewUser {
                 name: Some("Johnny Snowman".to_string()),
                 username: "johnny".to_string(),
@@ -1071,7 +1269,7 @@ mod test {
 
                 ..Default::default()
             };
-            u.set_password("password");
+            u.set_password("password", &PasswordHashParams::from(config));
             let result = User::insert(&u, conn, logger);
             assert!(result.is_some());
 
@@ -1082,4 +1280,30 @@ mod test {
             assert_eq!(1, rows_count);
         })
     }
+
+    #[test]
+    fn test_rehash_password() {
+        run(|conn, config, logger| {
+            let password = "Pa$$w0rd";
+            let mut u = USERS.get("oswald").unwrap().clone();
+            // legacy (bcrypt)
+            u.password = bcrypt::hash(password, 4).unwrap().into_bytes();
+            let mut user = diesel::insert_into(users::table)
+                .values(&u)
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+            assert!(user.verify_password(password));
+
+            let params = PasswordHashParams::from(config);
+            let result = user.rehash_password(password, &params, conn, logger);
+            assert_eq!(result, Ok(true));
+
+            let mut user = User::find_by_id(user.id, conn, logger).unwrap();
+            assert!(user.password.starts_with(b"$argon2id$"));
+            assert!(user.verify_password(password));
+
+            let result = user.rehash_password(password, &params, conn, logger);
+            assert_eq!(result, Ok(false));
+        })
+    }
 }
@@ -9,12 +9,14 @@ pub use crate::model::user_email_role::*;
 pub use crate::model::user_email_identification_state::*;
 pub use crate::schema::user_emails;
 
+use crate::id::IdGenerator;
 use crate::logger::Logger;
 use crate::model::Activatable;
 use crate::model::user::User;
-use crate::util::generate_random_hash;
+use crate::util::{constant_time_eq, hash_token};
+use crate::validation::email::{EmailPolicy, normalize};
 
-const VERIFICATION_HASH_LENGTH: i32 = 128;
+const VERIFICATION_HASH_LENGTH: usize = 128;
 const VERIFICATION_HASH_SOURCE: &[u8] =
     b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz01234567890";
 
@@ -108,6 +110,9 @@ impl UserEmail {
     }
 
     /// Finds only a non-verified (pending) UserEmail by identification token.
+    ///
+    /// The subject of the token is looked up by its hash, and compared with
+    /// the stored one again in constant time.
     pub fn find_by_token<T: Claims>(
         token: &str,
         issuer: &str,
@@ -125,9 +130,10 @@ impl UserEmail {
         if value.is_empty() {
             return None;
         }
+        let hash = hash_token(&value);
 
         let q = user_emails::table
-            .filter(user_emails::identification_token.eq(value))
+            .filter(user_emails::identification_token.eq(&hash))
             .filter(
                 user_emails::identification_state
                     .eq(UserEmailIdentificationState::Pending),
@@ -137,13 +143,21 @@ impl UserEmail {
         info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
 
         match q.first::<UserEmail>(conn) {
-            Ok(v) => Some(v),
+            Ok(v) if v.has_token_hash(&hash) => Some(v),
             _ => None,
         }
     }
 
-    pub fn generate_token() -> String {
-        generate_random_hash(VERIFICATION_HASH_SOURCE, VERIFICATION_HASH_LENGTH)
+    /// Returns true if the stored identification token is the hash.
+    pub fn has_token_hash(&self, hash: &str) -> bool {
+        self.identification_token
+            .as_ref()
+            .map(|t| constant_time_eq(t.as_bytes(), hash.as_bytes()))
+            .unwrap_or(false)
+    }
+
+    pub fn generate_token(ids: &dyn IdGenerator) -> String {
+        ids.random_hash(VERIFICATION_HASH_SOURCE, VERIFICATION_HASH_LENGTH)
     }
 
     /// Save a new user_email into user_emails.
@@ -161,9 +175,11 @@ impl UserEmail {
         conn: &PgConnection,
         logger: &Logger,
     ) -> Option<Self> {
+        // the address is validated (and normalized with the policy) before
+        let email = normalize(&user_email.email, &EmailPolicy::default());
         let q = diesel::insert_into(user_emails::table).values((
             user_emails::user_id.eq(&user_email.user_id),
-            Some(user_emails::email.eq(&user_email.email)),
+            Some(user_emails::email.eq(&email)),
             user_emails::role.eq(UserEmailRole::Primary),
             user_emails::identification_state
                 .eq(UserEmailIdentificationState::Pending),
@@ -180,6 +196,8 @@ impl UserEmail {
         }
     }
 
+    /// Saves the hash of the subject of the token as identification token,
+    /// and returns the subject (concrete token).
     pub fn grant_token<T: Claims>(
         &self,
         token: &str,
@@ -190,12 +208,13 @@ impl UserEmail {
     ) -> Result<String, &'static str> {
         // TODO: should we check duplication?
         let c = T::decode(token, issuer, secret).expect("Invalid value");
+        let concrete_token = c.get_subject();
 
         // for identification
         let q = diesel::update(self).set((
             user_emails::identification_state
                 .eq(UserEmailIdentificationState::Pending),
-            user_emails::identification_token.eq(c.get_subject()),
+            user_emails::identification_token.eq(hash_token(&concrete_token)),
             user_emails::identification_token_expires_at
                 .eq(c.get_expiration_time()),
             user_emails::identification_token_granted_at.eq(c.get_issued_at()),
@@ -208,7 +227,7 @@ impl UserEmail {
                 error!(logger, "err: {}", e);
                 Err("failed to grant an identification token")
             },
-            Ok(user_email) => Ok(user_email.identification_token.unwrap()),
+            Ok(_) => Ok(concrete_token),
         }
     }
 
@@ -302,6 +321,7 @@ mod test {
 
     use chrono::{Duration, Utc};
 
+    use crate::id::RandomIdGenerator;
     use crate::model::user::{User, users};
     use crate::model::token::{VerificationClaims, TokenData};
 
@@ -402,7 +422,7 @@ mod test {
 
             let now = Utc::now();
             let data = TokenData {
-                value: UserEmail::generate_token(),
+                value: UserEmail::generate_token(&RandomIdGenerator),
                 granted_at: now.timestamp(),
                 expires_at: (now + Duration::hours(1)).timestamp(),
             };
@@ -464,7 +484,7 @@ mod test {
 
             let now = Utc::now();
             let data = TokenData {
-                value: UserEmail::generate_token(),
+                value: UserEmail::generate_token(&RandomIdGenerator),
                 granted_at: now.timestamp(),
                 expires_at: (now + Duration::hours(1)).timestamp(),
             };
@@ -563,7 +583,7 @@ mod test {
 
             let now = Utc::now();
             let data = TokenData {
-                value: UserEmail::generate_token(),
+                value: UserEmail::generate_token(&RandomIdGenerator),
                 granted_at: now.timestamp(),
                 expires_at: (now + Duration::hours(1)).timestamp(),
             };
@@ -617,7 +637,7 @@ mod test {
 
             let now = Utc::now();
             let data = TokenData {
-                value: UserEmail::generate_token(),
+                value: UserEmail::generate_token(&RandomIdGenerator),
                 granted_at: now.timestamp(),
                 expires_at: (now + Duration::hours(1)).timestamp(),
             };
@@ -649,10 +669,13 @@ mod test {
                 .first::<UserEmail>(conn)
                 .unwrap();
 
-            assert_eq!(
+            // only the hash is stored
+            assert_ne!(
                 identification_token,
-                user_email.identification_token.unwrap()
+                user_email.identification_token.to_owned().unwrap()
             );
+            let hash = hash_token(&identification_token);
+            assert!(user_email.has_token_hash(&hash));
         });
     }
 }
@@ -0,0 +1,184 @@
+//! # WaitlistEntry
+//!
+//! An email address captured via the public sign-up (waitlist) API. It
+//! becomes confirmed when the link in the double opt-in email is followed.
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Queryable, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+
+pub use crate::schema::waitlist_entries;
+
+use crate::clock::Clock;
+use crate::id::IdGenerator;
+use crate::logger::Logger;
+use crate::request::waitlist::WaitlistEntry as RequestData;
+
+const CONFIRMATION_HASH_LENGTH: usize = 64;
+const CONFIRMATION_HASH_SOURCE: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz01234567890";
+
+/// NewWaitlistEntry
+#[derive(Debug)]
+pub struct NewWaitlistEntry {
+    pub email: String,
+}
+
+impl Default for NewWaitlistEntry {
+    // includes validation errors
+    fn default() -> Self {
+        Self {
+            email: "".to_string(),
+        }
+    }
+}
+
+impl From<RequestData> for NewWaitlistEntry {
+    fn from(data: RequestData) -> Self {
+        Self {
+            email: data.email.trim().to_lowercase(),
+        }
+    }
+}
+
+/// WaitlistEntry
+#[derive(Debug, Identifiable, Insertable, PartialEq, Queryable)]
+#[table_name = "waitlist_entries"]
+pub struct WaitlistEntry {
+    pub id: i64,
+    pub email: String,
+    pub confirmed_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl fmt::Display for WaitlistEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<WaitlistEntry {id}>", id = &self.id)
+    }
+}
+
+impl WaitlistEntry {
+    pub fn find_by_id(
+        id: i64,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        if id < 1 {
+            return None;
+        }
+
+        let q = waitlist_entries::table
+            .filter(waitlist_entries::id.eq(id))
+            .limit(1);
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.first::<Self>(conn) {
+            Ok(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn generate_token(ids: &dyn IdGenerator) -> String {
+        ids.random_hash(CONFIRMATION_HASH_SOURCE, CONFIRMATION_HASH_LENGTH)
+    }
+
+    /// Saves a new entry or returns the existing one for the email.
+    pub fn insert(
+        entry: &NewWaitlistEntry,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Option<Self> {
+        let q = diesel::insert_into(waitlist_entries::table)
+            .values(waitlist_entries::email.eq(&entry.email))
+            .on_conflict(waitlist_entries::email)
+            .do_update()
+            .set(waitlist_entries::updated_at.eq(diesel::dsl::now));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                None
+            },
+            Ok(v) => Some(v),
+        }
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+
+    pub fn confirm(
+        &self,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<(), &'static str> {
+        let q = diesel::update(self)
+            .set(waitlist_entries::confirmed_at.eq(clock.now().naive_utc()));
+
+        info!(logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        match q.get_result::<Self>(conn) {
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                Err("failed to confirm")
+            },
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::clock::SystemClock;
+    use crate::model::test::run;
+
+    #[test]
+    fn test_new_waitlist_entry_from_request_data() {
+        let data = RequestData {
+            email: " Postmaster@Example.org ".to_string(),
+        };
+        let entry = NewWaitlistEntry::from(data);
+        assert_eq!(entry.email, "postmaster@example.org");
+    }
+
+    #[test]
+    fn test_insert() {
+        run(|conn, _, logger| {
+            let entry = NewWaitlistEntry {
+                email: "postmaster@example.org".to_string(),
+            };
+
+            let result = WaitlistEntry::insert(&entry, conn, logger);
+            assert!(result.is_some());
+
+            let e1 = result.unwrap();
+            assert!(!e1.is_confirmed());
+
+            // same email
+            let e2 = WaitlistEntry::insert(&entry, conn, logger).unwrap();
+            assert_eq!(e1.id, e2.id);
+        });
+    }
+
+    #[test]
+    fn test_confirm() {
+        run(|conn, _, logger| {
+            let entry = NewWaitlistEntry {
+                email: "postmaster@example.org".to_string(),
+            };
+            let e = WaitlistEntry::insert(&entry, conn, logger).unwrap();
+            assert!(e.confirm(&SystemClock, conn, logger).is_ok());
+
+            let e = WaitlistEntry::find_by_id(e.id, conn, logger).unwrap();
+            assert!(e.is_confirmed());
+        });
+    }
+}
@@ -1,6 +1,15 @@
 //! The message queue and its connection manager.
+//!
+//! Jobs are enqueued through `QueueBackend`, which is Redis (fourche) or the
+//! memory of the process (`MESSAGE_QUEUE_BACKEND=memory`, for tests and local
+//! development).
+use std::mem;
 use std::ops::{Deref, DerefMut};
 
+use chrono::{DateTime, Utc};
+use fourche::queue::Queue;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
 use rocket::http::Status;
 use rocket::request::{self, FromRequest};
 use rocket::{Request, State, Outcome};
@@ -8,6 +17,12 @@ use r2d2_redis::{
     r2d2::Pool, r2d2::PooledConnection, redis, RedisConnectionManager,
 };
 
+use crate::config::{Config, MessageQueueBackend, PoolConfig};
+use crate::job::{Job, defer};
+use crate::service::fault_injection::fails_redis;
+use crate::service::pool_metrics::{PoolState, PoolStats};
+use crate::telemetry::{SpanKind, span};
+
 pub type MqPool = Pool<RedisConnectionManager>;
 pub type MqPooledConn = PooledConnection<RedisConnectionManager>;
 
@@ -17,11 +32,29 @@ pub struct MqConn(pub MqPooledConn);
 #[derive(Clone)]
 pub struct MqPoolHolder {
     pool: MqPool,
+    stats: PoolStats,
 }
 
 impl MqPoolHolder {
     pub fn get(&self) -> Option<MqPooledConn> {
-        self.pool.get().ok()
+        // waits for a connection in a span (see `telemetry`)
+        let s = span("redis.pool.get", SpanKind::Client);
+        s.set("db.system", "redis");
+        let conn = self.stats.wait(|| self.pool.get().ok());
+        if conn.is_none() {
+            s.fail();
+        }
+        conn
+    }
+
+    pub fn state(&self) -> PoolState {
+        let state = self.pool.state();
+        PoolState::new(
+            self.pool.max_size(),
+            state.connections,
+            state.idle_connections,
+            &self.stats,
+        )
     }
 }
 
@@ -30,13 +63,130 @@ impl<'a, 'r> FromRequest<'a, 'r> for MqConn {
 
     fn from_request(request: &'a Request<'r>) -> request::Outcome<MqConn, ()> {
         let holder = request.guard::<State<MqPoolHolder>>()?;
+        let config = request.guard::<State<Config>>()?;
         match holder.get() {
-            Some(conn) => Outcome::Success(MqConn(conn)),
+            Some(mut conn) => {
+                if fails_redis(&config, &mut conn) {
+                    return Outcome::Failure((Status::ServiceUnavailable, ()));
+                }
+                Outcome::Success(MqConn(conn))
+            },
             None => Outcome::Failure((Status::ServiceUnavailable, ())),
         }
     }
 }
 
+/// QueueBackend enqueues jobs for workers.
+pub trait QueueBackend {
+    fn enqueue(&mut self, job: Job<String>) -> Result<(), String>;
+
+    /// Defers the job until the time (see `job::defer`).
+    fn defer(
+        &mut self,
+        job: &Job<String>,
+        until: DateTime<Utc>,
+    ) -> Result<(), String>;
+}
+
+impl QueueBackend for redis::Connection {
+    fn enqueue(&mut self, job: Job<String>) -> Result<(), String> {
+        let mut queue = Queue::new("default", self);
+        queue
+            .enqueue::<Job<String>>(job)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn defer(
+        &mut self,
+        job: &Job<String>,
+        until: DateTime<Utc>,
+    ) -> Result<(), String> {
+        defer(job, until, self)
+    }
+}
+
+// a job and the time until which it's deferred
+type MemoryJob = (Job<String>, Option<DateTime<Utc>>);
+
+lazy_static! {
+    static ref MEMORY_JOBS: Mutex<Vec<MemoryJob>> = Mutex::new(vec![]);
+}
+
+/// MemoryQueue keeps jobs in the process. Nobody runs them, and they can be
+/// taken by `MemoryQueue::take` (e.g. to run them in tests).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryQueue;
+
+impl MemoryQueue {
+    /// Returns all of the jobs in the queue (the oldest first), and removes
+    /// them.
+    pub fn take() -> Vec<(Job<String>, Option<DateTime<Utc>>)> {
+        mem::take(&mut *MEMORY_JOBS.lock())
+    }
+}
+
+impl QueueBackend for MemoryQueue {
+    fn enqueue(&mut self, job: Job<String>) -> Result<(), String> {
+        MEMORY_JOBS.lock().push((job, None));
+        Ok(())
+    }
+
+    fn defer(
+        &mut self,
+        job: &Job<String>,
+        until: DateTime<Utc>,
+    ) -> Result<(), String> {
+        MEMORY_JOBS.lock().push((job.clone(), Some(until)));
+        Ok(())
+    }
+}
+
+/// JobQueue enqueues jobs into the backend of the config.
+pub enum JobQueue {
+    Redis(MqConn),
+    Memory(MemoryQueue),
+}
+
+impl JobQueue {
+    fn backend(&mut self) -> &mut dyn QueueBackend {
+        match self {
+            JobQueue::Redis(conn) => &mut **conn,
+            JobQueue::Memory(queue) => queue,
+        }
+    }
+
+    pub fn enqueue(&mut self, job: Job<String>) -> Result<(), String> {
+        self.backend().enqueue(job)
+    }
+
+    pub fn defer(
+        &mut self,
+        job: &Job<String>,
+        until: DateTime<Utc>,
+    ) -> Result<(), String> {
+        self.backend().defer(job, until)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for JobQueue {
+    type Error = ();
+
+    fn from_request(
+        request: &'a Request<'r>,
+    ) -> request::Outcome<JobQueue, ()> {
+        let config = request.guard::<State<Config>>()?;
+        match config.message_queue_backend {
+            MessageQueueBackend::Memory => {
+                Outcome::Success(JobQueue::Memory(MemoryQueue))
+            },
+            MessageQueueBackend::Redis => {
+                request.guard::<MqConn>().map(JobQueue::Redis)
+            },
+        }
+    }
+}
+
 impl Deref for MqConn {
     type Target = redis::Connection;
 
@@ -51,16 +201,48 @@ impl DerefMut for MqConn {
     }
 }
 
-// Initializes message queue connection pool holder
+// Initializes message queue connection pool holder (the URL has the TLS
+// options, see `Config::message_queue_connection_url`)
 pub fn init_pool_holder(
     message_queue_url: &str,
-    max_size: u32,
+    options: &PoolConfig,
 ) -> MqPoolHolder {
     let connection_manager =
         RedisConnectionManager::new(message_queue_url).unwrap();
     let pool = Pool::builder()
-        .max_size(max_size)
+        .max_size(options.max_size)
+        .min_idle(options.min_idle)
+        .connection_timeout(options.connection_timeout())
+        .idle_timeout(options.idle_timeout())
+        .max_lifetime(options.max_lifetime())
         .build(connection_manager)
         .expect("message queue pool");
-    MqPoolHolder { pool }
+    MqPoolHolder {
+        pool,
+        stats: PoolStats::default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::job::JobKind;
+
+    #[test]
+    fn test_memory_queue() {
+        let job = Job::<String> {
+            kind: JobKind::FlushRecentViews,
+            args: vec![],
+        };
+        let until = Utc::now();
+
+        let mut queue = MemoryQueue;
+        assert!(queue.enqueue(job.clone()).is_ok());
+        assert!(queue.defer(&job, until).is_ok());
+
+        let jobs = MemoryQueue::take();
+        assert_eq!(jobs, vec![(job.clone(), None), (job, Some(until))]);
+        assert!(MemoryQueue::take().is_empty());
+    }
 }
@@ -34,6 +34,16 @@ pub struct AccessTokenObject {
     pub state: AccessTokenState,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct AccessTokenScopesData {
+    pub access_token: AccessTokenScopesObject,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AccessTokenScopesObject {
+    pub scopes: Vec<String>,
+}
+
 impl<'v> FromData<'v> for AccessTokenData {
     type Error = AccessTokenError;
     type Owned = String;
@@ -0,0 +1,10 @@
+/// AlertSchedule
+///
+/// The timezone is an IANA name, and the quiet hours are local time as
+/// `HH:MM`. Both of the quiet hours are omitted to receive alerts at any time.
+#[derive(Clone, Default, Deserialize)]
+pub struct AlertSchedule {
+    pub timezone: Option<String>,
+    pub quiet_hours_start: Option<String>,
+    pub quiet_hours_end: Option<String>,
+}
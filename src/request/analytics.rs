@@ -0,0 +1,213 @@
+//! Product analytics (with `analytics` feature).
+//!
+//! Usage events (e.g. searches run, alert channels created) are recorded for
+//! a sample of them (`ANALYTICS_SAMPLE_RATE` in percent) without anything
+//! which identifies users: the actor is a hash of the user with
+//! `ANALYTICS_SALT`, and the time is truncated to the hour. They are pushed
+//! into the list `analytics-events` in the session store, then
+//! `FlushAnalyticsEvents` job (e.g. by cron) saves them into
+//! `analytics_events`. Nothing is sent to third parties.
+//!
+//! Without the feature (the default for self-hosted installations), nothing
+//! is recorded even if the rate is set.
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use rand::{Rng, thread_rng};
+use rocket::{Request, State, request};
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::model::analytics_event::NewAnalyticsEvent;
+use crate::model::user::User;
+use crate::service::user_agent::sampled;
+use crate::ss::SsConn;
+use crate::util::hash_token;
+
+pub const KEY: &str = "analytics-events";
+
+// the oldest ones are dropped if they are not flushed
+const MAX_PENDING: isize = 100_000;
+
+/// The names of events.
+pub const SEARCH: &str = "search";
+pub const CHANNEL_CREATED: &str = "channel_created";
+
+/// An event which is not flushed yet (JSON in the list).
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct PendingEvent {
+    pub name: String,
+    pub actor: Option<String>,
+    pub properties: BTreeMap<String, String>,
+    /// unix time (truncated to the hour)
+    pub occurred_at: i64,
+}
+
+impl PendingEvent {
+    pub fn into_new(self) -> NewAnalyticsEvent {
+        let properties = serde_json::to_string(&self.properties)
+            .unwrap_or_else(|_| "{}".to_string());
+        NewAnalyticsEvent {
+            name: self.name,
+            actor: self.actor,
+            properties,
+            occurred_at: NaiveDateTime::from_timestamp(self.occurred_at, 0),
+        }
+    }
+}
+
+/// Returns the hash of the user uuid with the salt (None for an empty salt).
+pub fn actor(salt: &str, user_uuid: &str) -> Option<String> {
+    if salt.is_empty() {
+        return None;
+    }
+    Some(hash_token(&format!("{}:{}", salt, user_uuid)))
+}
+
+pub fn truncate_to_hour(timestamp: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(3600)
+}
+
+/// Analytics
+///
+/// This never rejects requests. Events are just not recorded if the session
+/// store is not available.
+pub struct Analytics {
+    ss_conn: Option<SsConn>,
+    sample_rate: u32,
+    salt: String,
+    clock: SharedClock,
+    logger: Logger,
+}
+
+impl Analytics {
+    pub fn enabled(sample_rate: u32) -> bool {
+        cfg!(feature = "analytics") && sample_rate > 0
+    }
+
+    /// Records the event by the user if it's sampled.
+    pub fn record(
+        &mut self,
+        user: &User,
+        name: &str,
+        properties: &[(&str, String)],
+    ) {
+        if !Self::enabled(self.sample_rate)
+            || !sampled(self.sample_rate, thread_rng().gen_range(0..100))
+        {
+            return;
+        }
+        let ss_conn = match self.ss_conn {
+            Some(ref mut conn) => conn,
+            None => return,
+        };
+
+        let event = PendingEvent {
+            name: name.to_string(),
+            actor: actor(&self.salt, &user.uuid.to_string()),
+            properties: properties
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            occurred_at: truncate_to_hour(self.clock.now().timestamp()),
+        };
+        let value = match serde_json::to_string(&event) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(self.logger, "err: {}", e);
+                return;
+            },
+        };
+        let result: Result<(), _> = redis::pipe()
+            .rpush(KEY, value)
+            .ignore()
+            .ltrim(KEY, -MAX_PENDING, -1)
+            .ignore()
+            .query(&mut **ss_conn);
+        if let Err(e) = result {
+            error!(self.logger, "err: {}", e);
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Analytics {
+    type Error = ();
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let config = req.guard::<State<Config>>().unwrap();
+        let clock = req.guard::<State<SharedClock>>().unwrap();
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        // a connection is taken only if it's needed
+        let sample_rate = config.analytics_sample_rate;
+        let ss_conn = if Self::enabled(sample_rate) {
+            match req.guard::<SsConn>() {
+                request::Outcome::Success(conn) => Some(conn),
+                _ => {
+                    error!(logger, "err: session store is not available");
+                    None
+                },
+            }
+        } else {
+            None
+        };
+
+        request::Outcome::Success(Analytics {
+            ss_conn,
+            sample_rate,
+            salt: config.analytics_salt.to_string(),
+            clock: clock.inner().clone(),
+            logger: (*logger).clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_actor() {
+        let uuid = "4b9f6a3e-5c2d-4e8f-9a1b-2c3d4e5f6a7b";
+        assert_eq!(actor("", uuid), None);
+
+        let a = actor("salt", uuid).unwrap();
+        assert_eq!(a.len(), 64);
+        assert_ne!(a, hash_token(uuid));
+        assert_eq!(actor("salt", uuid), Some(a.to_string()));
+        assert_ne!(actor("pepper", uuid), Some(a));
+    }
+
+    #[test]
+    fn test_truncate_to_hour() {
+        assert_eq!(truncate_to_hour(1_625_734_799), 1_625_731_200);
+        assert_eq!(truncate_to_hour(1_625_731_200), 1_625_731_200);
+    }
+
+    #[test]
+    fn test_into_new() {
+        let mut properties = BTreeMap::new();
+        properties.insert("kind".to_string(), "email".to_string());
+        let e = PendingEvent {
+            name: CHANNEL_CREATED.to_string(),
+            actor: None,
+            properties,
+            occurred_at: 1_625_731_200,
+        }
+        .into_new();
+        assert_eq!(e.name, "channel_created");
+        assert_eq!(e.properties, r#"{"kind":"email"}"#);
+        assert_eq!(e.occurred_at.to_string(), "2021-07-08 08:00:00");
+    }
+
+    #[test]
+    fn test_enabled() {
+        assert!(!Analytics::enabled(0));
+        assert_eq!(Analytics::enabled(10), cfg!(feature = "analytics"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Versioning of the public API.
+//!
+//! The public API is mounted at `/_api/v<N>` (see `server`). The version is
+//! taken from the path, and the former mount point `/v1` keeps working as
+//! version 1 for the deployed shippers. Other paths (e.g. `/_` for the web
+//! console) may ask for a version by `X-Api-Version` header, otherwise they
+//! get the current one.
+//!
+//! Routes whose schema changes by the version (e.g. messages) take
+//! `ApiVersion` guard. It rejects unsupported (or conflicting) versions with
+//! 406 Not Acceptable, and `Response` puts the negotiated one into
+//! `X-Api-Version` header.
+use rocket::{Request, request};
+use rocket::http::Status;
+use rocket::request::FromRequest;
+
+pub const PREFIX: &str = "/_api";
+
+pub const HEADER: &str = "X-Api-Version";
+
+/// The latest version.
+pub const CURRENT: u32 = 1;
+
+pub const SUPPORTED: &[u32] = &[1];
+
+// the mount point before `PREFIX`
+const LEGACY_PREFIX: &str = "/v1";
+
+/// Returns the mount point of the version e.g. `/_api/v1`.
+pub fn base(version: u32) -> String {
+    format!("{}/v{}", PREFIX, version)
+}
+
+/// Parses a version like `1` or `v1`.
+pub fn parse(s: &str) -> Option<u32> {
+    let s = s.trim();
+    s.strip_prefix('v').unwrap_or(s).parse::<u32>().ok()
+}
+
+/// Returns the version in the path (if it's in a versioned mount point).
+pub fn version_in_path(path: &str) -> Option<u32> {
+    if let Some(rest) = path.strip_prefix(LEGACY_PREFIX) {
+        if rest.is_empty() || rest.starts_with('/') {
+            return Some(1);
+        }
+    }
+    let rest = path.strip_prefix(PREFIX)?.strip_prefix("/v")?;
+    rest.split('/').next().and_then(|v| v.parse::<u32>().ok())
+}
+
+/// Negotiates the version by the path and the header in the request (None if
+/// it's not acceptable).
+pub fn negotiate(path: &str, header: Option<&str>) -> Option<u32> {
+    let requested = match header {
+        Some(v) => Some(parse(v)?),
+        None => None,
+    };
+    let version = match (version_in_path(path), requested) {
+        (Some(a), Some(b)) if a != b => return None,
+        (Some(v), _) | (None, Some(v)) => v,
+        (None, None) => CURRENT,
+    };
+    Some(version).filter(|v| SUPPORTED.contains(v))
+}
+
+/// ApiVersion
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ApiVersion(pub u32);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ApiVersion {
+    type Error = ();
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let header = req.headers().get_one(HEADER);
+        match negotiate(req.uri().path(), header) {
+            Some(v) => {
+                let version = ApiVersion(v);
+                req.local_cache(|| Some(version));
+                request::Outcome::Success(version)
+            },
+            None => request::Outcome::Failure((Status::NotAcceptable, ())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_base() {
+        assert_eq!(base(1), "/_api/v1");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("1"), Some(1));
+        assert_eq!(parse(" v2 "), Some(2));
+        assert_eq!(parse("latest"), None);
+    }
+
+    #[test]
+    fn test_version_in_path() {
+        assert_eq!(version_in_path("/_api/v1/namespace/hgetall"), Some(1));
+        assert_eq!(version_in_path("/_api/v2/namespace/hgetall"), Some(2));
+        assert_eq!(version_in_path("/v1/namespace/hgetall"), Some(1));
+        assert_eq!(version_in_path("/_/login"), None);
+        assert_eq!(version_in_path("/_api/namespace/hgetall"), None);
+        assert_eq!(version_in_path("/v10/namespace/hgetall"), None);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("/_api/v1/message", None), Some(1));
+        assert_eq!(negotiate("/v1/message", Some("1")), Some(1));
+        assert_eq!(negotiate("/_/namespace", None), Some(CURRENT));
+        assert_eq!(negotiate("/_/namespace", Some("v1")), Some(1));
+
+        // unsupported, conflicting or invalid
+        assert_eq!(negotiate("/_api/v2/message", None), None);
+        assert_eq!(negotiate("/_/namespace", Some("2")), None);
+        assert_eq!(negotiate("/_api/v1/message", Some("2")), None);
+        assert_eq!(negotiate("/_/namespace", Some("latest")), None);
+    }
+}
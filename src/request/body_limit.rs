@@ -0,0 +1,84 @@
+//! Request body size limits by route group.
+//!
+//! Data guards (`JsonBody`, `Protobuf` and the login one) read the bodies
+//! within the limit of their group, e.g. a small one for authentication and
+//! a large one for messages sent by shippers. A body over it fails with 413
+//! Payload Too Large. The exceeded limit is kept in the request local cache,
+//! then the catcher tells it in the message.
+use rocket::{Data, Outcome, Request, State};
+
+use crate::config::Config;
+use crate::request::encoding::{self, EncodingError};
+
+const DEFAULT_LIMIT: u64 = 5 * 1024 * 1024; // 5 MB
+
+/// A group of routes which share a limit.
+pub trait BodyLimitGroup {
+    /// Returns the max size of bodies (bytes).
+    fn limit(config: &Config) -> u64;
+}
+
+/// Login, registration, sudo mode and password reset
+pub struct Auth;
+
+impl BodyLimitGroup for Auth {
+    fn limit(config: &Config) -> u64 {
+        config.server_limit_json_auth
+    }
+}
+
+/// The others (JSON)
+pub struct Standard;
+
+impl BodyLimitGroup for Standard {
+    fn limit(config: &Config) -> u64 {
+        config.server_limit_json
+    }
+}
+
+/// Messages sent as JSON (may be batches by shippers)
+pub struct Batch;
+
+impl BodyLimitGroup for Batch {
+    fn limit(config: &Config) -> u64 {
+        config.server_limit_json_batch
+    }
+}
+
+/// Messages sent as Protocol Buffers
+pub struct Binary;
+
+impl BodyLimitGroup for Binary {
+    fn limit(config: &Config) -> u64 {
+        config.server_limit_protobuf
+    }
+}
+
+/// BodyLimitState
+///
+/// This is cached per request if the body is too large.
+#[derive(Clone, Debug)]
+pub struct BodyLimitState {
+    pub limit: u64, // bytes
+}
+
+/// Returns the limit of the group.
+pub fn limit_of<G: BodyLimitGroup>(req: &Request) -> u64 {
+    match req.guard::<State<Config>>() {
+        Outcome::Success(config) => G::limit(&config),
+        _ => DEFAULT_LIMIT,
+    }
+}
+
+/// Reads the whole body within the limit of the group (see
+/// `encoding::read_body`).
+pub fn read_body<G: BodyLimitGroup>(
+    req: &Request,
+    data: Data,
+) -> Result<Vec<u8>, EncodingError> {
+    let result = encoding::read_body(req, data, limit_of::<G>(req));
+    if let Err(EncodingError::TooLarge(limit)) = result {
+        req.local_cache(|| Some(BodyLimitState { limit }));
+    }
+    result
+}
@@ -0,0 +1,9 @@
+/// BulkOperation
+///
+/// `action` and `argument` are not used for the count (preview).
+#[derive(Clone, Default, Deserialize)]
+pub struct BulkOperation {
+    pub filter: String,
+    pub action: Option<String>,
+    pub argument: Option<String>,
+}
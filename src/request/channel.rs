@@ -0,0 +1,12 @@
+/// Channel
+///
+/// Only `kind` and `template` are used for the preview. `rollup_window` is in
+/// seconds.
+#[derive(Clone, Default, Deserialize)]
+pub struct Channel {
+    pub kind: Option<String>,
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub template: Option<String>,
+    pub rollup_window: Option<i32>,
+}
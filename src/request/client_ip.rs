@@ -0,0 +1,36 @@
+//! ClientIp
+use std::net::IpAddr;
+
+use rocket::{Request, State, request};
+use rocket::request::FromRequest;
+
+use crate::config::Config;
+
+/// Returns the address of the client. It's the one in `X-Real-IP` header only
+/// if the remote one is a trusted proxy (`SERVER_TRUSTED_PROXIES`), as the
+/// header can be sent by anyone.
+pub fn client_ip(req: &Request, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let remote = req.remote()?.ip();
+    if trusted_proxies.contains(&remote) {
+        return req.real_ip().or(Some(remote));
+    }
+    Some(remote)
+}
+
+/// The address of the client (see `client_ip`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClientIp(pub IpAddr);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ClientIp {
+    type Error = ();
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let config = req.guard::<State<Config>>().unwrap();
+        match client_ip(req, &config.server_trusted_proxies) {
+            Some(ip) => request::Outcome::Success(ClientIp(ip)),
+            None => request::Outcome::Forward(()),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Concurrency limiting guard (bulkhead) for expensive route groups.
+//!
+//! Each group has its own cap of in-flight requests per server process, so
+//! heavy queries can't starve the other routes such as ingestion and login.
+//! A permit is held by the guard and released when it's dropped after the
+//! handler. If the group is saturated, the request fails with 503 and
+//! `Response` puts `Retry-After` header based on the state in the request
+//! local cache.
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use fnv::FnvHashMap;
+use parking_lot::Mutex;
+use rocket::{Request, State, request};
+use rocket::http::Status;
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+
+use crate::config::Config;
+
+/// A group of routes which share a cap.
+pub trait ConcurrencyGroup {
+    const NAME: &'static str;
+
+    /// Returns the number of in-flight requests (0 means unlimited).
+    fn limit(config: &Config) -> u32;
+}
+
+pub struct Search;
+
+impl ConcurrencyGroup for Search {
+    const NAME: &'static str = "search";
+
+    fn limit(config: &Config) -> u32 {
+        config.concurrency_search_limit
+    }
+}
+
+pub struct Export;
+
+impl ConcurrencyGroup for Export {
+    const NAME: &'static str = "export";
+
+    fn limit(config: &Config) -> u32 {
+        config.concurrency_export_limit
+    }
+}
+
+/// ConcurrencyLimitState
+///
+/// This is cached per request for the `Retry-After` header.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimitState {
+    pub retry_after: u64, // seconds
+}
+
+#[derive(Debug)]
+pub enum ConcurrencyLimitError {
+    Saturated,
+}
+
+/// Counters of in-flight requests for all groups (managed state).
+#[derive(Default)]
+pub struct Bulkheads {
+    counters: Mutex<FnvHashMap<&'static str, Arc<AtomicU32>>>,
+}
+
+/// A slot in a group, it's released on drop.
+pub struct Permit(Arc<AtomicU32>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Bulkheads {
+    /// Returns a permit for the group if it has a free slot.
+    pub fn acquire(&self, name: &'static str, limit: u32) -> Option<Permit> {
+        let counter = self
+            .counters
+            .lock()
+            .entry(name)
+            .or_insert_with(|| Arc::new(AtomicU32::new(0)))
+            .clone();
+
+        let permit = Permit(counter);
+        if permit.0.fetch_add(1, Ordering::SeqCst) >= limit {
+            return None; // dropped
+        }
+        Some(permit)
+    }
+}
+
+pub struct ConcurrencyLimit<G: ConcurrencyGroup> {
+    _permit: Option<Permit>,
+    group: PhantomData<G>,
+}
+
+impl<'a, 'r, G> FromRequest<'a, 'r> for ConcurrencyLimit<G>
+where G: ConcurrencyGroup
+{
+    type Error = ConcurrencyLimitError;
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let config = req.guard::<State<Config>>().unwrap();
+        let limit = G::limit(&config);
+        if limit == 0 {
+            return request::Outcome::Success(ConcurrencyLimit {
+                _permit: None,
+                group: PhantomData,
+            });
+        }
+
+        let bulkheads = req.guard::<State<Bulkheads>>().unwrap();
+        match bulkheads.acquire(G::NAME, limit) {
+            Some(permit) => {
+                request::Outcome::Success(ConcurrencyLimit {
+                    _permit: Some(permit),
+                    group: PhantomData,
+                })
+            },
+            None => {
+                let logger = req.guard::<SyncLogger>().unwrap();
+                warn!(logger, "concurrency limit saturated: {}", G::NAME);
+
+                let state = ConcurrencyLimitState {
+                    retry_after: config.concurrency_retry_after,
+                };
+                req.local_cache(|| Some(state));
+                request::Outcome::Failure((
+                    Status::ServiceUnavailable,
+                    ConcurrencyLimitError::Saturated,
+                ))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_acquire() {
+        let bulkheads = Bulkheads::default();
+
+        let a = bulkheads.acquire("search", 2);
+        let b = bulkheads.acquire("search", 2);
+        assert!(a.is_some());
+        assert!(b.is_some());
+        assert!(bulkheads.acquire("search", 2).is_none());
+
+        // other groups are not affected
+        assert!(bulkheads.acquire("export", 1).is_some());
+
+        drop(a);
+        let c = bulkheads.acquire("search", 2);
+        assert!(c.is_some());
+        assert!(bulkheads.acquire("search", 2).is_none());
+
+        drop(b);
+        drop(c);
+        assert!(bulkheads.acquire("search", 2).is_some());
+    }
+}
@@ -0,0 +1,19 @@
+//! The confirmation token for destructive API calls (see
+//! `service::confirmation`).
+use rocket::Request;
+use rocket::request::{FromRequest, Outcome};
+
+pub const HEADER_NAME: &str = "X-Confirmation-Token";
+
+/// A token in X-Confirmation-Token header (empty if it's not given).
+#[derive(Debug, Default)]
+pub struct ConfirmationToken(pub String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ConfirmationToken {
+    type Error = ();
+
+    fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let value = req.headers().get_one(HEADER_NAME).unwrap_or("");
+        Outcome::Success(Self(value.trim().to_string()))
+    }
+}
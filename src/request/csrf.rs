@@ -0,0 +1,126 @@
+//! CSRF token for form posts from the browser.
+//!
+//! The token is issued by a preignition (HEAD) route as a private cookie and
+//! its key is kept in the session store until it expires. The guard verifies
+//! both of them.
+//!
+//! NOTE:
+//! This guard must be put before `Cookies` in handler arguments, because only
+//! one `Cookies` instance can be active at once.
+use redis::{Commands, RedisError};
+use rocket::Request;
+use rocket::http::{Cookie, Cookies, SameSite};
+use rocket::request::{FromRequest, Outcome};
+use rocket_slog::SyncLogger;
+
+use crate::config::Config;
+use crate::id::IdGenerator;
+use crate::logger::Logger;
+use crate::ss::SsConn;
+
+use crate::unauthorized_by;
+
+pub const COOKIE_NAME: &str = "csrf_token";
+pub const KEY_PREFIX: &str = "xs-";
+
+pub struct CsrfToken(pub String);
+
+#[derive(Debug)]
+pub enum CsrfTokenError {
+    Expired,
+    Missing,
+}
+
+impl CsrfTokenError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            CsrfTokenError::Expired => {
+                "The CSRF token has been expired. Reload the page."
+            },
+            CsrfTokenError::Missing => "The CSRF token is required.",
+        }
+    }
+}
+
+impl CsrfToken {
+    /// Generates a new token, then saves it into the session store and sets
+    /// it as a private cookie.
+    pub fn issue(
+        cookies: &mut Cookies,
+        ss_conn: &mut SsConn,
+        config: &Config,
+        ids: &dyn IdGenerator,
+        logger: &Logger,
+    ) -> Result<Self, RedisError> {
+        let key_value =
+            ids.random_hash(Config::CSRF_HASH_SOURCE, Config::CSRF_HASH_LENGTH);
+        let key = format!("{}{}", KEY_PREFIX, key_value);
+        let duration = (Config::CSRF_HASH_DURATION * 60) as usize; // seconds
+
+        let _: String = ss_conn.set_ex(&key, "1", duration).map_err(|e| {
+            error!(logger, "error: {}", e);
+            e
+        })?;
+
+        let mut cookie = Cookie::new(COOKIE_NAME, key.clone());
+        cookie.set_http_only(true);
+        cookie.set_secure(config.cookie_secure);
+        cookie.set_same_site(SameSite::Strict);
+        // encrypted value with expires 1 week from now
+        cookies.add_private(cookie);
+
+        Ok(CsrfToken(key))
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for CsrfToken {
+    type Error = CsrfTokenError;
+
+    fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        let key = match req.cookies().get_private(COOKIE_NAME) {
+            Some(ref c) if c.value().starts_with(KEY_PREFIX) => {
+                c.value().to_string()
+            },
+            _ => {
+                info!(logger, "error: missing csrf_token");
+                return unauthorized_by!(CsrfTokenError::Missing);
+            },
+        };
+
+        let mut ss_conn = match req.guard::<SsConn>() {
+            Outcome::Success(conn) => conn,
+            _ => {
+                error!(logger, "err: session store is not available");
+                return unauthorized_by!(CsrfTokenError::Expired);
+            },
+        };
+        let result: Result<bool, RedisError> = ss_conn.exists(&key);
+        match result {
+            Ok(true) => Outcome::Success(CsrfToken(key)),
+            Ok(false) => unauthorized_by!(CsrfTokenError::Expired),
+            Err(e) => {
+                error!(logger, "error: {}", e);
+                unauthorized_by!(CsrfTokenError::Expired)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_message() {
+        assert_eq!(
+            CsrfTokenError::Missing.message(),
+            "The CSRF token is required."
+        );
+        assert_eq!(
+            CsrfTokenError::Expired.message(),
+            "The CSRF token has been expired. Reload the page."
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Deduplication window of ingestion.
+//!
+//! If `INGESTION_DEDUPLICATION_WINDOW` is set, the first message of a
+//! fingerprint in a stream is kept in the session store as `dw-<stream_id>:
+//! <digest>` (the value is the id of the message) until the window passes.
+//! The identical ones in the meantime are counted on it as occurrences
+//! (`messages.occurrences_count`) instead of being saved.
+use rocket::{Request, State, request};
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::model::stream::Stream;
+use crate::request::message::Message as RequestData;
+use crate::ss::SsConn;
+use crate::util::hash_token;
+
+pub const KEY_PREFIX: &str = "dw-";
+
+/// Returns the fingerprint given by the client, or the one of the title and
+/// the content.
+pub fn fingerprint_of(data: &RequestData) -> String {
+    match data.fingerprint {
+        Some(ref v) => v.to_string(),
+        None => format!(
+            "{}\n{}",
+            data.title.as_deref().unwrap_or(""),
+            data.content.as_deref().unwrap_or("")
+        ),
+    }
+}
+
+pub fn window_key(stream_id: i64, fingerprint: &str) -> String {
+    format!("{}{}:{}", KEY_PREFIX, stream_id, hash_token(fingerprint))
+}
+
+/// DuplicateWindow
+///
+/// This never rejects requests. Messages are not deduplicated if it's
+/// disabled or the session store is not available.
+pub struct DuplicateWindow {
+    ss_conn: Option<SsConn>,
+    window: u64,
+    logger: Logger,
+}
+
+impl DuplicateWindow {
+    /// Claims the fingerprint for the new message (id) in the stream. This
+    /// returns the id of the first message if it's a duplicate within the
+    /// window. The stream must be the one resolved from the namespace and
+    /// the slug of the request, as the fingerprints of other tenants are
+    /// not duplicates.
+    pub fn claim(
+        &mut self,
+        stream: &Stream,
+        fingerprint: &str,
+        id: &str,
+    ) -> Option<String> {
+        if self.window == 0 {
+            return None;
+        }
+        let ss_conn = match self.ss_conn {
+            Some(ref mut conn) => conn,
+            None => return None,
+        };
+        let key = window_key(stream.id, fingerprint);
+        let result: Result<(Option<String>, Option<String>), _> =
+            redis::pipe()
+                .cmd("SET")
+                .arg(&key)
+                .arg(id)
+                .arg("NX")
+                .arg("EX")
+                .arg(self.window)
+                .get(&key)
+                .query(&mut **ss_conn);
+        match result {
+            // it's the first one
+            Ok((Some(_), _)) => None,
+            Ok((None, first)) => first.filter(|v| v != id),
+            Err(e) => {
+                error!(self.logger, "err: {}", e);
+                None
+            },
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for DuplicateWindow {
+    type Error = ();
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let config = req.guard::<State<Config>>().unwrap();
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        let window = config.ingestion_deduplication_window;
+        let ss_conn = if window == 0 {
+            None
+        } else {
+            match req.guard::<SsConn>() {
+                request::Outcome::Success(conn) => Some(conn),
+                _ => {
+                    error!(logger, "err: session store is not available");
+                    None
+                },
+            }
+        };
+
+        request::Outcome::Success(DuplicateWindow {
+            ss_conn,
+            window,
+            logger: (*logger).clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_of() {
+        let data = RequestData {
+            title: Some("title".to_string()),
+            content: Some("content".to_string()),
+
+            ..Default::default()
+        };
+        assert_eq!(fingerprint_of(&data), "title\ncontent");
+
+        let data = RequestData {
+            title: Some("title".to_string()),
+            fingerprint: Some("fp".to_string()),
+
+            ..Default::default()
+        };
+        assert_eq!(fingerprint_of(&data), "fp");
+    }
+
+    #[test]
+    fn test_window_key() {
+        let key = window_key(3, "fp");
+        assert!(key.starts_with("dw-3:"));
+        assert_eq!(key, window_key(3, "fp"));
+        assert_ne!(key, window_key(4, "fp"));
+        assert_ne!(key, window_key(3, "fp2"));
+    }
+}
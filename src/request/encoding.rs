@@ -0,0 +1,208 @@
+//! Request body decoding for Content-Encoding.
+//!
+//! Shippers may compress batches with `gzip` or `zstd`. The body is
+//! decompressed while it's read, and it fails with 413 if the result exceeds
+//! `ingestion_max_decompressed_size` (against zip bombs). The size on the wire
+//! is limited by the route group (see `request::body_limit`).
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use rocket::{Data, Outcome, Request, State};
+use rocket::http::Status;
+
+use crate::config::Config;
+
+const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 50 * 1024 * 1024; // 50 MB
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    Zstd,
+}
+
+impl ContentEncoding {
+    /// Returns None if the encoding is not supported.
+    pub fn from_header(value: Option<&str>) -> Option<Self> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("identity") => {
+                Some(ContentEncoding::Identity)
+            },
+            Some("gzip") | Some("x-gzip") => Some(ContentEncoding::Gzip),
+            Some("zstd") => Some(ContentEncoding::Zstd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EncodingError {
+    Invalid,
+    /// The limit (bytes) which has been exceeded
+    TooLarge(u64),
+    Unsupported,
+}
+
+impl EncodingError {
+    pub fn status(&self) -> Status {
+        match self {
+            EncodingError::Invalid => Status::BadRequest,
+            EncodingError::TooLarge(_) => Status::PayloadTooLarge,
+            EncodingError::Unsupported => Status::UnsupportedMediaType,
+        }
+    }
+}
+
+/// Reads the whole body with decoding by its Content-Encoding.
+///
+/// The `limit` is the max size (bytes) of the body as it's sent.
+pub fn read_body(
+    req: &Request,
+    data: Data,
+    limit: u64,
+) -> Result<Vec<u8>, EncodingError> {
+    let encoding =
+        ContentEncoding::from_header(req.headers().get_one("Content-Encoding"))
+            .ok_or(EncodingError::Unsupported)?;
+
+    let max = match req.guard::<State<Config>>() {
+        Outcome::Success(config) => config.ingestion_max_decompressed_size,
+        _ => DEFAULT_MAX_DECOMPRESSED_SIZE,
+    };
+
+    let body = read_to_end(data.open(), limit)?;
+    match encoding {
+        ContentEncoding::Identity => Ok(body),
+        ContentEncoding::Gzip => read_to_end(GzDecoder::new(&body[..]), max),
+        ContentEncoding::Zstd => {
+            let decoder = zstd::stream::read::Decoder::new(&body[..])
+                .map_err(|_| EncodingError::Invalid)?;
+            read_to_end(decoder, max)
+        },
+    }
+}
+
+// reads at most max bytes, and fails if there are more
+fn read_to_end<R: Read>(r: R, max: u64) -> Result<Vec<u8>, EncodingError> {
+    let mut buf = Vec::new();
+    r.take(max.saturating_add(1))
+        .read_to_end(&mut buf)
+        .map_err(|_| EncodingError::Invalid)?;
+    if buf.len() as u64 > max {
+        return Err(EncodingError::TooLarge(max));
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use proptest::prelude::*;
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(body).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn test_content_encoding_from_header() {
+        assert_eq!(
+            ContentEncoding::from_header(None),
+            Some(ContentEncoding::Identity)
+        );
+        assert_eq!(
+            ContentEncoding::from_header(Some("identity")),
+            Some(ContentEncoding::Identity)
+        );
+        assert_eq!(
+            ContentEncoding::from_header(Some(" GZIP ")),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(
+            ContentEncoding::from_header(Some("zstd")),
+            Some(ContentEncoding::Zstd)
+        );
+        assert_eq!(ContentEncoding::from_header(Some("br")), None);
+        assert_eq!(ContentEncoding::from_header(Some("gzip, zstd")), None);
+    }
+
+    #[test]
+    fn test_read_to_end_gzip() {
+        let compressed = gzip(b"hello");
+
+        let result = read_to_end(GzDecoder::new(&compressed[..]), 5);
+        assert_eq!(result, Ok(b"hello".to_vec()));
+
+        let result = read_to_end(GzDecoder::new(&compressed[..]), 4);
+        assert_eq!(result, Err(EncodingError::TooLarge(4)));
+
+        let result = read_to_end(GzDecoder::new(&b"hello"[..]), 5);
+        assert_eq!(result, Err(EncodingError::Invalid));
+    }
+
+    #[test]
+    fn test_read_to_end_zstd() {
+        // highly compressible (like a zip bomb)
+        let body = vec![b'a'; 1024 * 1024];
+        let compressed = zstd::stream::encode_all(&body[..], 3).unwrap();
+        assert!(compressed.len() < 1024);
+
+        let decoder = zstd::stream::read::Decoder::new(&compressed[..]);
+        let result = read_to_end(decoder.unwrap(), 1024);
+        assert_eq!(result, Err(EncodingError::TooLarge(1024)));
+
+        let decoder = zstd::stream::read::Decoder::new(&compressed[..]);
+        let result = read_to_end(decoder.unwrap(), 1024 * 1024);
+        assert_eq!(result.map(|v| v.len()), Ok(1024 * 1024));
+    }
+
+    // compressed bodies come from agents, so broken ones must be rejected
+    // without panics, and must never be read over the max
+    proptest! {
+        #[test]
+        fn test_read_to_end_gzip_with_any_bytes(
+            body in prop::collection::vec(any::<u8>(), 0..1024),
+            max in 0..2048u64,
+        ) {
+            let result = read_to_end(GzDecoder::new(&body[..]), max);
+            if let Ok(v) = result {
+                prop_assert!(v.len() as u64 <= max);
+            }
+        }
+
+        #[test]
+        fn test_read_to_end_zstd_with_any_bytes(
+            body in prop::collection::vec(any::<u8>(), 0..1024),
+            max in 0..2048u64,
+        ) {
+            if let Ok(d) = zstd::stream::read::Decoder::new(&body[..]) {
+                if let Ok(v) = read_to_end(d, max) {
+                    prop_assert!(v.len() as u64 <= max);
+                }
+            }
+        }
+
+        #[test]
+        fn test_read_to_end_gzip_roundtrip(
+            body in prop::collection::vec(any::<u8>(), 0..4096),
+        ) {
+            let compressed = gzip(&body);
+            let max = body.len() as u64;
+
+            let result = read_to_end(GzDecoder::new(&compressed[..]), max);
+            prop_assert_eq!(result, Ok(body));
+
+            if max > 0 {
+                let result =
+                    read_to_end(GzDecoder::new(&compressed[..]), max - 1);
+                prop_assert_eq!(result, Err(EncodingError::TooLarge(max - 1)));
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Conditional GET with entity tags.
+//!
+//! List endpoints return an `ETag` which is generated from the latest
+//! `updated_at` and the number of records. If the client sends it back as
+//! `If-None-Match` and nothing has been changed, they respond with 304 Not
+//! Modified without body, so polling dashboards don't re-download the same
+//! data.
+use chrono::NaiveDateTime;
+use rocket::Request;
+use rocket::request::{FromRequest, Outcome};
+
+/// Returns a (strong) entity tag for a list.
+pub fn make_etag(updated_at: Option<NaiveDateTime>, count: i64) -> String {
+    let timestamp = updated_at.map(|t| t.timestamp_nanos()).unwrap_or(0);
+    format!("\"{:x}-{:x}\"", timestamp, count)
+}
+
+/// Entity tags in If-None-Match header (empty if it's not given).
+#[derive(Debug, Default)]
+pub struct IfNoneMatch(pub Vec<String>);
+
+impl IfNoneMatch {
+    pub fn parse(value: &str) -> Self {
+        Self(
+            value
+                .split(',')
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string())
+                .collect(),
+        )
+    }
+
+    /// Uses the weak comparison (RFC 7232 Section 3.2).
+    pub fn matches(&self, etag: &str) -> bool {
+        let etag = etag.trim_start_matches("W/");
+        self.0
+            .iter()
+            .any(|v| v == "*" || v.trim_start_matches("W/") == etag)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfNoneMatch {
+    type Error = ();
+
+    fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let value = req.headers().get("If-None-Match").collect::<Vec<_>>();
+        Outcome::Success(Self::parse(&value.join(",")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_make_etag() {
+        let dt = NaiveDate::from_ymd(2019, 8, 7).and_hms(6, 5, 4);
+        assert_eq!(make_etag(None, 0), r#""0-0""#);
+        assert_eq!(make_etag(Some(dt), 10), r#""15b88ed23550a000-a""#);
+        assert_ne!(make_etag(Some(dt), 10), make_etag(Some(dt), 11));
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(!IfNoneMatch::parse("").matches(r#""0-a""#));
+        assert!(IfNoneMatch::parse("*").matches(r#""0-a""#));
+        assert!(IfNoneMatch::parse(r#""0-a""#).matches(r#""0-a""#));
+        assert!(IfNoneMatch::parse(r#"W/"0-a""#).matches(r#""0-a""#));
+        assert!(IfNoneMatch::parse(r#""0-9", "0-a""#).matches(r#""0-a""#));
+        assert!(!IfNoneMatch::parse(r#""0-9""#).matches(r#""0-a""#));
+    }
+}
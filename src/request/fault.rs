@@ -0,0 +1,10 @@
+/// Fault
+///
+/// Faults to inject (see `service::fault_injection`). `smtp_delay` is in
+/// milliseconds, and `routes` are paths like `/v1/message/.../lrange`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Fault {
+    pub redis_failures: Option<u64>,
+    pub smtp_delay: Option<u64>,
+    pub routes: Option<Vec<String>>,
+}
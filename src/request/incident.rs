@@ -0,0 +1,10 @@
+/// Incident
+///
+/// Only `title` and `message_ids` are used for a new incident, `state` for
+/// the change of it, and `message_ids` for the attachment.
+#[derive(Clone, Default, Deserialize)]
+pub struct Incident {
+    pub title: Option<String>,
+    pub state: Option<String>,
+    pub message_ids: Option<Vec<String>>,
+}
@@ -0,0 +1,60 @@
+//! JSON data guard with Content-Encoding support.
+//!
+//! This works like `rocket_contrib::json::Json`, but the body may be
+//! compressed (see `request::encoding`), and its size is limited by the route
+//! group `G` (see `request::body_limit`).
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use rocket::data::{self, Data, FromDataSimple};
+use rocket::http::Status;
+use rocket::Outcome::{Failure, Success};
+use rocket::Request;
+use serde::de::DeserializeOwned;
+
+use crate::request::body_limit::{BodyLimitGroup, Standard, read_body};
+use crate::request::encoding::EncodingError;
+
+#[derive(Debug)]
+pub enum JsonError {
+    Encoding(EncodingError),
+    Parse(serde_json::Error),
+}
+
+pub struct JsonBody<T, G: BodyLimitGroup = Standard>(pub T, PhantomData<G>);
+
+impl<T, G: BodyLimitGroup> JsonBody<T, G> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, G: BodyLimitGroup> Deref for JsonBody<T, G> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned, G: BodyLimitGroup> FromDataSimple for JsonBody<T, G> {
+    type Error = JsonError;
+
+    fn from_data(
+        req: &Request,
+        data: Data,
+    ) -> data::Outcome<Self, Self::Error> {
+        let buf = match read_body::<G>(req, data) {
+            Ok(v) => v,
+            Err(e) => return Failure((e.status(), JsonError::Encoding(e))),
+        };
+        match serde_json::from_slice(&buf) {
+            Ok(v) => Success(JsonBody(v, PhantomData)),
+            // same as Json (syntax -> 400, data -> 422)
+            Err(e) if e.is_data() => {
+                Failure((Status::UnprocessableEntity, JsonError::Parse(e)))
+            },
+            Err(e) => Failure((Status::BadRequest, JsonError::Parse(e))),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Guard for the local-only endpoints for operators (e.g. maintenance mode,
+//! metrics and config reload).
+//!
+//! A request must come from the host itself (loopback) with the token of
+//! `SERVER_ADMIN_TOKEN` as X-Admin-Token header. The address alone is not
+//! enough, as every request looks local behind a reverse proxy on the same
+//! host. The endpoints are disabled if the token is empty.
+use std::net::SocketAddr;
+
+use rocket::{Request, State};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+
+use crate::config::Config;
+use crate::util::constant_time_eq;
+
+pub const HEADER: &str = "X-Admin-Token";
+
+pub struct LocalOnly;
+
+pub fn is_allowed(
+    admin_token: &str,
+    remote: Option<SocketAddr>,
+    token: Option<&str>,
+) -> bool {
+    if admin_token.is_empty() {
+        return false;
+    }
+    let is_local = remote.map(|a| a.ip().is_loopback()).unwrap_or(false);
+    match token {
+        Some(t) if is_local => {
+            constant_time_eq(t.as_bytes(), admin_token.as_bytes())
+        },
+        _ => false,
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for LocalOnly {
+    type Error = ();
+
+    // it responds with 404 (as if the endpoint doesn't exist)
+    fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let config = req.guard::<State<Config>>().unwrap();
+        let token = req.headers().get_one(HEADER);
+        if is_allowed(&config.server_admin_token, req.remote(), token) {
+            Outcome::Success(LocalOnly)
+        } else {
+            Outcome::Failure((Status::NotFound, ()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addr(s: &str) -> Option<SocketAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let local = addr("127.0.0.1:8000");
+        assert!(is_allowed("secret", local, Some("secret")));
+        assert!(is_allowed("secret", addr("[::1]:8000"), Some("secret")));
+
+        assert!(!is_allowed("secret", local, None));
+        assert!(!is_allowed("secret", local, Some("another")));
+        assert!(!is_allowed("secret", addr("192.0.2.1:8000"), Some("secret")));
+        assert!(!is_allowed("secret", None, Some("secret")));
+    }
+
+    #[test]
+    fn test_is_allowed_without_admin_token() {
+        let local = addr("127.0.0.1:8000");
+        assert!(!is_allowed("", local, None));
+        assert!(!is_allowed("", local, Some("")));
+    }
+}
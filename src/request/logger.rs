@@ -0,0 +1,113 @@
+//! Request-scoped logger.
+//!
+//! `RequestLogger` is the logger of the server (`SyncLogger`) with the context
+//! of the request, so that every line of it (including the queries by models)
+//! can be correlated:
+//!
+//! * `request_id` is the `X-Request-Id` header (e.g. given by a proxy), or a
+//!   new one if it's not given or invalid. It's sent back as `X-Request-Id`
+//!   in the response.
+//! * `user_id` is the user authenticated by `User` guard, if it has been
+//!   resolved before this guard (routes take the logger at the end).
+//! * `namespace_id` is the `<namespace_key>` segment of the route, if any.
+use std::ops::Deref;
+
+use rocket::{Request, request};
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+use uuid::Uuid;
+
+use crate::logger::Logger;
+use crate::model::user::User;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+const REQUEST_ID_MAX_LENGTH: usize = 64;
+
+/// RequestId is cached in the request by `RequestLogger` guard.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(pub String);
+
+// an id from clients must be short and printable
+fn is_valid_request_id(value: &str) -> bool {
+    !value.is_empty() &&
+        value.len() <= REQUEST_ID_MAX_LENGTH &&
+        value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+// the index of the segment in the path of the route (e.g.
+// `/v1/message/<namespace_key>/...`)
+fn segment_index(route: &str, name: &str) -> Option<usize> {
+    route
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .position(|s| s == name)
+}
+
+fn namespace_key_of(req: &Request) -> Option<String> {
+    let route = req.route()?;
+    let i = segment_index(route.uri.path(), "<namespace_key>")?;
+    req.uri().segments().nth(i).map(|s| s.to_string())
+}
+
+/// RequestLogger
+pub struct RequestLogger(Logger);
+
+impl Deref for RequestLogger {
+    type Target = Logger;
+
+    fn deref(&self) -> &Logger {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequestLogger {
+    type Error = ();
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        let request_id = req.local_cache(|| {
+            let id = match req.headers().get_one(REQUEST_ID_HEADER) {
+                Some(v) if is_valid_request_id(v) => v.to_string(),
+                _ => Uuid::new_v4().to_simple().to_string(),
+            };
+            Some(RequestId(id))
+        });
+        let request_id = request_id.as_ref().map(|v| v.0.to_string());
+        let user_id = req.local_cache(|| None::<User>).as_ref().map(|u| u.id);
+        let namespace_id = namespace_key_of(req);
+
+        request::Outcome::Success(Self(logger.new(slog::o!(
+            "request_id" => request_id,
+            "user_id" => user_id,
+            "namespace_id" => namespace_id
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_request_id() {
+        assert!(is_valid_request_id("f3b1c2d4-0a1b-4c5d-9e8f-5a6b7c8d9e0f"));
+        assert!(is_valid_request_id("req_01.a"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("a b"));
+        assert!(!is_valid_request_id("a\nb"));
+        assert!(!is_valid_request_id(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn test_segment_index() {
+        let route = "/v1/message/<namespace_key>/hget/<stream_slug>/<id>";
+        assert_eq!(segment_index(route, "<namespace_key>"), Some(2));
+        assert_eq!(segment_index("/_/health", "<namespace_key>"), None);
+    }
+}
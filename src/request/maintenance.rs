@@ -0,0 +1,9 @@
+/// Maintenance
+///
+/// The switch of maintenance mode (see `service::maintenance`).
+/// `retry_after` is in seconds, and it's the default one if it's not given.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Maintenance {
+    pub enabled: bool,
+    pub retry_after: Option<u64>,
+}
@@ -1,3 +1,5 @@
+use chrono::NaiveDateTime;
+
 /// Message
 #[derive(Clone, Deserialize)]
 pub struct Message {
@@ -10,6 +12,17 @@ pub struct Message {
     pub format: Option<String>,
     pub title: Option<String>,
     pub content: Option<String>,
+    /// Identical messages (within the deduplication window) have the same
+    /// one. It's the hash of the title and the content if omitted.
+    pub fingerprint: Option<String>,
+    /// The W3C Trace Context of the span where it's logged (see
+    /// `tracecontext`).
+    pub traceparent: Option<String>,
+    /// Where it comes from. The ones of the stream token are used if they are
+    /// omitted.
+    pub hostname: Option<String>,
+    pub service: Option<String>,
+    pub environment: Option<String>,
 }
 
 impl Default for Message {
@@ -24,6 +37,59 @@ impl Default for Message {
             format: None,
             title: None,
             content: None,
+            fingerprint: None,
+            traceparent: None,
+            hostname: None,
+            service: None,
+            environment: None,
         }
     }
 }
+
+/// Annotation
+///
+/// Changes to a message by a user. Omitted fields are kept as they are, and
+/// `updated_at` must be the one of the message which the changes are based
+/// on.
+#[derive(Clone, Default, Deserialize)]
+pub struct Annotation {
+    pub tags: Option<Vec<String>>,
+    pub acknowledged: Option<bool>,
+    pub resolved: Option<bool>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+/// Share
+///
+/// A link to share a message. `expires_in` is seconds (a day by default),
+/// and `context` is the number of the messages before and after it to be
+/// shown too (none by default).
+#[derive(Clone, Default, Deserialize)]
+pub struct Share {
+    pub expires_in: Option<i64>,
+    pub context: Option<i64>,
+}
+
+/// Search
+///
+/// The query string of search, like
+/// `?q=timeout&case_sensitive=false&unaccent=true`. With `facets=true`, the
+/// counts by the source of the matched messages are in the response too.
+#[derive(Debug, FromForm)]
+pub struct Search {
+    pub q: String,
+    pub case_sensitive: Option<bool>,
+    pub unaccent: Option<bool>,
+    pub facets: Option<bool>,
+}
+
+/// Source
+///
+/// The query string of filters by where messages come from, like
+/// `?service=api&environment=production`.
+#[derive(Debug, Default, FromForm)]
+pub struct Source {
+    pub hostname: Option<String>,
+    pub service: Option<String>,
+    pub environment: Option<String>,
+}
@@ -1,10 +1,41 @@
 pub mod access_token;
 pub mod agent_type;
+pub mod alert_schedule;
+pub mod analytics;
+pub mod api_version;
+pub mod body_limit;
+pub mod bulk_operation;
+pub mod channel;
+pub mod client_ip;
+pub mod concurrency;
+pub mod confirmation;
+pub mod csrf;
+pub mod duplicate_window;
+pub mod encoding;
+pub mod etag;
+pub mod fault;
+pub mod incident;
+pub mod json;
+pub mod local_only;
+pub mod logger;
+pub mod maintenance;
 pub mod message;
 pub mod namespace;
+pub mod oauth;
+pub mod pagination;
 pub mod password_reset;
+pub mod protobuf;
+pub mod push_device;
+pub mod quota;
+pub mod rate_limit;
+pub mod recent_view;
+pub mod scope;
+pub mod stream_buffer;
+pub mod stream_token;
+pub mod sudo;
 pub mod token;
 pub mod user;
+pub mod waitlist;
 
 #[macro_export]
 macro_rules! bad_request_by {
@@ -26,6 +57,16 @@ macro_rules! unauthorized_by {
     };
 }
 
+#[macro_export]
+macro_rules! forbidden_by {
+    ($reason:expr) => {
+        ::rocket::request::Outcome::Failure((
+            ::rocket::http::Status::Forbidden,
+            $reason,
+        ))
+    };
+}
+
 #[macro_export]
 macro_rules! not_found_by {
     ($reason:expr) => {
@@ -13,3 +13,26 @@ impl Default for Namespace {
         }
     }
 }
+
+/// NamespaceUpdate
+///
+/// The fields which are not given are not changed.
+#[derive(Clone, Deserialize)]
+pub struct NamespaceUpdate {
+    /// A level (e.g. `warning`), or an empty string to accept all levels
+    pub min_level: Option<String>,
+    /// Tags merged into ingested messages (e.g. `environment=staging`), or
+    /// an empty list to remove them
+    pub default_tags: Option<Vec<String>>,
+    /// A sensitivity of the detection of error-rate spikes (e.g. `high`), or
+    /// `off` to disable it
+    pub anomaly_sensitivity: Option<String>,
+    /// Whether the status badge is shown without the token
+    pub badge_public: Option<bool>,
+}
+
+/// NamespaceTransfer
+#[derive(Clone, Deserialize)]
+pub struct NamespaceTransfer {
+    pub user: Option<String>, // uuid of the new owner
+}
@@ -0,0 +1,17 @@
+/// OAuthCallback
+///
+/// The values which the provider has passed to the redirect URI.
+#[derive(Clone, Deserialize)]
+pub struct OAuthCallback {
+    pub code: String,
+    pub state: String,
+}
+
+impl Default for OAuthCallback {
+    fn default() -> Self {
+        Self {
+            code: "".to_string(),
+            state: "".to_string(),
+        }
+    }
+}
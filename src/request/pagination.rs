@@ -0,0 +1,191 @@
+//! Pagination of lists.
+//!
+//! List endpoints take `cursor` and `limit` in the query string, and respond
+//! with an envelope which has `next_cursor` (see `response::Paginated`). A
+//! cursor is opaque to clients. It's the offset in base64url for now, so that
+//! it can be changed into a keyset one without breaking them.
+use chrono::NaiveDateTime;
+
+use crate::validation::ValidationError;
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 200;
+
+/// Page
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Page {
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl Page {
+    /// Returns the page of the (inclusive) range like `.../<start>/<stop>`.
+    pub fn range(start: i64, stop: i64) -> Self {
+        Self {
+            offset: start.max(0),
+            limit: (stop - start + 1).max(1).min(MAX_LIMIT),
+        }
+    }
+
+    /// Applies `cursor` and `limit` given in the query string.
+    pub fn query(
+        self,
+        cursor: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Self, ValidationError> {
+        let offset = match cursor.map(decode_cursor) {
+            None => self.offset,
+            Some(Some(v)) => v,
+            Some(None) => return Err(invalid("cursor", "Must be a cursor")),
+        };
+        let limit = match limit {
+            None => self.limit,
+            Some(v) if (1..=MAX_LIMIT).contains(&v) => v,
+            Some(_) => {
+                let message = format!("Must be between 1 and {}", MAX_LIMIT);
+                return Err(invalid("limit", &message));
+            },
+        };
+        Ok(Self { offset, limit })
+    }
+
+    /// The number of records to fetch for the page. The extra one tells
+    /// whether there is a next page (see `split`).
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    /// Splits the records fetched by `offset` and `fetch_limit` into the ones
+    /// in the page and the cursor of the next page.
+    pub fn split<T>(&self, mut items: Vec<T>) -> (Vec<T>, Option<String>) {
+        if items.len() as i64 > self.limit {
+            items.truncate(self.limit as usize);
+            return (items, Some(encode_cursor(self.offset + self.limit)));
+        }
+        (items, None)
+    }
+
+    /// Takes the page from all of the records.
+    pub fn slice<T>(&self, items: Vec<T>) -> (Vec<T>, Option<String>) {
+        let items = items
+            .into_iter()
+            .skip(self.offset as usize)
+            .take(self.fetch_limit() as usize)
+            .collect();
+        self.split(items)
+    }
+
+    /// Returns an entity tag of the page in the list (see `request::etag`).
+    pub fn etag(
+        &self,
+        updated_at: Option<NaiveDateTime>,
+        count: i64,
+    ) -> String {
+        let timestamp = updated_at.map(|t| t.timestamp_nanos()).unwrap_or(0);
+        format!(
+            "\"{:x}-{:x}-{:x}-{:x}\"",
+            timestamp, count, self.offset, self.limit
+        )
+    }
+}
+
+pub fn encode_cursor(offset: i64) -> String {
+    base64::encode_config(offset.to_string(), base64::URL_SAFE_NO_PAD)
+}
+
+pub fn decode_cursor(cursor: &str) -> Option<i64> {
+    let v = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+    String::from_utf8(v)
+        .ok()?
+        .parse::<i64>()
+        .ok()
+        .filter(|offset| *offset >= 0)
+}
+
+fn invalid(field: &str, message: &str) -> ValidationError {
+    ValidationError {
+        field: field.to_string(),
+        messages: vec![message.to_string()],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_cursor() {
+        assert_eq!(encode_cursor(50), "NTA");
+        assert_eq!(decode_cursor("NTA"), Some(50));
+        assert_eq!(decode_cursor(&encode_cursor(0)), Some(0));
+        assert_eq!(decode_cursor(&encode_cursor(-1)), None);
+        assert_eq!(decode_cursor("***"), None);
+        assert_eq!(decode_cursor("YQ"), None); // "a"
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(Page::range(0, 9), Page { offset: 0, limit: 10 });
+        assert_eq!(Page::range(-5, -9), Page { offset: 0, limit: 1 });
+        assert_eq!(Page::range(0, 999).limit, MAX_LIMIT);
+    }
+
+    #[test]
+    fn test_query() {
+        let page = Page::default().query(None, None).unwrap();
+        assert_eq!(page, Page::default());
+
+        let cursor = encode_cursor(100);
+        let page = Page::range(0, 9).query(Some(&cursor), None).unwrap();
+        assert_eq!(page, Page { offset: 100, limit: 10 });
+
+        let page = Page::default().query(None, Some(20)).unwrap();
+        assert_eq!(page, Page { offset: 0, limit: 20 });
+
+        let e = Page::default().query(Some("***"), None).unwrap_err();
+        assert_eq!(e.field, "cursor");
+
+        for limit in &[0, MAX_LIMIT + 1] {
+            let e = Page::default().query(None, Some(*limit)).unwrap_err();
+            assert_eq!(e.field, "limit");
+        }
+    }
+
+    #[test]
+    fn test_split_and_slice() {
+        let page = Page { offset: 0, limit: 2 };
+        assert_eq!(page.split(vec![1, 2]), (vec![1, 2], None));
+        assert_eq!(
+            page.split(vec![1, 2, 3]),
+            (vec![1, 2], Some(encode_cursor(2)))
+        );
+
+        let page = Page { offset: 2, limit: 2 };
+        assert_eq!(
+            page.slice(vec![1, 2, 3, 4, 5]),
+            (vec![3, 4], Some(encode_cursor(4)))
+        );
+        assert_eq!(page.slice(vec![1, 2, 3, 4]), (vec![3, 4], None));
+        assert_eq!(page.slice(vec![1]), (vec![], None));
+    }
+
+    #[test]
+    fn test_etag() {
+        let dt = NaiveDate::from_ymd(2019, 8, 7).and_hms(6, 5, 4);
+        let a = Page { offset: 0, limit: 2 };
+        let b = Page { offset: 2, limit: 2 };
+        assert_eq!(a.etag(Some(dt), 10), r#""15b88ed23550a000-a-0-2""#);
+        assert_ne!(a.etag(Some(dt), 10), b.etag(Some(dt), 10));
+    }
+}
@@ -0,0 +1,81 @@
+//! Protocol Buffers data guard.
+//!
+//! Decodes the body of `application/x-protobuf` like `Json<T>` does for JSON.
+//! The size is limited by `SERVER_LIMIT_PROTOBUF` (see `request::body_limit`),
+//! and the body may be compressed (see `request::encoding`).
+//!
+//! Rocket doesn't know the media type, so the routes match it by the
+//! `ProtobufFormat` guard instead of `format`.
+use std::ops::Deref;
+
+use lazy_static::lazy_static;
+use rocket::data::{self, Data, FromDataSimple};
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest};
+use rocket::Outcome::{Failure, Forward, Success};
+use rocket::Request;
+
+use crate::request::body_limit::{Binary, read_body};
+use crate::request::encoding::EncodingError;
+
+lazy_static! {
+    pub static ref PROTOBUF: ContentType =
+        ContentType::new("application", "x-protobuf");
+}
+
+/// ProtobufFormat forwards the request unless it has a body of
+/// `application/x-protobuf` (the parameters are ignored).
+pub struct ProtobufFormat;
+
+impl<'a, 'r> FromRequest<'a, 'r> for ProtobufFormat {
+    type Error = ();
+
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        match req.content_type() {
+            Some(t) if t.media_type() == PROTOBUF.media_type() => {
+                Success(ProtobufFormat)
+            },
+            _ => Forward(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ProtobufError {
+    Decode,
+    Encoding(EncodingError),
+}
+
+pub struct Protobuf<T>(pub T);
+
+impl<T> Protobuf<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Protobuf<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: prost::Message + Default> FromDataSimple for Protobuf<T> {
+    type Error = ProtobufError;
+
+    fn from_data(
+        req: &Request,
+        data: Data,
+    ) -> data::Outcome<Self, Self::Error> {
+        let buf = match read_body::<Binary>(req, data) {
+            Ok(v) => v,
+            Err(e) => return Failure((e.status(), ProtobufError::Encoding(e))),
+        };
+        match T::decode(&buf[..]) {
+            Ok(v) => Success(Protobuf(v)),
+            Err(_) => Failure((Status::BadRequest, ProtobufError::Decode)),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+/// PushDevice
+///
+/// The platform is `apns` or `fcm`, and the token is the one issued by it to
+/// the app on the device.
+#[derive(Clone, Default, Deserialize)]
+pub struct PushDevice {
+    pub platform: Option<String>,
+    pub token: Option<String>,
+    pub name: Option<String>,
+}
@@ -0,0 +1,408 @@
+//! Ingestion quota guard and API call counter per namespace.
+//!
+//! Daily counters (API calls, messages and bytes) are kept in the session
+//! store as `qt-<namespace_key>-<YYYYMMDD>`. The handler reserves a message
+//! and its bytes (Content-Length) by the quota guard, once the namespace is
+//! resolved for the user (or the token). Once the plan limit is exceeded, the namespace has a grace period, then requests
+//! are rejected with 429. Owners are notified when the usage crosses the
+//! thresholds (e.g. 80% and 100%). The counters are flushed into
+//! `namespace_usages` by `FlushNamespaceUsages` job periodically.
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use fourche::queue::Queue;
+use redis::{Commands, Script};
+use rocket::{Request, State, request};
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::job::{Job, JobKind};
+use crate::mq::MqConn;
+use crate::ss::SsConn;
+
+pub const KEY_PREFIX: &str = "qt-";
+const FLUSH_LOCK_KEY: &str = "qt:flush";
+const KEY_EXPIRATION: usize = 172_800; // 2 days
+
+// KEYS[1]: counter key
+// ARGV[1]: bytes, ARGV[2]: max messages, ARGV[3]: max bytes,
+// ARGV[4]: expiration (seconds), ARGV[5]: now (seconds),
+// ARGV[6]: grace period (seconds)
+//
+// returns {allowed, messages, bytes, grace_until (0 means not started)}
+const RESERVE: &str = r#"
+local key = KEYS[1]
+local size = tonumber(ARGV[1])
+local max_messages = tonumber(ARGV[2])
+local max_bytes = tonumber(ARGV[3])
+local now = tonumber(ARGV[5])
+local grace = tonumber(ARGV[6])
+
+local messages = redis.call('HINCRBY', key, 'messages', 1)
+local bytes = redis.call('HINCRBY', key, 'bytes', size)
+local grace_until = tonumber(redis.call('HGET', key, 'grace_until') or 0)
+
+local allowed = 1
+if (max_messages > 0 and messages > max_messages) or
+   (max_bytes > 0 and bytes > max_bytes) then
+  if grace_until == 0 and grace > 0 then
+    grace_until = now + grace
+    redis.call('HSET', key, 'grace_until', grace_until)
+  end
+  if now >= grace_until then
+    messages = redis.call('HINCRBY', key, 'messages', -1)
+    bytes = redis.call('HINCRBY', key, 'bytes', -size)
+    allowed = 0
+  end
+end
+redis.call('EXPIRE', key, tonumber(ARGV[4]))
+
+return {allowed, messages, bytes, grace_until}
+"#;
+
+/// QuotaState
+///
+/// This is cached per request for the quota headers.
+#[derive(Clone, Debug)]
+pub struct QuotaState {
+    pub messages_limit: u64,
+    pub messages_remaining: u64,
+    pub bytes_limit: u64,
+    pub bytes_remaining: u64,
+    pub reset: u64,               // seconds
+    pub grace_until: Option<i64>, // timestamp
+}
+
+#[derive(Debug)]
+pub enum QuotaError {
+    Exceeded,
+}
+
+/// IngestionQuota
+///
+/// This doesn't reserve anything by itself, as the namespace in the path is
+/// not checked yet when the guards run (see `reserve`).
+pub struct IngestionQuota<'a, 'r>(&'a Request<'r>);
+
+/// ApiCallCount
+///
+/// Counts a call to the API for the namespace. This never rejects requests.
+pub struct ApiCallCount;
+
+pub fn counter_key(namespace_key: &str, date: NaiveDate) -> String {
+    format!("{}{}-{}", KEY_PREFIX, namespace_key, date.format("%Y%m%d"))
+}
+
+/// Splits a counter key into the namespace key and the date.
+pub fn parse_counter_key(key: &str) -> Option<(String, NaiveDate)> {
+    if !key.starts_with(KEY_PREFIX) {
+        return None;
+    }
+    let body = &key[KEY_PREFIX.len()..];
+    let i = body.rfind('-')?;
+    let date = NaiveDate::parse_from_str(&body[i + 1..], "%Y%m%d").ok()?;
+    let namespace_key = &body[..i];
+    if namespace_key.is_empty() {
+        return None;
+    }
+    Some((namespace_key.to_string(), date))
+}
+
+// the first dynamic segment of the route (after the mount point)
+fn first_param(req: &Request) -> Option<String> {
+    let route = req.route()?;
+    let i = route
+        .uri
+        .segments()
+        .skip(route.base.segments().count())
+        .position(|s| s.starts_with('<'))?;
+    req.get_param::<String>(i)?.ok()
+}
+
+// seconds until the next midnight (UTC)
+fn seconds_until_reset(now: NaiveDateTime) -> u64 {
+    let tomorrow = (now.date() + Duration::days(1)).and_hms(0, 0, 0);
+    (tomorrow - now).num_seconds().max(0) as u64
+}
+
+fn remaining(limit: u64, used: i64) -> u64 {
+    if limit == 0 {
+        return 0;
+    }
+    limit.saturating_sub(used.max(0) as u64)
+}
+
+fn percent(limit: u64, used: i64) -> u64 {
+    if limit == 0 {
+        return 0;
+    }
+    (used.max(0) as u64).saturating_mul(100) / limit
+}
+
+fn enqueue(req: &Request, job: Job<String>) -> bool {
+    let logger = req.guard::<SyncLogger>().unwrap();
+    match req.guard::<MqConn>() {
+        request::Outcome::Success(mut mq_conn) => {
+            let mut queue = Queue::new("default", &mut *mq_conn);
+            match queue.enqueue::<Job<String>>(job) {
+                Ok(_) => true,
+                Err(err) => {
+                    error!(logger, "error: {}", err);
+                    false
+                },
+            }
+        },
+        _ => {
+            error!(logger, "err: message queue is not available");
+            false
+        },
+    }
+}
+
+// enqueues a notification when the usage crosses thresholds (only once per
+// threshold and day)
+fn notify(
+    req: &Request,
+    ss_conn: &mut SsConn,
+    key: &str,
+    namespace_key: &str,
+    percent: u64,
+    config: &Config,
+) {
+    let logger = req.guard::<SyncLogger>().unwrap();
+
+    let mut crossed: Option<u64> = None;
+    for threshold in config.quota_notification_thresholds.iter() {
+        if percent < *threshold {
+            continue;
+        }
+        let field = format!("notified_{}", threshold);
+        match ss_conn.hset_nx::<_, _, _, bool>(key, &field, 1) {
+            Ok(true) => crossed = crossed.max(Some(*threshold)),
+            Ok(false) => (),
+            Err(e) => error!(logger, "err: {}", e),
+        }
+    }
+
+    if let Some(threshold) = crossed {
+        info!(logger, "quota {}% used: {}", threshold, namespace_key);
+        let job = Job::<String> {
+            kind: JobKind::SendQuotaNotificationEmail,
+            args: vec![namespace_key.to_string(), threshold.to_string()],
+        };
+        let _ = enqueue(req, job);
+    }
+}
+
+// enqueues a flush job at most once per the interval
+fn schedule_flush(req: &Request, ss_conn: &mut SsConn, config: &Config) {
+    let logger = req.guard::<SyncLogger>().unwrap();
+    let locked: Result<bool, _> = redis::cmd("SET")
+        .arg(FLUSH_LOCK_KEY)
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(config.quota_flush_interval.max(1))
+        .query::<Option<String>>(&mut **ss_conn)
+        .map(|v| v.is_some());
+    match locked {
+        Ok(true) => (),
+        Ok(false) => return,
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            return;
+        },
+    }
+
+    let job = Job::<String> {
+        kind: JobKind::FlushNamespaceUsages,
+        args: vec![],
+    };
+    if !enqueue(req, job) {
+        let _: Result<i64, _> = ss_conn.del(FLUSH_LOCK_KEY);
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IngestionQuota<'a, 'r> {
+    type Error = ();
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(IngestionQuota(req))
+    }
+}
+
+impl<'a, 'r> IngestionQuota<'a, 'r> {
+    /// Reserves a message for the namespace. It must be called after the
+    /// namespace is resolved (and the membership or the token is checked),
+    /// otherwise anyone could consume the quota of others.
+    pub fn reserve(&self, namespace_key: &str) -> Result<(), QuotaError> {
+        let req = self.0;
+        let config = req.guard::<State<Config>>().unwrap();
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        let size: u64 = req
+            .headers()
+            .get_one("Content-Length")
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
+
+        // the quota won't be applied if the store is not available
+        let mut ss_conn = match req.guard::<SsConn>() {
+            request::Outcome::Success(conn) => conn,
+            _ => {
+                error!(logger, "err: session store is not available");
+                return Ok(());
+            },
+        };
+
+        let clock = req.guard::<State<SharedClock>>().unwrap();
+        let now = clock.now().naive_utc();
+        let key = counter_key(namespace_key, now.date());
+        let result: Result<Vec<i64>, _> = Script::new(RESERVE)
+            .key(&key)
+            .arg(size)
+            .arg(config.quota_messages_per_day)
+            .arg(config.quota_bytes_per_day)
+            .arg(KEY_EXPIRATION)
+            .arg(now.timestamp())
+            .arg(config.quota_grace_period)
+            .invoke(&mut *ss_conn);
+
+        let values = match result {
+            Ok(ref v) if v.len() == 4 => v,
+            Ok(_) => {
+                error!(logger, "err: unexpected result for {}", key);
+                return Ok(());
+            },
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return Ok(());
+            },
+        };
+
+        let state = QuotaState {
+            messages_limit: config.quota_messages_per_day,
+            messages_remaining: remaining(
+                config.quota_messages_per_day,
+                values[1],
+            ),
+            bytes_limit: config.quota_bytes_per_day,
+            bytes_remaining: remaining(config.quota_bytes_per_day, values[2]),
+            reset: seconds_until_reset(now),
+            grace_until: if values[3] > 0 { Some(values[3]) } else { None },
+        };
+        req.local_cache(|| Some(state));
+
+        // the one nearest to be exceeded
+        let percent = percent(config.quota_messages_per_day, values[1])
+            .max(percent(config.quota_bytes_per_day, values[2]));
+        notify(req, &mut ss_conn, &key, namespace_key, percent, &config);
+
+        if values[0] == 1 {
+            schedule_flush(req, &mut ss_conn, &config);
+            Ok(())
+        } else {
+            warn!(logger, "quota exceeded: {}", namespace_key);
+            Err(QuotaError::Exceeded)
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ApiCallCount {
+    type Error = ();
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let config = req.guard::<State<Config>>().unwrap();
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        // the first dynamic segment in namespace routes
+        let namespace_key = match first_param(req) {
+            Some(v) => v,
+            None => return request::Outcome::Success(ApiCallCount),
+        };
+
+        let mut ss_conn = match req.guard::<SsConn>() {
+            request::Outcome::Success(conn) => conn,
+            _ => {
+                error!(logger, "err: session store is not available");
+                return request::Outcome::Success(ApiCallCount);
+            },
+        };
+
+        let clock = req.guard::<State<SharedClock>>().unwrap();
+        let key = counter_key(&namespace_key, clock.now().naive_utc().date());
+        let result: Result<(), _> = redis::pipe()
+            .hincr(&key, "calls", 1)
+            .ignore()
+            .expire(&key, KEY_EXPIRATION)
+            .ignore()
+            .query(&mut *ss_conn);
+        match result {
+            Ok(_) => schedule_flush(req, &mut ss_conn, &config),
+            Err(e) => error!(logger, "err: {}", e),
+        }
+        request::Outcome::Success(ApiCallCount)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_counter_key() {
+        let date = NaiveDate::from_ymd(2021, 6, 1);
+        assert_eq!(counter_key("key", date), "qt-key-20210601");
+    }
+
+    #[test]
+    fn test_parse_counter_key() {
+        let date = NaiveDate::from_ymd(2021, 6, 1);
+        assert_eq!(
+            parse_counter_key("qt-key-20210601"),
+            Some(("key".to_string(), date))
+        );
+
+        let uuid = "6b3f0c43-9a8e-4d5b-9f4c-8cfe1e2ad7a9";
+        assert_eq!(
+            parse_counter_key(&counter_key(uuid, date)),
+            Some((uuid.to_string(), date))
+        );
+
+        assert_eq!(parse_counter_key("qt-20210601"), None);
+        assert_eq!(parse_counter_key("qt--20210601"), None);
+        assert_eq!(parse_counter_key("qt-key-2021"), None);
+        assert_eq!(parse_counter_key("xs-key-20210601"), None);
+    }
+
+    #[test]
+    fn test_seconds_until_reset() {
+        let now = NaiveDate::from_ymd(2021, 6, 1).and_hms(23, 59, 0);
+        assert_eq!(seconds_until_reset(now), 60);
+
+        let now = NaiveDate::from_ymd(2021, 6, 1).and_hms(0, 0, 0);
+        assert_eq!(seconds_until_reset(now), 86_400);
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(percent(0, 10), 0);
+        assert_eq!(percent(10, 0), 0);
+        assert_eq!(percent(10, 8), 80);
+        assert_eq!(percent(1000, 1000), 100);
+        assert_eq!(percent(10, 12), 120);
+        assert_eq!(percent(10, -1), 0);
+    }
+
+    #[test]
+    fn test_remaining() {
+        assert_eq!(remaining(0, 10), 0);
+        assert_eq!(remaining(10, 3), 7);
+        assert_eq!(remaining(10, 12), 0);
+        assert_eq!(remaining(10, -1), 10);
+    }
+}
@@ -0,0 +1,259 @@
+//! Rate limiting guard using a token bucket in the session store.
+//!
+//! Each route group has its own bucket which is identified by the
+//! authenticated user (only for the groups of `BY_USER`) or the client IP
+//! address (see `request::client_ip`). A token in the Authorization header is
+//! not used as it is, because it can be anything until it's verified. The
+//! state is kept in the request local cache, then `Response` puts
+//! `X-RateLimit-*` headers (and `Retry-After` for 429) based on it.
+//!
+//! The limits are read from `DynamicConfig`, so that they can be changed at
+//! runtime.
+use std::marker::PhantomData;
+use std::sync::RwLock;
+
+use redis::Script;
+use rocket::{Request, State, request};
+use rocket::http::Status;
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+use sha2::{Digest, Sha256};
+
+use crate::clock::SharedClock;
+use crate::config::{Config, DynamicConfig};
+use crate::model::user::User;
+use crate::request::client_ip::client_ip;
+use crate::ss::SsConn;
+
+const WINDOW: i64 = 60_000; // milliseconds (per minute)
+
+// KEYS[1]: bucket key
+// ARGV[1]: capacity, ARGV[2]: window (ms), ARGV[3]: now (ms)
+//
+// returns {allowed, remaining, reset (ms), retry_after (ms)}
+const TOKEN_BUCKET: &str = r#"
+local key = KEYS[1]
+local capacity = tonumber(ARGV[1])
+local rate = capacity / tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+
+local bucket = redis.call('HMGET', key, 'tokens', 'ts')
+local tokens = tonumber(bucket[1])
+local ts = tonumber(bucket[2])
+if tokens == nil or ts == nil then
+  tokens = capacity
+  ts = now
+end
+
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate)
+
+local allowed = 0
+local retry_after = 0
+if tokens >= 1 then
+  tokens = tokens - 1
+  allowed = 1
+else
+  retry_after = math.ceil((1 - tokens) / rate)
+end
+
+local reset = math.ceil((capacity - tokens) / rate)
+redis.call('HMSET', key, 'tokens', tokens, 'ts', now)
+redis.call('PEXPIRE', key, reset + 1000)
+
+return {allowed, math.floor(tokens), reset, retry_after}
+"#;
+
+/// A group of routes which share a limit.
+pub trait RateLimitGroup {
+    const NAME: &'static str;
+
+    /// Whether an authenticated user has its own bucket (instead of the one
+    /// of the address).
+    const BY_USER: bool;
+
+    /// Returns requests per minute (0 means unlimited).
+    fn limit(config: &DynamicConfig) -> u32;
+}
+
+pub struct Login;
+
+impl RateLimitGroup for Login {
+    const NAME: &'static str = "login";
+    const BY_USER: bool = false;
+
+    fn limit(config: &DynamicConfig) -> u32 {
+        config.rate_limit_login_per_minute
+    }
+}
+
+pub struct Ingestion;
+
+impl RateLimitGroup for Ingestion {
+    const NAME: &'static str = "ingestion";
+    const BY_USER: bool = true;
+
+    fn limit(config: &DynamicConfig) -> u32 {
+        config.rate_limit_ingestion_per_minute
+    }
+}
+
+pub struct Api;
+
+impl RateLimitGroup for Api {
+    const NAME: &'static str = "api";
+    const BY_USER: bool = true;
+
+    fn limit(config: &DynamicConfig) -> u32 {
+        config.rate_limit_api_per_minute
+    }
+}
+
+pub struct Waitlist;
+
+impl RateLimitGroup for Waitlist {
+    const NAME: &'static str = "waitlist";
+    const BY_USER: bool = false;
+
+    fn limit(config: &DynamicConfig) -> u32 {
+        config.rate_limit_waitlist_per_minute
+    }
+}
+
+/// RateLimitState
+///
+/// This is cached per request for the rate limit headers.
+#[derive(Clone, Debug)]
+pub struct RateLimitState {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: u64,               // seconds
+    pub retry_after: Option<u64>, // seconds
+}
+
+#[derive(Debug)]
+pub enum RateLimitError {
+    Exceeded,
+}
+
+pub struct RateLimit<G: RateLimitGroup>(PhantomData<G>);
+
+fn to_seconds(milliseconds: i64) -> u64 {
+    ((milliseconds.max(0) + 999) / 1000) as u64
+}
+
+fn bucket_key(group: &str, identifier: &str) -> String {
+    let digest = Sha256::digest(identifier.as_bytes());
+    let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("rl-{}-{}", group, hash)
+}
+
+fn identify<G: RateLimitGroup>(req: &Request) -> String {
+    if G::BY_USER {
+        if let request::Outcome::Success(user) = req.guard::<&User>() {
+            return format!("user:{}", user.uuid);
+        }
+    }
+    let config = req.guard::<State<Config>>().unwrap();
+    match client_ip(req, &config.server_trusted_proxies) {
+        Some(ip) => ip.to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+impl<'a, 'r, G> FromRequest<'a, 'r> for RateLimit<G>
+where G: RateLimitGroup
+{
+    type Error = RateLimitError;
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let config = req.guard::<State<RwLock<DynamicConfig>>>().unwrap();
+        let limit = G::limit(&config.read().unwrap());
+        if limit == 0 {
+            return request::Outcome::Success(RateLimit(PhantomData));
+        }
+
+        let logger = req.guard::<SyncLogger>().unwrap();
+        // before the connection below, as the user may need another one
+        let key = bucket_key(G::NAME, &identify::<G>(req));
+
+        // the limit won't be applied if the store is not available
+        let mut ss_conn = match req.guard::<SsConn>() {
+            request::Outcome::Success(conn) => conn,
+            _ => {
+                error!(logger, "err: session store is not available");
+                return request::Outcome::Success(RateLimit(PhantomData));
+            },
+        };
+
+        let clock = req.guard::<State<SharedClock>>().unwrap();
+        let result: Result<Vec<i64>, _> = Script::new(TOKEN_BUCKET)
+            .key(&key)
+            .arg(limit)
+            .arg(WINDOW)
+            .arg(clock.now().timestamp_millis())
+            .invoke(&mut *ss_conn);
+
+        let values = match result {
+            Ok(ref v) if v.len() == 4 => v,
+            Ok(_) => {
+                error!(logger, "err: unexpected result for {}", key);
+                return request::Outcome::Success(RateLimit(PhantomData));
+            },
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return request::Outcome::Success(RateLimit(PhantomData));
+            },
+        };
+
+        let allowed = values[0] == 1;
+        let state = RateLimitState {
+            limit,
+            remaining: values[1].max(0) as u32,
+            reset: to_seconds(values[2]),
+            retry_after: if allowed {
+                None
+            } else {
+                Some(to_seconds(values[3]))
+            },
+        };
+        req.local_cache(|| Some(state));
+
+        if allowed {
+            request::Outcome::Success(RateLimit(PhantomData))
+        } else {
+            warn!(logger, "rate limit exceeded: {}", G::NAME);
+            request::Outcome::Failure((
+                Status::TooManyRequests,
+                RateLimitError::Exceeded,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_seconds() {
+        assert_eq!(to_seconds(-1), 0);
+        assert_eq!(to_seconds(0), 0);
+        assert_eq!(to_seconds(1), 1);
+        assert_eq!(to_seconds(1000), 1);
+        assert_eq!(to_seconds(1001), 2);
+    }
+
+    #[test]
+    fn test_bucket_key() {
+        let key = bucket_key("login", "127.0.0.1");
+        assert!(key.starts_with("rl-login-"));
+        assert!(!key.contains("127.0.0.1"));
+        assert_eq!(key.len(), "rl-login-".len() + 64);
+
+        assert_eq!(key, bucket_key("login", "127.0.0.1"));
+        assert_ne!(key, bucket_key("api", "127.0.0.1"));
+        assert_ne!(key, bucket_key("login", "127.0.0.2"));
+    }
+}
@@ -0,0 +1,246 @@
+//! Recent view tracker.
+//!
+//! Views of messages and searches are kept in the session store as a sorted
+//! set `rv-<user_id>` (member `<kind>:<target>`, score is the time in
+//! milliseconds), so that opening a message doesn't write into the database.
+//! The sets are flushed into `recent_views` by `FlushRecentViews` job
+//! periodically.
+use chrono::NaiveDateTime;
+use fourche::queue::Queue;
+use redis::Commands;
+use rocket::{Request, State, request};
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::job::{Job, JobKind};
+use crate::logger::Logger;
+use crate::model::recent_view::RecentViewKind;
+use crate::model::user::User;
+use crate::mq::MqConn;
+use crate::ss::SsConn;
+
+pub const KEY_PREFIX: &str = "rv-";
+const FLUSH_LOCK_KEY: &str = "rv:flush";
+const KEY_EXPIRATION: usize = 604_800; // 7 days
+
+const TARGET_MAX_LENGTH: usize = 512;
+
+/// ViewTracker
+///
+/// This never rejects requests. Views are just not tracked if the session
+/// store is not available.
+pub struct ViewTracker {
+    ss_conn: Option<SsConn>,
+    mq_conn: Option<MqConn>,
+    flush_interval: u64,
+    limit: u64,
+    clock: SharedClock,
+    logger: Logger,
+}
+
+pub fn view_key(user_id: i64) -> String {
+    format!("{}{}", KEY_PREFIX, user_id)
+}
+
+/// Returns the user id in the key.
+pub fn parse_view_key(key: &str) -> Option<i64> {
+    if !key.starts_with(KEY_PREFIX) {
+        return None;
+    }
+    key[KEY_PREFIX.len()..].parse::<i64>().ok()
+}
+
+pub fn view_member(kind: RecentViewKind, target: &str) -> String {
+    format!("{}:{}", kind, target)
+}
+
+/// Splits a member into the kind and the target.
+pub fn parse_view_member(member: &str) -> Option<(RecentViewKind, String)> {
+    let mut parts = member.splitn(2, ':');
+    let kind = RecentViewKind::from_name(parts.next()?)?;
+    let target = parts.next()?;
+    if target.is_empty() {
+        return None;
+    }
+    Some((kind, target.to_string()))
+}
+
+pub fn score_to_datetime(score: i64) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp(
+        score.div_euclid(1000),
+        (score.rem_euclid(1000) * 1_000_000) as u32,
+    )
+}
+
+impl ViewTracker {
+    /// Records a view of the target by the user.
+    pub fn record(&mut self, user: &User, kind: RecentViewKind, target: &str) {
+        let ss_conn = match self.ss_conn {
+            Some(ref mut conn) => conn,
+            None => return,
+        };
+        let target: String = target.chars().take(TARGET_MAX_LENGTH).collect();
+
+        let key = view_key(user.id);
+        let score = self.clock.now().naive_utc().timestamp_millis();
+        // keeps only the latest ones
+        let stop = -(self.limit.max(1) as isize) - 1;
+        let result: Result<(), _> = redis::pipe()
+            .zadd(&key, view_member(kind, &target), score)
+            .ignore()
+            .zremrangebyrank(&key, 0, stop)
+            .ignore()
+            .expire(&key, KEY_EXPIRATION)
+            .ignore()
+            .query(&mut **ss_conn);
+        match result {
+            Ok(_) => self.schedule_flush(),
+            Err(e) => error!(self.logger, "err: {}", e),
+        }
+    }
+
+    /// Returns views which are not flushed yet (the latest first).
+    pub fn pending(
+        &mut self,
+        user: &User,
+    ) -> Vec<(RecentViewKind, String, NaiveDateTime)> {
+        let ss_conn = match self.ss_conn {
+            Some(ref mut conn) => conn,
+            None => return vec![],
+        };
+        let key = view_key(user.id);
+        let entries: Vec<(String, i64)> =
+            match ss_conn.zrevrange_withscores(&key, 0, -1) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(self.logger, "err: {}", e);
+                    return vec![];
+                },
+            };
+        entries
+            .into_iter()
+            .filter_map(|(member, score)| {
+                let (kind, target) = parse_view_member(&member)?;
+                Some((kind, target, score_to_datetime(score)))
+            })
+            .collect()
+    }
+
+    // enqueues a flush job at most once per the interval
+    fn schedule_flush(&mut self) {
+        let ss_conn = match self.ss_conn {
+            Some(ref mut conn) => conn,
+            None => return,
+        };
+        let locked: Result<bool, _> = redis::cmd("SET")
+            .arg(FLUSH_LOCK_KEY)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(self.flush_interval.max(1))
+            .query::<Option<String>>(&mut **ss_conn)
+            .map(|v| v.is_some());
+        match locked {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(e) => {
+                error!(self.logger, "err: {}", e);
+                return;
+            },
+        }
+
+        let enqueued = match self.mq_conn {
+            Some(ref mut mq_conn) => {
+                let job = Job::<String> {
+                    kind: JobKind::FlushRecentViews,
+                    args: vec![],
+                };
+                let mut queue = Queue::new("default", &mut **mq_conn);
+                match queue.enqueue::<Job<String>>(job) {
+                    Ok(_) => true,
+                    Err(err) => {
+                        error!(self.logger, "error: {}", err);
+                        false
+                    },
+                }
+            },
+            None => {
+                error!(self.logger, "err: message queue is not available");
+                false
+            },
+        };
+        if !enqueued {
+            let _: Result<i64, _> = ss_conn.del(FLUSH_LOCK_KEY);
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ViewTracker {
+    type Error = ();
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let config = req.guard::<State<Config>>().unwrap();
+        let clock = req.guard::<State<SharedClock>>().unwrap();
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        let ss_conn = match req.guard::<SsConn>() {
+            request::Outcome::Success(conn) => Some(conn),
+            _ => {
+                error!(logger, "err: session store is not available");
+                None
+            },
+        };
+        let mq_conn = match req.guard::<MqConn>() {
+            request::Outcome::Success(conn) => Some(conn),
+            _ => None,
+        };
+
+        request::Outcome::Success(ViewTracker {
+            ss_conn,
+            mq_conn,
+            flush_interval: config.recent_view_flush_interval,
+            limit: config.recent_view_limit,
+            clock: clock.inner().clone(),
+            logger: (*logger).clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_view_key() {
+        assert_eq!(view_key(3), "rv-3");
+        assert_eq!(parse_view_key("rv-3"), Some(3));
+        assert_eq!(parse_view_key("rv-"), None);
+        assert_eq!(parse_view_key("rv-x"), None);
+        assert_eq!(parse_view_key("qt-3"), None);
+    }
+
+    #[test]
+    fn test_parse_view_member() {
+        let member = view_member(RecentViewKind::Search, "a:b c");
+        assert_eq!(member, "search:a:b c");
+        assert_eq!(
+            parse_view_member(&member),
+            Some((RecentViewKind::Search, "a:b c".to_string()))
+        );
+
+        assert_eq!(parse_view_member("message:"), None);
+        assert_eq!(parse_view_member("message"), None);
+        assert_eq!(parse_view_member("unknown:1"), None);
+    }
+
+    #[test]
+    fn test_score_to_datetime() {
+        let dt = score_to_datetime(1_624_093_200_123);
+        assert_eq!(dt.timestamp(), 1_624_093_200);
+        assert_eq!(dt.timestamp_subsec_millis(), 123);
+    }
+}
@@ -0,0 +1,140 @@
+//! Scope guard for routes which accept a personal access token.
+//!
+//! A personal access token can be used only for routes which require one of
+//! its scopes. The browser cookie token (web console) is not limited by
+//! scopes, its permission is checked via membership on each route.
+use std::marker::PhantomData;
+
+use rocket::{Request, State, request};
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+
+use crate::config::Config;
+use crate::db::DbConn;
+use crate::forbidden_by;
+use crate::model::access_token::{AccessToken, Scope};
+use crate::model::token::{
+    Claims, ExchangedTokenClaims, PersonalAccessTokenClaims,
+};
+use crate::request::token::TokenType;
+use crate::request::token::authentication::AuthenticationToken;
+use crate::service::token_exchange::TokenExchange;
+use crate::ss::SsConn;
+
+/// A scope which a route requires.
+pub trait ScopeRequirement {
+    const SCOPE: Scope;
+}
+
+pub struct IngestWrite;
+
+impl ScopeRequirement for IngestWrite {
+    const SCOPE: Scope = Scope::IngestWrite;
+}
+
+pub struct MessagesRead;
+
+impl ScopeRequirement for MessagesRead {
+    const SCOPE: Scope = Scope::MessagesRead;
+}
+
+pub struct MessagesWrite;
+
+impl ScopeRequirement for MessagesWrite {
+    const SCOPE: Scope = Scope::MessagesWrite;
+}
+
+pub struct NamespaceAdmin;
+
+impl ScopeRequirement for NamespaceAdmin {
+    const SCOPE: Scope = Scope::NamespaceAdmin;
+}
+
+#[derive(Debug)]
+pub enum ScopeError {
+    Insufficient,
+}
+
+pub struct Scoped<R: ScopeRequirement> {
+    requirement: PhantomData<R>,
+}
+
+impl<R: ScopeRequirement> Scoped<R> {
+    fn new() -> Self {
+        Self {
+            requirement: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'r, R: ScopeRequirement> FromRequest<'a, 'r> for Scoped<R> {
+    type Error = ScopeError;
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        // authentication itself is checked by the guard for User
+        let token_type = req
+            .guard::<TokenType>()
+            .failure_then(|_| request::Outcome::Forward(()))?;
+        if token_type == TokenType::BrowserCookieToken {
+            return request::Outcome::Success(Self::new());
+        }
+
+        let authentication_token = req
+            .guard::<AuthenticationToken>()
+            .failure_then(|_| request::Outcome::Forward(()))?;
+
+        let config = req.guard::<State<Config>>().unwrap();
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        // an exchanged token has only one scope
+        if token_type == TokenType::ExchangedToken {
+            let scope = ExchangedTokenClaims::decode(
+                &authentication_token,
+                &config.authentication_token_issuer,
+                &config.authentication_token_secret,
+            )
+            .ok()
+            .and_then(|c| {
+                let mut ss_conn = req.guard::<SsConn>().succeeded()?;
+                TokenExchange::new(&mut *ss_conn)
+                    .find(&c.get_subject())
+                    .ok()?
+                    .map(|(_, scope)| scope)
+            });
+            return match scope {
+                Some(s) if s == R::SCOPE => {
+                    request::Outcome::Success(Self::new())
+                },
+                Some(s) => {
+                    warn!(logger, "exchanged token has scope: {}", s);
+                    forbidden_by!(ScopeError::Insufficient)
+                },
+                None => request::Outcome::Forward(()),
+            };
+        }
+
+        let db_conn = req.guard::<DbConn>().unwrap();
+        let access_token = PersonalAccessTokenClaims::decode(
+            &authentication_token,
+            &config.authentication_token_issuer,
+            &config.authentication_token_secret,
+        )
+        .ok()
+        .and_then(|c| {
+            AccessToken::find_by_token(&c.get_subject(), &db_conn, &logger)
+        });
+
+        match access_token {
+            Some(ref t) if t.has_scope(R::SCOPE) => {
+                request::Outcome::Success(Self::new())
+            },
+            Some(t) => {
+                warn!(logger, "{} does not have scope: {}", t, R::SCOPE);
+                forbidden_by!(ScopeError::Insufficient)
+            },
+            None => request::Outcome::Forward(()),
+        }
+    }
+}
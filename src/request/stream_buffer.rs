@@ -0,0 +1,143 @@
+//! Ingestion buffer.
+//!
+//! Ingested messages are pushed into the buffers of their streams in the
+//! session store (see `service::stream_buffer`) if
+//! `STREAM_BUFFER_FLUSH_INTERVAL` is set. `FlushStreamBuffers` job is deferred
+//! by the interval to save them.
+use chrono::Duration;
+use redis::Commands;
+use rocket::{Request, State, request};
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::job::{Job, JobKind, defer};
+use crate::logger::Logger;
+use crate::model::message::MessageRow;
+use crate::mq::MqConn;
+use crate::service::stream_buffer::StreamBuffer;
+use crate::ss::SsConn;
+
+const FLUSH_LOCK_KEY: &str = "sb:flush";
+
+/// IngestionBuffer
+///
+/// This never rejects requests. Messages are saved on the ingestion as usual
+/// if the buffer is disabled or the session store is not available.
+pub struct IngestionBuffer {
+    ss_conn: Option<SsConn>,
+    mq_conn: Option<MqConn>,
+    flush_interval: u64,
+    clock: SharedClock,
+    logger: Logger,
+}
+
+impl IngestionBuffer {
+    /// Pushes the row into the buffer of its stream. The row is given back if
+    /// it's not buffered, then the caller must save it.
+    pub fn push(&mut self, row: MessageRow) -> Result<(), MessageRow> {
+        if self.flush_interval == 0 {
+            return Err(row);
+        }
+        let ss_conn = match self.ss_conn {
+            Some(ref mut conn) => conn,
+            None => return Err(row),
+        };
+        if StreamBuffer::new(&mut *ss_conn, &self.logger)
+            .push(&row)
+            .is_err()
+        {
+            return Err(row);
+        }
+        self.schedule_flush();
+        Ok(())
+    }
+
+    // defers a flush job at most once per the interval
+    fn schedule_flush(&mut self) {
+        let ss_conn = match self.ss_conn {
+            Some(ref mut conn) => conn,
+            None => return,
+        };
+        let locked: Result<bool, _> = redis::cmd("SET")
+            .arg(FLUSH_LOCK_KEY)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(self.flush_interval)
+            .query::<Option<String>>(&mut **ss_conn)
+            .map(|v| v.is_some());
+        match locked {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(e) => {
+                error!(self.logger, "err: {}", e);
+                return;
+            },
+        }
+
+        let deferred = match self.mq_conn {
+            Some(ref mut mq_conn) => {
+                let job = Job::<String> {
+                    kind: JobKind::FlushStreamBuffers,
+                    args: vec![],
+                };
+                let until = self.clock.now() +
+                    Duration::seconds(self.flush_interval as i64);
+                match defer(&job, until, &mut **mq_conn) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        error!(self.logger, "err: {}", e);
+                        false
+                    },
+                }
+            },
+            None => {
+                error!(self.logger, "err: message queue is not available");
+                false
+            },
+        };
+        if !deferred {
+            let _: Result<i64, _> = ss_conn.del(FLUSH_LOCK_KEY);
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IngestionBuffer {
+    type Error = ();
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let config = req.guard::<State<Config>>().unwrap();
+        let clock = req.guard::<State<SharedClock>>().unwrap();
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        let flush_interval = config.stream_buffer_flush_interval;
+        let (ss_conn, mq_conn) = if flush_interval == 0 {
+            (None, None)
+        } else {
+            let ss_conn = match req.guard::<SsConn>() {
+                request::Outcome::Success(conn) => Some(conn),
+                _ => {
+                    error!(logger, "err: session store is not available");
+                    None
+                },
+            };
+            let mq_conn = match req.guard::<MqConn>() {
+                request::Outcome::Success(conn) => Some(conn),
+                _ => None,
+            };
+            (ss_conn, mq_conn)
+        };
+
+        request::Outcome::Success(IngestionBuffer {
+            ss_conn,
+            mq_conn,
+            flush_interval,
+            clock: clock.inner().clone(),
+            logger: (*logger).clone(),
+        })
+    }
+}
@@ -0,0 +1,57 @@
+//! Stream tokens (see `model::stream_token`).
+//!
+//! A log shipper sends a stream token as `Authorization: Stream-Token
+//! <value>`. It's accepted only by ingestion routes for the namespace of the
+//! token.
+use rocket::{Request, request};
+use rocket::request::FromRequest;
+use rocket_slog::SyncLogger;
+
+use crate::db::DbConn;
+use crate::model::stream_token::StreamToken as Token;
+use crate::unauthorized_by;
+
+const AUTHORIZATION_HEADER_PREFIX: &str = "Stream-Token ";
+
+/// StreamToken
+#[derive(Clone, Default, Deserialize)]
+pub struct StreamToken {
+    pub name: Option<String>,
+    /// The default source of the messages (see `model::stream_token`)
+    pub hostname: Option<String>,
+    pub service: Option<String>,
+    pub environment: Option<String>,
+}
+
+/// IngestionToken is a valid (not revoked) stream token.
+pub struct IngestionToken(pub Token);
+
+#[derive(Debug)]
+pub enum IngestionTokenError {
+    Invalid,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IngestionToken {
+    type Error = IngestionTokenError;
+
+    fn from_request(
+        req: &'a Request<'r>,
+    ) -> request::Outcome<Self, Self::Error> {
+        let value = match req.headers().get_one("Authorization") {
+            Some(v) if v.starts_with(AUTHORIZATION_HEADER_PREFIX) => {
+                v[AUTHORIZATION_HEADER_PREFIX.len()..].trim()
+            },
+            _ => return request::Outcome::Forward(()),
+        };
+
+        let db_conn = req.guard::<DbConn>().unwrap();
+        let logger = req.guard::<SyncLogger>().unwrap();
+        match Token::find_by_token(value, &db_conn, &logger) {
+            Some(t) => request::Outcome::Success(Self(t)),
+            None => {
+                warn!(logger, "err: invalid stream token");
+                unauthorized_by!(IngestionTokenError::Invalid)
+            },
+        }
+    }
+}
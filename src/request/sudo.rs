@@ -0,0 +1,110 @@
+//! Sudo mode guard for sensitive settings.
+//!
+//! A user who re-entered the password (see `route::authentication::sudo`) is
+//! in sudo mode for `SUDO_MODE_DURATION` minutes. It's tracked as a flag in
+//! the session store with expiration, like GitHub's sudo mode.
+use redis::{Commands, RedisError};
+use rocket::{Request, State};
+use rocket::request::{FromRequest, Outcome};
+use rocket_slog::SyncLogger;
+
+use crate::config::Config;
+use crate::forbidden_by;
+use crate::logger::Logger;
+use crate::model::user::User;
+use crate::ss::SsConn;
+
+pub const KEY_PREFIX: &str = "su-";
+
+pub fn key(user_uuid: &str) -> String {
+    format!("{}{}", KEY_PREFIX, user_uuid)
+}
+
+/// The password which the user re-entered.
+#[derive(Clone, Deserialize)]
+pub struct SudoData {
+    pub password: String,
+}
+
+pub struct Sudo;
+
+#[derive(Debug)]
+pub enum SudoError {
+    Required,
+}
+
+impl SudoError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            SudoError::Required => "Confirm your password to continue.",
+        }
+    }
+}
+
+impl Sudo {
+    /// Enters sudo mode for the user, and returns its duration in seconds.
+    pub fn enable(
+        user: &User,
+        ss_conn: &mut SsConn,
+        config: &Config,
+        logger: &Logger,
+    ) -> Result<usize, RedisError> {
+        let duration = config.sudo_mode_duration.as_secs() as usize;
+        let _: String = ss_conn
+            .set_ex(key(&user.uuid.to_string()), "1", duration)
+            .map_err(|e| {
+                error!(logger, "error: {}", e);
+                e
+            })?;
+        Ok(duration)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Sudo {
+    type Error = SudoError;
+
+    fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let logger = req.guard::<SyncLogger>().unwrap();
+
+        let user = match req.guard::<&User>() {
+            Outcome::Success(u) => u,
+            _ => return forbidden_by!(SudoError::Required),
+        };
+
+        let config = req.guard::<State<Config>>().unwrap();
+        if config.sudo_mode_duration.as_secs() == 0 {
+            return Outcome::Success(Sudo);
+        }
+
+        let mut ss_conn = match req.guard::<SsConn>() {
+            Outcome::Success(conn) => conn,
+            _ => {
+                error!(logger, "err: session store is not available");
+                return forbidden_by!(SudoError::Required);
+            },
+        };
+        let result: Result<bool, RedisError> =
+            ss_conn.exists(key(&user.uuid.to_string()));
+        match result {
+            Ok(true) => Outcome::Success(Sudo),
+            Ok(false) => {
+                info!(logger, "error: sudo mode is required");
+                forbidden_by!(SudoError::Required)
+            },
+            Err(e) => {
+                error!(logger, "error: {}", e);
+                forbidden_by!(SudoError::Required)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key("u"), "su-u");
+    }
+}
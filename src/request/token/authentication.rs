@@ -8,8 +8,8 @@ use rocket_slog::SyncLogger;
 use crate::config::Config;
 use crate::model::token::AuthenticationClaims;
 use crate::request::token::{
-    AUTHORIZATION_HEADER_PREFIX, AUTHORIZATION_HEADER_TOKEN_PREFIX, TokenType,
-    verify_token,
+    AUTHORIZATION_HEADER_EXCHANGED_PREFIX, AUTHORIZATION_HEADER_PREFIX,
+    AUTHORIZATION_HEADER_TOKEN_PREFIX, TokenType, verify_token,
 };
 
 use crate::{bad_request_by, unauthorized_by};
@@ -59,6 +59,11 @@ impl<'a, 'r> FromRequest<'a, 'r> for AuthenticationToken {
                         let length = AUTHORIZATION_HEADER_PREFIX.len();
                         h[length..].to_string()
                     },
+                    TokenType::ExchangedToken => {
+                        let length =
+                            AUTHORIZATION_HEADER_EXCHANGED_PREFIX.len();
+                        h[length..].to_string()
+                    },
                     TokenType::PersonalAccessToken => {
                         let length = AUTHORIZATION_HEADER_TOKEN_PREFIX.len();
                         h[length..].to_string()
@@ -10,6 +10,7 @@ use crate::model::token::Claims;
 
 const AUTHORIZATION_HEADER_PREFIX: &str = "Bearer ";
 const AUTHORIZATION_HEADER_TOKEN_PREFIX: &str = "Access-Token ";
+const AUTHORIZATION_HEADER_EXCHANGED_PREFIX: &str = "Exchanged-Token ";
 
 // NOTE: this function does not check value in database.
 fn verify_token<T>(
@@ -28,6 +29,7 @@ where
 #[derive(PartialEq)]
 pub enum TokenType {
     BrowserCookieToken,
+    ExchangedToken, // see service::token_exchange
     PersonalAccessToken,
 }
 
@@ -49,6 +51,9 @@ impl<'a, 'r> FromRequest<'a, 'r> for TokenType {
                 return Outcome::Success(Self::BrowserCookieToken);
             } else if header.starts_with(AUTHORIZATION_HEADER_TOKEN_PREFIX) {
                 return Outcome::Success(Self::PersonalAccessToken);
+            } else if header.starts_with(AUTHORIZATION_HEADER_EXCHANGED_PREFIX)
+            {
+                return Outcome::Success(Self::ExchangedToken);
             }
         }
         unprocessable_entity_by!(Self::Error::Unknown)
@@ -82,7 +82,8 @@ impl<'a, 'r> FromRequest<'a, 'r> for VerificationToken {
                     return not_found_by!(VerificationTokenError::Unknown);
                 }
 
-                let verification_token = token + "." + &result.unwrap();
+                let verification_token =
+                    format!("{}.{}", token, result.unwrap());
                 let config = req.guard::<State<Config>>().unwrap();
                 match verify_token::<VerificationClaims>(
                     &verification_token,
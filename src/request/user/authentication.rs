@@ -1,17 +1,16 @@
-use std::io::{self, Read};
-
 use rocket::{Data, Outcome::*, Request};
 use rocket::data::{self, FromData, Transform, Transformed};
 use rocket::http::Status;
 
+use crate::request::body_limit::{Auth, read_body};
+use crate::request::encoding::EncodingError;
+
 /// UserAuthentication
 pub enum UserAuthenticationError {
-    Io(io::Error),
+    Encoding(EncodingError),
     Empty,
 }
 
-const USER_AUTHENTICATION_LENGTH_LIMIT: u64 = 256;
-
 #[derive(Clone, Debug, Deserialize)]
 pub struct UserAuthentication {
     pub username: String,
@@ -24,21 +23,21 @@ impl<'v> FromData<'v> for UserAuthentication {
     type Borrowed = str;
 
     fn transform(
-        _: &Request,
+        req: &Request,
         data: Data,
     ) -> Transform<data::Outcome<Self::Owned, Self::Error>> {
-        let mut stream = data.open().take(USER_AUTHENTICATION_LENGTH_LIMIT);
-        let mut string = String::with_capacity(
-            (USER_AUTHENTICATION_LENGTH_LIMIT / 2) as usize,
-        );
-        let outcome = match stream.read_to_string(&mut string) {
-            Ok(_) => Success(string),
-            Err(e) => {
+        let body = read_body::<Auth>(req, data).map(String::from_utf8);
+        let outcome = match body {
+            Ok(Ok(string)) => Success(string),
+            Ok(Err(_)) => {
                 Failure((
-                    Status::InternalServerError,
-                    UserAuthenticationError::Io(e),
+                    Status::UnprocessableEntity,
+                    UserAuthenticationError::Empty,
                 ))
             },
+            Err(e) => {
+                Failure((e.status(), UserAuthenticationError::Encoding(e)))
+            },
         };
 
         Transform::Borrowed(outcome)
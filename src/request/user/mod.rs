@@ -1,4 +1,5 @@
 pub mod authentication;
+pub mod notification_preference;
 pub mod registration;
 
 use rocket::{Request, State, request};
@@ -7,10 +8,15 @@ use rocket_slog::SyncLogger;
 
 use crate::config::Config;
 use crate::db::DbConn;
-use crate::model::token::{BrowserCookieTokenClaims, PersonalAccessTokenClaims};
+use crate::model::token::{
+    BrowserCookieTokenClaims, Claims, ExchangedTokenClaims,
+    PersonalAccessTokenClaims,
+};
 use crate::model::user::User;
 use crate::request::token::TokenType;
 use crate::request::token::authentication::AuthenticationToken;
+use crate::service::token_exchange::TokenExchange;
+use crate::ss::SsConn;
 
 /// User
 impl<'a, 'r> FromRequest<'a, 'r> for &'a User {
@@ -40,11 +46,30 @@ impl<'a, 'r> FromRequest<'a, 'r> for &'a User {
                         &logger,
                     )
                 },
+                TokenType::ExchangedToken => {
+                    let claims = ExchangedTokenClaims::decode(
+                        &authentication_token,
+                        &config.authentication_token_issuer,
+                        &config.authentication_token_secret,
+                    )
+                    .ok()?;
+                    let mut ss_conn = req.guard::<SsConn>().succeeded()?;
+                    let (uuid, _) = TokenExchange::new(&mut *ss_conn)
+                        .find(&claims.get_subject())
+                        .ok()??;
+                    User::find_by_uuid(&uuid, &db_conn, &logger)
+                },
+                // not by `find_by_token`, as the claims are of the same type
+                // as the ones of browser cookie tokens
                 TokenType::PersonalAccessToken => {
-                    User::find_by_token::<PersonalAccessTokenClaims>(
+                    let claims = PersonalAccessTokenClaims::decode(
                         &authentication_token,
                         &config.authentication_token_issuer,
                         &config.authentication_token_secret,
+                    )
+                    .ok()?;
+                    User::find_by_access_token(
+                        &claims.get_subject(),
                         &db_conn,
                         &logger,
                     )
@@ -0,0 +1,14 @@
+use std::collections::BTreeMap;
+
+/// NotificationPreference
+///
+/// The channel (`email`, `webhook` or `none`) of notifications on an event.
+/// `webhook_url` is only for `webhook`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct NotificationPreference {
+    pub channel: Option<String>,
+    pub webhook_url: Option<String>,
+}
+
+/// NotificationPreferences by the event (e.g. `alert`)
+pub type NotificationPreferences = BTreeMap<String, NotificationPreference>;
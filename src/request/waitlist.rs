@@ -0,0 +1,13 @@
+/// WaitlistEntry
+#[derive(Clone, Deserialize)]
+pub struct WaitlistEntry {
+    pub email: String,
+}
+
+impl Default for WaitlistEntry {
+    fn default() -> Self {
+        Self {
+            email: "".to_string(),
+        }
+    }
+}
@@ -1,13 +1,21 @@
 use std::io::Cursor;
 
 use rocket::State;
-use rocket::http::{Cookies, ContentType, Status};
+use rocket::http::{Cookies, ContentType, Status, StatusClass};
 use rocket::request::Request;
 use rocket::response::Responder;
 use rocket::response::Response as RawResponse;
 use rocket_contrib::json::JsonValue;
+use serde::Serialize;
 
 use crate::config::Config;
+use crate::request::api_version::{self, ApiVersion};
+use crate::request::concurrency::ConcurrencyLimitState;
+use crate::request::logger::{REQUEST_ID_HEADER, RequestId};
+use crate::request::quota::QuotaState;
+use crate::request::rate_limit::RateLimitState;
+use crate::service::deprecation::Used;
+use crate::validation::ValidationError;
 
 const MAX_AGE: &str = "10800"; // 3 hours
 const VARY: &str = "Accept-Encoding,Origin";
@@ -17,6 +25,8 @@ pub struct Response<'a> {
     pub cookies: Cookies<'a>,
     pub status: Status,
     pub data: JsonValue,
+    pub etag: Option<String>,
+    pub deprecation: Option<&'static str>,
 }
 
 impl<'a> Default for Response<'a> {
@@ -25,6 +35,8 @@ impl<'a> Default for Response<'a> {
             cookies: Cookies::empty(),
             status: Status::Ok,
             data: json!(null),
+            etag: None,
+            deprecation: None,
         }
     }
 }
@@ -45,6 +57,215 @@ impl<'a> Response<'a> {
         self.data = data;
         self
     }
+
+    // set an entity tag (see request::etag)
+    pub fn etag(mut self, etag: String) -> Response<'a> {
+        self.etag = Some(etag);
+        self
+    }
+
+    // mark a deprecated field in use (see service::deprecation)
+    pub fn deprecate(mut self, name: &'static str) -> Response<'a> {
+        self.deprecation = Some(name);
+        self
+    }
+
+    // set the status and the body of the error
+    pub fn error(self, error: ApiError) -> Response<'a> {
+        self.status(error.status).format(error.to_json())
+    }
+
+    // make a page of a list (see request::pagination)
+    pub fn paginate<T: Serialize>(
+        self,
+        data: Vec<T>,
+        next_cursor: Option<String>,
+    ) -> Paginated<'a, T> {
+        Paginated {
+            response: self,
+            data,
+            next_cursor,
+            total_estimate: None,
+        }
+    }
+}
+
+/// An error of the API.
+///
+/// All of the errors are rendered in the same envelope (also ones by catchers
+/// in `route::error`). The code is a stable one for clients (the status in
+/// snake case by default), and the message is for humans.
+///
+/// ```json
+/// {
+///   "code": "unprocessable_entity",
+///   "message": "The input is invalid",
+///   "field_errors": [{"field": "name", "messages": ["Must exist"]}]
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: Status,
+    pub code: String,
+    pub message: String,
+    pub field_errors: Vec<ValidationError>,
+}
+
+impl ApiError {
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            code: status.reason.to_lowercase().replace(' ', "_"),
+            message: default_message(status),
+            field_errors: vec![],
+        }
+    }
+
+    /// Returns 422 Unprocessable Entity with the errors of the input.
+    pub fn invalid(errors: Vec<ValidationError>) -> Self {
+        Self::new(Status::UnprocessableEntity).field_errors(errors)
+    }
+
+    pub fn code(mut self, code: &str) -> Self {
+        self.code = code.to_string();
+        self
+    }
+
+    pub fn message<T: ToString>(mut self, message: T) -> Self {
+        self.message = message.to_string();
+        self
+    }
+
+    pub fn field_errors(mut self, errors: Vec<ValidationError>) -> Self {
+        self.field_errors = errors;
+        self
+    }
+
+    /// Adds an error of the field.
+    pub fn field(mut self, field: &str, message: &str) -> Self {
+        self.field_errors.push(ValidationError {
+            field: field.to_string(),
+            messages: vec![message.to_string()],
+        });
+        self
+    }
+
+    /// Returns the body. It's serialized in the order of the fields (the one
+    /// of the keys in `JsonValue` depends on the features of serde_json).
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            code: &self.code,
+            message: &self.message,
+            field_errors: &self.field_errors,
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!(self.body())
+    }
+}
+
+/// The body of `ApiError`.
+#[derive(Debug, Serialize)]
+pub struct ErrorBody<'a> {
+    pub code: &'a str,
+    pub message: &'a str,
+    pub field_errors: &'a [ValidationError],
+}
+
+fn default_message(status: Status) -> String {
+    match status.code {
+        400 => "The request header/body is invalid",
+        401 => "The request is not allowed",
+        403 => "The request is not permitted",
+        404 => "The resource is not found",
+        406 => "The API version is not supported",
+        409 => "The resource has been changed",
+        413 => "The request body is too large",
+        415 => "The content encoding is not supported",
+        422 => "The input is invalid",
+        428 => "The request must be confirmed",
+        429 => "Too many requests. Retry later",
+        500 => "Internal server error occurred",
+        503 => "The server is busy. Retry later",
+        _ => status.reason,
+    }
+    .to_string()
+}
+
+impl<'r> Responder<'r> for ApiError {
+    fn respond_to(self, req: &Request) -> Result<RawResponse<'r>, Status> {
+        let res: Response = Default::default();
+        res.error(self).respond_to(req)
+    }
+}
+
+/// A page of a list.
+///
+/// The body is an envelope like this, and the next page is also in `Link`
+/// header (RFC 5988) as `rel="next"` if there is.
+///
+/// ```json
+/// {
+///   "data": [...],
+///   "next_cursor": "NTA",
+///   "total_estimate": 120
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Paginated<'a, T> {
+    pub response: Response<'a>,
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub total_estimate: Option<i64>,
+}
+
+impl<'a, T> Paginated<'a, T> {
+    // set the (approximate) number of all entries in the list
+    pub fn total_estimate(mut self, count: i64) -> Paginated<'a, T> {
+        self.total_estimate = Some(count);
+        self
+    }
+}
+
+/// Returns the target of `rel="next"` link which replaces the cursor in the
+/// query.
+pub fn next_link(path: &str, query: Option<&str>, cursor: &str) -> String {
+    let mut params: Vec<String> = query
+        .unwrap_or("")
+        .split('&')
+        .filter(|p| !p.is_empty() && p.split('=').next() != Some("cursor"))
+        .map(|p| p.to_string())
+        .collect();
+    params.push(format!("cursor={}", cursor));
+    format!("<{}?{}>; rel=\"next\"", path, params.join("&"))
+}
+
+impl<'r, T: Serialize> Responder<'r> for Paginated<'r, T> {
+    fn respond_to(self, req: &Request) -> Result<RawResponse<'r>, Status> {
+        let Paginated {
+            response,
+            data,
+            next_cursor,
+            total_estimate,
+        } = self;
+        let data = json!({
+            "data": data,
+            "next_cursor": next_cursor,
+            "total_estimate": total_estimate,
+        });
+        let mut res = response.format(data).respond_to(req)?;
+
+        res.set_raw_header("Access-Control-Expose-Headers", "Link");
+        if let Some(ref cursor) = next_cursor {
+            let uri = req.uri();
+            res.set_raw_header(
+                "Link",
+                next_link(uri.path(), uri.query(), cursor),
+            );
+        }
+        Ok(res)
+    }
 }
 
 impl<'r> Responder<'r> for Response<'r> {
@@ -66,7 +287,85 @@ impl<'r> Responder<'r> for Response<'r> {
             .raw_header("Access-Control-Allow-Credentials", "true")
             .raw_header("Vary", VARY);
 
-        let body = self.data.to_string();
+        // set by RateLimit guard
+        if let Some(ref state) = *req.local_cache(|| None::<RateLimitState>) {
+            builder
+                .raw_header("X-RateLimit-Limit", state.limit.to_string())
+                .raw_header(
+                    "X-RateLimit-Remaining",
+                    state.remaining.to_string(),
+                )
+                .raw_header("X-RateLimit-Reset", state.reset.to_string());
+            if let Some(retry_after) = state.retry_after {
+                builder.raw_header("Retry-After", retry_after.to_string());
+            }
+        }
+
+        // set by ConcurrencyLimit guard
+        if let Some(ref state) =
+            *req.local_cache(|| None::<ConcurrencyLimitState>)
+        {
+            builder.raw_header("Retry-After", state.retry_after.to_string());
+        }
+
+        // set by IngestionQuota::reserve
+        if let Some(ref state) = *req.local_cache(|| None::<QuotaState>) {
+            builder
+                .raw_header(
+                    "X-Quota-Messages-Limit",
+                    state.messages_limit.to_string(),
+                )
+                .raw_header(
+                    "X-Quota-Messages-Remaining",
+                    state.messages_remaining.to_string(),
+                )
+                .raw_header(
+                    "X-Quota-Bytes-Limit",
+                    state.bytes_limit.to_string(),
+                )
+                .raw_header(
+                    "X-Quota-Bytes-Remaining",
+                    state.bytes_remaining.to_string(),
+                )
+                .raw_header("X-Quota-Reset", state.reset.to_string());
+            if let Some(grace_until) = state.grace_until {
+                builder
+                    .raw_header("X-Quota-Grace-Until", grace_until.to_string());
+            }
+        }
+
+        // set by ApiVersion guard
+        if let Some(ref version) = *req.local_cache(|| None::<ApiVersion>) {
+            builder.raw_header(api_version::HEADER, version.0.to_string());
+        }
+
+        // set by RequestLogger guard
+        if let Some(ref id) = *req.local_cache(|| None::<RequestId>) {
+            builder.raw_header(REQUEST_ID_HEADER, id.0.to_string());
+        }
+
+        // for Deprecations fairing
+        if let Some(name) = self.deprecation {
+            req.local_cache(|| Some(Used(name)));
+        }
+
+        if let Some(etag) = self.etag {
+            builder.raw_header("ETag", etag);
+        }
+        if self.status == Status::NotModified {
+            return builder.ok();
+        }
+
+        // an error without any body is in the same format as the others
+        let class = self.status.class();
+        let body = if self.data.is_null()
+            && (class == StatusClass::ClientError
+                || class == StatusClass::ServerError)
+        {
+            ApiError::new(self.status).to_json().to_string()
+        } else {
+            self.data.to_string()
+        };
         builder.sized_body(Cursor::new(body)).ok()
     }
 }
@@ -79,7 +378,7 @@ pub fn no_content_for<'a>(methods: &str, config: &Config) -> RawResponse<'a> {
     res.set_raw_header("Access-Control-Allow-Credentials", "true");
     res.set_raw_header(
         "Access-Control-Allow-Headers",
-        "Authorization,Content-Type,X-Requested-With",
+        "Authorization,Content-Type,X-Api-Version,X-Requested-With",
     );
     res.set_raw_header(
         "Access-Control-Allow-Methods",
@@ -94,3 +393,46 @@ pub fn no_content_for<'a>(methods: &str, config: &Config) -> RawResponse<'a> {
     res.set_status(Status::NoContent);
     res
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_api_error() {
+        let e = ApiError::new(Status::PreconditionRequired);
+        assert_eq!(e.code, "precondition_required");
+        assert_eq!(e.message, "The request must be confirmed");
+
+        let e = ApiError::new(Status::UnprocessableEntity)
+            .code("undeliverable_email")
+            .field("email", "Must be a deliverable email address");
+        assert_eq!(
+            *e.to_json(),
+            serde_json::json!({
+                "code": "undeliverable_email",
+                "field_errors": [{
+                    "field": "email",
+                    "messages": ["Must be a deliverable email address"],
+                }],
+                "message": "The input is invalid",
+            })
+        );
+
+        let e = ApiError::new(Status::Gone);
+        assert_eq!(e.code, "gone");
+        assert_eq!(e.message, "Gone");
+    }
+
+    #[test]
+    fn test_next_link() {
+        assert_eq!(
+            next_link("/namespace/hgetall", None, "NTA"),
+            r#"</namespace/hgetall?cursor=NTA>; rel="next""#
+        );
+        assert_eq!(
+            next_link("/a", Some("limit=2&cursor=MA&service=api"), "Mg"),
+            r#"</a?limit=2&service=api&cursor=Mg>; rel="next""#
+        );
+    }
+}
@@ -1,41 +1,65 @@
 use diesel::result::Error;
 use rocket::State;
 use rocket::http::Status;
-use rocket_slog::SyncLogger;
+use rocket_contrib::json::Json;
+use rocket_contrib::json::JsonValue;
 use serde_json::Value;
 
+use crate::clock::SharedClock;
 use crate::config::Config;
 use crate::db::DbConn;
-use crate::model::access_token::{AccessToken, AgentType};
+use crate::id::SharedIdGenerator;
+use crate::model::access_token::{AccessToken, AgentType, Scope};
 use crate::model::token::{AuthenticationClaims, Claims, TokenData};
 use crate::model::user::User;
-use crate::request::access_token::AccessTokenData as RequestData;
-use crate::response::Response;
+use crate::request::access_token::{
+    AccessTokenData as RequestData, AccessTokenScopesData as ScopesData,
+};
+use crate::request::confirmation::ConfirmationToken;
+use crate::request::logger::RequestLogger;
+use crate::request::pagination::Page;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::request::scope::{NamespaceAdmin, Scoped};
+use crate::request::sudo::Sudo;
+use crate::response::{ApiError, Paginated, Response};
+use crate::service::confirmation::{Confirmation, ConfirmationAction};
+use crate::service::user_agent::UserAgents;
+use crate::ss::SsConn;
 
 pub mod preflight {
     use rocket::State;
     use rocket::response::Response as RawResponse;
-    use rocket_slog::SyncLogger;
 
     use crate::config::Config;
     use crate::model::access_token::AgentType;
+    use crate::request::logger::RequestLogger;
     use crate::response::no_content_for;
 
     #[options("/access_token/del/<uuid>", rank = 2)]
     pub fn del<'a>(
         uuid: String,
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(logger, "uuid: {}", uuid);
         no_content_for("PATCH", &config)
     }
 
+    #[options("/access_token/del/<uuid>/confirm", rank = 2)]
+    pub fn del_confirm<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "uuid: {}", uuid);
+        no_content_for("POST", &config)
+    }
+
     #[options("/access_token/dump/<uuid>", rank = 2)]
     pub fn dump<'a>(
         uuid: String,
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(logger, "uuid: {}", uuid);
         no_content_for("PATCH", &config)
@@ -45,7 +69,17 @@ pub mod preflight {
     pub fn hset_state<'a>(
         uuid: String,
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "uuid: {}", uuid);
+        no_content_for("PATCH", &config)
+    }
+
+    #[options("/access_token/hset/<uuid>/scopes", rank = 2)]
+    pub fn hset_scopes<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(logger, "uuid: {}", uuid);
         no_content_for("PATCH", &config)
@@ -55,7 +89,7 @@ pub mod preflight {
     pub fn append<'a>(
         agent_type: AgentType,
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(logger, "agent_type: {}", agent_type);
         no_content_for("PUT", &config)
@@ -67,7 +101,7 @@ pub mod preflight {
         start: i64,
         stop: i64,
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(
             logger,
@@ -77,13 +111,19 @@ pub mod preflight {
     }
 }
 
+// Reveals the token (only once). It requires sudo mode.
 #[patch("/access_token/dump/<uuid>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
 pub fn dump<'a>(
+    _rate_limit: RateLimit<Api>,
     uuid: String,
     user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    _sudo: Sudo,
     conn: DbConn,
     config: State<Config>,
-    logger: SyncLogger,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
 ) -> Response<'a> {
     info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
 
@@ -98,7 +138,7 @@ pub fn dump<'a>(
             match AccessToken::owned_by_uuid(&user, &uuid, &conn, &logger) {
                 // Note: this is available only once
                 Some(mut t) if t.token.is_none() => {
-                    let token = AccessToken::generate_token();
+                    let token = AccessToken::generate_token(&**ids);
                     match t.update_token(&token, &conn, &logger) {
                         Err(e) => {
                             error!(logger, "err: {}", e);
@@ -146,23 +186,102 @@ pub fn dump<'a>(
             "state": t.state.to_string(),
             "token": token,
             "revoked_at": Value::Null,
+            "scopes": t.scopes,
             "created_at": t.created_at,
             "updated_at": t.updated_at,
         }
     }))
 }
 
+// Issues a confirmation token for the revocation of the access token. It must
+// be given as X-Confirmation-Token header to `del` within the expiration.
+#[post("/access_token/del/<uuid>/confirm", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn del_confirm<'a>(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    conn: DbConn,
+    mut ss_conn: SsConn,
+    config: State<Config>,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    if AccessToken::owned_by_uuid(&user, &uuid, &conn, &logger).is_none() {
+        error!(logger, "err: not found {}", uuid);
+        return res.status(Status::NotFound);
+    }
+
+    let duration = config.token_duration_confirmation;
+    let result = Confirmation::new(&mut *ss_conn).issue(
+        &user.uuid.to_string(),
+        ConfirmationAction::RevokeAccessToken,
+        &uuid,
+        duration,
+        &**ids,
+    );
+    match result {
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            res.status(Status::InternalServerError)
+        },
+        Ok(token) => res.format(json!({
+            "confirmation_token": {
+                "token": token,
+                "expires_in": duration.as_secs(),
+            }
+        })),
+    }
+}
+
+// Revokes the access token. A confirmation token issued by `del_confirm` is
+// required unless the user is in sudo mode, otherwise it responds 428
+// Precondition Required.
 #[patch("/access_token/del/<uuid>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
 pub fn del<'a>(
+    _rate_limit: RateLimit<Api>,
     uuid: String,
     user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    sudo: Option<Sudo>,
+    confirmation: ConfirmationToken,
     conn: DbConn,
-    logger: SyncLogger,
+    mut ss_conn: SsConn,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
 ) -> Response<'a> {
     info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
 
     let res: Response = Default::default();
 
+    let confirmed = match sudo {
+        Some(_) => Ok(true),
+        None => Confirmation::new(&mut *ss_conn).consume(
+            &user.uuid.to_string(),
+            ConfirmationAction::RevokeAccessToken,
+            &uuid,
+            &confirmation.0,
+        ),
+    };
+    match confirmed {
+        Ok(true) => (),
+        result => {
+            if let Err(e) = result {
+                error!(logger, "err: {}", e);
+            }
+            return res.error(
+                ApiError::new(Status::PreconditionRequired)
+                    .field("confirmation_token", "Must be confirmed"),
+            );
+        },
+    }
+
     let result: Result<(), Error> = conn
         .build_transaction()
         .serializable()
@@ -175,7 +294,7 @@ pub fn del<'a>(
                     Err(Error::RollbackTransaction)
                 },
                 Some(t) => {
-                    match t.revoke(&conn, &logger) {
+                    match t.revoke(&**clock, &conn, &logger) {
                         Err(e) => {
                             error!(logger, "err: {}", e);
                             Err(Error::RollbackTransaction)
@@ -202,11 +321,13 @@ pub fn del<'a>(
     rank = 1
 )]
 pub fn hset_state<'a>(
+    _rate_limit: RateLimit<Api>,
     uuid: String,
     data: RequestData,
     user: &User,
+    _scope: Scoped<NamespaceAdmin>,
     conn: DbConn,
-    logger: SyncLogger,
+    logger: RequestLogger,
 ) -> Response<'a> {
     info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
 
@@ -245,11 +366,74 @@ pub fn hset_state<'a>(
     }))
 }
 
+#[patch(
+    "/access_token/hset/<uuid>/scopes",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+pub fn hset_scopes<'a>(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    data: Json<ScopesData>,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    let names = &data.access_token.scopes;
+    let scopes: Vec<Scope> =
+        names.iter().filter_map(|s| Scope::from_name(s)).collect();
+    if scopes.len() != names.len() {
+        return res.error(
+            ApiError::new(Status::UnprocessableEntity)
+                .field("scopes", "Contains unknown scope"),
+        );
+    }
+
+    let result: Result<(), Error> = conn
+        .build_transaction()
+        .serializable()
+        .deferrable()
+        .read_write()
+        .run::<(), diesel::result::Error, _>(|| {
+            match AccessToken::owned_by_uuid(&user, &uuid, &conn, &logger) {
+                None => {
+                    error!(logger, "err: not found {}", uuid);
+                    Err(Error::RollbackTransaction)
+                },
+                Some(t) => {
+                    match t.update_scopes(&scopes, &conn, &logger) {
+                        Err(e) => {
+                            error!(logger, "err: {}", e);
+                            Err(Error::RollbackTransaction)
+                        },
+                        Ok(_) => Ok(()),
+                    }
+                },
+            }
+        });
+
+    if result.is_err() {
+        return res.status(Status::NotFound);
+    }
+
+    res.format(json!({
+        "access_token": 1,
+    }))
+}
+
 #[put("/access_token/append/<agent_type>", rank = 1)]
 pub fn append<'a>(
+    _rate_limit: RateLimit<Api>,
     user: &User,
+    _scope: Scoped<NamespaceAdmin>,
     agent_type: AgentType,
-    logger: SyncLogger,
+    logger: RequestLogger,
 ) -> Response<'a> {
     info!(logger, "user: {}, agent_type: {}", user.uuid, agent_type);
 
@@ -258,63 +442,96 @@ pub fn append<'a>(
     res
 }
 
-#[get("/access_token/lrange/<agent_type>/<start>/<stop>", rank = 1)]
+#[get(
+    "/access_token/lrange/<agent_type>/<start>/<stop>?<cursor>&<limit>",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
 pub fn lrange<'a>(
+    _rate_limit: RateLimit<Api>,
     agent_type: AgentType,
     start: i64,
     stop: i64,
+    cursor: Option<String>,
+    limit: Option<i64>,
     user: &User,
+    _scope: Scoped<NamespaceAdmin>,
     conn: DbConn,
-    logger: SyncLogger,
-) -> Response<'a> {
+    mut ss_conn: SsConn,
+    logger: RequestLogger,
+) -> Result<Paginated<'a, JsonValue>, Response<'a>> {
     info!(
         logger,
-        "user: {}, agent_type: {}, start: {}, stop: {}",
+        "user: {}, agent_type: {}, start: {}, stop: {}, cursor: {:?}, \
+         limit: {:?}",
         user.uuid,
         agent_type,
         start,
         stop,
+        cursor,
+        limit,
     );
 
     let res: Response = Default::default();
 
-    // TODO
-    let mut offset = start;
-    if offset < 1 {
-        offset = 0;
-    }
-
-    let mut limit = stop - start + 2;
-    if limit < 1 {
-        limit = 1;
-    }
+    let page = match Page::range(start, stop).query(cursor.as_deref(), limit)
+    {
+        Ok(p) => p,
+        Err(e) => {
+            return Err(res.error(ApiError::invalid(vec![e])));
+        },
+    };
 
-    let data = match AccessToken::owned_all_by_agent_type(
-        &user, agent_type, offset, limit, &conn, &logger,
+    let (tokens, next_cursor) = match AccessToken::owned_all_by_agent_type(
+        &user,
+        agent_type,
+        page.offset,
+        page.fetch_limit(),
+        &conn,
+        &logger,
     ) {
         None => {
             error!(logger, "err: not found user.id {}", user.uuid);
-            vec![]
-        },
-        Some(a) => {
-            let token = "***";
-            a.iter()
-                .map(|t| {
-                    json!({
-                        "access_token": {
-                            "uuid": t.uuid.to_string(),
-                            "name": t.name,
-                            "agent_type": t.agent_type.to_string(),
-                            "state": t.state.to_string(),
-                            "token": token,
-                            "revoked_at": Value::Null,
-                            "created_at": t.created_at,
-                            "updated_at": t.updated_at,
-                        }
-                    })
-                })
-                .collect()
+            (vec![], None)
         },
+        Some(a) => page.split(a),
     };
-    res.format(json!(data))
+    let token = "***";
+    let data: Vec<JsonValue> = tokens
+        .iter()
+        .map(|t| {
+            // sampled ones (see service::user_agent)
+            let user_agents: Vec<JsonValue> =
+                match UserAgents::new(&mut *ss_conn).latest(&t.uuid) {
+                    Ok(v) => v
+                        .into_iter()
+                        .map(|(agent, ts)| {
+                            json!({
+                                "user_agent": agent,
+                                "last_seen_at": ts,
+                            })
+                        })
+                        .collect(),
+                    Err(e) => {
+                        error!(logger, "err: {}", e);
+                        vec![]
+                    },
+                };
+            json!({
+                "access_token": {
+                    "uuid": t.uuid.to_string(),
+                    "name": t.name,
+                    "agent_type": t.agent_type.to_string(),
+                    "state": t.state.to_string(),
+                    "token": token,
+                    "revoked_at": Value::Null,
+                    "scopes": t.scopes,
+                    "created_at": t.created_at,
+                    "updated_at": t.updated_at,
+                },
+                "user_agents": user_agents,
+            })
+        })
+        .collect();
+    Ok(res.paginate(data, next_cursor))
 }
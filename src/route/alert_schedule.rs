@@ -0,0 +1,125 @@
+use chrono::NaiveTime;
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+
+use crate::db::DbConn;
+use crate::model::alert_schedule::{AlertSchedule, NewAlertSchedule};
+use crate::model::user::User;
+use crate::request::alert_schedule::AlertSchedule as RequestData;
+use crate::request::logger::RequestLogger;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::response::{ApiError, Response};
+use crate::validation::alert_schedule::{TIME_FORMAT, Validator};
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/alert_schedule/hget", rank = 2)]
+    pub fn hget<'a>(
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "hget");
+        no_content_for("GET", &config)
+    }
+
+    #[options("/alert_schedule/hset", rank = 2)]
+    pub fn hset<'a>(
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "hset");
+        no_content_for("POST", &config)
+    }
+}
+
+fn format_time(t: Option<NaiveTime>) -> Option<String> {
+    t.map(|t| t.format(TIME_FORMAT).to_string())
+}
+
+fn format_alert_schedule(s: &AlertSchedule) -> JsonValue {
+    json!({"alert_schedule": {
+        "timezone": s.timezone,
+        "quiet_hours_start": format_time(s.quiet_hours_start),
+        "quiet_hours_end": format_time(s.quiet_hours_end),
+        "updated_at": s.updated_at,
+    }})
+}
+
+// Returns the schedule of alert emails of the user (the default is to receive
+// them at any time in UTC).
+#[get("/alert_schedule/hget", rank = 1)]
+pub fn hget(
+    _rate_limit: RateLimit<Api>,
+    user: &User,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}", user.uuid);
+
+    match AlertSchedule::find_by_user_id(user.id, &conn, &logger) {
+        Some(s) => res.format(format_alert_schedule(&s)),
+        None => res.format(json!({"alert_schedule": {
+            "timezone": "UTC",
+            "quiet_hours_start": null,
+            "quiet_hours_end": null,
+            "updated_at": null,
+        }})),
+    }
+}
+
+// Saves the schedule of alert emails of the user. Alerts in the quiet hours
+// are delivered at the end of them.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "timezone": "Europe/Zurich",
+//    "quiet_hours_start": "22:00",
+//    "quiet_hours_end": "07:00"
+// }
+// ```
+#[post("/alert_schedule/hset", data = "<data>", format = "json", rank = 1)]
+pub fn hset(
+    _rate_limit: RateLimit<Api>,
+    user: &User,
+    data: Json<RequestData>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}", user.uuid);
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let parse = |s: &Option<String>| {
+        s.as_ref()
+            .and_then(|s| NaiveTime::parse_from_str(s, TIME_FORMAT).ok())
+    };
+    let schedule = NewAlertSchedule {
+        user_id: user.id,
+        timezone: data
+            .0
+            .timezone
+            .clone()
+            .unwrap_or_else(|| "UTC".to_string()),
+        quiet_hours_start: parse(&data.0.quiet_hours_start),
+        quiet_hours_end: parse(&data.0.quiet_hours_end),
+    };
+    match AlertSchedule::upsert(&schedule, &conn, &logger) {
+        Some(s) => res.format(format_alert_schedule(&s)),
+        None => res.status(Status::InternalServerError),
+    }
+}
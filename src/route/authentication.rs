@@ -1,16 +1,35 @@
-use chrono::Utc;
-use redis::{Commands, RedisError};
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
 use rocket::State;
 use rocket::http::{Cookie, Cookies, Status};
-use rocket_slog::SyncLogger;
 
+use crate::clock::{Clock, SharedClock};
 use crate::config::Config;
 use crate::db::DbConn;
+use crate::id::SharedIdGenerator;
+use crate::job::{Job, JobKind};
+use crate::license::License;
+use crate::mailer::security::SecurityNotice;
+use crate::model::access_token::Scope;
 use crate::model::user::User;
-use crate::model::Authenticatable;
-use crate::model::token::{AuthenticationClaims, Claims, TokenData};
+use crate::model::token::{
+    AuthenticationClaims, Claims, ExchangedTokenClaims, TokenData,
+};
+use crate::mq::JobQueue;
+use crate::request::body_limit::Auth;
+use crate::request::client_ip::ClientIp;
+use crate::request::csrf::{CsrfToken, CsrfTokenError};
+use crate::request::json::JsonBody;
+use crate::request::logger::RequestLogger;
+use crate::request::rate_limit::{Api, Login, RateLimit};
+use crate::request::sudo::{Sudo, SudoData};
+use crate::request::token::TokenType;
 use crate::request::user::authentication::UserAuthentication as RequestData;
-use crate::response::Response;
+use crate::response::{ApiError, Response};
+use crate::service::auth_backend;
+use crate::service::known_ip::KnownIp;
+use crate::service::token_exchange::{EXPIRATION, TokenExchange};
 use crate::ss::SsConn;
 use crate::util::{split_token, make_cookie};
 
@@ -30,52 +49,49 @@ pub mod preflight {
     pub fn logout<'a>(config: State<Config>) -> RawResponse<'a> {
         no_content_for("POST", &config)
     }
+
+    #[options("/sudo", rank = 2)]
+    pub fn sudo<'a>(config: State<Config>) -> RawResponse<'a> {
+        no_content_for("POST", &config)
+    }
+
+    #[options("/token/exchange", rank = 2)]
+    pub fn exchange<'a>(config: State<Config>) -> RawResponse<'a> {
+        no_content_for("POST", &config)
+    }
 }
 
 pub mod preignition {
-    use chrono::{Duration, Utc};
-    use redis::{Commands, RedisError};
     use rocket::State;
-    use rocket::http::{Cookie, Cookies, SameSite, Status};
-    use rocket_slog::SyncLogger;
+    use rocket::http::{Cookies, Status};
 
     use crate::config::Config;
+    use crate::id::SharedIdGenerator;
+    use crate::request::csrf::CsrfToken;
+    use crate::request::logger::RequestLogger;
     use crate::response::Response;
     use crate::ss::SsConn;
-    use crate::util::generate_random_hash;
 
     #[head("/login", format = "json", rank = 3)]
     pub fn login<'a>(
         config: State<Config>,
+        ids: State<SharedIdGenerator>,
         mut cookies: Cookies,
-        logger: SyncLogger,
+        logger: RequestLogger,
         mut ss_conn: SsConn,
     ) -> Response<'a> {
         // returns CSRF token
         let res: Response = Default::default();
         info!(logger, "preignition");
 
-        let duration = Duration::minutes(Config::CSRF_HASH_DURATION);
-        let expires_at = (Utc::now() + duration).timestamp();
-        let key_value = generate_random_hash(
-            Config::CSRF_HASH_SOURCE,
-            Config::CSRF_HASH_LENGTH,
+        let result = CsrfToken::issue(
+            &mut cookies,
+            &mut ss_conn,
+            &config,
+            &**ids,
+            &logger,
         );
-        let key = format!("xs-{}", key_value);
-        let value = "1";
-        let result: Result<String, RedisError> = ss_conn
-            .set_ex(&key, value, expires_at as usize)
-            .map_err(|e| {
-                error!(logger, "error: {}", e);
-                e
-            });
         if result.is_ok() {
-            let mut cookie = Cookie::new("csrf_token", key);
-            cookie.set_http_only(true);
-            cookie.set_secure(config.cookie_secure);
-            cookie.set_same_site(SameSite::Strict);
-            // encrypted value with expires 1 week from now
-            cookies.add_private(cookie);
             return res.status(Status::Ok);
         }
         error!(logger, "something went wrong on login");
@@ -84,80 +100,148 @@ pub mod preignition {
 }
 
 #[post("/login", data = "<data>", format = "json", rank = 1)]
+#[allow(clippy::too_many_arguments)]
 pub fn login<'a>(
+    _rate_limit: RateLimit<Login>,
+    csrf_token: Result<CsrfToken, CsrfTokenError>,
     config: State<Config>,
-    mut cookies: Cookies<'a>,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    license: State<License>,
+    cookies: Cookies<'a>,
+    client_ip: Option<ClientIp>,
     data: RequestData,
     db_conn: DbConn,
-    logger: SyncLogger,
     mut ss_conn: SsConn,
+    mut queue: JobQueue,
+    logger: RequestLogger,
 ) -> Response<'a> {
     let res: Response = Default::default();
 
-    let cookie = cookies.get_private("csrf_token").ok_or("");
-    if cookie.is_err() {
-        info!(logger, "error: missing csrf_token");
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token is required."
-        }));
-    }
-    let key = cookie.ok().unwrap().value().to_string();
-    let result: Result<i64, RedisError> = ss_conn.get(&key).map_err(|e| {
-        error!(logger, "error: {}", e);
-        e
-    });
-    if result.is_err() {
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token has been expired. Reload the page."
-        }));
+    if let Err(e) = csrf_token {
+        info!(logger, "error: {:?}", e);
+        return res.error(
+            ApiError::new(Status::Unauthorized).message(e.message()),
+        );
     }
 
-    match User::find_by_email(&data.username, &db_conn, &logger) {
-        Some(ref user) if user.verify_password(&data.password) => {
-            // TODO:
-            // set valid expires_at and impl review mechanism (check also
-            // `validate_exp` for Validation struct for JWT)
-            // e.g. let expires_at = (now + Duration::weeks(2)).timestamp();
-            let data = TokenData {
-                value: user.uuid.to_urn().to_string(),
-                granted_at: Utc::now().timestamp(),
-                expires_at: 0,
-            };
-            let authentication_token = AuthenticationClaims::encode(
-                data,
-                &config.authentication_token_issuer,
-                &config.authentication_token_key_id,
-                &config.authentication_token_secret,
+    let backend = auth_backend::select(&config, &license, &**clock, &**ids);
+    let backend = match backend {
+        Ok(b) => b,
+        Err(e) => {
+            warn!(logger, "error: {}", e);
+            return res.error(
+                ApiError::new(Status::Forbidden).message(e.to_string()),
             );
+        },
+    };
 
-            // TODO:
-            // * consider about implementation "Are you there?" modal
-            // * consider about extension (re-set it again?)
-            let (token, sign) = match split_token(authentication_token) {
-                Some(result) => result,
-                None => {
-                    return res.status(Status::InternalServerError).format(
-                        json!({
-                         "message": "Something wrong happen, sorry :'("
-                        }),
-                    );
-                },
-            };
-
-            let cookie = make_cookie(sign, &config);
-            cookies.add_private(cookie);
-            res.cookies(cookies).format(json!({ "token": token }))
+    match backend.authenticate(
+        &data.username,
+        &data.password,
+        &db_conn,
+        &logger,
+    ) {
+        Ok(ref user) => {
+            let now = clock.now();
+            if let Some(ClientIp(ip)) = client_ip {
+                notify_unknown_ip(
+                    user,
+                    &ip,
+                    now,
+                    &mut ss_conn,
+                    &mut queue,
+                    &logger,
+                );
+            }
+            sign_in(user, &config, &**clock, cookies)
         },
-        _ => {
-            warn!(logger, "login failed: username {}", data.username);
+        Err(e) => {
+            warn!(logger, "login failed: username {} ({})", data.username, e);
 
-            res.status(Status::Unauthorized).format(json!({
-                "message": "The credentials you've entered are incorrect."
-            }))
+            res.error(
+                ApiError::new(Status::Unauthorized)
+                    .message("The credentials you've entered are incorrect."),
+            )
         },
     }
 }
 
+// Notices the sign-in to the user if it's from an unknown IP address (see
+// `service::known_ip`). Any failure on it doesn't stop the sign-in.
+fn notify_unknown_ip(
+    user: &User,
+    ip: &IpAddr,
+    now: DateTime<Utc>,
+    ss_conn: &mut SsConn,
+    queue: &mut JobQueue,
+    logger: &RequestLogger,
+) {
+    let uuid = user.uuid.to_string();
+    match KnownIp::new(&mut *ss_conn).remember(&uuid, ip) {
+        Ok(true) => (),
+        Ok(false) => return,
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            return;
+        },
+    }
+    let job = Job::<String> {
+        kind: JobKind::SendSecurityNotificationEmail,
+        args: vec![
+            uuid,
+            SecurityNotice::NewSignIn.as_str().to_string(),
+            now.to_rfc3339(),
+            ip.to_string(),
+        ],
+    };
+    if let Err(e) = queue.enqueue(job) {
+        error!(logger, "err: {}", e);
+    }
+}
+
+// Issues an authentication token for the user.
+//
+// The signature part of the token is set as a private cookie and the rest is
+// returned in the response body.
+pub(crate) fn sign_in<'a>(
+    user: &User,
+    config: &Config,
+    clock: &dyn Clock,
+    mut cookies: Cookies<'a>,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    // TODO:
+    // set valid expires_at and impl review mechanism (check also
+    // `validate_exp` for Validation struct for JWT)
+    // e.g. let expires_at = (now + Duration::weeks(2)).timestamp();
+    let data = TokenData::granted_to(user, clock);
+    let authentication_token = AuthenticationClaims::encode(
+        data,
+        &config.authentication_token_issuer,
+        &config.authentication_token_key_id,
+        &config.authentication_token_secret,
+    );
+
+    // TODO:
+    // * consider about implementation "Are you there?" modal
+    // * consider about extension (re-set it again?)
+    let (token, sign) = match split_token(authentication_token) {
+        Some(result) => result,
+        None => {
+            return res.error(
+                ApiError::new(Status::InternalServerError)
+                    .message("Something wrong happen, sorry :'("),
+            );
+        },
+    };
+
+    let cookie = make_cookie(sign, config);
+    cookies.add_private(cookie);
+    res.cookies(cookies).format(json!({ "token": token }))
+}
+
 // logout
 //
 // * Remove a cookie
@@ -166,7 +250,7 @@ pub fn login<'a>(
 pub fn logout<'a>(
     mut cookies: Cookies,
     user: &User,
-    logger: SyncLogger,
+    logger: RequestLogger,
 ) -> Response<'a> {
     let res: Response = Default::default();
     info!(logger, "user: {}", user.uuid);
@@ -176,3 +260,133 @@ pub fn logout<'a>(
 
     res.status(Status::Ok)
 }
+
+// Enters sudo mode by re-entering the password. It's required by the routes
+// for sensitive settings (see `request::sudo`).
+#[post("/sudo", data = "<data>", format = "json", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn sudo<'a>(
+    _rate_limit: RateLimit<Login>,
+    csrf_token: Result<CsrfToken, CsrfTokenError>,
+    user: &User,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    license: State<License>,
+    data: JsonBody<SudoData, Auth>,
+    db_conn: DbConn,
+    mut ss_conn: SsConn,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}", user.uuid);
+
+    if let Err(e) = csrf_token {
+        info!(logger, "error: {:?}", e);
+        return res.error(
+            ApiError::new(Status::Unauthorized).message(e.message()),
+        );
+    }
+
+    let backend = auth_backend::select(&config, &license, &**clock, &**ids);
+    let backend = match backend {
+        Ok(b) => b,
+        Err(e) => {
+            warn!(logger, "error: {}", e);
+            return res.error(
+                ApiError::new(Status::Forbidden).message(e.to_string()),
+            );
+        },
+    };
+
+    match backend.authenticate(&user.email, &data.password, &db_conn, &logger)
+    {
+        Ok(ref u) if u.id == user.id => (),
+        result => {
+            if let Err(e) = result {
+                warn!(logger, "sudo failed: user {} ({})", user.uuid, e);
+            }
+            return res.error(
+                ApiError::new(Status::Unauthorized)
+                    .message("The password you've entered is incorrect."),
+            );
+        },
+    }
+
+    match Sudo::enable(user, &mut ss_conn, &config, &logger) {
+        Ok(duration) => res.format(json!({
+            "sudo": {
+                "expires_in": duration,
+            }
+        })),
+        Err(_) => res.status(Status::InternalServerError),
+    }
+}
+
+// Exchanges the web session for a short-lived token, which has only one scope
+// (`ingest:write` or `messages:read`). It's for inline scripts on
+// server-rendered pages (see `service::token_exchange`).
+#[post("/token/exchange?<scope>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn exchange<'a>(
+    _rate_limit: RateLimit<Api>,
+    token_type: TokenType,
+    user: &User,
+    scope: Option<String>,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    mut ss_conn: SsConn,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}", user.uuid);
+
+    // another token can't be exchanged
+    if token_type != TokenType::BrowserCookieToken {
+        return res.status(Status::Forbidden);
+    }
+
+    let scope = match scope
+        .as_deref()
+        .map_or(Some(Scope::MessagesRead), Scope::from_name)
+    {
+        Some(s @ Scope::IngestWrite) | Some(s @ Scope::MessagesRead) => s,
+        _ => {
+            return res.error(ApiError::new(Status::UnprocessableEntity).field(
+                "scope",
+                "Must be one of ingest:write, messages:read",
+            ));
+        },
+    };
+
+    let result = TokenExchange::new(&mut *ss_conn).issue(user, scope, &**ids);
+    let subject = match result {
+        Ok(s) => s,
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            return res.status(Status::InternalServerError);
+        },
+    };
+    let now = clock.now().timestamp();
+    let data = TokenData {
+        value: subject,
+        granted_at: now,
+        expires_at: now + EXPIRATION as i64,
+    };
+    let token = ExchangedTokenClaims::encode(
+        data,
+        &config.authentication_token_issuer,
+        &config.authentication_token_key_id,
+        &config.authentication_token_secret,
+    );
+    res.format(json!({
+        "token": {
+            "value": token,
+            "scope": scope.to_string(),
+            "expires_in": EXPIRATION,
+        }
+    }))
+}
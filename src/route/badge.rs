@@ -0,0 +1,77 @@
+//! A public endpoint for status badges of namespaces (see `service::badge`).
+use std::io::Cursor;
+
+use chrono::Duration;
+use rocket::State;
+use rocket::http::{ContentType, Status};
+use rocket::response::Response as RawResponse;
+
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::db::{DbReadConn, with_statement_timeout};
+use crate::model::message::{LogLevel, StatsInterval};
+use crate::model::message_count::HourlyMessageCount;
+use crate::model::namespace::Namespace;
+use crate::request::logger::RequestLogger;
+use crate::service::badge::{Badge, verify};
+
+// seconds (proxies of images, e.g. GitHub's one, respect it)
+const MAX_AGE: u32 = 300;
+
+/// Returns the SVG badge of the namespace (`<uuid>.svg`) without any
+/// authentication. It's not found unless the namespace has `badge_public` or
+/// the token is valid.
+#[get("/badge/<file>?<token>", rank = 1)]
+pub fn show<'a>(
+    file: String,
+    token: Option<String>,
+    conn: DbReadConn,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Result<RawResponse<'a>, Status> {
+    info!(logger, "file: {}", file);
+
+    let key = file.strip_suffix(".svg").ok_or(Status::NotFound)?;
+    let namespace = match Namespace::find_by_key(key, &conn, &logger) {
+        Some(n) if n.archived_at.is_none() => n,
+        _ => return Err(Status::NotFound),
+    };
+    let secret = &config.authentication_token_secret;
+    let permitted = namespace.badge_public ||
+        token.map_or(false, |t| verify(&namespace, &t, secret));
+    if !permitted {
+        warn!(logger, "err: badge is not permitted: {}", namespace.uuid);
+        return Err(Status::NotFound);
+    }
+
+    let to = clock.now().naive_utc();
+    let from = to - Duration::days(1);
+    let result =
+        with_statement_timeout(&conn, config.database_statement_timeout, || {
+            Ok(HourlyMessageCount::count_by_level_and_time(
+                namespace.id,
+                StatsInterval::Day,
+                from,
+                to,
+                &conn,
+                &logger,
+            ))
+        });
+    let counts = result
+        .ok()
+        .flatten()
+        .ok_or(Status::InternalServerError)?;
+    let levels = [LogLevel::Error.to_string(), LogLevel::Critical.to_string()];
+    let count: i64 = counts
+        .iter()
+        .filter(|c| levels.contains(&c.level))
+        .map(|c| c.count)
+        .sum();
+
+    RawResponse::build()
+        .header(ContentType::SVG)
+        .raw_header("Cache-Control", format!("public, max-age={}", MAX_AGE))
+        .sized_body(Cursor::new(Badge::errors(count).render()))
+        .ok()
+}
@@ -0,0 +1,252 @@
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+
+use crate::db::{DbConn, DbReadConn};
+use crate::job::{Job, JobKind};
+use crate::model::bulk_operation::{
+    BulkOperation, BulkOperationAction, NewBulkOperation,
+};
+use crate::model::message::{Message, MessageFilter};
+use crate::model::user::User;
+use crate::mq::JobQueue;
+use crate::response::{ApiError, Response};
+use crate::route::message::find_stream;
+use crate::route::namespace::ensure_owner;
+use crate::request::bulk_operation::BulkOperation as RequestData;
+use crate::request::logger::RequestLogger;
+use crate::request::quota::ApiCallCount;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::request::scope::{NamespaceAdmin, Scoped};
+use crate::validation::bulk_operation::Validator;
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/bulk_operation/<namespace_key>/count/<stream_uuid>", rank = 2)]
+    pub fn count<'a>(
+        namespace_key: String,
+        stream_uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(
+            logger,
+            "namespace: {}, stream: {}", namespace_key, stream_uuid
+        );
+        no_content_for("POST", &config)
+    }
+
+    #[options("/bulk_operation/<namespace_key>/append/<stream_uuid>", rank = 2)]
+    pub fn append<'a>(
+        namespace_key: String,
+        stream_uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(
+            logger,
+            "namespace: {}, stream: {}", namespace_key, stream_uuid
+        );
+        no_content_for("POST", &config)
+    }
+
+    #[options("/bulk_operation/hget/<uuid>", rank = 2)]
+    pub fn hget<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "uuid: {}", uuid);
+        no_content_for("GET", &config)
+    }
+}
+
+fn format_operation(o: &BulkOperation) -> JsonValue {
+    json!({"bulk_operation": {
+        "uuid": o.uuid.to_string(),
+        "action": o.action.to_string(),
+        "argument": o.argument,
+        "filter": o.filter,
+        "state": o.state.to_string(),
+        "matched_count": o.matched_count,
+        "affected_count": o.affected_count,
+        "finished_at": o.finished_at,
+        "created_at": o.created_at,
+    }})
+}
+
+// Returns the number of messages which match the filter, as a preview
+// before applying an action.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "filter": "level:error tag:db timeout"
+// }
+// ```
+#[post(
+    "/bulk_operation/<namespace_key>/count/<stream_uuid>",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn count(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    namespace_key: String,
+    stream_uuid: String,
+    data: Json<RequestData>,
+    conn: DbReadConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, stream: {}",
+        user.uuid,
+        namespace_key,
+        stream_uuid
+    );
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate_filter() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let stream =
+        match find_stream(&namespace_key, &stream_uuid, user, &conn, &logger) {
+            Some((_, s)) => s,
+            None => return res.status(Status::NotFound),
+        };
+
+    let filter = MessageFilter::parse(&data.0.filter).unwrap();
+    match Message::count_by_filter(stream.id, &filter, &conn, &logger) {
+        Some(count) => res.format(json!({ "count": count })),
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Applies the action to all the messages which match the filter in the
+// background (only for owners). The result can be tracked via hget.
+//
+// The action is one of `add_tag` (argument: tag), `assign_incident`
+// (argument: incident uuid) or `soft_delete`.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "filter": "level:error tag:db timeout",
+//    "action": "add_tag",
+//    "argument": "outage"
+// }
+// ```
+#[post(
+    "/bulk_operation/<namespace_key>/append/<stream_uuid>",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn append(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    namespace_key: String,
+    stream_uuid: String,
+    data: Json<RequestData>,
+    conn: DbConn,
+    mut queue: JobQueue,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, stream: {}",
+        user.uuid,
+        namespace_key,
+        stream_uuid
+    );
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let (namespace, stream) =
+        match find_stream(&namespace_key, &stream_uuid, user, &conn, &logger) {
+            Some(v) => v,
+            None => return res.status(Status::NotFound),
+        };
+    if let Err(status) = ensure_owner(&namespace, user, &conn, &logger) {
+        return res.status(status);
+    }
+
+    let filter = MessageFilter::parse(&data.0.filter).unwrap();
+    let matched_count =
+        match Message::count_by_filter(stream.id, &filter, &conn, &logger) {
+            Some(n) => n,
+            None => return res.status(Status::InternalServerError),
+        };
+
+    let action = data.0.action.as_ref().unwrap();
+    let action = BulkOperationAction::from_name(action).unwrap();
+    let argument = match action {
+        BulkOperationAction::SoftDelete => None,
+        _ => data.0.argument.as_ref().map(|s| s.trim().to_string()),
+    };
+    let new_operation = NewBulkOperation {
+        user_id: user.id,
+        stream_id: stream.id,
+        action,
+        argument,
+        filter: data.0.filter.clone(),
+        matched_count,
+    };
+    let operation = match BulkOperation::insert(&new_operation, &conn, &logger)
+    {
+        Some(o) => o,
+        None => return res.status(Status::InternalServerError),
+    };
+
+    let job = Job::<String> {
+        kind: JobKind::ApplyBulkOperation,
+        args: vec![operation.uuid.to_string()],
+    };
+    if let Err(err) = queue.enqueue(job) {
+        error!(logger, "error: {}", err);
+        return res.status(Status::InternalServerError);
+    }
+    res.status(Status::Accepted).format(format_operation(&operation))
+}
+
+#[get("/bulk_operation/hget/<uuid>", rank = 1)]
+pub fn hget(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    match BulkOperation::owned_by_uuid(user, &uuid, &conn, &logger) {
+        Some(o) => res.format(format_operation(&o)),
+        None => res.status(Status::NotFound),
+    }
+}
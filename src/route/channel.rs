@@ -0,0 +1,229 @@
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+
+use crate::db::DbConn;
+use crate::model::channel::{Channel, ChannelKind, NewChannel};
+use crate::model::namespace::Namespace;
+use crate::model::user::User;
+use crate::request::analytics::{self, Analytics};
+use crate::request::logger::RequestLogger;
+use crate::response::{ApiError, Response};
+use crate::request::channel::Channel as RequestData;
+use crate::request::quota::ApiCallCount;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::request::scope::{NamespaceAdmin, Scoped};
+use crate::route::namespace::find_owned_namespace;
+use crate::service::payload_template::{PayloadContext, PayloadTemplate};
+use crate::validation::channel::Validator;
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/channel/<namespace_key>/hgetall", rank = 2)]
+    pub fn hgetall<'a>(
+        namespace_key: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}", namespace_key);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/channel/<namespace_key>/append", rank = 2)]
+    pub fn append<'a>(
+        namespace_key: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}", namespace_key);
+        no_content_for("POST", &config)
+    }
+
+    #[options("/channel/<namespace_key>/preview", rank = 2)]
+    pub fn preview<'a>(
+        namespace_key: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}", namespace_key);
+        no_content_for("POST", &config)
+    }
+}
+
+fn format_channel(c: &Channel) -> JsonValue {
+    json!({"channel": {
+        "uuid": c.uuid.to_string(),
+        "kind": c.kind.to_string(),
+        "name": c.name,
+        "url": c.url,
+        "template": c.template,
+        "rollup_window": c.rollup_window,
+        "created_at": c.created_at,
+        "updated_at": c.updated_at,
+    }})
+}
+
+// Returns channels of the namespace (only for owners).
+#[get("/channel/<namespace_key>/hgetall", rank = 1)]
+pub fn hgetall(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    namespace_key: String,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}, namespace: {}", user.uuid, namespace_key);
+
+    let namespace =
+        match find_owned_namespace(&namespace_key, user, &conn, &logger) {
+            Ok(n) => n,
+            Err(status) => return res.status(status),
+        };
+
+    let data: Vec<JsonValue> =
+        Channel::find_all_by_namespace_id(namespace.id, &conn, &logger)
+            .unwrap_or_else(Vec::new)
+            .iter()
+            .map(format_channel)
+            .collect();
+    res.format(json!(data))
+}
+
+// Saves a new channel (only for owners). The template is rendered with a
+// sample message to check it at save time. With `rollup_window` (seconds),
+// at most one alert is delivered per window.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "kind": "slack",
+//    "name": "alerts",
+//    "url": "https://hooks.slack.com/services/...",
+//    "template": "{\"text\": \"{{message.title}}\"}",
+//    "rollup_window": 300
+// }
+// ```
+#[post(
+    "/channel/<namespace_key>/append",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn append(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    mut analytics: Analytics,
+    namespace_key: String,
+    data: Json<RequestData>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}, namespace: {}", user.uuid, namespace_key);
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let namespace =
+        match find_owned_namespace(&namespace_key, user, &conn, &logger) {
+            Ok(n) => n,
+            Err(status) => return res.status(status),
+        };
+
+    let kind = data.0.kind.as_ref().unwrap();
+    let new_channel = NewChannel {
+        namespace_id: namespace.id,
+        kind: ChannelKind::from_name(kind).unwrap(),
+        name: data.0.name.as_ref().unwrap().trim().to_string(),
+        url: data.0.url.as_ref().unwrap().trim().to_string(),
+        template: data
+            .0
+            .template
+            .clone()
+            .filter(|t| !t.trim().is_empty()),
+        rollup_window: data.0.rollup_window.unwrap_or(0),
+    };
+    match Channel::insert(&new_channel, &conn, &logger) {
+        Some(c) => {
+            analytics.record(user, analytics::CHANNEL_CREATED, &[(
+                "kind",
+                c.kind.to_string(),
+            )]);
+            res.format(format_channel(&c))
+        },
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Renders the template with a sample message, and returns the payload as it
+// would be delivered.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "kind": "webhook",
+//    "template": "{\"text\": \"{{message.title}}\"}"
+// }
+// ```
+#[post(
+    "/channel/<namespace_key>/preview",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn preview(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    namespace_key: String,
+    data: Json<RequestData>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}, namespace: {}", user.uuid, namespace_key);
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate_template() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let namespace =
+        match Namespace::find_by_uuid(&namespace_key, user, &conn, &logger) {
+            Some(n) => n,
+            None => return res.status(Status::NotFound),
+        };
+
+    let mut context = PayloadContext::sample();
+    context.namespace.name = namespace.name;
+
+    let kind = ChannelKind::from_name(data.0.kind.as_ref().unwrap()).unwrap();
+    let payload = PayloadTemplate::new(kind, data.0.template.as_deref())
+        .and_then(|t| t.render(&context));
+    match payload {
+        Ok(p) => res.format(json!({ "payload": p })),
+        Err(e) => res.error(
+            ApiError::new(Status::UnprocessableEntity).field("template", &e),
+        ),
+    }
+}
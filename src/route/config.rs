@@ -0,0 +1,43 @@
+//! Local-only endpoint to reload the config at runtime.
+use std::sync::RwLock;
+
+use rocket::State;
+
+use crate::config::{Config, DynamicConfig};
+use crate::logger::{parse_level, set_level};
+use crate::request::local_only::LocalOnly;
+use crate::request::logger::RequestLogger;
+use crate::response::{ApiError, Response};
+use crate::validation::ValidationError;
+
+/// Reloads the values of `DynamicConfig` (log level, rate limits and read-only
+/// mode) from the sources of the config (e.g. `CONFIG_FILE`). The others need
+/// a restart.
+#[post("/config/reload", rank = 1)]
+pub fn reload<'a>(
+    _local_only: LocalOnly,
+    dynamic_config: State<RwLock<DynamicConfig>>,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let c = match Config::from(config.env_name) {
+        Ok(c) => DynamicConfig::from(&c),
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            return res.error(ApiError::invalid(vec![ValidationError {
+                field: "config".to_string(),
+                messages: e.errors,
+            }]));
+        },
+    };
+    set_level(parse_level(&c.log_level));
+
+    let mut current = dynamic_config.write().unwrap();
+    if *current != c {
+        warn!(logger, "config: {:?} -> {:?}", *current, c);
+        *current = c;
+    }
+    res.format(json!({ "config": *current }))
+}
@@ -1,82 +1,126 @@
 use rocket::Request;
-use rocket::http::{Cookies, Status};
+use rocket::http::Status;
 
-use crate::response::Response;
+use crate::request::body_limit::BodyLimitState;
+use crate::response::ApiError;
 
 #[catch(400)]
-pub fn bad_request<'a>(_req: &Request) -> Response<'a> {
-    Response {
-        cookies: Cookies::empty(),
-        status: Status::BadRequest,
-        data: json!({
-            "data": {
-                "message": "The request header/body is invalid".to_string(),
-            }
-        }),
-    }
+pub fn bad_request(_req: &Request) -> ApiError {
+    ApiError::new(Status::BadRequest)
 }
 
 #[catch(401)]
-pub fn unauthorized<'a>(_req: &Request) -> Response<'a> {
-    Response {
-        cookies: Cookies::empty(),
-        status: Status::Unauthorized,
-        data: json!({
-            "data": {
-                "message": "The request is not allowed".to_string(),
-            }
-        }),
-    }
+pub fn unauthorized(_req: &Request) -> ApiError {
+    ApiError::new(Status::Unauthorized)
 }
 
 #[catch(403)]
-pub fn forbidden<'a>(_req: &Request) -> Response<'a> {
-    Response {
-        cookies: Cookies::empty(),
-        status: Status::Unauthorized,
-        data: json!({
-            "data": {
-                "message": "The request is not prohibited".to_string(),
-            }
-        }),
-    }
+pub fn forbidden(_req: &Request) -> ApiError {
+    ApiError::new(Status::Forbidden)
 }
 
 #[catch(404)]
-pub fn not_found<'a>(req: &Request) -> Response<'a> {
-    Response {
-        cookies: Cookies::empty(),
-        status: Status::NotFound,
-        data: json!({
-            "data": {
-                "message": format!("'{path}' is not found", path=req.uri().path()),
-            }
-        }),
+pub fn not_found(req: &Request) -> ApiError {
+    ApiError::new(Status::NotFound)
+        .message(format!("'{path}' is not found", path = req.uri().path()))
+}
+
+#[catch(406)]
+pub fn not_acceptable(_req: &Request) -> ApiError {
+    ApiError::new(Status::NotAcceptable)
+}
+
+#[catch(413)]
+pub fn payload_too_large(req: &Request) -> ApiError {
+    let e = ApiError::new(Status::PayloadTooLarge);
+    // set by the data guards (see `request::body_limit`)
+    match *req.local_cache(|| None::<BodyLimitState>) {
+        Some(ref state) => e.message(format!(
+            "The request body must be at most {} bytes",
+            state.limit
+        )),
+        None => e,
     }
 }
 
+#[catch(415)]
+pub fn unsupported_media_type(_req: &Request) -> ApiError {
+    ApiError::new(Status::UnsupportedMediaType)
+}
+
 #[catch(422)]
-pub fn unprocessable_entity<'a>(_req: &Request) -> Response<'a> {
-    Response {
-        cookies: Cookies::empty(),
-        status: Status::UnprocessableEntity,
-        data: json!({
-            "data": {
-                "message": "The input is invalid".to_string(),
-            }
-        }),
-    }
+pub fn unprocessable_entity(_req: &Request) -> ApiError {
+    ApiError::new(Status::UnprocessableEntity)
+}
+
+#[catch(429)]
+pub fn too_many_requests(_req: &Request) -> ApiError {
+    ApiError::new(Status::TooManyRequests)
 }
 
 #[catch(500)]
-pub fn internal_server_error<'a>(_req: &Request) -> Response<'a> {
-    Response {
-        cookies: Cookies::empty(),
-        status: Status::InternalServerError,
-        data: json!({
-            "data": {
-                "message": "Internal server error occured".to_string(),
-            }
-        }),
+pub fn internal_server_error(_req: &Request) -> ApiError {
+    ApiError::new(Status::InternalServerError)
+}
+
+#[catch(503)]
+pub fn service_unavailable(_req: &Request) -> ApiError {
+    ApiError::new(Status::ServiceUnavailable)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use insta::assert_snapshot;
+    use rocket::http::Method;
+    use rocket::local::Client;
+
+    type Catcher = fn(&Request) -> ApiError;
+
+    fn render(e: ApiError) -> String {
+        format!(
+            "{}\n{}",
+            e.status,
+            serde_json::to_string_pretty(&e.body()).unwrap()
+        )
+    }
+
+    #[test]
+    fn test_catchers() {
+        let client = Client::new(rocket::ignite()).expect("valid rocket");
+        // the uri is set at dispatch, it's `/` here
+        let local = client.req(Method::Get, "/v1/unknown");
+        let req = local.inner();
+
+        let catchers: Vec<(&str, Catcher)> = vec![
+            ("bad_request", bad_request),
+            ("unauthorized", unauthorized),
+            ("forbidden", forbidden),
+            ("not_found", not_found),
+            ("not_acceptable", not_acceptable),
+            ("payload_too_large", payload_too_large),
+            ("unsupported_media_type", unsupported_media_type),
+            ("unprocessable_entity", unprocessable_entity),
+            ("too_many_requests", too_many_requests),
+            ("internal_server_error", internal_server_error),
+            ("service_unavailable", service_unavailable),
+        ];
+        for (name, catcher) in catchers {
+            assert_snapshot!(name, render(catcher(req)));
+        }
+    }
+
+    #[test]
+    fn test_payload_too_large_with_limit() {
+        let client = Client::new(rocket::ignite()).expect("valid rocket");
+        let local = client.req(Method::Post, "/_/login");
+        let req = local.inner();
+        req.local_cache(|| Some(BodyLimitState { limit: 4096 }));
+
+        let e = payload_too_large(req);
+        assert_eq!(e.status, Status::PayloadTooLarge);
+        assert_eq!(e.code, "payload_too_large");
+        assert_eq!(e.message, "The request body must be at most 4096 bytes");
     }
 }
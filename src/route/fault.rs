@@ -0,0 +1,103 @@
+//! Local-only endpoints for fault injection (see `service::fault_injection`).
+use rocket::State;
+use rocket::http::Status;
+use rocket_contrib::json::Json;
+
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::mq::MqPoolHolder;
+use crate::request::fault::Fault as RequestData;
+use crate::request::local_only::LocalOnly;
+use crate::request::logger::RequestLogger;
+use crate::response::Response;
+use crate::service::fault_injection::FaultInjection;
+use crate::ss::SsPoolHolder;
+
+// applies the changes to the message queue (`f`) and the session store (`g`).
+// the holders are used instead of guards, as the guards may fail by faults.
+fn apply<'a, F, G>(
+    mq_holder: &MqPoolHolder,
+    ss_holder: &SsPoolHolder,
+    logger: &Logger,
+    f: F,
+    g: G,
+) -> Response<'a>
+where
+    F: Fn(&mut FaultInjection) -> redis::RedisResult<()>,
+    G: Fn(&mut FaultInjection) -> redis::RedisResult<()>,
+{
+    let res: Response = Default::default();
+
+    let (mut mq_conn, mut ss_conn) = match (mq_holder.get(), ss_holder.get()) {
+        (Some(mq), Some(ss)) => (mq, ss),
+        _ => return res.status(Status::ServiceUnavailable),
+    };
+    let result = f(&mut FaultInjection::new(&mut *mq_conn))
+        .and_then(|_| g(&mut FaultInjection::new(&mut *ss_conn)))
+        .and_then(|_| FaultInjection::new(&mut *mq_conn).get());
+    match result {
+        Ok(fault) => res.format(json!({ "fault": fault })),
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            res.status(Status::InternalServerError)
+        },
+    }
+}
+
+/// Injects faults. The value looks like this:
+///
+/// ```json
+/// {
+///    "redis_failures": 3,
+///    "smtp_delay": 5000,
+///    "routes": ["/v1/namespace/hgetall"]
+/// }
+/// ```
+#[post("/fault", data = "<data>", format = "json", rank = 1)]
+pub fn inject<'a>(
+    _local_only: LocalOnly,
+    data: Json<RequestData>,
+    mq_holder: State<MqPoolHolder>,
+    ss_holder: State<SsPoolHolder>,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    // only if it's enabled
+    if !config.fault_injection {
+        let res: Response = Default::default();
+        return res.status(Status::NotFound);
+    }
+    warn!(logger, "fault: {:?}", data.0);
+
+    // only the count of failures is needed in the session store
+    let failures = RequestData {
+        redis_failures: data.0.redis_failures,
+        ..Default::default()
+    };
+    apply(
+        &mq_holder,
+        &ss_holder,
+        &logger,
+        |fi| fi.set(&data.0),
+        |fi| fi.set(&failures),
+    )
+}
+
+/// Clears all of the faults.
+#[delete("/fault", rank = 1)]
+pub fn clear<'a>(
+    _local_only: LocalOnly,
+    mq_holder: State<MqPoolHolder>,
+    ss_holder: State<SsPoolHolder>,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    // only if it's enabled
+    if !config.fault_injection {
+        let res: Response = Default::default();
+        return res.status(Status::NotFound);
+    }
+    info!(logger, "fault: clear");
+
+    apply(&mq_holder, &ss_holder, &logger, |fi| fi.clear(), |fi| fi.clear())
+}
@@ -0,0 +1,88 @@
+use juniper_rocket::{GraphQLRequest, GraphQLResponse};
+use parking_lot::Mutex;
+use rocket::State;
+use rocket::request::Form;
+use rocket_contrib::json::JsonValue;
+
+use crate::db::DbConn;
+use crate::graphql::{Context, Schema};
+use crate::model::user::User;
+use crate::request::logger::RequestLogger;
+use crate::response::Response;
+use crate::request::concurrency::{ConcurrencyLimit, Search};
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::request::scope::{MessagesRead, Scoped};
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/graphql", rank = 2)]
+    pub fn query<'a>(
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "graphql");
+        no_content_for("GET,POST", &config)
+    }
+}
+
+// Executes the request, and then converts the result into our response to keep
+// the headers (CORS, rate limit etc.).
+fn execute<'a>(
+    request: &GraphQLRequest,
+    schema: &Schema,
+    user: &User,
+    conn: DbConn,
+    logger: &RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let context = Context {
+        conn: Mutex::new(conn),
+        logger: (*logger).clone(),
+        user: user.clone(),
+    };
+    let GraphQLResponse(status, body) = request.execute_sync(schema, &context);
+    let data = serde_json::from_str(&body).unwrap_or_else(|e| {
+        error!(logger, "err: {}", e);
+        serde_json::Value::Null
+    });
+    res.status(status).format(JsonValue(data))
+}
+
+#[get("/graphql?<request..>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn get<'a>(
+    _rate_limit: RateLimit<Api>,
+    _concurrency: ConcurrencyLimit<Search>,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    request: Form<GraphQLRequest>,
+    schema: State<Schema>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}", user.uuid);
+    execute(&request, &schema, user, conn, &logger)
+}
+
+#[post("/graphql", data = "<request>", format = "json", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn post<'a>(
+    _rate_limit: RateLimit<Api>,
+    _concurrency: ConcurrencyLimit<Search>,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    request: GraphQLRequest,
+    schema: State<Schema>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}", user.uuid);
+    execute(&request, &schema, user, conn, &logger)
+}
@@ -1,14 +1,72 @@
+use diesel::RunQueryDsl;
 use rocket::State;
 use rocket::http::Status;
-use rocket_slog::SyncLogger;
 
+use crate::clock::SharedClock;
 use crate::config::Config;
+use crate::db::DbConn;
+use crate::mq::MqConn;
+use crate::request::logger::RequestLogger;
 use crate::response::Response;
+use crate::service::worker_heartbeat::WorkerHeartbeat;
 
 /// Returns just OK status. This route should be mounted both endpoints.
 #[get("/health", rank = 1)]
-pub fn check<'a>(logger: SyncLogger, _state: State<Config>) -> Response<'a> {
+pub fn check<'a>(
+    logger: RequestLogger,
+    _state: State<Config>,
+) -> Response<'a> {
     info!(logger, "");
     let res: Response = Default::default();
     res.status(Status::Ok)
 }
+
+/// Returns OK if the database is reachable and workers are alive, otherwise
+/// Service Unavailable. The heartbeats of workers are in the body.
+#[get("/readyz", rank = 1)]
+pub fn ready<'a>(
+    conn: DbConn,
+    mut mq_conn: MqConn,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    if let Err(e) = diesel::sql_query("SELECT 1").execute(&*conn) {
+        error!(logger, "err: {}", e);
+        return res.status(Status::ServiceUnavailable);
+    }
+
+    let now = clock.now().timestamp();
+    let timeout = config.worker_heartbeat_timeout as i64;
+    let status = match WorkerHeartbeat::new(&mut mq_conn).status(now, timeout)
+    {
+        Ok(s) => s,
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            return res.status(Status::ServiceUnavailable);
+        },
+    };
+
+    let ready = status.is_ready(timeout);
+    if !ready {
+        warn!(
+            logger,
+            "workers are not ready: {} alive, lag {}s",
+            status.alive,
+            status.queue_lag
+        );
+    }
+    res.status(if ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    })
+    .format(json!({"worker": {
+        "alive": status.alive,
+        "last_beat_at": status.last_beat_at,
+        "queue_lag": status.queue_lag,
+        "jobs": status.jobs,
+    }}))
+}
@@ -0,0 +1,491 @@
+use std::collections::BTreeSet;
+
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::result::Error;
+use rocket::State;
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+
+use crate::clock::SharedClock;
+use crate::db::DbConn;
+use crate::logger::Logger;
+use crate::model::incident::{Incident, IncidentState, NewIncident};
+use crate::model::incident_message::IncidentMessage;
+use crate::model::message::Message;
+use crate::model::namespace::Namespace;
+use crate::model::user::User;
+use crate::response::{ApiError, Response};
+use crate::request::incident::Incident as RequestData;
+use crate::request::logger::RequestLogger;
+use crate::request::quota::ApiCallCount;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::request::scope::{MessagesRead, MessagesWrite, Scoped};
+use crate::validation::incident::Validator;
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/incident/<namespace_key>/hgetall", rank = 2)]
+    pub fn hgetall<'a>(
+        namespace_key: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}", namespace_key);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/incident/<namespace_key>/append", rank = 2)]
+    pub fn append<'a>(
+        namespace_key: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}", namespace_key);
+        no_content_for("POST", &config)
+    }
+
+    #[options("/incident/<namespace_key>/hget/<uuid>", rank = 2)]
+    pub fn hget<'a>(
+        namespace_key: String,
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}, uuid: {}", namespace_key, uuid);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/incident/<namespace_key>/hset/<uuid>", rank = 2)]
+    pub fn hset<'a>(
+        namespace_key: String,
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}, uuid: {}", namespace_key, uuid);
+        no_content_for("PATCH", &config)
+    }
+
+    #[options("/incident/<namespace_key>/attach/<uuid>", rank = 2)]
+    pub fn attach<'a>(
+        namespace_key: String,
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}, uuid: {}", namespace_key, uuid);
+        no_content_for("POST", &config)
+    }
+
+    #[options("/incident/<namespace_key>/timeline/<uuid>", rank = 2)]
+    pub fn timeline<'a>(
+        namespace_key: String,
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}, uuid: {}", namespace_key, uuid);
+        no_content_for("GET", &config)
+    }
+}
+
+// returns the incident in the namespace which the user can see
+fn find_incident(
+    namespace_key: &str,
+    uuid: &str,
+    user: &User,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Option<(Namespace, Incident)> {
+    let namespace = Namespace::find_by_uuid(namespace_key, user, conn, logger)?;
+    let incident = Incident::find_by_uuid(uuid, namespace.id, conn, logger)?;
+    Some((namespace, incident))
+}
+
+// returns the ids of the messages (all of them must be in the namespace)
+fn find_message_ids(
+    namespace: &Namespace,
+    ids: &[String],
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Result<Vec<String>, ApiError> {
+    let ids: Vec<String> = ids
+        .iter()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .cloned()
+        .collect();
+    let messages = Message::find_all_by_ids(namespace.id, &ids, conn, logger)
+        .ok_or_else(|| ApiError::new(Status::InternalServerError))?;
+    if messages.len() != ids.len() {
+        return Err(ApiError::new(Status::UnprocessableEntity)
+            .field("message_ids", "Must be messages in the namespace"));
+    }
+    Ok(messages.into_iter().map(|m| m.id).collect())
+}
+
+fn format_incident(i: &Incident) -> JsonValue {
+    json!({"incident": {
+        "uuid": i.uuid.to_string(),
+        "title": i.title,
+        "state": i.state.to_string(),
+        "rule": i.rule,
+        "resolved_at": i.resolved_at,
+        "created_at": i.created_at,
+        "updated_at": i.updated_at,
+    }})
+}
+
+// the events of the incident in time order: the opening, the messages and
+// the resolution
+fn format_timeline(
+    incident: &Incident,
+    links: &[IncidentMessage],
+    messages: &[Message],
+) -> JsonValue {
+    let mut events: Vec<(NaiveDateTime, serde_json::Value)> =
+        vec![(incident.created_at, serde_json::json!({"kind": "opened"}))];
+    for m in messages {
+        let attached_at = links
+            .iter()
+            .find(|l| l.message_id == m.id)
+            .map(|l| l.created_at);
+        events.push((
+            m.created_at,
+            serde_json::json!({
+                "kind": "message",
+                "attached_at": attached_at,
+                "message": m,
+            }),
+        ));
+    }
+    if let Some(resolved_at) = incident.resolved_at {
+        events.push((resolved_at, serde_json::json!({"kind": "resolved"})));
+    }
+    // keeps the opening first if they are at the same time
+    events.sort_by_key(|(at, _)| *at);
+
+    let timeline: Vec<serde_json::Value> = events
+        .into_iter()
+        .map(|(at, mut event)| {
+            event["at"] = serde_json::json!(at);
+            event
+        })
+        .collect();
+    let mut data = format_incident(incident);
+    data["timeline"] = serde_json::Value::from(timeline);
+    data
+}
+
+// Returns incidents of the namespace (newest first).
+#[get("/incident/<namespace_key>/hgetall", rank = 1)]
+pub fn hgetall(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    namespace_key: String,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}, namespace: {}", user.uuid, namespace_key);
+
+    let namespace =
+        match Namespace::find_by_uuid(&namespace_key, user, &conn, &logger) {
+            Some(n) => n,
+            None => return res.status(Status::NotFound),
+        };
+
+    match Incident::find_all_by_namespace_id(namespace.id, &conn, &logger) {
+        Some(incidents) => {
+            let data: Vec<JsonValue> =
+                incidents.iter().map(format_incident).collect();
+            res.format(json!(data))
+        },
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Saves a new incident, and attaches the messages to it (optional). The
+// messages must be in the namespace.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "title": "Database outage",
+//    "message_ids": ["01F9ZJ4GZ6M3JD8Y0E5Q1W2R3T"]
+// }
+// ```
+#[post(
+    "/incident/<namespace_key>/append",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn append(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesWrite>,
+    namespace_key: String,
+    data: Json<RequestData>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}, namespace: {}", user.uuid, namespace_key);
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let namespace =
+        match Namespace::find_by_uuid(&namespace_key, user, &conn, &logger) {
+            Some(n) => n,
+            None => return res.status(Status::NotFound),
+        };
+    let ids = data.0.message_ids.as_deref().unwrap_or(&[]);
+    let message_ids = match find_message_ids(&namespace, ids, &conn, &logger) {
+        Ok(v) => v,
+        Err(e) => return res.error(e),
+    };
+
+    let new_incident = NewIncident {
+        namespace_id: namespace.id,
+        title: data.0.title.as_ref().unwrap().trim().to_string(),
+        rule: None,
+    };
+    let result: Result<Incident, Error> = conn
+        .build_transaction()
+        .read_write()
+        .run::<Incident, Error, _>(|| {
+            let incident = Incident::insert(&new_incident, &conn, &logger)
+                .ok_or(Error::RollbackTransaction)?;
+            if !message_ids.is_empty() {
+                IncidentMessage::attach(
+                    incident.id,
+                    &message_ids,
+                    &conn,
+                    &logger,
+                )
+                .ok_or(Error::RollbackTransaction)?;
+            }
+            Ok(incident)
+        });
+    match result {
+        Ok(i) => res.format(format_incident(&i)),
+        Err(_) => res.status(Status::InternalServerError),
+    }
+}
+
+#[get("/incident/<namespace_key>/hget/<uuid>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn hget(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    namespace_key: String,
+    uuid: String,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, uuid: {}", user.uuid, namespace_key, uuid
+    );
+
+    match find_incident(&namespace_key, &uuid, user, &conn, &logger) {
+        Some((_, i)) => res.format(format_incident(&i)),
+        None => res.status(Status::NotFound),
+    }
+}
+
+// Resolves the incident, or reopens it. An incident of an alert rule can't
+// be reopened while the rule has another open one (409).
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "state": "resolved"
+// }
+// ```
+#[patch(
+    "/incident/<namespace_key>/hset/<uuid>",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn hset<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesWrite>,
+    namespace_key: String,
+    uuid: String,
+    data: Json<RequestData>,
+    conn: DbConn,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, uuid: {}", user.uuid, namespace_key, uuid
+    );
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate_state() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let mut incident =
+        match find_incident(&namespace_key, &uuid, user, &conn, &logger) {
+            Some((_, i)) => i,
+            None => return res.status(Status::NotFound),
+        };
+
+    let state = IncidentState::from_name(data.0.state.as_ref().unwrap());
+    let now = clock.now().naive_utc();
+    match incident.update_state(state.unwrap(), now, &conn, &logger) {
+        Ok(_) => res.format(format_incident(&incident)),
+        Err("conflict") => {
+            res.status(Status::Conflict).format(format_incident(&incident))
+        },
+        Err(_) => res.status(Status::InternalServerError),
+    }
+}
+
+// Attaches the messages to the incident, and returns the number of the newly
+// attached ones. The messages must be in the namespace.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "message_ids": ["01F9ZJ4GZ6M3JD8Y0E5Q1W2R3T"]
+// }
+// ```
+#[post(
+    "/incident/<namespace_key>/attach/<uuid>",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn attach(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesWrite>,
+    namespace_key: String,
+    uuid: String,
+    data: Json<RequestData>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, uuid: {}", user.uuid, namespace_key, uuid
+    );
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate_message_ids() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let (namespace, incident) =
+        match find_incident(&namespace_key, &uuid, user, &conn, &logger) {
+            Some(v) => v,
+            None => return res.status(Status::NotFound),
+        };
+    let ids = data.0.message_ids.as_deref().unwrap();
+    let message_ids = match find_message_ids(&namespace, ids, &conn, &logger) {
+        Ok(v) => v,
+        Err(e) => return res.error(e),
+    };
+
+    match IncidentMessage::attach(incident.id, &message_ids, &conn, &logger) {
+        Some(n) => res.format(json!({ "count": n })),
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Returns the incident with the timeline of it.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "incident": {"uuid": "...", "title": "Database outage", ...},
+//    "timeline": [
+//      {"kind": "opened", "at": "2021-07-11T09:00:00"},
+//      {
+//        "kind": "message",
+//        "at": "2021-07-11T09:00:01",
+//        "attached_at": "2021-07-11T09:00:02",
+//        "message": {"id": "...", "title": "timeout", ...}
+//      },
+//      {"kind": "resolved", "at": "2021-07-11T10:00:00"}
+//    ]
+// }
+// ```
+#[get("/incident/<namespace_key>/timeline/<uuid>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn timeline(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    namespace_key: String,
+    uuid: String,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, uuid: {}", user.uuid, namespace_key, uuid
+    );
+
+    let (namespace, incident) =
+        match find_incident(&namespace_key, &uuid, user, &conn, &logger) {
+            Some(v) => v,
+            None => return res.status(Status::NotFound),
+        };
+
+    let links =
+        IncidentMessage::find_all_by_incident_id(incident.id, &conn, &logger);
+    let links = match links {
+        Some(v) => v,
+        None => return res.status(Status::InternalServerError),
+    };
+    let ids: Vec<String> = links.iter().map(|l| l.message_id.clone()).collect();
+    match Message::find_all_by_ids(namespace.id, &ids, &conn, &logger) {
+        Some(messages) => {
+            res.format(format_timeline(&incident, &links, &messages))
+        },
+        None => res.status(Status::InternalServerError),
+    }
+}
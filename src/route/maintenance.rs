@@ -0,0 +1,66 @@
+//! Local-only endpoints to switch maintenance mode (see
+//! `service::maintenance`).
+use rocket::http::Status;
+use rocket_contrib::json::Json;
+
+use crate::request::local_only::LocalOnly;
+use crate::request::logger::RequestLogger;
+use crate::request::maintenance::Maintenance as RequestData;
+use crate::response::{ApiError, Response};
+use crate::service::maintenance::{MAX_RETRY_AFTER, MaintenanceFlag};
+use crate::ss::SsConn;
+
+/// Returns whether maintenance mode is on.
+#[get("/maintenance", rank = 1)]
+pub fn hget<'a>(
+    _local_only: LocalOnly,
+    mut ss_conn: SsConn,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    match MaintenanceFlag::new(&mut ss_conn).get() {
+        Ok(maintenance) => res.format(json!({ "maintenance": maintenance })),
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            res.status(Status::InternalServerError)
+        },
+    }
+}
+
+/// Switches maintenance mode. The value looks like this:
+///
+/// ```json
+/// {
+///    "enabled": true,
+///    "retry_after": 1800
+/// }
+/// ```
+#[put("/maintenance", data = "<data>", format = "json", rank = 1)]
+pub fn hset<'a>(
+    _local_only: LocalOnly,
+    data: Json<RequestData>,
+    mut ss_conn: SsConn,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    if let Some(v) = data.0.retry_after {
+        if v == 0 || v > MAX_RETRY_AFTER {
+            let message = format!("Must be between 1 and {}", MAX_RETRY_AFTER);
+            return res.error(
+                ApiError::invalid(vec![]).field("retry_after", &message),
+            );
+        }
+    }
+    warn!(logger, "maintenance: {:?}", data.0);
+
+    let mut flag = MaintenanceFlag::new(&mut ss_conn);
+    match flag.set(&data.0).and_then(|_| flag.get()) {
+        Ok(maintenance) => res.format(json!({ "maintenance": maintenance })),
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            res.status(Status::InternalServerError)
+        },
+    }
+}
@@ -1,22 +1,62 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use rocket::State;
 use rocket::http::Status;
-use rocket_contrib::json::Json;
-use rocket_slog::SyncLogger;
+use rocket::request::Form;
+use rocket_contrib::json::{Json, JsonValue};
 
-use crate::db::DbConn;
-use crate::model::message::{AgentType, Message, NewMessage};
+use crate::clock::{Clock, SharedClock};
+use crate::config::Config;
+use crate::db::{DbConn, DbReadConn, with_statement_timeout};
+use crate::id::{SharedIdGenerator, is_ulid};
+use crate::logger::Logger;
+use crate::model::message::{
+    AgentType, Message, MessageRow, NewMessage, SearchOptions, SourceFilter,
+    proto,
+};
+use crate::model::namespace::Namespace;
+use crate::model::namespace_usage::NamespaceUsage;
+use crate::model::recent_view::RecentViewKind;
+use crate::model::stream::Stream;
 use crate::model::user::User;
-use crate::response::Response;
-use crate::request::message::Message as RequestData;
+use crate::response::{ApiError, Paginated, Response};
+use crate::request::analytics::{self, Analytics};
+use crate::request::api_version::ApiVersion;
+use crate::request::body_limit::Batch;
+use crate::request::concurrency::{ConcurrencyLimit, Search};
+use crate::request::duplicate_window::{DuplicateWindow, fingerprint_of};
+use crate::request::etag::IfNoneMatch;
+use crate::request::logger::RequestLogger;
+use crate::request::pagination::Page;
+use crate::request::quota::{ApiCallCount, IngestionQuota};
+use crate::request::rate_limit::{Api, Ingestion, RateLimit};
+use crate::request::recent_view::ViewTracker;
+use crate::request::scope::{IngestWrite, MessagesRead, MessagesWrite, Scoped};
+use crate::request::stream_buffer::IngestionBuffer;
+use crate::request::stream_token::IngestionToken;
+use crate::request::json::JsonBody;
+use crate::request::message::{
+    Annotation as AnnotationData, Message as RequestData, Search as SearchData,
+    Share as ShareData, Source as SourceData,
+};
+use crate::request::protobuf::{Protobuf, ProtobufFormat};
+use crate::service::highlighter::Highlighter;
+use crate::service::share_link::{
+    CONTEXT_MAX, EXPIRATION, EXPIRATION_MAX, ShareLink,
+};
+use crate::service::unfurl::{permalink, unfurl as unfurl_message};
+use crate::tracecontext::is_trace_id;
 use crate::validation::message::Validator;
+use crate::validation::message_annotation::Validator as AnnotationValidator;
 
 const MESSAGES_PER_REQUEST: i64 = 100;
 
 pub mod preflight {
     use rocket::State;
     use rocket::response::Response as RawResponse;
-    use rocket_slog::SyncLogger;
 
     use crate::config::Config;
+    use crate::request::logger::RequestLogger;
     use crate::response::no_content_for;
 
     #[options("/message/<namespace_key>/append/<stream_slug>", rank = 2)]
@@ -24,7 +64,21 @@ pub mod preflight {
         namespace_key: String,
         stream_slug: String,
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(
+            logger,
+            "namespace: {}, stream: {}", namespace_key, stream_slug
+        );
+        no_content_for("POST", &config)
+    }
+
+    #[options("/message/<namespace_key>/ingest/<stream_slug>", rank = 2)]
+    pub fn ingest<'a>(
+        namespace_key: String,
+        stream_slug: String,
+        config: State<Config>,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(
             logger,
@@ -43,7 +97,60 @@ pub mod preflight {
         start: i64,
         stop: i64,
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(
+            logger,
+            "namespace: {}, stream: {}, start: {}, stop: {}",
+            namespace_key,
+            stream_slug,
+            start,
+            stop
+        );
+        no_content_for("GET", &config)
+    }
+
+    #[options("/message/<namespace_key>/hget/<stream_slug>/<id>", rank = 2)]
+    pub fn hget<'a>(
+        namespace_key: String,
+        stream_slug: String,
+        id: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(
+            logger,
+            "namespace: {}, stream: {}, id: {}", namespace_key, stream_slug, id
+        );
+        no_content_for("GET", &config)
+    }
+
+    #[options("/message/<namespace_key>/hset/<stream_uuid>/<id>", rank = 2)]
+    pub fn hset<'a>(
+        namespace_key: String,
+        stream_uuid: String,
+        id: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(
+            logger,
+            "namespace: {}, stream: {}, id: {}", namespace_key, stream_uuid, id
+        );
+        no_content_for("PATCH", &config)
+    }
+
+    #[options(
+        "/message/<namespace_key>/search/<stream_slug>/<start>/<stop>",
+        rank = 2
+    )]
+    pub fn search<'a>(
+        namespace_key: String,
+        stream_slug: String,
+        start: i64,
+        stop: i64,
+        config: State<Config>,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(
             logger,
@@ -55,10 +162,53 @@ pub mod preflight {
         );
         no_content_for("GET", &config)
     }
+
+    #[options("/message/<namespace_key>/trace/<trace_id>", rank = 2)]
+    pub fn trace<'a>(
+        namespace_key: String,
+        trace_id: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}, trace: {}", namespace_key, trace_id);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/message/<namespace_key>/share/<stream_uuid>/<id>", rank = 2)]
+    pub fn share<'a>(
+        namespace_key: String,
+        stream_uuid: String,
+        id: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(
+            logger,
+            "namespace: {}, stream: {}, id: {}", namespace_key, stream_uuid, id
+        );
+        no_content_for("POST", &config)
+    }
+
+    #[options("/message/<namespace_key>/unfurl/<stream_uuid>/<id>", rank = 2)]
+    pub fn unfurl<'a>(
+        namespace_key: String,
+        stream_uuid: String,
+        id: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(
+            logger,
+            "namespace: {}, stream: {}, id: {}", namespace_key, stream_uuid, id
+        );
+        no_content_for("GET", &config)
+    }
 }
 
 // Save a new log message.
 //
+// The body may be compressed with `Content-Encoding: gzip` or `zstd`.
+//
 // ## TODO: Move ingest API
 //
 // The value looks like this:
@@ -77,16 +227,23 @@ pub mod preflight {
     data = "<data>",
     rank = 1
 )]
-pub fn append(
+#[allow(clippy::too_many_arguments)]
+pub fn append<'a>(
+    _version: ApiVersion,
+    _rate_limit: RateLimit<Ingestion>,
+    quota: IngestionQuota,
     user: &User,
+    _scope: Scoped<IngestWrite>,
     namespace_key: String,
     stream_slug: String,
-    data: Json<RequestData>,
+    data: JsonBody<RequestData, Batch>,
+    mut window: DuplicateWindow,
+    mut buffer: IngestionBuffer,
     conn: DbConn,
-    logger: SyncLogger,
-) -> Response {
-    let res: Response = Default::default();
-
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
     info!(
         logger,
         "user: {}, namespace: {}, stream: {}",
@@ -95,25 +252,357 @@ pub fn append(
         stream_slug
     );
 
+    let found =
+        find_stream(&namespace_key, &stream_slug, user, &conn, &logger);
+    let (namespace, stream) = match found {
+        Some(v) => v,
+        None => {
+            let res: Response = Default::default();
+            return res.status(Status::NotFound);
+        },
+    };
+    if quota.reserve(&namespace.uuid.to_string()).is_err() {
+        let res: Response = Default::default();
+        return res.error(ApiError::new(Status::TooManyRequests));
+    }
+
+    let data = Json(data.into_inner());
+    let now = clock.now();
+    let id = ids.ulid(now);
+    let agent = (user.id, AgentType::Person);
+    let target = (&namespace, &stream);
+    let (window, buffer) = (&mut window, &mut buffer);
+    ingest(
+        &data,
+        (&id, now),
+        agent,
+        target,
+        window,
+        buffer,
+        &**clock,
+        &conn,
+        &logger,
+    )
+}
+
+// Save a new log message sent as Protocol Buffers (see proto/message.proto).
+//
+// This is same as the JSON version above, except for the encoding.
+#[post(
+    "/message/<namespace_key>/append/<stream_slug>",
+    data = "<data>",
+    rank = 2
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn append_protobuf<'a>(
+    _format: ProtobufFormat,
+    _version: ApiVersion,
+    _rate_limit: RateLimit<Ingestion>,
+    quota: IngestionQuota,
+    user: &User,
+    _scope: Scoped<IngestWrite>,
+    namespace_key: String,
+    stream_slug: String,
+    data: Protobuf<proto::NewMessage>,
+    mut window: DuplicateWindow,
+    mut buffer: IngestionBuffer,
+    conn: DbConn,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(
+        logger,
+        "user: {}, namespace: {}, stream: {} (protobuf)",
+        user.uuid,
+        namespace_key,
+        stream_slug
+    );
+
+    let found =
+        find_stream(&namespace_key, &stream_slug, user, &conn, &logger);
+    let (namespace, stream) = match found {
+        Some(v) => v,
+        None => {
+            let res: Response = Default::default();
+            return res.status(Status::NotFound);
+        },
+    };
+    if quota.reserve(&namespace.uuid.to_string()).is_err() {
+        let res: Response = Default::default();
+        return res.error(ApiError::new(Status::TooManyRequests));
+    }
+
+    let data = Json(RequestData::from(data.into_inner()));
+    let now = clock.now();
+    let id = ids.ulid(now);
+    let agent = (user.id, AgentType::Person);
+    let target = (&namespace, &stream);
+    let (window, buffer) = (&mut window, &mut buffer);
+    ingest(
+        &data,
+        (&id, now),
+        agent,
+        target,
+        window,
+        buffer,
+        &**clock,
+        &conn,
+        &logger,
+    )
+}
+
+// Save a new log message with a stream token (`Authorization: Stream-Token
+// <value>`) of the namespace, for log shippers.
+//
+// The value is same as `append`. The source of the token is used for the
+// message if it's not given.
+#[post(
+    "/message/<namespace_key>/ingest/<stream_slug>",
+    format = "json",
+    data = "<data>",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn ingest_json<'a>(
+    _version: ApiVersion,
+    _rate_limit: RateLimit<Ingestion>,
+    quota: IngestionQuota,
+    token: IngestionToken,
+    namespace_key: String,
+    stream_slug: String,
+    data: JsonBody<RequestData, Batch>,
+    mut window: DuplicateWindow,
+    mut buffer: IngestionBuffer,
+    conn: DbConn,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(
+        logger,
+        "stream token: {}, namespace: {}, stream: {}",
+        token.0.uuid,
+        namespace_key,
+        stream_slug
+    );
+
+    let (namespace, stream) =
+        match stream_of(&token, &namespace_key, &stream_slug, &conn, &logger) {
+            Ok(v) => v,
+            Err(status) => {
+                let res: Response = Default::default();
+                return res.status(status);
+            },
+        };
+    if quota.reserve(&namespace.uuid.to_string()).is_err() {
+        let res: Response = Default::default();
+        return res.error(ApiError::new(Status::TooManyRequests));
+    }
+
+    let mut data = data.into_inner();
+    token.0.fill_source(&mut data);
+    let data = Json(data);
+    let now = clock.now();
+    let id = ids.ulid(now);
+    let agent = (token.0.id, AgentType::Client);
+    let target = (&namespace, &stream);
+    let (window, buffer) = (&mut window, &mut buffer);
+    ingest(
+        &data,
+        (&id, now),
+        agent,
+        target,
+        window,
+        buffer,
+        &**clock,
+        &conn,
+        &logger,
+    )
+}
+
+// Save a new log message sent as Protocol Buffers with a stream token.
+#[post(
+    "/message/<namespace_key>/ingest/<stream_slug>",
+    data = "<data>",
+    rank = 2
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn ingest_protobuf<'a>(
+    _format: ProtobufFormat,
+    _version: ApiVersion,
+    _rate_limit: RateLimit<Ingestion>,
+    quota: IngestionQuota,
+    token: IngestionToken,
+    namespace_key: String,
+    stream_slug: String,
+    data: Protobuf<proto::NewMessage>,
+    mut window: DuplicateWindow,
+    mut buffer: IngestionBuffer,
+    conn: DbConn,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(
+        logger,
+        "stream token: {}, namespace: {}, stream: {} (protobuf)",
+        token.0.uuid,
+        namespace_key,
+        stream_slug
+    );
+
+    let (namespace, stream) =
+        match stream_of(&token, &namespace_key, &stream_slug, &conn, &logger) {
+            Ok(v) => v,
+            Err(status) => {
+                let res: Response = Default::default();
+                return res.status(status);
+            },
+        };
+    if quota.reserve(&namespace.uuid.to_string()).is_err() {
+        let res: Response = Default::default();
+        return res.error(ApiError::new(Status::TooManyRequests));
+    }
+
+    let mut data = RequestData::from(data.into_inner());
+    token.0.fill_source(&mut data);
+    let data = Json(data);
+    let now = clock.now();
+    let id = ids.ulid(now);
+    let agent = (token.0.id, AgentType::Client);
+    let target = (&namespace, &stream);
+    let (window, buffer) = (&mut window, &mut buffer);
+    ingest(
+        &data,
+        (&id, now),
+        agent,
+        target,
+        window,
+        buffer,
+        &**clock,
+        &conn,
+        &logger,
+    )
+}
+
+/// Returns the stream in the namespace which the user can see.
+pub fn find_stream(
+    namespace_key: &str,
+    stream_slug: &str,
+    user: &User,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Option<(Namespace, Stream)> {
+    let namespace = Namespace::find_by_uuid(namespace_key, user, conn, logger)?;
+    match Stream::find_by_uuid(stream_slug, conn, logger) {
+        Some(s) if s.namespace_id == namespace.id => Some((namespace, s)),
+        _ => None,
+    }
+}
+
+// a stream token is bound to a namespace (which is not deleted), and it can
+// be used for its streams
+fn stream_of(
+    token: &IngestionToken,
+    namespace_key: &str,
+    stream_slug: &str,
+    conn: &DbConn,
+    logger: &RequestLogger,
+) -> Result<(Namespace, Stream), Status> {
+    let namespace =
+        match Namespace::find_by_id(token.0.namespace_id, conn, logger) {
+            Some(n) if n.archived_at.is_none() => n,
+            _ => return Err(Status::Forbidden),
+        };
+    if namespace.uuid.to_string() != namespace_key {
+        return Err(Status::Forbidden);
+    }
+    match Stream::find_by_uuid(stream_slug, conn, logger) {
+        Some(s) if s.namespace_id == namespace.id => Ok((namespace, s)),
+        _ => Err(Status::NotFound),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn ingest<'a>(
+    data: &Json<RequestData>,
+    (id, now): (&str, DateTime<Utc>),
+    (agent_id, agent_type): (i64, AgentType),
+    (namespace, stream): (&Namespace, &Stream),
+    window: &mut DuplicateWindow,
+    buffer: &mut IngestionBuffer,
+    clock: &dyn Clock,
+    conn: &DbConn,
+    logger: &RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
     // FIXME
-    // * namespace
-    // * validations for stream_id (slug) and agent_* fields
-    let v = Validator::new(&data, &logger);
+    // * validations for agent_* fields
+    let v = Validator::new(data, logger);
     match v.validate() {
         Err(errors) => {
-            res.status(Status::UnprocessableEntity).format(json!({
-                "errors": errors,
-            }))
+            res.error(ApiError::invalid(errors))
         },
         Ok(_) => {
-            // FIXME
-            let stream_id = 1;
             let mut m = NewMessage::from(data.0.clone());
-            m.stream_id = stream_id;
-            m.agent_id = user.id;
-            m.agent_type = AgentType::Person;
-            if let Some(id) = Message::insert(&m, &conn, &logger) {
-                info!(logger, "user: {}", user.uuid);
+            m.stream_id = stream.id;
+            m.agent_id = agent_id;
+            m.agent_type = agent_type;
+            info!(logger, "agent: {} {}", m.agent_type, m.agent_id);
+
+            // a message below the minimum level is only counted
+            if !namespace.accepts(&m.level) {
+                let today = now.date().naive_utc();
+                let _ = NamespaceUsage::increment_dropped(
+                    namespace.id,
+                    today,
+                    conn,
+                    logger,
+                );
+                return res.format(json!({"message": {
+                    "id": null,
+                    "dropped": true,
+                }}));
+            }
+            namespace.merge_default_tags(&mut m.tags);
+
+            // a duplicate is counted on the first one (unless it's not saved
+            // yet, e.g. in the buffer)
+            let fingerprint = fingerprint_of(&data.0);
+            if let Some(first) = window.claim(stream, &fingerprint, id) {
+                if let Some(n) =
+                    Message::increment_occurrences(&first, clock, conn, logger)
+                {
+                    return res.format(json!({"message": {
+                        "id": first,
+                        "occurrences_count": n,
+                    }}));
+                }
+            }
+
+            // it's accepted once it's in the buffer
+            let row = MessageRow {
+                id: id.to_string(),
+                message: m,
+                created_at: now.naive_utc(),
+            };
+            let m = match buffer.push(row) {
+                Ok(_) => {
+                    return res.format(json!({"message": {
+                        "id": id,
+                    }}));
+                },
+                Err(row) => row.message,
+            };
+
+            let result = if namespace.deduplicates_messages {
+                Message::insert_deduplicated(m, id, clock, conn, logger)
+            } else {
+                Message::insert(&m, id, conn, logger)
+            };
+            if let Some(id) = result {
                 return res.format(json!({"message": {
                     "id": id,
                 }}));
@@ -123,21 +612,329 @@ pub fn append(
     }
 }
 
+// Returns messages in the stream. It responds with 304 if the ETag given as
+// If-None-Match is still fresh.
+//
+// They can be filtered by the source (e.g. `?service=api&environment=
+// production`).
 #[get(
-    "/message/<namespace_key>/lrange/<stream_slug>/<start>/<stop>",
+    "/message/<namespace_key>/lrange/<stream_slug>/<start>/<stop>?<cursor>&\
+     <limit>&<source..>",
     rank = 1
 )]
-pub fn lrange(
+#[allow(clippy::too_many_arguments)]
+pub fn lrange<'a>(
+    _version: ApiVersion,
+    _rate_limit: RateLimit<Api>,
+    _concurrency: ConcurrencyLimit<Search>,
+    _api_call: ApiCallCount,
     user: &User,
+    _scope: Scoped<MessagesRead>,
+    if_none_match: IfNoneMatch,
     namespace_key: String,
     stream_slug: String,
     start: u64,
     stop: u64,
+    cursor: Option<String>,
+    limit: Option<i64>,
+    source: Form<SourceData>,
+    conn: DbReadConn,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Result<Paginated<'a, JsonValue>, Response<'a>> {
+    let mut res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, stream: {}, start: {}, stop: {}, \
+         cursor: {:?}, limit: {:?}",
+        user.uuid,
+        namespace_key,
+        stream_slug,
+        start,
+        stop,
+        cursor,
+        limit
+    );
+
+    let page = match Page::range(start as i64, stop as i64)
+        .query(cursor.as_deref(), limit)
+    {
+        Ok(p) => p,
+        Err(e) => {
+            return Err(res.error(ApiError::invalid(vec![e])));
+        },
+    };
+
+    let stream =
+        match find_stream(&namespace_key, &stream_slug, user, &conn, &logger) {
+            Some((_, s)) => s,
+            None => return Err(res.status(Status::NotFound)),
+        };
+
+    let mut total = None;
+    if let Some((updated_at, count)) =
+        Message::version_by_stream_id(stream.id, &conn, &logger)
+    {
+        let etag = page.etag(updated_at, count);
+        if if_none_match.matches(&etag) {
+            return Err(res.status(Status::NotModified).etag(etag));
+        }
+        res = res.etag(etag);
+        total = Some(count);
+    }
+
+    let filter = SourceFilter {
+        hostname: source.hostname.clone(),
+        service: source.service.clone(),
+        environment: source.environment.clone(),
+    };
+    let result =
+        with_statement_timeout(&conn, config.database_statement_timeout, || {
+            Ok(Message::fetch_by_stream_id(
+                stream.id,
+                &filter,
+                page.offset,
+                page.fetch_limit(),
+                &conn,
+                &logger,
+            ))
+        });
+    let (messages, next_cursor) = match result.ok().flatten() {
+        None => {
+            error!(logger, "err: not found user.id {}", user.uuid);
+            (vec![], None)
+        },
+        Some(a) => page.split(a),
+    };
+    let data: Vec<JsonValue> =
+        messages.iter().map(|m| json!({ "message": m })).collect();
+    let res = res.paginate(data, next_cursor);
+    Ok(match total {
+        Some(count) => res.total_estimate(count),
+        None => res,
+    })
+}
+
+// Returns a message, and records it as a recent view of the user.
+#[get("/message/<namespace_key>/hget/<stream_slug>/<id>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn hget(
+    _version: ApiVersion,
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    mut tracker: ViewTracker,
+    namespace_key: String,
+    stream_slug: String,
+    id: String,
     conn: DbConn,
-    logger: SyncLogger,
+    logger: RequestLogger,
 ) -> Response {
     let res: Response = Default::default();
 
+    info!(
+        logger,
+        "user: {}, namespace: {}, stream: {}, id: {}",
+        user.uuid,
+        namespace_key,
+        stream_slug,
+        id
+    );
+
+    if !is_ulid(&id) {
+        return res.status(Status::NotFound);
+    }
+
+    let stream =
+        match find_stream(&namespace_key, &stream_slug, user, &conn, &logger) {
+            Some((_, s)) => s,
+            None => return res.status(Status::NotFound),
+        };
+    match Message::first_by_stream_id(&id, stream.id, &conn, &logger) {
+        None => res.status(Status::NotFound),
+        Some(m) => {
+            let target = format!("{}/{}/{}", namespace_key, stream_slug, m.id);
+            tracker.record(user, RecentViewKind::Message, &target);
+            res.format(json!({ "message": m }))
+        },
+    }
+}
+
+// Annotates a message by a member of the namespace: replaces the tags, and
+// marks it as acknowledged and/or resolved (or not). Omitted fields are kept.
+//
+// `updated_at` must be the one of the message which the changes are based on.
+// If the message has been changed since, it responds with 409 and the current
+// message.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "tags": ["db", "outage"],
+//    "acknowledged": true,
+//    "resolved": false,
+//    "updated_at": "2021-06-29T09:00:00.123456"
+// }
+// ```
+#[patch(
+    "/message/<namespace_key>/hset/<stream_uuid>/<id>",
+    format = "json",
+    data = "<data>",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn hset<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesWrite>,
+    namespace_key: String,
+    stream_uuid: String,
+    id: String,
+    data: Json<AnnotationData>,
+    conn: DbConn,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, stream: {}, id: {}",
+        user.uuid,
+        namespace_key,
+        stream_uuid,
+        id
+    );
+
+    let v = AnnotationValidator::new(&data, &logger);
+    if let Err(errors) = v.validate() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    if !is_ulid(&id) {
+        return res.status(Status::NotFound);
+    }
+    let namespace =
+        match Namespace::find_by_uuid(&namespace_key, user, &conn, &logger) {
+            Some(n) => n,
+            None => return res.status(Status::NotFound),
+        };
+    let stream = match Stream::find_by_uuid(&stream_uuid, &conn, &logger) {
+        Some(s) if s.namespace_id == namespace.id => s,
+        _ => return res.status(Status::NotFound),
+    };
+    let found = Message::first_by_stream_id(&id, stream.id, &conn, &logger);
+    let mut m = match found {
+        Some(m) => m,
+        None => return res.status(Status::NotFound),
+    };
+
+    if data.0.updated_at != Some(m.updated_at) {
+        return res.status(Status::Conflict).format(json!({ "message": m }));
+    }
+
+    annotate(&mut m, &data.0, clock.now().naive_utc());
+    match Message::update(&mut m, &**clock, &conn, &logger) {
+        Ok(_) => res.format(json!({ "message": m })),
+        Err("conflict") => {
+            match Message::first_by_stream_id(&id, stream.id, &conn, &logger) {
+                Some(m) => {
+                    res.status(Status::Conflict).format(json!({ "message": m }))
+                },
+                None => res.status(Status::NotFound),
+            }
+        },
+        Err(_) => res.status(Status::InternalServerError),
+    }
+}
+
+// applies the changes (the time of acknowledgement and resolution is kept if
+// it's already marked)
+fn annotate(m: &mut Message, data: &AnnotationData, now: NaiveDateTime) {
+    if let Some(ref tags) = data.tags {
+        let mut values: Vec<String> = vec![];
+        for tag in tags.iter().map(|t| t.trim().to_string()) {
+            if !values.contains(&tag) {
+                values.push(tag);
+            }
+        }
+        m.tags = values;
+    }
+    if let Some(acknowledged) = data.acknowledged {
+        m.acknowledged_at = if acknowledged {
+            m.acknowledged_at.or(Some(now))
+        } else {
+            None
+        };
+    }
+    if let Some(resolved) = data.resolved {
+        m.resolved_at = if resolved {
+            m.resolved_at.or(Some(now))
+        } else {
+            None
+        };
+    }
+}
+
+// Searches messages which contain all the terms in `q`, and returns them with
+// highlighted fragments of the title and content.
+//
+// The matching is case-insensitive unless `case_sensitive=true`, and
+// `unaccent=true` ignores diacritics (e.g. "ete" matches "été").
+//
+// The value looks like this:
+//
+// ```json
+// [{
+//    "message": {...},
+//    "highlights": {
+//      "title": {"fragment": "<em>Timeout</em> on", "offsets": [[0, 7]]},
+//      "content": null
+//    }
+// }]
+// ```
+//
+// With `facets=true`, they are in `messages` with the numbers of all the
+// matched messages by the source (top 10 values of each):
+//
+// ```json
+// {
+//    "messages": [...],
+//    "facets": {
+//      "hostname": [{"value": "web-1", "count": 3}],
+//      "service": [{"value": "api", "count": 2}, {"value": "worker", ...}],
+//      "environment": []
+//    }
+// }
+// ```
+#[get(
+    "/message/<namespace_key>/search/<stream_slug>/<start>/<stop>?<search..>",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn search<'a>(
+    _rate_limit: RateLimit<Api>,
+    _concurrency: ConcurrencyLimit<Search>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    mut tracker: ViewTracker,
+    mut analytics: Analytics,
+    namespace_key: String,
+    stream_slug: String,
+    start: u64,
+    stop: u64,
+    search: Form<SearchData>,
+    conn: DbReadConn,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
     info!(
         logger,
         "user: {}, namespace: {}, stream: {}, start: {}, stop: {}",
@@ -148,28 +945,342 @@ pub fn lrange(
         stop
     );
 
-    // TODO
-    let offset = start as i64;
-    let mut limit = (stop - start + 2) as i64;
-    if limit < 1 {
-        limit = 1;
+    let q = &search.q;
+    if q.trim().is_empty() {
+        return res.error(
+            ApiError::new(Status::UnprocessableEntity)
+                .field("q", "Must not be empty"),
+        );
     }
+    let stream =
+        match find_stream(&namespace_key, &stream_slug, user, &conn, &logger) {
+            Some((_, s)) => s,
+            None => return res.status(Status::NotFound),
+        };
 
-    // FIXME
-    // * visible to user (and use namespace_key)
+    let target = format!("{}/{}/{}", namespace_key, stream_slug, q.trim());
+    tracker.record(user, RecentViewKind::Search, &target);
 
-    let data = match Message::fetch_by_stream_slug(
-        stream_slug,
-        offset,
-        limit,
-        &conn,
-        &logger,
-    ) {
+    let offset = start as i64;
+    let limit = ((stop as i64) - (start as i64) + 1)
+        .max(1)
+        .min(MESSAGES_PER_REQUEST);
+
+    let options = SearchOptions {
+        case_sensitive: search.case_sensitive.unwrap_or(false),
+        unaccent: search.unaccent.unwrap_or(false),
+    };
+    let highlighter = Highlighter::new(q, &options);
+    let with_facets = search.facets.unwrap_or(false);
+    let result =
+        with_statement_timeout(&conn, config.database_statement_timeout, || {
+            let facets = if with_facets {
+                Message::count_facets_by_search(
+                    stream.id,
+                    q,
+                    &options,
+                    &conn,
+                    &logger,
+                )
+            } else {
+                None
+            };
+            let messages = Message::search_by_stream_id(
+                stream.id,
+                q,
+                &options,
+                offset,
+                limit,
+                &conn,
+                &logger,
+            );
+            Ok((messages, facets))
+        });
+    let (messages, facets) = result.unwrap_or((None, None));
+    let data = match messages {
         None => {
             error!(logger, "err: not found user.id {}", user.uuid);
             vec![]
         },
-        Some(a) => a.iter().map(|m| json!({ "message": m })).collect(),
+        Some(a) => {
+            a.iter()
+                .map(|m| {
+                    let content = m
+                        .content
+                        .as_ref()
+                        .and_then(|c| highlighter.highlight(c));
+                    json!({
+                        "message": m,
+                        "highlights": {
+                            "title": highlighter.highlight(&m.title),
+                            "content": content,
+                        },
+                    })
+                })
+                .collect()
+        },
     };
+    // neither the query nor the stream is recorded
+    analytics.record(user, analytics::SEARCH, &[
+        ("results", data.len().to_string()),
+        ("facets", with_facets.to_string()),
+    ]);
+    if with_facets {
+        return res.format(json!({
+            "messages": data,
+            "facets": facets,
+        }));
+    }
     res.format(json!(data))
 }
+
+// Returns the messages of a distributed trace (the trace id of W3C Trace
+// Context) in all the streams of the namespace, oldest first.
+#[get("/message/<namespace_key>/trace/<trace_id>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn trace<'a>(
+    _rate_limit: RateLimit<Api>,
+    _concurrency: ConcurrencyLimit<Search>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    namespace_key: String,
+    trace_id: String,
+    conn: DbReadConn,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, trace: {}", user.uuid, namespace_key, trace_id
+    );
+
+    if !is_trace_id(&trace_id) {
+        return res.status(Status::NotFound);
+    }
+    let namespace =
+        match Namespace::find_by_uuid(&namespace_key, user, &conn, &logger) {
+            Some(n) => n,
+            None => return res.status(Status::NotFound),
+        };
+
+    let result =
+        with_statement_timeout(&conn, config.database_statement_timeout, || {
+            Ok(Message::find_all_by_trace_id(
+                namespace.id,
+                &trace_id,
+                MESSAGES_PER_REQUEST,
+                &conn,
+                &logger,
+            ))
+        });
+    match result.ok().flatten() {
+        Some(a) => {
+            let data: Vec<_> =
+                a.iter().map(|m| json!({ "message": m })).collect();
+            res.format(json!(data))
+        },
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Returns the metadata of the preview of the permalink of a message (see
+// `service::unfurl`). It's not found unless the user is a member of the
+// namespace.
+#[get("/message/<namespace_key>/unfurl/<stream_uuid>/<id>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn unfurl<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    namespace_key: String,
+    stream_uuid: String,
+    id: String,
+    conn: DbReadConn,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, stream: {}, id: {}",
+        user.uuid,
+        namespace_key,
+        stream_uuid,
+        id
+    );
+
+    if !is_ulid(&id) {
+        return res.status(Status::NotFound);
+    }
+    let namespace =
+        match Namespace::find_by_uuid(&namespace_key, user, &conn, &logger) {
+            Some(n) => n,
+            None => return res.status(Status::NotFound),
+        };
+    let stream = match Stream::find_by_uuid(&stream_uuid, &conn, &logger) {
+        Some(s) if s.namespace_id == namespace.id => s,
+        _ => return res.status(Status::NotFound),
+    };
+    match Message::first_by_stream_id(&id, stream.id, &conn, &logger) {
+        Some(m) => {
+            let key = namespace.uuid.to_string();
+            let url =
+                permalink(&config, &key, &stream.uuid.to_string(), &m.id);
+            res.format(json!(unfurl_message(&config, &url, &m)))
+        },
+        None => res.status(Status::NotFound),
+    }
+}
+
+// Creates a link to share the message with the people who are not members of
+// the namespace (see `service::share_link`). The link is the path of the
+// `shared` route, which works without any authentication until it expires.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "expires_in": 3600,
+//    "context": 3
+// }
+// ```
+#[post(
+    "/message/<namespace_key>/share/<stream_uuid>/<id>",
+    format = "json",
+    data = "<data>",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn share<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    namespace_key: String,
+    stream_uuid: String,
+    id: String,
+    data: Json<ShareData>,
+    conn: DbConn,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, stream: {}, id: {}",
+        user.uuid,
+        namespace_key,
+        stream_uuid,
+        id
+    );
+
+    let expires_in = data.0.expires_in.unwrap_or(EXPIRATION);
+    if !(1..=EXPIRATION_MAX).contains(&expires_in) {
+        let message = format!("Must be between 1 and {}", EXPIRATION_MAX);
+        return res.error(
+            ApiError::new(Status::UnprocessableEntity)
+                .field("expires_in", &message),
+        );
+    }
+    let context = data.0.context.unwrap_or(0);
+    if !(0..=CONTEXT_MAX).contains(&context) {
+        let message = format!("Must be between 0 and {}", CONTEXT_MAX);
+        return res.error(
+            ApiError::new(Status::UnprocessableEntity)
+                .field("context", &message),
+        );
+    }
+
+    if !is_ulid(&id) {
+        return res.status(Status::NotFound);
+    }
+    let namespace =
+        match Namespace::find_by_uuid(&namespace_key, user, &conn, &logger) {
+            Some(n) => n,
+            None => return res.status(Status::NotFound),
+        };
+    let stream = match Stream::find_by_uuid(&stream_uuid, &conn, &logger) {
+        Some(s) if s.namespace_id == namespace.id => s,
+        _ => return res.status(Status::NotFound),
+    };
+    let message =
+        match Message::first_by_stream_id(&id, stream.id, &conn, &logger) {
+            Some(m) => m,
+            None => return res.status(Status::NotFound),
+        };
+
+    let expires_at = clock.now().timestamp() + expires_in;
+    let link = ShareLink {
+        stream: stream.uuid.to_string(),
+        message: message.id,
+        context,
+        expires_at,
+    };
+    let token = link.encode(&config.authentication_token_secret);
+    res.format(json!({"share": {
+        "path": format!("/shared/message/{}", token),
+        "expires_at": NaiveDateTime::from_timestamp(expires_at, 0),
+    }}))
+}
+
+// Returns the shared message (and the ones around it) without any
+// authentication. It's not found if the link is invalid or expired, or the
+// message (or the namespace) has been deleted.
+#[get("/shared/message/<token>", rank = 1)]
+pub fn shared<'a>(
+    _rate_limit: RateLimit<Api>,
+    token: String,
+    conn: DbReadConn,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let now = clock.now().timestamp();
+    let secret = &config.authentication_token_secret;
+    let link = match ShareLink::decode(&token, secret, now) {
+        Ok(l) => l,
+        Err(e) => {
+            warn!(logger, "err: {}", e);
+            return res.status(Status::NotFound);
+        },
+    };
+    info!(logger, "stream: {}, id: {}", link.stream, link.message);
+
+    let stream = match Stream::find_by_uuid(&link.stream, &conn, &logger) {
+        Some(s) => s,
+        None => return res.status(Status::NotFound),
+    };
+    match Namespace::find_by_id(stream.namespace_id, &conn, &logger) {
+        Some(n) if n.archived_at.is_none() => (),
+        _ => return res.status(Status::NotFound),
+    }
+    let id = &link.message;
+    let message =
+        match Message::first_by_stream_id(id, stream.id, &conn, &logger) {
+            Some(m) => m,
+            None => return res.status(Status::NotFound),
+        };
+    let (before, after) =
+        match Message::find_all_around(&message, link.context, &conn, &logger) {
+            Some(v) => v,
+            None => return res.status(Status::InternalServerError),
+        };
+
+    res.format(json!({
+        "message": message,
+        "context": {
+            "before": before,
+            "after": after,
+        },
+        "expires_at": NaiveDateTime::from_timestamp(link.expires_at, 0),
+    }))
+}
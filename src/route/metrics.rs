@@ -0,0 +1,52 @@
+//! A local-only endpoint for the metrics of the process (see
+//! `service::pool_metrics`), for operators and their monitoring. The render
+//! metrics are of all the processes (see `service::render_metrics`).
+use rocket::State;
+
+use crate::db::{DbPoolHolder, DbReplicaPoolHolder};
+use crate::mq::MqPoolHolder;
+use crate::request::local_only::LocalOnly;
+use crate::request::logger::RequestLogger;
+use crate::response::Response;
+use crate::service::render_metrics::{
+    RenderHistogram, RenderKind, RenderMetrics,
+};
+use crate::ss::SsPoolHolder;
+
+/// Returns the state of the connection pools (the replica is null if it's not
+/// configured) and the histograms of the render timings (null if the session
+/// store is not available).
+#[get("/metrics", rank = 1)]
+pub fn pools<'a>(
+    _local_only: LocalOnly,
+    db_holder: State<DbPoolHolder>,
+    db_replica_holder: State<DbReplicaPoolHolder>,
+    mq_holder: State<MqPoolHolder>,
+    ss_holder: State<SsPoolHolder>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    // before the connection for the histograms is taken
+    let pools = json!({
+        "database": db_holder.state(),
+        "database_replica": db_replica_holder.state(),
+        "message_queue": mq_holder.state(),
+        "session_store": ss_holder.state(),
+    });
+
+    let mut ss_conn = ss_holder.get();
+    let mut histogram = |kind: RenderKind| -> Option<RenderHistogram> {
+        let conn = ss_conn.as_mut()?;
+        RenderMetrics::new(&mut **conn)
+            .histogram(kind)
+            .map_err(|e| error!(logger, "err: {}", e))
+            .ok()
+    };
+    let render = json!({
+        "template": histogram(RenderKind::Template),
+        "payload": histogram(RenderKind::Payload),
+    });
+
+    res.format(json!({"pools": pools, "render": render}))
+}
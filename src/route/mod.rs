@@ -1,9 +1,28 @@
 pub mod access_token;
 pub mod activation;
+pub mod alert_schedule;
 pub mod authentication;
+pub mod badge;
+pub mod bulk_operation;
+pub mod channel;
+pub mod config;
 pub mod error;
+pub mod fault;
+#[cfg(feature = "graphql")]
+pub mod graphql;
 pub mod health;
+pub mod incident;
+pub mod maintenance;
 pub mod message;
+pub mod metrics;
 pub mod namespace;
+pub mod oauth;
 pub mod password_reset;
+pub mod push_device;
+pub mod recent_view;
 pub mod registration;
+pub mod slo;
+pub mod stream;
+pub mod stream_token;
+pub mod user;
+pub mod waitlist;
@@ -1,38 +1,128 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, NaiveDateTime};
+use diesel::pg::PgConnection;
 use diesel::result::Error;
+use rocket::State;
 use rocket::http::Status;
 use rocket_contrib::json::{Json, JsonValue};
-use rocket_slog::SyncLogger;
 
-use crate::db::DbConn;
-use crate::model::namespace::{Namespace, NewNamespace};
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::db::{DbConn, DbReadConn, with_statement_timeout};
+use crate::id::{IdGenerator, SharedIdGenerator};
+use crate::job::{Job, JobKind};
+use crate::logger::Logger;
+use crate::model::external_id::is_legacy;
+use crate::model::message::{LogLevel, Message, StatsInterval};
+use crate::model::message_count::HourlyMessageCount;
+use crate::model::namespace::{AnomalySensitivity, Namespace, NewNamespace};
 use crate::model::user::User;
 use crate::model::membership::{Membership, MembershipRole, NewMembership};
-use crate::response::Response;
-use crate::request::namespace::Namespace as RequestData;
+use crate::model::usage_record::UsageRecord;
+use crate::mq::JobQueue;
+use crate::request::logger::RequestLogger;
+use crate::response::{ApiError, Paginated, Response};
+use crate::request::confirmation::ConfirmationToken;
+use crate::request::etag::IfNoneMatch;
+use crate::request::pagination::Page;
+use crate::request::quota::ApiCallCount;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::request::scope::{MessagesRead, NamespaceAdmin, Scoped};
+use crate::request::sudo::Sudo;
+use crate::request::namespace::{
+    Namespace as RequestData, NamespaceTransfer as TransferData,
+    NamespaceUpdate as UpdateData,
+};
+use crate::service::badge::token as badge_token;
+use crate::service::confirmation::{Confirmation, ConfirmationAction};
+use crate::service::deprecation::LEGACY_ID;
+use crate::ss::SsConn;
 use crate::validation::namespace::Validator;
 
+// 100 years
+const RETENTION_DAYS_MAX: u32 = 36500;
+
+// time buckets in a response of stats
+const STATS_BUCKETS_MAX: i64 = 1_000;
+
+// the same as the ones of messages
+const DEFAULT_TAG_MAX_LENGTH: usize = 64;
+const DEFAULT_TAGS_MAX: usize = 16;
+
 pub mod preflight {
     use rocket::State;
     use rocket::response::Response as RawResponse;
-    use rocket_slog::SyncLogger;
 
     use crate::config::Config;
+    use crate::request::logger::RequestLogger;
     use crate::response::no_content_for;
 
     #[options("/namespace/hget/<uuid>", rank = 2)]
     pub fn hget<'a>(
         uuid: String,
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(logger, "hget uuid: {}", uuid);
         no_content_for("GET", &config)
     }
 
+    #[options("/namespace/hget/<uuid>/badge", rank = 2)]
+    pub fn badge<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "badge uuid: {}", uuid);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/namespace/hget/<uuid>/usage", rank = 2)]
+    pub fn usage<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "usage uuid: {}", uuid);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/namespace/hget/<uuid>/retention/preview?<days>", rank = 2)]
+    pub fn retention_preview<'a>(
+        uuid: String,
+        days: Option<u32>,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "uuid: {}, days: {:?}", uuid, days);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/namespace/hget/<uuid>/stats", rank = 2)]
+    pub fn stats<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "stats uuid: {}", uuid);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/namespace/hget/<uuid>/memberships", rank = 2)]
+    pub fn memberships<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "memberships uuid: {}", uuid);
+        no_content_for("GET", &config)
+    }
+
     #[options("/namespace/hgetall", rank = 2)]
     pub fn hgetall<'a>(
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(logger, "hgetall");
         no_content_for("GET", &config)
@@ -41,23 +131,139 @@ pub mod preflight {
     #[options("/namespace/hset", rank = 2)]
     pub fn hset<'a>(
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(logger, "hset");
         no_content_for("POST", &config)
     }
+
+    #[options("/namespace/del/<uuid>", rank = 2)]
+    pub fn del<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "del uuid: {}", uuid);
+        no_content_for("DELETE", &config)
+    }
+
+    #[options("/namespace/del/<uuid>/confirm", rank = 2)]
+    pub fn del_confirm<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "del confirm uuid: {}", uuid);
+        no_content_for("POST", &config)
+    }
+
+    #[options("/namespace/restore/<uuid>", rank = 2)]
+    pub fn restore<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "restore uuid: {}", uuid);
+        no_content_for("POST", &config)
+    }
+
+    #[options("/namespace/transfer/<uuid>", rank = 2)]
+    pub fn transfer<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "transfer uuid: {}", uuid);
+        no_content_for("POST", &config)
+    }
+
+    #[options("/namespace/transfer/<uuid>/confirm", rank = 2)]
+    pub fn transfer_confirm<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "transfer confirm uuid: {}", uuid);
+        no_content_for("POST", &config)
+    }
+
+    #[options("/namespace/transfer/<uuid>/accept", rank = 2)]
+    pub fn transfer_accept<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "transfer accept uuid: {}", uuid);
+        no_content_for("POST", &config)
+    }
+
+    #[options("/namespace/hset/<uuid>", rank = 2)]
+    pub fn update<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "update uuid: {}", uuid);
+        no_content_for("PATCH", &config)
+    }
+}
+
+// returns an error status unless the user is an owner of the namespace
+pub(crate) fn ensure_owner(
+    namespace: &Namespace,
+    user: &User,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Result<(), Status> {
+    match Membership::find_by_namespace_id_and_user_id(
+        namespace.id,
+        user.id,
+        conn,
+        logger,
+    ) {
+        Some(ref m) if m.is_owner() => Ok(()),
+        _ => {
+            warn!(logger, "err: not an owner of namespace: {}", namespace.uuid);
+            Err(Status::Forbidden)
+        },
+    }
+}
+
+// returns the namespace if the user is an owner of it
+pub(crate) fn find_owned_namespace(
+    namespace_key: &str,
+    user: &User,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Result<Namespace, Status> {
+    let namespace =
+        match Namespace::find_by_uuid(namespace_key, user, conn, logger) {
+            Some(n) => n,
+            None => {
+                error!(logger, "err: no namespace for uuid: {}", namespace_key);
+                return Err(Status::NotFound);
+            },
+        };
+    ensure_owner(&namespace, user, conn, logger)?;
+    Ok(namespace)
 }
 
 #[get("/namespace/hget/<uuid>", rank = 1)]
 pub fn hget(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
     uuid: String,
     user: &User,
+    _scope: Scoped<MessagesRead>,
     conn: DbConn,
-    logger: SyncLogger,
+    logger: RequestLogger,
 ) -> Response {
     info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
 
-    let res: Response = Default::default();
+    let mut res: Response = Default::default();
+    if is_legacy(&uuid) {
+        res = res.deprecate(LEGACY_ID);
+    }
 
     let data: Result<JsonValue, Error> =
         match Namespace::find_by_uuid(&uuid, &user, &conn, &logger) {
@@ -73,28 +279,475 @@ pub fn hget(
     res.format(data.unwrap())
 }
 
-#[get("/namespace/hgetall", rank = 1)]
-pub fn hgetall(user: &User, conn: DbConn, logger: SyncLogger) -> Response {
+// Returns the token and the path of the status badge of the namespace (only
+// for owners). The token is not needed if `badge_public` is true.
+#[get("/namespace/hget/<uuid>/badge", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn badge<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    conn: DbConn,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
     let res: Response = Default::default();
 
-    info!(logger, "user: {}", user.uuid);
+    let namespace = match find_owned_namespace(&uuid, user, &conn, &logger) {
+        Ok(n) => n,
+        Err(status) => return res.status(status),
+    };
+
+    let token = badge_token(&namespace, &config.authentication_token_secret);
+    res.format(json!({"badge": {
+        "path": format!("/badge/{}.svg?token={}", namespace.uuid, token),
+        "public": namespace.badge_public,
+        "token": token,
+    }}))
+}
+
+// Returns monthly usage records of the namespace (only for owners).
+#[get("/namespace/hget/<uuid>/usage", rank = 1)]
+pub fn usage(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let mut res: Response = Default::default();
+    if is_legacy(&uuid) {
+        res = res.deprecate(LEGACY_ID);
+    }
 
-    let data = match Namespace::find_all(user, &conn, &logger) {
+    let namespace = match find_owned_namespace(&uuid, user, &conn, &logger) {
+        Ok(n) => n,
+        Err(status) => return res.status(status),
+    };
+
+    let data = UsageRecord::find_all_by_namespace_id(
+        namespace.id,
+        &conn,
+        &logger,
+    )
+    .unwrap_or_else(Vec::new);
+    res.format(json!({ "usage_records": data }))
+}
+
+// Returns how many messages (and bytes) a retention of the days would delete,
+// and the oldest message which would survive (only for owners). Nothing is
+// deleted.
+#[get("/namespace/hget/<uuid>/retention/preview?<days>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn retention_preview<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    uuid: String,
+    days: Option<u32>,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    conn: DbReadConn,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}, days: {:?}", user.uuid, uuid, days);
+
+    let mut res: Response = Default::default();
+    if is_legacy(&uuid) {
+        res = res.deprecate(LEGACY_ID);
+    }
+
+    let days = match days {
+        Some(d) if d > 0 && d <= RETENTION_DAYS_MAX => d,
+        _ => {
+            let message =
+                format!("Must be between 1 and {}", RETENTION_DAYS_MAX);
+            return invalid(res, "days", &message);
+        },
+    };
+
+    let namespace = match find_owned_namespace(&uuid, user, &conn, &logger) {
+        Ok(n) => n,
+        Err(status) => return res.status(status),
+    };
+
+    let before = clock.now().naive_utc() - Duration::days(i64::from(days));
+    let result =
+        with_statement_timeout(&conn, config.database_statement_timeout, || {
+            Ok(Message::preview_retention(namespace.id, before, &conn, &logger))
+        });
+    match result.ok().flatten() {
+        Some(p) => res.format(json!({"retention_preview": {
+            "days": days,
+            "before": before,
+            "messages_count": p.messages_count,
+            "bytes_count": p.bytes_count,
+            "oldest_created_at": p.oldest_created_at,
+        }})),
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Returns the number of messages in the namespace by the level for each time
+// bucket in `[from, to)`, for charts on dashboards. The `interval` of the
+// buckets is hour (default) or day, and the range is the last day by default.
+// The time is RFC 3339 or `2021-06-30T00:00:00` (UTC).
+//
+// Every bucket in the range is returned, even if it has no message. The counts
+// over a range longer than a day are read from the hourly rollups (see
+// `model::message_count`) as far as they have been aggregated.
+//
+// The value looks like this:
+//
+// ```json
+// {"stats": {
+//    "interval": "hour",
+//    "from": "2021-06-30T00:00:00",
+//    "to": "2021-07-01T00:00:00",
+//    "buckets": [{
+//      "time": "2021-06-30T00:00:00",
+//      "total": 3,
+//      "levels": {"critical": 0, "debug": 0, "error": 2, ...}
+//    }, ...]
+// }}
+// ```
+#[get("/namespace/hget/<uuid>/stats?<from>&<to>&<interval>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn stats<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    uuid: String,
+    from: Option<String>,
+    to: Option<String>,
+    interval: Option<String>,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    conn: DbReadConn,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(
+        logger,
+        "user: {}, uuid: {}, from: {:?}, to: {:?}, interval: {:?}",
+        user.uuid,
+        uuid,
+        from,
+        to,
+        interval
+    );
+
+    let mut res: Response = Default::default();
+    if is_legacy(&uuid) {
+        res = res.deprecate(LEGACY_ID);
+    }
+
+    let interval = match interval.as_deref().map(StatsInterval::from_name) {
+        None => StatsInterval::Hour,
+        Some(Some(i)) => i,
+        Some(None) => {
+            return invalid(res, "interval", "Must be one of hour, day");
+        },
+    };
+    let to = match to.as_deref().map(parse_time) {
+        None => clock.now().naive_utc(),
+        Some(Some(t)) => t,
+        Some(None) => return invalid(res, "to", "Must be a time"),
+    };
+    let from = match from.as_deref().map(parse_time) {
+        None => to - Duration::days(1),
+        Some(Some(t)) => t,
+        Some(None) => return invalid(res, "from", "Must be a time"),
+    };
+    if from >= to {
+        return invalid(res, "from", "Must be before to");
+    }
+    let width = interval.duration().num_seconds();
+    let start = interval.truncate(from);
+    if (to - start).num_seconds() > width * STATS_BUCKETS_MAX {
+        let message =
+            format!("Must contain less than {} buckets", STATS_BUCKETS_MAX);
+        return invalid(res, "interval", &message);
+    }
+
+    let namespace = match Namespace::find_by_uuid(&uuid, &user, &conn, &logger)
+    {
+        Some(n) => n,
+        None => {
+            error!(logger, "err: no namespace for uuid: {}", uuid);
+            return res.status(Status::NotFound);
+        },
+    };
+
+    let result =
+        with_statement_timeout(&conn, config.database_statement_timeout, || {
+            if to - from > Duration::days(1) {
+                return Ok(HourlyMessageCount::count_by_level_and_time(
+                    namespace.id,
+                    interval,
+                    from,
+                    to,
+                    &conn,
+                    &logger,
+                ));
+            }
+            Ok(Message::count_by_level_and_time(
+                namespace.id,
+                interval,
+                from,
+                to,
+                &conn,
+                &logger,
+            ))
+        });
+    let counts = match result.ok().flatten() {
+        Some(v) => v,
+        None => return res.status(Status::InternalServerError),
+    };
+
+    let mut times: Vec<NaiveDateTime> = vec![];
+    let mut t = start;
+    while t < to {
+        times.push(t);
+        t += interval.duration();
+    }
+    let index: HashMap<NaiveDateTime, usize> =
+        times.iter().enumerate().map(|(i, t)| (*t, i)).collect();
+    let levels: BTreeMap<String, i64> =
+        LogLevel::iter().map(|l| (l.to_string(), 0)).collect();
+    let mut buckets: Vec<(i64, BTreeMap<String, i64>)> =
+        vec![(0, levels); times.len()];
+    for c in counts {
+        if let Some(&i) = index.get(&c.time) {
+            buckets[i].0 += c.count;
+            *buckets[i].1.entry(c.level).or_insert(0) += c.count;
+        }
+    }
+
+    let buckets: Vec<JsonValue> = times
+        .iter()
+        .zip(buckets.into_iter())
+        .map(|(time, (total, levels))| {
+            json!({
+                "time": time,
+                "total": total,
+                "levels": levels,
+            })
+        })
+        .collect();
+    res.format(json!({"stats": {
+        "interval": interval.to_string(),
+        "from": from,
+        "to": to,
+        "buckets": buckets,
+    }}))
+}
+
+// accepts RFC 3339 or the one without the offset (UTC)
+fn parse_time(s: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.naive_utc())
+        .or_else(|_| s.parse::<NaiveDateTime>())
+        .ok()
+}
+
+fn invalid<'a>(res: Response<'a>, field: &str, message: &str) -> Response<'a> {
+    res.error(ApiError::new(Status::UnprocessableEntity).field(field, message))
+}
+
+// Consumes the confirmation token of the action on the namespace by the user
+// (it's not needed in sudo mode).
+fn consume_confirmation(
+    user: &User,
+    action: ConfirmationAction,
+    namespace: &Namespace,
+    (sudo, token): (Option<Sudo>, ConfirmationToken),
+    ss_conn: &mut SsConn,
+    logger: &RequestLogger,
+) -> bool {
+    if sudo.is_some() {
+        return true;
+    }
+    let result = Confirmation::new(&mut *ss_conn).consume(
+        &user.uuid.to_string(),
+        action,
+        &namespace.uuid.to_string(),
+        &token.0,
+    );
+    match result {
+        Ok(v) => v,
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            false
+        },
+    }
+}
+
+// Issues a confirmation token of the action on the namespace.
+#[allow(clippy::too_many_arguments)]
+fn issue_confirmation<'a>(
+    res: Response<'a>,
+    user: &User,
+    action: ConfirmationAction,
+    namespace: &Namespace,
+    ss_conn: &mut SsConn,
+    config: &Config,
+    ids: &dyn IdGenerator,
+    logger: &RequestLogger,
+) -> Response<'a> {
+    let duration = config.token_duration_confirmation;
+    let result = Confirmation::new(&mut *ss_conn).issue(
+        &user.uuid.to_string(),
+        action,
+        &namespace.uuid.to_string(),
+        duration,
+        ids,
+    );
+    match result {
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            res.status(Status::InternalServerError)
+        },
+        Ok(token) => res.format(json!({
+            "confirmation_token": {
+                "token": token,
+                "expires_in": duration.as_secs(),
+            }
+        })),
+    }
+}
+
+// Returns a page of namespaces visible to the user. It responds with 304 if
+// the ETag given as If-None-Match is still fresh.
+#[get("/namespace/hgetall?<cursor>&<limit>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn hgetall<'a>(
+    _rate_limit: RateLimit<Api>,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    if_none_match: IfNoneMatch,
+    cursor: Option<String>,
+    limit: Option<i64>,
+    conn: DbReadConn,
+    logger: RequestLogger,
+) -> Result<Paginated<'a, JsonValue>, Response<'a>> {
+    let mut res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, cursor: {:?}, limit: {:?}", user.uuid, cursor, limit
+    );
+
+    let page = match Page::default().query(cursor.as_deref(), limit) {
+        Ok(p) => p,
+        Err(e) => return Err(res.error(ApiError::invalid(vec![e]))),
+    };
+
+    if let Some((updated_at, count)) = Namespace::version(user, &conn, &logger)
+    {
+        let etag = page.etag(updated_at, count);
+        if if_none_match.matches(&etag) {
+            return Err(res.status(Status::NotModified).etag(etag));
+        }
+        res = res.etag(etag);
+    }
+
+    let namespaces = match Namespace::find_all(user, &conn, &logger) {
         None => {
             error!(logger, "err: no namespace for user: {}", user.uuid);
             vec![]
         },
-        Some(a) => a.iter().map(|n| json!({ "namespace": n })).collect(),
+        Some(a) => a,
     };
-    res.format(json!(data))
+    let total = namespaces.len() as i64;
+    let (namespaces, next_cursor) = page.slice(namespaces);
+    let data: Vec<JsonValue> = namespaces
+        .iter()
+        .map(|n| json!({ "namespace": n }))
+        .collect();
+    Ok(res.paginate(data, next_cursor).total_estimate(total))
+}
+
+// Returns a page of members of the namespace visible to the user.
+#[get("/namespace/hget/<uuid>/memberships?<cursor>&<limit>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn memberships<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    uuid: String,
+    cursor: Option<String>,
+    limit: Option<i64>,
+    user: &User,
+    _scope: Scoped<MessagesRead>,
+    conn: DbReadConn,
+    logger: RequestLogger,
+) -> Result<Paginated<'a, JsonValue>, Response<'a>> {
+    info!(
+        logger,
+        "user: {}, uuid: {}, cursor: {:?}, limit: {:?}",
+        user.uuid,
+        uuid,
+        cursor,
+        limit
+    );
+
+    let res: Response = Default::default();
+    let page = match Page::default().query(cursor.as_deref(), limit) {
+        Ok(p) => p,
+        Err(e) => return Err(res.error(ApiError::invalid(vec![e]))),
+    };
+
+    let namespace = match Namespace::find_by_uuid(&uuid, &user, &conn, &logger)
+    {
+        Some(n) => n,
+        None => {
+            error!(logger, "err: no namespace for uuid: {}", uuid);
+            return Err(res.status(Status::NotFound));
+        },
+    };
+
+    let memberships = Membership::find_all_with_users_by_namespace_id(
+        namespace.id,
+        &conn,
+        &logger,
+    )
+    .unwrap_or_else(Vec::new);
+    let total = memberships.len() as i64;
+    let (memberships, next_cursor) = page.slice(memberships);
+    let data: Vec<JsonValue> = memberships
+        .iter()
+        .map(|(m, u)| {
+            json!({"membership": {
+                "role": m.role.to_string(),
+                "user": {
+                    "uuid": u.uuid.to_string(),
+                    "name": u.name,
+                    "username": u.username,
+                },
+                "created_at": m.created_at,
+            }})
+        })
+        .collect();
+    Ok(res.paginate(data, next_cursor).total_estimate(total))
 }
 
 #[post("/namespace/hset", data = "<data>", format = "json", rank = 1)]
 pub fn hset(
+    _rate_limit: RateLimit<Api>,
     user: &User,
+    _scope: Scoped<NamespaceAdmin>,
     data: Json<RequestData>,
     conn: DbConn,
-    logger: SyncLogger,
+    logger: RequestLogger,
 ) -> Response {
     let res: Response = Default::default();
 
@@ -103,9 +756,7 @@ pub fn hset(
     let v = Validator::new(&data, &logger);
     match v.validate() {
         Err(errors) => {
-            res.status(Status::UnprocessableEntity).format(json!({
-                "errors": errors,
-            }))
+            res.error(ApiError::invalid(errors))
         },
         Ok(_) => {
             let result: Result<String, Error> = conn
@@ -138,3 +789,543 @@ pub fn hset(
         },
     }
 }
+
+// Changes the settings of the namespace (only for owners): `min_level`
+// (messages below it are counted as dropped but not saved on ingestion) and
+// `default_tags` (merged into the tags of ingested messages, so that they can
+// be filtered by them without configuring agents) and `anomaly_sensitivity`
+// (how large a spike of errors must be to be alerted, see
+// service::anomaly_detector) and `badge_public` (whether the status badge is
+// shown without the token, see service::badge).
+#[patch("/namespace/hset/<uuid>", data = "<data>", format = "json", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn update<'a>(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    data: Json<UpdateData>,
+    conn: DbConn,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    let min_level = match data.0.min_level.as_deref() {
+        None => None,
+        Some("") => Some(None),
+        Some(v) => {
+            let name = v.to_ascii_lowercase();
+            match LogLevel::iter().find(|l| l.to_string() == name) {
+                Some(l) => Some(Some(l.clone())),
+                None => {
+                    let names: Vec<String> =
+                        LogLevel::iter().map(|l| l.to_string()).collect();
+                    let message =
+                        format!("Must be one of {}", names.join(", "));
+                    return invalid(res, "min_level", &message);
+                },
+            }
+        },
+    };
+
+    let default_tags = match data.0.default_tags {
+        None => None,
+        Some(ref tags) => {
+            let mut values: Vec<String> = vec![];
+            for tag in tags.iter().map(|t| t.trim().to_string()) {
+                if tag.is_empty() || values.contains(&tag) {
+                    continue;
+                }
+                if tag.chars().count() > DEFAULT_TAG_MAX_LENGTH {
+                    let message = format!(
+                        "Must be at most {} characters",
+                        DEFAULT_TAG_MAX_LENGTH
+                    );
+                    return invalid(res, "default_tags", &message);
+                }
+                values.push(tag);
+            }
+            if values.len() > DEFAULT_TAGS_MAX {
+                let message =
+                    format!("Must be at most {} tags", DEFAULT_TAGS_MAX);
+                return invalid(res, "default_tags", &message);
+            }
+            Some(values)
+        },
+    };
+
+    let anomaly_sensitivity = match data.0.anomaly_sensitivity.as_deref() {
+        None => None,
+        Some(v) => {
+            let name = v.trim().to_ascii_lowercase();
+            match AnomalySensitivity::from_name(&name) {
+                Some(s) => Some(s),
+                None => {
+                    let names: Vec<String> = AnomalySensitivity::iter()
+                        .map(|s| s.to_string())
+                        .collect();
+                    let message =
+                        format!("Must be one of {}", names.join(", "));
+                    return invalid(res, "anomaly_sensitivity", &message);
+                },
+            }
+        },
+    };
+
+    let namespace = match find_owned_namespace(&uuid, user, &conn, &logger) {
+        Ok(n) => n,
+        Err(status) => return res.status(status),
+    };
+
+    let now = clock.now().naive_utc();
+    let namespace = match min_level {
+        None => namespace,
+        Some(level) => {
+            match namespace.set_min_level(level, now, &conn, &logger) {
+                Some(n) => n,
+                None => return res.status(Status::InternalServerError),
+            }
+        },
+    };
+    let namespace = match default_tags {
+        None => namespace,
+        Some(tags) => {
+            match namespace.set_default_tags(tags, now, &conn, &logger) {
+                Some(n) => n,
+                None => return res.status(Status::InternalServerError),
+            }
+        },
+    };
+    let namespace = match anomaly_sensitivity {
+        None => namespace,
+        Some(s) => {
+            match namespace.set_anomaly_sensitivity(s, now, &conn, &logger) {
+                Some(n) => n,
+                None => return res.status(Status::InternalServerError),
+            }
+        },
+    };
+    let namespace = match data.0.badge_public {
+        None => namespace,
+        Some(v) => match namespace.set_badge_public(v, now, &conn, &logger) {
+            Some(n) => n,
+            None => return res.status(Status::InternalServerError),
+        },
+    };
+    res.format(json!({ "namespace": namespace }))
+}
+
+// Issues a confirmation token for the deletion of the namespace (only for
+// owners). It must be given as X-Confirmation-Token header to `del` within the
+// expiration.
+#[post("/namespace/del/<uuid>/confirm", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn del_confirm<'a>(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    conn: DbConn,
+    mut ss_conn: SsConn,
+    config: State<Config>,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    let namespace = match find_owned_namespace(&uuid, user, &conn, &logger) {
+        Ok(n) => n,
+        Err(status) => return res.status(status),
+    };
+
+    issue_confirmation(
+        res,
+        user,
+        ConfirmationAction::DeleteNamespace,
+        &namespace,
+        &mut ss_conn,
+        &config,
+        &**ids,
+        &logger,
+    )
+}
+
+// Deletes the namespace softly (only for owners). It can be restored until
+// it's purged after the grace period (see service::namespace_purger). A
+// confirmation token issued by `del_confirm` is required unless the user is in
+// sudo mode, otherwise it responds 403.
+#[delete("/namespace/del/<uuid>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn del<'a>(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    sudo: Option<Sudo>,
+    confirmation: ConfirmationToken,
+    conn: DbConn,
+    mut queue: JobQueue,
+    mut ss_conn: SsConn,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    let namespace = match find_owned_namespace(&uuid, user, &conn, &logger) {
+        Ok(n) => n,
+        Err(status) => return res.status(status),
+    };
+
+    if !consume_confirmation(
+        user,
+        ConfirmationAction::DeleteNamespace,
+        &namespace,
+        (sudo, confirmation),
+        &mut ss_conn,
+        &logger,
+    ) {
+        return res.error(
+            ApiError::new(Status::Forbidden)
+                .field("confirmation_token", "Must be confirmed"),
+        );
+    }
+
+    let now = clock.now();
+    let namespace = match namespace.archive(now.naive_utc(), &conn, &logger) {
+        Some(n) => n,
+        None => return res.status(Status::InternalServerError),
+    };
+
+    let period = Duration::seconds(config.namespace_purge_grace_period as i64);
+    let job = Job::<String> {
+        kind: JobKind::PurgeNamespaces,
+        args: vec![],
+    };
+    // the job may also be run by hand before it
+    if let Err(e) = queue.defer(&job, now + period) {
+        error!(logger, "err: {}", e);
+    }
+
+    res.format(json!({"namespace": {
+        "uuid": namespace.uuid.to_string(),
+        "archived_at": namespace.archived_at,
+        "restorable_until": (now + period).naive_utc(),
+    }}))
+}
+
+// Restores the deleted namespace within the grace period (only for owners).
+#[post("/namespace/restore/<uuid>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn restore<'a>(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    conn: DbConn,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    let namespace =
+        match Namespace::find_archived_by_uuid(&uuid, &user, &conn, &logger) {
+            Some(n) => n,
+            None => {
+                error!(logger, "err: no deleted namespace for uuid: {}", uuid);
+                return res.status(Status::NotFound);
+            },
+        };
+
+    if let Err(status) = ensure_owner(&namespace, user, &conn, &logger) {
+        return res.status(status);
+    }
+
+    let now = clock.now().naive_utc();
+    let period = Duration::seconds(config.namespace_purge_grace_period as i64);
+    match namespace.archived_at {
+        Some(t) if t + period > now => (),
+        _ => {
+            warn!(logger, "err: grace period has passed: {}", uuid);
+            return res.status(Status::Gone);
+        },
+    }
+
+    match namespace.restore(now, &conn, &logger) {
+        Some(n) => res.format(json!({ "namespace": n })),
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Issues a confirmation token for a request of transfer of the namespace (only
+// for the primary owner). It must be given as X-Confirmation-Token header to
+// `transfer` within the expiration.
+#[post("/namespace/transfer/<uuid>/confirm", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_confirm<'a>(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    conn: DbConn,
+    mut ss_conn: SsConn,
+    config: State<Config>,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    let namespace = match Namespace::find_by_uuid(&uuid, &user, &conn, &logger)
+    {
+        Some(n) => n,
+        None => {
+            error!(logger, "err: no namespace for uuid: {}", uuid);
+            return res.status(Status::NotFound);
+        },
+    };
+
+    match Membership::find_by_namespace_id_and_user_id(
+        namespace.id,
+        user.id,
+        &conn,
+        &logger,
+    ) {
+        Some(ref m) if m.role == MembershipRole::PrimaryOwner => (),
+        _ => {
+            warn!(logger, "err: not the primary owner of namespace: {}", uuid);
+            return res.status(Status::Forbidden);
+        },
+    }
+
+    issue_confirmation(
+        res,
+        user,
+        ConfirmationAction::RequestNamespaceTransfer,
+        &namespace,
+        &mut ss_conn,
+        &config,
+        &**ids,
+        &logger,
+    )
+}
+
+// Requests a transfer of the namespace to another member (only for the
+// primary owner). The new owner receives a token by email, and the roles are
+// changed when it's accepted by `transfer_accept`. A confirmation token issued
+// by `transfer_confirm` is required unless the user is in sudo mode, otherwise
+// it responds 403.
+#[post(
+    "/namespace/transfer/<uuid>",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn transfer<'a>(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    data: Json<TransferData>,
+    sudo: Option<Sudo>,
+    confirmation: ConfirmationToken,
+    conn: DbConn,
+    mut queue: JobQueue,
+    mut ss_conn: SsConn,
+    config: State<Config>,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    let namespace = match Namespace::find_by_uuid(&uuid, &user, &conn, &logger)
+    {
+        Some(n) => n,
+        None => {
+            error!(logger, "err: no namespace for uuid: {}", uuid);
+            return res.status(Status::NotFound);
+        },
+    };
+
+    match Membership::find_by_namespace_id_and_user_id(
+        namespace.id,
+        user.id,
+        &conn,
+        &logger,
+    ) {
+        Some(ref m) if m.role == MembershipRole::PrimaryOwner => (),
+        _ => {
+            warn!(logger, "err: not the primary owner of namespace: {}", uuid);
+            return res.status(Status::Forbidden);
+        },
+    }
+
+    let key = data.0.user.unwrap_or_default();
+    let new_owner = User::find_by_uuid(&key, &conn, &logger).filter(|u| {
+        u.id != user.id &&
+            Membership::find_by_namespace_id_and_user_id(
+                namespace.id,
+                u.id,
+                &conn,
+                &logger,
+            )
+            .is_some()
+    });
+    let new_owner = match new_owner {
+        Some(u) => u,
+        None => {
+            return invalid(
+                res,
+                "user",
+                "Must be another member of the namespace",
+            );
+        },
+    };
+
+    if !consume_confirmation(
+        user,
+        ConfirmationAction::RequestNamespaceTransfer,
+        &namespace,
+        (sudo, confirmation),
+        &mut ss_conn,
+        &logger,
+    ) {
+        return res.error(
+            ApiError::new(Status::Forbidden)
+                .field("confirmation_token", "Must be confirmed"),
+        );
+    }
+
+    let namespace_uuid = namespace.uuid.to_string();
+    let duration = config.token_duration_namespace_transfer;
+    let result = Confirmation::new(&mut *ss_conn).issue(
+        &new_owner.uuid.to_string(),
+        ConfirmationAction::TransferNamespace,
+        &namespace_uuid,
+        duration,
+        &**ids,
+    );
+    let token = match result {
+        Ok(t) => t,
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            return res.status(Status::InternalServerError);
+        },
+    };
+
+    let job = Job::<String> {
+        kind: JobKind::SendNamespaceTransferEmail,
+        args: vec![new_owner.uuid.to_string(), namespace_uuid.clone(), token],
+    };
+    if let Err(err) = queue.enqueue(job) {
+        error!(logger, "error: {}", err);
+        return res.status(Status::InternalServerError);
+    }
+
+    res.status(Status::Accepted).format(json!({"namespace_transfer": {
+        "namespace": namespace_uuid,
+        "user": new_owner.uuid.to_string(),
+        "expires_in": duration.as_secs(),
+    }}))
+}
+
+// Accepts the transfer of the namespace with the token (as X-Confirmation-Token
+// header) sent by `transfer`. The user becomes the primary owner, and the
+// current one becomes an owner.
+#[post("/namespace/transfer/<uuid>/accept", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_accept(
+    _rate_limit: RateLimit<Api>,
+    uuid: String,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    confirmation: ConfirmationToken,
+    conn: DbConn,
+    mut ss_conn: SsConn,
+    logger: RequestLogger,
+) -> Response {
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let res: Response = Default::default();
+
+    let namespace = match Namespace::find_by_uuid(&uuid, &user, &conn, &logger)
+    {
+        Some(n) => n,
+        None => {
+            error!(logger, "err: no namespace for uuid: {}", uuid);
+            return res.status(Status::NotFound);
+        },
+    };
+
+    let confirmed = Confirmation::new(&mut *ss_conn).consume(
+        &user.uuid.to_string(),
+        ConfirmationAction::TransferNamespace,
+        &namespace.uuid.to_string(),
+        &confirmation.0,
+    );
+    match confirmed {
+        Ok(true) => (),
+        result => {
+            if let Err(e) = result {
+                error!(logger, "err: {}", e);
+            }
+            return res.error(
+                ApiError::new(Status::PreconditionRequired)
+                    .field("confirmation_token", "Must be confirmed"),
+            );
+        },
+    }
+
+    let result: Result<(), Error> = conn
+        .build_transaction()
+        .serializable()
+        .read_write()
+        .run::<(), diesel::result::Error, _>(|| {
+            let memberships = Membership::find_all_by_namespace_id(
+                namespace.id,
+                &conn,
+                &logger,
+            )
+            .ok_or(Error::RollbackTransaction)?;
+            let new_owner = memberships
+                .iter()
+                .find(|m| m.user_id == user.id)
+                .ok_or(Error::NotFound)?;
+            for m in memberships.iter().filter(|m| {
+                m.user_id != user.id && m.role == MembershipRole::PrimaryOwner
+            }) {
+                m.update_role(MembershipRole::Owner, &conn, &logger)
+                    .map_err(|_| Error::RollbackTransaction)?;
+            }
+            new_owner
+                .update_role(MembershipRole::PrimaryOwner, &conn, &logger)
+                .map_err(|_| Error::RollbackTransaction)?;
+            Ok(())
+        });
+
+    match result {
+        Ok(_) => res.format(json!({"namespace": {
+            "uuid": namespace.uuid.to_string(),
+            "primary_owner": user.uuid.to_string(),
+        }})),
+        Err(Error::NotFound) => res.status(Status::NotFound),
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            res.status(Status::InternalServerError)
+        },
+    }
+}
@@ -0,0 +1,213 @@
+use redis::{Commands, RedisError};
+use rocket::State;
+use rocket::http::{Cookie, Cookies, SameSite, Status};
+use rocket_contrib::json::Json;
+
+use crate::clock::{Clock, SharedClock};
+use crate::config::Config;
+use crate::db::DbConn;
+use crate::id::SharedIdGenerator;
+use crate::license::{Feature, License};
+use crate::model::identity::IdentityProvider;
+use crate::request::logger::RequestLogger;
+use crate::request::oauth::OAuthCallback as RequestData;
+use crate::request::rate_limit::{Login, RateLimit};
+use crate::response::{ApiError, Response};
+use crate::route::authentication::sign_in;
+use crate::service::oauth::{self, Client};
+use crate::ss::SsConn;
+
+const COOKIE_NAME: &str = "oauth_state";
+const KEY_PREFIX: &str = "oa-";
+const STATE_DURATION: usize = 600; // 10 minutes (seconds)
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/oauth/<provider>", rank = 2)]
+    pub fn authorize<'a>(
+        provider: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "provider: {}", provider);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/oauth/<provider>/callback", rank = 2)]
+    pub fn callback<'a>(
+        provider: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "provider: {}", provider);
+        no_content_for("POST", &config)
+    }
+}
+
+// Returns a client for the provider, or an error response if it's unknown,
+// not licensed or not configured.
+fn client_for<'a, 'b>(
+    provider: &str,
+    config: &'b Config,
+    license: &License,
+    clock: &dyn Clock,
+    logger: &'b RequestLogger,
+) -> Result<Client<'b>, Response<'a>> {
+    let res: Response = Default::default();
+
+    let provider = match IdentityProvider::from_name(provider) {
+        // ldap is not an oauth provider (see login)
+        Some(IdentityProvider::Ldap) | None => {
+            return Err(res.status(Status::NotFound));
+        },
+        Some(p) => p,
+    };
+
+    if let Err(e) = license.require(Feature::Sso, clock) {
+        warn!(logger, "error: {}", e);
+        return Err(res.error(
+            ApiError::new(Status::Forbidden).message(e.to_string()),
+        ));
+    }
+
+    match Client::new(provider, config, logger) {
+        Some(c) => Ok(c),
+        None => {
+            warn!(logger, "unavailable provider: {}", provider);
+            Err(res.status(Status::NotFound))
+        },
+    }
+}
+
+// Returns the URL of the provider to start the authorization.
+//
+// The state is saved in the session store with a short duration and set as a
+// private cookie for the callback.
+#[get("/oauth/<provider>", format = "json", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn authorize<'a>(
+    provider: String,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    license: State<License>,
+    mut cookies: Cookies,
+    logger: RequestLogger,
+    mut ss_conn: SsConn,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let client =
+        match client_for(&provider, &config, &license, &**clock, &logger) {
+            Ok(c) => c,
+            Err(res) => return res,
+        };
+
+    let state =
+        ids.random_hash(Config::CSRF_HASH_SOURCE, Config::CSRF_HASH_LENGTH);
+    let key = format!("{}{}", KEY_PREFIX, state);
+    let result: Result<String, RedisError> =
+        ss_conn.set_ex(&key, &provider, STATE_DURATION);
+    if let Err(e) = result {
+        error!(logger, "error: {}", e);
+        return res.status(Status::InternalServerError);
+    }
+
+    let mut cookie = Cookie::new(COOKIE_NAME, state.clone());
+    cookie.set_http_only(true);
+    cookie.set_secure(config.cookie_secure);
+    cookie.set_same_site(SameSite::Strict);
+    cookies.add_private(cookie);
+
+    res.format(json!({ "url": client.authorize_url(&state) }))
+}
+
+// Signs in with the code from the provider.
+//
+// An account will be created or linked (with the same verified email) at the
+// first time.
+#[post(
+    "/oauth/<provider>/callback",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn callback<'a>(
+    _rate_limit: RateLimit<Login>,
+    provider: String,
+    data: Json<RequestData>,
+    config: State<Config>,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    license: State<License>,
+    mut cookies: Cookies<'a>,
+    db_conn: DbConn,
+    logger: RequestLogger,
+    mut ss_conn: SsConn,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let client =
+        match client_for(&provider, &config, &license, &**clock, &logger) {
+            Ok(c) => c,
+            Err(res) => return res,
+        };
+    let provider = client.provider;
+
+    // the state can be used only once
+    let state = cookies
+        .get_private(COOKIE_NAME)
+        .map(|c| c.value().to_string())
+        .unwrap_or_else(|| "".to_string());
+    cookies.remove_private(Cookie::named(COOKIE_NAME));
+
+    let key = format!("{}{}", KEY_PREFIX, data.state);
+    let value: Result<Option<String>, RedisError> = redis::pipe()
+        .atomic()
+        .get(&key)
+        .del(&key)
+        .ignore()
+        .query(&mut *ss_conn)
+        .map(|(v,): (Option<String>,)| v);
+    let expected = provider.to_string();
+    match value {
+        Ok(Some(ref v)) if *v == expected && state == data.state => (),
+        Ok(_) => {
+            warn!(logger, "invalid state");
+            return res.error(
+                ApiError::new(Status::Unauthorized)
+                    .message("The authorization has been expired. Try again."),
+            );
+        },
+        Err(e) => {
+            error!(logger, "error: {}", e);
+            return res.status(Status::InternalServerError);
+        },
+    }
+
+    let profile = match client.fetch_profile(&data.code) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!(logger, "error: {}", e);
+            return res.error(
+                ApiError::new(Status::Unauthorized)
+                    .message("The authorization has been failed. Try again."),
+            );
+        },
+    };
+
+    match oauth::sign_in(provider, &profile, &**ids, &db_conn, &logger) {
+        Ok(ref user) => sign_in(user, &config, &**clock, cookies),
+        Err(message) => {
+            warn!(logger, "login failed: {} ({})", message, provider);
+            res.error(ApiError::new(Status::Unauthorized).message(message))
+        },
+    }
+}
@@ -1,23 +1,29 @@
-use chrono::{Duration, Utc};
 use diesel::result::Error;
-use fourche::queue::Queue;
 use redis::{Commands, RedisError};
 use rocket::State;
-use rocket::http::{Cookies, Status};
+use rocket::http::Status;
 use rocket_contrib::json::Json;
-use rocket_slog::SyncLogger;
 
+use crate::clock::SharedClock;
 use crate::config::Config;
 use crate::db::DbConn;
+use crate::id::SharedIdGenerator;
 use crate::job::{Job, JobKind};
+use crate::mailer::security::SecurityNotice;
 use crate::model::token::{VerificationClaims, Claims, TokenData};
 use crate::model::user::User;
-use crate::mq::MqConn;
+use crate::mq::JobQueue;
+use crate::request::body_limit::Auth;
+use crate::request::csrf::{CsrfToken, CsrfTokenError};
+use crate::request::json::JsonBody;
+use crate::request::logger::RequestLogger;
+use crate::request::rate_limit::{Login, RateLimit};
 use crate::request::password_reset::{
     PasswordReset, PasswordResetRequest, PasswordResetUpdate,
 };
 use crate::request::token::verification::VerificationToken;
-use crate::response::Response;
+use crate::response::{ApiError, Response};
+use crate::service::auth_backend;
 use crate::service::password_updater::PasswordUpdater;
 use crate::validation::ValidationError;
 use crate::validation::password_reset::Validator as PasswordResetValidator;
@@ -28,9 +34,9 @@ use crate::util::split_token;
 pub mod preflight {
     use rocket::State;
     use rocket::response::Response as RawResponse;
-    use rocket_slog::SyncLogger;
 
     use crate::config::Config;
+    use crate::request::logger::RequestLogger;
     use crate::response::no_content_for;
 
     #[options("/password/reset", rank = 2)]
@@ -42,7 +48,7 @@ pub mod preflight {
     pub fn verify_update<'a>(
         session_id: String,
         config: State<Config>,
-        logger: SyncLogger,
+        logger: RequestLogger,
     ) -> RawResponse<'a> {
         info!(logger, "session_id: {}", session_id);
         no_content_for("GET,HEAD,PATCH", &config)
@@ -50,21 +56,21 @@ pub mod preflight {
 }
 
 pub mod preignition {
-    use chrono::{Duration, Utc};
-    use redis::{Commands, RedisError};
     use rocket::State;
-    use rocket::http::{Cookie, Cookies, SameSite, Status};
-    use rocket_slog::SyncLogger;
+    use rocket::http::{Cookies, Status};
 
     use crate::config::Config;
+    use crate::id::SharedIdGenerator;
+    use crate::request::csrf::CsrfToken;
+    use crate::request::logger::RequestLogger;
     use crate::response::Response;
     use crate::ss::SsConn;
-    use crate::util::generate_random_hash;
 
     #[head("/password/reset", format = "json", rank = 3)]
     pub fn request<'a>(
         config: State<Config>,
-        logger: SyncLogger,
+        ids: State<SharedIdGenerator>,
+        logger: RequestLogger,
         mut cookies: Cookies,
         mut ss_conn: SsConn,
     ) -> Response<'a> {
@@ -72,37 +78,25 @@ pub mod preignition {
         let res: Response = Default::default();
         info!(logger, "preignition");
 
-        let duration = Duration::minutes(Config::CSRF_HASH_DURATION);
-        let expires_at = (Utc::now() + duration).timestamp();
-        let key_value = generate_random_hash(
-            Config::CSRF_HASH_SOURCE,
-            Config::CSRF_HASH_LENGTH,
+        let result = CsrfToken::issue(
+            &mut cookies,
+            &mut ss_conn,
+            &config,
+            &**ids,
+            &logger,
         );
-        let key = format!("xs-{}", key_value);
-        let value = "1";
-        let result: Result<String, RedisError> = ss_conn
-            .set_ex(&key, value, expires_at as usize)
-            .map_err(|e| {
-                error!(logger, "error: {}", e);
-                e
-            });
         if result.is_ok() {
-            let mut cookie = Cookie::new("csrf_token", key);
-            cookie.set_http_only(true);
-            cookie.set_secure(config.cookie_secure);
-            cookie.set_same_site(SameSite::Strict);
-            // encrypted value with expires 1 week from now
-            cookies.add_private(cookie);
             return res.status(Status::Ok);
         }
-        error!(logger, "something went wrong on login");
+        error!(logger, "something went wrong on password reset");
         res.status(Status::InternalServerError)
     }
 
     #[head("/password/reset/<session_id>", format = "json", rank = 3)]
     pub fn update<'a>(
         config: State<Config>,
-        logger: SyncLogger,
+        ids: State<SharedIdGenerator>,
+        logger: RequestLogger,
         session_id: String,
         mut cookies: Cookies,
         mut ss_conn: SsConn,
@@ -113,65 +107,53 @@ pub mod preignition {
         let res: Response = Default::default();
         info!(logger, "preignition");
 
-        let duration = Duration::minutes(Config::CSRF_HASH_DURATION);
-        let expires_at = (Utc::now() + duration).timestamp();
-        let key_value = generate_random_hash(
-            Config::CSRF_HASH_SOURCE,
-            Config::CSRF_HASH_LENGTH,
+        let result = CsrfToken::issue(
+            &mut cookies,
+            &mut ss_conn,
+            &config,
+            &**ids,
+            &logger,
         );
-        let key = format!("xs-{}", key_value);
-        let value = "1";
-        let result: Result<String, RedisError> = ss_conn
-            .set_ex(&key, value, expires_at as usize)
-            .map_err(|e| {
-                error!(logger, "error: {}", e);
-                e
-            });
         if result.is_ok() {
-            let mut cookie = Cookie::new("csrf_token", key);
-            cookie.set_http_only(true);
-            cookie.set_secure(config.cookie_secure);
-            cookie.set_same_site(SameSite::Strict);
-            // encrypted value with expires 1 week from now
-            cookies.add_private(cookie);
             return res.status(Status::Ok);
         }
-        error!(logger, "something went wrong on login");
+        error!(logger, "something went wrong on password reset");
         res.status(Status::InternalServerError)
     }
 }
 
 #[put("/password/reset", data = "<payload>", format = "json", rank = 1)]
+#[allow(clippy::too_many_arguments)]
 pub fn request<'a>(
-    logger: SyncLogger,
-    mut cookies: Cookies,
+    _rate_limit: RateLimit<Login>,
+    csrf_token: Result<CsrfToken, CsrfTokenError>,
+    logger: RequestLogger,
     config: State<Config>,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
     mut ss_conn: SsConn,
-    mut mq_conn: MqConn,
+    mut queue: JobQueue,
     db_conn: DbConn,
-    payload: Json<PasswordResetRequest>,
+    payload: JsonBody<PasswordResetRequest, Auth>,
 ) -> Response<'a> {
     // FIXME: create `password_renewer` service
     let res: Response = Default::default();
 
-    let cookie = cookies.get_private("csrf_token").ok_or("");
-    if cookie.is_err() {
-        info!(logger, "error: missing csrf_token");
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token is required."
-        }));
+    if let Err(e) = csrf_token {
+        info!(logger, "error: {:?}", e);
+        return res.error(
+            ApiError::new(Status::Unauthorized).message(e.message()),
+        );
     }
-    let key = cookie.ok().unwrap().value().to_string();
-    let result: Result<i64, RedisError> = ss_conn.get(&key).map_err(|e| {
-        error!(logger, "error: {}", e);
-        e
-    });
-    if result.is_err() {
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token has been expired. Reload the page."
-        }));
+
+    if !auth_backend::Kind::from_config(&config).uses_local_password() {
+        return res.error(
+            ApiError::new(Status::Forbidden)
+                .message("The password is managed by the directory."),
+        );
     }
 
+    let payload = Json(payload.into_inner());
     if PasswordResetRequestValidator::new(&db_conn, &payload, &logger)
         .validate()
         .is_err()
@@ -182,12 +164,16 @@ pub fn request<'a>(
     let email = payload.0.email;
     info!(logger, "email: {}", &email);
 
+    let now = clock.now();
     if let Some(user) = User::find_by_email_only_in_available_to_reset(
-        &email, &db_conn, &logger,
+        &email,
+        now.naive_utc(),
+        &db_conn,
+        &logger,
     ) {
-        let now = Utc::now();
         let granted_at = now.timestamp();
-        let expires_at = (now + Duration::hours(1)).timestamp();
+        let duration = config.token_duration_password_reset.as_secs();
+        let expires_at = granted_at + duration as i64;
 
         let result: Result<(i64, String), Error> = db_conn
             .build_transaction()
@@ -196,7 +182,7 @@ pub fn request<'a>(
             .read_write()
             .run::<(i64, String), diesel::result::Error, _>(|| {
                 let data = TokenData {
-                    value: User::generate_password_reset_token(),
+                    value: User::generate_password_reset_token(&**ids),
                     granted_at,
                     expires_at,
                 };
@@ -223,7 +209,7 @@ pub fn request<'a>(
         if let Ok((id, raw_token)) = result {
             if let Some((token, sign)) = split_token(raw_token) {
                 // TODO: use general value
-                let session_id = User::generate_password_reset_token();
+                let session_id = User::generate_password_reset_token(&**ids);
                 let key = format!("pr-{}", session_id);
 
                 // Instead of saving the signature into a cookie,
@@ -244,8 +230,7 @@ pub fn request<'a>(
                         kind: JobKind::SendPasswordResetEmail,
                         args: vec![id.to_string(), session_id, token],
                     };
-                    let mut queue = Queue::new("default", &mut *mq_conn);
-                    if let Err(err) = queue.enqueue::<Job<String>>(job) {
+                    if let Err(err) = queue.enqueue(job) {
                         error!(logger, "error: {}", err);
                     } else {
                         return res;
@@ -253,9 +238,10 @@ pub fn request<'a>(
                 }
             }
         }
-        return res.status(Status::InternalServerError).format(json!({
-            "message": "Something wrong happen, sorry :'("
-        }));
+        return res.error(
+            ApiError::new(Status::InternalServerError)
+                .message("Something wrong happen, sorry :'("),
+        );
     }
     res.status(Status::NotFound)
 }
@@ -265,7 +251,7 @@ pub fn request<'a>(
 // https://github.com/SergioBenitez/Rocket/issues/2
 #[get("/password/reset/<session_id>", format = "json", rank = 1)]
 pub fn verify<'a>(
-    logger: SyncLogger,
+    logger: RequestLogger,
     session_id: String,
     token: VerificationToken,
 ) -> Response<'a> {
@@ -284,35 +270,26 @@ pub fn verify<'a>(
     rank = 1
 )]
 pub fn update<'a>(
-    logger: SyncLogger,
-    mut cookies: Cookies,
+    csrf_token: Result<CsrfToken, CsrfTokenError>,
+    logger: RequestLogger,
     token: VerificationToken,
     config: State<Config>,
+    clock: State<SharedClock>,
     session_id: String,
     mut ss_conn: SsConn,
-    payload: Json<PasswordResetUpdate>,
+    mut queue: JobQueue,
+    payload: JsonBody<PasswordResetUpdate, Auth>,
     db_conn: DbConn,
 ) -> Response<'a> {
     info!(logger, "session_id: {}", session_id);
 
     let res: Response = Default::default();
 
-    let cookie = cookies.get_private("csrf_token").ok_or("");
-    if cookie.is_err() {
-        info!(logger, "error: missing csrf_token");
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token is required."
-        }));
-    }
-    let key = cookie.ok().unwrap().value().to_string();
-    let result: Result<i64, RedisError> = ss_conn.get(&key).map_err(|e| {
-        error!(logger, "error: {}", e);
-        e
-    });
-    if result.is_err() {
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token has been expired. Reload the page."
-        }));
+    if let Err(e) = csrf_token {
+        info!(logger, "error: {:?}", e);
+        return res.error(
+            ApiError::new(Status::Unauthorized).message(e.message()),
+        );
     }
 
     let mut errors: Vec<ValidationError> = vec![];
@@ -329,6 +306,7 @@ pub fn update<'a>(
                     let new_password = payload.0.new_password;
                     // FIXME: can we omit this clone?
                     let user = u.target.clone().unwrap();
+                    let uuid = user.uuid.to_string();
                     let data = Json(PasswordReset {
                         username: user.username,
                         password: new_password.to_string(),
@@ -353,7 +331,7 @@ pub fn update<'a>(
                             let key = format!("pr-{}", session_id);
                             ss_conn
                                 .del(&key)
-                                .map(|r: i64| r.to_string())
+                                .map(|_: i64| uuid)
                                 .map_err(|e| {
                                     error!(logger, "error: {}", e);
                                     Error::RollbackTransaction
@@ -366,11 +344,23 @@ pub fn update<'a>(
         });
 
     match result {
-        Ok(_) => res.status(Status::Ok),
+        Ok(uuid) => {
+            let job = Job::<String> {
+                kind: JobKind::SendSecurityNotificationEmail,
+                args: vec![
+                    uuid,
+                    SecurityNotice::PasswordChanged.as_str().to_string(),
+                    clock.now().to_rfc3339(),
+                ],
+            };
+            // the password has been changed anyway
+            if let Err(e) = queue.enqueue(job) {
+                error!(logger, "error: {}", e);
+            }
+            res.status(Status::Ok)
+        },
         Err(_) if !errors.is_empty() => {
-            res.status(Status::UnprocessableEntity).format(json!({
-                "errors": errors,
-            }))
+            res.error(ApiError::invalid(errors))
         },
         _ => res.status(Status::NotFound),
     }
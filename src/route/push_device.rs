@@ -0,0 +1,213 @@
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+
+use crate::db::DbConn;
+use crate::model::push_delivery::PushDelivery;
+use crate::model::push_device::{NewPushDevice, PushDevice, PushPlatform};
+use crate::model::user::User;
+use crate::request::logger::RequestLogger;
+use crate::request::push_device::PushDevice as RequestData;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::response::{ApiError, Response};
+use crate::validation::push_device::Validator;
+
+// the recent receipts in the response
+const DELIVERIES_LIMIT: i64 = 50;
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/push_device/hgetall", rank = 2)]
+    pub fn hgetall<'a>(
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "hgetall");
+        no_content_for("GET", &config)
+    }
+
+    #[options("/push_device/append", rank = 2)]
+    pub fn append<'a>(
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "append");
+        no_content_for("POST", &config)
+    }
+
+    #[options("/push_device/hget/<uuid>/deliveries", rank = 2)]
+    pub fn deliveries<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "uuid: {}", uuid);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/push_device/del/<uuid>", rank = 2)]
+    pub fn del<'a>(
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "uuid: {}", uuid);
+        no_content_for("DELETE", &config)
+    }
+}
+
+// the token is not returned (it's known only by the device)
+fn format_push_device(d: &PushDevice) -> JsonValue {
+    json!({"push_device": {
+        "uuid": d.uuid.to_string(),
+        "platform": d.platform.to_string(),
+        "name": d.name,
+        "last_delivered_at": d.last_delivered_at,
+        "created_at": d.created_at,
+        "updated_at": d.updated_at,
+    }})
+}
+
+// Returns the devices of the user which receive push notifications.
+#[get("/push_device/hgetall", rank = 1)]
+pub fn hgetall(
+    _rate_limit: RateLimit<Api>,
+    user: &User,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}", user.uuid);
+
+    let data: Vec<JsonValue> =
+        PushDevice::find_all_by_user_id(user.id, &conn, &logger)
+            .unwrap_or_else(Vec::new)
+            .iter()
+            .map(format_push_device)
+            .collect();
+    res.format(json!(data))
+}
+
+// Registers the device of the user for push notifications of critical
+// alerts. A token which is already registered is updated (also by another
+// user).
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "platform": "apns",
+//    "token": "740f4707bebcf74f9b7c25d48e3358945f6aa01da5ddb387462c7eaf6",
+//    "name": "iPhone"
+// }
+// ```
+#[post("/push_device/append", data = "<data>", format = "json", rank = 1)]
+pub fn append(
+    _rate_limit: RateLimit<Api>,
+    user: &User,
+    data: Json<RequestData>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}", user.uuid);
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let platform = data.0.platform.as_ref().unwrap().trim();
+    let new_device = NewPushDevice {
+        user_id: user.id,
+        platform: PushPlatform::from_name(platform).unwrap(),
+        token: data.0.token.as_ref().unwrap().trim().to_string(),
+        name: data.0.name.as_ref().unwrap().trim().to_string(),
+    };
+    match PushDevice::upsert(&new_device, &conn, &logger) {
+        Some(d) => res.format(format_push_device(&d)),
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Returns the recent receipts of push notifications to the device.
+#[get("/push_device/hget/<uuid>/deliveries", rank = 1)]
+pub fn deliveries(
+    _rate_limit: RateLimit<Api>,
+    user: &User,
+    uuid: String,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let device = match PushDevice::find_by_user_id_and_uuid(
+        user.id,
+        &uuid,
+        &conn,
+        &logger,
+    ) {
+        Some(d) => d,
+        None => return res.status(Status::NotFound),
+    };
+
+    let data: Vec<JsonValue> = PushDelivery::find_all_by_push_device_id(
+        device.id,
+        DELIVERIES_LIMIT,
+        &conn,
+        &logger,
+    )
+    .unwrap_or_else(Vec::new)
+    .iter()
+    .map(|d| {
+        json!({
+            "message_id": d.message_id,
+            "delivered": d.delivered,
+            "provider_id": d.provider_id,
+            "error": d.error,
+            "created_at": d.created_at,
+        })
+    })
+    .collect();
+    res.format(json!({"deliveries": data}))
+}
+
+// Unregisters the device of the user. Its receipts are also deleted.
+#[delete("/push_device/del/<uuid>", rank = 1)]
+pub fn del(
+    _rate_limit: RateLimit<Api>,
+    user: &User,
+    uuid: String,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}, uuid: {}", user.uuid, uuid);
+
+    let device = match PushDevice::find_by_user_id_and_uuid(
+        user.id,
+        &uuid,
+        &conn,
+        &logger,
+    ) {
+        Some(d) => d,
+        None => return res.status(Status::NotFound),
+    };
+
+    match device.delete(&conn, &logger) {
+        Ok(_) => res.format(json!({"push_device": {
+            "uuid": device.uuid.to_string(),
+        }})),
+        Err(_) => res.status(Status::InternalServerError),
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use rocket::State;
+
+use crate::config::Config;
+use crate::db::DbReadConn;
+use crate::model::recent_view::{RecentView, RecentViewKind};
+use crate::model::user::User;
+use crate::request::logger::RequestLogger;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::request::recent_view::ViewTracker;
+use crate::response::Response;
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/recent_view/lrange/<start>/<stop>", rank = 2)]
+    pub fn lrange<'a>(
+        start: i64,
+        stop: i64,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "start: {}, stop: {}", start, stop);
+        no_content_for("GET", &config)
+    }
+}
+
+// merges views in the store which are not flushed yet into saved ones, and
+// returns them (the latest first)
+fn merge(
+    saved: Vec<RecentView>,
+    pending: Vec<(RecentViewKind, String, NaiveDateTime)>,
+) -> Vec<(RecentViewKind, String, NaiveDateTime)> {
+    let mut views: HashMap<(String, String), NaiveDateTime> = HashMap::new();
+    let entries = saved
+        .into_iter()
+        .map(|v| (v.kind, v.target, v.viewed_at))
+        .chain(pending.into_iter());
+    for (kind, target, viewed_at) in entries {
+        let t = views.entry((kind.to_string(), target)).or_insert(viewed_at);
+        if *t < viewed_at {
+            *t = viewed_at;
+        }
+    }
+
+    let mut result: Vec<(RecentViewKind, String, NaiveDateTime)> = views
+        .into_iter()
+        .filter_map(|((kind, target), viewed_at)| {
+            Some((RecentViewKind::from_name(&kind)?, target, viewed_at))
+        })
+        .collect();
+    result.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.1.cmp(&b.1)));
+    result
+}
+
+// Returns messages and searches which the user opened recently.
+//
+// The target is `<namespace_key>/<stream_slug>/<id>` for a message, and
+// `<namespace_key>/<stream_slug>/<q>` for a search.
+//
+// The value looks like this:
+//
+// ```json
+// [{
+//    "recent_view": {
+//      "kind": "search",
+//      "target": "...",
+//      "viewed_at": "2021-06-19T09:00:00"
+//    }
+// }]
+// ```
+#[get("/recent_view/lrange/<start>/<stop>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn lrange<'a>(
+    _rate_limit: RateLimit<Api>,
+    start: i64,
+    stop: i64,
+    user: &User,
+    mut tracker: ViewTracker,
+    conn: DbReadConn,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    info!(logger, "user: {}, start: {}, stop: {}", user.uuid, start, stop);
+
+    let res: Response = Default::default();
+
+    let limit = config.recent_view_limit as i64;
+    let saved =
+        RecentView::find_all_by_user_id(user.id, 0, limit, &conn, &logger)
+            .unwrap_or_else(|| {
+                error!(logger, "err: not found user.id {}", user.uuid);
+                vec![]
+            });
+    let pending = tracker.pending(user);
+
+    let offset = start.max(0) as usize;
+    let count = (stop - start + 1).max(1) as usize;
+    let data: Vec<_> = merge(saved, pending)
+        .into_iter()
+        .take(limit.max(0) as usize)
+        .skip(offset)
+        .take(count)
+        .map(|(kind, target, viewed_at)| {
+            json!({
+                "recent_view": {
+                    "kind": kind.to_string(),
+                    "target": target,
+                    "viewed_at": viewed_at,
+                }
+            })
+        })
+        .collect();
+    res.format(json!(data))
+}
+
@@ -1,24 +1,33 @@
-use chrono::{Duration, Utc};
 use diesel::result::Error;
-use fourche::queue::Queue;
 use redis::{Commands, RedisError};
 use rocket::State;
-use rocket::http::{Cookies, Status};
+use rocket::http::Status;
 use rocket_contrib::json::Json;
-use rocket_slog::SyncLogger;
 
+use crate::clock::SharedClock;
 use crate::config::Config;
 use crate::db::DbConn;
+use crate::id::SharedIdGenerator;
 use crate::job::{Job, JobKind};
 use crate::model::token::{VerificationClaims, Claims, TokenData};
 use crate::model::namespace::{Namespace, NewNamespace};
+use crate::model::password_hash::PasswordHashParams;
 use crate::model::membership::{Membership, MembershipRole, NewMembership};
 use crate::model::stream::{Stream, NewStream};
 use crate::model::user::{NewUser, User};
 use crate::model::user_email::{NewUserEmail, UserEmail};
-use crate::mq::MqConn;
-use crate::response::Response;
+use crate::mq::JobQueue;
+use crate::request::body_limit::Auth;
+use crate::request::csrf::{CsrfToken, CsrfTokenError};
+use crate::request::json::JsonBody;
+use crate::request::logger::RequestLogger;
+use crate::request::rate_limit::{Login, RateLimit};
+use crate::response::{ApiError, Response};
 use crate::request::user::registration::UserRegistration;
+use crate::service::auth_backend;
+use crate::service::email_suggester::EmailSuggester;
+use crate::service::mx_checker::{Deliverability, MxChecker};
+use crate::validation::email::{EmailPolicy, normalize};
 use crate::validation::user::Validator;
 use crate::ss::SsConn;
 use crate::util::split_token;
@@ -42,49 +51,36 @@ pub mod preflight {
 }
 
 pub mod preignition {
-    use chrono::{Duration, Utc};
-    use redis::{Commands, RedisError};
     use rocket::State;
-    use rocket::http::{Cookie, Cookies, SameSite, Status};
-    use rocket_slog::SyncLogger;
+    use rocket::http::{Cookies, Status};
 
     use crate::config::Config;
+    use crate::id::SharedIdGenerator;
+    use crate::request::csrf::CsrfToken;
+    use crate::request::logger::RequestLogger;
     use crate::response::Response;
     use crate::ss::SsConn;
-    use crate::util::generate_random_hash;
 
     #[head("/register", format = "json", rank = 3)]
     pub fn register<'a>(
         config: State<Config>,
+        ids: State<SharedIdGenerator>,
         mut cookies: Cookies,
-        logger: SyncLogger,
+        logger: RequestLogger,
         mut ss_conn: SsConn,
     ) -> Response<'a> {
         // returns CSRF token
         let res: Response = Default::default();
         info!(logger, "preignition");
 
-        let duration = Duration::minutes(Config::CSRF_HASH_DURATION);
-        let expires_at = (Utc::now() + duration).timestamp();
-        let key_value = generate_random_hash(
-            Config::CSRF_HASH_SOURCE,
-            Config::CSRF_HASH_LENGTH,
+        let result = CsrfToken::issue(
+            &mut cookies,
+            &mut ss_conn,
+            &config,
+            &**ids,
+            &logger,
         );
-        let key = format!("xs-{}", key_value);
-        let value = "1";
-        let result: Result<String, RedisError> = ss_conn
-            .set_ex(&key, value, expires_at as usize)
-            .map_err(|e| {
-                error!(logger, "error: {}", e);
-                e
-            });
         if result.is_ok() {
-            let mut cookie = Cookie::new("csrf_token", key);
-            cookie.set_http_only(true);
-            cookie.set_secure(config.cookie_secure);
-            cookie.set_same_site(SameSite::Strict);
-            // encrypted value with expires 1 week from now
-            cookies.add_private(cookie);
             return res.status(Status::Ok);
         }
         error!(logger, "something went wrong on register");
@@ -93,50 +89,70 @@ pub mod preignition {
 }
 
 #[post("/register", data = "<data>", format = "json", rank = 1)]
+#[allow(clippy::too_many_arguments)]
 pub fn register<'a>(
-    data: Json<UserRegistration>,
-    mut cookies: Cookies,
+    _rate_limit: RateLimit<Login>,
+    csrf_token: Result<CsrfToken, CsrfTokenError>,
+    data: JsonBody<UserRegistration, Auth>,
     db_conn: DbConn,
-    mut mq_conn: MqConn,
+    mut queue: JobQueue,
     mut ss_conn: SsConn,
-    logger: SyncLogger,
+    logger: RequestLogger,
     config: State<Config>,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
 ) -> Response<'a> {
     // FIXME: create `account_registrar` service
     let res: Response = Default::default();
 
-    let cookie = cookies.get_private("csrf_token").ok_or("");
-    if cookie.is_err() {
-        info!(logger, "error: missing csrf_token");
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token is required."
-        }));
+    if let Err(e) = csrf_token {
+        info!(logger, "error: {:?}", e);
+        return res.error(
+            ApiError::new(Status::Unauthorized).message(e.message()),
+        );
     }
-    let key = cookie.ok().unwrap().value().to_string();
-    let result: Result<i64, RedisError> = ss_conn.get(&key).map_err(|e| {
-        error!(logger, "error: {}", e);
-        e
-    });
-    if result.is_err() {
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token has been expired. Reload the page."
-        }));
+
+    if !auth_backend::Kind::from_config(&config).uses_local_password() {
+        return res.error(
+            ApiError::new(Status::Forbidden)
+                .message("The password is managed by the directory."),
+        );
     }
 
-    let v = Validator::new(&db_conn, &data, &logger);
+    let policy = EmailPolicy::from(&*config);
+    let mut data = Json(data.into_inner());
+    data.email = normalize(&data.email, &policy);
+
+    let v = Validator::new(&db_conn, &data, &policy, &logger);
     match v.validate() {
         Err(errors) => {
-            res.status(Status::UnprocessableEntity).format(json!({
-                "errors": errors,
-            }))
+            res.error(ApiError::invalid(errors))
         },
         Ok(_) => {
+            // an obviously undeliverable address is rejected before the
+            // activation email (see `service::mx_checker`)
+            if let Some(checker) = MxChecker::from_config(&config, &logger) {
+                if checker.check(&data.email, &mut ss_conn) ==
+                    Deliverability::Undeliverable
+                {
+                    return res.error(
+                        ApiError::new(Status::UnprocessableEntity)
+                            .code("undeliverable_email")
+                            .field(
+                                "email",
+                                "Must be a deliverable email address",
+                            ),
+                    );
+                }
+            }
+
             // TODO:
             // impl service object handles token generation/activation.
             // see also login
-            let now = Utc::now();
+            let now = clock.now();
             let granted_at = now.timestamp();
-            let expires_at = (now + Duration::hours(1)).timestamp();
+            let duration = config.token_duration_user_activation.as_secs();
+            let expires_at = granted_at + duration as i64;
 
             let result: Result<(i64, String), Error> = db_conn
                 .build_transaction()
@@ -145,7 +161,10 @@ pub fn register<'a>(
                 .read_write()
                 .run::<(i64, String), diesel::result::Error, _>(|| {
                     let mut u = NewUser::from(&data.0);
-                    u.set_password(&data.password);
+                    u.set_password(
+                        &data.password,
+                        &PasswordHashParams::from(&*config),
+                    );
                     let user = User::insert(&u, &db_conn, &logger).unwrap();
                     let ue = NewUserEmail::from(&user);
                     let user_email =
@@ -178,7 +197,7 @@ pub fn register<'a>(
                     }
 
                     let data = TokenData {
-                        value: UserEmail::generate_token(),
+                        value: UserEmail::generate_token(&**ids),
                         granted_at,
                         expires_at,
                     };
@@ -207,7 +226,7 @@ pub fn register<'a>(
             if let Ok((id, raw_token)) = result {
                 if let Some((token, sign)) = split_token(raw_token) {
                     // TODO: use general value
-                    let session_id = UserEmail::generate_token();
+                    let session_id = UserEmail::generate_token(&**ids);
                     let key = format!("ua-{}", session_id);
 
                     // Instead of saving the signature into a cookie,
@@ -228,49 +247,45 @@ pub fn register<'a>(
                             kind: JobKind::SendUserActivationEmail,
                             args: vec![id.to_string(), session_id, token],
                         };
-                        let mut queue = Queue::new("default", &mut *mq_conn);
-                        if let Err(err) = queue.enqueue::<Job<String>>(job) {
+                        if let Err(err) = queue.enqueue(job) {
                             error!(logger, "error: {}", err);
                         } else {
-                            return res;
+                            // non-blocking hint for a likely typo
+                            let suggestion = EmailSuggester::new(
+                                &config.email_suggestion_domains,
+                                config.email_suggestion_distance,
+                            )
+                            .suggest(&data.email);
+                            return res.format(json!({
+                                "suggestion": suggestion,
+                            }));
                         }
                     }
                 }
             }
-            res.status(Status::InternalServerError).format(json!({
-                "message": "Something wrong happen, sorry :'("
-            }))
+            res.error(
+                ApiError::new(Status::InternalServerError)
+                    .message("Something wrong happen, sorry :'("),
+            )
         },
     }
 }
 
 #[post("/deregister", format = "json", rank = 1)]
 pub fn deregister<'a>(
-    mut cookies: Cookies,
+    csrf_token: Result<CsrfToken, CsrfTokenError>,
     user: &User,
-    mut ss_conn: SsConn,
-    logger: SyncLogger,
+    logger: RequestLogger,
 ) -> Response<'a> {
     let res: Response = Default::default();
 
     info!(logger, "user: {}", user.uuid);
 
-    let cookie = cookies.get_private("csrf_token").ok_or("");
-    if cookie.is_err() {
-        info!(logger, "error: missing csrf_token");
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token is required."
-        }));
-    }
-    let key = cookie.ok().unwrap().value().to_string();
-    let result: Result<i64, RedisError> = ss_conn.get(&key).map_err(|e| {
-        error!(logger, "error: {}", e);
-        e
-    });
-    if result.is_err() {
-        return res.status(Status::Unauthorized).format(json!({
-            "message": "The CSRF token has been expired. Reload the page."
-        }));
+    if let Err(e) = csrf_token {
+        info!(logger, "error: {:?}", e);
+        return res.error(
+            ApiError::new(Status::Unauthorized).message(e.message()),
+        );
     }
 
     // TODO
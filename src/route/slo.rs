@@ -0,0 +1,50 @@
+//! A local-only endpoint for the burn rates of the error budgets (see
+//! `service::slo`), for operators and their monitoring.
+use rocket::State;
+use rocket::http::Status;
+
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::request::local_only::LocalOnly;
+use crate::request::logger::RequestLogger;
+use crate::response::Response;
+use crate::service::slo::{Slo, is_burning};
+use crate::ss::SsPoolHolder;
+
+/// Returns the SLOs and the burn rates over the windows until now. It responds
+/// with 404 if the request metrics are disabled.
+#[get("/slo", rank = 1)]
+pub fn burn_rates<'a>(
+    _local_only: LocalOnly,
+    ss_holder: State<SsPoolHolder>,
+    clock: State<SharedClock>,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let slo = Slo::new(&config, &logger);
+    if !slo.is_enabled() {
+        return res.status(Status::NotFound);
+    }
+    let mut ss_conn = match ss_holder.get() {
+        Some(c) => c,
+        None => return res.status(Status::ServiceUnavailable),
+    };
+
+    let minute = clock.now().timestamp() / 60;
+    match slo.burn_rates(&mut *ss_conn, minute) {
+        Ok(rates) => res.format(json!({"slo": {
+            "availability_target": config.slo_availability_target,
+            "latency_target": config.slo_latency_target,
+            "latency_threshold": config.slo_latency_threshold,
+            "alert_burn_rate": config.slo_alert_burn_rate,
+            "burning": is_burning(&rates, config.slo_alert_burn_rate),
+            "burn_rates": rates,
+        }})),
+        Err(e) => {
+            error!(logger, "err: {}", e);
+            res.status(Status::InternalServerError)
+        },
+    }
+}
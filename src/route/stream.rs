@@ -0,0 +1,86 @@
+//! Local-only endpoints for the buffers of ingested messages (see
+//! `service::stream_buffer`), for operators to check the durability window.
+use rocket::State;
+use rocket::http::Status;
+
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::db::DbConn;
+use crate::model::stream::Stream;
+use crate::request::local_only::LocalOnly;
+use crate::request::logger::RequestLogger;
+use crate::response::Response;
+use crate::service::stream_buffer::StreamBuffer;
+use crate::ss::SsPoolHolder;
+
+/// Returns the number of the pending messages in the buffer of the stream and
+/// the age of the oldest one (in seconds).
+#[get("/stream/<uuid>/buffer", rank = 1)]
+pub fn buffer<'a>(
+    _local_only: LocalOnly,
+    uuid: String,
+    conn: DbConn,
+    ss_holder: State<SsPoolHolder>,
+    clock: State<SharedClock>,
+    config: State<Config>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let stream = match Stream::find_by_uuid(&uuid, &conn, &logger) {
+        Some(s) => s,
+        None => return res.status(Status::NotFound),
+    };
+    let mut ss_conn = match ss_holder.get() {
+        Some(c) => c,
+        None => return res.status(Status::ServiceUnavailable),
+    };
+
+    let now = clock.now().naive_utc();
+    match StreamBuffer::new(&mut *ss_conn, &logger).state(stream.id) {
+        Ok(state) => res.format(json!({"buffer": {
+            "enabled": config.stream_buffer_flush_interval > 0,
+            "pending": state.pending,
+            "oldest_created_at": state.oldest_created_at,
+            "oldest_age": state.oldest_age(now),
+        }})),
+        Err(_) => res.status(Status::InternalServerError),
+    }
+}
+
+/// Saves the pending messages in the buffer of the stream now. It responds
+/// with 409 if the buffer is being flushed (e.g. by the job).
+#[post("/stream/<uuid>/flush", rank = 1)]
+pub fn flush<'a>(
+    _local_only: LocalOnly,
+    uuid: String,
+    conn: DbConn,
+    ss_holder: State<SsPoolHolder>,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let stream = match Stream::find_by_uuid(&uuid, &conn, &logger) {
+        Some(s) => s,
+        None => return res.status(Status::NotFound),
+    };
+    let mut ss_conn = match ss_holder.get() {
+        Some(c) => c,
+        None => return res.status(Status::ServiceUnavailable),
+    };
+
+    let mut buffer = StreamBuffer::new(&mut *ss_conn, &logger);
+    let flushed = match buffer.flush(stream.id, &**clock, &conn) {
+        Ok(n) => n,
+        Err("flush in progress") => return res.status(Status::Conflict),
+        Err(_) => return res.status(Status::InternalServerError),
+    };
+    match buffer.state(stream.id) {
+        Ok(state) => res.format(json!({"buffer": {
+            "flushed": flushed,
+            "pending": state.pending,
+        }})),
+        Err(_) => res.status(Status::InternalServerError),
+    }
+}
@@ -0,0 +1,292 @@
+use rocket::State;
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+
+use crate::clock::SharedClock;
+use crate::db::DbConn;
+use crate::id::SharedIdGenerator;
+use crate::model::stream_token::{NewStreamToken, StreamToken};
+use crate::model::user::User;
+use crate::request::logger::RequestLogger;
+use crate::request::pagination::Page;
+use crate::response::{ApiError, Paginated, Response};
+use crate::request::quota::ApiCallCount;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::request::scope::{NamespaceAdmin, Scoped};
+use crate::request::stream_token::StreamToken as RequestData;
+use crate::route::namespace::find_owned_namespace;
+use crate::validation::stream_token::Validator;
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/stream_token/<namespace_key>/hgetall", rank = 2)]
+    pub fn hgetall<'a>(
+        namespace_key: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}", namespace_key);
+        no_content_for("GET", &config)
+    }
+
+    #[options("/stream_token/<namespace_key>/append", rank = 2)]
+    pub fn append<'a>(
+        namespace_key: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}", namespace_key);
+        no_content_for("POST", &config)
+    }
+
+    #[options("/stream_token/<namespace_key>/dump/<uuid>", rank = 2)]
+    pub fn dump<'a>(
+        namespace_key: String,
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}, uuid: {}", namespace_key, uuid);
+        no_content_for("PATCH", &config)
+    }
+
+    #[options("/stream_token/<namespace_key>/del/<uuid>", rank = 2)]
+    pub fn del<'a>(
+        namespace_key: String,
+        uuid: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        info!(logger, "namespace: {}, uuid: {}", namespace_key, uuid);
+        no_content_for("DELETE", &config)
+    }
+}
+
+// the raw value is given only when it's issued (or rotated)
+fn format_stream_token(t: &StreamToken, raw: Option<&str>) -> JsonValue {
+    json!({"stream_token": {
+        "uuid": t.uuid.to_string(),
+        "name": t.name,
+        "token": raw,
+        "hostname": t.hostname,
+        "service": t.service,
+        "environment": t.environment,
+        "created_at": t.created_at,
+        "updated_at": t.updated_at,
+    }})
+}
+
+// Returns a page of stream tokens of the namespace (only for owners).
+#[get("/stream_token/<namespace_key>/hgetall?<cursor>&<limit>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn hgetall<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    namespace_key: String,
+    cursor: Option<String>,
+    limit: Option<i64>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Result<Paginated<'a, JsonValue>, Response<'a>> {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, cursor: {:?}, limit: {:?}",
+        user.uuid,
+        namespace_key,
+        cursor,
+        limit
+    );
+
+    let page = match Page::default().query(cursor.as_deref(), limit) {
+        Ok(p) => p,
+        Err(e) => {
+            return Err(res.error(ApiError::invalid(vec![e])));
+        },
+    };
+
+    let namespace =
+        match find_owned_namespace(&namespace_key, user, &conn, &logger) {
+            Ok(n) => n,
+            Err(status) => return Err(res.status(status)),
+        };
+
+    let tokens =
+        StreamToken::find_all_by_namespace_id(namespace.id, &conn, &logger)
+            .unwrap_or_else(Vec::new);
+    let total = tokens.len() as i64;
+    let (tokens, next_cursor) = page.slice(tokens);
+    let data: Vec<JsonValue> = tokens
+        .iter()
+        .map(|t| format_stream_token(t, None))
+        .collect();
+    Ok(res.paginate(data, next_cursor).total_estimate(total))
+}
+
+// Issues a new stream token (only for owners). The raw value is in the
+// response only (it's not saved).
+//
+// The value looks like this (the source is the default one of the messages
+// which are ingested with the token, and it's optional):
+//
+// ```json
+// {
+//    "name": "fluent-bit",
+//    "hostname": "web-1",
+//    "service": "api",
+//    "environment": "production"
+// }
+// ```
+#[post(
+    "/stream_token/<namespace_key>/append",
+    data = "<data>",
+    format = "json",
+    rank = 1
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn append<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    namespace_key: String,
+    data: Json<RequestData>,
+    conn: DbConn,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}, namespace: {}", user.uuid, namespace_key);
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let namespace =
+        match find_owned_namespace(&namespace_key, user, &conn, &logger) {
+            Ok(n) => n,
+            Err(status) => return res.status(status),
+        };
+
+    // blank ones are same as omitted
+    let source = |v: &Option<String>| {
+        v.as_ref()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+    let new_stream_token = NewStreamToken {
+        namespace_id: namespace.id,
+        name: data.0.name.as_ref().unwrap().trim().to_string(),
+        hostname: source(&data.0.hostname),
+        service: source(&data.0.service),
+        environment: source(&data.0.environment),
+    };
+    let raw = StreamToken::generate_token(&**ids);
+    match StreamToken::insert(&new_stream_token, &raw, &conn, &logger) {
+        Some(t) => res.format(format_stream_token(&t, Some(&raw))),
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Rotates the stream token (only for owners). The old value is invalidated
+// at once.
+#[patch("/stream_token/<namespace_key>/dump/<uuid>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn dump<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    namespace_key: String,
+    uuid: String,
+    conn: DbConn,
+    clock: State<SharedClock>,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, uuid: {}", user.uuid, namespace_key, uuid
+    );
+
+    let namespace =
+        match find_owned_namespace(&namespace_key, user, &conn, &logger) {
+            Ok(n) => n,
+            Err(status) => return res.status(status),
+        };
+    let stream_token = match StreamToken::find_by_namespace_id_and_uuid(
+        namespace.id,
+        &uuid,
+        &conn,
+        &logger,
+    ) {
+        Some(t) => t,
+        None => return res.status(Status::NotFound),
+    };
+
+    let raw = StreamToken::generate_token(&**ids);
+    let now = clock.now().naive_utc();
+    match stream_token.rotate(&raw, now, &conn, &logger) {
+        Ok(t) => res.format(format_stream_token(&t, Some(&raw))),
+        Err(_) => res.status(Status::InternalServerError),
+    }
+}
+
+// Revokes the stream token (only for owners).
+#[delete("/stream_token/<namespace_key>/del/<uuid>", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn del<'a>(
+    _rate_limit: RateLimit<Api>,
+    _api_call: ApiCallCount,
+    user: &User,
+    _scope: Scoped<NamespaceAdmin>,
+    namespace_key: String,
+    uuid: String,
+    conn: DbConn,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    info!(
+        logger,
+        "user: {}, namespace: {}, uuid: {}", user.uuid, namespace_key, uuid
+    );
+
+    let namespace =
+        match find_owned_namespace(&namespace_key, user, &conn, &logger) {
+            Ok(n) => n,
+            Err(status) => return res.status(status),
+        };
+    let stream_token = match StreamToken::find_by_namespace_id_and_uuid(
+        namespace.id,
+        &uuid,
+        &conn,
+        &logger,
+    ) {
+        Some(t) => t,
+        None => return res.status(Status::NotFound),
+    };
+
+    let now = clock.now().naive_utc();
+    match stream_token.revoke(now, &conn, &logger) {
+        Ok(t) => res.format(json!({"stream_token": {
+            "uuid": t.uuid.to_string(),
+            "revoked_at": t.revoked_at,
+        }})),
+        Err(_) => res.status(Status::InternalServerError),
+    }
+}
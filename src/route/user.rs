@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+
+use crate::db::DbConn;
+use crate::model::notification_preference::{
+    NewNotificationPreference, NotificationChannel, NotificationEvent,
+    NotificationPreference,
+};
+use crate::model::user::User;
+use crate::request::logger::RequestLogger;
+use crate::request::rate_limit::{Api, RateLimit};
+use crate::request::user::notification_preference::NotificationPreferences as RequestData;
+use crate::response::{ApiError, Response};
+use crate::validation::notification_preference::Validator;
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::response::no_content_for;
+
+    #[options("/user/notifications", rank = 2)]
+    pub fn notifications<'a>(config: State<Config>) -> RawResponse<'a> {
+        no_content_for("GET,PATCH", &config)
+    }
+}
+
+// all of the events (email for the ones without the preference)
+fn format_notification_preferences(
+    preferences: &[NotificationPreference],
+) -> JsonValue {
+    let mut data = BTreeMap::new();
+    for event in NotificationEvent::iter() {
+        let p = preferences.iter().find(|p| p.event == *event);
+        let channel = p.map_or(NotificationChannel::Email, |p| p.channel);
+        data.insert(
+            event.to_string(),
+            serde_json::json!({
+                "channel": channel.to_string(),
+                "webhook_url": p.and_then(|p| p.webhook_url.as_ref()),
+            }),
+        );
+    }
+    json!({ "notifications": data })
+}
+
+// Returns the channels of notifications of the user by the event.
+#[get("/user/notifications", rank = 1)]
+pub fn hget_notifications(
+    _rate_limit: RateLimit<Api>,
+    user: &User,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}", user.uuid);
+
+    match NotificationPreference::find_all_by_user_id(user.id, &conn, &logger) {
+        Some(preferences) => {
+            res.format(format_notification_preferences(&preferences))
+        },
+        None => res.status(Status::InternalServerError),
+    }
+}
+
+// Changes the channels of notifications of the user on the given events
+// (the others are kept). The critical security notices are always sent by
+// email, even if the channel is `none`.
+//
+// The value looks like this:
+//
+// ```json
+// {
+//    "alert": {
+//      "channel": "webhook",
+//      "webhook_url": "https://example.org/hook"
+//    },
+//    "security": {
+//      "channel": "none"
+//    }
+// }
+// ```
+#[patch("/user/notifications", data = "<data>", format = "json", rank = 1)]
+pub fn hset_notifications(
+    _rate_limit: RateLimit<Api>,
+    user: &User,
+    data: Json<RequestData>,
+    conn: DbConn,
+    logger: RequestLogger,
+) -> Response {
+    let res: Response = Default::default();
+
+    info!(logger, "user: {}", user.uuid);
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let saved = data.0.iter().all(|(event, p)| {
+        // they are valid
+        let channel = p
+            .channel
+            .as_ref()
+            .and_then(|c| NotificationChannel::from_name(c.trim()))
+            .unwrap();
+        let webhook_url = match channel {
+            NotificationChannel::Webhook => {
+                p.webhook_url.as_ref().map(|s| s.trim().to_string())
+            },
+            _ => None,
+        };
+        let preference = NewNotificationPreference {
+            user_id: user.id,
+            event: NotificationEvent::from_name(event).unwrap(),
+            channel,
+            webhook_url,
+        };
+        NotificationPreference::upsert(&preference, &conn, &logger).is_some()
+    });
+    if !saved {
+        return res.status(Status::InternalServerError);
+    }
+
+    match NotificationPreference::find_all_by_user_id(user.id, &conn, &logger) {
+        Some(preferences) => {
+            res.format(format_notification_preferences(&preferences))
+        },
+        None => res.status(Status::InternalServerError),
+    }
+}
@@ -0,0 +1,164 @@
+use redis::{Commands, RedisError};
+use rocket::State;
+use rocket::http::Status;
+use rocket_contrib::json::Json;
+
+use crate::clock::SharedClock;
+use crate::db::DbConn;
+use crate::id::SharedIdGenerator;
+use crate::job::{Job, JobKind};
+use crate::model::waitlist_entry::{NewWaitlistEntry, WaitlistEntry};
+use crate::mq::JobQueue;
+use crate::request::logger::RequestLogger;
+use crate::request::rate_limit::{RateLimit, Waitlist};
+use crate::request::waitlist::WaitlistEntry as RequestData;
+use crate::response::{ApiError, Response};
+use crate::ss::SsConn;
+use crate::validation::waitlist::Validator;
+
+const CONFIRMATION_DURATION: usize = 86_400; // 1 day (seconds)
+const RESEND_INTERVAL: usize = 600; // 10 minutes (seconds)
+
+pub mod preflight {
+    use rocket::State;
+    use rocket::response::Response as RawResponse;
+
+    use crate::config::Config;
+    use crate::request::logger::RequestLogger;
+    use crate::response::no_content_for;
+
+    #[options("/waitlist", rank = 2)]
+    pub fn join<'a>(config: State<Config>) -> RawResponse<'a> {
+        no_content_for("POST", &config)
+    }
+
+    #[options("/waitlist/confirm/<token>", rank = 2)]
+    pub fn confirm<'a>(
+        token: String,
+        config: State<Config>,
+        logger: RequestLogger,
+    ) -> RawResponse<'a> {
+        // only a part of the token
+        let t: String = token.chars().take(6).collect();
+        info!(logger, "token: {}...", t);
+        no_content_for("PATCH", &config)
+    }
+}
+
+// Captures an email for the waitlist (unauthenticated).
+//
+// The response is same for an email which has been already registered, and
+// for the one whose confirmation email has been sent within the interval.
+#[post("/waitlist", data = "<data>", format = "json", rank = 1)]
+#[allow(clippy::too_many_arguments)]
+pub fn join<'a>(
+    _rate_limit: RateLimit<Waitlist>,
+    data: Json<RequestData>,
+    db_conn: DbConn,
+    mut queue: JobQueue,
+    mut ss_conn: SsConn,
+    ids: State<SharedIdGenerator>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let v = Validator::new(&data, &logger);
+    if let Err(errors) = v.validate() {
+        return res.error(ApiError::invalid(errors));
+    }
+
+    let entry = NewWaitlistEntry::from(data.0);
+    let entry = match WaitlistEntry::insert(&entry, &db_conn, &logger) {
+        Some(e) => e,
+        None => return res.status(Status::InternalServerError),
+    };
+
+    let done = json!({
+        "message": "Check your inbox to confirm the email address."
+    });
+    if entry.is_confirmed() {
+        info!(logger, "already confirmed: {}", entry);
+        return res.format(done);
+    }
+
+    // a confirmation email per address in the interval
+    let key = format!("wl-sent-{}", entry.id);
+    let result: Result<Option<String>, RedisError> = redis::cmd("SET")
+        .arg(&key)
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(RESEND_INTERVAL)
+        .query(&mut *ss_conn)
+        .map_err(|e| {
+            error!(logger, "error: {}", e);
+            e
+        });
+    match result {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            info!(logger, "already sent: {}", entry);
+            return res.format(done);
+        },
+        Err(_) => return res.status(Status::InternalServerError),
+    }
+
+    let token = WaitlistEntry::generate_token(&**ids);
+    let key = format!("wl-{}", token);
+    let result: Result<String, RedisError> = ss_conn
+        .set_ex(&key, entry.id, CONFIRMATION_DURATION)
+        .map_err(|e| {
+            error!(logger, "error: {}", e);
+            e
+        });
+    if result.is_err() {
+        return res.status(Status::InternalServerError);
+    }
+
+    let job = Job::<String> {
+        kind: JobKind::SendWaitlistConfirmationEmail,
+        args: vec![entry.id.to_string(), token],
+    };
+    if let Err(err) = queue.enqueue(job) {
+        error!(logger, "error: {}", err);
+        return res.status(Status::InternalServerError);
+    }
+    res.format(done)
+}
+
+#[patch("/waitlist/confirm/<token>", rank = 1)]
+pub fn confirm<'a>(
+    _rate_limit: RateLimit<Waitlist>,
+    token: String,
+    db_conn: DbConn,
+    mut ss_conn: SsConn,
+    clock: State<SharedClock>,
+    logger: RequestLogger,
+) -> Response<'a> {
+    let res: Response = Default::default();
+
+    let key = format!("wl-{}", token);
+    let result: Result<i64, RedisError> = ss_conn.get(&key).map_err(|e| {
+        error!(logger, "error: {}", e);
+        e
+    });
+
+    let confirmed = match result {
+        Ok(id) => {
+            match WaitlistEntry::find_by_id(id, &db_conn, &logger) {
+                Some(ref e) if e.is_confirmed() => true,
+                Some(ref e) => e.confirm(&**clock, &db_conn, &logger).is_ok(),
+                None => false,
+            }
+        },
+        Err(_) => false,
+    };
+    if !confirmed {
+        return res.status(Status::BadRequest).format(json!({
+            "message": "The confirmation link has been expired or is invalid"
+        }));
+    }
+
+    let _: Result<i64, RedisError> = ss_conn.del(&key);
+    res.status(Status::Ok)
+}
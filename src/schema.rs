@@ -1,6 +1,9 @@
 table! {
     use diesel::sql_types::*;
 
+    use crate::model::message::ELogLevel;
+    use crate::model::namespace::EAnomalySensitivity;
+
     namespaces (id) {
         id -> Int8,
         uuid -> Uuid,
@@ -10,6 +13,42 @@ table! {
         archived_at -> Nullable<Timestamp>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        deduplicates_messages -> Bool,
+        min_level -> Nullable<ELogLevel>,
+        default_tags -> Array<Varchar>,
+        anomaly_sensitivity -> EAnomalySensitivity,
+        badge_public -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    namespace_usages (id) {
+        id -> Int8,
+        namespace_id -> Int8,
+        date -> Date,
+        messages_count -> Int8,
+        bytes_count -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        api_calls_count -> Int8,
+        dropped_messages_count -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    usage_records (id) {
+        id -> Int8,
+        namespace_id -> Int8,
+        month -> Date,
+        api_calls_count -> Int8,
+        messages_count -> Int8,
+        bytes_count -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -34,7 +73,7 @@ table! {
     use crate::model::message::{EAgentType, ELogFormat, ELogLevel};
 
     messages (id) {
-        id -> Int8,
+        id -> Varchar,
         agent_id -> Int8,
         agent_type -> EAgentType,
         stream_id -> Int8,
@@ -46,6 +85,46 @@ table! {
         content -> Nullable<Text>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        tags -> Array<Varchar>,
+        deleted_at -> Nullable<Timestamp>,
+        acknowledged_at -> Nullable<Timestamp>,
+        resolved_at -> Nullable<Timestamp>,
+        body_id -> Nullable<Int8>,
+        occurrences_count -> Int4,
+        trace_id -> Nullable<Varchar>,
+        span_id -> Nullable<Varchar>,
+        hostname -> Nullable<Varchar>,
+        service -> Nullable<Varchar>,
+        environment -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    message_bodies (id) {
+        id -> Int8,
+        digest -> Varchar,
+        content -> Text,
+        references_count -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    use crate::model::message::ELogLevel;
+
+    message_counts (id) {
+        id -> Int8,
+        namespace_id -> Int8,
+        hour -> Timestamp,
+        level -> ELogLevel,
+        count -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -127,20 +206,274 @@ table! {
         revoked_at -> Nullable<Timestamp>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        scopes -> Array<Varchar>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    waitlist_entries (id) {
+        id -> Int8,
+        email -> Varchar,
+        confirmed_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    use crate::model::identity::EIdentityProvider;
+
+    identities (id) {
+        id -> Int8,
+        user_id -> Int8,
+        provider -> EIdentityProvider,
+        uid -> Varchar,
+        email -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    use crate::model::recent_view::ERecentViewKind;
+
+    recent_views (id) {
+        id -> Int8,
+        user_id -> Int8,
+        kind -> ERecentViewKind,
+        target -> Varchar,
+        viewed_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel::pg::types::sql_types::Uuid;
+
+    use crate::model::bulk_operation::{
+        EBulkOperationAction, EBulkOperationState,
+    };
+
+    bulk_operations (id) {
+        id -> Int8,
+        uuid -> Uuid,
+        user_id -> Int8,
+        stream_id -> Int8,
+        action -> EBulkOperationAction,
+        argument -> Nullable<Varchar>,
+        filter -> Text,
+        state -> EBulkOperationState,
+        matched_count -> Int8,
+        affected_count -> Int8,
+        finished_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel::pg::types::sql_types::Uuid;
+
+    use crate::model::channel::EChannelKind;
+
+    channels (id) {
+        id -> Int8,
+        uuid -> Uuid,
+        namespace_id -> Int8,
+        kind -> EChannelKind,
+        name -> Varchar,
+        url -> Varchar,
+        template -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        rollup_window -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    alert_schedules (id) {
+        id -> Int8,
+        user_id -> Int8,
+        timezone -> Varchar,
+        quiet_hours_start -> Nullable<Time>,
+        quiet_hours_end -> Nullable<Time>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    stream_tokens (id) {
+        id -> Int8,
+        uuid -> Uuid,
+        namespace_id -> Int8,
+        name -> Varchar,
+        token -> Varchar,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        hostname -> Nullable<Varchar>,
+        service -> Nullable<Varchar>,
+        environment -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel::pg::types::sql_types::Uuid;
+
+    use crate::model::push_device::EPushPlatform;
+
+    push_devices (id) {
+        id -> Int8,
+        uuid -> Uuid,
+        user_id -> Int8,
+        platform -> EPushPlatform,
+        token -> Varchar,
+        name -> Varchar,
+        last_delivered_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    use crate::model::notification_preference::{
+        ENotificationChannel, ENotificationEvent,
+    };
+
+    notification_preferences (id) {
+        id -> Int8,
+        user_id -> Int8,
+        event -> ENotificationEvent,
+        channel -> ENotificationChannel,
+        webhook_url -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+
+    push_deliveries (id) {
+        id -> Int8,
+        push_device_id -> Int8,
+        message_id -> Varchar,
+        delivered -> Bool,
+        provider_id -> Nullable<Varchar>,
+        error -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel::pg::types::sql_types::Uuid;
+
+    use crate::model::incident::EIncidentState;
+
+    incidents (id) {
+        id -> Int8,
+        uuid -> Uuid,
+        namespace_id -> Int8,
+        title -> Varchar,
+        state -> EIncidentState,
+        rule -> Nullable<Varchar>,
+        resolved_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    incident_messages (id) {
+        id -> Int8,
+        incident_id -> Int8,
+        message_id -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    analytics_events (id) {
+        id -> Int8,
+        name -> Varchar,
+        actor -> Nullable<Varchar>,
+        properties -> Text,
+        occurred_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+joinable!(alert_schedules -> users (user_id));
+joinable!(bulk_operations -> streams (stream_id));
+joinable!(channels -> namespaces (namespace_id));
+joinable!(bulk_operations -> users (user_id));
+joinable!(identities -> users (user_id));
+joinable!(incidents -> namespaces (namespace_id));
+joinable!(incident_messages -> incidents (incident_id));
+joinable!(incident_messages -> messages (message_id));
+joinable!(notification_preferences -> users (user_id));
+joinable!(push_deliveries -> push_devices (push_device_id));
+joinable!(push_devices -> users (user_id));
+joinable!(recent_views -> users (user_id));
 joinable!(user_emails -> users (user_id));
 joinable!(streams -> namespaces (namespace_id));
+joinable!(stream_tokens -> namespaces (namespace_id));
 joinable!(messages -> streams (stream_id));
+joinable!(messages -> message_bodies (body_id));
 joinable!(memberships -> namespaces (namespace_id));
+joinable!(message_counts -> namespaces (namespace_id));
+joinable!(namespace_usages -> namespaces (namespace_id));
+joinable!(usage_records -> namespaces (namespace_id));
 joinable!(memberships -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(users, access_tokens);
+allow_tables_to_appear_in_same_query!(users, alert_schedules);
+allow_tables_to_appear_in_same_query!(users, bulk_operations);
+allow_tables_to_appear_in_same_query!(users, identities);
 allow_tables_to_appear_in_same_query!(users, memberships);
+allow_tables_to_appear_in_same_query!(users, notification_preferences);
+allow_tables_to_appear_in_same_query!(users, push_devices);
+allow_tables_to_appear_in_same_query!(users, recent_views);
 allow_tables_to_appear_in_same_query!(users, user_emails);
 
+allow_tables_to_appear_in_same_query!(namespaces, channels);
+allow_tables_to_appear_in_same_query!(namespaces, incidents);
 allow_tables_to_appear_in_same_query!(namespaces, memberships);
+allow_tables_to_appear_in_same_query!(namespaces, message_counts);
+allow_tables_to_appear_in_same_query!(namespaces, namespace_usages);
 allow_tables_to_appear_in_same_query!(namespaces, streams);
+allow_tables_to_appear_in_same_query!(namespaces, stream_tokens);
+allow_tables_to_appear_in_same_query!(namespaces, usage_records);
 
+allow_tables_to_appear_in_same_query!(incidents, incident_messages);
+allow_tables_to_appear_in_same_query!(incidents, streams);
+allow_tables_to_appear_in_same_query!(incident_messages, messages);
+
+allow_tables_to_appear_in_same_query!(push_devices, push_deliveries);
+
+allow_tables_to_appear_in_same_query!(streams, bulk_operations);
 allow_tables_to_appear_in_same_query!(streams, messages);
+
+allow_tables_to_appear_in_same_query!(messages, message_bodies);
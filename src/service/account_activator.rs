@@ -1,9 +1,8 @@
 use std::fmt;
 
-use rocket_slog::SyncLogger;
-
 use crate::config::Config;
 use crate::db::DbConn;
+use crate::logger::Logger;
 use crate::model::{Activatable, Verifiable};
 
 pub struct AccountActivator<'a, T, U>
@@ -13,7 +12,7 @@ where
 {
     db_conn: &'a DbConn,
     config: &'a Config,
-    logger: &'a SyncLogger,
+    logger: &'a Logger,
     pub target: Option<(T, U)>,
 }
 
@@ -25,7 +24,7 @@ where
     pub fn new(
         db_conn: &'a DbConn,
         config: &'a Config,
-        logger: &'a SyncLogger,
+        logger: &'a Logger,
     ) -> Self {
         Self {
             db_conn,
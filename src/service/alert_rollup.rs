@@ -0,0 +1,81 @@
+//! Rollup windows for alerts.
+//!
+//! A channel with `rollup_window` delivers at most one alert per window for
+//! each rule (the stream for now). The first alert opens the window, and the
+//! following ones in it are suppressed but counted. The count is given to the
+//! next delivered alert as `rollup.suppressed_count`, so that storms don't
+//! flood the channel but aren't lost silently.
+//!
+//! The state is kept in the session store (Redis) with expiration.
+use redis::{Commands, Connection, RedisResult};
+
+pub const KEY_PREFIX: &str = "ar-";
+
+// how long a suppressed count is kept after the last alert (1 day)
+const COUNT_EXPIRATION: usize = 86400;
+
+pub fn window_key(channel_uuid: &str, rule: &str) -> String {
+    format!("{}{}-{}", KEY_PREFIX, channel_uuid, rule)
+}
+
+pub fn count_key(channel_uuid: &str, rule: &str) -> String {
+    format!("{}:suppressed", window_key(channel_uuid, rule))
+}
+
+pub struct AlertRollup<'a> {
+    conn: &'a mut Connection,
+    window: usize,
+}
+
+impl<'a> AlertRollup<'a> {
+    /// The window is in seconds (0 disables the rollup).
+    pub fn new(conn: &'a mut Connection, window: usize) -> Self {
+        Self { conn, window }
+    }
+
+    /// Returns the number of the suppressed alerts since the last delivery if
+    /// the alert should be delivered now, or None if it's suppressed.
+    pub fn admit(
+        &mut self,
+        channel_uuid: &str,
+        rule: &str,
+    ) -> RedisResult<Option<i64>> {
+        if self.window == 0 {
+            return Ok(Some(0));
+        }
+
+        let key = window_key(channel_uuid, rule);
+        let count_key = count_key(channel_uuid, rule);
+
+        let opened: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(self.window)
+            .query(&mut *self.conn)?;
+        if opened.is_some() {
+            let (count, _): (Option<i64>, i64) = redis::pipe()
+                .atomic()
+                .get(&count_key)
+                .del(&count_key)
+                .query(&mut *self.conn)?;
+            return Ok(Some(count.unwrap_or(0)));
+        }
+
+        let _: i64 = self.conn.incr(&count_key, 1)?;
+        let _: bool = self.conn.expire(&count_key, COUNT_EXPIRATION)?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(window_key("a", "b"), "ar-a-b");
+        assert_eq!(count_key("a", "b"), "ar-a-b:suppressed");
+    }
+}
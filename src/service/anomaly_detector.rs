@@ -0,0 +1,192 @@
+//! Detection of spikes of errors in namespaces.
+//!
+//! The errors (`error` and `critical` messages) of the last whole hour in a
+//! namespace are compared against the baseline of the hours before it, which
+//! is read from the hourly counts (see `model::message_count`). It's a spike
+//! if the count is above the mean of the baseline by more than the deviation
+//! times the threshold of the sensitivity of the namespace (e.g. 3 for
+//! `medium`). The `DetectAnomalies` job alerts spikes via the channels of the
+//! namespace.
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDateTime};
+use diesel::PgConnection;
+
+use crate::logger::Logger;
+use crate::model::message_count::HourlyMessageCount;
+use crate::model::namespace::Namespace;
+
+// hours of the baseline before the last hour
+const BASELINE_HOURS: i64 = 24;
+
+// fewer errors in the last hour are never a spike
+const MIN_COUNT: i64 = 10;
+
+/// The rule of the incidents (and the rollup) of anomalies.
+pub const RULE: &str = "anomaly";
+
+/// Anomaly
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    /// The start of the hour
+    pub hour: NaiveDateTime,
+    pub count: i64,
+    /// The mean of the hours of the baseline
+    pub baseline: f64,
+}
+
+impl Anomaly {
+    pub fn title(&self) -> String {
+        format!(
+            "Error rate spike: {} errors in the hour (baseline {:.1})",
+            self.count, self.baseline
+        )
+    }
+}
+
+// returns the mean and the standard deviation
+fn baseline(counts: &[i64]) -> (f64, f64) {
+    if counts.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = counts.len() as f64;
+    let mean = counts.iter().sum::<i64>() as f64 / n;
+    let variance = counts
+        .iter()
+        .map(|c| (*c as f64 - mean).powi(2))
+        .sum::<f64>() /
+        n;
+    (mean, variance.sqrt())
+}
+
+// the deviation is at least the one of Poisson (and 1), so that a flat
+// baseline does not make any small change a spike
+fn is_spike(count: i64, mean: f64, stddev: f64, threshold: f64) -> bool {
+    if count < MIN_COUNT {
+        return false;
+    }
+    let deviation = stddev.max(mean.sqrt()).max(1.0);
+    count as f64 > mean + threshold * deviation
+}
+
+// the counts of the hours `[from, to)` (zero for the hours without errors)
+fn fill_hours(
+    counts: &BTreeMap<NaiveDateTime, i64>,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Vec<i64> {
+    let mut values = vec![];
+    let mut t = from;
+    while t < to {
+        values.push(*counts.get(&t).unwrap_or(&0));
+        t += Duration::hours(1);
+    }
+    values
+}
+
+pub struct AnomalyDetector<'a> {
+    conn: &'a PgConnection,
+    logger: &'a Logger,
+}
+
+impl<'a> AnomalyDetector<'a> {
+    pub fn new(conn: &'a PgConnection, logger: &'a Logger) -> Self {
+        Self { conn, logger }
+    }
+
+    /// Returns a spike in the hour before the end (the start of an hour
+    /// which has been aggregated), if there is.
+    pub fn detect(
+        &self,
+        namespace: &Namespace,
+        end: NaiveDateTime,
+    ) -> Result<Option<Anomaly>, &'static str> {
+        let threshold = match namespace.anomaly_sensitivity.threshold() {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let hour = end - Duration::hours(1);
+        let from = hour - Duration::hours(BASELINE_HOURS);
+        let counts = HourlyMessageCount::count_errors_by_hour(
+            namespace.id,
+            from,
+            end,
+            self.conn,
+            self.logger,
+        )
+        .ok_or("failed to load message counts")?;
+
+        let count = *counts.get(&hour).unwrap_or(&0);
+        let (mean, stddev) = baseline(&fill_hours(&counts, from, hour));
+        info!(
+            self.logger,
+            "namespace: {}, count: {}, mean: {}, stddev: {}",
+            namespace.id,
+            count,
+            mean,
+            stddev
+        );
+        if !is_spike(count, mean, stddev, threshold) {
+            return Ok(None);
+        }
+        Ok(Some(Anomaly {
+            hour,
+            count,
+            baseline: mean,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_baseline() {
+        assert_eq!(baseline(&[]), (0.0, 0.0));
+        assert_eq!(baseline(&[3, 3, 3]), (3.0, 0.0));
+        assert_eq!(baseline(&[2, 4, 4, 4, 5, 5, 7, 9]), (5.0, 2.0));
+    }
+
+    #[test]
+    fn test_is_spike() {
+        // below the minimum
+        assert!(!is_spike(9, 0.0, 0.0, 2.0));
+        assert!(is_spike(10, 0.0, 0.0, 2.0));
+
+        // 100 + 3 * 10
+        assert!(!is_spike(130, 100.0, 0.0, 3.0));
+        assert!(is_spike(131, 100.0, 0.0, 3.0));
+
+        // the deviation of the baseline is larger
+        assert!(!is_spike(131, 100.0, 20.0, 3.0));
+        assert!(is_spike(131, 100.0, 20.0, 1.5));
+    }
+
+    #[test]
+    fn test_fill_hours() {
+        let from = Utc.ymd(2021, 7, 12).and_hms(0, 0, 0).naive_utc();
+        let mut counts = BTreeMap::new();
+        counts.insert(from + Duration::hours(1), 5);
+        counts.insert(from + Duration::hours(3), 2);
+        assert_eq!(
+            fill_hours(&counts, from, from + Duration::hours(3)),
+            vec![0, 5, 0]
+        );
+    }
+
+    #[test]
+    fn test_title() {
+        let anomaly = Anomaly {
+            hour: Utc.ymd(2021, 7, 12).and_hms(9, 0, 0).naive_utc(),
+            count: 42,
+            baseline: 3.0,
+        };
+        assert_eq!(
+            anomaly.title(),
+            "Error rate spike: 42 errors in the hour (baseline 3.0)"
+        );
+    }
+}
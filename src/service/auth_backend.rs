@@ -0,0 +1,151 @@
+//! Authentication backends for login with username and password.
+//!
+//! A deployment selects one of them by `authentication_backend` in config.
+//! The local backend verifies the password saved in users table, the others
+//! verify credentials on an external directory and never use local passwords
+//! (registration and password reset are not available with them).
+use diesel::PgConnection;
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::id::IdGenerator;
+use crate::license::{Feature, License, LicenseError};
+use crate::logger::Logger;
+use crate::model::Authenticatable;
+use crate::model::password_hash::PasswordHashParams;
+use crate::model::user::User;
+use crate::service::ldap::Ldap;
+use crate::validation::email::{EmailPolicy, normalize};
+
+pub trait AuthBackend {
+    /// Returns the user for the credentials.
+    ///
+    /// The error is only for logging, it must not be shown to the client.
+    fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<User, &'static str>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Local,
+    Ldap,
+}
+
+impl Kind {
+    pub fn from_config(config: &Config) -> Self {
+        match config.authentication_backend.to_ascii_lowercase().as_ref() {
+            "ldap" => Self::Ldap,
+            _ => Self::Local,
+        }
+    }
+
+    /// Returns true if users have (and can reset) a local password.
+    pub fn uses_local_password(self) -> bool {
+        self == Self::Local
+    }
+}
+
+/// Verifies the email and password of an active user.
+///
+/// The password hash is replaced with the current parameters on success if
+/// it's a legacy one (see `model::password_hash`).
+pub struct Local {
+    pub params: PasswordHashParams,
+    pub email_policy: EmailPolicy,
+}
+
+impl AuthBackend for Local {
+    fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<User, &'static str> {
+        // saved addresses are normalized, but older ones may not be
+        let email = normalize(username, &self.email_policy);
+        let user = User::find_by_email(&email, conn, logger).or_else(|| {
+            if email != username {
+                User::find_by_email(username, conn, logger)
+            } else {
+                None
+            }
+        });
+        match user {
+            Some(mut user) if user.verify_password(password) => {
+                if let Err(e) =
+                    user.rehash_password(password, &self.params, conn, logger)
+                {
+                    warn!(logger, "err: {}", e);
+                }
+                Ok(user)
+            },
+            _ => Err("invalid credentials"),
+        }
+    }
+}
+
+/// Returns the backend for the deployment.
+///
+/// Directory backends are available only in the enterprise edition.
+pub fn select<'a>(
+    config: &'a Config,
+    license: &License,
+    clock: &dyn Clock,
+    ids: &'a dyn IdGenerator,
+) -> Result<Box<dyn AuthBackend + 'a>, LicenseError> {
+    match Kind::from_config(config) {
+        Kind::Local => Ok(Box::new(Local {
+            params: PasswordHashParams::from(config),
+            email_policy: EmailPolicy::from(config),
+        })),
+        Kind::Ldap => {
+            license.require(Feature::Sso, clock)?;
+            Ok(Box::new(Ldap::new(config, ids)))
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::clock::SystemClock;
+    use crate::id::RandomIdGenerator;
+    use crate::model::test::CONFIG;
+
+    #[test]
+    fn test_kind_from_config() {
+        let mut config = CONFIG.clone();
+        assert_eq!(Kind::from_config(&config), Kind::Local);
+
+        config.authentication_backend = "LDAP".to_string();
+        assert_eq!(Kind::from_config(&config), Kind::Ldap);
+        assert!(!Kind::from_config(&config).uses_local_password());
+
+        config.authentication_backend = "unknown".to_string();
+        assert_eq!(Kind::from_config(&config), Kind::Local);
+        assert!(Kind::from_config(&config).uses_local_password());
+    }
+
+    #[test]
+    fn test_select_ldap_in_community_edition() {
+        let mut config = CONFIG.clone();
+        config.authentication_backend = "ldap".to_string();
+
+        let license = License::default();
+        assert_eq!(
+            select(&config, &license, &SystemClock, &RandomIdGenerator).err(),
+            Some(LicenseError::NotAvailable(Feature::Sso))
+        );
+
+        config.authentication_backend = "local".to_string();
+        let ids = RandomIdGenerator;
+        assert!(select(&config, &license, &SystemClock, &ids).is_ok());
+    }
+}
@@ -0,0 +1,144 @@
+//! Status badges of namespaces.
+//!
+//! A badge is a small SVG image of the errors (`error` and `critical`
+//! messages) in a namespace in the last 24 hours, which can be embedded in
+//! READMEs and dashboards. It's shown only with the token signed for the
+//! namespace (see `token`), unless the namespace has `badge_public`. The
+//! token is a HMAC of the uuid by `AUTHENTICATION_TOKEN_SECRET`, thus it
+//! doesn't expire but changes if the secret is rotated.
+use base64::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use crate::model::namespace::Namespace;
+
+const LABEL: &str = "errors (24h)";
+
+const COLOR_LABEL: &str = "#555";
+const COLOR_HEALTHY: &str = "#4c1";
+const COLOR_ERROR: &str = "#e05d44";
+
+// an approximate width of a character of Verdana in 11px
+const CHAR_WIDTH: usize = 7;
+const PADDING: usize = 10;
+
+/// Badge
+#[derive(Debug, PartialEq)]
+pub struct Badge {
+    pub label: String,
+    pub value: String,
+    pub color: &'static str,
+}
+
+impl Badge {
+    /// Returns a badge of the number of the errors (`healthy` if none).
+    pub fn errors(count: i64) -> Self {
+        let (value, color) = if count > 0 {
+            (count.to_string(), COLOR_ERROR)
+        } else {
+            ("healthy".to_string(), COLOR_HEALTHY)
+        };
+        Self {
+            label: LABEL.to_string(),
+            value,
+            color,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let label_width = width(&self.label);
+        let value_width = width(&self.value);
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" "#,
+                r#"height="20" role="img" aria-label="{l}: {v}">"#,
+                r#"<title>{l}: {v}</title>"#,
+                r#"<rect width="{lw}" height="20" fill="{lc}"/>"#,
+                r#"<rect x="{lw}" width="{vw}" height="20" fill="{vc}"/>"#,
+                r##"<g fill="#fff" text-anchor="middle" "##,
+                r#"font-family="Verdana,Geneva,DejaVu Sans,sans-serif" "#,
+                r#"font-size="11">"#,
+                r#"<text x="{lx}" y="14">{l}</text>"#,
+                r#"<text x="{vx}" y="14">{v}</text>"#,
+                r#"</g></svg>"#,
+            ),
+            w = label_width + value_width,
+            l = escape_xml(&self.label),
+            v = escape_xml(&self.value),
+            lw = label_width,
+            vw = value_width,
+            lc = COLOR_LABEL,
+            vc = self.color,
+            lx = label_width / 2,
+            vx = label_width + value_width / 2,
+        )
+    }
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count() * CHAR_WIDTH + PADDING
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn mac(namespace: &Namespace, secret: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap();
+    mac.update(format!("badge:{}", namespace.uuid).as_bytes());
+    mac
+}
+
+/// Returns the token to show the badge of the namespace.
+pub fn token(namespace: &Namespace, secret: &str) -> String {
+    let bytes = mac(namespace, secret).finalize().into_bytes();
+    base64::encode_config(&bytes, URL_SAFE_NO_PAD)
+}
+
+/// Checks the token in constant time.
+pub fn verify(namespace: &Namespace, token: &str, secret: &str) -> bool {
+    match base64::decode_config(token, URL_SAFE_NO_PAD) {
+        Ok(bytes) => mac(namespace, secret).verify(&bytes).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::namespace::data::NAMESPACES;
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Badge::errors(0).value, "healthy");
+        assert_eq!(Badge::errors(0).color, COLOR_HEALTHY);
+        assert_eq!(Badge::errors(12).value, "12");
+        assert_eq!(Badge::errors(12).color, COLOR_ERROR);
+    }
+
+    #[test]
+    fn test_render() {
+        let svg = Badge::errors(12).render();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains(r#"width="118""#));
+        assert!(svg.contains("<title>errors (24h): 12</title>"));
+        assert!(svg.ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let piano = NAMESPACES.get("piano").unwrap();
+        let ball = NAMESPACES.get("ball").unwrap();
+
+        let t = token(piano, "secret");
+        assert!(verify(piano, &t, "secret"));
+        assert!(!verify(piano, &t, "another"));
+        assert!(!verify(ball, &t, "secret"));
+        assert!(!verify(piano, "", "secret"));
+        assert!(!verify(piano, "!", "secret"));
+    }
+}
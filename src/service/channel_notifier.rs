@@ -0,0 +1,84 @@
+//! Delivers alerts to channels.
+//!
+//! The payload is rendered with the template of the channel (see
+//! `service::payload_template`), then it's posted as JSON to the URL. Both
+//! webhook and Slack (incoming webhook) channels work in the same way.
+//!
+//! The timings of compiling the template and rendering the payload are
+//! counted in the session store if it's available (see
+//! `service::render_metrics`).
+use std::time::{Duration, Instant};
+
+use redis::Connection;
+
+use crate::logger::Logger;
+use crate::model::channel::Channel;
+use crate::service::payload_template::{PayloadContext, PayloadTemplate};
+use crate::service::render_metrics::{RenderKind, RenderMetrics};
+
+const TIMEOUT: u64 = 10; // seconds
+
+const USER_AGENT: &str = "eloquentlog-console-api";
+
+pub struct ChannelNotifier<'a> {
+    channel: &'a Channel,
+    logger: &'a Logger,
+}
+
+impl<'a> ChannelNotifier<'a> {
+    pub fn new(channel: &'a Channel, logger: &'a Logger) -> Self {
+        Self { channel, logger }
+    }
+
+    pub fn notify(
+        &self,
+        context: &PayloadContext,
+        mut ss_conn: Option<&mut Connection>,
+    ) -> Result<(), &'static str> {
+        let started_at = Instant::now();
+        let template = PayloadTemplate::new(
+            self.channel.kind,
+            self.channel.template.as_deref(),
+        )
+        .map_err(|e| self.log(e, "invalid template"))?;
+        self.record(&mut ss_conn, RenderKind::Template, started_at);
+
+        let started_at = Instant::now();
+        let payload = template
+            .render(context)
+            .map_err(|e| self.log(e, "failed to render the payload"))?;
+        self.record(&mut ss_conn, RenderKind::Payload, started_at);
+
+        ureq::post(&self.channel.url)
+            .set("Content-Type", "application/json")
+            .set("User-Agent", USER_AGENT)
+            .timeout(Duration::from_secs(TIMEOUT))
+            .send_string(&payload)
+            .map_err(|e| self.log(e, "failed to deliver the payload"))?;
+        Ok(())
+    }
+
+    // a failure of the metrics doesn't stop the delivery
+    fn record(
+        &self,
+        ss_conn: &mut Option<&mut Connection>,
+        kind: RenderKind,
+        started_at: Instant,
+    ) {
+        if let Some(conn) = ss_conn {
+            let elapsed = started_at.elapsed();
+            if let Err(e) = RenderMetrics::new(conn).record(kind, elapsed) {
+                error!(self.logger, "err: {}", e);
+            }
+        }
+    }
+
+    fn log<E: std::fmt::Display>(
+        &self,
+        e: E,
+        message: &'static str,
+    ) -> &'static str {
+        error!(self.logger, "err: {} ({})", e, self.channel);
+        message
+    }
+}
@@ -0,0 +1,136 @@
+//! Confirmation tokens for destructive API calls.
+//!
+//! A destructive call (e.g. revocation of an access token, deletion or a
+//! request of transfer of a namespace) requires a token issued by a prior
+//! `.../confirm` call for the same user, action and target. The token is
+//! short-lived and can be used only once, so that a mistake of automation
+//! can't repeat the call blindly.
+//! A user in sudo mode (see `request::sudo`) doesn't need it.
+//!
+//! A transfer of a namespace is confirmed by the new owner with a token sent
+//! by email, so it lives longer (`TOKEN_DURATION_NAMESPACE_TRANSFER`) than the
+//! others (`TOKEN_DURATION_CONFIRMATION`).
+//!
+//! The tokens are kept in the session store (Redis) with expiration.
+use std::fmt;
+use std::time::Duration;
+
+use redis::{Connection, RedisResult, Script};
+
+use crate::config::Config;
+use crate::id::IdGenerator;
+
+pub const KEY_PREFIX: &str = "ct-";
+
+const TOKEN_LENGTH: usize = 32;
+
+// deletes the key only if the value is the token
+const CONSUME_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+  return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// ConfirmationAction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfirmationAction {
+    DeleteNamespace,
+    RequestNamespaceTransfer,
+    RevokeAccessToken,
+    TransferNamespace,
+}
+
+impl fmt::Display for ConfirmationAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DeleteNamespace => write!(f, "delete_namespace"),
+            Self::RequestNamespaceTransfer => {
+                write!(f, "request_namespace_transfer")
+            },
+            Self::RevokeAccessToken => write!(f, "revoke_access_token"),
+            Self::TransferNamespace => write!(f, "transfer_namespace"),
+        }
+    }
+}
+
+pub fn key(
+    user_uuid: &str,
+    action: ConfirmationAction,
+    target: &str,
+) -> String {
+    format!("{}{}-{}-{}", KEY_PREFIX, user_uuid, action, target)
+}
+
+pub struct Confirmation<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> Confirmation<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    /// Issues a new token for the action on the target, which expires after
+    /// the duration. A token issued before for the same one is replaced.
+    pub fn issue(
+        &mut self,
+        user_uuid: &str,
+        action: ConfirmationAction,
+        target: &str,
+        duration: Duration,
+        ids: &dyn IdGenerator,
+    ) -> RedisResult<String> {
+        let token = ids.random_hash(Config::CSRF_HASH_SOURCE, TOKEN_LENGTH);
+        redis::cmd("SET")
+            .arg(key(user_uuid, action, target))
+            .arg(&token)
+            .arg("EX")
+            .arg(duration.as_secs())
+            .query::<()>(&mut *self.conn)?;
+        Ok(token)
+    }
+
+    /// Returns true if the token is valid. It can't be used again.
+    pub fn consume(
+        &mut self,
+        user_uuid: &str,
+        action: ConfirmationAction,
+        target: &str,
+        token: &str,
+    ) -> RedisResult<bool> {
+        if token.is_empty() {
+            return Ok(false);
+        }
+        let deleted: i64 = Script::new(CONSUME_SCRIPT)
+            .key(key(user_uuid, action, target))
+            .arg(token)
+            .invoke(&mut *self.conn)?;
+        Ok(deleted == 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(
+            key("u", ConfirmationAction::RevokeAccessToken, "t"),
+            "ct-u-revoke_access_token-t"
+        );
+        assert_eq!(
+            key("u", ConfirmationAction::DeleteNamespace, "n"),
+            "ct-u-delete_namespace-n"
+        );
+        assert_eq!(
+            key("u", ConfirmationAction::RequestNamespaceTransfer, "n"),
+            "ct-u-request_namespace_transfer-n"
+        );
+        assert_eq!(
+            key("u", ConfirmationAction::TransferNamespace, "n"),
+            "ct-u-transfer_namespace-n"
+        );
+    }
+}
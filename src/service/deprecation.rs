@@ -0,0 +1,321 @@
+//! Deprecations of routes and fields.
+//!
+//! A deprecation is listed in `DEPRECATIONS`, either for a route (by its
+//! method and mounted uri) or for a field (or a form of a value), which the
+//! route marks on its response (`Response::deprecate`). The responses get:
+//!
+//! * `Deprecation: @<timestamp>` (since when)
+//! * `Sunset: <HTTP-date>` (when it will be removed, if it's decided)
+//! * `Link: <url>; rel="deprecation"` (documentation, if any)
+//!
+//! Each usage is logged and counted by the client (the kind of the token and
+//! the user) in the session store, as `dp-<name>` (counts) and `dpt-<name>`
+//! (timestamps of the last usage). `cli deprecations` reports them, so that
+//! we know who still needs to migrate before a sunset.
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use redis::{Commands, Connection, RedisResult};
+use rocket::{Request, Response, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket_slog::SyncLogger;
+
+use crate::clock::SharedClock;
+use crate::model::user::User;
+use crate::request::token::TokenType;
+use crate::ss::SsConn;
+
+pub const KEY_PREFIX: &str = "dp-";
+pub const LAST_USED_KEY_PREFIX: &str = "dpt-";
+
+const ANONYMOUS: &str = "anonymous";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Lookups of users and namespaces by sequential id (see
+/// `model::external_id`).
+pub const LEGACY_ID: &str = "legacy-id";
+
+#[derive(Debug, PartialEq)]
+pub struct Deprecation {
+    pub name: &'static str,
+    /// (method, uri) e.g. `("GET", "/v1/namespace/hget/<uuid>")`
+    pub route: Option<(&'static str, &'static str)>,
+    /// YYYY-MM-DD
+    pub since: &'static str,
+    /// YYYY-MM-DD
+    pub sunset: Option<&'static str>,
+    pub link: Option<&'static str>,
+}
+
+pub static DEPRECATIONS: &[Deprecation] = &[Deprecation {
+    name: LEGACY_ID,
+    route: None,
+    since: "2021-06-27",
+    sunset: None,
+    link: None,
+}];
+
+pub fn key(name: &str) -> String {
+    format!("{}{}", KEY_PREFIX, name)
+}
+
+pub fn last_used_key(name: &str) -> String {
+    format!("{}{}", LAST_USED_KEY_PREFIX, name)
+}
+
+fn parse_date(s: &str) -> Option<NaiveDateTime> {
+    NaiveDate::parse_from_str(s, DATE_FORMAT)
+        .ok()
+        .map(|d| d.and_hms(0, 0, 0))
+}
+
+impl Deprecation {
+    /// Returns the value of `Deprecation` header.
+    pub fn deprecation_header(&self) -> Option<String> {
+        parse_date(self.since).map(|t| format!("@{}", t.timestamp()))
+    }
+
+    /// Returns the value of `Sunset` header.
+    pub fn sunset_header(&self) -> Option<String> {
+        self.sunset
+            .and_then(parse_date)
+            .map(|t| t.format(HTTP_DATE_FORMAT).to_string())
+    }
+
+    /// Returns the value of `Link` header.
+    pub fn link_header(&self) -> Option<String> {
+        self.link.map(|l| format!("<{}>; rel=\"deprecation\"", l))
+    }
+}
+
+pub fn find_by_name<'a>(
+    deprecations: &'a [Deprecation],
+    name: &str,
+) -> Option<&'a Deprecation> {
+    deprecations.iter().find(|d| d.name == name)
+}
+
+pub fn find_by_route<'a>(
+    deprecations: &'a [Deprecation],
+    method: &str,
+    uri: &str,
+) -> Option<&'a Deprecation> {
+    deprecations.iter().find(|d| d.route == Some((method, uri)))
+}
+
+/// Usage is a count of usages of a deprecation by a client.
+#[derive(Debug, PartialEq)]
+pub struct Usage {
+    pub name: String,
+    pub client: String,
+    pub count: i64,
+    pub last_used_at: Option<i64>,
+}
+
+pub struct DeprecationUsage<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> DeprecationUsage<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn record(
+        &mut self,
+        name: &str,
+        client: &str,
+        now: i64,
+    ) -> RedisResult<()> {
+        redis::pipe()
+            .hincr(key(name), client, 1)
+            .ignore()
+            .hset(last_used_key(name), client, now)
+            .ignore()
+            .query(&mut *self.conn)
+    }
+
+    /// Returns the usages of the deprecations (the most used first).
+    pub fn report(
+        &mut self,
+        deprecations: &[Deprecation],
+    ) -> RedisResult<Vec<Usage>> {
+        let mut usages = vec![];
+        for d in deprecations {
+            let counts: HashMap<String, i64> = self.conn.hgetall(key(d.name))?;
+            let times: HashMap<String, i64> =
+                self.conn.hgetall(last_used_key(d.name))?;
+            let mut v: Vec<Usage> = counts
+                .into_iter()
+                .map(|(client, count)| Usage {
+                    name: d.name.to_string(),
+                    last_used_at: times.get(&client).cloned(),
+                    client,
+                    count,
+                })
+                .collect();
+            v.sort_by(|a, b| {
+                b.count.cmp(&a.count).then_with(|| a.client.cmp(&b.client))
+            });
+            usages.extend(v);
+        }
+        Ok(usages)
+    }
+}
+
+/// Used is a deprecation marked by the route (see `Response::deprecate`).
+pub struct Used(pub &'static str);
+
+fn token_kind(token_type: &TokenType) -> &'static str {
+    match token_type {
+        TokenType::BrowserCookieToken => "browser",
+        TokenType::ExchangedToken => "exchanged",
+        TokenType::PersonalAccessToken => "personal",
+    }
+}
+
+/// Returns the client of the request like `personal:<user uuid>`.
+fn client_of(req: &Request) -> String {
+    // set by the User guard
+    let user = req.local_cache(|| None::<User>);
+    match (req.guard::<TokenType>().succeeded(), user) {
+        (Some(t), Some(u)) => format!("{}:{}", token_kind(&t), u.uuid),
+        _ => ANONYMOUS.to_string(),
+    }
+}
+
+/// Deprecations adds the headers to responses of deprecated routes and
+/// fields, and records the usages.
+pub struct Deprecations;
+
+impl Fairing for Deprecations {
+    fn info(&self) -> Info {
+        Info {
+            name: "Deprecation",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, req: &Request, res: &mut Response) {
+        let route = req.route().and_then(|r| {
+            find_by_route(DEPRECATIONS, r.method.as_str(), &r.uri.to_string())
+        });
+        let field = req
+            .local_cache(|| None::<Used>)
+            .as_ref()
+            .and_then(|u| find_by_name(DEPRECATIONS, u.0));
+        let used: Vec<&Deprecation> = route.into_iter().chain(field).collect();
+        if used.is_empty() {
+            return;
+        }
+
+        // the earliest ones (dates are YYYY-MM-DD)
+        let since = used.iter().min_by_key(|d| d.since);
+        if let Some(v) = since.and_then(|d| d.deprecation_header()) {
+            res.set_raw_header("Deprecation", v);
+        }
+        let sunset = used
+            .iter()
+            .filter(|d| d.sunset.is_some())
+            .min_by_key(|d| d.sunset);
+        if let Some(v) = sunset.and_then(|d| d.sunset_header()) {
+            res.set_raw_header("Sunset", v);
+        }
+        for v in used.iter().filter_map(|d| d.link_header()) {
+            res.adjoin_raw_header("Link", v);
+        }
+
+        let client = client_of(req);
+        let logger = req.guard::<SyncLogger>().unwrap();
+        let clock = req.guard::<State<SharedClock>>().unwrap();
+        let now = clock.now().timestamp();
+        let mut ss_conn = match req.guard::<SsConn>().succeeded() {
+            Some(conn) => conn,
+            None => {
+                error!(logger, "err: session store is not available");
+                return;
+            },
+        };
+        for d in used {
+            warn!(logger, "deprecated: {} is used by {}", d.name, client);
+            let mut usage = DeprecationUsage::new(&mut *ss_conn);
+            if let Err(e) = usage.record(d.name, &client, now) {
+                error!(logger, "err: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_DEPRECATIONS: &[Deprecation] = &[
+        Deprecation {
+            name: "old-route",
+            route: Some(("GET", "/v1/old/<id>")),
+            since: "2021-06-01",
+            sunset: Some("2021-12-31"),
+            link: Some("https://example.org/deprecations/old-route"),
+        },
+        Deprecation {
+            name: "old-field",
+            route: None,
+            since: "2021-06-27",
+            sunset: None,
+            link: None,
+        },
+    ];
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key(LEGACY_ID), "dp-legacy-id");
+        assert_eq!(last_used_key(LEGACY_ID), "dpt-legacy-id");
+    }
+
+    #[test]
+    fn test_deprecations_are_valid() {
+        for d in DEPRECATIONS {
+            assert!(d.deprecation_header().is_some(), "{}", d.name);
+            assert_eq!(d.sunset.is_some(), d.sunset_header().is_some());
+            assert_eq!(find_by_name(DEPRECATIONS, d.name), Some(d));
+        }
+    }
+
+    #[test]
+    fn test_headers() {
+        let d = &TEST_DEPRECATIONS[0];
+        assert_eq!(d.deprecation_header(), Some("@1622505600".to_string()));
+        assert_eq!(
+            d.sunset_header(),
+            Some("Fri, 31 Dec 2021 00:00:00 GMT".to_string())
+        );
+        assert_eq!(
+            d.link_header(),
+            Some(
+                "<https://example.org/deprecations/old-route>; \
+                 rel=\"deprecation\""
+                    .to_string()
+            )
+        );
+
+        let d = &TEST_DEPRECATIONS[1];
+        assert_eq!(d.sunset_header(), None);
+        assert_eq!(d.link_header(), None);
+    }
+
+    #[test]
+    fn test_find() {
+        let d = find_by_route(TEST_DEPRECATIONS, "GET", "/v1/old/<id>");
+        assert_eq!(d.map(|d| d.name), Some("old-route"));
+
+        assert!(find_by_route(TEST_DEPRECATIONS, "POST", "/v1/old/<id>")
+            .is_none());
+        assert!(find_by_route(TEST_DEPRECATIONS, "GET", "/v1/new").is_none());
+
+        let d = find_by_name(TEST_DEPRECATIONS, "old-field");
+        assert_eq!(d.map(|d| d.route), Some(None));
+        assert!(find_by_name(TEST_DEPRECATIONS, "unknown").is_none());
+    }
+}
@@ -0,0 +1,118 @@
+//! Suggests a correction for likely typos in the domain part of an email.
+//!
+//! e.g. `johnny@gamil.com` -> `johnny@gmail.com`
+//!
+//! This never blocks anything. The result is returned as just a hint.
+pub struct EmailSuggester<'a> {
+    domains: &'a [String],
+    max_distance: usize,
+}
+
+impl<'a> EmailSuggester<'a> {
+    pub fn new(domains: &'a [String], max_distance: usize) -> Self {
+        Self {
+            domains,
+            max_distance,
+        }
+    }
+
+    /// Returns a suggested email if its domain looks like a typo of a
+    /// known domain.
+    pub fn suggest(&self, email: &str) -> Option<String> {
+        let i = email.rfind('@')?;
+        let (local, domain) = (&email[..i], email[i + 1..].to_lowercase());
+        if local.is_empty() || domain.is_empty() || self.max_distance == 0 {
+            return None;
+        }
+        if self.domains.iter().any(|d| d == &domain) {
+            return None;
+        }
+
+        self.domains
+            .iter()
+            .map(|d| (distance(&domain, d), d))
+            .filter(|(n, _)| *n <= self.max_distance)
+            .min_by_key(|(n, _)| *n)
+            .map(|(_, d)| format!("{}@{}", local, d))
+    }
+}
+
+// optimal string alignment distance (Damerau-Levenshtein without
+// substring edits), transposition counts as 1
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let (m, n) = (a.len(), b.len());
+
+    let mut dist = vec![vec![0usize; n + 1]; m + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=n {
+        dist[0][j] = j;
+    }
+
+    for i in 1..=m {
+        for j in 1..=n {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            dist[i][j] = (dist[i - 1][j] + 1)
+                .min(dist[i][j - 1] + 1)
+                .min(dist[i - 1][j - 1] + cost);
+            if i > 1 &&
+                j > 1 &&
+                a[i - 1] == b[j - 2] &&
+                a[i - 2] == b[j - 1]
+            {
+                dist[i][j] = dist[i][j].min(dist[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    dist[m][n]
+}
+
+#[rustfmt::skip::attributes(rstest)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest(
+        a, b, expected,
+        case("gmail.com", "gmail.com", 0),
+        case("gamil.com", "gmail.com", 1),
+        case("gmail.con", "gmail.com", 1),
+        case("gmial.co", "gmail.com", 2),
+        case("", "abc", 3),
+        ::trace
+    )]
+    #[test]
+    fn test_distance(a: &'static str, b: &'static str, expected: usize) {
+        assert_eq!(distance(a, b), expected);
+    }
+
+    #[rstest(
+        email, expected,
+        case("johnny@gamil.com", Some("johnny@gmail.com")),
+        case("johnny@hotmial.com", Some("johnny@hotmail.com")),
+        case("johnny@GMAIL.CON", Some("johnny@gmail.com")),
+        case("johnny@gmail.com", None),
+        case("johnny@example.org", None),
+        case("johnny", None),
+        case("@gamil.com", None),
+        ::trace
+    )]
+    #[test]
+    fn test_suggest(email: &'static str, expected: Option<&'static str>) {
+        let domains = vec!["gmail.com".to_string(), "hotmail.com".to_string()];
+        let s = EmailSuggester::new(&domains, 2);
+        assert_eq!(s.suggest(email), expected.map(|v| v.to_string()));
+    }
+
+    #[test]
+    fn test_suggest_disabled() {
+        let domains = vec!["gmail.com".to_string()];
+        let s = EmailSuggester::new(&domains, 0);
+        assert_eq!(s.suggest("johnny@gamil.com"), None);
+    }
+}
@@ -0,0 +1,184 @@
+//! Error reporting to Sentry (with `error-tracking` feature).
+//!
+//! If `SENTRY_DSN` is set, panics, 5xx responses and the records logged as
+//! error or critical (e.g. failures of jobs in the worker) are sent as events.
+//! The context of the request-scoped logger (see `request::logger`) is
+//! attached to them: `user_id` as the user, and the other ones (e.g.
+//! `request_id`) as tags.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use rocket::{Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use sentry::protocol::{Event, Level as EventLevel, User as EventUser};
+use sentry::ClientInitGuard;
+use slog::{Drain, KV, Key, Level, Never, OwnedKVList, Record, Serializer};
+
+use crate::config::Config;
+use crate::model::user::User;
+use crate::request::logger::RequestId;
+
+// the keys of the context which are sent as tags
+const TAG_KEYS: &[&str] = &["request_id", "namespace_id"];
+
+/// Starts the client unless `SENTRY_DSN` is empty. Events are sent until the
+/// guard is dropped.
+pub fn init(config: &Config) -> Option<ClientInitGuard> {
+    if config.sentry_dsn.is_empty() {
+        return None;
+    }
+    let options = sentry::ClientOptions {
+        environment: Some(config.env_name.into()),
+        release: sentry::release_name!(),
+        ..Default::default()
+    };
+    Some(sentry::init((config.sentry_dsn.as_str(), options)))
+}
+
+// the key-values of a record (None values are omitted)
+#[derive(Default)]
+struct Fields(BTreeMap<String, String>);
+
+impl Serializer for Fields {
+    fn emit_arguments(
+        &mut self,
+        key: Key,
+        val: &fmt::Arguments,
+    ) -> slog::Result {
+        let value = val.to_string();
+        if value != "None" {
+            self.0.entry(key.to_string()).or_insert(value);
+        }
+        Ok(())
+    }
+}
+
+fn event_level(level: Level) -> EventLevel {
+    match level {
+        Level::Critical => EventLevel::Fatal,
+        Level::Error => EventLevel::Error,
+        Level::Warning => EventLevel::Warning,
+        Level::Info => EventLevel::Info,
+        _ => EventLevel::Debug,
+    }
+}
+
+// builds an event with the context (a user and tags)
+fn event_of(
+    message: String,
+    level: EventLevel,
+    mut fields: BTreeMap<String, String>,
+) -> Event<'static> {
+    let user = fields.remove("user_id").map(|id| EventUser {
+        id: Some(id),
+        ..Default::default()
+    });
+    let mut tags = BTreeMap::new();
+    let mut extra = BTreeMap::new();
+    for (k, v) in fields {
+        if TAG_KEYS.contains(&k.as_str()) {
+            tags.insert(k, v);
+        } else {
+            extra.insert(k, v.into());
+        }
+    }
+    Event {
+        message: Some(message),
+        level,
+        user,
+        tags,
+        extra,
+        ..Default::default()
+    }
+}
+
+/// ErrorDrain sends the records of error or critical as events. The others
+/// are ignored.
+pub struct ErrorDrain;
+
+impl Drain for ErrorDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(
+        &self,
+        record: &Record,
+        values: &OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        if !record.level().is_at_least(Level::Error) {
+            return Ok(());
+        }
+        let mut fields = Fields::default();
+        // the ones of the record are preferred
+        let _ = record.kv().serialize(record, &mut fields);
+        let _ = values.serialize(record, &mut fields);
+
+        let message = record.msg().to_string();
+        let level = event_level(record.level());
+        sentry::capture_event(event_of(message, level, fields.0));
+        Ok(())
+    }
+}
+
+/// ErrorReporting sends 5xx responses as events.
+pub struct ErrorReporting;
+
+impl Fairing for ErrorReporting {
+    fn info(&self) -> Info {
+        Info {
+            name: "Error Reporting",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, req: &Request, res: &mut Response) {
+        let status = res.status();
+        if status.code < 500 {
+            return;
+        }
+
+        // set by RequestLogger and User guards (if they have been run)
+        let mut fields = BTreeMap::new();
+        if let Some(ref id) = *req.local_cache(|| None::<RequestId>) {
+            fields.insert("request_id".to_string(), id.0.to_string());
+        }
+        if let Some(ref user) = *req.local_cache(|| None::<User>) {
+            fields.insert("user_id".to_string(), user.id.to_string());
+        }
+        fields.insert("method".to_string(), req.method().to_string());
+        fields.insert("path".to_string(), req.uri().path().to_string());
+
+        let message = format!("{} {} {}", status, req.method(), req.uri());
+        sentry::capture_event(event_of(message, EventLevel::Error, fields));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::Value;
+
+    #[test]
+    fn test_event_of() {
+        let mut fields = BTreeMap::new();
+        fields.insert("request_id".to_string(), "abc".to_string());
+        fields.insert("user_id".to_string(), "1".to_string());
+        fields.insert("stream".to_string(), "slug".to_string());
+
+        let message = "err: timeout".to_string();
+        let event = event_of(message, EventLevel::Error, fields);
+        assert_eq!(event.message, Some("err: timeout".to_string()));
+        assert_eq!(event.user.and_then(|u| u.id), Some("1".to_string()));
+        assert_eq!(event.tags.get("request_id"), Some(&"abc".to_string()));
+        assert_eq!(event.tags.get("stream"), None);
+        assert_eq!(event.extra.get("stream"), Some(&Value::from("slug")));
+    }
+
+    #[test]
+    fn test_event_level() {
+        assert_eq!(event_level(Level::Critical), EventLevel::Fatal);
+        assert_eq!(event_level(Level::Error), EventLevel::Error);
+        assert_eq!(event_level(Level::Trace), EventLevel::Debug);
+    }
+}
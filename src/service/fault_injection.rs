@@ -0,0 +1,170 @@
+//! Fault injection for resilience tests.
+//!
+//! With `FAULT_INJECTION=true` (honored only in testing), faults are set via
+//! `/_/fault` from localhost:
+//!
+//! * the next N connections to each Redis (message queue, session store) fail
+//! * SMTP is delayed by X milliseconds
+//! * the routes (request paths) return Internal Server Error
+//!
+//! They are kept in the message queue (Redis), so that the worker sees them
+//! too. Only the count of failures is also kept in the session store, as
+//! each connection checks its own.
+use std::io::Cursor;
+use std::time::Duration;
+
+use redis::{Client, Commands, Connection, RedisResult, Script};
+use rocket::{Outcome, Request, Response, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Status};
+
+use crate::config::Config;
+use crate::mq::MqPoolHolder;
+use crate::request::fault::Fault;
+use crate::response::ApiError;
+
+pub const KEY_PREFIX: &str = "fi-";
+
+// consumes one of the failures if any
+const TAKE_FAILURE: &str = r#"
+local n = tonumber(redis.call('GET', KEYS[1]) or '0')
+if n > 0 then
+  redis.call('DECR', KEYS[1])
+  return 1
+end
+return 0
+"#;
+
+pub fn key(name: &str) -> String {
+    format!("{}{}", KEY_PREFIX, name)
+}
+
+pub struct FaultInjection<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> FaultInjection<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    /// Sets the faults. The ones which are not given are kept.
+    pub fn set(&mut self, fault: &Fault) -> RedisResult<()> {
+        if let Some(n) = fault.redis_failures {
+            let _: () = self.conn.set(key("redis-failures"), n)?;
+        }
+        if let Some(ms) = fault.smtp_delay {
+            let _: () = self.conn.set(key("smtp-delay"), ms)?;
+        }
+        if let Some(ref routes) = fault.routes {
+            let _: i64 = self.conn.del(key("routes"))?;
+            if !routes.is_empty() {
+                let _: i64 = self.conn.sadd(key("routes"), &routes[..])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&mut self) -> RedisResult<Fault> {
+        let redis_failures: Option<u64> =
+            self.conn.get(key("redis-failures"))?;
+        let smtp_delay: Option<u64> = self.conn.get(key("smtp-delay"))?;
+        let mut routes: Vec<String> = self.conn.smembers(key("routes"))?;
+        routes.sort();
+        Ok(Fault {
+            redis_failures: Some(redis_failures.unwrap_or(0)),
+            smtp_delay: Some(smtp_delay.unwrap_or(0)),
+            routes: Some(routes),
+        })
+    }
+
+    pub fn clear(&mut self) -> RedisResult<()> {
+        self.conn.del(vec![
+            key("redis-failures"),
+            key("smtp-delay"),
+            key("routes"),
+        ])
+    }
+
+    pub fn take_redis_failure(&mut self) -> RedisResult<bool> {
+        let taken: i64 = Script::new(TAKE_FAILURE)
+            .key(key("redis-failures"))
+            .invoke(&mut *self.conn)?;
+        Ok(taken == 1)
+    }
+
+    pub fn smtp_delay(&mut self) -> RedisResult<Duration> {
+        let ms: Option<u64> = self.conn.get(key("smtp-delay"))?;
+        Ok(Duration::from_millis(ms.unwrap_or(0)))
+    }
+
+    pub fn fails_route(&mut self, path: &str) -> RedisResult<bool> {
+        self.conn.sismember(key("routes"), path)
+    }
+}
+
+/// Returns true if a connection to Redis should fail now.
+pub fn fails_redis(config: &Config, conn: &mut Connection) -> bool {
+    config.fault_injection &&
+        FaultInjection::new(conn)
+            .take_redis_failure()
+            .unwrap_or(false)
+}
+
+/// Returns the delay of SMTP (the mailer has no connection to Redis).
+pub fn smtp_delay(config: &Config) -> Duration {
+    if !config.fault_injection {
+        return Duration::from_millis(0);
+    }
+    Client::open(config.message_queue_connection_url().as_str())
+        .and_then(|c| c.get_connection())
+        .and_then(|mut conn| FaultInjection::new(&mut conn).smtp_delay())
+        .unwrap_or_else(|_| Duration::from_millis(0))
+}
+
+/// RouteFaults replaces responses of the routes with Internal Server Error.
+pub struct RouteFaults;
+
+impl Fairing for RouteFaults {
+    fn info(&self) -> Info {
+        Info {
+            name: "Fault Injection",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, req: &Request, res: &mut Response) {
+        match req.guard::<State<Config>>() {
+            Outcome::Success(ref c) if c.fault_injection => (),
+            _ => return,
+        }
+        let mut conn = match req.guard::<State<MqPoolHolder>>() {
+            Outcome::Success(holder) => match holder.get() {
+                Some(conn) => conn,
+                None => return,
+            },
+            _ => return,
+        };
+        let fails = FaultInjection::new(&mut *conn)
+            .fails_route(req.uri().path())
+            .unwrap_or(false);
+        if !fails {
+            return;
+        }
+
+        let body = ApiError::new(Status::InternalServerError).to_json();
+        res.set_status(Status::InternalServerError);
+        res.set_header(ContentType::JSON);
+        res.set_sized_body(Cursor::new(body.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key("routes"), "fi-routes");
+    }
+}
@@ -0,0 +1,230 @@
+//! Finds matched terms in a text for search results.
+//!
+//! The offsets are character (not byte) based ranges `[start, end)` in the
+//! whole text, and the fragment is a short excerpt around the first match
+//! with `<em>` markers. The fragment is escaped so that it can be rendered
+//! as HTML as it is.
+//!
+//! The matching follows the search options of the query in database. For
+//! `unaccent`, a character is compared by its base character (e.g. `é` as
+//! `e`), so that the offsets stay on the original text.
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+use crate::model::message::SearchOptions;
+
+const CONTEXT_LENGTH: usize = 32;
+const FRAGMENT_LENGTH: usize = 160;
+
+const ELLIPSIS: &str = "…";
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Highlight {
+    pub fragment: String,
+    pub offsets: Vec<(usize, usize)>,
+}
+
+pub struct Highlighter {
+    terms: Vec<Vec<char>>,
+    case_sensitive: bool,
+    unaccent: bool,
+}
+
+impl Highlighter {
+    /// Creates a highlighter. The query is split into terms by whitespace.
+    pub fn new(query: &str, options: &SearchOptions) -> Self {
+        let terms = query
+            .split_whitespace()
+            .map(|t| t.chars().collect())
+            .collect();
+        Self {
+            terms,
+            case_sensitive: options.case_sensitive,
+            unaccent: options.unaccent,
+        }
+    }
+
+    /// Returns None if no term is found in the text.
+    pub fn highlight(&self, text: &str) -> Option<Highlight> {
+        let chars: Vec<char> = text.chars().collect();
+        let offsets = self.find(&chars);
+        if offsets.is_empty() {
+            return None;
+        }
+
+        let start = offsets[0].0.saturating_sub(CONTEXT_LENGTH);
+        let end = chars.len().min(start + FRAGMENT_LENGTH);
+
+        let mut fragment = String::new();
+        if start > 0 {
+            fragment.push_str(ELLIPSIS);
+        }
+        let mut i = start;
+        let visible = offsets.iter().filter(|(s, e)| *s >= start && *e <= end);
+        for &(s, e) in visible {
+            fragment.push_str(&escape(&chars[i..s]));
+            fragment.push_str("<em>");
+            fragment.push_str(&escape(&chars[s..e]));
+            fragment.push_str("</em>");
+            i = e;
+        }
+        fragment.push_str(&escape(&chars[i..end]));
+        if end < chars.len() {
+            fragment.push_str(ELLIPSIS);
+        }
+
+        Some(Highlight { fragment, offsets })
+    }
+
+    // returns sorted and merged ranges of all terms
+    fn find(&self, chars: &[char]) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = vec![];
+        for term in self.terms.iter() {
+            let n = term.len();
+            if n == 0 || n > chars.len() {
+                continue;
+            }
+            let mut i = 0;
+            while i + n <= chars.len() {
+                let window = &chars[i..i + n];
+                if window.iter().zip(term).all(|(a, b)| self.eq(*a, *b)) {
+                    ranges.push((i, i + n));
+                    i += n;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(usize, usize)> = vec![];
+        for (s, e) in ranges {
+            match merged.last_mut() {
+                Some(last) if s <= last.1 => last.1 = last.1.max(e),
+                _ => merged.push((s, e)),
+            }
+        }
+        merged
+    }
+
+    fn eq(&self, a: char, b: char) -> bool {
+        let (a, b) = if self.unaccent {
+            (base(a), base(b))
+        } else {
+            (a, b)
+        };
+        a == b
+            || (!self.case_sensitive && a.to_lowercase().eq(b.to_lowercase()))
+    }
+}
+
+// returns the character without diacritics
+fn base(c: char) -> char {
+    Some(c)
+        .into_iter()
+        .nfd()
+        .find(|d| !is_combining_mark(*d))
+        .unwrap_or(c)
+}
+
+fn escape(chars: &[char]) -> String {
+    let mut s = String::with_capacity(chars.len());
+    for c in chars {
+        match c {
+            '&' => s.push_str("&amp;"),
+            '<' => s.push_str("&lt;"),
+            '>' => s.push_str("&gt;"),
+            '"' => s.push_str("&quot;"),
+            '\'' => s.push_str("&#39;"),
+            _ => s.push(*c),
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_highlight_not_found() {
+        let options = SearchOptions::default();
+        let h = Highlighter::new("foo", &options);
+        assert_eq!(h.highlight("bar baz"), None);
+
+        let h = Highlighter::new("  ", &options);
+        assert_eq!(h.highlight("bar baz"), None);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let options = SearchOptions::default();
+        let h = Highlighter::new("error TIMEOUT", &options);
+        let result = h.highlight("Connection Timeout: <db> error").unwrap();
+        assert_eq!(result.offsets, vec![(11, 18), (25, 30)]);
+        assert_eq!(
+            result.fragment,
+            "Connection <em>Timeout</em>: &lt;db&gt; <em>error</em>"
+        );
+    }
+
+    #[test]
+    fn test_highlight_overlapped_terms() {
+        let options = SearchOptions::default();
+        let h = Highlighter::new("ab bc", &options);
+        let result = h.highlight("xabcx").unwrap();
+        assert_eq!(result.offsets, vec![(1, 4)]);
+        assert_eq!(result.fragment, "x<em>abc</em>x");
+    }
+
+    #[test]
+    fn test_highlight_long_text() {
+        let options = SearchOptions::default();
+        let text = format!("{}needle{}", "a".repeat(100), "b".repeat(200));
+        let h = Highlighter::new("needle", &options);
+        let result = h.highlight(&text).unwrap();
+        assert_eq!(result.offsets, vec![(100, 106)]);
+        assert!(result.fragment.starts_with("…aaa"));
+        assert!(result.fragment.contains("<em>needle</em>"));
+        assert!(result.fragment.ends_with("bbb…"));
+    }
+
+    #[test]
+    fn test_highlight_multibyte() {
+        let options = SearchOptions::default();
+        let h = Highlighter::new("ÉTÉ", &options);
+        let result = h.highlight("un été chaud").unwrap();
+        assert_eq!(result.offsets, vec![(3, 6)]);
+        assert_eq!(result.fragment, "un <em>été</em> chaud");
+    }
+
+    #[test]
+    fn test_highlight_case_sensitive() {
+        let options = SearchOptions {
+            case_sensitive: true,
+            unaccent: false,
+        };
+        let h = Highlighter::new("Error", &options);
+        assert_eq!(h.highlight("error"), None);
+
+        let result = h.highlight("error Error").unwrap();
+        assert_eq!(result.offsets, vec![(6, 11)]);
+    }
+
+    #[test]
+    fn test_highlight_unaccent() {
+        let options = SearchOptions {
+            case_sensitive: false,
+            unaccent: true,
+        };
+        let h = Highlighter::new("ETE", &options);
+        let result = h.highlight("un été chaud").unwrap();
+        assert_eq!(result.offsets, vec![(3, 6)]);
+        assert_eq!(result.fragment, "un <em>été</em> chaud");
+
+        let options = SearchOptions::default();
+        let h = Highlighter::new("ETE", &options);
+        assert_eq!(h.highlight("un été chaud"), None);
+    }
+}
@@ -0,0 +1,47 @@
+//! Known IP addresses of users.
+//!
+//! The addresses from which a user has signed in are kept as digests in a
+//! set per user in the session store, for `RETENTION` since the last sign-in.
+//! A sign-in from an address which is not in the set is noticed to the user
+//! (see `mailer::security`).
+use std::net::IpAddr;
+
+use redis::{Connection, RedisResult};
+
+use crate::util::hash_token;
+
+pub const KEY_PREFIX: &str = "ki-";
+
+const RETENTION: usize = 90 * 24 * 60 * 60; // seconds (90 days)
+
+fn key(user_uuid: &str) -> String {
+    format!("{}{}", KEY_PREFIX, user_uuid)
+}
+
+pub struct KnownIp<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> KnownIp<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    /// Remembers the address, and returns true if it is unknown yet. The
+    /// first sign-in of the user is not the case, as nothing is known.
+    pub fn remember(
+        &mut self,
+        user_uuid: &str,
+        ip: &IpAddr,
+    ) -> RedisResult<bool> {
+        let key = key(user_uuid);
+        let (added, count): (i64, i64) = redis::pipe()
+            .atomic()
+            .sadd(&key, hash_token(&ip.to_string()))
+            .scard(&key)
+            .expire(&key, RETENTION)
+            .ignore()
+            .query(self.conn)?;
+        Ok(added == 1 && count > 1)
+    }
+}
@@ -0,0 +1,329 @@
+//! LDAP (and Active Directory) authentication backend.
+//!
+//! The entry for the username is searched with the service account (bind
+//! DN), then the password is verified by binding as the entry. The entry is
+//! linked to a user via an identity at the first login. Groups in `memberOf`
+//! are mapped to a role of the membership in the namespace given by config.
+use diesel::PgConnection;
+use ldap3::{LdapConn, LdapError, Scope, SearchEntry, ldap_escape};
+
+use crate::config::Config;
+use crate::id::IdGenerator;
+use crate::logger::Logger;
+use crate::model::identity::IdentityProvider;
+use crate::model::membership::{Membership, MembershipRole, NewMembership};
+use crate::model::namespace::Namespace;
+use crate::model::user::User;
+use crate::service::auth_backend::AuthBackend;
+use crate::service::oauth::{self, Profile};
+
+const MEMBER_OF: &str = "memberOf";
+
+/// An entry on the directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub dn: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub groups: Vec<String>,
+}
+
+pub struct Ldap<'a> {
+    config: &'a Config,
+    ids: &'a dyn IdGenerator,
+}
+
+impl<'a> Ldap<'a> {
+    pub fn new(config: &'a Config, ids: &'a dyn IdGenerator) -> Self {
+        Self { config, ids }
+    }
+
+    fn filter(&self, username: &str) -> String {
+        self.config
+            .ldap_user_filter
+            .replace("{username}", &ldap_escape(username))
+    }
+
+    // attribute names are case-insensitive
+    fn to_entry(&self, e: SearchEntry) -> Entry {
+        let values = |name: &str| -> Vec<String> {
+            e.attrs
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.to_owned())
+                .unwrap_or_else(Vec::new)
+        };
+        Entry {
+            dn: e.dn.to_string(),
+            email: values(&self.config.ldap_attribute_email)
+                .first()
+                .map(|v| v.to_lowercase()),
+            name: values(&self.config.ldap_attribute_name).first().cloned(),
+            groups: values(MEMBER_OF),
+        }
+    }
+
+    /// Returns the entry for the username if the password is correct.
+    pub fn bind(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<Entry>, LdapError> {
+        let c = self.config;
+        let mut ldap = LdapConn::new(&c.ldap_url)?;
+        ldap.simple_bind(&c.ldap_bind_dn, &c.ldap_bind_password)?
+            .success()?;
+
+        let attrs = vec![
+            c.ldap_attribute_email.as_str(),
+            c.ldap_attribute_name.as_str(),
+            MEMBER_OF,
+        ];
+        let filter = self.filter(username);
+        let (entries, _) = ldap
+            .search(&c.ldap_base_dn, Scope::Subtree, &filter, attrs)?
+            .success()?;
+
+        // the username must identify an entry
+        if entries.len() != 1 {
+            let _ = ldap.unbind();
+            return Ok(None);
+        }
+        let entry = SearchEntry::construct(entries.into_iter().next().unwrap());
+
+        let result = ldap.simple_bind(&entry.dn, password)?;
+        let _ = ldap.unbind();
+        if result.rc != 0 {
+            return Ok(None);
+        }
+        Ok(Some(self.to_entry(entry)))
+    }
+
+    // Keeps the membership in the namespace for the role.
+    fn sync_membership(
+        &self,
+        user: &User,
+        role: MembershipRole,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<(), &'static str> {
+        if self.config.ldap_namespace.is_empty() {
+            return Ok(());
+        }
+
+        let namespace =
+            Namespace::find_by_key(&self.config.ldap_namespace, conn, logger)
+                .ok_or("namespace for ldap is not found")?;
+
+        match Membership::find_by_namespace_id_and_user_id(
+            namespace.id,
+            user.id,
+            conn,
+            logger,
+        ) {
+            // the primary owner is managed on the console
+            Some(ref m)
+                if m.role == MembershipRole::PrimaryOwner || m.role == role =>
+            {
+                Ok(())
+            },
+            Some(m) => m.update_role(role, conn, logger).map(|_| ()),
+            None => {
+                let m = NewMembership {
+                    namespace_id: namespace.id,
+                    user_id: user.id,
+                    role,
+                };
+                Membership::insert(&m, conn, logger)
+                    .map(|_| ())
+                    .ok_or("failed to save membership")
+            },
+        }
+    }
+}
+
+/// Returns the role for the first mapping (`role=group DN`) which matches
+/// one of the groups.
+///
+/// The primary owner can't be mapped.
+pub fn role_for(
+    groups: &[String],
+    mapping: &[String],
+) -> Option<MembershipRole> {
+    mapping
+        .iter()
+        .filter_map(|m| {
+            let mut parts = m.splitn(2, '=');
+            let role = match parts.next()?.trim() {
+                "owner" => MembershipRole::Owner,
+                "member" => MembershipRole::Member,
+                _ => return None,
+            };
+            Some((role, parts.next()?.trim()))
+        })
+        .find(|(_, dn)| groups.iter().any(|g| g.eq_ignore_ascii_case(dn)))
+        .map(|(role, _)| role)
+}
+
+impl<'a> AuthBackend for Ldap<'a> {
+    fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<User, &'static str> {
+        // an empty password makes an unauthenticated bind, which succeeds
+        if username.is_empty() || password.is_empty() {
+            return Err("invalid credentials");
+        }
+
+        let entry = match self.bind(username, password) {
+            Ok(Some(e)) => e,
+            Ok(None) => return Err("invalid credentials"),
+            Err(e) => {
+                error!(logger, "error: {}", e);
+                return Err("directory is not available");
+            },
+        };
+
+        let profile = Profile {
+            uid: entry.dn.to_lowercase(),
+            email: entry.email.clone().ok_or("no email on the entry")?,
+            // the directory is trusted
+            email_verified: true,
+            name: entry.name.clone(),
+            username: Some(username.to_string()),
+        };
+        let user = oauth::sign_in(
+            IdentityProvider::Ldap,
+            &profile,
+            self.ids,
+            conn,
+            logger,
+        )?;
+
+        if let Some(role) =
+            role_for(&entry.groups, &self.config.ldap_group_roles)
+        {
+            self.sync_membership(&user, role, conn, logger)?;
+        }
+        Ok(user)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use diesel::prelude::*;
+
+    use crate::id::RandomIdGenerator;
+    use crate::model::namespace::namespaces;
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::test::{CONFIG, run};
+    use crate::model::user::users;
+    use crate::model::user::data::USERS;
+
+    const ADMINS: &str = "cn=admins,ou=groups,dc=example,dc=org";
+    const DEVELOPERS: &str = "cn=developers,ou=groups,dc=example,dc=org";
+
+    #[test]
+    fn test_filter() {
+        let mut config = CONFIG.clone();
+        config.ldap_user_filter =
+            "(&(objectClass=person)(uid={username}))".to_string();
+
+        let ldap = Ldap::new(&config, &RandomIdGenerator);
+        assert_eq!(
+            ldap.filter("oswald"),
+            "(&(objectClass=person)(uid=oswald))"
+        );
+        assert_eq!(
+            ldap.filter("*)(uid=*"),
+            "(&(objectClass=person)(uid=\\2a\\29\\28uid=\\2a))"
+        );
+    }
+
+    #[test]
+    fn test_to_entry() {
+        let ldap = Ldap::new(&CONFIG, &RandomIdGenerator);
+
+        let mut attrs = HashMap::new();
+        let email = "Oswald@Example.org".to_string();
+        attrs.insert("Mail".to_string(), vec![email]);
+        attrs.insert("cn".to_string(), vec!["Oswald".to_string()]);
+        attrs.insert(MEMBER_OF.to_string(), vec![ADMINS.to_string()]);
+
+        let entry = ldap.to_entry(SearchEntry {
+            dn: "uid=oswald,ou=people,dc=example,dc=org".to_string(),
+            attrs,
+            bin_attrs: HashMap::new(),
+        });
+        assert_eq!(
+            entry,
+            Entry {
+                dn: "uid=oswald,ou=people,dc=example,dc=org".to_string(),
+                email: Some("oswald@example.org".to_string()),
+                name: Some("Oswald".to_string()),
+                groups: vec![ADMINS.to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_role_for() {
+        let mapping = vec![
+            format!("owner={}", ADMINS),
+            format!("member={}", DEVELOPERS),
+            format!("primary_owner={}", DEVELOPERS),
+        ];
+
+        let groups = vec![DEVELOPERS.to_string(), ADMINS.to_uppercase()];
+        assert_eq!(role_for(&groups, &mapping), Some(MembershipRole::Owner));
+
+        let groups = vec![DEVELOPERS.to_string()];
+        assert_eq!(role_for(&groups, &mapping), Some(MembershipRole::Member));
+
+        let groups = vec!["cn=others,dc=example,dc=org".to_string()];
+        assert_eq!(role_for(&groups, &mapping), None);
+        assert_eq!(role_for(&[], &mapping), None);
+    }
+
+    #[test]
+    fn test_sync_membership() {
+        run(|conn, config, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let namespace = NAMESPACES.get("piano").unwrap();
+            let _ = diesel::insert_into(namespaces::table)
+                .values(namespace)
+                .execute(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut config = config.clone();
+            config.ldap_namespace = namespace.uuid.to_string();
+            let ldap = Ldap::new(&config, &RandomIdGenerator);
+
+            let role = MembershipRole::Member;
+            assert!(ldap.sync_membership(&user, role, conn, logger).is_ok());
+
+            let role = MembershipRole::Owner;
+            assert!(ldap.sync_membership(&user, role, conn, logger).is_ok());
+
+            let m = Membership::find_by_namespace_id_and_user_id(
+                namespace.id,
+                user.id,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(m.role, MembershipRole::Owner);
+        });
+    }
+}
@@ -0,0 +1,148 @@
+//! Maintenance mode for database maintenance windows.
+//!
+//! The flag is kept in the session store (Redis), so that all of the servers
+//! see it. It's switched via `/_/maintenance` by operators (see
+//! `request::local_only`). While it's on, `MaintenanceMode` answers the
+//! requests with 503 Service Unavailable and `Retry-After` without running the
+//! routes, except the health checks and the local-only endpoints (e.g. to
+//! switch it off).
+use std::io::Cursor;
+
+use redis::{Commands, Connection, RedisResult};
+use rocket::{Data, Outcome, Request, Response, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Status};
+use rocket::http::uri::Origin;
+
+use crate::request::maintenance::Maintenance;
+use crate::response::ApiError;
+use crate::ss::SsPoolHolder;
+
+pub const KEY: &str = "mt-mode";
+
+/// Seconds for `Retry-After` (if it's not given).
+pub const DEFAULT_RETRY_AFTER: u64 = 300;
+pub const MAX_RETRY_AFTER: u64 = 86400;
+
+const EXEMPT_PATHS: &[&str] = &[
+    "/_/config/reload",
+    "/_/fault",
+    "/_/health",
+    "/_/maintenance",
+    "/_/metrics",
+    "/_/readyz",
+    "/_api/v1/health",
+    "/v1/health",
+];
+
+// no route matches it (see `on_request`)
+const UNAVAILABLE_PATH: &str = "/_/maintenance/unavailable";
+
+pub fn is_exempt(path: &str) -> bool {
+    EXEMPT_PATHS.contains(&path)
+}
+
+pub struct MaintenanceFlag<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> MaintenanceFlag<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    /// Returns `Retry-After` (seconds) if it's on.
+    pub fn retry_after(&mut self) -> RedisResult<Option<u64>> {
+        self.conn.get(KEY)
+    }
+
+    pub fn get(&mut self) -> RedisResult<Maintenance> {
+        let retry_after = self.retry_after()?;
+        Ok(Maintenance {
+            enabled: retry_after.is_some(),
+            retry_after,
+        })
+    }
+
+    pub fn set(&mut self, maintenance: &Maintenance) -> RedisResult<()> {
+        if maintenance.enabled {
+            let retry_after =
+                maintenance.retry_after.unwrap_or(DEFAULT_RETRY_AFTER);
+            self.conn.set(KEY, retry_after)
+        } else {
+            let _: i64 = self.conn.del(KEY)?;
+            Ok(())
+        }
+    }
+}
+
+/// MaintenanceState
+///
+/// This is cached per request while it's on.
+#[derive(Clone, Debug)]
+struct MaintenanceState {
+    retry_after: u64, // seconds
+}
+
+/// MaintenanceMode answers requests with Service Unavailable while it's on.
+pub struct MaintenanceMode;
+
+impl Fairing for MaintenanceMode {
+    fn info(&self) -> Info {
+        Info {
+            name: "Maintenance Mode",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, req: &mut Request, _: &Data) {
+        if is_exempt(req.uri().path()) {
+            return;
+        }
+        // it's off if the store is not available
+        let retry_after = match req.guard::<State<SsPoolHolder>>() {
+            Outcome::Success(holder) => match holder.get() {
+                Some(mut conn) => MaintenanceFlag::new(&mut *conn)
+                    .retry_after()
+                    .unwrap_or(None),
+                None => None,
+            },
+            _ => None,
+        };
+        if let Some(retry_after) = retry_after {
+            req.local_cache(|| Some(MaintenanceState { retry_after }));
+            // the routes must not run (e.g. against the database)
+            req.set_uri(Origin::parse(UNAVAILABLE_PATH).unwrap());
+        }
+    }
+
+    fn on_response(&self, req: &Request, res: &mut Response) {
+        let retry_after = match *req.local_cache(|| None::<MaintenanceState>) {
+            Some(ref state) => state.retry_after,
+            None => return,
+        };
+        let body = ApiError::new(Status::ServiceUnavailable)
+            .code("maintenance")
+            .message("The service is under maintenance")
+            .to_json();
+        res.set_status(Status::ServiceUnavailable);
+        res.set_header(ContentType::JSON);
+        res.set_raw_header("Retry-After", retry_after.to_string());
+        res.set_sized_body(Cursor::new(body.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_exempt() {
+        assert!(is_exempt("/_/health"));
+        assert!(is_exempt("/v1/health"));
+        assert!(is_exempt("/_/maintenance"));
+        assert!(!is_exempt("/_/login"));
+        assert!(!is_exempt("/v1/namespace/hgetall"));
+        assert!(!is_exempt(UNAVAILABLE_PATH));
+    }
+}
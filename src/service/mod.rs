@@ -1,2 +1,37 @@
 pub mod account_activator;
+pub mod alert_rollup;
+pub mod anomaly_detector;
+pub mod auth_backend;
+pub mod badge;
+pub mod channel_notifier;
+pub mod confirmation;
+pub mod deprecation;
+pub mod email_suggester;
+#[cfg(feature = "error-tracking")]
+pub mod error_tracking;
+pub mod fault_injection;
+pub mod highlighter;
+pub mod known_ip;
+pub mod maintenance;
+pub mod ldap;
+pub mod mx_checker;
+pub mod namespace_backup;
+pub mod namespace_purger;
+pub mod notifier;
+pub mod oauth;
 pub mod password_updater;
+pub mod payload_template;
+pub mod pool_metrics;
+pub mod push_notifier;
+pub mod quiet_hours;
+pub mod read_only;
+pub mod render_metrics;
+pub mod request_metrics;
+pub mod secrets_provider;
+pub mod share_link;
+pub mod slo;
+pub mod stream_buffer;
+pub mod token_exchange;
+pub mod unfurl;
+pub mod user_agent;
+pub mod worker_heartbeat;
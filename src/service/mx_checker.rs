@@ -0,0 +1,323 @@
+//! MX checks of email addresses.
+//!
+//! If `EMAIL_MX_CHECK_URL` is set, the domain of an address is looked up
+//! through the resolver (DNS over HTTPS, JSON API) on registration, so that
+//! obviously undeliverable addresses are rejected before any activation email
+//! is sent. An address is undeliverable if its domain doesn't exist, it has a
+//! null MX (RFC 7505), or it has neither MX nor A/AAAA records (the implicit
+//! MX of RFC 5321).
+//!
+//! The results are cached as `mx-<domain>` in the session store for
+//! `EMAIL_MX_CHECK_CACHE_TTL` seconds. A failed lookup (e.g. a timeout or
+//! SERVFAIL) accepts the address, and it's not cached.
+use std::time::Duration;
+
+use redis::{Commands, Connection};
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::logger::Logger;
+
+pub const KEY_PREFIX: &str = "mx-";
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+// response codes
+const NOERROR: u64 = 0;
+const NXDOMAIN: u64 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Mx,
+}
+
+impl RecordType {
+    fn name(self) -> &'static str {
+        match self {
+            Self::A => "A",
+            Self::Aaaa => "AAAA",
+            Self::Mx => "MX",
+        }
+    }
+
+    fn code(self) -> u64 {
+        match self {
+            Self::A => 1,
+            Self::Aaaa => 28,
+            Self::Mx => 15,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Deliverability {
+    Deliverable,
+    Undeliverable,
+    /// The lookup has failed.
+    Unknown,
+}
+
+pub trait Resolver {
+    /// Returns the data of the records (e.g. `10 mx.example.org.` for MX),
+    /// or None if the domain doesn't exist.
+    fn query(
+        &self,
+        domain: &str,
+        kind: RecordType,
+    ) -> Result<Option<Vec<String>>, String>;
+}
+
+/// DNS over HTTPS with the JSON API (e.g. Cloudflare or Google).
+pub struct DnsOverHttps {
+    pub url: String,
+}
+
+impl Resolver for DnsOverHttps {
+    fn query(
+        &self,
+        domain: &str,
+        kind: RecordType,
+    ) -> Result<Option<Vec<String>>, String> {
+        let res = ureq::get(&self.url)
+            .timeout(TIMEOUT)
+            .query("name", domain)
+            .query("type", kind.name())
+            .set("Accept", "application/dns-json")
+            .call()
+            .map_err(|e| e.to_string())?
+            .into_json::<Value>()
+            .map_err(|e| e.to_string())?;
+        parse_answers(&res, kind)
+    }
+}
+
+// the data of the answers of the type (CNAMEs in the chain are skipped)
+fn parse_answers(
+    res: &Value,
+    kind: RecordType,
+) -> Result<Option<Vec<String>>, String> {
+    match res.get("Status").and_then(|v| v.as_u64()) {
+        Some(NOERROR) => (),
+        Some(NXDOMAIN) => return Ok(None),
+        Some(s) => return Err(format!("status {}", s)),
+        None => return Err("no status in response".to_string()),
+    }
+    let is_kind = |r: &&Value| {
+        r.get("type").and_then(|v| v.as_u64()) == Some(kind.code())
+    };
+    let answers = match res.get("Answer").and_then(|v| v.as_array()) {
+        Some(a) => a
+            .iter()
+            .filter(is_kind)
+            .filter_map(|r| r.get("data").and_then(|v| v.as_str()))
+            .map(|v| v.to_string())
+            .collect(),
+        None => vec![],
+    };
+    Ok(Some(answers))
+}
+
+// `0 .` means that the domain accepts no email (RFC 7505)
+fn is_null_mx(records: &[String]) -> bool {
+    records.len() == 1 && records[0].split_whitespace().nth(1) == Some(".")
+}
+
+fn domain_of(email: &str) -> Option<String> {
+    let i = email.rfind('@')?;
+    let domain = email[i + 1..].trim_end_matches('.').to_lowercase();
+    if domain.is_empty() {
+        return None;
+    }
+    Some(domain)
+}
+
+/// Looks up the domain without the cache.
+pub fn lookup(
+    resolver: &dyn Resolver,
+    domain: &str,
+) -> Result<Deliverability, String> {
+    let records = match resolver.query(domain, RecordType::Mx)? {
+        Some(v) => v,
+        None => return Ok(Deliverability::Undeliverable),
+    };
+    if is_null_mx(&records) {
+        return Ok(Deliverability::Undeliverable);
+    }
+    if !records.is_empty() {
+        return Ok(Deliverability::Deliverable);
+    }
+    for kind in &[RecordType::A, RecordType::Aaaa] {
+        if resolver.query(domain, *kind)?.map_or(false, |v| !v.is_empty()) {
+            return Ok(Deliverability::Deliverable);
+        }
+    }
+    Ok(Deliverability::Undeliverable)
+}
+
+pub struct MxChecker<'a> {
+    resolver: Box<dyn Resolver>,
+    cache_ttl: u64,
+    logger: &'a Logger,
+}
+
+impl<'a> MxChecker<'a> {
+    pub fn new(
+        resolver: Box<dyn Resolver>,
+        cache_ttl: u64,
+        logger: &'a Logger,
+    ) -> Self {
+        Self {
+            resolver,
+            cache_ttl,
+            logger,
+        }
+    }
+
+    /// Returns None if the MX checks are disabled.
+    pub fn from_config(config: &Config, logger: &'a Logger) -> Option<Self> {
+        if config.email_mx_check_url.is_empty() {
+            return None;
+        }
+        let resolver = DnsOverHttps {
+            url: config.email_mx_check_url.to_string(),
+        };
+        Some(Self::new(
+            Box::new(resolver),
+            config.email_mx_check_cache_ttl,
+            logger,
+        ))
+    }
+
+    /// Checks the domain of the (normalized) address.
+    pub fn check(
+        &self,
+        email: &str,
+        ss_conn: &mut Connection,
+    ) -> Deliverability {
+        let domain = match domain_of(email) {
+            Some(d) => d,
+            None => return Deliverability::Undeliverable,
+        };
+        let key = format!("{}{}", KEY_PREFIX, domain);
+        match ss_conn.get::<_, Option<String>>(&key) {
+            Ok(Some(v)) if v == "1" => return Deliverability::Deliverable,
+            Ok(Some(_)) => return Deliverability::Undeliverable,
+            Ok(None) => (),
+            Err(e) => error!(self.logger, "err: {}", e),
+        }
+
+        match lookup(&*self.resolver, &domain) {
+            Ok(d) => {
+                let value = match d {
+                    Deliverability::Deliverable => "1",
+                    _ => "0",
+                };
+                let result: Result<String, _> =
+                    ss_conn.set_ex(&key, value, self.cache_ttl as usize);
+                if let Err(e) = result {
+                    error!(self.logger, "err: {}", e);
+                }
+                d
+            },
+            Err(e) => {
+                warn!(self.logger, "mx check failed: {} {}", domain, e);
+                Deliverability::Unknown
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    // records by the domain and the type (a missing domain doesn't exist)
+    type Records = HashMap<&'static str, Vec<(RecordType, &'static str)>>;
+
+    struct StaticResolver(Records);
+
+    impl Resolver for StaticResolver {
+        fn query(
+            &self,
+            domain: &str,
+            kind: RecordType,
+        ) -> Result<Option<Vec<String>>, String> {
+            if domain == "timeout.example" {
+                return Err("timeout".to_string());
+            }
+            Ok(self.0.get(domain).map(|records| {
+                records
+                    .iter()
+                    .filter(|(k, _)| *k == kind)
+                    .map(|(_, v)| v.to_string())
+                    .collect()
+            }))
+        }
+    }
+
+    #[test]
+    fn test_parse_answers() {
+        let res = json!({
+            "Status": 0,
+            "Answer": [
+                {"name": "example.org", "type": 5, "data": "mail.example."},
+                {"name": "mail.example", "type": 15, "data": "10 mx.example."},
+            ],
+        });
+        assert_eq!(
+            parse_answers(&res, RecordType::Mx),
+            Ok(Some(vec!["10 mx.example.".to_string()]))
+        );
+        assert_eq!(parse_answers(&res, RecordType::A), Ok(Some(vec![])));
+
+        let res = json!({"Status": 0});
+        assert_eq!(parse_answers(&res, RecordType::Mx), Ok(Some(vec![])));
+
+        let res = json!({"Status": 3});
+        assert_eq!(parse_answers(&res, RecordType::Mx), Ok(None));
+
+        let res = json!({"Status": 2});
+        assert!(parse_answers(&res, RecordType::Mx).is_err());
+        assert!(parse_answers(&json!({}), RecordType::Mx).is_err());
+    }
+
+    #[test]
+    fn test_domain_of() {
+        assert_eq!(
+            domain_of("oswald@Example.ORG."),
+            Some("example.org".to_string())
+        );
+        assert_eq!(domain_of("oswald@"), None);
+        assert_eq!(domain_of("oswald"), None);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut records: Records = HashMap::new();
+        records.insert("mx.example", vec![(RecordType::Mx, "10 mx.example.")]);
+        records.insert("a.example", vec![(RecordType::A, "192.0.2.1")]);
+        records.insert("aaaa.example", vec![(RecordType::Aaaa, "2001:db8::1")]);
+        records.insert("null.example", vec![(RecordType::Mx, "0 .")]);
+        records.insert("empty.example", vec![]);
+        let resolver = StaticResolver(records);
+
+        let cases = [
+            ("mx.example", Ok(Deliverability::Deliverable)),
+            ("a.example", Ok(Deliverability::Deliverable)),
+            ("aaaa.example", Ok(Deliverability::Deliverable)),
+            ("null.example", Ok(Deliverability::Undeliverable)),
+            ("empty.example", Ok(Deliverability::Undeliverable)),
+            ("unknown.example", Ok(Deliverability::Undeliverable)),
+            ("timeout.example", Err("timeout".to_string())),
+        ];
+        for (domain, expected) in cases.iter() {
+            assert_eq!(&lookup(&resolver, domain), expected, "{}", domain);
+        }
+    }
+}
@@ -0,0 +1,1085 @@
+//! Export/Import of a namespace as a portable backup.
+//!
+//! A backup is a directory which contains NDJSON chunk files (`00000.ndjson`,
+//! `00001.ndjson`, ...). Each line is a `Record` and the first one must be a
+//! header. Records don't have any database ids, so that the backup can be
+//! restored into another deployment. Members are identified by their email
+//! addresses and the streams by the uuid in the backup.
+//!
+//! The directory also has a manifest (`manifest.json`) with the number of
+//! the lines and the SHA-256 checksum of each chunk file. It's written after
+//! all the chunks, so that a backup can be verified later (see `verify`).
+//!
+//! A backup can also be written into (and read from) a single stream (see
+//! `export_to`). Its header has no `exported_at`, so that the output is the
+//! same for the same records (e.g. for offsite backups by cron).
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{self, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use diesel::result::Error;
+use postgres::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::id::{IdGenerator, RandomIdGenerator};
+use crate::logger::Logger;
+use crate::model::membership::{
+    Membership, MembershipRole, NewMembership, memberships,
+};
+use crate::model::message::{
+    AgentType, LogFormat, LogLevel, Message, MessageRow, NewMessage, messages,
+};
+use crate::model::message_body::MessageBody;
+use crate::model::namespace::{Namespace, NewNamespace, namespaces};
+use crate::model::stream::{NewStream, Stream, streams};
+use crate::model::user::{User, users};
+
+pub const VERSION: u32 = 1;
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+const CHUNK_SIZE: usize = 10_000; // records per file
+const BATCH_SIZE: i64 = 1_000; // messages per query
+const COPY_SIZE: usize = 10_000; // messages per COPY
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Header {
+        version: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exported_at: Option<NaiveDateTime>,
+        // messages created before it are not included
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        since: Option<NaiveDateTime>,
+    },
+    Namespace {
+        name: String,
+        description: Option<String>,
+    },
+    Membership {
+        email: String,
+        role: String,
+    },
+    Stream {
+        uuid: String,
+        name: String,
+        description: Option<String>,
+    },
+    Message {
+        stream: String,
+        agent: Option<String>, // email of the person
+        code: Option<String>,
+        lang: String,
+        level: String,
+        format: String,
+        title: String,
+        content: Option<String>,
+        created_at: NaiveDateTime,
+    },
+}
+
+/// Manifest of a backup directory.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub chunks: Vec<Chunk>,
+}
+
+/// Chunk is an entry of the manifest.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Chunk {
+    pub name: String, // file name in the directory
+    pub lines: usize,
+    pub sha256: String, // hex
+}
+
+// writes records into files in the directory for each CHUNK_SIZE lines
+struct ChunkWriter {
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+    chunks: Vec<Chunk>,
+    count: usize,
+    hasher: Sha256,
+    writer: Option<BufWriter<File>>,
+}
+
+impl ChunkWriter {
+    fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            files: vec![],
+            chunks: vec![],
+            count: 0,
+            hasher: Sha256::new(),
+            writer: None,
+        }
+    }
+
+    fn write(&mut self, record: &Record) -> Result<(), &'static str> {
+        if self.writer.is_none() || self.count >= CHUNK_SIZE {
+            self.close()?;
+            let path = self.dir.join(format!("{:05}.ndjson", self.files.len()));
+            let file = File::create(&path).map_err(|_| "failed to create")?;
+            self.files.push(path);
+            self.writer = Some(BufWriter::new(file));
+            self.count = 0;
+        }
+
+        let mut line =
+            serde_json::to_string(record).map_err(|_| "failed to serialize")?;
+        line.push('\n');
+        let writer = self.writer.as_mut().unwrap();
+        writer
+            .write_all(line.as_bytes())
+            .map_err(|_| "failed to write")?;
+        self.hasher.update(line.as_bytes());
+        self.count += 1;
+        Ok(())
+    }
+
+    // flushes the current file and adds it to the chunks
+    fn close(&mut self) -> Result<(), &'static str> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush().map_err(|_| "failed to write")?;
+            let hasher = mem::take(&mut self.hasher);
+            self.chunks.push(Chunk {
+                name: file_name(self.files.last().unwrap()),
+                lines: self.count,
+                sha256: to_hex(&hasher.finalize()),
+            });
+        }
+        Ok(())
+    }
+
+    // closes the current file, and writes the manifest
+    fn finish(&mut self) -> Result<(), &'static str> {
+        self.close()?;
+        let manifest = Manifest {
+            version: VERSION,
+            chunks: mem::take(&mut self.chunks),
+        };
+        let json = serde_json::to_string_pretty(&manifest)
+            .map_err(|_| "failed to serialize")?;
+        fs::write(self.dir.join(MANIFEST_FILE), json)
+            .map_err(|_| "failed to write manifest")
+    }
+}
+
+pub struct NamespaceBackup<'a> {
+    conn: &'a PgConnection,
+    logger: &'a Logger,
+}
+
+impl<'a> NamespaceBackup<'a> {
+    pub fn new(conn: &'a PgConnection, logger: &'a Logger) -> Self {
+        Self { conn, logger }
+    }
+
+    /// Writes all the records of the namespace into the directory and returns
+    /// the chunk files.
+    pub fn export(
+        &self,
+        namespace: &Namespace,
+        dir: &Path,
+        exported_at: NaiveDateTime,
+    ) -> Result<Vec<PathBuf>, &'static str> {
+        fs::create_dir_all(dir).map_err(|_| "failed to create directory")?;
+
+        let mut w = ChunkWriter::new(dir);
+        w.write(&Record::Header {
+            version: VERSION,
+            exported_at: Some(exported_at),
+            since: None,
+        })?;
+        self.write_records(namespace, None, |r| w.write(r))?;
+        w.finish()?;
+
+        info!(self.logger, "exported: {} into {:?}", namespace, dir);
+        Ok(w.files)
+    }
+
+    /// Writes the records of the namespace as NDJSON into the writer and
+    /// returns the number of the lines. Messages created before `since` are
+    /// not included.
+    ///
+    /// Records are in the order of the ids, so the output doesn't change
+    /// unless the records are changed.
+    pub fn export_to<W: Write>(
+        &self,
+        namespace: &Namespace,
+        since: Option<NaiveDateTime>,
+        writer: &mut W,
+    ) -> Result<usize, &'static str> {
+        let mut count = 0;
+        let mut write = |record: &Record| -> Result<(), &'static str> {
+            let line = serde_json::to_string(record)
+                .map_err(|_| "failed to serialize")?;
+            writeln!(writer, "{}", line).map_err(|_| "failed to write")?;
+            count += 1;
+            Ok(())
+        };
+        write(&Record::Header {
+            version: VERSION,
+            exported_at: None,
+            since,
+        })?;
+        self.write_records(namespace, since, &mut write)?;
+        writer.flush().map_err(|_| "failed to write")?;
+
+        info!(self.logger, "exported: {} ({} lines)", namespace, count);
+        Ok(count)
+    }
+
+    // passes the records (except the header) to `write` in order
+    fn write_records<F>(
+        &self,
+        namespace: &Namespace,
+        since: Option<NaiveDateTime>,
+        mut write: F,
+    ) -> Result<(), &'static str>
+    where
+        F: FnMut(&Record) -> Result<(), &'static str>,
+    {
+        let since =
+            since.unwrap_or_else(|| NaiveDateTime::from_timestamp(0, 0));
+
+        write(&Record::Namespace {
+            name: namespace.name.clone(),
+            description: namespace.description.clone(),
+        })?;
+
+        let q = memberships::table
+            .inner_join(users::table)
+            .filter(memberships::namespace_id.eq(namespace.id))
+            .filter(memberships::revoked_at.is_null())
+            .select((users::email, memberships::role))
+            .order(memberships::id.asc());
+
+        info!(self.logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        let members = q
+            .load::<(String, MembershipRole)>(self.conn)
+            .map_err(|e| self.log(e, "failed to load memberships"))?;
+        for (email, role) in members {
+            write(&Record::Membership {
+                email,
+                role: role.to_string(),
+            })?;
+        }
+
+        let q = streams::table
+            .filter(streams::namespace_id.eq(namespace.id))
+            .order(streams::id.asc());
+
+        info!(self.logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+        let streams = q
+            .load::<Stream>(self.conn)
+            .map_err(|e| self.log(e, "failed to load streams"))?;
+        for s in streams.iter() {
+            write(&Record::Stream {
+                uuid: s.uuid.to_string(),
+                name: s.name.clone(),
+                description: s.description.clone(),
+            })?;
+        }
+
+        let mut agents: HashMap<i64, Option<String>> = HashMap::new();
+        for s in streams.iter() {
+            let mut last_id = "".to_string();
+            loop {
+                let q = messages::table
+                    .filter(messages::stream_id.eq(s.id))
+                    .filter(messages::id.gt(&last_id))
+                    .filter(messages::created_at.ge(since))
+                    .order(messages::id.asc())
+                    .limit(BATCH_SIZE);
+
+                info!(self.logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+                let mut batch = q
+                    .load::<Message>(self.conn)
+                    .map_err(|e| self.log(e, "failed to load messages"))?;
+                if batch.is_empty() {
+                    break;
+                }
+                MessageBody::fill(&mut batch, self.conn, self.logger)
+                    .map_err(|e| self.log(e, "failed to load bodies"))?;
+                for m in batch.iter() {
+                    let agent = match m.agent_type {
+                        AgentType::Person => agents
+                            .entry(m.agent_id)
+                            .or_insert_with(|| {
+                                User::find_by_id(
+                                    m.agent_id,
+                                    self.conn,
+                                    self.logger,
+                                )
+                                .map(|u| u.email)
+                            })
+                            .clone(),
+                        AgentType::Client => None,
+                    };
+                    write(&Record::Message {
+                        stream: s.uuid.to_string(),
+                        agent,
+                        code: m.code.clone(),
+                        lang: m.lang.clone(),
+                        level: m.level.to_string(),
+                        format: m.format.to_string(),
+                        title: m.title.clone(),
+                        content: m.content.clone(),
+                        created_at: m.created_at,
+                    })?;
+                    last_id = m.id.clone();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores the backup in the directory as a new namespace.
+    ///
+    /// Members who don't have an account in this deployment are skipped, and
+    /// messages by unknown agents are attributed to the first owner.
+    pub fn import(&self, dir: &Path) -> Result<Namespace, &'static str> {
+        let files = chunk_files(dir)?;
+
+        self.restore_all(|f| read_records(&files, f))
+    }
+
+    /// Restores the backup in the reader (e.g. the output of `export_to`) as
+    /// a new namespace like `import`.
+    pub fn import_from<R: BufRead>(
+        &self,
+        reader: R,
+    ) -> Result<Namespace, &'static str> {
+        self.restore_all(|f| read_lines(reader, &mut true, f))
+    }
+
+    // restores the records by `read` in a transaction
+    fn restore_all<R>(&self, read: R) -> Result<Namespace, &'static str>
+    where R: FnOnce(Callback) -> Result<(), &'static str> {
+        let mut failure: &'static str = "failed to import";
+        let result = self.conn.transaction::<Namespace, Error, _>(|| {
+            self.restore(read, false)
+                .and_then(|r| r.namespace.ok_or("no namespace"))
+                .map_err(|e| {
+                    failure = e;
+                    Error::RollbackTransaction
+                })
+        });
+        result.map_err(|_| failure)
+    }
+
+    /// Restores the backup like `import`, but the messages are saved using
+    /// COPY through the client (see `Message::copy_insert`), which is much
+    /// faster for a large backup.
+    ///
+    /// The messages are copied after the commit of the other records, because
+    /// the client is another connection. If it fails, the restored namespace
+    /// is removed.
+    pub fn import_by_copy(
+        &self,
+        dir: &Path,
+        client: &mut Client,
+    ) -> Result<Namespace, &'static str> {
+        let files = chunk_files(dir)?;
+
+        let mut failure: &'static str = "failed to import";
+        let result = self.conn.transaction::<Restoration, Error, _>(|| {
+            self.restore(|f| read_records(&files, f), true).map_err(|e| {
+                failure = e;
+                Error::RollbackTransaction
+            })
+        });
+        let mut restoration = result.map_err(|_| failure)?;
+        let namespace = restoration.namespace.take().ok_or("no namespace")?;
+
+        match self.copy_messages(&files, &mut restoration, client) {
+            Err(e) => {
+                self.discard(&namespace);
+                Err(e)
+            },
+            Ok(n) => {
+                info!(self.logger, "copied: {} messages", n);
+                Ok(namespace)
+            },
+        }
+    }
+
+    // saves the records by `read` except messages if `copy` is true
+    fn restore<R>(
+        &self,
+        read: R,
+        copy: bool,
+    ) -> Result<Restoration, &'static str>
+    where
+        R: FnOnce(Callback) -> Result<(), &'static str>,
+    {
+        let mut r = Restoration::default();
+
+        read(&mut |record: Record| {
+            match record {
+                Record::Header { .. } => return Err("invalid record"),
+                Record::Namespace { name, description } => {
+                    if r.namespace.is_some() {
+                        return Err("invalid record");
+                    }
+                    let n = NewNamespace {
+                        name,
+                        description,
+                        streams_count: 0,
+                    };
+                    r.namespace = Some(
+                        Namespace::insert(&n, self.conn, self.logger)
+                            .ok_or("failed to save namespace")?,
+                    );
+                },
+                Record::Membership { email, role } => {
+                    let namespace =
+                        r.namespace.as_ref().ok_or("invalid record")?;
+                    let user = match User::find_by_email(
+                        &email,
+                        self.conn,
+                        self.logger,
+                    ) {
+                        Some(u) => u,
+                        None => {
+                            warn!(self.logger, "skip member: {}", email);
+                            return Ok(());
+                        },
+                    };
+                    let role = MembershipRole::from(role);
+                    if r.owner_id.is_none() && role != MembershipRole::Member {
+                        r.owner_id = Some(user.id);
+                    }
+                    let m = NewMembership {
+                        namespace_id: namespace.id,
+                        user_id: user.id,
+                        role,
+                    };
+                    Membership::insert(&m, self.conn, self.logger)
+                        .ok_or("failed to save membership")?;
+                    r.agents.insert(email, Some(user.id));
+                },
+                Record::Stream {
+                    uuid,
+                    name,
+                    description,
+                } => {
+                    let namespace =
+                        r.namespace.as_ref().ok_or("invalid record")?;
+                    let s = NewStream {
+                        namespace_id: namespace.id,
+                        name,
+                        description,
+                    };
+                    let stream = Stream::insert(&s, self.conn, self.logger)
+                        .ok_or("failed to save stream")?;
+                    r.streams.insert(uuid, stream.id);
+                },
+                // see copy_messages
+                Record::Message { .. } if copy => (),
+                Record::Message { .. } => {
+                    let row = self.message_row(record, &mut r)?;
+                    let q = diesel::insert_into(messages::table).values((
+                        &row.message,
+                        messages::id.eq(&row.id),
+                        messages::created_at.eq(row.created_at),
+                    ));
+
+                    info!(
+                        self.logger,
+                        "{}",
+                        debug_query::<Pg, _>(&q).to_string()
+                    );
+
+                    q.execute(self.conn)
+                        .map_err(|e| self.log(e, "failed to save message"))?;
+                },
+            }
+            Ok(())
+        })?;
+
+        if let Some(ref namespace) = r.namespace {
+            info!(self.logger, "imported: {}", namespace);
+        }
+        Ok(r)
+    }
+
+    // saves the messages in a transaction of the client for each COPY_SIZE
+    fn copy_messages(
+        &self,
+        files: &[PathBuf],
+        r: &mut Restoration,
+        client: &mut Client,
+    ) -> Result<u64, &'static str> {
+        let mut tx = client
+            .transaction()
+            .map_err(|e| self.log(e, "failed to begin transaction"))?;
+
+        let mut count: u64 = 0;
+        let mut rows: Vec<MessageRow> = Vec::with_capacity(COPY_SIZE);
+        read_records(files, |record| {
+            if let Record::Message { .. } = record {
+                rows.push(self.message_row(record, r)?);
+            }
+            if rows.len() >= COPY_SIZE {
+                count += Message::copy_insert(&rows, &mut tx, self.logger)
+                    .ok_or("failed to save messages")?;
+                rows.clear();
+            }
+            Ok(())
+        })?;
+        count += Message::copy_insert(&rows, &mut tx, self.logger)
+            .ok_or("failed to save messages")?;
+
+        tx.commit().map_err(|e| self.log(e, "failed to commit"))?;
+        Ok(count)
+    }
+
+    fn message_row(
+        &self,
+        record: Record,
+        r: &mut Restoration,
+    ) -> Result<MessageRow, &'static str> {
+        match record {
+            Record::Message {
+                stream,
+                agent,
+                code,
+                lang,
+                level,
+                format,
+                title,
+                content,
+                created_at,
+            } => {
+                let stream_id =
+                    *r.streams.get(&stream).ok_or("unknown stream")?;
+                let agent_id = agent
+                    .and_then(|email| {
+                        *r.agents.entry(email.clone()).or_insert_with(|| {
+                            User::find_by_email(&email, self.conn, self.logger)
+                                .map(|u| u.id)
+                        })
+                    })
+                    .or(r.owner_id)
+                    .ok_or("no owner")?;
+                let message = NewMessage {
+                    agent_id,
+                    agent_type: AgentType::Person,
+                    stream_id,
+                    code,
+                    lang,
+                    level: LogLevel::from(level),
+                    format: LogFormat::from(format),
+                    title: Some(title),
+                    content,
+                    trace_id: None,
+                    span_id: None,
+                    hostname: None,
+                    service: None,
+                    environment: None,
+                    tags: vec![],
+                };
+                // the new id is at the original time
+                let id = RandomIdGenerator
+                    .ulid(DateTime::<Utc>::from_utc(created_at, Utc));
+                Ok(MessageRow {
+                    id,
+                    message,
+                    created_at,
+                })
+            },
+            _ => Err("invalid record"),
+        }
+    }
+
+    // removes the restored records (there is no message)
+    fn discard(&self, namespace: &Namespace) {
+        info!(self.logger, "discard: {}", namespace);
+
+        let id = namespace.id;
+        let result = self.conn.transaction::<usize, Error, _>(|| {
+            diesel::delete(
+                memberships::table.filter(memberships::namespace_id.eq(id)),
+            )
+            .execute(self.conn)?;
+            diesel::delete(streams::table.filter(streams::namespace_id.eq(id)))
+                .execute(self.conn)?;
+            diesel::delete(namespaces::table.filter(namespaces::id.eq(id)))
+                .execute(self.conn)
+        });
+        if let Err(e) = result {
+            error!(self.logger, "err: {}", e);
+        }
+    }
+
+    fn log(&self, e: impl fmt::Display, message: &'static str) -> &'static str {
+        error!(self.logger, "err: {}", e);
+        message
+    }
+}
+
+// the records restored in this deployment, which are referred by messages
+#[derive(Default)]
+struct Restoration {
+    namespace: Option<Namespace>,
+    owner_id: Option<i64>,
+    streams: HashMap<String, i64>, // uuid in the backup -> id
+    agents: HashMap<String, Option<i64>>, // email -> id
+}
+
+/// Verifies the chunk files in the backup directory with its manifest (the
+/// names, the number of the lines and the checksums), and returns the number
+/// of the records.
+pub fn verify(dir: &Path) -> Result<usize, &'static str> {
+    let file = File::open(dir.join(MANIFEST_FILE)).map_err(|_| "no manifest")?;
+    let manifest: Manifest = serde_json::from_reader(BufReader::new(file))
+        .map_err(|_| "invalid manifest")?;
+    if manifest.version != VERSION {
+        return Err("unsupported backup");
+    }
+
+    let names: Vec<String> =
+        chunk_files(dir)?.iter().map(|p| file_name(p)).collect();
+    if names.len() != manifest.chunks.len() ||
+        names.iter().zip(manifest.chunks.iter()).any(|(n, c)| n != &c.name)
+    {
+        return Err("unexpected chunk files");
+    }
+
+    let mut count = 0;
+    for chunk in manifest.chunks.iter() {
+        let (lines, sha256) = digest(&dir.join(&chunk.name))?;
+        if lines != chunk.lines {
+            return Err("line count mismatch");
+        }
+        if sha256 != chunk.sha256 {
+            return Err("checksum mismatch");
+        }
+        count += lines;
+    }
+    Ok(count)
+}
+
+/// The result of `verify` per backup directory.
+pub type Verification = (PathBuf, Result<usize, &'static str>);
+
+/// Verifies each backup directory in the root (e.g. `BACKUP_DIRECTORY`).
+pub fn verify_all(root: &Path) -> Result<Vec<Verification>, &'static str> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(root)
+        .map_err(|_| "failed to read directory")?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_dir())
+        .collect();
+    dirs.sort();
+    Ok(dirs
+        .into_iter()
+        .map(|d| {
+            let result = verify(&d);
+            (d, result)
+        })
+        .collect())
+}
+
+// returns the number of the lines and the checksum of the file
+fn digest(path: &Path) -> Result<(usize, String), &'static str> {
+    let mut file = File::open(path).map_err(|_| "failed to open")?;
+    let mut hasher = Sha256::new();
+    let mut lines = 0;
+    let mut buf = [0; 8192];
+    loop {
+        let n = file.read(&mut buf).map_err(|_| "failed to read")?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        lines += buf[..n].iter().filter(|b| **b == b'\n').count();
+    }
+    Ok((lines, to_hex(&hasher.finalize())))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn chunk_files(dir: &Path) -> Result<Vec<PathBuf>, &'static str> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|_| "failed to read directory")?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().map_or(false, |e| e == "ndjson"))
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        return Err("no chunk file");
+    }
+    Ok(files)
+}
+
+// a function which takes each record
+type Callback<'a> = &'a mut dyn FnMut(Record) -> Result<(), &'static str>;
+
+// reads the records in the files in order, after the header
+fn read_records<F>(files: &[PathBuf], mut f: F) -> Result<(), &'static str>
+where F: FnMut(Record) -> Result<(), &'static str> {
+    let mut header = true;
+    for path in files {
+        let file = File::open(path).map_err(|_| "failed to open")?;
+        read_lines(BufReader::new(file), &mut header, &mut f)?;
+    }
+    Ok(())
+}
+
+// reads the records in the lines, `header` is true until the header is read
+fn read_lines<R, F>(
+    reader: R,
+    header: &mut bool,
+    mut f: F,
+) -> Result<(), &'static str>
+where
+    R: BufRead,
+    F: FnMut(Record) -> Result<(), &'static str>,
+{
+    for line in reader.lines() {
+        let line = line.map_err(|_| "failed to read")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record =
+            serde_json::from_str(&line).map_err(|_| "invalid record")?;
+
+        if *header {
+            match record {
+                Record::Header { version, .. } if version == VERSION => {
+                    *header = false;
+                    continue;
+                },
+                _ => return Err("unsupported backup"),
+            }
+        }
+        f(record)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    use chrono::TimeZone;
+
+    use crate::model::namespace::namespaces;
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::stream::data::STREAMS;
+    use crate::model::test::run;
+    use crate::model::user::data::USERS;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "eloquentlog-{}-{}",
+            name,
+            Utc::now().timestamp_nanos()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    // the fixtures have their ids, thus the restored ones would conflict
+    fn sync_sequences(conn: &PgConnection) {
+        for table in &["namespaces", "streams"] {
+            let q = format!(
+                "SELECT setval('{t}_id_seq', (SELECT max(id) FROM {t}))",
+                t = table
+            );
+            let _ = diesel::sql_query(q).execute(conn).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_record_serialization() {
+        let record = Record::Membership {
+            email: "oswald@example.org".to_string(),
+            role: "owner".to_string(),
+        };
+        let line = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            line,
+            concat!(
+                r#"{"type":"membership","#,
+                r#""email":"oswald@example.org","role":"owner"}"#
+            )
+        );
+        assert_eq!(serde_json::from_str::<Record>(&line).unwrap(), record);
+    }
+
+    #[test]
+    fn test_chunk_writer() {
+        let dir = temp_dir("chunk");
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut w = ChunkWriter::new(&dir);
+        let record = Record::Namespace {
+            name: "piano".to_string(),
+            description: None,
+        };
+        for _ in 0..(CHUNK_SIZE + 1) {
+            w.write(&record).unwrap();
+        }
+        w.finish().unwrap();
+
+        assert_eq!(w.files.len(), 2);
+        let lines = fs::read_to_string(&w.files[1]).unwrap();
+        assert_eq!(lines.lines().count(), 1);
+
+        let json = fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        let manifest: Manifest = serde_json::from_str(&json).unwrap();
+        assert_eq!(manifest.chunks.len(), 2);
+        assert_eq!(manifest.chunks[0].name, "00000.ndjson");
+        assert_eq!(manifest.chunks[0].lines, CHUNK_SIZE);
+        assert_eq!(manifest.chunks[1].lines, 1);
+        assert_eq!(
+            manifest.chunks[1].sha256,
+            to_hex(&Sha256::digest(lines.as_bytes()))
+        );
+        assert_eq!(verify(&dir), Ok(CHUNK_SIZE + 1));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_verify() {
+        let root = temp_dir("verify");
+        let dir = root.join("piano");
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(verify(&dir), Err("no manifest"));
+
+        let mut w = ChunkWriter::new(&dir);
+        w.write(&Record::Namespace {
+            name: "piano".to_string(),
+            description: None,
+        })
+        .unwrap();
+        w.finish().unwrap();
+        assert_eq!(verify(&dir), Ok(1));
+
+        let path = dir.join("00000.ndjson");
+        let lines = fs::read_to_string(&path).unwrap();
+        fs::write(&path, lines.replace("piano", "forte")).unwrap();
+        assert_eq!(verify(&dir), Err("checksum mismatch"));
+
+        fs::write(&path, format!("{}{}", lines, lines)).unwrap();
+        assert_eq!(verify(&dir), Err("line count mismatch"));
+
+        fs::write(&path, &lines).unwrap();
+        fs::write(dir.join("00001.ndjson"), &lines).unwrap();
+        assert_eq!(verify(&dir), Err("unexpected chunk files"));
+
+        let results = verify_all(&root).unwrap();
+        assert_eq!(results, vec![(dir, Err("unexpected chunk files"))]);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_import_unsupported_backup() {
+        run(|conn, _, logger| {
+            let dir = temp_dir("unsupported");
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join("00000.ndjson"),
+                r#"{"type":"namespace","name":"piano","description":null}"#,
+            )
+            .unwrap();
+
+            let backup = NamespaceBackup::new(conn, logger);
+            assert_eq!(backup.import(&dir).err(), Some("unsupported backup"));
+
+            let _ = fs::remove_dir_all(&dir);
+        });
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let m = NewMembership {
+                namespace_id: namespace.id,
+                user_id: user.id,
+                role: MembershipRole::PrimaryOwner,
+            };
+            let _ = Membership::insert(&m, conn, logger).unwrap();
+
+            let stream = diesel::insert_into(streams::table)
+                .values(STREAMS.get("oswald's stream").unwrap())
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let m = NewMessage {
+                agent_id: user.id,
+                agent_type: AgentType::Person,
+                stream_id: stream.id,
+                title: Some("title".to_string()),
+                content: Some("content".to_string()),
+
+                ..Default::default()
+            };
+            let id = RandomIdGenerator.ulid(Utc::now());
+            let _ = Message::insert(&m, &id, conn, logger).unwrap();
+
+            let dir = temp_dir("backup");
+            let backup = NamespaceBackup::new(conn, logger);
+
+            let exported_at = Utc.ymd(2021, 6, 1).and_hms(0, 0, 0).naive_utc();
+            let files = backup.export(&namespace, &dir, exported_at).unwrap();
+            assert_eq!(files.len(), 1);
+
+            let lines = fs::read_to_string(&files[0]).unwrap();
+            assert_eq!(lines.lines().count(), 5);
+            assert_eq!(verify(&dir), Ok(5));
+
+            // names are unique
+            diesel::update(namespaces::table.find(namespace.id))
+                .set(namespaces::name.eq("exported"))
+                .execute(conn)
+                .unwrap();
+            sync_sequences(conn);
+            let imported = backup.import(&dir).unwrap();
+            assert_ne!(imported.id, namespace.id);
+            assert_eq!(imported.name, namespace.name);
+
+            let m = Membership::find_by_namespace_id_and_user_id(
+                imported.id,
+                user.id,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(m.role, MembershipRole::PrimaryOwner);
+
+            let count: i64 = messages::table
+                .inner_join(streams::table)
+                .filter(streams::namespace_id.eq(imported.id))
+                .count()
+                .get_result(conn)
+                .unwrap();
+            assert_eq!(count, 1);
+
+            let _ = fs::remove_dir_all(&dir);
+        });
+    }
+
+    #[test]
+    fn test_export_to_and_import_from() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let m = NewMembership {
+                namespace_id: namespace.id,
+                user_id: user.id,
+                role: MembershipRole::PrimaryOwner,
+            };
+            let _ = Membership::insert(&m, conn, logger).unwrap();
+
+            let stream = diesel::insert_into(streams::table)
+                .values(STREAMS.get("oswald's stream").unwrap())
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let m = NewMessage {
+                agent_id: user.id,
+                agent_type: AgentType::Person,
+                stream_id: stream.id,
+                title: Some("title".to_string()),
+
+                ..Default::default()
+            };
+            let since = Utc.ymd(2021, 6, 1).and_hms(0, 0, 0);
+            for t in &[since - chrono::Duration::days(1), since] {
+                let id = RandomIdGenerator.ulid(*t);
+                let _ = Message::insert(&m, &id, conn, logger).unwrap();
+                diesel::update(messages::table.filter(messages::id.eq(&id)))
+                    .set(messages::created_at.eq(t.naive_utc()))
+                    .execute(conn)
+                    .unwrap();
+            }
+
+            let backup = NamespaceBackup::new(conn, logger);
+
+            let mut all: Vec<u8> = vec![];
+            assert_eq!(backup.export_to(&namespace, None, &mut all), Ok(6));
+            let mut again: Vec<u8> = vec![];
+            let _ = backup.export_to(&namespace, None, &mut again).unwrap();
+            assert_eq!(all, again);
+
+            let mut out: Vec<u8> = vec![];
+            let since = Some(since.naive_utc());
+            assert_eq!(backup.export_to(&namespace, since, &mut out), Ok(5));
+
+            let lines = String::from_utf8(out.clone()).unwrap();
+            assert_eq!(
+                lines.lines().next(),
+                Some(concat!(
+                    r#"{"type":"header","version":1,"#,
+                    r#""since":"2021-06-01T00:00:00"}"#
+                ))
+            );
+
+            // names are unique
+            diesel::update(namespaces::table.find(namespace.id))
+                .set(namespaces::name.eq("exported"))
+                .execute(conn)
+                .unwrap();
+            sync_sequences(conn);
+            let imported = backup.import_from(&out[..]).unwrap();
+            assert_ne!(imported.id, namespace.id);
+
+            let count: i64 = messages::table
+                .inner_join(streams::table)
+                .filter(streams::namespace_id.eq(imported.id))
+                .count()
+                .get_result(conn)
+                .unwrap();
+            assert_eq!(count, 1);
+
+            assert_eq!(
+                backup.import_from(&b""[..]).err(),
+                Some("no namespace")
+            );
+        });
+    }
+}
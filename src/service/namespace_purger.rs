@@ -0,0 +1,275 @@
+//! Purge of deleted namespaces.
+//!
+//! A namespace is deleted softly (`archived_at`), and it can be restored by
+//! an owner within `NAMESPACE_PURGE_GRACE_PERIOD`. After that, the namespace
+//! is removed with its records (and stream tokens) by the `PurgeNamespaces`
+//! job. Messages are deleted in batches (each batch is a transaction, which
+//! releases the bodies of the deduplicated ones), then the other records and
+//! the unreferenced bodies are removed in a transaction.
+use std::fmt;
+
+use diesel::{self, debug_query, prelude::*};
+use diesel::pg::{Pg, PgConnection};
+use diesel::result::Error;
+
+use crate::clock::Clock;
+use crate::logger::Logger;
+use crate::model::bulk_operation::bulk_operations;
+use crate::model::channel::channels;
+use crate::model::incident::incidents;
+use crate::model::membership::memberships;
+use crate::model::message::messages;
+use crate::model::message_body::MessageBody;
+use crate::model::message_count::message_counts;
+use crate::model::namespace::{Namespace, namespaces};
+use crate::model::namespace_usage::namespace_usages;
+use crate::model::stream::streams;
+use crate::model::stream_token::stream_tokens;
+use crate::model::usage_record::usage_records;
+
+const BATCH_SIZE: i64 = 1_000; // messages per query
+
+pub struct NamespacePurger<'a> {
+    conn: &'a PgConnection,
+    clock: &'a dyn Clock,
+    logger: &'a Logger,
+}
+
+impl<'a> NamespacePurger<'a> {
+    pub fn new(
+        conn: &'a PgConnection,
+        clock: &'a dyn Clock,
+        logger: &'a Logger,
+    ) -> Self {
+        Self {
+            conn,
+            clock,
+            logger,
+        }
+    }
+
+    /// Removes the (deleted) namespace and all the records of it, and
+    /// returns the number of the messages removed.
+    pub fn purge(&self, namespace: &Namespace) -> Result<usize, &'static str> {
+        if namespace.archived_at.is_none() {
+            return Err("namespace is not deleted");
+        }
+
+        let stream_ids = streams::table
+            .filter(streams::namespace_id.eq(namespace.id))
+            .select(streams::id)
+            .load::<i64>(self.conn)
+            .map_err(|e| self.log(e, "failed to load streams"))?;
+
+        let mut count = 0;
+        loop {
+            let n = self
+                .delete_messages(&stream_ids)
+                .map_err(|e| self.log(e, "failed to delete messages"))?;
+            if n == 0 {
+                break;
+            }
+            count += n;
+        }
+
+        self.conn
+            .transaction::<_, Error, _>(|| {
+                let id = namespace.id;
+                let in_streams =
+                    bulk_operations::stream_id.eq_any(&stream_ids[..]);
+                diesel::delete(bulk_operations::table.filter(in_streams))
+                    .execute(self.conn)?;
+                diesel::delete(
+                    streams::table.filter(streams::namespace_id.eq(id)),
+                )
+                .execute(self.conn)?;
+                diesel::delete(
+                    channels::table.filter(channels::namespace_id.eq(id)),
+                )
+                .execute(self.conn)?;
+                // the links to messages are deleted with them
+                diesel::delete(
+                    incidents::table.filter(incidents::namespace_id.eq(id)),
+                )
+                .execute(self.conn)?;
+                diesel::delete(
+                    memberships::table.filter(memberships::namespace_id.eq(id)),
+                )
+                .execute(self.conn)?;
+                diesel::delete(
+                    stream_tokens::table
+                        .filter(stream_tokens::namespace_id.eq(id)),
+                )
+                .execute(self.conn)?;
+                diesel::delete(
+                    namespace_usages::table
+                        .filter(namespace_usages::namespace_id.eq(id)),
+                )
+                .execute(self.conn)?;
+                diesel::delete(
+                    usage_records::table
+                        .filter(usage_records::namespace_id.eq(id)),
+                )
+                .execute(self.conn)?;
+                diesel::delete(
+                    message_counts::table
+                        .filter(message_counts::namespace_id.eq(id)),
+                )
+                .execute(self.conn)?;
+                diesel::delete(namespaces::table.filter(namespaces::id.eq(id)))
+                    .execute(self.conn)?;
+                MessageBody::delete_unreferenced(self.conn, self.logger)?;
+                Ok(())
+            })
+            .map_err(|e| self.log(e, "failed to delete namespace"))?;
+
+        info!(self.logger, "purged: {} ({} messages)", namespace, count);
+        Ok(count)
+    }
+
+    // deletes a batch of the messages in the streams, and releases the bodies
+    // which they refer to
+    fn delete_messages(&self, stream_ids: &[i64]) -> Result<usize, Error> {
+        self.conn.transaction(|| {
+            let ids = messages::table
+                .filter(messages::stream_id.eq_any(stream_ids))
+                .select(messages::id)
+                .limit(BATCH_SIZE)
+                .load::<String>(self.conn)?;
+            let q = diesel::delete(
+                messages::table.filter(messages::id.eq_any(ids)),
+            )
+            .returning(messages::body_id);
+
+            info!(self.logger, "{}", debug_query::<Pg, _>(&q).to_string());
+
+            let body_ids = q.get_results::<Option<i64>>(self.conn)?;
+            let n = body_ids.len();
+            let body_ids: Vec<i64> = body_ids.into_iter().flatten().collect();
+            MessageBody::release(
+                &body_ids,
+                self.clock,
+                self.conn,
+                self.logger,
+            )?;
+            Ok(n)
+        })
+    }
+
+    fn log(&self, e: impl fmt::Display, message: &'static str) -> &'static str {
+        error!(self.logger, "err: {}", e);
+        message
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::Utc;
+
+    use crate::clock::SystemClock;
+    use crate::id::{IdGenerator, RandomIdGenerator};
+    use crate::model::membership::{Membership, MembershipRole, NewMembership};
+    use crate::model::message::{AgentType, Message, NewMessage};
+    use crate::model::message_body::message_bodies;
+    use crate::model::stream::Stream;
+    use crate::model::test::run;
+    use crate::model::user::{User, users};
+
+    use crate::model::namespace::data::NAMESPACES;
+    use crate::model::stream::data::STREAMS;
+    use crate::model::user::data::USERS;
+
+    #[test]
+    fn test_purge() {
+        run(|conn, _, logger| {
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let namespace = diesel::insert_into(namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<Namespace>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let m = NewMembership {
+                namespace_id: namespace.id,
+                user_id: user.id,
+                role: MembershipRole::PrimaryOwner,
+            };
+            let _ = Membership::insert(&m, conn, logger).unwrap();
+
+            let stream = diesel::insert_into(streams::table)
+                .values(STREAMS.get("oswald's stream").unwrap())
+                .get_result::<Stream>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let m = NewMessage {
+                agent_id: user.id,
+                agent_type: AgentType::Person,
+                stream_id: stream.id,
+                title: Some("title".to_string()),
+
+                ..Default::default()
+            };
+            for _ in 0..3 {
+                let id = RandomIdGenerator.ulid(Utc::now());
+                let _ = Message::insert(&m, &id, conn, logger).unwrap();
+            }
+            for _ in 0..2 {
+                let m = NewMessage {
+                    agent_id: user.id,
+                    agent_type: AgentType::Person,
+                    stream_id: stream.id,
+                    title: Some("title".to_string()),
+                    content: Some("content".to_string()),
+
+                    ..Default::default()
+                };
+                let id = RandomIdGenerator.ulid(Utc::now());
+                let _ = Message::insert_deduplicated(
+                    m,
+                    &id,
+                    &SystemClock,
+                    conn,
+                    logger,
+                )
+                .unwrap();
+            }
+
+            let purger = NamespacePurger::new(conn, &SystemClock, logger);
+            assert_eq!(
+                purger.purge(&namespace).err(),
+                Some("namespace is not deleted")
+            );
+
+            let now = Utc::now().naive_utc();
+            let namespace = namespace.archive(now, conn, logger).unwrap();
+            assert_eq!(purger.purge(&namespace), Ok(5));
+
+            let count: i64 = namespaces::table
+                .filter(namespaces::id.eq(namespace.id))
+                .count()
+                .get_result(conn)
+                .unwrap();
+            assert_eq!(count, 0);
+
+            let count: i64 = memberships::table
+                .filter(memberships::namespace_id.eq(namespace.id))
+                .count()
+                .get_result(conn)
+                .unwrap();
+            assert_eq!(count, 0);
+
+            let count: i64 =
+                messages::table.count().get_result(conn).unwrap();
+            assert_eq!(count, 0);
+
+            let count: i64 =
+                message_bodies::table.count().get_result(conn).unwrap();
+            assert_eq!(count, 0);
+        });
+    }
+}
@@ -0,0 +1,112 @@
+//! Delivers notifications to users via the channels of their preferences.
+//!
+//! Every job which notifies a user on an event (e.g. alert emails) asks
+//! `Notifier` for the delivery first (see `model::notification_preference`).
+//! It's email unless the user has chosen a webhook or none. A webhook gets
+//! the event as JSON like this:
+//!
+//! ```json
+//! {
+//!    "event": "quota",
+//!    "namespace": "piano",
+//!    "percent": 80
+//! }
+//! ```
+use std::time::Duration;
+
+use diesel::PgConnection;
+use serde_json::Value;
+
+use crate::logger::Logger;
+use crate::model::notification_preference::{
+    NotificationChannel, NotificationEvent, NotificationPreference,
+};
+use crate::model::user::User;
+
+const TIMEOUT: u64 = 10; // seconds
+
+const USER_AGENT: &str = "eloquentlog-console-api";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Delivery {
+    Email,
+    Webhook(String), // url
+    Skip,
+}
+
+pub struct Notifier<'a> {
+    conn: &'a PgConnection,
+    logger: &'a Logger,
+}
+
+impl<'a> Notifier<'a> {
+    pub fn new(conn: &'a PgConnection, logger: &'a Logger) -> Self {
+        Self { conn, logger }
+    }
+
+    /// Returns how the user receives notifications on the event.
+    pub fn delivery(&self, user: &User, event: NotificationEvent) -> Delivery {
+        let preference = NotificationPreference::find_by_user_id_and_event(
+            user.id,
+            event,
+            self.conn,
+            self.logger,
+        );
+        match preference {
+            Some(p) => to_delivery(p.channel, p.webhook_url),
+            None => Delivery::Email,
+        }
+    }
+
+    /// Posts the payload to the webhook of a user.
+    pub fn post(&self, url: &str, payload: &Value) -> Result<(), &'static str> {
+        ureq::post(url)
+            .set("Content-Type", "application/json")
+            .set("User-Agent", USER_AGENT)
+            .timeout(Duration::from_secs(TIMEOUT))
+            .send_string(&payload.to_string())
+            .map_err(|e| {
+                error!(self.logger, "err: {}", e);
+                "failed to deliver the payload"
+            })?;
+        Ok(())
+    }
+}
+
+// a webhook without the url falls back to email (it's validated on save)
+fn to_delivery(
+    channel: NotificationChannel,
+    webhook_url: Option<String>,
+) -> Delivery {
+    match (channel, webhook_url) {
+        (NotificationChannel::Webhook, Some(url)) => Delivery::Webhook(url),
+        (NotificationChannel::None, _) => Delivery::Skip,
+        _ => Delivery::Email,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_delivery() {
+        let url = "https://example.org/hook".to_string();
+        assert_eq!(
+            to_delivery(NotificationChannel::Email, Some(url.clone())),
+            Delivery::Email
+        );
+        assert_eq!(
+            to_delivery(NotificationChannel::Webhook, Some(url.clone())),
+            Delivery::Webhook(url)
+        );
+        assert_eq!(
+            to_delivery(NotificationChannel::Webhook, None),
+            Delivery::Email
+        );
+        assert_eq!(
+            to_delivery(NotificationChannel::None, None),
+            Delivery::Skip
+        );
+    }
+}
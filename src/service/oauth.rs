@@ -0,0 +1,483 @@
+//! OAuth2 (and OpenID Connect) login via external providers.
+//!
+//! The flow is the authorization code grant. The web console redirects the
+//! browser to `Client::authorize_url`, then the provider redirects it back to
+//! the console with `code` and `state`, which are passed to the callback API.
+//! The code is exchanged for an access token and the profile is fetched with
+//! it. Only a verified email on the provider side can be used to link or
+//! create an account.
+use diesel::{Connection, PgConnection};
+use diesel::result::Error;
+use rand::{Rng, thread_rng};
+use serde::Deserialize;
+use url::Url;
+
+use crate::config::Config;
+use crate::id::IdGenerator;
+use crate::logger::Logger;
+use crate::model::Activatable;
+use crate::model::identity::{Identity, IdentityProvider, NewIdentity};
+use crate::model::password_hash::PasswordHashParams;
+use crate::model::user::{NewUser, User, UserState};
+use crate::model::user_email::{NewUserEmail, UserEmail};
+use crate::validation::username::is_reserved;
+
+const PASSWORD_HASH_LENGTH: usize = 64;
+const PASSWORD_HASH_SOURCE: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+const USERNAME_MAX_LENGTH: usize = 28; // leaves space for a suffix
+const USERNAME_MAX_ATTEMPTS: usize = 10;
+
+const USER_AGENT: &str = "eloquentlog-console-api";
+
+struct Endpoint {
+    authorize: &'static str,
+    token: &'static str,
+    profile: &'static str,
+    scope: &'static str,
+}
+
+const GITHUB: Endpoint = Endpoint {
+    authorize: "https://github.com/login/oauth/authorize",
+    token: "https://github.com/login/oauth/access_token",
+    profile: "https://api.github.com/user",
+    scope: "read:user user:email",
+};
+
+const GOOGLE: Endpoint = Endpoint {
+    authorize: "https://accounts.google.com/o/oauth2/v2/auth",
+    token: "https://oauth2.googleapis.com/token",
+    profile: "https://openidconnect.googleapis.com/v1/userinfo",
+    scope: "openid email profile",
+};
+
+/// A user on the provider.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub uid: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    id: i64,
+    login: String,
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitHubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+#[derive(Deserialize)]
+struct GoogleUser {
+    sub: String,
+    email: String,
+    email_verified: bool,
+    name: Option<String>,
+}
+
+pub struct Client<'a> {
+    pub provider: IdentityProvider,
+    client_id: &'a str,
+    client_secret: &'a str,
+    redirect_uri: String,
+    logger: &'a Logger,
+}
+
+impl<'a> Client<'a> {
+    /// Returns None if the provider is not configured.
+    pub fn new(
+        provider: IdentityProvider,
+        config: &'a Config,
+        logger: &'a Logger,
+    ) -> Option<Self> {
+        let (client_id, client_secret) = match provider {
+            IdentityProvider::GitHub => (
+                &config.oauth_github_client_id,
+                &config.oauth_github_client_secret,
+            ),
+            IdentityProvider::Google => (
+                &config.oauth_google_client_id,
+                &config.oauth_google_client_secret,
+            ),
+            // see service::ldap
+            IdentityProvider::Ldap => return None,
+        };
+        if client_id.is_empty() {
+            return None;
+        }
+        Some(Self {
+            provider,
+            client_id,
+            client_secret,
+            redirect_uri: redirect_uri(&config.application_url, provider),
+            logger,
+        })
+    }
+
+    fn endpoint(&self) -> &'static Endpoint {
+        match self.provider {
+            IdentityProvider::GitHub => &GITHUB,
+            IdentityProvider::Google => &GOOGLE,
+            IdentityProvider::Ldap => unreachable!("not an oauth provider"),
+        }
+    }
+
+    pub fn authorize_url(&self, state: &str) -> String {
+        let e = self.endpoint();
+        Url::parse_with_params(
+            e.authorize,
+            &[
+                ("client_id", self.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("response_type", "code"),
+                ("scope", e.scope),
+                ("state", state),
+            ],
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    /// Exchanges the code for an access token, then fetches the profile.
+    pub fn fetch_profile(&self, code: &str) -> Result<Profile, &'static str> {
+        let token = self.exchange(code)?;
+        match self.provider {
+            IdentityProvider::GitHub => self.fetch_github_profile(&token),
+            IdentityProvider::Google => self.fetch_google_profile(&token),
+            IdentityProvider::Ldap => Err("unsupported provider"),
+        }
+    }
+
+    fn exchange(&self, code: &str) -> Result<String, &'static str> {
+        let res = ureq::post(self.endpoint().token)
+            .set("Accept", "application/json")
+            .set("User-Agent", USER_AGENT)
+            .send_form(&[
+                ("client_id", self.client_id),
+                ("client_secret", self.client_secret),
+                ("code", code),
+                ("grant_type", "authorization_code"),
+                ("redirect_uri", &self.redirect_uri),
+            ])
+            .map_err(|e| self.log(e, "failed to exchange the code"))?;
+
+        res.into_json::<TokenResponse>()
+            .map(|t| t.access_token)
+            .map_err(|e| self.log(e, "invalid token response"))
+    }
+
+    fn get(
+        &self,
+        url: &str,
+        token: &str,
+    ) -> Result<ureq::Response, &'static str> {
+        ureq::get(url)
+            .set("Accept", "application/json")
+            .set("Authorization", &format!("Bearer {}", token))
+            .set("User-Agent", USER_AGENT)
+            .call()
+            .map_err(|e| self.log(e, "failed to fetch the profile"))
+    }
+
+    fn fetch_github_profile(
+        &self,
+        token: &str,
+    ) -> Result<Profile, &'static str> {
+        let user = self
+            .get(GITHUB.profile, token)?
+            .into_json::<GitHubUser>()
+            .map_err(|e| self.log(e, "invalid profile"))?;
+
+        // the email in the profile is only a public one (it may be null)
+        let url = format!("{}/emails", GITHUB.profile);
+        let emails = self
+            .get(&url, token)?
+            .into_json::<Vec<GitHubEmail>>()
+            .map_err(|e| self.log(e, "invalid profile"))?;
+        let email = emails
+            .into_iter()
+            .find(|e| e.primary)
+            .ok_or("no primary email")?;
+
+        Ok(Profile {
+            uid: user.id.to_string(),
+            email: email.email,
+            email_verified: email.verified,
+            name: user.name,
+            username: Some(user.login),
+        })
+    }
+
+    fn fetch_google_profile(
+        &self,
+        token: &str,
+    ) -> Result<Profile, &'static str> {
+        let user = self
+            .get(GOOGLE.profile, token)?
+            .into_json::<GoogleUser>()
+            .map_err(|e| self.log(e, "invalid profile"))?;
+
+        Ok(Profile {
+            uid: user.sub,
+            email: user.email,
+            email_verified: user.email_verified,
+            name: user.name,
+            username: None,
+        })
+    }
+
+    fn log<E: std::fmt::Display>(
+        &self,
+        e: E,
+        message: &'static str,
+    ) -> &'static str {
+        error!(self.logger, "err: {} ({})", e, self.provider);
+        message
+    }
+}
+
+/// Returns the callback URL in the web console.
+pub fn redirect_uri(
+    application_url: &str,
+    provider: IdentityProvider,
+) -> String {
+    format!(
+        "{}/oauth/{}/callback",
+        application_url.trim_end_matches('/'),
+        provider
+    )
+}
+
+/// Makes a valid username candidate from the username on the provider or the
+/// local part of the email.
+pub fn make_username(s: &str) -> String {
+    let local = s.split('@').next().unwrap_or("");
+    let username: String = local
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .skip_while(|c| c.is_ascii_digit() || *c == '_')
+        .take(USERNAME_MAX_LENGTH)
+        .collect();
+    let username = username.trim_end_matches('_');
+    if username.len() < 3 {
+        return "user".to_string();
+    }
+    username.to_string()
+}
+
+/// Finds the user linked to the profile.
+///
+/// If there is no identity yet, it will be linked to the (active) user who
+/// has the same email, or a new activated user will be created for it.
+pub fn sign_in(
+    provider: IdentityProvider,
+    profile: &Profile,
+    ids: &dyn IdGenerator,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Result<User, &'static str> {
+    if let Some(identity) =
+        Identity::find_by_provider_and_uid(provider, &profile.uid, conn, logger)
+    {
+        return match User::find_by_id(identity.user_id, conn, logger) {
+            Some(ref u) if u.state == UserState::Active => Ok(u.clone()),
+            _ => Err("The account is not available."),
+        };
+    }
+
+    if !profile.email_verified {
+        return Err("The email on the provider is not verified.");
+    }
+
+    let mut failure: &'static str = "Something wrong happen, sorry :'(";
+    let result = conn.transaction::<User, Error, _>(|| {
+        let user = match User::find_by_email(&profile.email, conn, logger) {
+            Some(u) => u,
+            None => create_user(profile, ids, conn, logger).map_err(|e| {
+                failure = e;
+                Error::RollbackTransaction
+            })?,
+        };
+
+        let identity = NewIdentity {
+            user_id: user.id,
+            provider,
+            uid: profile.uid.to_string(),
+            email: profile.email.to_string(),
+        };
+        Identity::insert(&identity, conn, logger)
+            .ok_or(Error::RollbackTransaction)?;
+        Ok(user)
+    });
+    result.map_err(|_| failure)
+}
+
+fn create_user(
+    profile: &Profile,
+    ids: &dyn IdGenerator,
+    conn: &PgConnection,
+    logger: &Logger,
+) -> Result<User, &'static str> {
+    // e.g. the email is used by a pending user
+    if !User::check_email_uniqueness(&profile.email, conn, logger) {
+        return Err("The email is already taken.");
+    }
+
+    let base = make_username(
+        profile.username.as_deref().unwrap_or(&profile.email),
+    );
+    let username = (0..USERNAME_MAX_ATTEMPTS)
+        .map(|i| {
+            if i == 0 {
+                base.to_string()
+            } else {
+                format!("{}{}", base, thread_rng().gen_range(1000..10000))
+            }
+        })
+        .find(|u| {
+            !is_reserved(u) && User::check_username_uniqueness(u, conn, logger)
+        })
+        .ok_or("The username is not available.")?;
+
+    let mut u = NewUser {
+        name: profile.name.clone(),
+        username,
+        email: profile.email.to_string(),
+
+        ..Default::default()
+    };
+    // it's not known by anyone, the user needs to reset it to use password
+    u.set_password(
+        &ids.random_hash(PASSWORD_HASH_SOURCE, PASSWORD_HASH_LENGTH),
+        &PasswordHashParams::default(),
+    );
+
+    let user = User::insert(&u, conn, logger).ok_or("failed to save user")?;
+    let e = NewUserEmail::from(&user);
+    UserEmail::insert(&e, conn, logger).ok_or("failed to save email")?;
+    user.activate(conn, logger)?;
+
+    User::find_by_id(user.id, conn, logger).ok_or("failed to activate user")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use diesel::prelude::*;
+
+    use crate::id::RandomIdGenerator;
+    use crate::model::test::run;
+    use crate::model::user::users;
+    use crate::model::user::data::USERS;
+
+    fn profile() -> Profile {
+        Profile {
+            uid: "1234".to_string(),
+            email: "hennry@example.org".to_string(),
+            email_verified: true,
+            name: Some("Hennry".to_string()),
+            username: Some("hennry".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_redirect_uri() {
+        assert_eq!(
+            redirect_uri("http://127.0.0.1:3000/", IdentityProvider::GitHub),
+            "http://127.0.0.1:3000/oauth/github/callback"
+        );
+        assert_eq!(
+            redirect_uri("http://127.0.0.1:3000", IdentityProvider::Google),
+            "http://127.0.0.1:3000/oauth/google/callback"
+        );
+    }
+
+    #[test]
+    fn test_make_username() {
+        assert_eq!(make_username("hennry"), "hennry");
+        assert_eq!(make_username("Hennry.Sm1th@example.org"), "hennry_sm1th");
+        assert_eq!(make_username("123_hennry-"), "hennry");
+        assert_eq!(make_username("ab@example.org"), "user");
+        assert_eq!(make_username(&"a".repeat(64)).len(), 28);
+    }
+
+    #[test]
+    fn test_sign_in_with_unverified_email() {
+        run(|conn, _, logger| {
+            let ids = RandomIdGenerator;
+            let mut p = profile();
+            p.email_verified = false;
+
+            let result =
+                sign_in(IdentityProvider::GitHub, &p, &ids, conn, logger);
+            assert_eq!(
+                result.err(),
+                Some("The email on the provider is not verified.")
+            );
+        });
+    }
+
+    #[test]
+    fn test_sign_in_creates_user() {
+        run(|conn, _, logger| {
+            let ids = RandomIdGenerator;
+            let p = profile();
+            let user =
+                sign_in(IdentityProvider::GitHub, &p, &ids, conn, logger)
+                    .unwrap();
+            assert_eq!(user.email, p.email);
+            assert_eq!(user.username, "hennry");
+            assert_eq!(user.state, UserState::Active);
+
+            // linked
+            let u =
+                sign_in(IdentityProvider::GitHub, &p, &ids, conn, logger)
+                    .unwrap();
+            assert_eq!(u.id, user.id);
+        });
+    }
+
+    #[test]
+    fn test_sign_in_links_existing_user() {
+        run(|conn, _, logger| {
+            let ids = RandomIdGenerator;
+            let user = diesel::insert_into(users::table)
+                .values(USERS.get("oswald").unwrap())
+                .get_result::<User>(conn)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+            let mut p = profile();
+            p.email = user.email.to_string();
+
+            let u =
+                sign_in(IdentityProvider::Google, &p, &ids, conn, logger)
+                    .unwrap();
+            assert_eq!(u.id, user.id);
+
+            let identity = Identity::find_by_provider_and_uid(
+                IdentityProvider::Google,
+                &p.uid,
+                conn,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(identity.user_id, user.id);
+        });
+    }
+}
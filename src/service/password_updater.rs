@@ -1,17 +1,17 @@
 use std::fmt;
 
-use rocket_slog::SyncLogger;
-
 use crate::config::Config;
 use crate::db::DbConn;
+use crate::logger::Logger;
 use crate::model::{Authenticatable, Verifiable};
+use crate::model::password_hash::PasswordHashParams;
 
 pub struct PasswordUpdater<'a, T>
 where T: Authenticatable + Clone + Verifiable<T> + fmt::Display
 {
     db_conn: &'a DbConn,
     config: &'a Config,
-    logger: &'a SyncLogger,
+    logger: &'a Logger,
     pub target: Option<T>,
 }
 
@@ -21,7 +21,7 @@ where T: Authenticatable + Clone + Verifiable<T> + fmt::Display
     pub fn new(
         db_conn: &'a DbConn,
         config: &'a Config,
-        logger: &'a SyncLogger,
+        logger: &'a Logger,
     ) -> Self {
         Self {
             db_conn,
@@ -52,7 +52,12 @@ where T: Authenticatable + Clone + Verifiable<T> + fmt::Display
     pub fn update(&self, new_password: &str) -> Result<(), &str> {
         if let Some(mut user) = self.target.clone() {
             return user
-                .update_password(new_password, self.db_conn, self.logger)
+                .update_password(
+                    new_password,
+                    &PasswordHashParams::from(self.config),
+                    self.db_conn,
+                    self.logger,
+                )
                 .map(|_| {
                     info!(
                         self.logger,
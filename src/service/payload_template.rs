@@ -0,0 +1,288 @@
+//! Templates for outgoing payloads of channels (webhook and Slack).
+//!
+//! A template is written in Handlebars and must render as a JSON object.
+//! Values are escaped as JSON string contents (not as HTML), so that they
+//! can be put in double quotes like `"text": "{{message.title}}"`. Only the
+//! variables in `PayloadContext` can be used; the others are rejected by the
+//! strict mode.
+//!
+//! | variable                  | value                                  |
+//! |---------------------------|----------------------------------------|
+//! | `namespace.name`          | name of the namespace                  |
+//! | `stream.name`             | name of the stream                     |
+//! | `message.id`              | id of the message (ULID)               |
+//! | `message.level`           | log level (e.g. `error`)               |
+//! | `message.code`            | code (empty if it's not given)         |
+//! | `message.lang`            | language                               |
+//! | `message.title`           | title                                  |
+//! | `message.content`         | content (empty if it's not given)      |
+//! | `message.created_at`      | `YYYY-MM-DDTHH:MM:SS` (UTC)            |
+//! | `rollup.suppressed_count` | alerts suppressed since the last one   |
+//!
+//! An alert of a spike of errors (see `service::anomaly_detector`) is not of
+//! a message. It's like a message of the `error` level with the code
+//! `anomaly` in all the streams (`*`), and the id is empty.
+use handlebars::Handlebars;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::model::channel::ChannelKind;
+use crate::model::message::{LogLevel, Message};
+use crate::model::namespace::Namespace;
+use crate::model::stream::Stream;
+use crate::service::anomaly_detector::{Anomaly, RULE as ANOMALY_RULE};
+
+const TEMPLATE_NAME: &str = "payload";
+
+const WEBHOOK_TEMPLATE: &str = r#"{
+  "namespace": "{{namespace.name}}",
+  "stream": "{{stream.name}}",
+  "message": {
+    "id": "{{message.id}}",
+    "level": "{{message.level}}",
+    "code": "{{message.code}}",
+    "title": "{{message.title}}",
+    "created_at": "{{message.created_at}}"
+  },
+  "suppressed_count": {{rollup.suppressed_count}}
+}"#;
+
+const SLACK_TEMPLATE: &str = concat!(
+    r#"{"text": "[{{message.level}}] {{message.title}} "#,
+    r#"({{namespace.name}}/{{stream.name}})"#,
+    r#"{{#if rollup.suppressed_count}} "#,
+    r#"+{{rollup.suppressed_count}} suppressed{{/if}}"}"#,
+);
+
+#[derive(Debug, Serialize)]
+pub struct NamespaceContext {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamContext {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageContext {
+    pub id: String,
+    pub level: String,
+    pub code: String,
+    pub lang: String,
+    pub title: String,
+    pub content: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RollupContext {
+    pub suppressed_count: i64,
+}
+
+/// Variables for templates.
+#[derive(Debug, Serialize)]
+pub struct PayloadContext {
+    pub namespace: NamespaceContext,
+    pub stream: StreamContext,
+    pub message: MessageContext,
+    pub rollup: RollupContext,
+}
+
+impl PayloadContext {
+    pub fn new(
+        namespace: &Namespace,
+        stream: &Stream,
+        message: &Message,
+    ) -> Self {
+        Self {
+            namespace: NamespaceContext {
+                name: namespace.name.to_string(),
+            },
+            stream: StreamContext {
+                name: stream.name.to_string(),
+            },
+            message: MessageContext {
+                id: message.id.to_string(),
+                level: message.level.to_string(),
+                code: message.code.clone().unwrap_or_default(),
+                lang: message.lang.to_string(),
+                title: message.title.to_string(),
+                content: message.content.clone().unwrap_or_default(),
+                created_at: message
+                    .created_at
+                    .format("%Y-%m-%dT%H:%M:%S")
+                    .to_string(),
+            },
+            rollup: RollupContext::default(),
+        }
+    }
+
+    /// Returns the variables for an alert of the anomaly.
+    pub fn anomaly(namespace: &Namespace, anomaly: &Anomaly) -> Self {
+        Self {
+            namespace: NamespaceContext {
+                name: namespace.name.to_string(),
+            },
+            stream: StreamContext {
+                name: "*".to_string(),
+            },
+            message: MessageContext {
+                id: "".to_string(),
+                level: LogLevel::Error.to_string(),
+                code: ANOMALY_RULE.to_string(),
+                lang: "en".to_string(),
+                title: anomaly.title(),
+                content: "".to_string(),
+                created_at: anomaly
+                    .hour
+                    .format("%Y-%m-%dT%H:%M:%S")
+                    .to_string(),
+            },
+            rollup: RollupContext::default(),
+        }
+    }
+
+    /// Returns an example for validation and preview.
+    pub fn sample() -> Self {
+        Self {
+            namespace: NamespaceContext {
+                name: "piano".to_string(),
+            },
+            stream: StreamContext {
+                name: "production".to_string(),
+            },
+            message: MessageContext {
+                id: "01F8PWRHM00000000000000001".to_string(),
+                level: "error".to_string(),
+                code: "E001".to_string(),
+                lang: "en".to_string(),
+                title: "Connection \"db\" timed out".to_string(),
+                content: "timeout after 30s\nretrying".to_string(),
+                created_at: "2021-06-21T09:00:00".to_string(),
+            },
+            rollup: RollupContext {
+                suppressed_count: 0,
+            },
+        }
+    }
+}
+
+// escapes a value as contents of a JSON string
+fn escape_json(s: &str) -> String {
+    let quoted = serde_json::to_string(s).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+pub struct PayloadTemplate<'a> {
+    registry: Handlebars<'a>,
+}
+
+impl<'a> PayloadTemplate<'a> {
+    /// Compiles the template of the channel (or the default one for the
+    /// kind). It fails if the template has a syntax error.
+    pub fn new(
+        kind: ChannelKind,
+        source: Option<&str>,
+    ) -> Result<Self, String> {
+        let source = match source {
+            Some(s) if !s.trim().is_empty() => s,
+            _ => match kind {
+                ChannelKind::Webhook => WEBHOOK_TEMPLATE,
+                ChannelKind::Slack => SLACK_TEMPLATE,
+            },
+        };
+
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        registry.register_escape_fn(escape_json);
+        registry
+            .register_template_string(TEMPLATE_NAME, source)
+            .map_err(|e| format!("Invalid template: {}", e))?;
+        Ok(Self { registry })
+    }
+
+    /// Renders a payload. It fails if the template uses unknown variables or
+    /// helpers, or the result is not a JSON object.
+    pub fn render(&self, context: &PayloadContext) -> Result<String, String> {
+        let payload = self
+            .registry
+            .render(TEMPLATE_NAME, context)
+            .map_err(|e| format!("Invalid template: {}", e))?;
+        match serde_json::from_str::<Value>(&payload) {
+            Ok(Value::Object(_)) => Ok(payload),
+            _ => Err("Must render a JSON object".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_default_templates() {
+        let context = PayloadContext::sample();
+
+        let t = PayloadTemplate::new(ChannelKind::Webhook, None).unwrap();
+        let payload: Value =
+            serde_json::from_str(&t.render(&context).unwrap()).unwrap();
+        assert_eq!(payload["message"]["id"], "01F8PWRHM00000000000000001");
+        assert_eq!(payload["message"]["title"], "Connection \"db\" timed out");
+
+        let t = PayloadTemplate::new(ChannelKind::Slack, Some(" ")).unwrap();
+        let payload: Value =
+            serde_json::from_str(&t.render(&context).unwrap()).unwrap();
+        assert_eq!(
+            payload["text"],
+            "[error] Connection \"db\" timed out (piano/production)"
+        );
+
+        let mut context = PayloadContext::sample();
+        context.rollup.suppressed_count = 3;
+        let payload: Value =
+            serde_json::from_str(&t.render(&context).unwrap()).unwrap();
+        assert_eq!(
+            payload["text"],
+            "[error] Connection \"db\" timed out (piano/production) \
+             +3 suppressed"
+        );
+    }
+
+    #[test]
+    fn test_render_custom_template() {
+        let context = PayloadContext::sample();
+
+        let source = r#"{"text": "{{message.title}}: {{message.content}}"}"#;
+        let t = PayloadTemplate::new(ChannelKind::Slack, Some(source)).unwrap();
+        let payload: Value =
+            serde_json::from_str(&t.render(&context).unwrap()).unwrap();
+        assert_eq!(
+            payload["text"],
+            "Connection \"db\" timed out: timeout after 30s\nretrying"
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let context = PayloadContext::sample();
+
+        // syntax error
+        let source = "{{#if}";
+        let result = PayloadTemplate::new(ChannelKind::Webhook, Some(source));
+        assert!(result.is_err());
+
+        // unknown variable
+        let source = r#"{"text": "{{user.password}}"}"#;
+        let t = PayloadTemplate::new(ChannelKind::Slack, Some(source)).unwrap();
+        assert!(t.render(&context).is_err());
+
+        // not a JSON object
+        let source = r#""{{message.title}}""#;
+        let t = PayloadTemplate::new(ChannelKind::Slack, Some(source)).unwrap();
+        assert_eq!(
+            t.render(&context),
+            Err("Must render a JSON object".to_string())
+        );
+    }
+}
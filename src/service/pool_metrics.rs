@@ -0,0 +1,123 @@
+//! Metrics of the connection pools (see `route::metrics`).
+//!
+//! The holders of the pools (`db`, `mq` and `ss`) count the waits for
+//! connections and their time in the process, and the number of connections
+//! in use and idle ones are taken from r2d2 when they are read.
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct Counts {
+    waits: AtomicU64,
+    timeouts: AtomicU64,
+    // microseconds
+    wait_time: AtomicU64,
+    max_wait_time: AtomicU64,
+}
+
+/// PoolStats counts the waits for connections of a pool. The clones of it
+/// share the counts (e.g. over reconnects of the pool).
+#[derive(Clone, Debug, Default)]
+pub struct PoolStats(Arc<Counts>);
+
+impl PoolStats {
+    /// Counts a wait. A failed one has been timed out.
+    pub fn record(&self, elapsed: Duration, ok: bool) {
+        let micros = elapsed.as_micros() as u64;
+        self.0.waits.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            self.0.timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+        self.0.wait_time.fetch_add(micros, Ordering::Relaxed);
+        self.0.max_wait_time.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// Gets a connection by the function, and counts the wait for it.
+    pub fn wait<T, F>(&self, f: F) -> Option<T>
+    where F: FnOnce() -> Option<T> {
+        let started_at = Instant::now();
+        let conn = f();
+        self.record(started_at.elapsed(), conn.is_some());
+        conn
+    }
+}
+
+/// PoolState
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PoolState {
+    pub max_size: u32,
+    pub connections: u32,
+    pub in_use: u32,
+    pub idle: u32,
+    pub waits: u64,
+    pub timeouts: u64,
+    /// milliseconds
+    pub wait_time_avg: f64,
+    /// milliseconds
+    pub wait_time_max: f64,
+}
+
+impl PoolState {
+    pub fn new(
+        max_size: u32,
+        connections: u32,
+        idle: u32,
+        stats: &PoolStats,
+    ) -> Self {
+        let waits = stats.0.waits.load(Ordering::Relaxed);
+        let wait_time = stats.0.wait_time.load(Ordering::Relaxed) as f64;
+        let max_wait_time = stats.0.max_wait_time.load(Ordering::Relaxed);
+        Self {
+            max_size,
+            connections,
+            in_use: connections.saturating_sub(idle),
+            idle,
+            waits,
+            timeouts: stats.0.timeouts.load(Ordering::Relaxed),
+            wait_time_avg: if waits == 0 {
+                0.0
+            } else {
+                wait_time / waits as f64 / 1000.0
+            },
+            wait_time_max: max_wait_time as f64 / 1000.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[allow(clippy::redundant_clone)]
+    #[test]
+    fn test_pool_state() {
+        let stats = PoolStats::default();
+        let state = PoolState::new(4, 0, 0, &stats);
+        assert_eq!(state.waits, 0);
+        assert!(state.wait_time_avg.abs() < f64::EPSILON);
+
+        stats.record(Duration::from_millis(2), true);
+        // clones share the counters
+        stats.clone().record(Duration::from_millis(4), false);
+
+        let state = PoolState::new(4, 3, 1, &stats);
+        assert_eq!(state.in_use, 2);
+        assert_eq!(state.idle, 1);
+        assert_eq!(state.waits, 2);
+        assert_eq!(state.timeouts, 1);
+        assert!((state.wait_time_avg - 3.0).abs() < 1e-9);
+        assert!((state.wait_time_max - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_wait() {
+        let stats = PoolStats::default();
+        assert_eq!(stats.wait(|| Some(1)), Some(1));
+        assert_eq!(stats.wait(|| None::<i32>), None);
+
+        let state = PoolState::new(1, 1, 1, &stats);
+        assert_eq!(state.waits, 2);
+        assert_eq!(state.timeouts, 1);
+    }
+}
@@ -0,0 +1,383 @@
+//! Delivers push notifications of critical alerts to devices (see
+//! `model::push_device`).
+//!
+//! APNs gets a provider token, which is a JWT signed by ES256 with the key of
+//! `PUSH_APNS_KEY_FILE`, and FCM gets the server key (the legacy HTTP API).
+//! The HTTP client speaks only HTTP/1.1 and APNs accepts only HTTP/2, so
+//! `PUSH_APNS_ENDPOINT` must be a gateway which forwards the requests to
+//! `https://api.push.apple.com` as they are.
+//!
+//! A token which is rejected as unregistered by the platform is reported as
+//! such, then the device is removed by the caller.
+use std::fmt;
+use std::fs;
+use std::time::Duration;
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde_json::Value;
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::model::message::Message;
+use crate::model::namespace::Namespace;
+use crate::model::push_device::{PushDevice, PushPlatform};
+use crate::model::stream::Stream;
+use crate::service::unfurl::{permalink, truncate};
+
+const TIMEOUT: u64 = 10; // seconds
+
+const USER_AGENT: &str = "eloquentlog-console-api";
+
+// characters (an error is saved in the receipt)
+const TITLE_MAX_LENGTH: usize = 100;
+const BODY_MAX_LENGTH: usize = 200;
+const ERROR_MAX_LENGTH: usize = 255;
+
+// seconds to reuse a provider token (APNs accepts it for an hour, and
+// refuses too frequent updates)
+const APNS_TOKEN_TTL: i64 = 3000;
+
+// reasons of APNs and errors of FCM for tokens which are not valid anymore
+const APNS_UNREGISTERED: &[&str] =
+    &["BadDeviceToken", "DeviceTokenNotForTopic", "Unregistered"];
+const FCM_UNREGISTERED: &[&str] = &["InvalidRegistration", "NotRegistered"];
+
+lazy_static! {
+    // the issued time and the token
+    static ref APNS_TOKEN: Mutex<Option<(i64, String)>> = Mutex::new(None);
+}
+
+/// PushNotification
+#[derive(Clone, Debug)]
+pub struct PushNotification {
+    pub title: String,
+    pub body: String,
+    /// The permalink of the message
+    pub url: String,
+    pub message_id: String,
+}
+
+impl PushNotification {
+    pub fn new(
+        config: &Config,
+        namespace: &Namespace,
+        stream: &Stream,
+        message: &Message,
+    ) -> Self {
+        let body = message
+            .content
+            .as_ref()
+            .map(|c| truncate(c, BODY_MAX_LENGTH))
+            .unwrap_or_default();
+        Self {
+            title: truncate(
+                &format!("[{}] {}", stream.name, message.title),
+                TITLE_MAX_LENGTH,
+            ),
+            body,
+            url: permalink(
+                config,
+                &namespace.uuid.to_string(),
+                &stream.uuid.to_string(),
+                &message.id,
+            ),
+            message_id: message.id.to_string(),
+        }
+    }
+}
+
+/// The result of a delivery to a device.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// With the id given by the platform (apns-id or the message id of FCM)
+    Delivered(Option<String>),
+    /// The token is not valid anymore
+    Unregistered(String),
+    Failed(String),
+}
+
+fn failed<E: fmt::Display>(e: E) -> Outcome {
+    Outcome::Failed(e.to_string().chars().take(ERROR_MAX_LENGTH).collect())
+}
+
+#[derive(Serialize)]
+struct ProviderClaims<'a> {
+    iss: &'a str,
+    iat: i64,
+}
+
+fn apns_payload(n: &PushNotification) -> Value {
+    serde_json::json!({
+        "aps": {
+            "alert": {
+                "title": n.title,
+                "body": n.body,
+            },
+            "sound": "default",
+            "interruption-level": "time-sensitive",
+        },
+        "message_id": n.message_id,
+        "url": n.url,
+    })
+}
+
+fn apns_outcome(
+    status: u16,
+    apns_id: Option<String>,
+    reason: Option<&str>,
+) -> Outcome {
+    match (status, reason) {
+        (200, _) => Outcome::Delivered(apns_id),
+        (410, r) => Outcome::Unregistered(r.unwrap_or("Unregistered").into()),
+        (_, Some(r)) if APNS_UNREGISTERED.contains(&r) => {
+            Outcome::Unregistered(r.to_string())
+        },
+        (_, Some(r)) => failed(r),
+        (s, None) => failed(format!("status {}", s)),
+    }
+}
+
+fn fcm_payload(token: &str, n: &PushNotification) -> Value {
+    serde_json::json!({
+        "to": token,
+        "priority": "high",
+        "notification": {
+            "title": n.title,
+            "body": n.body,
+        },
+        "data": {
+            "message_id": n.message_id,
+            "url": n.url,
+        },
+    })
+}
+
+// the response has a result per token (only one here)
+fn fcm_outcome(body: &Value) -> Outcome {
+    let result = &body["results"][0];
+    if let Some(id) = result["message_id"].as_str() {
+        return Outcome::Delivered(Some(id.to_string()));
+    }
+    match result["error"].as_str() {
+        Some(e) if FCM_UNREGISTERED.contains(&e) => {
+            Outcome::Unregistered(e.to_string())
+        },
+        Some(e) => failed(e),
+        None => failed("no result"),
+    }
+}
+
+pub struct PushNotifier<'a> {
+    config: &'a Config,
+    clock: &'a dyn Clock,
+    logger: &'a Logger,
+}
+
+impl<'a> PushNotifier<'a> {
+    pub fn new(
+        config: &'a Config,
+        clock: &'a dyn Clock,
+        logger: &'a Logger,
+    ) -> Self {
+        Self {
+            config,
+            clock,
+            logger,
+        }
+    }
+
+    pub fn notify(
+        &self,
+        device: &PushDevice,
+        notification: &PushNotification,
+    ) -> Outcome {
+        let outcome = match device.platform {
+            PushPlatform::Apns => self.notify_apns(device, notification),
+            PushPlatform::Fcm => self.notify_fcm(device, notification),
+        };
+        if let Outcome::Failed(ref e) = outcome {
+            error!(self.logger, "err: {} ({})", e, device);
+        }
+        outcome
+    }
+
+    fn notify_apns(
+        &self,
+        device: &PushDevice,
+        n: &PushNotification,
+    ) -> Outcome {
+        let c = self.config;
+        if c.push_apns_endpoint.is_empty() {
+            return failed("apns is disabled");
+        }
+        let token = match self.apns_token() {
+            Ok(t) => t,
+            Err(e) => return failed(e),
+        };
+
+        let url = format!(
+            "{}/3/device/{}",
+            c.push_apns_endpoint.trim_end_matches('/'),
+            device.token
+        );
+        let result = ureq::post(&url)
+            .set("Authorization", &format!("bearer {}", token))
+            .set("apns-topic", &c.push_apns_topic)
+            .set("apns-push-type", "alert")
+            .set("apns-priority", "10")
+            .set("User-Agent", USER_AGENT)
+            .timeout(Duration::from_secs(TIMEOUT))
+            .send_json(apns_payload(n));
+        match result {
+            Ok(res) => {
+                let apns_id = res.header("apns-id").map(|v| v.to_string());
+                apns_outcome(res.status(), apns_id, None)
+            },
+            Err(ureq::Error::Status(status, res)) => {
+                let body: Value = res.into_json().unwrap_or(Value::Null);
+                apns_outcome(status, None, body["reason"].as_str())
+            },
+            Err(e) => failed(e),
+        }
+    }
+
+    // reuses the provider token until it's expired
+    fn apns_token(&self) -> Result<String, String> {
+        let now = self.clock.now().timestamp();
+        let mut cache = APNS_TOKEN.lock();
+        if let Some((issued_at, ref token)) = *cache {
+            if now - issued_at < APNS_TOKEN_TTL {
+                return Ok(token.to_string());
+            }
+        }
+
+        let c = self.config;
+        let key = fs::read(&c.push_apns_key_file)
+            .map_err(|e| format!("failed to read the key: {}", e))?;
+        let key = EncodingKey::from_ec_pem(&key).map_err(|e| e.to_string())?;
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(c.push_apns_key_id.to_string());
+        let claims = ProviderClaims {
+            iss: &c.push_apns_team_id,
+            iat: now,
+        };
+        let token = jsonwebtoken::encode(&header, &claims, &key)
+            .map_err(|e| e.to_string())?;
+        *cache = Some((now, token.to_string()));
+        Ok(token)
+    }
+
+    fn notify_fcm(&self, device: &PushDevice, n: &PushNotification) -> Outcome {
+        let c = self.config;
+        if c.push_fcm_endpoint.is_empty() {
+            return failed("fcm is disabled");
+        }
+
+        let result = ureq::post(&c.push_fcm_endpoint)
+            .set("Authorization", &format!("key={}", c.push_fcm_server_key))
+            .set("User-Agent", USER_AGENT)
+            .timeout(Duration::from_secs(TIMEOUT))
+            .send_json(fcm_payload(&device.token, n));
+        match result {
+            Ok(res) => match res.into_json::<Value>() {
+                Ok(body) => fcm_outcome(&body),
+                Err(e) => failed(e),
+            },
+            Err(ureq::Error::Status(status, _)) => {
+                failed(format!("status {}", status))
+            },
+            Err(e) => failed(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn notification() -> PushNotification {
+        PushNotification {
+            title: "[api] Connection timeout".to_string(),
+            body: "timed out after 30s".to_string(),
+            url: "https://example.org/message/ns/st/01F9DKJ8M0".to_string(),
+            message_id: "01F9DKJ8M0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apns_payload() {
+        let v = apns_payload(&notification());
+        assert_eq!(v["aps"]["alert"]["title"], "[api] Connection timeout");
+        assert_eq!(v["aps"]["interruption-level"], "time-sensitive");
+        assert_eq!(v["message_id"], "01F9DKJ8M0");
+    }
+
+    #[test]
+    fn test_apns_outcome() {
+        assert_eq!(
+            apns_outcome(200, Some("abc".to_string()), None),
+            Outcome::Delivered(Some("abc".to_string()))
+        );
+        assert_eq!(
+            apns_outcome(410, None, None),
+            Outcome::Unregistered("Unregistered".to_string())
+        );
+        assert_eq!(
+            apns_outcome(400, None, Some("BadDeviceToken")),
+            Outcome::Unregistered("BadDeviceToken".to_string())
+        );
+        assert_eq!(
+            apns_outcome(429, None, Some("TooManyRequests")),
+            Outcome::Failed("TooManyRequests".to_string())
+        );
+        assert_eq!(
+            apns_outcome(503, None, None),
+            Outcome::Failed("status 503".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fcm_payload() {
+        let v = fcm_payload("token", &notification());
+        assert_eq!(v["to"], "token");
+        assert_eq!(v["priority"], "high");
+        assert_eq!(v["data"]["url"], notification().url);
+    }
+
+    #[test]
+    fn test_fcm_outcome() {
+        let body = serde_json::json!({
+            "success": 1,
+            "results": [{"message_id": "0:1"}],
+        });
+        assert_eq!(
+            fcm_outcome(&body),
+            Outcome::Delivered(Some("0:1".to_string()))
+        );
+
+        let body = serde_json::json!({"results": [{"error": "NotRegistered"}]});
+        assert_eq!(
+            fcm_outcome(&body),
+            Outcome::Unregistered("NotRegistered".to_string())
+        );
+
+        let body = serde_json::json!({"results": [{"error": "Unavailable"}]});
+        assert_eq!(fcm_outcome(&body), Outcome::Failed("Unavailable".into()));
+        assert_eq!(
+            fcm_outcome(&Value::Null),
+            Outcome::Failed("no result".to_string())
+        );
+    }
+
+    #[test]
+    fn test_failed() {
+        let e = "x".repeat(300);
+        match failed(e) {
+            Outcome::Failed(m) => assert_eq!(m.len(), ERROR_MAX_LENGTH),
+            o => panic!("unexpected: {:?}", o),
+        }
+    }
+}
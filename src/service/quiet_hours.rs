@@ -0,0 +1,126 @@
+//! Quiet hours of a user for alert emails.
+//!
+//! The hours are the local time in the timezone of the user, and may wrap
+//! around midnight (e.g. 22:00 - 07:00). An alert in the quiet hours is not
+//! dropped, but deferred to the end of them.
+use chrono::{DateTime, Duration, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+pub struct QuietHours {
+    timezone: Tz,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn new(
+        timezone: &str,
+        start: NaiveTime,
+        end: NaiveTime,
+    ) -> Result<Self, String> {
+        let timezone = timezone.parse::<Tz>()?;
+        Ok(Self {
+            timezone,
+            start,
+            end,
+        })
+    }
+
+    /// Returns the end of the quiet hours if the time is in them.
+    pub fn ends_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        // an empty window
+        if self.start == self.end {
+            return None;
+        }
+
+        let local = now.with_timezone(&self.timezone);
+        let t = local.time();
+        let date = local.date().naive_local();
+
+        let end_date = if self.start < self.end {
+            if t < self.start || t >= self.end {
+                return None;
+            }
+            date
+        } else if t >= self.start {
+            date.succ()
+        } else if t < self.end {
+            date
+        } else {
+            return None;
+        };
+        Some(self.to_utc(end_date.and_time(self.end)))
+    }
+
+    fn to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        // the time may be skipped by DST (it moves forward by an hour)
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                self.timezone
+                    .from_local_datetime(&(local + Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|d| d.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hm(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms(h, m, 0)
+    }
+
+    #[test]
+    fn test_new_with_unknown_timezone() {
+        assert!(QuietHours::new("Mars/Olympus", hm(22, 0), hm(7, 0)).is_err());
+    }
+
+    #[test]
+    fn test_ends_at_around_midnight() {
+        let q = QuietHours::new("UTC", hm(22, 0), hm(7, 0)).unwrap();
+
+        let now = Utc.ymd(2021, 6, 23).and_hms(23, 0, 0);
+        assert_eq!(q.ends_at(now), Some(Utc.ymd(2021, 6, 24).and_hms(7, 0, 0)));
+
+        let now = Utc.ymd(2021, 6, 23).and_hms(6, 59, 0);
+        assert_eq!(q.ends_at(now), Some(Utc.ymd(2021, 6, 23).and_hms(7, 0, 0)));
+
+        let now = Utc.ymd(2021, 6, 23).and_hms(7, 0, 0);
+        assert_eq!(q.ends_at(now), None);
+    }
+
+    #[test]
+    fn test_ends_at_in_a_day() {
+        let q = QuietHours::new("UTC", hm(12, 0), hm(13, 30)).unwrap();
+
+        let now = Utc.ymd(2021, 6, 23).and_hms(12, 0, 0);
+        assert_eq!(
+            q.ends_at(now),
+            Some(Utc.ymd(2021, 6, 23).and_hms(13, 30, 0))
+        );
+
+        let now = Utc.ymd(2021, 6, 23).and_hms(11, 59, 0);
+        assert_eq!(q.ends_at(now), None);
+
+        // empty
+        let q = QuietHours::new("UTC", hm(12, 0), hm(12, 0)).unwrap();
+        assert_eq!(q.ends_at(now), None);
+    }
+
+    #[test]
+    fn test_ends_at_in_timezone() {
+        // CEST (+02:00)
+        let q = QuietHours::new("Europe/Zurich", hm(22, 0), hm(7, 0)).unwrap();
+
+        let now = Utc.ymd(2021, 6, 23).and_hms(21, 0, 0); // 23:00
+        assert_eq!(q.ends_at(now), Some(Utc.ymd(2021, 6, 24).and_hms(5, 0, 0)));
+
+        let now = Utc.ymd(2021, 6, 23).and_hms(19, 0, 0); // 21:00
+        assert_eq!(q.ends_at(now), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! Read-only mode for failovers of the primary database.
+//!
+//! It's on by `READ_ONLY=true`, and it can be switched at runtime by
+//! reloading the config (see `route::config::reload`). While it's on,
+//! `ReadOnlyMode` answers the mutations (`POST`, `PUT`, `PATCH` and
+//! `DELETE`) with 503 Service Unavailable without running the routes. Reads
+//! keep working, including the ones by `POST` which write nothing into the
+//! database (e.g. counts and previews).
+//!
+//! Login and sudo are not reads, as the backends may write (the rehash of a
+//! password, or the user and the memberships from the directory), and so are
+//! OAuth callbacks. The sessions signed in before keep working.
+use std::io::Cursor;
+use std::sync::RwLock;
+
+use rocket::{Data, Outcome, Request, Response, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Method, Status};
+use rocket::http::uri::Origin;
+
+use crate::config::DynamicConfig;
+use crate::response::ApiError;
+
+// the mount points (the longest first)
+const PREFIXES: &[&str] = &["/_api/v1", "/v1", "/_"];
+
+// the paths under the mount points (`*` is a segment)
+const READS: &[&str] = &[
+    "/bulk_operation/*/count/*",
+    "/channel/*/preview",
+    "/config/reload",
+    "/fault",
+    "/graphql",
+    "/logout",
+    "/maintenance",
+    "/token/exchange",
+];
+
+// no route matches it (see `on_request`)
+const UNAVAILABLE_PATH: &str = "/_/read_only/unavailable";
+
+fn matches(pattern: &str, path: &str) -> bool {
+    let mut a = pattern.split('/');
+    let mut b = path.split('/');
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some("*"), Some(s)) if !s.is_empty() => continue,
+            (Some(x), Some(y)) if x == y => continue,
+            _ => return false,
+        }
+    }
+}
+
+/// Returns true if the request may change something.
+pub fn is_mutation(method: Method, path: &str) -> bool {
+    match method {
+        Method::Post | Method::Put | Method::Patch | Method::Delete => (),
+        _ => return false,
+    }
+    let path = PREFIXES
+        .iter()
+        .filter_map(|p| path.strip_prefix(p))
+        .find(|rest| rest.starts_with('/'))
+        .unwrap_or(path);
+    !READS.iter().any(|pattern| matches(pattern, path))
+}
+
+/// ReadOnlyState
+///
+/// This is cached per request if it's rejected.
+#[derive(Clone, Debug)]
+struct ReadOnlyState;
+
+/// ReadOnlyMode answers mutations with Service Unavailable while it's on.
+pub struct ReadOnlyMode;
+
+impl Fairing for ReadOnlyMode {
+    fn info(&self) -> Info {
+        Info {
+            name: "Read-only Mode",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, req: &mut Request, _: &Data) {
+        let read_only = match req.guard::<State<RwLock<DynamicConfig>>>() {
+            Outcome::Success(config) => config.read().unwrap().read_only,
+            _ => false,
+        };
+        if !read_only || !is_mutation(req.method(), req.uri().path()) {
+            return;
+        }
+        req.local_cache(|| Some(ReadOnlyState));
+        // the routes must not run (e.g. against the database)
+        req.set_uri(Origin::parse(UNAVAILABLE_PATH).unwrap());
+    }
+
+    fn on_response(&self, req: &Request, res: &mut Response) {
+        if req.local_cache(|| None::<ReadOnlyState>).is_none() {
+            return;
+        }
+        let body = ApiError::new(Status::ServiceUnavailable)
+            .code("read_only")
+            .message("The service is read-only for now")
+            .to_json();
+        res.set_status(Status::ServiceUnavailable);
+        res.set_header(ContentType::JSON);
+        res.set_sized_body(Cursor::new(body.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("/login", "/login"));
+        assert!(matches("/channel/*/preview", "/channel/key/preview"));
+        assert!(!matches("/channel/*/preview", "/channel//preview"));
+        assert!(!matches("/channel/*/preview", "/channel/key/append"));
+        assert!(!matches("/login", "/login/extra"));
+    }
+
+    #[test]
+    fn test_is_mutation() {
+        assert!(!is_mutation(Method::Get, "/v1/namespace/hgetall"));
+        assert!(!is_mutation(Method::Options, "/v1/namespace/hset"));
+        assert!(!is_mutation(Method::Post, "/_/logout"));
+        assert!(!is_mutation(Method::Post, "/v1/bulk_operation/k/count/u"));
+        assert!(!is_mutation(Method::Post, "/_api/v1/channel/k/preview"));
+
+        assert!(is_mutation(Method::Post, "/v1/namespace/hset"));
+        assert!(is_mutation(Method::Post, "/_api/v1/message/k/append/s"));
+        assert!(is_mutation(Method::Patch, "/v1/access_token/dump/u"));
+        assert!(is_mutation(Method::Delete, "/_/fault/extra"));
+        assert!(is_mutation(Method::Post, "/_/register"));
+        assert!(is_mutation(Method::Post, "/_/login"));
+        assert!(is_mutation(Method::Post, "/_/sudo"));
+        assert!(is_mutation(Method::Post, "/_/oauth/github/callback"));
+    }
+}
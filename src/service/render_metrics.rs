@@ -0,0 +1,179 @@
+//! Render metrics of the payload templates of channels (see
+//! `service::channel_notifier` and `route::metrics`).
+//!
+//! The alerts are delivered by the worker, so the timings are counted in the
+//! session store (not in the process) as a histogram per hash `rt-<kind>`:
+//! the compiling of templates (`template`) and the rendering of payloads with
+//! them (`payload`). A hash has the number of samples in each bucket (by the
+//! upper bound in microseconds, or `inf`), the total (`count`) and the sum of
+//! the timings in microseconds (`sum`). The counts are accumulated.
+use std::fmt;
+use std::time::Duration;
+
+use redis::{Connection, RedisResult};
+
+pub const KEY_PREFIX: &str = "rt-";
+
+/// The upper bounds of the buckets (microseconds).
+pub const BUCKETS: [u64; 10] =
+    [100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000];
+
+const INF: &str = "inf";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderKind {
+    Template,
+    Payload,
+}
+
+impl fmt::Display for RenderKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Template => write!(f, "template"),
+            Self::Payload => write!(f, "payload"),
+        }
+    }
+}
+
+pub fn key(kind: RenderKind) -> String {
+    format!("{}{}", KEY_PREFIX, kind)
+}
+
+// returns the field of the bucket for the timing
+fn bucket_of(micros: u64) -> String {
+    match BUCKETS.iter().find(|b| micros <= **b) {
+        Some(b) => b.to_string(),
+        None => INF.to_string(),
+    }
+}
+
+/// RenderBucket
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RenderBucket {
+    /// milliseconds
+    pub le: f64,
+    /// the number of the samples until the bound (cumulative)
+    pub count: u64,
+}
+
+/// RenderHistogram is the histogram of a kind. The samples over the last
+/// bound are only in the total.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RenderHistogram {
+    pub count: u64,
+    /// milliseconds
+    pub sum: f64,
+    pub buckets: Vec<RenderBucket>,
+}
+
+impl RenderHistogram {
+    // makes a histogram from the counts of the buckets (not cumulative), the
+    // total and the sum in microseconds
+    fn new(counts: &[u64], count: u64, sum: u64) -> Self {
+        let mut acc = 0;
+        let buckets = BUCKETS
+            .iter()
+            .zip(counts.iter())
+            .map(|(b, c)| {
+                acc += c;
+                RenderBucket {
+                    le: *b as f64 / 1000.0,
+                    count: acc,
+                }
+            })
+            .collect();
+        Self {
+            count,
+            sum: sum as f64 / 1000.0,
+            buckets,
+        }
+    }
+}
+
+pub struct RenderMetrics<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> RenderMetrics<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    /// Counts a timing of the kind.
+    pub fn record(
+        &mut self,
+        kind: RenderKind,
+        elapsed: Duration,
+    ) -> RedisResult<()> {
+        let key = key(kind);
+        let micros = elapsed.as_micros() as u64;
+        redis::pipe()
+            .hincr(&key, bucket_of(micros), 1)
+            .ignore()
+            .hincr(&key, "count", 1)
+            .ignore()
+            .hincr(&key, "sum", micros)
+            .ignore()
+            .query(&mut *self.conn)
+    }
+
+    /// Returns the histogram of the kind.
+    pub fn histogram(
+        &mut self,
+        kind: RenderKind,
+    ) -> RedisResult<RenderHistogram> {
+        let mut cmd = redis::cmd("HMGET");
+        cmd.arg(key(kind));
+        for b in BUCKETS.iter() {
+            cmd.arg(b.to_string());
+        }
+        cmd.arg("count").arg("sum");
+        let row: Vec<Option<u64>> = cmd.query(&mut *self.conn)?;
+
+        let value = |i: usize| row.get(i).copied().flatten().unwrap_or(0);
+        let counts: Vec<u64> = (0..BUCKETS.len()).map(value).collect();
+        Ok(RenderHistogram::new(
+            &counts,
+            value(BUCKETS.len()),
+            value(BUCKETS.len() + 1),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key(RenderKind::Template), "rt-template");
+        assert_eq!(key(RenderKind::Payload), "rt-payload");
+    }
+
+    #[test]
+    fn test_bucket_of() {
+        assert_eq!(bucket_of(0), "100");
+        assert_eq!(bucket_of(100), "100");
+        assert_eq!(bucket_of(101), "250");
+        assert_eq!(bucket_of(7_000), "10000");
+        assert_eq!(bucket_of(100_001), "inf");
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut counts = [0; 10];
+        counts[0] = 2;
+        counts[3] = 1;
+        let histogram = RenderHistogram::new(&counts, 4, 200_900);
+
+        assert_eq!(histogram.count, 4);
+        assert!((histogram.sum - 200.9).abs() < 1e-9);
+        assert_eq!(histogram.buckets.len(), BUCKETS.len());
+        assert_eq!(histogram.buckets[0].count, 2);
+        assert!((histogram.buckets[0].le - 0.1).abs() < 1e-9);
+        assert_eq!(histogram.buckets[2].count, 2);
+        assert_eq!(histogram.buckets[3].count, 3);
+        // the one over the last bound
+        assert_eq!(histogram.buckets[9].count, 3);
+    }
+}
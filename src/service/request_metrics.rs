@@ -0,0 +1,191 @@
+//! Request metrics for SLOs (see `service::slo`).
+//!
+//! If `SLO_WINDOWS` is set, the responses are counted per minute in the
+//! session store as a hash `rm-<minute>` (the unix time in minutes) of the
+//! number of all the requests (`total`), the ones which have failed with 5xx
+//! (`errors`) and the ones which took longer than `SLO_LATENCY_THRESHOLD`
+//! (`slow`). The counts are kept until the longest window passes.
+//!
+//! Preflight requests (OPTIONS) are not counted.
+use std::time::Instant;
+
+use redis::{Connection, RedisResult};
+use rocket::{Data, Request, Response, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Method;
+use rocket_slog::SyncLogger;
+
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::ss::SsPoolHolder;
+
+pub const KEY_PREFIX: &str = "rm-";
+
+pub fn key(minute: i64) -> String {
+    format!("{}{}", KEY_PREFIX, minute)
+}
+
+/// RequestCounts
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RequestCounts {
+    pub total: u64,
+    pub errors: u64,
+    pub slow: u64,
+}
+
+impl RequestCounts {
+    fn add(&mut self, other: &Self) {
+        self.total += other.total;
+        self.errors += other.errors;
+        self.slow += other.slow;
+    }
+}
+
+pub struct RequestMetrics<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> RequestMetrics<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    /// Counts a response in the minute. The counts expire after the minutes
+    /// of the retention.
+    pub fn record(
+        &mut self,
+        minute: i64,
+        error: bool,
+        slow: bool,
+        retention: u64,
+    ) -> RedisResult<()> {
+        let key = key(minute);
+        redis::pipe()
+            .hincr(&key, "total", 1)
+            .ignore()
+            .hincr(&key, "errors", if error { 1 } else { 0 })
+            .ignore()
+            .hincr(&key, "slow", if slow { 1 } else { 0 })
+            .ignore()
+            .expire(&key, ((retention + 1) * 60) as usize)
+            .ignore()
+            .query(&mut *self.conn)
+    }
+
+    /// Returns the sum of the counts in the last minutes (until the minute).
+    pub fn sum(
+        &mut self,
+        minute: i64,
+        minutes: u64,
+    ) -> RedisResult<RequestCounts> {
+        let mut pipe = redis::pipe();
+        for m in (minute - minutes as i64 + 1)..=minute {
+            pipe.cmd("HMGET")
+                .arg(key(m))
+                .arg("total")
+                .arg("errors")
+                .arg("slow");
+        }
+        // a tuple can't be used for a row (tuples in a `Vec` are read from
+        // a flat array)
+        let rows: Vec<Vec<Option<u64>>> = pipe.query(&mut *self.conn)?;
+
+        let mut counts = RequestCounts::default();
+        for row in rows {
+            let value = |i: usize| row.get(i).copied().flatten().unwrap_or(0);
+            counts.add(&RequestCounts {
+                total: value(0),
+                errors: value(1),
+                slow: value(2),
+            });
+        }
+        Ok(counts)
+    }
+}
+
+// the time when the request has been received
+struct StartedAt(Option<Instant>);
+
+/// RequestCounting counts the responses (see above).
+pub struct RequestCounting;
+
+impl Fairing for RequestCounting {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Counting",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, req: &mut Request, _: &Data) {
+        req.local_cache(|| StartedAt(Some(Instant::now())));
+    }
+
+    fn on_response(&self, req: &Request, res: &mut Response) {
+        let config = req.guard::<State<Config>>().unwrap();
+        let retention = match config.slo_windows.iter().max() {
+            Some(v) => *v,
+            None => return,
+        };
+        if req.method() == Method::Options {
+            return;
+        }
+        let elapsed = match req.local_cache(|| StartedAt(None)).0 {
+            Some(t) => t.elapsed().as_millis() as u64,
+            None => return,
+        };
+
+        let logger = req.guard::<SyncLogger>().unwrap();
+        // not by the guard, as the injected failures (see `fault_injection`)
+        // are for the requests
+        let holder = req.guard::<State<SsPoolHolder>>().unwrap();
+        let mut ss_conn = match holder.get() {
+            Some(conn) => conn,
+            None => {
+                error!(logger, "err: session store is not available");
+                return;
+            },
+        };
+        let clock = req.guard::<State<SharedClock>>().unwrap();
+        let minute = clock.now().timestamp() / 60;
+        let error = res.status().code >= 500;
+        let slow = elapsed > config.slo_latency_threshold;
+        if let Err(e) = RequestMetrics::new(&mut *ss_conn).record(
+            minute, error, slow, retention,
+        ) {
+            error!(logger, "err: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key(27_000_000), "rm-27000000");
+    }
+
+    #[test]
+    fn test_add() {
+        let mut counts = RequestCounts {
+            total: 10,
+            errors: 1,
+            slow: 2,
+        };
+        counts.add(&RequestCounts {
+            total: 5,
+            errors: 0,
+            slow: 1,
+        });
+        assert_eq!(
+            counts,
+            RequestCounts {
+                total: 15,
+                errors: 1,
+                slow: 3,
+            }
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! Secrets providers.
+//!
+//! Secrets like `DATABASE_URL` or `MAILER_SMTP_PASSWORD` can also be resolved
+//! from HashiCorp Vault (KV) or AWS Secrets Manager (see `config`). The secret
+//! must be a flat JSON object, and its keys are the names of the variables
+//! without the prefix (e.g. `{"DATABASE_URL": "postgresql://..."}`).
+//!
+//! The secrets are fetched at boot, and the `Refresher` fetches them again
+//! every `SECRETS_REFRESH_INTERVAL` seconds so that rotated credentials
+//! (database and SMTP) are picked up without restart.
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac, NewMac};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::logger::Logger;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const USER_AGENT: &str = "eloquentlog-console-api";
+
+pub trait SecretsProvider {
+    /// Returns the values by the names in uppercase. The clock is for the
+    /// signing of the request (if needed).
+    fn fetch(
+        &self,
+        clock: &dyn Clock,
+    ) -> Result<HashMap<String, String>, String>;
+}
+
+// converts the object into the values (non-string values as JSON)
+fn to_values(value: &Value) -> Result<HashMap<String, String>, String> {
+    let object = value.as_object().ok_or("secret must be an object")?;
+    Ok(object
+        .iter()
+        .map(|(k, v)| {
+            let v = match v {
+                Value::String(s) => s.to_string(),
+                v => v.to_string(),
+            };
+            (k.to_ascii_uppercase(), v)
+        })
+        .collect())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// HashiCorp Vault (KV secrets engine version 1 or 2).
+pub struct Vault {
+    pub addr: String,
+    pub token: String,
+    /// e.g. `secret/data/eloquentlog` (version 2)
+    pub path: String,
+}
+
+impl SecretsProvider for Vault {
+    fn fetch(
+        &self,
+        _clock: &dyn Clock,
+    ) -> Result<HashMap<String, String>, String> {
+        let url = format!(
+            "{}/v1/{}",
+            self.addr.trim_end_matches('/'),
+            self.path.trim_start_matches('/')
+        );
+        let res = ureq::get(&url)
+            .timeout(TIMEOUT)
+            .set("Accept", "application/json")
+            .set("User-Agent", USER_AGENT)
+            .set("X-Vault-Token", &self.token)
+            .call()
+            .map_err(|e| e.to_string())?
+            .into_json::<Value>()
+            .map_err(|e| e.to_string())?;
+
+        // version 2 has the values in `data.data` (with `data.metadata`)
+        let data = res.get("data").ok_or("no data in response")?;
+        match data.get("data") {
+            Some(v) if data.get("metadata").is_some() => to_values(v),
+            _ => to_values(data),
+        }
+    }
+}
+
+/// AWS Secrets Manager (the secret string must be JSON).
+pub struct AwsSecretsManager {
+    pub region: String,
+    pub secret_id: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// only for temporary credentials
+    pub session_token: String,
+}
+
+impl AwsSecretsManager {
+    const SERVICE: &'static str = "secretsmanager";
+    const TARGET: &'static str = "secretsmanager.GetSecretValue";
+
+    fn host(&self) -> String {
+        format!("{}.{}.amazonaws.com", Self::SERVICE, self.region)
+    }
+}
+
+// the signing key of AWS Signature Version 4
+fn signing_key(
+    secret: &str,
+    date: &str,
+    region: &str,
+    service: &str,
+) -> Vec<u8> {
+    fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_varkey(key).unwrap();
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    let key = hmac(format!("AWS4{}", secret).as_bytes(), date);
+    let key = hmac(&key, region);
+    let key = hmac(&key, service);
+    hmac(&key, "aws4_request")
+}
+
+// returns the signature of AWS Signature Version 4. The headers must be
+// sorted by the names in lowercase.
+fn signature(
+    key: &[u8],
+    datetime: &str,
+    scope: &str,
+    request: (&str, &str, &str),
+    headers: &[(&str, &str)],
+    body: &str,
+) -> String {
+    let (method, path, query) = request;
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(k, v)| format!("{}:{}\n", k, v.trim()))
+        .collect();
+    let signed_headers: Vec<&str> = headers.iter().map(|(k, _)| *k).collect();
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method,
+        path,
+        query,
+        canonical_headers,
+        signed_headers.join(";"),
+        hex(&Sha256::digest(body.as_bytes())),
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        datetime,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes())),
+    );
+
+    let mut mac = Hmac::<Sha256>::new_varkey(key).unwrap();
+    mac.update(string_to_sign.as_bytes());
+    hex(&mac.finalize().into_bytes())
+}
+
+impl SecretsProvider for AwsSecretsManager {
+    fn fetch(
+        &self,
+        clock: &dyn Clock,
+    ) -> Result<HashMap<String, String>, String> {
+        let now = clock.now();
+        let datetime = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let scope = format!(
+            "{}/{}/{}/aws4_request",
+            date,
+            self.region,
+            Self::SERVICE
+        );
+
+        let body = json!({ "SecretId": self.secret_id }).to_string();
+        let host = self.host();
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1"),
+            ("host", host.as_str()),
+            ("x-amz-date", datetime.as_str()),
+        ];
+        if !self.session_token.is_empty() {
+            headers.push(("x-amz-security-token", self.session_token.as_str()));
+        }
+        headers.push(("x-amz-target", Self::TARGET));
+
+        let key = signing_key(
+            &self.secret_access_key,
+            &date,
+            &self.region,
+            Self::SERVICE,
+        );
+        let signature = signature(
+            &key,
+            &datetime,
+            &scope,
+            ("POST", "/", ""),
+            &headers,
+            &body,
+        );
+        let signed_headers: Vec<&str> =
+            headers.iter().map(|(k, _)| *k).collect();
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, \
+             Signature={}",
+            self.access_key_id,
+            scope,
+            signed_headers.join(";"),
+            signature,
+        );
+
+        let mut req = ureq::post(&format!("https://{}/", host))
+            .timeout(TIMEOUT)
+            .set("Authorization", &authorization)
+            .set("User-Agent", USER_AGENT);
+        for (k, v) in &headers {
+            if *k != "host" {
+                req = req.set(k, v);
+            }
+        }
+        let res = req
+            .send_string(&body)
+            .map_err(|e| e.to_string())?
+            .into_json::<Value>()
+            .map_err(|e| e.to_string())?;
+
+        let secret = res
+            .get("SecretString")
+            .and_then(Value::as_str)
+            .ok_or("no SecretString in response")?;
+        let value = serde_json::from_str::<Value>(secret)
+            .map_err(|e| e.to_string())?;
+        to_values(&value)
+    }
+}
+
+// the credentials which can be rotated without restart
+fn credentials(c: &Config) -> [&str; 4] {
+    [
+        c.database_url.as_str(),
+        c.database_replica_url.as_str(),
+        c.mailer_smtp_username.as_str(),
+        c.mailer_smtp_password.as_str(),
+    ]
+}
+
+/// Refresher reloads the config periodically in a thread.
+pub struct Refresher {
+    config: Config,
+    logger: Logger,
+}
+
+impl Refresher {
+    pub fn new(config: &Config, logger: &Logger) -> Self {
+        Self {
+            config: config.clone(),
+            logger: logger.clone(),
+        }
+    }
+
+    /// Calls `on_rotate` with the current and the new config when any of the
+    /// credentials has been rotated. It does nothing without the provider or
+    /// the interval.
+    pub fn spawn<F>(self, on_rotate: F)
+    where F: Fn(&Config, &Config) + Send + 'static {
+        let interval = self.config.secrets_refresh_interval;
+        if self.config.secrets_provider == "none" || interval == 0 {
+            return;
+        }
+
+        let Refresher { mut config, logger } = self;
+        thread::spawn(move || {
+            loop {
+                thread::sleep(Duration::from_secs(interval));
+
+                // keeps the current one if the provider isn't available
+                let c = match Config::from(config.env_name) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        error!(logger, "err: {}", e);
+                        continue;
+                    },
+                };
+                if credentials(&c) != credentials(&config) {
+                    info!(logger, "secrets have been rotated");
+                    on_rotate(&config, &c);
+                    config = c;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn test_to_values() {
+        let value = json!({"database_url": "postgresql://", "PORT": 465});
+        let values = to_values(&value).unwrap();
+        assert_eq!(values.get("DATABASE_URL").unwrap(), "postgresql://");
+        assert_eq!(values.get("PORT").unwrap(), "465");
+
+        assert!(to_values(&json!("postgresql://")).is_err());
+    }
+
+    // the example in the AWS General Reference
+    #[test]
+    fn test_signature() {
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20150830",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex(&key),
+            "c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9"
+        );
+
+        let headers = vec![
+            (
+                "content-type",
+                "application/x-www-form-urlencoded; charset=utf-8",
+            ),
+            ("host", "iam.amazonaws.com"),
+            ("x-amz-date", "20150830T123600Z"),
+        ];
+        assert_eq!(
+            signature(
+                &key,
+                "20150830T123600Z",
+                "20150830/us-east-1/iam/aws4_request",
+                ("GET", "/", "Action=ListUsers&Version=2010-05-08"),
+                &headers,
+                "",
+            ),
+            "5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+}
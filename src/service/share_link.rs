@@ -0,0 +1,133 @@
+//! Shareable read-only links of messages.
+//!
+//! A member of a namespace can share a message with the people who are not
+//! members (e.g. in a chat during an incident) by a link, which shows the
+//! message (and the ones around it in the stream, if asked) without any
+//! authentication until it expires.
+//!
+//! The token of a link is the claims in base64 and the HMAC of them by
+//! `AUTHENTICATION_TOKEN_SECRET` (like `service::badge`). Nothing is stored,
+//! thus a link can't be revoked, but it doesn't work after the message is
+//! deleted or the secret is rotated.
+use base64::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Seconds until a link expires (by default).
+pub const EXPIRATION: i64 = 86_400;
+
+/// The max seconds of the expiration.
+pub const EXPIRATION_MAX: i64 = 604_800;
+
+/// The max number of the messages before (and after) the shared one.
+pub const CONTEXT_MAX: i64 = 10;
+
+/// ShareLink
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ShareLink {
+    /// The uuid of the stream
+    pub stream: String,
+    /// The id of the message
+    pub message: String,
+    /// The number of the messages before (and after) it
+    pub context: i64,
+    /// Unix time
+    pub expires_at: i64,
+}
+
+fn mac(claims: &str, secret: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap();
+    mac.update(format!("share:{}", claims).as_bytes());
+    mac
+}
+
+impl ShareLink {
+    /// Returns the token of the link.
+    pub fn encode(&self, secret: &str) -> String {
+        let json = serde_json::to_string(self).unwrap();
+        let claims = base64::encode_config(json.as_bytes(), URL_SAFE_NO_PAD);
+        let bytes = mac(&claims, secret).finalize().into_bytes();
+        let signature = base64::encode_config(&bytes, URL_SAFE_NO_PAD);
+        format!("{}.{}", claims, signature)
+    }
+
+    /// Returns the link of the token if it's valid and not expired.
+    pub fn decode(
+        token: &str,
+        secret: &str,
+        now: i64,
+    ) -> Result<Self, &'static str> {
+        let mut parts = token.splitn(2, '.');
+        let (claims, signature) = match (parts.next(), parts.next()) {
+            (Some(c), Some(s)) => (c, s),
+            _ => return Err("malformed token"),
+        };
+        let bytes = base64::decode_config(signature, URL_SAFE_NO_PAD)
+            .map_err(|_| "malformed token")?;
+        mac(claims, secret)
+            .verify(&bytes)
+            .map_err(|_| "invalid signature")?;
+
+        let json = base64::decode_config(claims, URL_SAFE_NO_PAD)
+            .map_err(|_| "malformed token")?;
+        let link: Self =
+            serde_json::from_slice(&json).map_err(|_| "malformed token")?;
+        if link.expires_at <= now {
+            return Err("expired");
+        }
+        Ok(link)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn link() -> ShareLink {
+        ShareLink {
+            stream: "c5bb6bf2-87cd-4d24-a8b5-a1e2e5ee2b4b".to_string(),
+            message: "01F9DKJ8M00000000000000001".to_string(),
+            context: 3,
+            expires_at: 1_626_048_000,
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let token = link().encode("secret");
+        assert_eq!(
+            ShareLink::decode(&token, "secret", 1_626_047_999),
+            Ok(link())
+        );
+        assert_eq!(
+            ShareLink::decode(&token, "secret", 1_626_048_000),
+            Err("expired")
+        );
+        assert_eq!(
+            ShareLink::decode(&token, "another", 1_626_047_999),
+            Err("invalid signature")
+        );
+        assert_eq!(
+            ShareLink::decode("", "secret", 1_626_047_999),
+            Err("malformed token")
+        );
+    }
+
+    #[test]
+    fn test_decode_tampered_token() {
+        let token = link().encode("secret");
+        let signature = token.split('.').nth(1).unwrap();
+
+        let mut other = link();
+        other.context = CONTEXT_MAX;
+        let claims = other.encode("secret");
+        let claims = claims.split('.').next().unwrap();
+
+        let token = format!("{}.{}", claims, signature);
+        assert_eq!(
+            ShareLink::decode(&token, "secret", 1_626_047_999),
+            Err("invalid signature")
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! SLOs of availability and latency with error budgets.
+//!
+//! The availability SLO is the percent of the requests which don't fail with
+//! 5xx (`SLO_AVAILABILITY_TARGET`), and the latency SLO is the percent of the
+//! requests which are faster than `SLO_LATENCY_THRESHOLD` milliseconds
+//! (`SLO_LATENCY_TARGET`). They are computed from the request metrics (see
+//! `service::request_metrics`) over each of `SLO_WINDOWS` (in minutes).
+//!
+//! The burn rate is how fast the error budget is spent in a window (1.0 spends
+//! exactly the budget over the SLO period). If `SLO_ALERT_BURN_RATE` is set,
+//! `CheckSloBurnRates` job alerts when the burn rates of all the windows
+//! exceed it (e.g. both the 1 hour and the 5 minutes ones, so that it doesn't
+//! alert on a short spike or on a burn which has already stopped). The alert
+//! is logged as an error, and it's posted to `SLO_ALERT_WEBHOOK_URL` if it's
+//! set.
+use std::time::Duration;
+
+use redis::{Connection, RedisResult};
+
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::service::request_metrics::{RequestCounts, RequestMetrics};
+
+const TIMEOUT: u64 = 10; // seconds
+
+const USER_AGENT: &str = "eloquentlog-console-api";
+
+/// BurnRate
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BurnRate {
+    /// minutes
+    pub window: u64,
+    pub requests: u64,
+    pub errors: u64,
+    pub slow: u64,
+    pub availability: f64,
+    pub latency: f64,
+}
+
+/// Returns the ratio of the bad ones to the budget of the target (percent).
+pub fn burn_rate(bad: u64, total: u64, target: f64) -> f64 {
+    let budget = 1.0 - target / 100.0;
+    if total == 0 || budget <= 0.0 {
+        return 0.0;
+    }
+    (bad as f64 / total as f64) / budget
+}
+
+/// Returns true if all the burn rates (of either SLO) exceed the threshold.
+pub fn is_burning(rates: &[BurnRate], threshold: f64) -> bool {
+    if rates.is_empty() || threshold <= 0.0 {
+        return false;
+    }
+    rates.iter().all(|r| r.availability > threshold) ||
+        rates.iter().all(|r| r.latency > threshold)
+}
+
+pub struct Slo<'a> {
+    config: &'a Config,
+    logger: &'a Logger,
+}
+
+impl<'a> Slo<'a> {
+    pub fn new(config: &'a Config, logger: &'a Logger) -> Self {
+        Self { config, logger }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.config.slo_windows.is_empty()
+    }
+
+    fn rate(&self, window: u64, counts: &RequestCounts) -> BurnRate {
+        BurnRate {
+            window,
+            requests: counts.total,
+            errors: counts.errors,
+            slow: counts.slow,
+            availability: burn_rate(
+                counts.errors,
+                counts.total,
+                self.config.slo_availability_target,
+            ),
+            latency: burn_rate(
+                counts.slow,
+                counts.total,
+                self.config.slo_latency_target,
+            ),
+        }
+    }
+
+    /// Returns the burn rates of the windows until the minute (the unix time
+    /// in minutes).
+    pub fn burn_rates(
+        &self,
+        conn: &mut Connection,
+        minute: i64,
+    ) -> RedisResult<Vec<BurnRate>> {
+        let mut metrics = RequestMetrics::new(conn);
+        let mut rates = vec![];
+        for window in &self.config.slo_windows {
+            let counts = metrics.sum(minute, *window)?;
+            rates.push(self.rate(*window, &counts));
+        }
+        Ok(rates)
+    }
+
+    /// Alerts if the error budget is burning too fast, and returns true if
+    /// it's alerted.
+    pub fn check(&self, rates: &[BurnRate]) -> bool {
+        if !is_burning(rates, self.config.slo_alert_burn_rate) {
+            return false;
+        }
+        for r in rates {
+            error!(
+                self.logger,
+                "slo: burn rate over {} minutes: availability {:.2}, latency \
+                 {:.2} ({} requests)",
+                r.window,
+                r.availability,
+                r.latency,
+                r.requests
+            );
+        }
+
+        let url = &self.config.slo_alert_webhook_url;
+        if !url.is_empty() {
+            let payload = serde_json::json!({
+                "text": "The error budget is burning too fast",
+                "threshold": self.config.slo_alert_burn_rate,
+                "burn_rates": rates,
+            });
+            if let Err(e) = ureq::post(url)
+                .set("User-Agent", USER_AGENT)
+                .timeout(Duration::from_secs(TIMEOUT))
+                .send_json(payload)
+            {
+                error!(self.logger, "err: {}", e);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rate(window: u64, availability: f64, latency: f64) -> BurnRate {
+        BurnRate {
+            window,
+            requests: 100,
+            errors: 0,
+            slow: 0,
+            availability,
+            latency,
+        }
+    }
+
+    #[test]
+    fn test_burn_rate() {
+        let cases = [
+            ((0, 0, 99.9), 0.0),
+            ((0, 1000, 99.9), 0.0),
+            ((1, 1000, 99.9), 1.0),
+            ((5, 100, 99.0), 5.0),
+            // no budget
+            ((1, 10, 100.0), 0.0),
+        ];
+        for ((bad, total, target), expected) in cases.iter() {
+            let rate = burn_rate(*bad, *total, *target);
+            assert!((rate - expected).abs() < 1e-9, "{} {}", bad, total);
+        }
+    }
+
+    #[test]
+    fn test_is_burning() {
+        let rates = vec![rate(60, 15.0, 0.5), rate(5, 20.0, 30.0)];
+        assert!(is_burning(&rates, 14.4));
+        assert!(!is_burning(&rates, 0.0));
+        assert!(!is_burning(&[], 14.4));
+
+        // the burn has stopped
+        let rates = vec![rate(60, 15.0, 0.5), rate(5, 1.0, 0.0)];
+        assert!(!is_burning(&rates, 14.4));
+
+        let rates = vec![rate(60, 0.0, 20.0), rate(5, 0.0, 15.0)];
+        assert!(is_burning(&rates, 14.4));
+    }
+}
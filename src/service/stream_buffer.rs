@@ -0,0 +1,370 @@
+//! Write-behind buffer of ingested messages.
+//!
+//! If `STREAM_BUFFER_FLUSH_INTERVAL` is set, an ingested message is pushed
+//! into a list `sb-<stream_id>` in the session store instead of being saved,
+//! and `FlushStreamBuffers` job saves the lists into `messages` (see
+//! `request::stream_buffer`). The message is accepted already while it's in
+//! the buffer, so the pending ones are the durability window of the stream.
+//!
+//! Messages are saved before they are removed from the list, and saving is
+//! idempotent by the id. A failed flush is just retried by the next one.
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::result::Error;
+use redis::{Commands, Connection};
+
+use crate::clock::Clock;
+use crate::logger::Logger;
+use crate::model::message::{
+    AgentType, LogFormat, LogLevel, Message, MessageRow, NewMessage,
+};
+use crate::model::namespace::Namespace;
+
+pub const KEY_PREFIX: &str = "sb-";
+const FLUSH_LOCK_PREFIX: &str = "sb:lock:";
+const FLUSH_LOCK_EXPIRATION: usize = 300; // seconds
+
+const BATCH_SIZE: isize = 500; // messages per transaction
+
+pub fn buffer_key(stream_id: i64) -> String {
+    format!("{}{}", KEY_PREFIX, stream_id)
+}
+
+/// Returns the stream id in the key.
+pub fn parse_buffer_key(key: &str) -> Option<i64> {
+    if !key.starts_with(KEY_PREFIX) {
+        return None;
+    }
+    key[KEY_PREFIX.len()..].parse::<i64>().ok()
+}
+
+/// Entry
+///
+/// A buffered message, which is stored as JSON in the list.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    pub id: String,
+    pub agent_id: i64,
+    pub agent_type: String,
+    pub stream_id: i64,
+    pub code: Option<String>,
+    pub lang: String,
+    pub level: String,
+    pub format: String,
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub created_at: NaiveDateTime,
+    // these are missing in the entries which were buffered before they are
+    // added
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub hostname: Option<String>,
+    pub service: Option<String>,
+    pub environment: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl From<&MessageRow> for Entry {
+    fn from(row: &MessageRow) -> Self {
+        let m = &row.message;
+        Self {
+            id: row.id.clone(),
+            agent_id: m.agent_id,
+            agent_type: m.agent_type.to_string(),
+            stream_id: m.stream_id,
+            code: m.code.clone(),
+            lang: m.lang.clone(),
+            level: m.level.to_string(),
+            format: m.format.to_string(),
+            title: m.title.clone(),
+            content: m.content.clone(),
+            created_at: row.created_at,
+            trace_id: m.trace_id.clone(),
+            span_id: m.span_id.clone(),
+            hostname: m.hostname.clone(),
+            service: m.service.clone(),
+            environment: m.environment.clone(),
+            tags: m.tags.clone(),
+        }
+    }
+}
+
+impl From<Entry> for MessageRow {
+    fn from(entry: Entry) -> Self {
+        Self {
+            id: entry.id,
+            message: NewMessage {
+                agent_id: entry.agent_id,
+                agent_type: AgentType::from(entry.agent_type),
+                stream_id: entry.stream_id,
+                code: entry.code,
+                lang: entry.lang,
+                level: LogLevel::from(entry.level),
+                format: LogFormat::from(entry.format),
+                title: entry.title,
+                content: entry.content,
+                trace_id: entry.trace_id,
+                span_id: entry.span_id,
+                hostname: entry.hostname,
+                service: entry.service,
+                environment: entry.environment,
+                tags: entry.tags,
+            },
+            created_at: entry.created_at,
+        }
+    }
+}
+
+/// BufferState
+///
+/// The messages in the buffer of a stream which are not saved yet.
+#[derive(Debug, PartialEq)]
+pub struct BufferState {
+    pub pending: usize,
+    pub oldest_created_at: Option<NaiveDateTime>,
+}
+
+impl BufferState {
+    /// Returns seconds since the oldest pending message was accepted.
+    pub fn oldest_age(&self, now: NaiveDateTime) -> Option<i64> {
+        self.oldest_created_at
+            .map(|t| (now - t).num_seconds().max(0))
+    }
+}
+
+pub struct StreamBuffer<'a> {
+    ss_conn: &'a mut Connection,
+    logger: &'a Logger,
+}
+
+impl<'a> StreamBuffer<'a> {
+    pub fn new(ss_conn: &'a mut Connection, logger: &'a Logger) -> Self {
+        Self { ss_conn, logger }
+    }
+
+    /// Appends the message to the buffer of its stream.
+    pub fn push(&mut self, row: &MessageRow) -> Result<(), &'static str> {
+        let logger = self.logger;
+        let value = serde_json::to_string(&Entry::from(row))
+            .map_err(|e| fail(logger, e, "failed to encode message"))?;
+        self.ss_conn
+            .rpush::<_, _, i64>(buffer_key(row.message.stream_id), value)
+            .map(|_| ())
+            .map_err(|e| fail(logger, e, "failed to push message"))
+    }
+
+    pub fn state(
+        &mut self,
+        stream_id: i64,
+    ) -> Result<BufferState, &'static str> {
+        let logger = self.logger;
+        let key = buffer_key(stream_id);
+        let (pending, oldest): (usize, Option<String>) = redis::pipe()
+            .llen(&key)
+            .lindex(&key, 0)
+            .query(&mut *self.ss_conn)
+            .map_err(|e| fail(logger, e, "failed to load buffer"))?;
+        let oldest_created_at = oldest
+            .and_then(|v| serde_json::from_str::<Entry>(&v).ok())
+            .map(|e| e.created_at);
+        Ok(BufferState {
+            pending,
+            oldest_created_at,
+        })
+    }
+
+    /// Saves the messages in the buffer of the stream, and returns the number
+    /// of them. It fails if another flush of the stream is running.
+    pub fn flush(
+        &mut self,
+        stream_id: i64,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+    ) -> Result<usize, &'static str> {
+        let logger = self.logger;
+        let lock = format!("{}{}", FLUSH_LOCK_PREFIX, stream_id);
+        let locked = redis::cmd("SET")
+            .arg(&lock)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(FLUSH_LOCK_EXPIRATION)
+            .query::<Option<String>>(&mut *self.ss_conn)
+            .map_err(|e| fail(logger, e, "failed to lock buffer"))?;
+        if locked.is_none() {
+            return Err("flush in progress");
+        }
+
+        let result = self.drain(stream_id, clock, conn);
+        let _: Result<i64, _> = self.ss_conn.del(&lock);
+        result
+    }
+
+    /// Flushes the buffers of all the streams (e.g. by the job), and returns
+    /// the number of the saved messages.
+    pub fn flush_all(
+        &mut self,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+    ) -> Result<usize, &'static str> {
+        let logger = self.logger;
+        let keys: Vec<String> = self
+            .ss_conn
+            .scan_match::<_, String>(format!("{}*", KEY_PREFIX))
+            .map_err(|e| fail(logger, e, "failed to scan buffers"))?
+            .collect();
+
+        let mut count = 0;
+        for stream_id in keys.iter().filter_map(|k| parse_buffer_key(k)) {
+            match self.flush(stream_id, clock, conn) {
+                Ok(n) => count += n,
+                Err(e) => {
+                    error!(logger, "err: stream {} {}", stream_id, e)
+                },
+            }
+        }
+        Ok(count)
+    }
+
+    // saves the messages from the head of the list in batches
+    fn drain(
+        &mut self,
+        stream_id: i64,
+        clock: &dyn Clock,
+        conn: &PgConnection,
+    ) -> Result<usize, &'static str> {
+        let logger = self.logger;
+        let key = buffer_key(stream_id);
+        let deduplicates = Namespace::find_by_stream_id(stream_id, conn, logger)
+            .map_or(false, |n| n.deduplicates_messages);
+
+        let mut count = 0;
+        loop {
+            let values: Vec<String> = self
+                .ss_conn
+                .lrange(&key, 0, BATCH_SIZE - 1)
+                .map_err(|e| fail(logger, e, "failed to load buffer"))?;
+            if values.is_empty() {
+                break;
+            }
+
+            let saved = conn
+                .build_transaction()
+                .run::<_, Error, _>(|| {
+                    let mut n = 0;
+                    for v in values.iter() {
+                        let entry = match serde_json::from_str::<Entry>(v) {
+                            Ok(e) => e,
+                            Err(e) => {
+                                // it can't be saved anyway
+                                error!(logger, "err: invalid entry {}", e);
+                                continue;
+                            },
+                        };
+                        let row = MessageRow::from(entry);
+                        n += Message::insert_row(
+                            row,
+                            deduplicates,
+                            clock,
+                            conn,
+                            logger,
+                        )?;
+                    }
+                    Ok(n)
+                })
+                .map_err(|e| fail(logger, e, "failed to save messages"))?;
+
+            self.ss_conn
+                .ltrim::<_, ()>(&key, values.len() as isize, -1)
+                .map_err(|e| fail(logger, e, "failed to trim buffer"))?;
+            count += saved;
+        }
+        info!(logger, "flushed: stream {} ({} messages)", stream_id, count);
+        Ok(count)
+    }
+}
+
+fn fail(
+    logger: &Logger,
+    e: impl fmt::Display,
+    message: &'static str,
+) -> &'static str {
+    error!(logger, "err: {}", e);
+    message
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn test_buffer_key() {
+        assert_eq!(buffer_key(3), "sb-3");
+        assert_eq!(parse_buffer_key("sb-3"), Some(3));
+        assert_eq!(parse_buffer_key("sb-"), None);
+        assert_eq!(parse_buffer_key("sb-x"), None);
+        assert_eq!(parse_buffer_key("rv-3"), None);
+    }
+
+    #[test]
+    fn test_entry() {
+        let created_at = Utc.ymd(2021, 6, 30).and_hms(9, 0, 0).naive_utc();
+        let row = MessageRow {
+            id: "01F9DKJ8M00000000000000001".to_string(),
+            message: NewMessage {
+                agent_id: 1,
+                agent_type: AgentType::Client,
+                stream_id: 2,
+                level: LogLevel::Warning,
+                title: Some("title".to_string()),
+                content: Some("content".to_string()),
+                trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+                tags: vec!["environment=staging".to_string()],
+
+                ..Default::default()
+            },
+            created_at,
+        };
+        let entry = Entry::from(&row);
+        assert_eq!(entry.agent_type, "client");
+        assert_eq!(entry.level, "warning");
+
+        let value = serde_json::to_string(&entry).unwrap();
+        let entry = serde_json::from_str::<Entry>(&value).unwrap();
+        let restored = MessageRow::from(entry);
+        assert_eq!(restored.to_csv(), row.to_csv());
+        assert_eq!(restored.message.trace_id, row.message.trace_id);
+        assert_eq!(restored.message.tags, row.message.tags);
+
+        // buffered before trace ids
+        let mut value = serde_json::to_value(Entry::from(&row)).unwrap();
+        let m = value.as_object_mut().unwrap();
+        m.remove("trace_id");
+        m.remove("span_id");
+        m.remove("tags");
+        let entry = serde_json::from_value::<Entry>(value).unwrap();
+        assert_eq!(entry.trace_id, None);
+        assert!(entry.tags.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_age() {
+        let now = Utc.ymd(2021, 6, 30).and_hms(9, 0, 0).naive_utc();
+        let state = BufferState {
+            pending: 2,
+            oldest_created_at: Some(now - Duration::seconds(90)),
+        };
+        assert_eq!(state.oldest_age(now), Some(90));
+
+        let state = BufferState {
+            pending: 0,
+            oldest_created_at: None,
+        };
+        assert_eq!(state.oldest_age(now), None);
+    }
+}
@@ -0,0 +1,73 @@
+//! Session-to-token exchange.
+//!
+//! Inline scripts on server-rendered pages can't use the web session, because
+//! its `sign` cookie is HttpOnly. Instead, they call the API with a token
+//! exchanged for the session, which is short-lived and has only one scope.
+//! It's given as `Authorization: Exchanged-Token <token>` header.
+//!
+//! The subject of the token is kept in the session store with expiration.
+use redis::{Commands, Connection, RedisResult};
+
+use crate::config::Config;
+use crate::id::IdGenerator;
+use crate::model::access_token::Scope;
+use crate::model::user::User;
+
+pub const KEY_PREFIX: &str = "sx-";
+
+/// Seconds until an exchanged token expires.
+pub const EXPIRATION: usize = 300;
+
+const SUBJECT_LENGTH: usize = 32;
+
+pub fn key(subject: &str) -> String {
+    format!("{}{}", KEY_PREFIX, subject)
+}
+
+pub struct TokenExchange<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> TokenExchange<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    /// Saves a new subject for the user and the scope, and returns it.
+    pub fn issue(
+        &mut self,
+        user: &User,
+        scope: Scope,
+        ids: &dyn IdGenerator,
+    ) -> RedisResult<String> {
+        let subject = ids.random_hash(Config::CSRF_HASH_SOURCE, SUBJECT_LENGTH);
+        let value = format!("{} {}", user.uuid.to_urn(), scope);
+        let _: () = self.conn.set_ex(key(&subject), value, EXPIRATION)?;
+        Ok(subject)
+    }
+
+    /// Returns the uuid (URN) of the user and the scope for the subject if it
+    /// has not been expired yet.
+    pub fn find(
+        &mut self,
+        subject: &str,
+    ) -> RedisResult<Option<(String, Scope)>> {
+        let value: Option<String> = self.conn.get(key(subject))?;
+        Ok(value.and_then(|v| {
+            let mut parts = v.splitn(2, ' ');
+            let uuid = parts.next()?.to_string();
+            let scope = Scope::from_name(parts.next()?)?;
+            Some((uuid, scope))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key("s"), "sx-s");
+    }
+}
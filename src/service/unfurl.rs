@@ -0,0 +1,178 @@
+//! Link previews (unfurls) of messages.
+//!
+//! A permalink of a message (`URL_TEMPLATE_MESSAGE`) is a page of the
+//! frontend. It takes the metadata of the preview from the unfurl API, and
+//! embeds it as Open Graph tags and an oEmbed (`link` type) document, so that
+//! chat tools (e.g. Slack) show the level, the time and the head of the
+//! content instead of the bare URL.
+//!
+//! Permalinks are private. The metadata is returned only to the members of
+//! the namespace, and the others can't tell whether the message exists.
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::model::message::Message;
+
+pub const PROVIDER_NAME: &str = "Eloquentlog";
+
+// characters (including an ellipsis)
+const TITLE_MAX_LENGTH: usize = 100;
+const DESCRIPTION_MAX_LENGTH: usize = 200;
+
+// seconds for consumers to cache the preview
+const CACHE_AGE: u64 = 300;
+
+/// Collapses the whitespace of the text, and truncates it at a character
+/// boundary with an ellipsis.
+pub fn truncate(text: &str, max: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max {
+        return text;
+    }
+    let mut s: String = text.chars().take(max.saturating_sub(1)).collect();
+    s.push('…');
+    s
+}
+
+/// Returns the permalink of the message on the frontend.
+pub fn permalink(
+    config: &Config,
+    namespace_key: &str,
+    stream_uuid: &str,
+    id: &str,
+) -> String {
+    let application_url = config.application_url.trim_end_matches('/');
+    config
+        .url_template_message
+        .replace("{application_url}", application_url)
+        .replace("{namespace}", namespace_key)
+        .replace("{stream}", stream_uuid)
+        .replace("{id}", id)
+}
+
+// e.g. `error at 2019-08-07 06:05:04 UTC: Connection ...`
+fn description(m: &Message) -> String {
+    let summary = format!(
+        "{} at {} UTC",
+        m.level,
+        m.created_at.format("%Y-%m-%d %H:%M:%S")
+    );
+    let content = m
+        .content
+        .as_ref()
+        .map(|c| truncate(c, DESCRIPTION_MAX_LENGTH))
+        .unwrap_or_default();
+    if content.is_empty() {
+        summary
+    } else {
+        format!("{}: {}", summary, content)
+    }
+}
+
+/// Returns the metadata of the preview for the permalink of the message.
+pub fn unfurl(config: &Config, url: &str, m: &Message) -> Value {
+    let title = truncate(&m.title, TITLE_MAX_LENGTH);
+    let description = description(m);
+    serde_json::json!({
+        "url": url,
+        "title": title,
+        "description": description,
+        "level": m.level.to_string(),
+        "created_at": m.created_at,
+        "oembed": {
+            "version": "1.0",
+            "type": "link",
+            "title": title,
+            "provider_name": PROVIDER_NAME,
+            "provider_url": config.application_url.trim_end_matches('/'),
+            "cache_age": CACHE_AGE,
+        },
+        "open_graph": {
+            "og:type": "article",
+            "og:site_name": PROVIDER_NAME,
+            "og:title": title,
+            "og:description": description,
+            "og:url": url,
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::model::message::{AgentType, LogFormat, LogLevel};
+    use crate::model::test::CONFIG;
+
+    fn message(content: Option<&str>) -> Message {
+        let t = Utc.ymd(2019, 8, 7).and_hms(6, 5, 4).naive_utc();
+        Message {
+            id: "01DHNAWKYD0000000000000001".to_string(),
+            agent_id: 1,
+            agent_type: AgentType::Person,
+            stream_id: 1,
+            code: None,
+            lang: "en".to_string(),
+            level: LogLevel::Error,
+            format: LogFormat::TOML,
+            title: "Connection timeout".to_string(),
+            content: content.map(|c| c.to_string()),
+            created_at: t,
+            updated_at: t,
+            tags: vec![],
+            deleted_at: None,
+            acknowledged_at: None,
+            resolved_at: None,
+            body_id: None,
+            occurrences_count: 1,
+            trace_id: None,
+            span_id: None,
+            hostname: None,
+            service: None,
+            environment: None,
+        }
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("  a\n\tb  ", 10), "a b");
+        assert_eq!(truncate("abcdef", 6), "abcdef");
+        assert_eq!(truncate("abcdefg", 6), "abcde…");
+        assert_eq!(truncate("ääääää", 3), "ää…");
+        assert_eq!(truncate("", 3), "");
+    }
+
+    #[test]
+    fn test_permalink() {
+        let url = permalink(&CONFIG, "ns", "st", "01DHNAWKYD0000000000000001");
+        let application_url = CONFIG.application_url.trim_end_matches('/');
+        assert_eq!(
+            url,
+            format!(
+                "{}/message/ns/st/01DHNAWKYD0000000000000001",
+                application_url
+            )
+        );
+    }
+
+    #[test]
+    fn test_unfurl() {
+        let m = message(Some("timed out\nafter 30s"));
+        let v = unfurl(&CONFIG, "https://example.org/m", &m);
+        assert_eq!(v["title"], "Connection timeout");
+        assert_eq!(
+            v["description"],
+            "error at 2019-08-07 06:05:04 UTC: timed out after 30s"
+        );
+        assert_eq!(v["level"], "error");
+        assert_eq!(v["oembed"]["type"], "link");
+        assert_eq!(v["oembed"]["provider_name"], PROVIDER_NAME);
+        assert_eq!(v["open_graph"]["og:url"], "https://example.org/m");
+
+        let m = message(None);
+        let v = unfurl(&CONFIG, "https://example.org/m", &m);
+        assert_eq!(v["description"], "error at 2019-08-07 06:05:04 UTC");
+    }
+}
@@ -0,0 +1,188 @@
+//! User agents of API callers.
+//!
+//! The `User-Agent` header of requests with personal access tokens is
+//! recorded per token, for a sample of them (`USER_AGENT_SAMPLE_RATE` in
+//! percent). It's kept in the session store as a sorted set `ua-<uuid>` of
+//! the token (agent => timestamp of the last one seen), and it's shown in the
+//! token list, so that we can see which clients (and versions of SDKs) are
+//! still used before breaking changes.
+use rand::{Rng, thread_rng};
+use redis::{Commands, Connection, RedisResult};
+use rocket::{Request, Response, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket_slog::SyncLogger;
+use uuid::Uuid;
+
+use crate::clock::SharedClock;
+use crate::config::Config;
+use crate::db::DbConn;
+use crate::model::access_token::AccessToken;
+use crate::model::token::{Claims, PersonalAccessTokenClaims};
+use crate::model::user::User;
+use crate::request::token::TokenType;
+use crate::request::token::authentication::AuthenticationToken;
+use crate::ss::SsConn;
+
+pub const KEY_PREFIX: &str = "ua-";
+
+/// The number of agents kept per token (the latest ones).
+pub const MAX_AGENTS: isize = 20;
+
+const MAX_LENGTH: usize = 256;
+const KEY_EXPIRATION: usize = 2_592_000; // 30 days
+
+pub fn key(uuid: &Uuid) -> String {
+    format!("{}{}", KEY_PREFIX, uuid)
+}
+
+/// Returns true if the roll (0..100) is in the rate (percent).
+pub fn sampled(rate: u32, roll: u32) -> bool {
+    roll < rate
+}
+
+fn truncate(agent: &str) -> String {
+    agent.trim().chars().take(MAX_LENGTH).collect()
+}
+
+pub struct UserAgents<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> UserAgents<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn record(
+        &mut self,
+        uuid: &Uuid,
+        agent: &str,
+        now: i64,
+    ) -> RedisResult<()> {
+        let agent = truncate(agent);
+        if agent.is_empty() {
+            return Ok(());
+        }
+        let key = key(uuid);
+        redis::pipe()
+            .zadd(&key, agent, now)
+            .ignore()
+            .zremrangebyrank(&key, 0, -MAX_AGENTS - 1)
+            .ignore()
+            .expire(&key, KEY_EXPIRATION)
+            .ignore()
+            .query(&mut *self.conn)
+    }
+
+    /// Returns the agents with the timestamps (the latest first).
+    pub fn latest(&mut self, uuid: &Uuid) -> RedisResult<Vec<(String, i64)>> {
+        self.conn.zrevrange_withscores(key(uuid), 0, MAX_AGENTS - 1)
+    }
+}
+
+/// UserAgentSampling records the agents of requests with personal access
+/// tokens.
+pub struct UserAgentSampling;
+
+impl Fairing for UserAgentSampling {
+    fn info(&self) -> Info {
+        Info {
+            name: "User-Agent Sampling",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, req: &Request, _: &mut Response) {
+        let config = req.guard::<State<Config>>().unwrap();
+        if config.user_agent_sample_rate == 0 {
+            return;
+        }
+        match req.guard::<TokenType>().succeeded() {
+            Some(TokenType::PersonalAccessToken) => (),
+            _ => return,
+        }
+        // authenticated by the User guard
+        if req.local_cache(|| None::<User>).is_none() {
+            return;
+        }
+        let roll = thread_rng().gen_range(0..100);
+        if !sampled(config.user_agent_sample_rate, roll) {
+            return;
+        }
+        let agent = match req.headers().get_one("User-Agent") {
+            Some(v) => v,
+            None => return,
+        };
+
+        let logger = req.guard::<SyncLogger>().unwrap();
+        let token = match req.guard::<AuthenticationToken>().succeeded() {
+            Some(t) => t,
+            None => return,
+        };
+        let claims = match PersonalAccessTokenClaims::decode(
+            &token,
+            &config.authentication_token_issuer,
+            &config.authentication_token_secret,
+        ) {
+            Ok(c) => c,
+            Err(e) => {
+                error!(logger, "err: {}", e);
+                return;
+            },
+        };
+        let db_conn = req.guard::<DbConn>().unwrap();
+        let access_token = match AccessToken::find_by_token(
+            &claims.get_subject(),
+            &db_conn,
+            &logger,
+        ) {
+            Some(t) => t,
+            None => return,
+        };
+
+        let mut ss_conn = match req.guard::<SsConn>().succeeded() {
+            Some(conn) => conn,
+            None => {
+                error!(logger, "err: session store is not available");
+                return;
+            },
+        };
+        let clock = req.guard::<State<SharedClock>>().unwrap();
+        let now = clock.now().timestamp();
+        if let Err(e) = UserAgents::new(&mut *ss_conn).record(
+            &access_token.uuid,
+            agent,
+            now,
+        ) {
+            error!(logger, "err: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        let uuid = Uuid::nil();
+        assert_eq!(key(&uuid), "ua-00000000-0000-0000-0000-000000000000");
+    }
+
+    #[test]
+    fn test_sampled() {
+        assert!(!sampled(0, 0));
+        assert!(sampled(10, 0));
+        assert!(sampled(10, 9));
+        assert!(!sampled(10, 10));
+        assert!(sampled(100, 99));
+    }
+
+    #[test]
+    fn test_truncate() {
+        let agent = "eloquentlog-cli/0.1.0";
+        assert_eq!(truncate(&format!(" {} ", agent)), agent);
+        assert_eq!(truncate(&"a".repeat(300)).len(), MAX_LENGTH);
+        assert_eq!(truncate(""), "");
+    }
+}
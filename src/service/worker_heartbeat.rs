@@ -0,0 +1,118 @@
+//! Heartbeats of workers.
+//!
+//! Each worker beats every second into the message queue (Redis), and records
+//! when it finished a job of each kind last. `/_/readyz` surfaces them, so
+//! that a stuck worker (or no worker at all) is detectable by orchestration.
+//!
+//! A worker stops beating while a job runs longer than the timeout, and the
+//! lag of the queue is the age of the oldest deferred job which is overdue.
+use std::collections::HashMap;
+
+use redis::{Commands, Connection, RedisResult};
+
+use crate::job::DEFERRED_KEY;
+
+pub const KEY_PREFIX: &str = "wh-";
+
+// dead workers are forgotten after this (1 day)
+const WORKER_EXPIRATION: i64 = 86400;
+
+pub fn workers_key() -> String {
+    format!("{}workers", KEY_PREFIX)
+}
+
+pub fn jobs_key() -> String {
+    format!("{}jobs", KEY_PREFIX)
+}
+
+pub struct WorkerStatus {
+    /// The number of workers which beat within the timeout.
+    pub alive: usize,
+    /// The timestamp of the last heartbeat of any worker.
+    pub last_beat_at: Option<i64>,
+    /// Seconds since the oldest overdue deferred job was due.
+    pub queue_lag: i64,
+    /// The timestamps of the last finished job by kind.
+    pub jobs: HashMap<String, i64>,
+}
+
+impl WorkerStatus {
+    pub fn is_ready(&self, timeout: i64) -> bool {
+        self.alive > 0 && self.queue_lag < timeout
+    }
+}
+
+pub struct WorkerHeartbeat<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> WorkerHeartbeat<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn beat(&mut self, worker_id: &str, now: i64) -> RedisResult<()> {
+        self.conn.zadd(workers_key(), worker_id, now)
+    }
+
+    pub fn finish(&mut self, kind: &str, now: i64) -> RedisResult<()> {
+        self.conn.hset(jobs_key(), kind, now)
+    }
+
+    pub fn status(
+        &mut self,
+        now: i64,
+        timeout: i64,
+    ) -> RedisResult<WorkerStatus> {
+        let key = workers_key();
+        let _: i64 =
+            self.conn.zrembyscore(&key, "-inf", now - WORKER_EXPIRATION)?;
+        let alive: usize = self.conn.zcount(&key, now - timeout, "+inf")?;
+        let last: Vec<(String, i64)> =
+            self.conn.zrevrange_withscores(&key, 0, 0)?;
+
+        let overdue: Vec<(String, i64)> = self
+            .conn
+            .zrangebyscore_limit_withscores(DEFERRED_KEY, "-inf", now, 0, 1)?;
+
+        let jobs: HashMap<String, i64> = self.conn.hgetall(jobs_key())?;
+        Ok(WorkerStatus {
+            alive,
+            last_beat_at: last.first().map(|(_, t)| *t),
+            queue_lag: overdue.first().map(|(_, t)| now - t).unwrap_or(0),
+            jobs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(workers_key(), "wh-workers");
+        assert_eq!(jobs_key(), "wh-jobs");
+    }
+
+    #[test]
+    fn test_is_ready() {
+        let status = WorkerStatus {
+            alive: 1,
+            last_beat_at: Some(0),
+            queue_lag: 0,
+            jobs: HashMap::new(),
+        };
+        assert!(status.is_ready(30));
+
+        let status = WorkerStatus { alive: 0, ..status };
+        assert!(!status.is_ready(30));
+
+        let status = WorkerStatus {
+            alive: 1,
+            queue_lag: 30,
+            ..status
+        };
+        assert!(!status.is_ready(30));
+    }
+}
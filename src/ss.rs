@@ -8,6 +8,11 @@ use r2d2_redis::{
     r2d2::Pool, r2d2::PooledConnection, redis, RedisConnectionManager,
 };
 
+use crate::config::Config;
+use crate::service::fault_injection::fails_redis;
+use crate::service::pool_metrics::{PoolState, PoolStats};
+use crate::telemetry::{SpanKind, span};
+
 pub type SsPool = Pool<RedisConnectionManager>;
 pub type SsPooledConn = PooledConnection<RedisConnectionManager>;
 
@@ -17,11 +22,29 @@ pub struct SsConn(pub SsPooledConn);
 #[derive(Clone)]
 pub struct SsPoolHolder {
     pool: SsPool,
+    stats: PoolStats,
 }
 
 impl SsPoolHolder {
     pub fn get(&self) -> Option<SsPooledConn> {
-        self.pool.get().ok()
+        // waits for a connection in a span (see `telemetry`)
+        let s = span("redis.pool.get", SpanKind::Client);
+        s.set("db.system", "redis");
+        let conn = self.stats.wait(|| self.pool.get().ok());
+        if conn.is_none() {
+            s.fail();
+        }
+        conn
+    }
+
+    pub fn state(&self) -> PoolState {
+        let state = self.pool.state();
+        PoolState::new(
+            self.pool.max_size(),
+            state.connections,
+            state.idle_connections,
+            &self.stats,
+        )
     }
 }
 
@@ -30,8 +53,14 @@ impl<'a, 'r> FromRequest<'a, 'r> for SsConn {
 
     fn from_request(request: &'a Request<'r>) -> request::Outcome<SsConn, ()> {
         let holder = request.guard::<State<SsPoolHolder>>()?;
+        let config = request.guard::<State<Config>>()?;
         match holder.get() {
-            Some(conn) => Outcome::Success(SsConn(conn)),
+            Some(mut conn) => {
+                if fails_redis(&config, &mut conn) {
+                    return Outcome::Failure((Status::ServiceUnavailable, ()));
+                }
+                Outcome::Success(SsConn(conn))
+            },
             None => Outcome::Failure((Status::ServiceUnavailable, ())),
         }
     }
@@ -51,7 +80,8 @@ impl DerefMut for SsConn {
     }
 }
 
-// Initializes session store connection pool holder
+// Initializes session store connection pool holder (the URL has the TLS
+// options, see `Config::session_store_connection_url`)
 pub fn init_pool_holder(
     session_store_url: &str,
     max_size: u32,
@@ -62,5 +92,8 @@ pub fn init_pool_holder(
         .max_size(max_size)
         .build(connection_manager)
         .expect("session store pool");
-    SsPoolHolder { pool }
+    SsPoolHolder {
+        pool,
+        stats: PoolStats::default(),
+    }
 }
@@ -0,0 +1,482 @@
+//! Tracing spans exported over OTLP.
+//!
+//! If `OTLP_ENDPOINT` is set, each request is traced as a server span, and the
+//! hops in it are recorded as its child spans:
+//!
+//! * `db.query` for the queries run by `db::with_retry` and
+//!   `db::with_statement_timeout` (the ones of models)
+//! * `db.pool.get` and `redis.pool.get` for the waits for a connection of the
+//!   database, the message queue or the session store
+//! * `mailer.send` for the transports of emails
+//!
+//! A trace given by `traceparent` header (see `tracecontext`) is continued if
+//! it has been sampled by the caller, and it's not recorded otherwise. The
+//! jobs in the worker are traced in the same way as consumer spans.
+//!
+//! The spans of a trace are collected in the thread (a request is handled in
+//! a single thread), and they are posted to `<OTLP_ENDPOINT>/v1/traces` in
+//! the JSON encoding of OTLP/HTTP in the background when it finishes. `span`
+//! does nothing outside of a trace (e.g. in commands and tests).
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::request::logger::RequestId;
+use crate::tracecontext::{TraceParent, is_span_id, is_trace_id};
+
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+const TIMEOUT: u64 = 10; // seconds
+
+const USER_AGENT: &str = "eloquentlog-console-api";
+
+// a trace doesn't grow without limits (e.g. a batch of many queries)
+const SPANS_MAX: usize = 1024;
+
+/// SpanKind (the values are the ones of OTLP)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpanKind {
+    Server = 2,
+    Client = 3,
+    Consumer = 5,
+}
+
+/// SpanData
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpanData {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub kind: SpanKind,
+    /// nanoseconds since the unix epoch
+    pub start: u128,
+    /// 0 until it finishes
+    pub end: u128,
+    pub attributes: Vec<(String, String)>,
+    pub error: bool,
+}
+
+// the spans of the trace in the thread (the first one is the root)
+struct Trace {
+    spans: Vec<SpanData>,
+    // the indices of the unfinished ones (the last one is the innermost)
+    stack: Vec<usize>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Trace>> = RefCell::new(None);
+}
+
+fn now() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+// random ids in lowercase hex (all zero is invalid)
+fn new_trace_id() -> String {
+    loop {
+        let id = format!("{:032x}", rand::random::<u128>());
+        if is_trace_id(&id) {
+            return id;
+        }
+    }
+}
+
+fn new_span_id() -> String {
+    loop {
+        let id = format!("{:016x}", rand::random::<u64>());
+        if is_span_id(&id) {
+            return id;
+        }
+    }
+}
+
+/// Starts a trace in the thread with the root span. The trace id and the
+/// parent of the root are taken over from the caller, if given.
+pub fn start(name: &str, kind: SpanKind, parent: Option<&TraceParent>) {
+    let (trace_id, parent_span_id) = match parent {
+        Some(p) => (p.trace_id.to_string(), Some(p.span_id.to_string())),
+        None => (new_trace_id(), None),
+    };
+    let root = SpanData {
+        trace_id,
+        span_id: new_span_id(),
+        parent_span_id,
+        name: name.to_string(),
+        kind,
+        start: now(),
+        end: 0,
+        attributes: vec![],
+        error: false,
+    };
+    CURRENT.with(|c| {
+        *c.borrow_mut() = Some(Trace {
+            spans: vec![root],
+            stack: vec![0],
+        })
+    });
+}
+
+/// Finishes the trace in the thread, and returns its spans. The name of the
+/// root span is replaced if given (e.g. by the matched route), and the
+/// attributes are added to it.
+pub fn finish(
+    name: Option<String>,
+    attributes: Vec<(String, String)>,
+    error: bool,
+) -> Option<Vec<SpanData>> {
+    let Trace { mut spans, stack } =
+        CURRENT.with(|c| c.borrow_mut().take())?;
+    // including the ones left unfinished (e.g. by a panic)
+    let end = now();
+    for i in stack {
+        spans[i].end = end;
+    }
+
+    let root = &mut spans[0];
+    if let Some(name) = name {
+        root.name = name;
+    }
+    root.attributes.extend(attributes);
+    root.error |= error;
+    Some(spans)
+}
+
+/// Span records a hop in the trace until it's dropped.
+pub struct Span {
+    // the index in the trace (None outside of a trace)
+    index: Option<usize>,
+}
+
+impl Span {
+    fn update<F>(&self, f: F)
+    where F: FnOnce(&mut SpanData) {
+        let i = match self.index {
+            Some(i) => i,
+            None => return,
+        };
+        let _ = CURRENT.try_with(|c| {
+            if let Some(trace) = c.borrow_mut().as_mut() {
+                if let Some(data) = trace.spans.get_mut(i) {
+                    f(data);
+                }
+            }
+        });
+    }
+
+    pub fn set(&self, key: &str, value: &str) {
+        self.update(|d| {
+            d.attributes.push((key.to_string(), value.to_string()))
+        });
+    }
+
+    /// Marks the span as failed.
+    pub fn fail(&self) {
+        self.update(|d| d.error = true);
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let i = match self.index {
+            Some(i) => i,
+            None => return,
+        };
+        let _ = CURRENT.try_with(|c| {
+            if let Some(trace) = c.borrow_mut().as_mut() {
+                trace.stack.retain(|j| *j != i);
+                if let Some(data) = trace.spans.get_mut(i) {
+                    data.end = now();
+                }
+            }
+        });
+    }
+}
+
+/// Starts a child span of the innermost one in the trace of the thread.
+pub fn span(name: &str, kind: SpanKind) -> Span {
+    let index = CURRENT.with(|c| {
+        let mut c = c.borrow_mut();
+        let trace = c.as_mut()?;
+        if trace.spans.len() >= SPANS_MAX {
+            return None;
+        }
+        let parent = &trace.spans[*trace.stack.last()?];
+        let data = SpanData {
+            trace_id: parent.trace_id.to_string(),
+            span_id: new_span_id(),
+            parent_span_id: Some(parent.span_id.to_string()),
+            name: name.to_string(),
+            kind,
+            start: now(),
+            end: 0,
+            attributes: vec![],
+            error: false,
+        };
+        trace.spans.push(data);
+        let i = trace.spans.len() - 1;
+        trace.stack.push(i);
+        Some(i)
+    });
+    Span { index }
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    serde_json::json!({
+        "key": key,
+        "value": {"stringValue": value},
+    })
+}
+
+fn span_value(s: &SpanData) -> Value {
+    let attributes: Vec<Value> =
+        s.attributes.iter().map(|(k, v)| attribute(k, v)).collect();
+    // 0 is unset, 2 is error
+    let code = if s.error { 2 } else { 0 };
+    let mut value = serde_json::json!({
+        "traceId": s.trace_id,
+        "spanId": s.span_id,
+        "name": s.name,
+        "kind": s.kind as u8,
+        "startTimeUnixNano": s.start.to_string(),
+        "endTimeUnixNano": s.end.to_string(),
+        "attributes": attributes,
+        "status": {"code": code},
+    });
+    if let Some(ref id) = s.parent_span_id {
+        value["parentSpanId"] = Value::from(id.as_str());
+    }
+    value
+}
+
+/// Returns the request body of OTLP/HTTP (JSON) for the spans.
+pub fn payload(service_name: &str, spans: &[SpanData]) -> Value {
+    let spans: Vec<Value> = spans.iter().map(span_value).collect();
+    serde_json::json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", service_name)],
+            },
+            "scopeSpans": [{
+                "scope": {"name": USER_AGENT},
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// Exporter posts the spans of finished traces in the background.
+pub struct Exporter {
+    tx: Mutex<Sender<Vec<SpanData>>>,
+}
+
+impl Exporter {
+    /// Returns None if `OTLP_ENDPOINT` is empty.
+    pub fn from_config(config: &Config, logger: &Logger) -> Option<Self> {
+        if config.otlp_endpoint.is_empty() {
+            return None;
+        }
+        let url = format!(
+            "{}/v1/traces",
+            config.otlp_endpoint.trim_end_matches('/')
+        );
+        let service_name = config.otlp_service_name.to_string();
+        let logger = logger.clone();
+
+        let (tx, rx) = mpsc::channel::<Vec<SpanData>>();
+        thread::spawn(move || {
+            for spans in rx {
+                if let Err(e) = ureq::post(&url)
+                    .set("User-Agent", USER_AGENT)
+                    .timeout(Duration::from_secs(TIMEOUT))
+                    .send_json(payload(&service_name, &spans))
+                {
+                    error!(logger, "err: {}", e);
+                }
+            }
+        });
+        Some(Self { tx: Mutex::new(tx) })
+    }
+
+    pub fn export(&self, spans: Vec<SpanData>) {
+        let _ = self.tx.lock().unwrap().send(spans);
+    }
+}
+
+/// Tracing traces the requests (see above). It does nothing without an
+/// exporter.
+pub struct Tracing {
+    exporter: Option<Exporter>,
+}
+
+impl Tracing {
+    pub fn new(exporter: Option<Exporter>) -> Self {
+        Self { exporter }
+    }
+}
+
+impl Fairing for Tracing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Tracing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, req: &mut Request, _: &Data) {
+        if self.exporter.is_none() {
+            return;
+        }
+        let parent = req
+            .headers()
+            .get_one(TRACEPARENT_HEADER)
+            .and_then(TraceParent::parse);
+        match parent {
+            Some(ref p) if !p.is_sampled() => {
+                // drops the one left in the thread, if any
+                let _ = finish(None, vec![], false);
+            },
+            _ => {
+                let method = req.method().as_str();
+                start(method, SpanKind::Server, parent.as_ref())
+            },
+        }
+    }
+
+    fn on_response(&self, req: &Request, res: &mut Response) {
+        let exporter = match self.exporter {
+            Some(ref e) => e,
+            None => return,
+        };
+        let method = req.method().as_str();
+        let route = req.route().map(|r| r.uri.path().to_string());
+        let name = route.as_ref().map(|r| format!("{} {}", method, r));
+        let status = res.status().code;
+
+        let mut attributes = vec![
+            ("http.method".to_string(), method.to_string()),
+            ("http.status_code".to_string(), status.to_string()),
+        ];
+        if let Some(route) = route {
+            attributes.push(("http.route".to_string(), route));
+        }
+        // set by RequestLogger guard (if it has been run)
+        if let Some(ref id) = *req.local_cache(|| None::<RequestId>) {
+            attributes.push(("request_id".to_string(), id.0.to_string()));
+        }
+        if let Some(spans) = finish(name, attributes, status >= 500) {
+            exporter.export(spans);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+
+    #[test]
+    fn test_span_without_trace() {
+        let _ = finish(None, vec![], false);
+
+        let s = span("db.query", SpanKind::Client);
+        s.set("db.system", "postgresql");
+        s.fail();
+        drop(s);
+        assert_eq!(finish(None, vec![], false), None);
+    }
+
+    #[test]
+    fn test_span() {
+        let parent = TraceParent {
+            trace_id: TRACE_ID.to_string(),
+            span_id: SPAN_ID.to_string(),
+            flags: 1,
+        };
+        start("GET", SpanKind::Server, Some(&parent));
+        {
+            let outer = span("db.transaction", SpanKind::Client);
+            let inner = span("db.query", SpanKind::Client);
+            inner.set("db.system", "postgresql");
+            inner.fail();
+            drop(inner);
+            drop(outer);
+        }
+        let _mail = span("mailer.send", SpanKind::Client);
+
+        let attributes = vec![("http.status_code".to_string(), "200".into())];
+        let name = Some("GET /_/health".to_string());
+        let spans = finish(name, attributes, false).unwrap();
+        assert_eq!(spans.len(), 4);
+
+        let root = &spans[0];
+        assert_eq!(root.name, "GET /_/health");
+        assert_eq!(root.trace_id, TRACE_ID);
+        assert_eq!(root.parent_span_id, Some(SPAN_ID.to_string()));
+        assert!(is_span_id(&root.span_id));
+        assert_eq!(root.attributes.len(), 1);
+
+        assert!(spans.iter().all(|s| s.trace_id == TRACE_ID));
+        assert!(spans.iter().all(|s| s.end >= s.start && s.end > 0));
+        assert_eq!(spans[1].parent_span_id, Some(root.span_id.to_string()));
+        assert_eq!(spans[2].parent_span_id, Some(spans[1].span_id.clone()));
+        assert_eq!(spans[3].parent_span_id, Some(root.span_id.to_string()));
+        assert!(spans[2].error);
+        assert!(!spans[1].error);
+
+        // the trace has been finished
+        assert_eq!(finish(None, vec![], false), None);
+    }
+
+    #[test]
+    fn test_start_without_parent() {
+        start("job", SpanKind::Consumer, None);
+        let spans = finish(None, vec![], true).unwrap();
+        assert_eq!(spans.len(), 1);
+        assert!(is_trace_id(&spans[0].trace_id));
+        assert_eq!(spans[0].parent_span_id, None);
+        assert!(spans[0].error);
+    }
+
+    #[test]
+    fn test_payload() {
+        let spans = vec![SpanData {
+            trace_id: TRACE_ID.to_string(),
+            span_id: SPAN_ID.to_string(),
+            parent_span_id: Some("b7ad6b7169203331".to_string()),
+            name: "db.query".to_string(),
+            kind: SpanKind::Client,
+            start: 1_625_097_600_000_000_000,
+            end: 1_625_097_600_001_000_000,
+            attributes: vec![("db.system".to_string(), "postgresql".into())],
+            error: true,
+        }];
+        let value = payload("console-api", &spans);
+        let resource = &value["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0]["value"]["stringValue"],
+            "console-api"
+        );
+
+        let s = &resource["scopeSpans"][0]["spans"][0];
+        assert_eq!(s["traceId"], TRACE_ID);
+        assert_eq!(s["parentSpanId"], "b7ad6b7169203331");
+        assert_eq!(s["kind"], 3);
+        assert_eq!(s["startTimeUnixNano"], "1625097600000000000");
+        assert_eq!(s["attributes"][0]["key"], "db.system");
+        assert_eq!(s["status"]["code"], 2);
+    }
+}
@@ -0,0 +1,305 @@
+//! Ephemeral servers and isolated state for black-box tests.
+//!
+//! `spawn_app` launches the whole app (the same one as `serve`) on a random
+//! port of the loopback interface, so that it can be called over HTTP (e.g.
+//! with ureq) by tests and local smoke tests.
+//!
+//! Each app (and each route test by `run_test`) gets its own database
+//! (`TestDatabase`, migrated on creation) and its own Redis db index for the
+//! message queue and the session store (`RedisDatabase`), so that they don't
+//! share any state and can run in parallel. Both are removed (flushed) on
+//! drop.
+//!
+//! Rocket 0.4 can't be shut down, so the server keeps running until the end
+//! of the process.
+//!
+//! It's only built for tests (the e2e tests require the `testing` feature,
+//! e.g. `cargo test --features testing --test e2e`). See `factory` for test
+//! data.
+use std::net::TcpListener;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use diesel::connection::SimpleConnection;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use dotenv::dotenv;
+use lazy_static::lazy_static;
+use parking_lot::{Condvar, Mutex};
+use url::Url;
+use uuid::Uuid;
+
+use crate::cli::{migrate, serve};
+use crate::config::Config;
+
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+const READY_INTERVAL: Duration = Duration::from_millis(50);
+
+// 0 is left for development
+const REDIS_DATABASES: usize = 15;
+
+lazy_static! {
+    static ref FREE_REDIS_DATABASES: Mutex<Vec<usize>> =
+        Mutex::new(redis_databases(process::id() as usize));
+    static ref REDIS_DATABASE_RELEASED: Condvar = Condvar::new();
+}
+
+/// TestDatabase is a database (`test_<hex>`) migrated on creation. It's
+/// dropped on drop.
+///
+/// It's not a schema in the database of the url, as diesel looks up the
+/// custom types (e.g. `e_user_state`) by their names in all schemas.
+pub struct TestDatabase {
+    /// The url of the database
+    pub url: String,
+    database_url: String,
+    name: String,
+}
+
+impl TestDatabase {
+    /// Creates the database on the server of the url, and migrates it.
+    pub fn create(database_url: &str) -> Self {
+        let name = format!("test_{}", Uuid::new_v4().to_simple());
+
+        let conn = PgConnection::establish(database_url)
+            .expect("failed to connect to the database");
+        conn.batch_execute(&format!("CREATE DATABASE {}", name))
+            .expect("failed to create the database");
+
+        let url = with_database(database_url, &name)
+            .expect("invalid database url");
+        let conn = PgConnection::establish(&url)
+            .expect("failed to connect to the test database");
+        migrate::run(&conn).expect("failed to migrate the test database");
+
+        Self {
+            url,
+            database_url: database_url.to_string(),
+            name,
+        }
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        // the spawned app can't be shut down, its connections are closed here
+        // (statements in a batch run in a transaction, but DROP DATABASE
+        // can't)
+        let sqls = [
+            format!(
+                "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+                 WHERE datname = '{}' AND pid <> pg_backend_pid()",
+                self.name
+            ),
+            format!("DROP DATABASE IF EXISTS {}", self.name),
+        ];
+        if let Err(e) = PgConnection::establish(&self.database_url)
+            .map_err(|e| e.to_string())
+            .and_then(|c| {
+                sqls.iter().try_for_each(|sql| {
+                    c.batch_execute(sql).map_err(|e| e.to_string())
+                })
+            })
+        {
+            eprintln!("err: {}", e);
+        }
+    }
+}
+
+/// RedisDatabase is a Redis db index (1..=15) leased exclusively in the
+/// process. It's flushed when it's leased and on drop (released).
+pub struct RedisDatabase {
+    pub index: usize,
+    urls: Vec<String>,
+}
+
+impl RedisDatabase {
+    /// Waits for a free db index, and leases it for the Redis servers of the
+    /// urls.
+    pub fn lease(urls: &[&str]) -> Self {
+        let db = Self {
+            index: acquire_redis_database(),
+            urls: urls.iter().map(|u| u.to_string()).collect(),
+        };
+        db.flush();
+        db
+    }
+
+    /// Returns the url with the leased db index.
+    pub fn url(&self, url: &str) -> String {
+        with_database(url, &self.index.to_string()).expect("invalid redis url")
+    }
+
+    fn flush(&self) {
+        for url in &self.urls {
+            if let Err(e) = flush(&self.url(url)) {
+                eprintln!("err: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for RedisDatabase {
+    fn drop(&mut self) {
+        self.flush();
+        release_redis_database(self.index);
+    }
+}
+
+/// TestApp is a handle of the spawned app.
+pub struct TestApp {
+    /// e.g. `http://127.0.0.1:49152`
+    pub base_url: String,
+    /// The config used by the app (with its own database and Redis urls)
+    pub config: Config,
+    // removed on drop
+    _database: TestDatabase,
+    _redis: RedisDatabase,
+}
+
+impl TestApp {
+    /// Returns the url of the path (e.g. `/_/health`).
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Drops the database and flushes the Redis db of the app (the same as
+    /// drop).
+    pub fn teardown(self) {}
+}
+
+/// Spawns the app with the testing config (`TEST_` variables).
+pub fn spawn_app() -> TestApp {
+    dotenv().ok();
+    let config = Config::from("testing").expect("failed to get config");
+    spawn_app_with(config)
+}
+
+/// Spawns the app with the config. The server address, the port, the
+/// database and the Redis urls are replaced.
+pub fn spawn_app_with(mut config: Config) -> TestApp {
+    let database = TestDatabase::create(&config.database_connection_url());
+    config.database_url = database.url.clone();
+    config.database_replica_url = database.url.clone();
+
+    let redis = RedisDatabase::lease(&[
+        &config.message_queue_connection_url(),
+        &config.session_store_connection_url(),
+    ]);
+    config.message_queue_url = redis.url(&config.message_queue_url);
+    config.session_store_url = redis.url(&config.session_store_url);
+
+    config.server_address = "127.0.0.1".to_string();
+    config.server_port = free_port();
+
+    let base_url = format!(
+        "http://{}:{}",
+        config.server_address, config.server_port
+    );
+    let app = TestApp {
+        base_url,
+        config: config.clone(),
+        _database: database,
+        _redis: redis,
+    };
+
+    thread::spawn(move || {
+        serve::app(config).launch();
+    });
+    wait(&app.url("/_/health"));
+    app
+}
+
+// replaces the database (the path) of the url, e.g. a db index of Redis
+fn with_database(url: &str, database: &str) -> Result<String, String> {
+    let mut u = Url::parse(url).map_err(|e| e.to_string())?;
+    u.set_path(&format!("/{}", database));
+    Ok(u.to_string())
+}
+
+// 1..=15, the one of the offset first (so that processes are likely to use
+// different ones)
+fn redis_databases(offset: usize) -> Vec<usize> {
+    (0..REDIS_DATABASES)
+        .map(|i| (offset + i) % REDIS_DATABASES + 1)
+        .rev()
+        .collect()
+}
+
+fn acquire_redis_database() -> usize {
+    let mut free = FREE_REDIS_DATABASES.lock();
+    loop {
+        if let Some(index) = free.pop() {
+            return index;
+        }
+        REDIS_DATABASE_RELEASED.wait(&mut free);
+    }
+}
+
+fn release_redis_database(index: usize) {
+    FREE_REDIS_DATABASES.lock().push(index);
+    REDIS_DATABASE_RELEASED.notify_one();
+}
+
+fn flush(url: &str) -> redis::RedisResult<()> {
+    let client = redis::Client::open(url)?;
+    let mut conn = client.get_connection()?;
+    redis::cmd("FLUSHDB").query(&mut conn)
+}
+
+// the port is released before the launch (it may be taken in between, but
+// it's rare)
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .map(|a| a.port())
+        .expect("failed to find a free port")
+}
+
+fn wait(url: &str) {
+    let started_at = Instant::now();
+    loop {
+        match ureq::get(url).timeout(READY_INTERVAL).call() {
+            Ok(_) | Err(ureq::Error::Status(_, _)) => return,
+            Err(_) if started_at.elapsed() < READY_TIMEOUT => {
+                thread::sleep(READY_INTERVAL);
+            },
+            Err(e) => panic!("app is not ready: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_with_database() {
+        assert_eq!(
+            with_database("redis://127.0.0.1:6379/0", "3").unwrap(),
+            "redis://127.0.0.1:6379/3"
+        );
+        assert_eq!(
+            with_database("redis://127.0.0.1:6379", "3").unwrap(),
+            "redis://127.0.0.1:6379/3"
+        );
+        assert_eq!(
+            with_database(
+                "postgresql://u:p@localhost/db?sslmode=disable",
+                "test_a"
+            )
+            .unwrap(),
+            "postgresql://u:p@localhost/test_a?sslmode=disable"
+        );
+    }
+
+    #[test]
+    fn test_redis_databases() {
+        let mut databases = redis_databases(3);
+        assert_eq!(databases.last(), Some(&4));
+
+        databases.sort_unstable();
+        assert_eq!(databases, (1..=REDIS_DATABASES).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,131 @@
+//! W3C Trace Context
+//!
+//! A message may be sent with the `traceparent` of the span where it's logged
+//! (e.g. `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`), which is
+//! `<version>-<trace-id>-<parent-id>-<trace-flags>` in lowercase hex. The
+//! trace id and the parent id (as the span id) are saved with the message, so
+//! that the logs of a distributed trace can be pulled together.
+//!
+//! See https://www.w3.org/TR/trace-context/#traceparent-header.
+
+pub const TRACE_ID_LENGTH: usize = 32;
+pub const SPAN_ID_LENGTH: usize = 16;
+
+/// TraceParent
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceParent {
+    pub trace_id: String,
+    pub span_id: String,
+    pub flags: u8,
+}
+
+fn is_hex(s: &str, length: usize) -> bool {
+    s.len() == length &&
+        s.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+fn is_zero(s: &str) -> bool {
+    s.chars().all(|c| c == '0')
+}
+
+/// Returns true if the value is a valid trace id (all zero is invalid).
+pub fn is_trace_id(s: &str) -> bool {
+    is_hex(s, TRACE_ID_LENGTH) && !is_zero(s)
+}
+
+/// Returns true if the value is a valid span (parent) id.
+pub fn is_span_id(s: &str) -> bool {
+    is_hex(s, SPAN_ID_LENGTH) && !is_zero(s)
+}
+
+impl TraceParent {
+    /// Parses a value of `traceparent`. Fields after the flags are allowed
+    /// only in the versions newer than `00`, as the spec says.
+    pub fn parse(s: &str) -> Option<Self> {
+        let fields: Vec<&str> = s.trim().split('-').collect();
+        if fields.len() < 4 {
+            return None;
+        }
+        let (version, trace_id, span_id, flags) =
+            (fields[0], fields[1], fields[2], fields[3]);
+        if !is_hex(version, 2) || version == "ff" {
+            return None;
+        }
+        if version == "00" && fields.len() != 4 {
+            return None;
+        }
+        if !is_trace_id(trace_id) ||
+            !is_span_id(span_id) ||
+            !is_hex(flags, 2)
+        {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            flags: u8::from_str_radix(flags, 16).ok()?,
+        })
+    }
+
+    /// Returns true if the caller has recorded the trace.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & 0x01 == 0x01
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+
+    #[test]
+    fn test_parse() {
+        let v = format!("00-{}-{}-01", TRACE_ID, SPAN_ID);
+        let t = TraceParent::parse(&v).unwrap();
+        assert_eq!(t.trace_id, TRACE_ID);
+        assert_eq!(t.span_id, SPAN_ID);
+        assert_eq!(t.flags, 1);
+        assert!(t.is_sampled());
+
+        let v = format!(" 00-{}-{}-00 ", TRACE_ID, SPAN_ID);
+        let t = TraceParent::parse(&v);
+        assert_eq!(t.map(|t| t.is_sampled()), Some(false));
+
+        // a future version may have more fields
+        let v = format!("01-{}-{}-01-what-the-future", TRACE_ID, SPAN_ID);
+        assert!(TraceParent::parse(&v).is_some());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let zero_trace_id = "0".repeat(TRACE_ID_LENGTH);
+        let zero_span_id = "0".repeat(SPAN_ID_LENGTH);
+        let values = [
+            "".to_string(),
+            "00".to_string(),
+            format!("00-{}-{}", TRACE_ID, SPAN_ID),
+            format!("00-{}-{}-01-extra", TRACE_ID, SPAN_ID),
+            format!("ff-{}-{}-01", TRACE_ID, SPAN_ID),
+            format!("0-{}-{}-01", TRACE_ID, SPAN_ID),
+            format!("00-{}-{}-01", TRACE_ID.to_uppercase(), SPAN_ID),
+            format!("00-{}-{}-01", &TRACE_ID[1..], SPAN_ID),
+            format!("00-{}-{}-1", TRACE_ID, SPAN_ID),
+            format!("00-{}-{}-0g", TRACE_ID, SPAN_ID),
+            format!("00-{}-{}-01", zero_trace_id, SPAN_ID),
+            format!("00-{}-{}-01", TRACE_ID, zero_span_id),
+        ];
+        for v in values.iter() {
+            assert_eq!(TraceParent::parse(v), None, "{}", v);
+        }
+    }
+
+    #[test]
+    fn test_is_trace_id() {
+        assert!(is_trace_id(TRACE_ID));
+        assert!(!is_trace_id(SPAN_ID));
+        assert!(!is_trace_id(&"0".repeat(TRACE_ID_LENGTH)));
+        assert!(!is_trace_id(&TRACE_ID.to_uppercase()));
+    }
+}
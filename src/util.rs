@@ -1,24 +1,22 @@
-use rand::prelude::*;
 use rocket::http::{Cookie, SameSite};
 use rocket::Request;
+use sha2::{Digest, Sha256};
 
 use crate::config::Config;
 
-// Creates random hash based on source characters
-pub fn generate_random_hash(source: &[u8], length: i32) -> String {
-    if length < 1 {
-        return "".to_string();
-    }
-
-    let mut rng = thread_rng();
-    let source_length = source.len();
+/// Returns the SHA-256 digest of the token in hex. Only this digest of a
+/// concrete token is stored, so that a leaked row can't be used to identify.
+pub fn hash_token(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
-    (0..length)
-        .map(|_| {
-            let idx = rng.gen_range(0..source_length);
-            char::from(unsafe { *source.get_unchecked(idx) })
-        })
-        .collect()
+/// Compares the values in constant time (in the length of them).
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
 pub fn split_token(token: String) -> Option<(String, String)> {
@@ -90,26 +88,24 @@ mod test {
     use rocket::local::Client;
 
     #[test]
-    fn test_generate_random_hash_length() {
-        let s = b".";
-        assert_eq!(generate_random_hash(s, -1), "".to_string());
-        assert_eq!(generate_random_hash(s, 0), "".to_string());
-
-        assert_eq!(generate_random_hash(s, 1), ".".to_string());
-        assert_eq!(generate_random_hash(s, 3), "...".to_string());
-        assert_eq!(generate_random_hash(s, 6), "......".to_string());
-        assert_eq!(generate_random_hash(s, 9), ".........".to_string());
+    fn test_hash_token() {
+        let h = hash_token("token");
+        assert_eq!(
+            h,
+            "3c469e9d6c5875d37a43f353d4f88e61fcf812c66eee3457465a40b0da4153e0"
+        );
+        assert_eq!(hash_token("token"), h);
+        assert_ne!(hash_token("tokem"), h);
     }
 
     #[test]
-    fn test_generate_random_hash_source() {
-        let s = b"abcdefghijklmnopqrstuvwxyz";
-        let t = String::from_utf8_lossy(s);
-        let value = generate_random_hash(s, 128);
-
-        for v in value.chars() {
-            assert!(t.contains(v));
-        }
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"token", b"token"));
+
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"tokens"));
+        assert!(!constant_time_eq(b"token", b""));
     }
 
     #[test]
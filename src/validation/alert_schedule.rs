@@ -0,0 +1,145 @@
+use std::result::Result;
+
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+use rocket_contrib::json::Json;
+
+use crate::logger::Logger;
+use crate::request::alert_schedule::AlertSchedule as RequestData;
+use crate::validation::*;
+
+pub const TIME_FORMAT: &str = "%H:%M";
+
+pub struct Validator<'a> {
+    data: &'a Json<RequestData>,
+    logger: &'a Logger,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(data: &'a Json<RequestData>, logger: &'a Logger) -> Self {
+        Self { data, logger }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors: Vec<ValidationError> = vec![];
+
+        if let Some(ref tz) = self.data.0.timezone {
+            if tz.parse::<Tz>().is_err() {
+                errors.push(ValidationError {
+                    field: "timezone".to_string(),
+                    messages: vec!["Must be a valid timezone".to_string()],
+                });
+            }
+        }
+
+        let start = self.data.0.quiet_hours_start.as_deref();
+        let end = self.data.0.quiet_hours_end.as_deref();
+        for (field, value, other) in &[
+            ("quiet_hours_start", start, end),
+            ("quiet_hours_end", end, start),
+        ] {
+            let message = match (value, other) {
+                (None, None) => None,
+                (None, Some(_)) => Some("Must exist".to_string()),
+                (Some(v), _) => match NaiveTime::parse_from_str(v, TIME_FORMAT)
+                {
+                    Ok(_) => None,
+                    Err(_) => Some("Must be a time as HH:MM".to_string()),
+                },
+            };
+            if let Some(m) = message {
+                errors.push(ValidationError {
+                    field: field.to_string(),
+                    messages: vec![m],
+                });
+            }
+        }
+        self.result(errors)
+    }
+
+    fn result(
+        &self,
+        errors: Vec<ValidationError>,
+    ) -> Result<(), Vec<ValidationError>> {
+        if !errors.is_empty() {
+            for e in &errors {
+                info!(
+                    self.logger,
+                    "validation error: {} {}",
+                    e.field,
+                    e.messages.join(",")
+                );
+            }
+            return Err(errors);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rocket_contrib::json::Json;
+
+    use crate::model::test::run;
+
+    #[test]
+    fn test_validate_timezone_is_unknown() {
+        run(|_, _, logger| {
+            let data = &Json(RequestData {
+                timezone: Some("Mars/Olympus".to_string()),
+
+                ..Default::default()
+            });
+            let v = Validator::new(data, logger);
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("timezone", errors[0].field);
+                assert_eq!(
+                    vec!["Must be a valid timezone"],
+                    errors[0].messages
+                );
+            } else {
+                panic!("must fail");
+            }
+        });
+    }
+
+    #[test]
+    fn test_validate_quiet_hours() {
+        run(|_, _, logger| {
+            let data = &Json(RequestData {
+                timezone: Some("Europe/Zurich".to_string()),
+                quiet_hours_start: Some("25:00".to_string()),
+                quiet_hours_end: None,
+            });
+            let v = Validator::new(data, logger);
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(2, errors.len());
+                assert_eq!("quiet_hours_start", errors[0].field);
+                assert_eq!(vec!["Must be a time as HH:MM"], errors[0].messages);
+                assert_eq!("quiet_hours_end", errors[1].field);
+                assert_eq!(vec!["Must exist"], errors[1].messages);
+            } else {
+                panic!("must fail");
+            }
+
+            let data = &Json(RequestData {
+                timezone: Some("Europe/Zurich".to_string()),
+                quiet_hours_start: Some("22:00".to_string()),
+                quiet_hours_end: Some("07:00".to_string()),
+            });
+            let v = Validator::new(data, logger);
+            assert!(v.validate().is_ok());
+        });
+    }
+}
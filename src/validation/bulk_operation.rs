@@ -0,0 +1,221 @@
+use std::result::Result;
+
+use rocket_contrib::json::Json;
+use uuid::Uuid;
+
+use crate::logger::Logger;
+use crate::model::bulk_operation::BulkOperationAction;
+use crate::model::message::MessageFilter;
+use crate::request::bulk_operation::BulkOperation as RequestData;
+use crate::validation::*;
+
+const TAG_MAX_LENGTH: usize = 64;
+
+pub struct Validator<'a> {
+    data: &'a Json<RequestData>,
+    logger: &'a Logger,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(data: &'a Json<RequestData>, logger: &'a Logger) -> Self {
+        Self { data, logger }
+    }
+
+    /// Validates only the filter (for the count).
+    pub fn validate_filter(&self) -> Result<(), Vec<ValidationError>> {
+        self.result(self.filter_errors())
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = self.filter_errors();
+
+        let action = self
+            .data
+            .0
+            .action
+            .as_ref()
+            .and_then(|a| BulkOperationAction::from_name(a));
+        let action = match action {
+            Some(a) => a,
+            None => {
+                let names: Vec<String> = BulkOperationAction::iter()
+                    .map(|a| a.to_string())
+                    .collect();
+                errors.push(ValidationError {
+                    field: "action".to_string(),
+                    messages: vec![format!(
+                        "Must be one of {}",
+                        names.join(", ")
+                    )],
+                });
+                return self.result(errors);
+            },
+        };
+
+        let argument = self.data.0.argument.as_ref().map(|s| s.trim());
+        let message = match (action, argument) {
+            (BulkOperationAction::SoftDelete, _) => None,
+            (_, None) | (_, Some("")) => Some("Must exist".to_string()),
+            (BulkOperationAction::AddTag, Some(tag))
+                if tag.chars().count() > TAG_MAX_LENGTH =>
+            {
+                Some(format!(
+                    "Must contain less than {} characters",
+                    TAG_MAX_LENGTH
+                ))
+            },
+            (BulkOperationAction::AssignIncident, Some(uuid))
+                if Uuid::parse_str(uuid).is_err() =>
+            {
+                Some("Must be an incident uuid".to_string())
+            },
+            _ => None,
+        };
+        if let Some(m) = message {
+            errors.push(ValidationError {
+                field: "argument".to_string(),
+                messages: vec![m],
+            });
+        }
+        self.result(errors)
+    }
+
+    fn filter_errors(&self) -> Vec<ValidationError> {
+        match MessageFilter::parse(&self.data.0.filter) {
+            Ok(_) => vec![],
+            Err(e) => vec![ValidationError {
+                field: "filter".to_string(),
+                messages: vec![e.to_string()],
+            }],
+        }
+    }
+
+    fn result(
+        &self,
+        errors: Vec<ValidationError>,
+    ) -> Result<(), Vec<ValidationError>> {
+        if !errors.is_empty() {
+            for e in &errors {
+                info!(
+                    self.logger,
+                    "validation error: {} {}",
+                    e.field,
+                    e.messages.join(",")
+                );
+            }
+            return Err(errors);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rocket_contrib::json::Json;
+
+    use crate::model::test::run;
+
+    #[test]
+    fn test_validate_filter_is_invalid() {
+        run(|_, _, logger| {
+            let data = &Json(RequestData {
+                filter: "agent:1".to_string(),
+                action: Some("soft_delete".to_string()),
+
+                ..Default::default()
+            });
+            let v = Validator::new(data, logger);
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("filter", errors[0].field);
+                assert_eq!(vec!["Unknown field: agent"], errors[0].messages);
+            } else {
+                panic!("must fail");
+            }
+        });
+    }
+
+    #[test]
+    fn test_validate_action_is_unknown() {
+        run(|_, _, logger| {
+            let data = &Json(RequestData {
+                filter: "level:error".to_string(),
+                action: Some("delete".to_string()),
+
+                ..Default::default()
+            });
+            let v = Validator::new(data, logger);
+
+            // the filter is valid
+            assert!(v.validate_filter().is_ok());
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("action", errors[0].field);
+                assert_eq!(
+                    vec![
+                        "Must be one of add_tag, assign_incident, soft_delete"
+                    ],
+                    errors[0].messages
+                );
+            } else {
+                panic!("must fail");
+            }
+        });
+    }
+
+    #[test]
+    fn test_validate_argument() {
+        run(|_, _, logger| {
+            let long = "long".repeat(17);
+            let cases = vec![
+                ("add_tag", None, Some("Must exist")),
+                ("add_tag", Some(" "), Some("Must exist")),
+                (
+                    "add_tag",
+                    Some(long.as_str()),
+                    Some("Must contain less than 64 characters"),
+                ),
+                ("add_tag", Some("db"), None),
+                (
+                    "assign_incident",
+                    Some("12"),
+                    Some("Must be an incident uuid"),
+                ),
+                (
+                    "assign_incident",
+                    Some("a1f2e5b8-0f6e-4d0c-9a43-7c1e2b6e5d10"),
+                    None,
+                ),
+                ("soft_delete", None, None),
+            ];
+            for (action, argument, message) in cases {
+                let data = &Json(RequestData {
+                    filter: "level:error".to_string(),
+                    action: Some(action.to_string()),
+                    argument: argument.map(|s| s.to_string()),
+                });
+                let v = Validator::new(data, logger);
+
+                match (v.validate(), message) {
+                    (Ok(_), None) => (),
+                    (Err(errors), Some(m)) => {
+                        assert_eq!(1, errors.len());
+                        assert_eq!("argument", errors[0].field);
+                        assert_eq!(vec![m], errors[0].messages);
+                    },
+                    (result, _) => panic!("unexpected: {:?}", result.err()),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,230 @@
+use std::result::Result;
+
+use rocket_contrib::json::Json;
+use url::Url;
+
+use crate::logger::Logger;
+use crate::model::channel::ChannelKind;
+use crate::request::channel::Channel as RequestData;
+use crate::service::payload_template::{PayloadContext, PayloadTemplate};
+use crate::validation::*;
+
+const NAME_MAX_LENGTH: usize = 64;
+const URL_MAX_LENGTH: usize = 2048;
+const ROLLUP_WINDOW_MAX: i32 = 86400; // 1 day
+
+pub struct Validator<'a> {
+    data: &'a Json<RequestData>,
+    logger: &'a Logger,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(data: &'a Json<RequestData>, logger: &'a Logger) -> Self {
+        Self { data, logger }
+    }
+
+    /// Validates only the kind and the template (for the preview).
+    pub fn validate_template(&self) -> Result<(), Vec<ValidationError>> {
+        self.result(self.template_errors())
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = self.template_errors();
+
+        let name = self.data.0.name.as_ref().map(|s| s.trim());
+        let message = match name {
+            None | Some("") => Some("Must exist".to_string()),
+            Some(s) if s.chars().count() > NAME_MAX_LENGTH => Some(format!(
+                "Must contain less than {} characters",
+                NAME_MAX_LENGTH
+            )),
+            _ => None,
+        };
+        if let Some(m) = message {
+            errors.push(ValidationError {
+                field: "name".to_string(),
+                messages: vec![m],
+            });
+        }
+
+        let url = self.data.0.url.as_ref().map(|s| s.trim());
+        let message = match url {
+            None | Some("") => Some("Must exist".to_string()),
+            Some(s) if s.len() > URL_MAX_LENGTH => Some(format!(
+                "Must contain less than {} characters",
+                URL_MAX_LENGTH
+            )),
+            Some(s) => match Url::parse(s) {
+                Ok(u) if u.scheme() == "https" || u.scheme() == "http" => None,
+                _ => Some("Must be a http(s) URL".to_string()),
+            },
+        };
+        if let Some(m) = message {
+            errors.push(ValidationError {
+                field: "url".to_string(),
+                messages: vec![m],
+            });
+        }
+
+        let window = self.data.0.rollup_window.unwrap_or(0);
+        if !(0..=ROLLUP_WINDOW_MAX).contains(&window) {
+            errors.push(ValidationError {
+                field: "rollup_window".to_string(),
+                messages: vec![format!(
+                    "Must be between 0 and {}",
+                    ROLLUP_WINDOW_MAX
+                )],
+            });
+        }
+        self.result(errors)
+    }
+
+    // renders the template with a sample so that the errors of variables are
+    // also detected at save time
+    fn template_errors(&self) -> Vec<ValidationError> {
+        let kind = self
+            .data
+            .0
+            .kind
+            .as_ref()
+            .and_then(|k| ChannelKind::from_name(k));
+        let kind = match kind {
+            Some(k) => k,
+            None => {
+                let names: Vec<String> =
+                    ChannelKind::iter().map(|k| k.to_string()).collect();
+                return vec![ValidationError {
+                    field: "kind".to_string(),
+                    messages: vec![format!(
+                        "Must be one of {}",
+                        names.join(", ")
+                    )],
+                }];
+            },
+        };
+        let template = self.data.0.template.as_deref();
+        let result = PayloadTemplate::new(kind, template)
+            .and_then(|t| t.render(&PayloadContext::sample()));
+        match result {
+            Ok(_) => vec![],
+            Err(e) => vec![ValidationError {
+                field: "template".to_string(),
+                messages: vec![e],
+            }],
+        }
+    }
+
+    fn result(
+        &self,
+        errors: Vec<ValidationError>,
+    ) -> Result<(), Vec<ValidationError>> {
+        if !errors.is_empty() {
+            for e in &errors {
+                info!(
+                    self.logger,
+                    "validation error: {} {}",
+                    e.field,
+                    e.messages.join(",")
+                );
+            }
+            return Err(errors);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rocket_contrib::json::Json;
+
+    use crate::model::test::run;
+
+    #[test]
+    fn test_validate_kind_is_unknown() {
+        run(|_, _, logger| {
+            let data = &Json(RequestData {
+                kind: Some("email".to_string()),
+                name: Some("alerts".to_string()),
+                url: Some("https://example.org/hook".to_string()),
+
+                ..Default::default()
+            });
+            let v = Validator::new(data, logger);
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("kind", errors[0].field);
+                assert_eq!(
+                    vec!["Must be one of webhook, slack"],
+                    errors[0].messages
+                );
+            } else {
+                panic!("must fail");
+            }
+        });
+    }
+
+    #[test]
+    fn test_validate_template_with_unknown_variable() {
+        run(|_, _, logger| {
+            let data = &Json(RequestData {
+                kind: Some("slack".to_string()),
+                template: Some(r#"{"text": "{{user.email}}"}"#.to_string()),
+
+                ..Default::default()
+            });
+            let v = Validator::new(data, logger);
+
+            let result = v.validate_template();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("template", errors[0].field);
+                assert!(errors[0].messages[0].starts_with("Invalid template"));
+            } else {
+                panic!("must fail");
+            }
+        });
+    }
+
+    #[test]
+    fn test_validate_name_url_and_rollup_window() {
+        run(|_, _, logger| {
+            let data = &Json(RequestData {
+                kind: Some("webhook".to_string()),
+                name: Some(" ".to_string()),
+                url: Some("ftp://example.org".to_string()),
+                template: None,
+                rollup_window: Some(-1),
+            });
+            let v = Validator::new(data, logger);
+
+            // the (default) template is valid
+            assert!(v.validate_template().is_ok());
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(3, errors.len());
+                assert_eq!("name", errors[0].field);
+                assert_eq!(vec!["Must exist"], errors[0].messages);
+                assert_eq!("url", errors[1].field);
+                assert_eq!(vec!["Must be a http(s) URL"], errors[1].messages);
+                assert_eq!("rollup_window", errors[2].field);
+                assert_eq!(
+                    vec!["Must be between 0 and 86400"],
+                    errors[2].messages
+                );
+            } else {
+                panic!("must fail");
+            }
+        });
+    }
+}
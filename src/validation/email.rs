@@ -0,0 +1,223 @@
+//! Normalization and validation of email addresses.
+//!
+//! An address is normalized before it's validated and saved (`users.email`
+//! and `user_emails.email`), so that the same mailbox can't be registered
+//! twice in different forms.
+//!
+//! * surrounding spaces are removed
+//! * it's composed into Unicode NFC, and lowercased
+//! * dots in the local part of Gmail addresses are removed (optional)
+//!
+//! The syntax is the dot-atom form of RFC 5322 (with UTF-8 characters of
+//! RFC 6531). Quoted local parts and address literals (`[127.0.0.1]`) are not
+//! accepted. Disposable domains in `EMAIL_DISPOSABLE_DOMAINS` (and their
+//! subdomains) can be rejected.
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::Config;
+
+const GMAIL_DOMAINS: &[&str] = &["gmail.com", "googlemail.com"];
+
+// other than alphanumeric ones in atext
+const ATEXT_SYMBOLS: &str = "!#$%&'*+-/=?^_`{|}~";
+
+const MAX_LENGTH: usize = 254;
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+const MAX_DOMAIN_LENGTH: usize = 253;
+const MAX_LABEL_LENGTH: usize = 63;
+
+/// EmailPolicy
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EmailPolicy {
+    pub disposable_domains: Vec<String>,
+    pub normalize_gmail_dots: bool,
+}
+
+impl From<&Config> for EmailPolicy {
+    fn from(c: &Config) -> Self {
+        Self {
+            disposable_domains: c.email_disposable_domains.clone(),
+            normalize_gmail_dots: c.email_normalize_gmail_dots,
+        }
+    }
+}
+
+fn split(email: &str) -> Option<(&str, &str)> {
+    let i = email.rfind('@')?;
+    Some((&email[..i], &email[i + 1..]))
+}
+
+/// Returns the normalized address. The value is returned as it is (but
+/// normalized) if it doesn't look like an address.
+pub fn normalize(email: &str, policy: &EmailPolicy) -> String {
+    let email = email.trim().nfc().collect::<String>().to_lowercase();
+    let (local, domain) = match split(&email) {
+        Some(v) => v,
+        None => return email,
+    };
+    if policy.normalize_gmail_dots && GMAIL_DOMAINS.contains(&domain) {
+        return format!("{}@{}", local.replace('.', ""), domain);
+    }
+    email
+}
+
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || ATEXT_SYMBOLS.contains(c) || !c.is_ascii()
+}
+
+fn is_valid_label(label: &str) -> bool {
+    !label.is_empty() &&
+        label.len() <= MAX_LABEL_LENGTH &&
+        !label.starts_with('-') &&
+        !label.ends_with('-') &&
+        label.chars().all(|c| c.is_alphanumeric() || c == '-')
+}
+
+/// Returns true if the address is valid in the syntax.
+pub fn is_valid(email: &str) -> bool {
+    let (local, domain) = match split(email) {
+        Some(v) if email.len() <= MAX_LENGTH => v,
+        _ => return false,
+    };
+    if local.is_empty() || local.len() > MAX_LOCAL_PART_LENGTH {
+        return false;
+    }
+    // dot-atom (no leading, trailing or consecutive dots)
+    if !local.split('.').all(|a| !a.is_empty() && a.chars().all(is_atext)) {
+        return false;
+    }
+
+    if domain.is_empty() || domain.len() > MAX_DOMAIN_LENGTH {
+        return false;
+    }
+    let labels: Vec<&str> = domain.split('.').collect();
+    labels.len() > 1 &&
+        labels.iter().all(|l| is_valid_label(l)) &&
+        !labels[labels.len() - 1].chars().all(|c| c.is_ascii_digit())
+}
+
+/// Returns true if the domain of the address is (or is a subdomain of) one
+/// of the disposable domains.
+pub fn is_disposable(email: &str, policy: &EmailPolicy) -> bool {
+    let domain = match split(email) {
+        Some((_, d)) => d.to_lowercase(),
+        None => return false,
+    };
+    policy.disposable_domains.iter().any(|d| {
+        domain == *d ||
+            (domain.ends_with(d.as_str()) &&
+                domain[..domain.len() - d.len()].ends_with('.'))
+    })
+}
+
+#[rustfmt::skip::attributes(rstest)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::model::test::CONFIG;
+
+    #[test]
+    fn test_email_policy_from_config() {
+        let mut config = CONFIG.clone();
+        config.email_disposable_domains = vec!["mailinator.com".to_string()];
+        config.email_normalize_gmail_dots = true;
+
+        let policy = EmailPolicy::from(&config);
+        assert_eq!(policy.disposable_domains, vec!["mailinator.com"]);
+        assert!(policy.normalize_gmail_dots);
+    }
+
+    #[rstest(
+        raw_s, gmail_dots, expected,
+        case("  oswald@example.org ", false, "oswald@example.org"),
+        case("Oswald@Example.ORG", false, "oswald@example.org"),
+        // e + combining acute accent (NFD)
+        case("Re\u{301}my@example.org", false, "r\u{e9}my@example.org"),
+        case("o.s.wald@gmail.com", false, "o.s.wald@gmail.com"),
+        case("O.s.wald@Gmail.com", true, "oswald@gmail.com"),
+        case("o.s.wald@googlemail.com", true, "oswald@googlemail.com"),
+        case("o.s.wald@example.org", true, "o.s.wald@example.org"),
+        case("Not an email", true, "not an email"),
+        ::trace
+    )]
+    #[test]
+    fn test_normalize(
+        raw_s: &'static str,
+        gmail_dots: bool,
+        expected: &'static str,
+    ) {
+        let policy = EmailPolicy {
+            normalize_gmail_dots: gmail_dots,
+
+            ..Default::default()
+        };
+        assert_eq!(normalize(raw_s, &policy), expected);
+    }
+
+    #[rstest(
+        raw_s, expected,
+        case("oswald@example.org", true),
+        case("o.s.wald+log@mail.example.org", true),
+        case("o'hara!#$%&*/=?^_`{|}~-@example.org", true),
+        case("r\u{e9}my@b\u{fc}cher.example", true),
+        case("", false),
+        case("oswald", false),
+        case("@example.org", false),
+        case("oswald@", false),
+        case("oswald@localhost", false),
+        case("oswald@example.123", false),
+        case(".oswald@example.org", false),
+        case("oswald.@example.org", false),
+        case("os..wald@example.org", false),
+        case("os wald@example.org", false),
+        case("os@wald@example.org", false),
+        case("\"oswald\"@example.org", false),
+        case("oswald@[127.0.0.1]", false),
+        case("oswald@-example.org", false),
+        case("oswald@example-.org", false),
+        case("oswald@example..org", false),
+        case("oswald@exa_mple.org", false),
+        ::trace
+    )]
+    #[test]
+    fn test_is_valid(raw_s: &'static str, expected: bool) {
+        assert_eq!(is_valid(raw_s), expected);
+    }
+
+    #[test]
+    fn test_is_valid_length() {
+        let local = "a".repeat(64);
+        assert!(is_valid(&format!("{}@example.org", local)));
+        assert!(!is_valid(&format!("a{}@example.org", local)));
+
+        let label = "a".repeat(63);
+        assert!(is_valid(&format!("oswald@{}.org", label)));
+        assert!(!is_valid(&format!("oswald@a{}.org", label)));
+
+        let domain = format!("{}.{}.{}.org", label, label, label);
+        assert!(is_valid(&format!("oswald@{}", domain)));
+        assert!(!is_valid(&format!("oswald@{}.{}", label, domain)));
+    }
+
+    #[rstest(
+        raw_s, expected,
+        case("oswald@mailinator.com", true),
+        case("oswald@sub.mailinator.com", true),
+        case("oswald@notmailinator.com", false),
+        case("oswald@example.org", false),
+        case("oswald", false),
+        ::trace
+    )]
+    #[test]
+    fn test_is_disposable(raw_s: &'static str, expected: bool) {
+        let policy = EmailPolicy {
+            disposable_domains: vec!["mailinator.com".to_string()],
+
+            ..Default::default()
+        };
+        assert_eq!(is_disposable(raw_s, &policy), expected);
+    }
+}
@@ -0,0 +1,186 @@
+use std::result::Result;
+
+use rocket_contrib::json::Json;
+
+use crate::id::is_ulid;
+use crate::logger::Logger;
+use crate::model::incident::IncidentState;
+use crate::request::incident::Incident as RequestData;
+use crate::validation::*;
+
+const TITLE_MAX_LENGTH: usize = 255;
+const MESSAGE_IDS_MAX_COUNT: usize = 100; // per request
+
+pub struct Validator<'a> {
+    data: &'a Json<RequestData>,
+    logger: &'a Logger,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(data: &'a Json<RequestData>, logger: &'a Logger) -> Self {
+        Self { data, logger }
+    }
+
+    /// Validates the title and the messages (optional) of a new incident.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+
+        let title = self.data.0.title.as_ref().map(|s| s.trim());
+        let message = match title {
+            None | Some("") => Some("Must exist".to_string()),
+            Some(s) if s.chars().count() > TITLE_MAX_LENGTH => Some(format!(
+                "Must contain less than {} characters",
+                TITLE_MAX_LENGTH
+            )),
+            _ => None,
+        };
+        if let Some(m) = message {
+            errors.push(ValidationError {
+                field: "title".to_string(),
+                messages: vec![m],
+            });
+        }
+
+        if self.data.0.message_ids.is_some() {
+            errors.extend(self.message_ids_errors());
+        }
+        self.result(errors)
+    }
+
+    /// Validates only the state (for the change).
+    pub fn validate_state(&self) -> Result<(), Vec<ValidationError>> {
+        let state = self
+            .data
+            .0
+            .state
+            .as_ref()
+            .and_then(|s| IncidentState::from_name(s));
+        if state.is_some() {
+            return Ok(());
+        }
+        let names: Vec<String> =
+            IncidentState::iter().map(|s| s.to_string()).collect();
+        self.result(vec![ValidationError {
+            field: "state".to_string(),
+            messages: vec![format!("Must be one of {}", names.join(", "))],
+        }])
+    }
+
+    /// Validates only the messages (for the attachment).
+    pub fn validate_message_ids(&self) -> Result<(), Vec<ValidationError>> {
+        self.result(self.message_ids_errors())
+    }
+
+    fn message_ids_errors(&self) -> Vec<ValidationError> {
+        let ids = self.data.0.message_ids.as_deref().unwrap_or(&[]);
+        let message = if ids.is_empty() {
+            Some("Must exist".to_string())
+        } else if ids.len() > MESSAGE_IDS_MAX_COUNT {
+            Some(format!(
+                "Must contain less than {} ids",
+                MESSAGE_IDS_MAX_COUNT
+            ))
+        } else if !ids.iter().all(|id| is_ulid(id)) {
+            Some("Must be message ids".to_string())
+        } else {
+            None
+        };
+        match message {
+            Some(m) => vec![ValidationError {
+                field: "message_ids".to_string(),
+                messages: vec![m],
+            }],
+            None => vec![],
+        }
+    }
+
+    fn result(
+        &self,
+        errors: Vec<ValidationError>,
+    ) -> Result<(), Vec<ValidationError>> {
+        if !errors.is_empty() {
+            for e in &errors {
+                info!(
+                    self.logger,
+                    "validation error: {} {}",
+                    e.field,
+                    e.messages.join(",")
+                );
+            }
+            return Err(errors);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::Utc;
+
+    use crate::id::{IdGenerator, RandomIdGenerator};
+    use crate::model::test::run;
+
+    #[test]
+    fn test_validate() {
+        run(|_, _, logger| {
+            let data = &Json(RequestData {
+                title: Some("outage".to_string()),
+                ..Default::default()
+            });
+            assert!(Validator::new(data, logger).validate().is_ok());
+
+            let data = &Json(RequestData {
+                title: Some(" ".to_string()),
+                message_ids: Some(vec!["x".to_string()]),
+                ..Default::default()
+            });
+            let errors = Validator::new(data, logger).validate().unwrap_err();
+            let fields: Vec<&str> =
+                errors.iter().map(|e| e.field.as_str()).collect();
+            assert_eq!(fields, vec!["title", "message_ids"]);
+        });
+    }
+
+    #[test]
+    fn test_validate_state() {
+        run(|_, _, logger| {
+            let data = &Json(RequestData {
+                state: Some("resolved".to_string()),
+                ..Default::default()
+            });
+            assert!(Validator::new(data, logger).validate_state().is_ok());
+
+            let data = &Json(RequestData {
+                state: Some("closed".to_string()),
+                ..Default::default()
+            });
+            let result = Validator::new(data, logger).validate_state();
+            assert_eq!(result.unwrap_err()[0].field, "state");
+        });
+    }
+
+    #[test]
+    fn test_validate_message_ids() {
+        run(|_, _, logger| {
+            let id = RandomIdGenerator.ulid(Utc::now());
+            let data = &Json(RequestData {
+                message_ids: Some(vec![id.clone()]),
+                ..Default::default()
+            });
+            let v = Validator::new(data, logger);
+            assert!(v.validate_message_ids().is_ok());
+
+            for ids in &[vec![], vec![id; MESSAGE_IDS_MAX_COUNT + 1]] {
+                let data = &Json(RequestData {
+                    message_ids: Some(ids.clone()),
+                    ..Default::default()
+                });
+                let v = Validator::new(data, logger);
+                let result = v.validate_message_ids();
+                assert_eq!(result.unwrap_err()[0].field, "message_ids");
+            }
+        });
+    }
+}
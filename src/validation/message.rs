@@ -6,14 +6,9 @@ use rocket_contrib::json::Json;
 use crate::logger::Logger;
 use crate::model::message::{LogFormat, LogLevel, NewMessage};
 use crate::request::message::Message as RequestData;
+use crate::tracecontext::TraceParent;
 use crate::validation::*;
 
-#[derive(Debug, Clone, Serialize)]
-pub struct ValidationError {
-    pub field: String,
-    pub messages: Vec<String>,
-}
-
 pub struct Validator<'a> {
     data: &'a Json<RequestData>,
     _logger: &'a Logger,
@@ -33,11 +28,18 @@ impl<'a> Validator<'a> {
             "level" => m.level => [either(LogLevel::as_vec())],
             "format" => m.format => [either(LogFormat::as_vec())],
             "title" => m.title => [required(), max_if_present(255)],
-            "content" => m.content => [length_if_present(0, 8000)]
+            "content" => m.content => [length_if_present(0, 8000)],
+            "fingerprint" => self.data.0.fingerprint => [
+                length_if_present(1, 128)
+            ],
+            "hostname" => m.hostname => [length_if_present(1, 255)],
+            "service" => m.service => [length_if_present(1, 128)],
+            "environment" => m.environment => [length_if_present(1, 64)]
         };
+        let mut errors: Vec<ValidationError> = vec![];
         if let Err(v) = result {
             // MultipleError to Vec<ValidationError>
-            let errors =
+            errors =
                 v.0.iter()
                     .map(|e| {
                         ValidationError {
@@ -50,6 +52,19 @@ impl<'a> Validator<'a> {
                         }
                     })
                     .collect();
+        }
+        if let Some(ref v) = self.data.0.traceparent {
+            if TraceParent::parse(v).is_none() {
+                errors.push(ValidationError {
+                    field: "traceparent".to_string(),
+                    messages: vec![
+                        "Must be a traceparent of W3C Trace Context"
+                            .to_string(),
+                    ],
+                });
+            }
+        }
+        if !errors.is_empty() {
             return Err(errors);
         }
         Ok(())
@@ -392,6 +407,33 @@ mod test {
         })
     }
 
+    #[test]
+    fn test_validate_fingerprint_is_too_long() {
+        run(|logger| {
+            let data = Json(RequestData {
+                title: Some("title".to_string()),
+                fingerprint: Some("a".repeat(129)),
+
+                ..Default::default()
+            });
+            let v = Validator::new(&data, &logger);
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("fingerprint", errors[0].field);
+                assert_eq!(
+                    vec!["Must contain less than 128 characters"],
+                    errors[0].messages
+                );
+            } else {
+                panic!("must fail");
+            }
+        })
+    }
+
     #[test]
     fn test_validate_content_is_none() {
         run(|logger| {
@@ -445,6 +487,33 @@ mod test {
         })
     }
 
+    #[test]
+    fn test_validate_traceparent_is_invalid() {
+        run(|logger| {
+            let data = Json(RequestData {
+                title: Some("title".to_string()),
+                traceparent: Some("00-invalid-00f067aa0ba902b7-01".to_string()),
+
+                ..Default::default()
+            });
+            let v = Validator::new(&data, &logger);
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("traceparent", errors[0].field);
+                assert_eq!(
+                    vec!["Must be a traceparent of W3C Trace Context"],
+                    errors[0].messages
+                );
+            } else {
+                panic!("must fail");
+            }
+        })
+    }
+
     #[test]
     fn test_validate() {
         run(|logger| {
@@ -467,6 +536,14 @@ description = "It's deprecated. Use panic!() instead"
 "#
                     .to_string(),
                 ),
+                fingerprint: None,
+                traceparent: Some(
+                    "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+                        .to_string(),
+                ),
+                hostname: Some("web-1".to_string()),
+                service: Some("api".to_string()),
+                environment: Some("production".to_string()),
             });
             let v = Validator::new(&data, &logger);
 
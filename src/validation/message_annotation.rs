@@ -0,0 +1,123 @@
+use std::result::Result;
+
+use rocket_contrib::json::Json;
+
+use crate::logger::Logger;
+use crate::request::message::Annotation as RequestData;
+use crate::validation::*;
+
+const TAG_MAX_LENGTH: usize = 64;
+const TAGS_MAX_COUNT: usize = 32;
+
+pub struct Validator<'a> {
+    data: &'a Json<RequestData>,
+    logger: &'a Logger,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(data: &'a Json<RequestData>, logger: &'a Logger) -> Self {
+        Self { data, logger }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors: Vec<ValidationError> = vec![];
+
+        if let Some(m) = self.tags_message() {
+            errors.push(ValidationError {
+                field: "tags".to_string(),
+                messages: vec![m],
+            });
+        }
+        if self.data.0.updated_at.is_none() {
+            errors.push(ValidationError {
+                field: "updated_at".to_string(),
+                messages: vec!["Must exist".to_string()],
+            });
+        }
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+        for e in errors.iter() {
+            info!(
+                self.logger,
+                "validation error: {} {}",
+                e.field,
+                e.messages.join(", ")
+            );
+        }
+        Err(errors)
+    }
+
+    fn tags_message(&self) -> Option<String> {
+        let tags = self.data.0.tags.as_ref()?;
+        if tags.len() > TAGS_MAX_COUNT {
+            return Some(format!(
+                "Must contain less than {} tags",
+                TAGS_MAX_COUNT
+            ));
+        }
+        for tag in tags.iter().map(|t| t.trim()) {
+            if tag.is_empty() {
+                return Some("Must not contain an empty tag".to_string());
+            }
+            if tag.chars().count() > TAG_MAX_LENGTH {
+                return Some(format!(
+                    "Must contain less than {} characters for each tag",
+                    TAG_MAX_LENGTH
+                ));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::model::test::run;
+
+    #[test]
+    fn test_validate() {
+        run(|_, _, logger| {
+            let updated_at =
+                Some(Utc.ymd(2021, 6, 29).and_hms(9, 0, 0).naive_utc());
+
+            let data = Json(RequestData {
+                tags: Some(vec![" db ".to_string()]),
+                resolved: Some(true),
+                updated_at,
+
+                ..Default::default()
+            });
+            assert!(Validator::new(&data, logger).validate().is_ok());
+
+            let data = Json(RequestData {
+                tags: Some(vec!["db".to_string()]),
+
+                ..Default::default()
+            });
+            let errors = Validator::new(&data, logger).validate().unwrap_err();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].field, "updated_at");
+
+            for tags in &[
+                vec![" ".to_string()],
+                vec!["a".repeat(65)],
+                vec!["db".to_string(); 33],
+            ] {
+                let data = Json(RequestData {
+                    tags: Some(tags.clone()),
+                    updated_at,
+
+                    ..Default::default()
+                });
+                let errors = Validator::new(&data, logger).validate();
+                assert_eq!(errors.unwrap_err()[0].field, "tags");
+            }
+        });
+    }
+}
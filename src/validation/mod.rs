@@ -1,8 +1,19 @@
+pub mod alert_schedule;
+pub mod bulk_operation;
+pub mod channel;
+pub mod email;
+pub mod incident;
 pub mod message;
+pub mod message_annotation;
 pub mod namespace;
+pub mod notification_preference;
 pub mod password_reset;
 pub mod password_reset_request;
+pub mod push_device;
+pub mod stream_token;
 pub mod user;
+pub mod username;
+pub mod waitlist;
 
 use accord::{Invalid, ValidatorResult};
 use accord::validators::{alphanumeric, max as original_max};
@@ -154,6 +165,7 @@ fn max_if_present(
 mod test {
     use super::*;
 
+    use insta::assert_snapshot;
     use rstest::rstest;
 
     #[rstest(
@@ -307,4 +319,27 @@ mod test {
 
         assert_eq!(expected, f(s).is_ok());
     }
+
+    // the payload of 422 responses by the validators
+    #[test]
+    fn test_validation_error_payload() {
+        let errors = vec![
+            ValidationError {
+                field: "email".to_string(),
+                messages: vec!["Must not be empty".to_string()],
+            },
+            ValidationError {
+                field: "password".to_string(),
+                messages: vec![
+                    "Must contain 'a-z'".to_string(),
+                    "Must not contain only digits or underscore".to_string(),
+                ],
+            },
+        ];
+        let payload = serde_json::json!({ "errors": errors });
+        assert_snapshot!(
+            "validation_error_payload",
+            serde_json::to_string_pretty(&payload).unwrap()
+        );
+    }
 }
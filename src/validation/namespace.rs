@@ -6,12 +6,7 @@ use rocket_contrib::json::Json;
 use crate::logger::Logger;
 use crate::model::namespace::NewNamespace;
 use crate::request::namespace::Namespace as RequestData;
-
-#[derive(Debug, Clone, Serialize)]
-pub struct ValidationError {
-    pub field: String,
-    pub messages: Vec<String>,
-}
+use crate::validation::ValidationError;
 
 pub struct Validator<'a> {
     data: &'a Json<RequestData>,
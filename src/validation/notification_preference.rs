@@ -0,0 +1,140 @@
+use std::result::Result;
+
+use rocket_contrib::json::Json;
+use url::Url;
+
+use crate::logger::Logger;
+use crate::model::notification_preference::{
+    NotificationChannel, NotificationEvent,
+};
+use crate::request::user::notification_preference::NotificationPreferences as RequestData;
+use crate::validation::*;
+
+const URL_MAX_LENGTH: usize = 2048;
+
+pub struct Validator<'a> {
+    data: &'a Json<RequestData>,
+    logger: &'a Logger,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(data: &'a Json<RequestData>, logger: &'a Logger) -> Self {
+        Self { data, logger }
+    }
+
+    /// Validates the preferences by the event (the field is like
+    /// `alert.channel`).
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+
+        for (event, preference) in self.data.0.iter() {
+            if NotificationEvent::from_name(event).is_none() {
+                let names: Vec<String> =
+                    NotificationEvent::iter().map(|e| e.to_string()).collect();
+                errors.push((
+                    event.to_string(),
+                    format!("Must be one of {}", names.join(", ")),
+                ));
+                continue;
+            }
+
+            let channel = preference
+                .channel
+                .as_ref()
+                .and_then(|c| NotificationChannel::from_name(c.trim()));
+            if channel.is_none() {
+                let names: Vec<String> = NotificationChannel::iter()
+                    .map(|c| c.to_string())
+                    .collect();
+                errors.push((
+                    format!("{}.channel", event),
+                    format!("Must be one of {}", names.join(", ")),
+                ));
+            }
+            if channel != Some(NotificationChannel::Webhook) {
+                continue;
+            }
+
+            let url = preference.webhook_url.as_ref().map(|s| s.trim());
+            let message = match url {
+                None | Some("") => Some("Must exist".to_string()),
+                Some(s) if s.len() > URL_MAX_LENGTH => Some(format!(
+                    "Must contain less than {} characters",
+                    URL_MAX_LENGTH
+                )),
+                Some(s) => match Url::parse(s) {
+                    Ok(u) if u.scheme() == "https" || u.scheme() == "http" => {
+                        None
+                    },
+                    _ => Some("Must be a http(s) URL".to_string()),
+                },
+            };
+            if let Some(m) = message {
+                errors.push((format!("{}.webhook_url", event), m));
+            }
+        }
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(errors
+            .into_iter()
+            .map(|(field, m)| {
+                info!(self.logger, "validation error: {} {}", field, m);
+                ValidationError {
+                    field,
+                    messages: vec![m],
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::test::run;
+    use crate::request::user::notification_preference::NotificationPreference;
+
+    fn preference(
+        channel: &str,
+        webhook_url: Option<&str>,
+    ) -> NotificationPreference {
+        NotificationPreference {
+            channel: Some(channel.to_string()),
+            webhook_url: webhook_url.map(|s| s.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        run(|_, _, logger| {
+            let mut data = Json(RequestData::new());
+            data.0.insert("alert".to_string(), preference("email", None));
+            data.0.insert(
+                "quota".to_string(),
+                preference("webhook", Some("https://example.org/hook")),
+            );
+            data.0.insert("security".to_string(), preference("none", None));
+            assert!(Validator::new(&data, logger).validate().is_ok());
+
+            let mut data = Json(RequestData::new());
+            data.0.insert("transfer".to_string(), preference("email", None));
+            let errors = Validator::new(&data, logger).validate();
+            assert_eq!(errors.unwrap_err()[0].field, "transfer");
+
+            let mut data = Json(RequestData::new());
+            data.0.insert("alert".to_string(), preference("sms", None));
+            let errors = Validator::new(&data, logger).validate();
+            assert_eq!(errors.unwrap_err()[0].field, "alert.channel");
+
+            for url in &[None, Some(""), Some("ftp://example.org")] {
+                let mut data = Json(RequestData::new());
+                data.0.insert("alert".to_string(), preference("webhook", *url));
+                let errors = Validator::new(&data, logger).validate();
+                assert_eq!(errors.unwrap_err()[0].field, "alert.webhook_url");
+            }
+        });
+    }
+}
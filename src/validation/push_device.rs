@@ -0,0 +1,144 @@
+use std::result::Result;
+
+use rocket_contrib::json::Json;
+
+use crate::logger::Logger;
+use crate::model::push_device::PushPlatform;
+use crate::request::push_device::PushDevice as RequestData;
+use crate::validation::*;
+
+const NAME_MAX_LENGTH: usize = 64;
+const TOKEN_MAX_LENGTH: usize = 4096;
+
+pub struct Validator<'a> {
+    data: &'a Json<RequestData>,
+    logger: &'a Logger,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(data: &'a Json<RequestData>, logger: &'a Logger) -> Self {
+        Self { data, logger }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+
+        let platform = self
+            .data
+            .0
+            .platform
+            .as_ref()
+            .and_then(|p| PushPlatform::from_name(p.trim()));
+        if platform.is_none() {
+            let names: Vec<String> =
+                PushPlatform::iter().map(|p| p.to_string()).collect();
+            errors.push((
+                "platform",
+                format!("Must be one of {}", names.join(", ")),
+            ));
+        }
+
+        // a token of APNs is hex, and the ones of FCM are opaque
+        let token = self.data.0.token.as_ref().map(|s| s.trim());
+        let message = match token {
+            None | Some("") => Some("Must exist".to_string()),
+            Some(s) if s.len() > TOKEN_MAX_LENGTH => Some(format!(
+                "Must contain less than {} characters",
+                TOKEN_MAX_LENGTH
+            )),
+            Some(s) if s.chars().any(char::is_whitespace) => {
+                Some("Must not contain any whitespace".to_string())
+            },
+            Some(s)
+                if platform == Some(PushPlatform::Apns)
+                    && !s.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                Some("Must be hex".to_string())
+            },
+            _ => None,
+        };
+        if let Some(m) = message {
+            errors.push(("token", m));
+        }
+
+        let name = self.data.0.name.as_ref().map(|s| s.trim());
+        let message = match name {
+            None | Some("") => Some("Must exist".to_string()),
+            Some(s) if s.chars().count() > NAME_MAX_LENGTH => Some(format!(
+                "Must contain less than {} characters",
+                NAME_MAX_LENGTH
+            )),
+            _ => None,
+        };
+        if let Some(m) = message {
+            errors.push(("name", m));
+        }
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(errors
+            .into_iter()
+            .map(|(field, m)| {
+                info!(self.logger, "validation error: {} {}", field, m);
+                ValidationError {
+                    field: field.to_string(),
+                    messages: vec![m],
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::test::run;
+
+    #[test]
+    fn test_validate() {
+        run(|_, _, logger| {
+            let data = Json(RequestData {
+                platform: Some("apns".to_string()),
+                token: Some(" 0a1b2c ".to_string()),
+                name: Some("iPhone".to_string()),
+            });
+            assert!(Validator::new(&data, logger).validate().is_ok());
+
+            let data = Json(RequestData {
+                platform: Some("fcm".to_string()),
+                token: Some("dGVzdA:APA91b-x_y".to_string()),
+                name: Some("Pixel".to_string()),
+            });
+            assert!(Validator::new(&data, logger).validate().is_ok());
+
+            let data = Json(RequestData {
+                platform: Some("webpush".to_string()),
+                token: Some("a".to_string()),
+                name: Some("Browser".to_string()),
+            });
+            let errors = Validator::new(&data, logger).validate();
+            assert_eq!(errors.unwrap_err()[0].field, "platform");
+
+            for token in &[None, Some("xyz"), Some("0a 1b")] {
+                let data = Json(RequestData {
+                    platform: Some("apns".to_string()),
+                    token: token.map(|t| t.to_string()),
+                    name: Some("iPhone".to_string()),
+                });
+                let errors = Validator::new(&data, logger).validate();
+                assert_eq!(errors.unwrap_err()[0].field, "token");
+            }
+
+            let data = Json(RequestData {
+                platform: Some("fcm".to_string()),
+                token: Some("token".to_string()),
+
+                ..Default::default()
+            });
+            let errors = Validator::new(&data, logger).validate();
+            assert_eq!(errors.unwrap_err()[0].field, "name");
+        });
+    }
+}
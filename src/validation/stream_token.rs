@@ -0,0 +1,106 @@
+use std::result::Result;
+
+use rocket_contrib::json::Json;
+
+use crate::logger::Logger;
+use crate::request::stream_token::StreamToken as RequestData;
+use crate::validation::*;
+
+const NAME_MAX_LENGTH: usize = 64;
+
+// the source of the messages (same as `validation::message`)
+const SOURCE_MAX_LENGTHS: [(&str, usize); 3] =
+    [("hostname", 255), ("service", 128), ("environment", 64)];
+
+pub struct Validator<'a> {
+    data: &'a Json<RequestData>,
+    logger: &'a Logger,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(data: &'a Json<RequestData>, logger: &'a Logger) -> Self {
+        Self { data, logger }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+
+        let name = self.data.0.name.as_ref().map(|s| s.trim());
+        let message = match name {
+            None | Some("") => Some("Must exist".to_string()),
+            Some(s) if s.chars().count() > NAME_MAX_LENGTH => Some(format!(
+                "Must contain less than {} characters",
+                NAME_MAX_LENGTH
+            )),
+            _ => None,
+        };
+        if let Some(m) = message {
+            errors.push(("name", m));
+        }
+
+        let data = &self.data.0;
+        let sources = [&data.hostname, &data.service, &data.environment];
+        for ((field, max), value) in SOURCE_MAX_LENGTHS.iter().zip(&sources) {
+            let length = value.as_ref().map_or(0, |s| s.trim().chars().count());
+            if length > *max {
+                errors.push((
+                    *field,
+                    format!("Must contain less than {} characters", max),
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(errors
+            .into_iter()
+            .map(|(field, m)| {
+                info!(self.logger, "validation error: {} {}", field, m);
+                ValidationError {
+                    field: field.to_string(),
+                    messages: vec![m],
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::model::test::run;
+
+    #[test]
+    fn test_validate() {
+        run(|_, _, logger| {
+            let data = Json(RequestData {
+                name: Some(" fluent-bit ".to_string()),
+                service: Some("api".to_string()),
+
+                ..Default::default()
+            });
+            assert!(Validator::new(&data, logger).validate().is_ok());
+
+            for name in &[None, Some(" ".to_string()), Some("a".repeat(65))] {
+                let data = Json(RequestData {
+                    name: name.clone(),
+
+                    ..Default::default()
+                });
+                let errors = Validator::new(&data, logger).validate();
+                assert_eq!(errors.unwrap_err()[0].field, "name");
+            }
+
+            let data = Json(RequestData {
+                name: Some("fluent-bit".to_string()),
+                environment: Some("a".repeat(65)),
+
+                ..Default::default()
+            });
+            let errors = Validator::new(&data, logger).validate();
+            assert_eq!(errors.unwrap_err()[0].field, "environment");
+        });
+    }
+}
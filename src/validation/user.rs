@@ -8,10 +8,17 @@ use crate::logger::Logger;
 use crate::model::user::{NewUser, User};
 use crate::request::user::registration::UserRegistration as RequestData;
 use crate::validation::*;
+use crate::validation::email::{EmailPolicy, is_disposable, is_valid};
+use crate::validation::username;
 
+/// Validator
+///
+/// The email in the data is expected to be normalized (see
+/// `validation::email::normalize`).
 pub struct Validator<'a> {
     conn: &'a PgConnection,
     data: &'a Json<RequestData>,
+    policy: &'a EmailPolicy,
     logger: &'a Logger,
 }
 
@@ -19,9 +26,30 @@ impl<'a> Validator<'a> {
     pub fn new(
         conn: &'a PgConnection,
         data: &'a Json<RequestData>,
+        policy: &'a EmailPolicy,
         logger: &'a Logger,
     ) -> Self {
-        Self { conn, data, logger }
+        Self {
+            conn,
+            data,
+            policy,
+            logger,
+        }
+    }
+
+    fn validate_email_address(&self) -> Result<(), ValidationError> {
+        let email = &self.data.0.email;
+        let message = if !is_valid(email) {
+            "Must be a valid email address"
+        } else if is_disposable(email, self.policy) {
+            "Must not be a disposable email address"
+        } else {
+            return Ok(());
+        };
+        Err(ValidationError {
+            field: "email".to_string(),
+            messages: vec![message.to_string()],
+        })
     }
 
     fn validate_email_uniqueness(&self) -> Result<(), ValidationError> {
@@ -55,20 +83,10 @@ impl<'a> Validator<'a> {
     #[allow(clippy::redundant_closure)]
     pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
         let u = NewUser::from(&self.data.0);
-        // TODO:
-        // * check email format
-        // * check whether username is reserved or not
         let result = rules! {
             "name" => u.name => [
                 max_if_present(64)
             ],
-            "username" => u.username => [
-                contain_only_alphanumeric_or_underscore(),
-                not_contain_only_digits_or_underscore(),
-                not_start_with_digits(),
-                not_start_with("_"),
-                length(3, 32)
-            ],
             "email" => u.email => [
                 contains("@"),
                 contains("."),
@@ -78,7 +96,7 @@ impl<'a> Validator<'a> {
                 contain_any(CHARS_LOWER, "a-z"),
                 contain_any(CHARS_UPPER, "A-Z"),
                 contain_any(DIGITS, "0-9"),
-                not_overlap_with("username")(u.username),
+                not_overlap_with("username")(u.username.clone()),
                 length(8, 1024)
             ]
         };
@@ -102,8 +120,20 @@ impl<'a> Validator<'a> {
                     .collect();
         }
 
+        // the username policy (see validation::username), after name
+        if let Err(e) = username::validate(&u.username) {
+            let i = errors
+                .iter()
+                .position(|e| "name" != e.field)
+                .unwrap_or_else(|| errors.len());
+            errors.insert(i, e);
+        }
+
         if !errors.iter().any(|e| "email" == e.field) {
-            if let Err(e) = self.validate_email_uniqueness() {
+            if let Err(e) = self
+                .validate_email_address()
+                .and_then(|_| self.validate_email_uniqueness())
+            {
                 errors.push(e);
             }
         }
@@ -129,6 +159,7 @@ mod test {
     use rocket_contrib::json::Json;
     use rstest::rstest;
 
+    use crate::model::password_hash::PasswordHashParams;
     use crate::model::test::run;
 
     #[test]
@@ -141,7 +172,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -173,7 +210,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -201,7 +244,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -233,7 +282,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -261,7 +316,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -283,6 +344,78 @@ mod test {
         })
     }
 
+    #[test]
+    fn test_validate_email_is_invalid_in_syntax() {
+        run(|conn, _, logger| {
+            let data = &Json(RequestData {
+                email: "post..master@example.org".to_string(),
+                username: "username".to_string(),
+                password: "Passw0rd".to_string(),
+
+                ..Default::default()
+            });
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("email", errors[0].field);
+                assert_eq!(
+                    vec!["Must be a valid email address"],
+                    errors[0].messages
+                );
+            } else {
+                panic!("must fail");
+            }
+        })
+    }
+
+    #[test]
+    fn test_validate_email_is_disposable() {
+        run(|conn, _, logger| {
+            let data = &Json(RequestData {
+                email: "postmaster@mail.mailinator.com".to_string(),
+                username: "username".to_string(),
+                password: "Passw0rd".to_string(),
+
+                ..Default::default()
+            });
+            let policy = &EmailPolicy {
+                disposable_domains: vec!["mailinator.com".to_string()],
+
+                ..Default::default()
+            };
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("email", errors[0].field);
+                assert_eq!(
+                    vec!["Must not be a disposable email address"],
+                    errors[0].messages
+                );
+            } else {
+                panic!("must fail");
+            }
+        })
+    }
+
     #[test]
     fn test_validate_email() {
         run(|conn, _, logger| {
@@ -293,7 +426,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_ok());
@@ -309,7 +448,13 @@ mod test {
                 username: "username".to_string(),
                 password: "Passw0rd".to_string(),
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -336,7 +481,13 @@ mod test {
                 username: "username".to_string(),
                 password: "Passw0rd".to_string(),
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_ok());
@@ -352,7 +503,13 @@ mod test {
                 username: "username".to_string(),
                 password: "Passw0rd".to_string(),
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_ok());
@@ -369,7 +526,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -397,7 +560,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -435,7 +604,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -488,7 +663,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -516,7 +697,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -555,7 +742,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_ok());
@@ -579,7 +772,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -597,6 +796,44 @@ mod test {
         })
     }
 
+    #[rstest(
+        username,
+        case("admin"),
+        case("Login"),
+        case("API"),
+        ::trace
+    )]
+    #[test]
+    fn test_validate_username_is_reserved(username: &'static str) {
+        run(|conn, _, logger| {
+            let data = &Json(RequestData {
+                email: "postmaster@example.org".to_string(),
+                username: username.to_string(),
+                password: "Passw0rd".to_string(),
+
+                ..Default::default()
+            });
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
+
+            let result = v.validate();
+            assert!(result.is_err());
+
+            if let Err(errors) = &result {
+                assert_eq!(1, errors.len());
+                assert_eq!("username", errors[0].field);
+                assert_eq!(vec!["Must not be reserved"], errors[0].messages);
+            } else {
+                panic!("must fail");
+            }
+        })
+    }
+
     #[test]
     fn test_validate_password_is_too_short() {
         run(|conn, _, logger| {
@@ -607,7 +844,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -635,7 +878,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -663,7 +912,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -691,7 +946,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -719,7 +980,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -757,7 +1024,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -774,7 +1047,7 @@ mod test {
 
     #[test]
     fn test_validate_email_uniqueness() {
-        run(|conn, _, logger| {
+        run(|conn, config, logger| {
             let data = &Json(RequestData {
                 email: "postmaster@example.org".to_string(),
                 username: "username".to_string(),
@@ -784,7 +1057,8 @@ mod test {
             });
 
             let mut u = NewUser::from(&data.0);
-            u.set_password(&data.password);
+            let params = PasswordHashParams::from(config);
+            u.set_password(&data.password, &params);
 
             let _id = User::insert(&u, conn, logger)
                 .unwrap_or_else(|| panic!("Error inserting: {}", u));
@@ -796,7 +1070,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
@@ -813,7 +1093,7 @@ mod test {
 
     #[test]
     fn test_validate_username_uniqueness() {
-        run(|conn, _, logger| {
+        run(|conn, config, logger| {
             let data = &Json(RequestData {
                 email: "postmaster@example.org".to_string(),
                 username: "username".to_string(),
@@ -823,7 +1103,8 @@ mod test {
             });
 
             let mut u = NewUser::from(&data.0);
-            u.set_password(&data.password);
+            let params = PasswordHashParams::from(config);
+            u.set_password(&data.password, &params);
 
             let _id = User::insert(&u, conn, logger)
                 .unwrap_or_else(|| panic!("Error inserting: {}", u));
@@ -835,7 +1116,13 @@ mod test {
 
                 ..Default::default()
             });
-            let v = Validator { conn, data, logger };
+            let policy = &EmailPolicy::default();
+            let v = Validator {
+                conn,
+                data,
+                policy,
+                logger,
+            };
 
             let result = v.validate();
             assert!(result.is_err());
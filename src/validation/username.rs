@@ -0,0 +1,117 @@
+//! Username policy.
+//!
+//! Usernames will appear in routed URLs (e.g. `/<username>`), so the names
+//! of routes and the ones which look official are reserved. The comparison
+//! is case-insensitive.
+use accord::Invalid;
+use accord::validators::length;
+
+use crate::validation::*;
+
+pub const RESERVED_USERNAMES: &[&str] = &[
+    "about", "account", "admin", "administrator", "api", "app", "assets",
+    "auth", "blog", "console", "dashboard", "deregister", "docs",
+    "eloquentlog", "graphql", "health", "help", "login", "logout", "me",
+    "message", "namespace", "new", "oauth", "password", "readyz", "register",
+    "root", "security", "settings", "signup", "static", "status", "stream",
+    "support", "system", "user", "users", "v1", "waitlist", "www",
+];
+
+pub fn is_reserved(username: &str) -> bool {
+    let username = username.to_lowercase();
+    RESERVED_USERNAMES.iter().any(|r| *r == username)
+}
+
+fn not_reserved() -> SV {
+    Box::new(move |s: &String| {
+        if is_reserved(s) {
+            return Err(Invalid {
+                msg: "Must not be reserved".to_string(),
+                args: vec![],
+                human_readable: "Must not be reserved".to_string(),
+            });
+        }
+        Ok(())
+    })
+}
+
+/// Validates the format of the username (not its uniqueness).
+#[allow(clippy::redundant_closure)]
+pub fn validate(username: &str) -> Result<(), ValidationError> {
+    let username = username.to_string();
+    let result = rules! {
+        "username" => username => [
+            contain_only_alphanumeric_or_underscore(),
+            not_contain_only_digits_or_underscore(),
+            not_start_with_digits(),
+            not_start_with("_"),
+            not_reserved(),
+            length(3, 32)
+        ]
+    };
+    result.map_err(|v| {
+        ValidationError {
+            field: "username".to_string(),
+            messages: v
+                .0
+                .iter()
+                .flat_map(|e| e.invalids.iter())
+                .map(|i| i.human_readable.to_string())
+                .collect(),
+        }
+    })
+}
+
+#[rustfmt::skip::attributes(rstest)]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest(
+        raw_s, expected,
+        case("admin", true),
+        case("Admin", true),
+        case("LOGIN", true),
+        case("v1", true),
+        case("admins", false),
+        case("oswald", false),
+        ::trace
+    )]
+    #[test]
+    fn test_is_reserved(raw_s: &'static str, expected: bool) {
+        assert_eq!(is_reserved(raw_s), expected);
+    }
+
+    #[test]
+    fn test_reserved_usernames_are_lowercase_and_sorted() {
+        let mut v = RESERVED_USERNAMES.to_vec();
+        v.sort_unstable();
+        v.dedup();
+        assert_eq!(v, RESERVED_USERNAMES);
+        assert!(v.iter().all(|r| r.to_lowercase() == *r));
+    }
+
+    #[rstest(
+        raw_s, expected,
+        case("oswald", vec![]),
+        case("o_swald_1", vec![]),
+        case("Assets", vec!["Must not be reserved"]),
+        case("1oswald", vec!["Must not start with digits"]),
+        case("_oswald", vec!["Must not start with '_'"]),
+        case("os", vec!["Must contain more than 3 characters"]),
+        case("o-swald", vec!["Must not contain '-'"]),
+        ::trace
+    )]
+    #[test]
+    fn test_validate(raw_s: &'static str, expected: Vec<&'static str>) {
+        match validate(raw_s) {
+            Ok(_) => assert!(expected.is_empty()),
+            Err(e) => {
+                assert_eq!(e.field, "username");
+                assert_eq!(e.messages, expected);
+            },
+        }
+    }
+}
@@ -4,16 +4,17 @@ use rocket::http::{ContentType, Header, Status};
 use serde_json::Value;
 use uuid::Uuid;
 
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::id::RandomIdGenerator;
 use eloquentlog_console_api::model;
 
-use crate::{minify, run_test, load_user, make_raw_password, USERS};
+use crate::{minify, run_test};
 
 #[test]
 fn test_access_token_hset_state_failure() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -62,9 +63,8 @@ fn test_access_token_hset_state_failure() {
 #[test]
 fn test_access_token_hset_state() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -93,7 +93,9 @@ fn test_access_token_hset_state() {
         // 2019-08-07T06:05:04.333
         let dt = Utc.ymd(2019, 8, 7).and_hms_milli(6, 5, 4, 333);
 
-        let v = model::access_token::AccessToken::generate_token();
+        let v = model::access_token::AccessToken::generate_token(
+            &RandomIdGenerator,
+        );
         let t = model::access_token::AccessToken {
             id: 1,
             uuid: Uuid::new_v4(),
@@ -105,6 +107,7 @@ fn test_access_token_hset_state() {
             revoked_at: None,
             created_at: dt.naive_utc(),
             updated_at: dt.naive_utc(),
+            scopes: vec![],
         };
 
         let access_token =
@@ -136,12 +139,327 @@ fn test_access_token_hset_state() {
     });
 }
 
+#[test]
+fn test_access_token_hset_scopes() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        // 2019-08-07T06:05:04.333
+        let dt = Utc.ymd(2019, 8, 7).and_hms_milli(6, 5, 4, 333);
+
+        let v = model::access_token::AccessToken::generate_token(
+            &RandomIdGenerator,
+        );
+        let t = model::access_token::AccessToken {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            agent_id: user.id,
+            agent_type: model::access_token::AgentType::Client,
+            name: "client token".to_string(),
+            token: Some(v.into_bytes()),
+            state: model::access_token::AccessTokenState::Enabled,
+            revoked_at: None,
+            created_at: dt.naive_utc(),
+            updated_at: dt.naive_utc(),
+            scopes: vec![],
+        };
+
+        let access_token =
+            diesel::insert_into(model::access_token::access_tokens::table)
+                .values(&t)
+                .get_result::<model::access_token::AccessToken>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", t));
+
+        let uri = format!("/v1/access_token/hset/{}/scopes", access_token.uuid);
+        let res = client
+            .patch(&uri)
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(
+                r#"{
+                  "access_token": {
+                    "scopes": ["ingest:write", "namespace:write"]
+                  }
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let mut res = client
+            .patch(&uri)
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(
+                r#"{
+                  "access_token": {
+                    "scopes": ["messages:read", "ingest:write"]
+                  }
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        assert_eq!(body, "{\"access_token\":1}");
+
+        let result = model::access_token::access_tokens::table
+            .filter(model::access_token::access_tokens::id.eq(access_token.id))
+            .first::<model::access_token::AccessToken>(conn.db)
+            .expect("Failed to get a record");
+        assert_eq!(
+            result.scopes,
+            vec!["ingest:write".to_string(), "messages:read".to_string()]
+        );
+    });
+}
+
+#[test]
+fn test_access_token_del_requires_confirmation() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        // 2019-08-07T06:05:04.333
+        let dt = Utc.ymd(2019, 8, 7).and_hms_milli(6, 5, 4, 333);
+
+        let v = model::access_token::AccessToken::generate_token(
+            &RandomIdGenerator,
+        );
+        let t = model::access_token::AccessToken {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            agent_id: user.id,
+            agent_type: model::access_token::AgentType::Client,
+            name: "client token".to_string(),
+            token: Some(v.into_bytes()),
+            state: model::access_token::AccessTokenState::Enabled,
+            revoked_at: None,
+            created_at: dt.naive_utc(),
+            updated_at: dt.naive_utc(),
+            scopes: vec![],
+        };
+
+        let access_token =
+            diesel::insert_into(model::access_token::access_tokens::table)
+                .values(&t)
+                .get_result::<model::access_token::AccessToken>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", t));
+
+        let uri = format!("/v1/access_token/del/{}", access_token.uuid);
+        let mut res = client
+            .patch(&uri)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::PreconditionRequired);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["field_errors"][0]["field"], "confirmation_token");
+
+        let mut res = client
+            .post(format!("{}/confirm", uri))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let confirmation = result["confirmation_token"]["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(result["confirmation_token"]["expires_in"], 300);
+
+        let mut res = client
+            .patch(&uri)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Confirmation-Token", confirmation.clone()))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        assert_eq!(body, "{\"access_token\":1}");
+
+        // it can't be used again
+        let res = client
+            .patch(&uri)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Confirmation-Token", confirmation))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::PreconditionRequired);
+    });
+}
+
+#[test]
+fn test_access_token_dump_requires_sudo() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        // 2019-08-07T06:05:04.333
+        let dt = Utc.ymd(2019, 8, 7).and_hms_milli(6, 5, 4, 333);
+
+        let t = model::access_token::AccessToken {
+            id: 1,
+            uuid: Uuid::new_v4(),
+            agent_id: user.id,
+            agent_type: model::access_token::AgentType::Client,
+            name: "client token".to_string(),
+            token: None,
+            state: model::access_token::AccessTokenState::Enabled,
+            revoked_at: None,
+            created_at: dt.naive_utc(),
+            updated_at: dt.naive_utc(),
+            scopes: vec![],
+        };
+
+        let access_token =
+            diesel::insert_into(model::access_token::access_tokens::table)
+                .values(&t)
+                .get_result::<model::access_token::AccessToken>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", t));
+
+        let uri = format!("/v1/access_token/dump/{}", access_token.uuid);
+        let res = client
+            .patch(&uri)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+
+        let res = client
+            .post("/_/sudo")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"password": "wrong"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Unauthorized);
+
+        let mut res = client
+            .post("/_/sudo")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(format!(r#"{{"password": "{}"}}"#, password))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["sudo"]["expires_in"], 900);
+
+        let mut res = client
+            .patch(&uri)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert!(result["access_token"]["token"].is_string());
+
+        // no confirmation token is needed in sudo mode
+        let res = client
+            .patch(format!("/v1/access_token/del/{}", access_token.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+    });
+}
+
 #[test]
 fn test_access_token_lrange_returns_empty_if_not_exist() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -176,16 +494,18 @@ fn test_access_token_lrange_returns_empty_if_not_exist() {
         assert_eq!(res.status(), Status::Ok);
 
         let body = res.body_string().unwrap();
-        assert_eq!(body, "[]");
+        assert_eq!(
+            body,
+            r#"{"data":[],"next_cursor":null,"total_estimate":null}"#
+        );
     });
 }
 
 #[test]
 fn test_access_token_lrange_returns_a_list_contain_tokens() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -213,7 +533,9 @@ fn test_access_token_lrange_returns_a_list_contain_tokens() {
 
         // 2019-08-07T06:05:04.333
         let dt = Utc.ymd(2019, 8, 7).and_hms_milli(6, 5, 4, 333);
-        let v = model::access_token::AccessToken::generate_token();
+        let v = model::access_token::AccessToken::generate_token(
+            &RandomIdGenerator,
+        );
         let t = model::access_token::AccessToken {
             id: 1,
             uuid: Uuid::new_v4(),
@@ -225,6 +547,7 @@ fn test_access_token_lrange_returns_a_list_contain_tokens() {
             revoked_at: None,
             created_at: dt.naive_utc(),
             updated_at: dt.naive_utc(),
+            scopes: vec!["messages:read".to_string()],
         };
 
         let access_token_1 =
@@ -234,7 +557,9 @@ fn test_access_token_lrange_returns_a_list_contain_tokens() {
                 .unwrap_or_else(|_| panic!("Error inserting: {}", t));
 
         let dt = Utc.ymd(2020, 2, 18).and_hms_milli(5, 4, 3, 222);
-        let v = model::access_token::AccessToken::generate_token();
+        let v = model::access_token::AccessToken::generate_token(
+            &RandomIdGenerator,
+        );
         let t = model::access_token::AccessToken {
             id: 2,
             uuid: Uuid::new_v4(),
@@ -246,6 +571,7 @@ fn test_access_token_lrange_returns_a_list_contain_tokens() {
             revoked_at: None,
             created_at: dt.naive_utc(),
             updated_at: dt.naive_utc(),
+            scopes: vec![],
         };
 
         let access_token_2 =
@@ -269,27 +595,36 @@ fn test_access_token_lrange_returns_a_list_contain_tokens() {
         assert_eq!(
             body,
             minify(format!(
-                r#"[{{
+                r#"{{"data": [{{
 "access_token": {{
   "agent_type": "client",
   "created_at": "2019-08-07T06:05:04.333",
   "name": "client token 1",
   "revoked_at": null,
+  "scopes": ["messages:read"],
   "state": "enabled",
   "token": "***",
   "updated_at": "2019-08-07T06:05:04.333",
   "uuid": "{}"
-}}}},{{
+}},
+"user_agents": []
+}},{{
 "access_token": {{
   "agent_type": "client",
   "created_at": "2020-02-18T05:04:03.222",
   "name": "client token 2",
   "revoked_at": null,
+  "scopes": [],
   "state": "enabled",
   "token": "***",
   "updated_at": "2020-02-18T05:04:03.222",
   "uuid": "{}"
-}}}}]"#,
+}},
+"user_agents": []
+}}],
+"next_cursor": null,
+"total_estimate": null
+}}"#,
                 access_token_1.uuid, access_token_2.uuid,
             ))
         );
@@ -0,0 +1,144 @@
+use chrono::{Duration, Utc};
+use diesel::{self, prelude::*};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use eloquentlog_console_api::clock;
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::job;
+use eloquentlog_console_api::model;
+
+use crate::{run_test, MEMBERSHIPS, NAMESPACES, STREAMS};
+
+#[test]
+fn test_hset_and_deferred_alert_email() {
+    run_test(|client, conn, config, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let mut res = client
+            .get("/v1/alert_schedule/hget")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["alert_schedule"]["timezone"], "UTC");
+        assert!(result["alert_schedule"]["quiet_hours_start"].is_null());
+
+        let res = client
+            .post("/v1/alert_schedule/hset")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"timezone": "Mars/Olympus"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        // quiet hours around now
+        let now = Utc::now();
+        let start = (now - Duration::hours(1)).format("%H:%M");
+        let end = (now + Duration::hours(1)).format("%H:%M");
+        let mut res = client
+            .post("/v1/alert_schedule/hset")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(format!(
+                r#"{{
+                    "timezone": "UTC",
+                    "quiet_hours_start": "{}",
+                    "quiet_hours_end": "{}"
+                }}"#,
+                start, end
+            ))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            result["alert_schedule"]["quiet_hours_start"],
+            start.to_string()
+        );
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let _ = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .returning(model::stream::streams::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let job = job::Job::<String> {
+            kind: job::JobKind::SendAlertEmail,
+            args: vec![
+                user.uuid.to_string(),
+                s.uuid.to_string(),
+                "01DHNAWKYD0000000000000001".into(),
+            ],
+        };
+        // within the quiet hours
+        let clock = clock::FrozenClock::new(now);
+        job.invoke(conn.db, config, &clock, logger);
+
+        let count = redis::cmd("ZCARD")
+            .arg(job::DEFERRED_KEY)
+            .query::<i64>(conn.mq)
+            .unwrap();
+        assert_eq!(count, 1);
+
+        // not due yet
+        let result = job::enqueue_deferred(now, conn.mq);
+        assert_eq!(result, Ok(0));
+
+        let result = job::enqueue_deferred(now + Duration::hours(2), conn.mq);
+        assert_eq!(result, Ok(1));
+    });
+}
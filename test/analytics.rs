@@ -0,0 +1,62 @@
+use redis::Commands;
+use serde_json::json;
+
+use eloquentlog_console_api::clock;
+use eloquentlog_console_api::job;
+use eloquentlog_console_api::model::analytics_event::AnalyticsEvent;
+use eloquentlog_console_api::request::analytics::KEY;
+
+use crate::run_test;
+
+#[test]
+fn test_flush_analytics_events() {
+    run_test(|_, conn, config, logger| {
+        let values = vec![
+            json!({
+                "name": "search",
+                "actor": null,
+                "properties": {"results": "3"},
+                "occurred_at": 1_625_731_200,
+            }),
+            json!({
+                "name": "channel_created",
+                "actor": "a1b2",
+                "properties": {"kind": "email"},
+                "occurred_at": 1_625_731_200,
+            }),
+            json!({"name": "broken"}),
+        ];
+        for v in values.iter() {
+            let _: i64 = conn.ss.rpush(KEY, v.to_string()).unwrap();
+        }
+
+        let job = job::Job::<String> {
+            kind: job::JobKind::FlushAnalyticsEvents,
+            args: vec![],
+        };
+        job.invoke(conn.db, config, &clock::SystemClock, logger);
+
+        let db = conn.db;
+        let count =
+            |name| AnalyticsEvent::count_by_name(name, db, logger).unwrap();
+        assert_eq!(count("search"), 1);
+        assert_eq!(count("channel_created"), 1);
+        assert_eq!(count("broken"), 0);
+
+        let len: i64 = conn.ss.llen(KEY).unwrap();
+        assert_eq!(len, 0);
+
+        // nothing to flush
+        job.invoke(conn.db, config, &clock::SystemClock, logger);
+        assert_eq!(count("search"), 1);
+
+        // more than a batch
+        let v = values[0].to_string();
+        let _: i64 = conn.ss.rpush(KEY, vec![v; 501]).unwrap();
+        job.invoke(conn.db, config, &clock::SystemClock, logger);
+        assert_eq!(count("search"), 502);
+
+        let len: i64 = conn.ss.llen(KEY).unwrap();
+        assert_eq!(len, 0);
+    });
+}
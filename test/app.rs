@@ -0,0 +1,14 @@
+use eloquentlog_console_api::testing::spawn_app;
+
+#[test]
+fn test_spawn_app() {
+    let app = spawn_app();
+
+    let res = ureq::get(&app.url("/_/health"))
+        .set("X-Requested-With", "XMLHttpRequest")
+        .call()
+        .unwrap();
+    assert_eq!(res.status(), 200);
+
+    app.teardown();
+}
@@ -1,6 +1,8 @@
 use fourche::queue::Queue;
 use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
 
+use eloquentlog_console_api::factory;
 use eloquentlog_console_api::job;
 
 use crate::run_test;
@@ -218,3 +220,296 @@ fn test_login() {
         assert_eq!(res.status(), Status::Ok);
     });
 }
+
+#[test]
+fn test_login_rate_limit() {
+    run_test(|client, _, config, _| {
+        let login = || {
+            client
+                .post("/_/login")
+                .header(ContentType::JSON)
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .body(
+                    r#"{
+                      "username": "johnny@example.org",
+                      "password": "pa$$w0rD"
+                    }"#,
+                )
+                .dispatch()
+        };
+
+        for _ in 0..config.rate_limit_login_per_minute {
+            let res = login();
+            assert_ne!(res.status(), Status::TooManyRequests);
+            assert!(res.headers().get_one("X-RateLimit-Remaining").is_some());
+        }
+
+        let res = login();
+        assert_eq!(res.status(), Status::TooManyRequests);
+        assert_eq!(res.headers().get_one("X-RateLimit-Remaining"), Some("0"));
+        assert!(res.headers().get_one("Retry-After").is_some());
+    });
+}
+
+#[test]
+fn test_login_rate_limit_with_spoofed_headers() {
+    run_test(|client, _, config, _| {
+        let login = |i: u32| {
+            client
+                .post("/_/login")
+                .header(ContentType::JSON)
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .header(Header::new("Authorization", format!("Bearer {}", i)))
+                .header(Header::new("X-Real-IP", format!("192.0.2.{}", i)))
+                // not a trusted proxy
+                .remote("198.51.100.1:8000".parse().unwrap())
+                .body(
+                    r#"{
+                      "username": "johnny@example.org",
+                      "password": "pa$$w0rD"
+                    }"#,
+                )
+                .dispatch()
+        };
+
+        for i in 0..config.rate_limit_login_per_minute {
+            let res = login(i);
+            assert_ne!(res.status(), Status::TooManyRequests);
+        }
+
+        let res = login(config.rate_limit_login_per_minute);
+        assert_eq!(res.status(), Status::TooManyRequests);
+    });
+}
+
+#[test]
+fn test_login_with_too_large_body() {
+    run_test(|client, _, config, _| {
+        let limit = config.server_limit_json_auth;
+        let password = "a".repeat(limit as usize);
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                  "username": "johnny@example.org",
+                  "password": "{}"
+                }}"#,
+                password,
+            ))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::PayloadTooLarge);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["code"], "payload_too_large");
+        assert_eq!(
+            result["message"],
+            format!("The request body must be at most {} bytes", limit)
+        );
+    });
+}
+
+#[test]
+fn test_login_without_csrf_token() {
+    run_test(|client, _, _, _| {
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(
+                r#"{
+                  "username": "johnny@example.org",
+                  "password": "pa$$w0rD"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Unauthorized);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["message"], "The CSRF token is required.");
+    });
+}
+
+#[test]
+fn test_login_with_expired_csrf_token() {
+    run_test(|client, conn, _, _| {
+        let _ = client
+            .head("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        // the key has gone from the session store
+        redis::cmd("FLUSHDB").execute(conn.ss);
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(
+                r#"{
+                  "username": "johnny@example.org",
+                  "password": "pa$$w0rD"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Unauthorized);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            result["message"],
+            "The CSRF token has been expired. Reload the page."
+        );
+    });
+}
+
+#[test]
+fn test_token_exchange() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let res = client
+            .post("/_/token/exchange?scope=namespace:admin")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let mut res = client
+            .post("/_/token/exchange")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["token"]["scope"], "messages:read");
+        assert_eq!(result["token"]["expires_in"], 300);
+        let authorization = format!(
+            "Exchanged-Token {}",
+            result["token"]["value"].as_str().unwrap()
+        );
+
+        let res = client
+            .get("/v1/namespace/hgetall")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", authorization.clone()))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        // out of the scope
+        let res = client
+            .get(format!(
+                "/v1/namespace/hget/{}/usage",
+                "00000000-0000-0000-0000-000000000000"
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", authorization.clone()))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+
+        // the exchanged token can't be exchanged again
+        let res = client
+            .post("/_/token/exchange")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", authorization.clone()))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+
+        // expired
+        redis::cmd("FLUSHDB").execute(conn.ss);
+
+        let res = client
+            .get("/v1/namespace/hgetall")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", authorization))
+            .dispatch();
+
+        assert_ne!(res.status(), Status::Ok);
+    });
+}
+
+#[test]
+fn test_login_from_unknown_ip() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let login = |ip: &str| {
+            let _ = client
+                .head("/_/login/")
+                .header(ContentType::JSON)
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .body("{}")
+                .dispatch();
+
+            client
+                .post("/_/login")
+                .header(ContentType::JSON)
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .header(Header::new("X-Real-IP", ip.to_string()))
+                // a trusted proxy (see `TEST_SERVER_TRUSTED_PROXIES`)
+                .remote("127.0.0.1:8000".parse().unwrap())
+                .body(format!(
+                    r#"{{
+                        "username": "{}",
+                        "password": "{}"
+                    }}"#,
+                    user.email, password,
+                ))
+                .dispatch()
+        };
+
+        // the first one and the known one are not noticed
+        assert_eq!(login("192.0.2.1").status(), Status::Ok);
+        assert_eq!(login("192.0.2.1").status(), Status::Ok);
+        assert_eq!(login("198.51.100.1").status(), Status::Ok);
+
+        let mut queue = Queue::new("default", conn.mq);
+        let job = queue.dequeue::<job::Job<String>>().ok().unwrap();
+
+        assert_eq!(job.kind, job::JobKind::SendSecurityNotificationEmail);
+        assert_eq!(job.args[0], user.uuid.to_string());
+        assert_eq!(job.args[1], "new_sign_in");
+        assert_eq!(job.args[3], "198.51.100.1");
+    });
+}
@@ -0,0 +1,103 @@
+use chrono::{Duration, Utc};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::model::message::LogLevel;
+
+use crate::run_test;
+
+#[test]
+fn test_show() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+        let auth = || {
+            Header::new("Authorization", format!("Bearer {}", token))
+        };
+
+        let ns = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&ns).insert(conn.db);
+        let now = Utc::now();
+        let _ = factory::message()
+            .stream(&stream)
+            .agent(&user)
+            .level(LogLevel::Error)
+            .between(now - Duration::hours(2), now - Duration::hours(1))
+            .count(3)
+            .insert(conn.db);
+        let _ = factory::message()
+            .stream(&stream)
+            .agent(&user)
+            .level(LogLevel::Warning)
+            .insert(conn.db);
+
+        // without any token
+        let res = client.get(format!("/v1/badge/{}.svg", ns.uuid)).dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+
+        let res = client
+            .get(format!("/v1/badge/{}.svg?token=invalid", ns.uuid))
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+
+        let mut res = client
+            .get(format!("/v1/namespace/hget/{}/badge", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["badge"]["public"].as_bool(), Some(false));
+        let path = result["badge"]["path"].as_str().unwrap();
+
+        let mut res = client.get(format!("/v1{}", path)).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::SVG));
+
+        let body = res.body_string().unwrap();
+        assert!(body.contains("<title>errors (24h): 3</title>"));
+
+        let res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth())
+            .body(r#"{"badge_public": true}"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let res = client.get(format!("/v1/badge/{}.svg", ns.uuid)).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        // not a badge
+        let res = client.get(format!("/v1/badge/{}", ns.uuid)).dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
@@ -0,0 +1,341 @@
+use diesel::{self, prelude::*};
+use chrono::{Utc, TimeZone};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use eloquentlog_console_api::clock;
+use eloquentlog_console_api::job;
+use eloquentlog_console_api::model;
+use eloquentlog_console_api::model::bulk_operation::{
+    BulkOperation, BulkOperationState,
+};
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::model::incident_message::IncidentMessage;
+
+use crate::{run_test, MEMBERSHIPS, NAMESPACES, STREAMS};
+
+#[test]
+fn test_count_with_invalid_filter() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let s = STREAMS.get("oswald's stream").unwrap();
+
+        let mut res = client
+            .post(format!("/v1/bulk_operation/{}/count/{}", ns.uuid, s.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"filter": "level:unknown"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["field_errors"][0]["field"], "filter");
+        assert_eq!(
+            result["field_errors"][0]["messages"][0],
+            "Invalid value: level:unknown"
+        );
+    });
+}
+
+#[test]
+fn test_count_and_append() {
+    run_test(|client, conn, config, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let stream_id = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .returning(model::stream::streams::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let dt = Utc.ymd(2019, 8, 7).and_hms(6, 5, 4);
+        let messages = vec![
+            ("1", model::message::LogLevel::Error, "Connection timeout"),
+            ("2", model::message::LogLevel::Error, "Read timeout"),
+            ("3", model::message::LogLevel::Information, "Request timeout"),
+        ];
+        for (id, level, title) in messages {
+            let m = model::message::Message {
+                id: format!("01DHNAWKM0000000000000000{}", id),
+                agent_id: user.id,
+                agent_type: model::message::AgentType::Person,
+                stream_id,
+                code: None,
+                lang: "en".to_string(),
+                level,
+                format: model::message::LogFormat::TOML,
+                title: title.to_string(),
+                content: None,
+                created_at: dt.naive_utc(),
+                updated_at: dt.naive_utc(),
+                tags: vec![],
+                deleted_at: None,
+                acknowledged_at: None,
+                resolved_at: None,
+                body_id: None,
+                occurrences_count: 1,
+                trace_id: None,
+                span_id: None,
+                hostname: None,
+                service: None,
+                environment: None,
+            };
+            let _ = diesel::insert_into(model::message::messages::table)
+                .values(&m)
+                .execute(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", m));
+        }
+
+        let mut res = client
+            .post(format!(
+                "/v1/bulk_operation/{}/count/{}",
+                namespace.uuid, s.uuid
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"filter": "level:error timeout"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.body_string().unwrap(), r#"{"count":2}"#);
+
+        let mut res = client
+            .post(format!(
+                "/v1/bulk_operation/{}/append/{}",
+                namespace.uuid, s.uuid
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(
+                r#"{
+                    "filter": "level:error timeout",
+                    "action": "add_tag",
+                    "argument": "outage"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Accepted);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["bulk_operation"]["state"], "pending");
+        assert_eq!(result["bulk_operation"]["matched_count"], 2);
+        let uuid = result["bulk_operation"]["uuid"].as_str().unwrap();
+
+        let job = job::Job::<String> {
+            kind: job::JobKind::ApplyBulkOperation,
+            args: vec![uuid.to_string()],
+        };
+        job.invoke(conn.db, config, &clock::SystemClock, logger);
+
+        let mut res = client
+            .get(format!("/v1/bulk_operation/hget/{}", uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["bulk_operation"]["state"], "finished");
+        assert_eq!(result["bulk_operation"]["affected_count"], 2);
+
+        let tags = model::message::messages::table
+            .select(model::message::messages::tags)
+            .order(model::message::messages::id)
+            .load::<Vec<String>>(conn.db)
+            .unwrap();
+        assert_eq!(
+            tags,
+            vec![vec!["outage".to_string()], vec!["outage".to_string()], vec![]]
+        );
+    });
+}
+
+#[test]
+fn test_append_assign_incident() {
+    run_test(|client, conn, config, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let namespace = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&namespace).insert(conn.db);
+        let _ = factory::message()
+            .stream(&stream)
+            .level(model::message::LogLevel::Error)
+            .count(2)
+            .insert(conn.db);
+
+        let other = factory::namespace().with_owner(&user).insert(conn.db);
+        let mut incidents = vec![];
+        for n in &[&namespace, &other] {
+            let incident = model::incident::NewIncident {
+                namespace_id: n.id,
+                title: "Outage".to_string(),
+                rule: None,
+            };
+            incidents.push(
+                model::incident::Incident::insert(&incident, conn.db, logger)
+                    .unwrap(),
+            );
+        }
+
+        // the one of the other namespace is not assigned
+        let mut states = vec![];
+        for incident in incidents.iter().rev() {
+            let mut res = client
+                .post(format!(
+                    "/v1/bulk_operation/{}/append/{}",
+                    namespace.uuid, stream.uuid
+                ))
+                .header(ContentType::JSON)
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", token),
+                ))
+                .body(format!(
+                    r#"{{
+                        "filter": "level:error",
+                        "action": "assign_incident",
+                        "argument": "{}"
+                    }}"#,
+                    incident.uuid,
+                ))
+                .dispatch();
+
+            assert_eq!(res.status(), Status::Accepted);
+            let body = res.body_string().unwrap();
+            let result: Value = serde_json::from_str(&body).unwrap();
+            let uuid = result["bulk_operation"]["uuid"].as_str().unwrap();
+
+            let job = job::Job::<String> {
+                kind: job::JobKind::ApplyBulkOperation,
+                args: vec![uuid.to_string()],
+            };
+            job.invoke(conn.db, config, &clock::SystemClock, logger);
+
+            let operation =
+                BulkOperation::find_by_uuid(uuid, conn.db, logger).unwrap();
+            states.push((operation.state, operation.affected_count));
+        }
+        assert_eq!(
+            states,
+            vec![
+                (BulkOperationState::Failed, 0),
+                (BulkOperationState::Finished, 2),
+            ]
+        );
+
+        for (incident, count) in incidents.iter().zip(&[2, 0]) {
+            let links = IncidentMessage::find_all_by_incident_id(
+                incident.id,
+                conn.db,
+                logger,
+            )
+            .unwrap();
+            assert_eq!(links.len(), *count);
+        }
+    });
+}
@@ -0,0 +1,138 @@
+use diesel::{self, prelude::*};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::model;
+
+use crate::{run_test, MEMBERSHIPS, NAMESPACES};
+
+#[test]
+fn test_preview_and_append() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        // unknown variable
+        let mut res = client
+            .post(format!("/v1/channel/{}/preview", namespace.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(
+                r#"{
+                    "kind": "slack",
+                    "template": "{\"text\": \"{{user.email}}\"}"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["field_errors"][0]["field"], "template");
+
+        let template =
+            r#"{\"text\": \"{{namespace.name}}: {{message.title}}\"}"#;
+        let mut res = client
+            .post(format!("/v1/channel/{}/preview", namespace.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(format!(
+                r#"{{
+                    "kind": "slack",
+                    "template": "{}"
+                }}"#,
+                template
+            ))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let payload: Value =
+            serde_json::from_str(result["payload"].as_str().unwrap()).unwrap();
+        assert_eq!(payload["text"], "piano: Connection \"db\" timed out");
+
+        let mut res = client
+            .post(format!("/v1/channel/{}/append", namespace.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(format!(
+                r#"{{
+                    "kind": "slack",
+                    "name": "alerts",
+                    "url": "https://hooks.slack.com/services/T0/B0/X",
+                    "template": "{}"
+                }}"#,
+                template
+            ))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["channel"]["kind"], "slack");
+        assert_eq!(result["channel"]["name"], "alerts");
+
+        let mut res = client
+            .get(format!("/v1/channel/{}/hgetall", namespace.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 1);
+        assert_eq!(
+            result[0]["channel"]["url"],
+            "https://hooks.slack.com/services/T0/B0/X"
+        );
+    });
+}
@@ -0,0 +1,65 @@
+use std::env;
+use std::net::SocketAddr;
+
+use rocket::http::{ContentType, Status};
+use serde_json::Value;
+
+use crate::{admin_token, run_test};
+
+fn localhost() -> SocketAddr {
+    "127.0.0.1:8000".parse().unwrap()
+}
+
+#[test]
+fn test_config_reload_from_remote() {
+    run_test(|client, _, _, _| {
+        let res = client
+            .post("/_/config/reload")
+            .remote("192.0.2.1:8000".parse().unwrap())
+            .header(admin_token())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_config_reload() {
+    run_test(|client, _, config, _| {
+        let key = "TEST_RATE_LIMIT_WAITLIST_PER_MINUTE";
+        let origin = env::var(key);
+        env::set_var(key, "1");
+
+        let mut res = client
+            .post("/_/config/reload")
+            .remote(localhost())
+            .header(admin_token())
+            .dispatch();
+
+        match origin {
+            Ok(v) => env::set_var(key, v),
+            Err(_) => env::remove_var(key),
+        }
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["config"]["rate_limit_waitlist_per_minute"], 1);
+        assert_eq!(
+            result["config"]["rate_limit_api_per_minute"],
+            config.rate_limit_api_per_minute
+        );
+
+        // the new limit is applied without restart
+        let join = |email: &str| {
+            client
+                .post("/_/waitlist")
+                .header(ContentType::JSON)
+                .body(format!(r#"{{"email": "{}"}}"#, email))
+                .dispatch()
+                .status()
+        };
+        assert_eq!(join("johnny@example.org"), Status::Ok);
+        assert_eq!(join("tommy@example.org"), Status::TooManyRequests);
+    });
+}
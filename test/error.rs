@@ -1,6 +1,8 @@
-use rocket::http::Status;
+use rocket::http::{Header, Status};
+use serde_json::Value;
+use uuid::Uuid;
 
-use crate::run_test;
+use crate::{admin_token, run_test};
 
 #[test]
 fn test_404_not_found() {
@@ -22,3 +24,37 @@ fn test_404_not_found() {
             .contains("'/_/unknown-path' is not found"));
     });
 }
+
+#[test]
+fn test_error_format() {
+    // by the catcher (a request guard fails)
+    run_test(|client, _, _, _| {
+        let mut res = client
+            .get("/v1/namespace/hgetall")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", "Bearer invalid"))
+            .dispatch();
+        assert_eq!(res.status(), Status::Unauthorized);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["code"], "unauthorized");
+        assert_eq!(result["message"], "The request is not allowed");
+        assert_eq!(result["field_errors"], serde_json::json!([]));
+    });
+
+    // by the route (without any body)
+    run_test(|client, _, _, _| {
+        let mut res = client
+            .get(format!("/_/stream/{}/buffer", Uuid::new_v4()))
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["code"], "not_found");
+        assert_eq!(result["message"], "The resource is not found");
+    });
+}
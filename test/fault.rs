@@ -0,0 +1,91 @@
+use std::net::SocketAddr;
+
+use chrono::Utc;
+use rocket::http::{ContentType, Status};
+use serde_json::Value;
+
+use crate::{admin_token, run_test};
+
+fn localhost() -> SocketAddr {
+    "127.0.0.1:8000".parse().unwrap()
+}
+
+#[test]
+fn test_inject_not_from_localhost() {
+    run_test(|client, _, _, _| {
+        let res = client
+            .post("/_/fault")
+            .header(ContentType::JSON)
+            .remote("192.0.2.1:8000".parse().unwrap())
+            .header(admin_token())
+            .body(r#"{"routes": ["/_/health"]}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+
+        let res = client.get("/_/health").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    });
+}
+
+#[test]
+fn test_inject_route_fault() {
+    run_test(|client, _, _, _| {
+        let mut res = client
+            .post("/_/fault")
+            .header(ContentType::JSON)
+            .remote(localhost())
+            .header(admin_token())
+            .body(r#"{"routes": ["/_/health"]}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["fault"]["routes"][0], "/_/health");
+
+        let res = client.get("/_/health").dispatch();
+        assert_eq!(res.status(), Status::InternalServerError);
+
+        let res = client
+            .delete("/_/fault")
+            .remote(localhost())
+            .header(admin_token())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let res = client.get("/_/health").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    });
+}
+
+#[test]
+fn test_inject_redis_failures() {
+    run_test(|client, conn, _, _| {
+        let _: i64 = redis::cmd("ZADD")
+            .arg("wh-workers")
+            .arg(Utc::now().timestamp())
+            .arg("worker-1")
+            .query(conn.mq)
+            .unwrap();
+
+        let res = client
+            .post("/_/fault")
+            .header(ContentType::JSON)
+            .remote(localhost())
+            .header(admin_token())
+            .body(r#"{"redis_failures": 1}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        // only the next one fails
+        let res = client.get("/_/readyz").dispatch();
+        assert_eq!(res.status(), Status::ServiceUnavailable);
+
+        let res = client.get("/_/readyz").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    });
+}
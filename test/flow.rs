@@ -0,0 +1,174 @@
+use diesel::{self, prelude::*};
+use fourche::queue::Queue;
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::job;
+use eloquentlog_console_api::model;
+
+use crate::run_test;
+
+// registration -> activation (by the token in the queued email job) -> login
+// -> namespace -> ingestion -> search
+#[test]
+fn test_activate_login_ingest_and_search() {
+    run_test(|client, conn, _, logger| {
+        let email = "hennry@example.org";
+        let password = "pa$$w0rD";
+
+        let _ = client
+            .head("/_/register")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let res = client
+            .post("/_/register")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                  "email": "{}",
+                  "username": "hennry",
+                  "password": "{}"
+                }}"#,
+                &email, &password,
+            ))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        // the worker would send this as an email
+        let mut queue = Queue::new("default", conn.mq);
+        let job = queue.dequeue::<job::Job<String>>().ok().unwrap();
+        assert_eq!(job.kind, job::JobKind::SendUserActivationEmail);
+
+        let session_id = job.args[1].to_string();
+        let token = job.args[2].to_string();
+
+        let res = client
+            .patch(format!("/_/activate/{}", session_id))
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                  "username": "{}",
+                  "password": "{}"
+                }}"#,
+                &email, &password,
+            ))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap().to_string();
+        let authorization = format!("Bearer {}", token);
+
+        let mut res = client
+            .post("/v1/namespace/hset")
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", authorization.clone()))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(r#"{"name": "piano", "description": "flow"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let namespace_key = result["namespace"]["uuid"].as_str().unwrap();
+
+        let user =
+            model::user::User::find_by_email(email, conn.db, logger).unwrap();
+        let namespace = model::namespace::Namespace::find_by_uuid(
+            namespace_key,
+            &user,
+            conn.db,
+            logger,
+        )
+        .unwrap();
+
+        // NOTE:
+        // There is no API for streams yet.
+        let stream = factory::stream().namespace(&namespace).insert(conn.db);
+        let stream_id = stream.id;
+        let stream_slug = stream.uuid.to_string();
+
+        let batch = vec![
+            ("error", "Connection timeout", "<db> is not reachable"),
+            ("warning", "Slow query", "SELECT took 3s"),
+            ("information", "Deployed", "v1.2.3"),
+        ];
+        for (level, title, content) in &batch {
+            let res = client
+                .post(format!(
+                    "/v1/message/{}/append/{}",
+                    namespace_key, stream_slug
+                ))
+                .header(ContentType::JSON)
+                .header(Header::new("Authorization", authorization.clone()))
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .body(
+                    serde_json::json!({
+                        "agent_id": user.id,
+                        "agent_type": "person",
+                        "stream_id": stream_id,
+                        "format": "toml",
+                        "level": level,
+                        "title": title,
+                        "content": content,
+                    })
+                    .to_string(),
+                )
+                .dispatch();
+
+            assert_eq!(res.status(), Status::Ok);
+        }
+
+        let count = model::message::messages::table
+            .filter(model::message::messages::stream_id.eq(stream_id))
+            .count()
+            .get_result::<i64>(conn.db)
+            .unwrap();
+        assert_eq!(count, batch.len() as i64);
+
+        let mut res = client
+            .get(format!(
+                "/v1/message/{}/search/{}/0/9?q=timeout",
+                namespace_key, stream_slug
+            ))
+            .header(Header::new("Authorization", authorization))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let messages = result.as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["message"]["title"], "Connection timeout");
+        assert_eq!(messages[0]["message"]["level"], "Error");
+    });
+}
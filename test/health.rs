@@ -1,7 +1,10 @@
+use chrono::Utc;
 use rocket::http::{ContentType, Header, Status};
 use serde_json::Value;
 
-use crate::{run_test, load_user, make_raw_password, USERS};
+use eloquentlog_console_api::factory;
+
+use crate::run_test;
 
 #[test]
 fn test_health_check() {
@@ -16,12 +19,79 @@ fn test_health_check() {
     });
 }
 
+#[test]
+fn test_health_check_with_request_id() {
+    run_test(|client, _, _, _| {
+        let res = client
+            .get("/_/health")
+            .header(Header::new("X-Request-Id", "req-01.a_b"))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.headers().get_one("X-Request-Id"), Some("req-01.a_b"));
+
+        // an invalid one is replaced
+        let res = client
+            .get("/_/health")
+            .header(Header::new("X-Request-Id", "req 01"))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let id = res.headers().get_one("X-Request-Id").unwrap();
+        assert_ne!(id, "req 01");
+        assert_eq!(id.len(), 32);
+    });
+}
+
+#[test]
+fn test_readyz_without_workers() {
+    run_test(|client, _, _, _| {
+        let mut res = client.get("/_/readyz").dispatch();
+
+        assert_eq!(res.status(), Status::ServiceUnavailable);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["worker"]["alive"], 0);
+        assert!(result["worker"]["last_beat_at"].is_null());
+    });
+}
+
+#[test]
+fn test_readyz_with_a_heartbeat() {
+    run_test(|client, conn, _, _| {
+        let now = Utc::now().timestamp();
+        let _: i64 = redis::cmd("ZADD")
+            .arg("wh-workers")
+            .arg(now)
+            .arg("worker-1")
+            .query(conn.mq)
+            .unwrap();
+        let _: i64 = redis::cmd("HSET")
+            .arg("wh-jobs")
+            .arg("FlushNamespaceUsages")
+            .arg(now)
+            .query(conn.mq)
+            .unwrap();
+
+        let mut res = client.get("/_/readyz").dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["worker"]["alive"], 1);
+        assert_eq!(result["worker"]["last_beat_at"], now);
+        assert_eq!(result["worker"]["queue_lag"], 0);
+        assert_eq!(result["worker"]["jobs"]["FlushNamespaceUsages"], now);
+    });
+}
+
 #[test]
 fn test_v1_health_check() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, &conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -0,0 +1,188 @@
+use chrono::{Duration, Utc};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::model;
+
+use crate::run_test;
+
+#[test]
+fn test_append_attach_and_resolve() {
+    run_test(|client, conn, _, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+        let auth = || {
+            Header::new("Authorization", format!("Bearer {}", token))
+        };
+
+        let ns = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&ns).insert(conn.db);
+        let now = Utc::now();
+        let ids = factory::message()
+            .stream(&stream)
+            .agent(&user)
+            .between(now - Duration::minutes(10), now - Duration::minutes(5))
+            .count(2)
+            .insert(conn.db);
+
+        // a message in another namespace
+        let other = factory::namespace().insert(conn.db);
+        let other_stream = factory::stream().namespace(&other).insert(conn.db);
+        let other_ids = factory::message()
+            .stream(&other_stream)
+            .agent(&user)
+            .insert(conn.db);
+
+        let mut res = client
+            .post(format!("/v1/incident/{}/append", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth())
+            .body(format!(
+                r#"{{"title": " Database outage ", "message_ids": ["{}"]}}"#,
+                ids[0]
+            ))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let uuid = result["incident"]["uuid"].as_str().unwrap().to_string();
+        assert_eq!(result["incident"]["title"], "Database outage");
+        assert_eq!(result["incident"]["state"], "open");
+        assert!(result["incident"]["rule"].is_null());
+
+        let attach = |message_ids: &[String]| {
+            let values: Vec<String> =
+                message_ids.iter().map(|id| format!(r#""{}""#, id)).collect();
+            client
+                .post(format!("/v1/incident/{}/attach/{}", ns.uuid, uuid))
+                .header(ContentType::JSON)
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .header(auth())
+                .body(format!(r#"{{"message_ids": [{}]}}"#, values.join(",")))
+                .dispatch()
+        };
+
+        let res = attach(&other_ids);
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        // the first one is already attached
+        let mut res = attach(&ids);
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["count"], 1);
+
+        let mut res = client
+            .patch(format!("/v1/incident/{}/hset/{}", ns.uuid, uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth())
+            .body(r#"{"state": "resolved"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["incident"]["state"], "resolved");
+        assert!(!result["incident"]["resolved_at"].is_null());
+
+        let mut res = client
+            .get(format!("/v1/incident/{}/timeline/{}", ns.uuid, uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let kinds: Vec<&str> = result["timeline"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["message", "message", "opened", "resolved"]);
+        assert_eq!(result["timeline"][0]["message"]["id"], ids[0].as_str());
+
+        let incidents = model::incident::Incident::find_all_by_namespace_id(
+            ns.id, conn.db, logger,
+        )
+        .unwrap();
+        assert_eq!(incidents.len(), 1);
+    });
+}
+
+#[test]
+fn test_hget_by_non_member() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = factory::namespace().insert(conn.db);
+
+        let res = client
+            .get(format!("/v1/incident/{}/hgetall", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
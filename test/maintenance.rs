@@ -0,0 +1,109 @@
+use std::net::SocketAddr;
+
+use rocket::http::{ContentType, Status};
+use serde_json::Value;
+
+use crate::{admin_token, run_test};
+
+fn localhost() -> SocketAddr {
+    "127.0.0.1:8000".parse().unwrap()
+}
+
+#[test]
+fn test_maintenance_from_remote() {
+    run_test(|client, _, _, _| {
+        let res = client
+            .put("/_/maintenance")
+            .header(ContentType::JSON)
+            .remote("192.0.2.1:8000".parse().unwrap())
+            .header(admin_token())
+            .body(r#"{"enabled": true}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+
+        let res = client.get("/v1/health").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    });
+}
+
+#[test]
+fn test_maintenance_with_invalid_retry_after() {
+    run_test(|client, _, _, _| {
+        let mut res = client
+            .put("/_/maintenance")
+            .header(ContentType::JSON)
+            .remote(localhost())
+            .header(admin_token())
+            .body(r#"{"enabled": true, "retry_after": 0}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["field_errors"][0]["field"], "retry_after");
+    });
+}
+
+#[test]
+fn test_maintenance() {
+    run_test(|client, _, _, _| {
+        let mut res = client
+            .put("/_/maintenance")
+            .header(ContentType::JSON)
+            .remote(localhost())
+            .header(admin_token())
+            .body(r#"{"enabled": true, "retry_after": 1800}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["maintenance"]["enabled"].as_bool(), Some(true));
+        assert_eq!(result["maintenance"]["retry_after"], 1800);
+
+        // the other routes are unavailable
+        for path in &["/_/login", "/v1/namespace/hgetall"] {
+            let mut res = client.get(*path).dispatch();
+            assert_eq!(res.status(), Status::ServiceUnavailable);
+            assert_eq!(res.headers().get_one("Retry-After"), Some("1800"));
+
+            let body = res.body_string().unwrap();
+            let result: Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(result["code"], "maintenance");
+        }
+
+        // but health checks keep answering
+        for path in &["/_/health", "/v1/health"] {
+            let res = client.get(*path).dispatch();
+            assert_eq!(res.status(), Status::Ok);
+        }
+
+        let mut res = client
+            .get("/_/maintenance")
+            .remote(localhost())
+            .header(admin_token())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["maintenance"]["enabled"].as_bool(), Some(true));
+
+        let res = client
+            .put("/_/maintenance")
+            .header(ContentType::JSON)
+            .remote(localhost())
+            .header(admin_token())
+            .body(r#"{"enabled": false}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let res = client.get("/v1/namespace/hgetall").dispatch();
+        assert_ne!(res.status(), Status::ServiceUnavailable);
+    });
+}
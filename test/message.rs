@@ -1,21 +1,28 @@
+use std::io::Write;
+
 use diesel::{self, prelude::*};
 use chrono::{Utc, TimeZone};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use fourche::queue::Queue;
+use prost::Message as _;
 use rocket::http::{ContentType, Header, Status};
 use serde_json::Value;
 use uuid::Uuid;
 
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::id;
+use eloquentlog_console_api::job;
 use eloquentlog_console_api::model;
+use eloquentlog_console_api::model::token::Claims;
 
-use crate::{
-    minify, run_test, load_user, make_raw_password, NAMESPACES, STREAMS, USERS,
-};
+use crate::{minify, run_test, MEMBERSHIPS, NAMESPACES, STREAMS};
 
 #[test]
 fn test_lrange_no_message() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -41,29 +48,120 @@ fn test_lrange_no_message() {
         let result: Value = serde_json::from_str(&body).unwrap();
         let token = result["token"].as_str().unwrap();
 
-        let namespace_key = "key";
-        let stream_slug = "slug";
+        let namespace = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&namespace).insert(conn.db);
 
         let mut res = client
             .get(format!(
                 "/v1/message/{}/lrange/{}/0/2",
-                namespace_key, stream_slug
+                namespace.uuid, stream.uuid
             ))
             .header(Header::new("X-Requested-With", "XMLHttpRequest"))
             .header(Header::new("Authorization", format!("Bearer {}", token)))
             .dispatch();
 
         assert_eq!(res.status(), Status::Ok);
-        assert!(res.body_string().unwrap().contains("[]"));
+        assert!(res.body_string().unwrap().contains(r#""data":[]"#));
+
+        // a stream of another namespace
+        let other = factory::namespace().insert(conn.db);
+        let other_stream = factory::stream().namespace(&other).insert(conn.db);
+        for (namespace_key, stream_slug) in &[
+            (namespace.uuid, other_stream.uuid),
+            (other.uuid, other_stream.uuid),
+        ] {
+            let res = client
+                .get(format!(
+                    "/v1/message/{}/lrange/{}/0/2",
+                    namespace_key, stream_slug
+                ))
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", token),
+                ))
+                .dispatch();
+
+            assert_eq!(res.status(), Status::NotFound);
+        }
+    });
+}
+
+#[test]
+fn test_lrange_versioned() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let namespace = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&namespace).insert(conn.db);
+
+        // the versioned mount point and the unversioned one (as v1)
+        for path in &["/_api/v1", "/v1"] {
+            let mut res = client
+                .get(format!(
+                    "{}/message/{}/lrange/{}/0/2",
+                    path, namespace.uuid, stream.uuid
+                ))
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", token),
+                ))
+                .dispatch();
+
+            assert_eq!(res.status(), Status::Ok);
+            assert_eq!(res.headers().get_one("X-Api-Version"), Some("1"));
+            assert!(res.body_string().unwrap().contains(r#""data":[]"#));
+        }
+
+        // unsupported
+        let mut res = client
+            .get(format!(
+                "/_api/v1/message/{}/lrange/{}/0/2",
+                namespace.uuid, stream.uuid
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Api-Version", "2"))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotAcceptable);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["code"], "not_acceptable");
     });
 }
 
 #[test]
 fn test_lrange_messages() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -90,25 +188,38 @@ fn test_lrange_messages() {
         let token = result["token"].as_str().unwrap();
 
         let ns = NAMESPACES.get("piano").unwrap();
-        let namespace_id =
+        let namespace =
             diesel::insert_into(model::namespace::namespaces::table)
                 .values(ns)
-                .returning(model::namespace::namespaces::id)
-                .get_result::<i64>(conn.db)
+                .get_result::<model::namespace::Namespace>(conn.db)
                 .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
 
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
         let mut s = STREAMS.get("oswald's stream").unwrap().clone();
-        s.namespace_id = namespace_id;
+        s.namespace_id = namespace.id;
         let stream_id = diesel::insert_into(model::stream::streams::table)
             .values(&s)
             .returning(model::stream::streams::id)
             .get_result::<i64>(conn.db)
             .unwrap_or_else(|_| panic!("Error inserting: {}", s));
 
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = s.uuid.to_string();
+
         // 2019-08-07T06:05:04.333
         let dt = Utc.ymd(2019, 8, 7).and_hms_milli(6, 5, 4, 333);
         let m = model::message::Message {
-            id: 1,
+            id: "01DHNAWKYD0000000000000001".to_string(),
             agent_id: user.id,
             agent_type: model::message::AgentType::Person,
             stream_id,
@@ -120,17 +231,25 @@ fn test_lrange_messages() {
             content: None,
             created_at: dt.naive_utc(),
             updated_at: dt.naive_utc(),
+            tags: vec![],
+            deleted_at: None,
+            acknowledged_at: None,
+            resolved_at: None,
+            body_id: None,
+            occurrences_count: 1,
+            trace_id: None,
+            span_id: None,
+            hostname: None,
+            service: None,
+            environment: None,
         };
 
         let id = diesel::insert_into(model::message::messages::table)
             .values(&m)
             .returning(model::message::messages::id)
-            .get_result::<i64>(conn.db)
+            .get_result::<String>(conn.db)
             .unwrap_or_else(|_| panic!("Error inserting: {}", m));
 
-        let namespace_key = "key";
-        let stream_slug = "slug"; // FIXME
-
         let mut res = client
             .get(format!(
                 "/v1/message/{}/lrange/{}/0/2",
@@ -145,34 +264,223 @@ fn test_lrange_messages() {
         assert_eq!(
             res.body_string().unwrap(),
             minify(format!(
-                r#"[{{
+                r#"{{"data": [{{
 "message": {{
-  "agent_id": 1,
+  "acknowledged_at": null,
+  "agent_id": {},
   "agent_type": "Person",
   "code": null,
   "content": null,
   "created_at": "2019-08-07T06:05:04.333",
+  "environment": null,
   "format": "TOML",
-  "id": {},
+  "hostname": null,
+  "id": "{}",
   "lang": "en",
   "level": "Information",
+  "occurrences_count": 1,
+  "resolved_at": null,
+  "service": null,
+  "span_id": null,
   "stream_id": 1,
+  "tags": [],
   "title": "title",
+  "trace_id": null,
   "updated_at": "2019-08-07T06:05:04.333"
 }}
-}}]"#,
-                id,
+}}],
+"next_cursor": null,
+"total_estimate": 1
+}}"#,
+                user.id, id,
+            ))
+        );
+    });
+}
+
+#[test]
+fn test_search_messages() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let stream_id = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .returning(model::stream::streams::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = s.uuid.to_string();
+
+        let dt = Utc.ymd(2019, 8, 7).and_hms_milli(6, 5, 4, 333);
+        let m = model::message::Message {
+            id: "01DHNAWKYD0000000000000001".to_string(),
+            agent_id: user.id,
+            agent_type: model::message::AgentType::Person,
+            stream_id,
+            code: None,
+            lang: "en".to_string(),
+            level: model::message::LogLevel::Error,
+            format: model::message::LogFormat::TOML,
+            title: "Connection timeout".to_string(),
+            content: Some("<db> is not reachable".to_string()),
+            created_at: dt.naive_utc(),
+            updated_at: dt.naive_utc(),
+            tags: vec![],
+            deleted_at: None,
+            acknowledged_at: None,
+            resolved_at: None,
+            body_id: None,
+            occurrences_count: 1,
+            trace_id: None,
+            span_id: None,
+            hostname: None,
+            service: None,
+            environment: None,
+        };
+
+        let _ = diesel::insert_into(model::message::messages::table)
+            .values(&m)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", m));
+
+        let mut res = client
+            .get(format!(
+                "/v1/message/{}/search/{}/0/9?q=TIMEOUT%20db",
+                namespace_key, stream_slug
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 1);
+        assert_eq!(
+            result[0]["highlights"]["title"],
+            serde_json::json!({
+                "fragment": "Connection <em>timeout</em>",
+                "offsets": [[11, 18]],
+            })
+        );
+        assert_eq!(
+            result[0]["highlights"]["content"],
+            serde_json::json!({
+                "fragment": "&lt;<em>db</em>&gt; is not reachable",
+                "offsets": [[1, 3]],
+            })
+        );
+
+        // case sensitive
+        let mut res = client
+            .get(format!(
+                "/v1/message/{}/search/{}/0/9?q=TIMEOUT&case_sensitive=true",
+                namespace_key, stream_slug
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.body_string().unwrap(), "[]");
+
+        // unaccent (é)
+        let mut res = client
+            .get(format!(
+                "/v1/message/{}/search/{}/0/9?q=r%C3%A9achable&unaccent=true",
+                namespace_key, stream_slug
             ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 1);
+        assert_eq!(
+            result[0]["highlights"]["content"]["offsets"],
+            serde_json::json!([[12, 21]])
         );
+
+        // no match
+        let mut res = client
+            .get(format!(
+                "/v1/message/{}/search/{}/0/9?q=unknown",
+                namespace_key, stream_slug
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.body_string().unwrap(), "[]");
+
+        let res = client
+            .get(format!(
+                "/v1/message/{}/search/{}/0/9?q=%20",
+                namespace_key, stream_slug
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
     });
 }
 
 #[test]
 fn test_append_with_validation_errors() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -198,8 +506,10 @@ fn test_append_with_validation_errors() {
         let result: Value = serde_json::from_str(&body).unwrap();
         let token = result["token"].as_str().unwrap();
 
-        let namespace_key = "key";
-        let stream_slug = "slug";
+        let namespace = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&namespace).insert(conn.db);
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = stream.uuid.to_string();
 
         let mut res = client
             .post(format!(
@@ -222,16 +532,15 @@ fn test_append_with_validation_errors() {
             .dispatch();
 
         assert_eq!(res.status(), Status::UnprocessableEntity);
-        assert!(res.body_string().unwrap().contains("errors"));
+        assert!(res.body_string().unwrap().contains("field_errors"));
     });
 }
 
 #[test]
 fn test_append() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -257,25 +566,35 @@ fn test_append() {
         let result: Value = serde_json::from_str(&body).unwrap();
         let token = result["token"].as_str().unwrap();
 
-        let namespace_key = "key";
-        let stream_slug = "slug";
-
         let ns = NAMESPACES.get("piano").unwrap();
-        let namespace_id =
+        let namespace =
             diesel::insert_into(model::namespace::namespaces::table)
                 .values(ns)
-                .returning(model::namespace::namespaces::id)
-                .get_result::<i64>(conn.db)
+                .get_result::<model::namespace::Namespace>(conn.db)
                 .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
 
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
         let mut s = STREAMS.get("oswald's stream").unwrap().clone();
-        s.namespace_id = namespace_id;
+        s.namespace_id = namespace.id;
         let stream_uuid = diesel::insert_into(model::stream::streams::table)
             .values(&s)
             .returning(model::stream::streams::uuid)
             .get_result::<Uuid>(conn.db)
             .unwrap_or_else(|_| panic!("Error inserting: {}", s));
 
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = s.uuid.to_string();
+
         let mut res = client
             .post(format!(
                 "/v1/message/{}/append/{}",
@@ -300,6 +619,1685 @@ fn test_append() {
             .dispatch();
 
         assert_eq!(res.status(), Status::Ok);
-        assert!(res.body_string().unwrap().contains("id"));
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let id = result["message"]["id"].as_str().unwrap();
+        assert!(id::is_ulid(id));
+    });
+}
+
+#[test]
+fn test_append_below_min_level() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let mut ns = NAMESPACES.get("piano").unwrap().clone();
+        ns.min_level = Some(model::message::LogLevel::Warning);
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(&ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let _ = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .returning(model::stream::streams::uuid)
+            .get_result::<Uuid>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let mut res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace.uuid, s.uuid
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(
+                r#"{
+                    "agent_id": 1,
+                    "stream_id": 1,
+                    "level": "debug",
+                    "title": "New message"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert!(result["message"]["id"].is_null());
+        assert_eq!(result["message"]["dropped"].as_bool(), Some(true));
+
+        let count = model::message::messages::table
+            .count()
+            .get_result::<i64>(conn.db)
+            .expect("Failed to count messages");
+        assert_eq!(count, 0);
+
+        let usage = model::namespace_usage::namespace_usages::table
+            .first::<model::namespace_usage::NamespaceUsage>(conn.db)
+            .expect("Failed to load usage");
+        assert_eq!(usage.dropped_messages_count, 1);
+    });
+}
+
+#[test]
+fn test_append_below_min_level_of_another_namespace() {
+    run_test(|client, conn, _, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let now = Utc::now().naive_utc();
+        let strict = factory::namespace()
+            .with_owner(&user)
+            .insert(conn.db)
+            .set_min_level(
+                Some(model::message::LogLevel::Warning),
+                now,
+                conn.db,
+                logger,
+            )
+            .unwrap();
+        let strict_stream =
+            factory::stream().namespace(&strict).insert(conn.db);
+        let lenient = factory::namespace().with_owner(&user).insert(conn.db);
+        let lenient_stream =
+            factory::stream().namespace(&lenient).insert(conn.db);
+
+        let data = r#"{
+            "agent_id": 1,
+            "stream_id": 1,
+            "level": "debug",
+            "title": "New message"
+        }"#;
+
+        // the level of the other namespace doesn't apply
+        let mut res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                lenient.uuid, lenient_stream.uuid
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(data)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert!(result["message"]["id"].is_string());
+        assert!(result["message"]["dropped"].is_null());
+
+        let mut res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                strict.uuid, strict_stream.uuid
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(data)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert!(result["message"]["id"].is_null());
+        assert_eq!(result["message"]["dropped"].as_bool(), Some(true));
+
+        let count = model::message::messages::table
+            .filter(model::message::messages::stream_id.eq(lenient_stream.id))
+            .count()
+            .get_result::<i64>(conn.db)
+            .expect("Failed to count messages");
+        assert_eq!(count, 1);
+
+        let count = model::message::messages::table
+            .filter(model::message::messages::stream_id.eq(strict_stream.id))
+            .count()
+            .get_result::<i64>(conn.db)
+            .expect("Failed to count messages");
+        assert_eq!(count, 0);
+    });
+}
+
+#[test]
+fn test_append_with_traceparent() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let _ = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let append = |traceparent: &str| {
+            client
+                .post(format!(
+                    "/v1/message/{}/append/{}",
+                    namespace.uuid, s.uuid
+                ))
+                .header(ContentType::JSON)
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", token),
+                ))
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .body(format!(
+                    r#"{{
+                        "agent_id": 1,
+                        "stream_id": 1,
+                        "title": "New message",
+                        "traceparent": "{}"
+                    }}"#,
+                    traceparent
+                ))
+                .dispatch()
+        };
+
+        let mut res = append("00-invalid-00f067aa0ba902b7-01");
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["field_errors"][0]["field"], "traceparent");
+
+        let res = append(&format!("00-{}-00f067aa0ba902b7-01", trace_id));
+        assert_eq!(res.status(), Status::Ok);
+
+        let mut res = client
+            .get(format!("/v1/message/{}/trace/{}", namespace.uuid, trace_id))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let messages = result.as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["message"]["trace_id"], trace_id);
+        assert_eq!(messages[0]["message"]["span_id"], "00f067aa0ba902b7");
+
+        let res = client
+            .get(format!(
+                "/v1/message/{}/trace/{}",
+                namespace.uuid,
+                trace_id.to_uppercase()
+            ))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_append_with_source() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let _ = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        for service in &["api", "worker", "api"] {
+            let res = client
+                .post(format!(
+                    "/v1/message/{}/append/{}",
+                    namespace.uuid, s.uuid
+                ))
+                .header(ContentType::JSON)
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", token),
+                ))
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .body(format!(
+                    r#"{{
+                        "agent_id": 1,
+                        "stream_id": 1,
+                        "title": "Connection timeout",
+                        "hostname": "web-1",
+                        "service": "{}"
+                    }}"#,
+                    service
+                ))
+                .dispatch();
+            assert_eq!(res.status(), Status::Ok);
+        }
+
+        let mut res = client
+            .get(format!(
+                "/v1/message/{}/lrange/{}/0/9?service=api",
+                namespace.uuid, s.uuid
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let messages = result["data"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["message"]["service"], "api");
+        assert_eq!(messages[0]["message"]["hostname"], "web-1");
+        assert!(messages[0]["message"]["environment"].is_null());
+
+        let mut res = client
+            .get(format!(
+                "/v1/message/{}/search/{}/0/9?q=timeout&facets=true",
+                namespace.uuid, s.uuid
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["messages"].as_array().unwrap().len(), 3);
+        assert_eq!(
+            result["facets"],
+            serde_json::json!({
+                "hostname": [{"value": "web-1", "count": 3}],
+                "service": [
+                    {"value": "api", "count": 2},
+                    {"value": "worker", "count": 1},
+                ],
+                "environment": [],
+            })
+        );
+    });
+}
+
+#[test]
+fn test_append_with_default_tags() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let mut ns = NAMESPACES.get("piano").unwrap().clone();
+        ns.default_tags = vec![
+            "environment=staging".to_string(),
+            "team=payments".to_string(),
+        ];
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(&ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let _ = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace.uuid, s.uuid
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(r#"{"agent_id": 1, "stream_id": 1, "title": "New message"}"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let mut res = client
+            .get(format!(
+                "/v1/message/{}/lrange/{}/0/9",
+                namespace.uuid, s.uuid
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let messages = result["data"].as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0]["message"]["tags"],
+            serde_json::json!(["environment=staging", "team=payments"])
+        );
+    });
+}
+
+#[test]
+fn test_append_with_default_tags_of_each_namespace() {
+    run_test(|client, conn, _, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let now = Utc::now().naive_utc();
+        let mut targets = vec![];
+        for tags in &[["team=payments"], ["team=search"]] {
+            let namespace = factory::namespace()
+                .with_owner(&user)
+                .insert(conn.db)
+                .set_default_tags(
+                    tags.iter().map(|t| t.to_string()).collect(),
+                    now,
+                    conn.db,
+                    logger,
+                )
+                .unwrap();
+            let stream =
+                factory::stream().namespace(&namespace).insert(conn.db);
+            targets.push((namespace, stream, tags[0]));
+        }
+
+        let data = r#"{"agent_id": 1, "stream_id": 1, "title": "New message"}"#;
+        for (namespace, stream, _) in &targets {
+            let res = client
+                .post(format!(
+                    "/v1/message/{}/append/{}",
+                    namespace.uuid, stream.uuid
+                ))
+                .header(ContentType::JSON)
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", token),
+                ))
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .body(data)
+                .dispatch();
+            assert_eq!(res.status(), Status::Ok);
+        }
+
+        for (namespace, stream, tag) in &targets {
+            let mut res = client
+                .get(format!(
+                    "/v1/message/{}/lrange/{}/0/9",
+                    namespace.uuid, stream.uuid
+                ))
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", token),
+                ))
+                .dispatch();
+
+            assert_eq!(res.status(), Status::Ok);
+            let body = res.body_string().unwrap();
+            let result: Value = serde_json::from_str(&body).unwrap();
+            let messages = result["data"].as_array().unwrap();
+            assert_eq!(messages.len(), 1);
+            assert_eq!(
+                messages[0]["message"]["tags"],
+                serde_json::json!([tag])
+            );
+        }
+    });
+}
+
+#[test]
+fn test_append_deduplicated_only_in_its_namespace() {
+    run_test(|client, conn, _, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let now = Utc::now().naive_utc();
+        let mut targets = vec![];
+        for deduplicates in &[true, false] {
+            let namespace = factory::namespace()
+                .with_owner(&user)
+                .insert(conn.db)
+                .set_deduplicates_messages(*deduplicates, now, conn.db, logger)
+                .unwrap();
+            let stream =
+                factory::stream().namespace(&namespace).insert(conn.db);
+            targets.push((namespace, stream, *deduplicates));
+        }
+
+        let data = r#"{
+            "agent_id": 1,
+            "stream_id": 1,
+            "title": "New message",
+            "content": "Hello, world!"
+        }"#;
+        for (namespace, stream, _) in &targets {
+            let res = client
+                .post(format!(
+                    "/v1/message/{}/append/{}",
+                    namespace.uuid, stream.uuid
+                ))
+                .header(ContentType::JSON)
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", token),
+                ))
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .body(data)
+                .dispatch();
+            assert_eq!(res.status(), Status::Ok);
+        }
+
+        for (_, stream, deduplicates) in &targets {
+            let m = model::message::messages::table
+                .filter(model::message::messages::stream_id.eq(stream.id))
+                .first::<model::message::Message>(conn.db)
+                .expect("Failed to load message");
+            assert_eq!(m.body_id.is_some(), *deduplicates);
+            assert_eq!(m.content.is_none(), *deduplicates);
+        }
+    });
+}
+
+#[test]
+fn test_append_gzip() {
+    run_test(|client, conn, config, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let _ = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = s.uuid.to_string();
+
+        let gzip = |body: &[u8]| {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            e.write_all(body).unwrap();
+            e.finish().unwrap()
+        };
+        let message = r#"{
+            "agent_id": 1,
+            "agent_type": "person",
+            "stream_id": 1,
+            "format": "toml",
+            "title": "New message",
+            "content": "Hello, world!"
+        }"#;
+
+        let mut res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("Content-Encoding", "gzip"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(gzip(message.as_bytes()))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert!(res.body_string().unwrap().contains("id"));
+
+        // unsupported encoding
+        let res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("Content-Encoding", "br"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(message)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnsupportedMediaType);
+
+        // zip bomb
+        let size = config.ingestion_max_decompressed_size as usize + 1;
+        let res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("Content-Encoding", "gzip"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(gzip(&vec![b' '; size]))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::PayloadTooLarge);
+    });
+}
+
+#[test]
+fn test_append_protobuf() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let _ = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = s.uuid.to_string();
+
+        let protobuf = ContentType::new("application", "x-protobuf");
+        let m = model::message::proto::NewMessage {
+            agent_id: 1,
+            agent_type: Some("person".to_string()),
+            stream_id: 1,
+            code: Some("200".to_string()),
+            format: Some("toml".to_string()),
+            title: Some("New message".to_string()),
+            content: Some("Hello, world!".to_string()),
+            ..Default::default()
+        };
+
+        let mut res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(protobuf.clone())
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(m.encode_to_vec())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert!(res.body_string().unwrap().contains("id"));
+
+        // validation errors
+        let m = model::message::proto::NewMessage {
+            title: None,
+            ..m
+        };
+        let res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(protobuf.clone())
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(m.encode_to_vec())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        // broken body
+        let res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(protobuf)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(&b"\xff\xff\xff"[..])
+            .dispatch();
+
+        assert_eq!(res.status(), Status::BadRequest);
+
+        // other media types don't match
+        let res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(ContentType::new("application", "octet-stream"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(m.encode_to_vec())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_append_over_quota() {
+    run_test(|client, conn, config, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let namespace = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&namespace).insert(conn.db);
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = stream.uuid.to_string();
+
+        // fill up today's quota
+        let key = format!(
+            "qt-{}-{}",
+            namespace_key,
+            Utc::now().naive_utc().date().format("%Y%m%d")
+        );
+        // the grace period has been over
+        redis::cmd("HSET")
+            .arg(&key)
+            .arg("messages")
+            .arg(config.quota_messages_per_day)
+            .arg("grace_until")
+            .arg(Utc::now().timestamp() - 1)
+            .execute(conn.ss);
+
+        let res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(
+                r#"{
+                    "agent_id": 1,
+                    "agent_type": "person",
+                    "stream_id": 1,
+                    "code": "200",
+                    "title": "New message",
+                    "content": "Hello, world!"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::TooManyRequests);
+        assert_eq!(
+            res.headers().get_one("X-Quota-Messages-Remaining"),
+            Some("0")
+        );
+        assert!(res.headers().get_one("X-Quota-Reset").is_some());
+    });
+}
+
+#[test]
+fn test_append_over_quota_in_grace_period() {
+    run_test(|client, conn, config, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let namespace = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&namespace).insert(conn.db);
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = stream.uuid.to_string();
+
+        // fill up today's quota
+        let key = format!(
+            "qt-{}-{}",
+            namespace_key,
+            Utc::now().naive_utc().date().format("%Y%m%d")
+        );
+        redis::cmd("HSET")
+            .arg(&key)
+            .arg("messages")
+            .arg(config.quota_messages_per_day)
+            .execute(conn.ss);
+
+        let res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(
+                r#"{
+                    "agent_id": 1,
+                    "agent_type": "person",
+                    "stream_id": 1,
+                    "code": "200",
+                    "title": "New message",
+                    "content": "Hello, world!"
+                }"#,
+            )
+            .dispatch();
+
+        assert_ne!(res.status(), Status::TooManyRequests);
+        assert!(res.headers().get_one("X-Quota-Grace-Until").is_some());
+
+        let mut queue = Queue::new("default", conn.mq);
+        let job = queue.dequeue::<job::Job<String>>().ok().unwrap();
+        assert_eq!(job.kind, job::JobKind::SendQuotaNotificationEmail);
+        assert_eq!(job.args[0], namespace_key);
+        assert_eq!(job.args[1], "100");
+    });
+}
+
+#[test]
+fn test_append_without_membership_does_not_consume_quota() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        // the user is not a member of it
+        let owner = factory::user().insert(conn.db);
+        let namespace = factory::namespace().with_owner(&owner).insert(conn.db);
+        let stream = factory::stream().namespace(&namespace).insert(conn.db);
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = stream.uuid.to_string();
+
+        let data = r#"{
+            "agent_id": 1,
+            "agent_type": "person",
+            "stream_id": 1,
+            "code": "200",
+            "title": "New message",
+            "content": "Hello, world!"
+        }"#;
+        let url =
+            format!("/v1/message/{}/append/{}", namespace_key, stream_slug);
+
+        let res = client
+            .post(url.clone())
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(data)
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+
+        let res = client
+            .post(url)
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(data)
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+        assert!(res.headers().get_one("X-Quota-Messages-Remaining").is_none());
+
+        let key = format!(
+            "qt-{}-{}",
+            namespace_key,
+            Utc::now().naive_utc().date().format("%Y%m%d")
+        );
+        let exists: bool =
+            redis::cmd("EXISTS").arg(&key).query(conn.ss).unwrap();
+        assert!(!exists);
+    });
+}
+
+#[test]
+fn test_append_with_access_token_without_scope() {
+    run_test(|client, conn, config, _| {
+        let user = factory::user().insert(conn.db);
+
+        let value = model::access_token::AccessToken::generate_token(
+            &id::RandomIdGenerator,
+        );
+        let _ = diesel::insert_into(model::access_token::access_tokens::table)
+            .values((
+                model::access_token::access_tokens::agent_id.eq(user.id),
+                model::access_token::access_tokens::agent_type
+                    .eq(model::access_token::AgentType::Person),
+                model::access_token::access_tokens::name.eq("read only"),
+                model::access_token::access_tokens::state
+                    .eq(model::access_token::AccessTokenState::Enabled),
+                model::access_token::access_tokens::token
+                    .eq(Some(value.as_bytes())),
+                model::access_token::access_tokens::scopes
+                    .eq(vec!["messages:read".to_string()]),
+            ))
+            .execute(conn.db)
+            .unwrap_or_else(|e| panic!("Error inserting: {}", e));
+
+        let data = model::token::TokenData {
+            value,
+            granted_at: Utc::now().timestamp(),
+            expires_at: 0,
+        };
+        let token = model::token::AuthenticationClaims::encode(
+            data,
+            &config.authentication_token_issuer,
+            &config.authentication_token_key_id,
+            &config.authentication_token_secret,
+        );
+
+        let namespace = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&namespace).insert(conn.db);
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = stream.uuid.to_string();
+
+        let res = client
+            .get(format!(
+                "/v1/message/{}/lrange/{}/0/2",
+                namespace_key, stream_slug
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new(
+                "Authorization",
+                format!("Access-Token {}", token),
+            ))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let mut res = client
+            .post(format!(
+                "/v1/message/{}/append/{}",
+                namespace_key, stream_slug
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Access-Token {}", token),
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(
+                r#"{
+                    "agent_id": 1,
+                    "agent_type": "person",
+                    "stream_id": 1,
+                    "code": "200",
+                    "format": "toml",
+                    "title": "New message",
+                    "content": "Hello, world!"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+        assert!(res.body_string().unwrap().contains("not permitted"));
+    });
+}
+
+#[test]
+fn test_hset() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let stream_id = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .returning(model::stream::streams::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let dt = Utc.ymd(2019, 8, 7).and_hms(6, 5, 4);
+        let m = model::message::Message {
+            id: "01DHNAWKYD0000000000000001".to_string(),
+            agent_id: user.id,
+            agent_type: model::message::AgentType::Person,
+            stream_id,
+            code: None,
+            lang: "en".to_string(),
+            level: model::message::LogLevel::Error,
+            format: model::message::LogFormat::TOML,
+            title: "Connection timeout".to_string(),
+            content: None,
+            created_at: dt.naive_utc(),
+            updated_at: dt.naive_utc(),
+            tags: vec!["db".to_string()],
+            deleted_at: None,
+            acknowledged_at: None,
+            resolved_at: None,
+            body_id: None,
+            occurrences_count: 1,
+            trace_id: None,
+            span_id: None,
+            hostname: None,
+            service: None,
+            environment: None,
+        };
+        let _ = diesel::insert_into(model::message::messages::table)
+            .values(&m)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", m));
+
+        let url = format!(
+            "/v1/message/{}/hset/{}/{}",
+            namespace.uuid, s.uuid, m.id
+        );
+
+        // validation
+        let mut res = client
+            .patch(url.clone())
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"resolved": true}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["field_errors"][0]["field"], "updated_at");
+
+        let mut res = client
+            .patch(url.clone())
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(
+                r#"{
+                    "tags": ["db", "outage", "db"],
+                    "resolved": true,
+                    "updated_at": "2019-08-07T06:05:04"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            result["message"]["tags"],
+            serde_json::json!(["db", "outage"])
+        );
+        assert!(result["message"]["resolved_at"].is_string());
+        assert!(result["message"]["acknowledged_at"].is_null());
+        assert_ne!(result["message"]["updated_at"], "2019-08-07T06:05:04");
+
+        // based on the old version
+        let mut res = client
+            .patch(url)
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(
+                r#"{
+                    "acknowledged": true,
+                    "updated_at": "2019-08-07T06:05:04"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Conflict);
+        let body = res.body_string().unwrap();
+        let current: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(current["message"], result["message"]);
+
+        let res = client
+            .patch(format!(
+                "/v1/message/{}/hset/{}/{}",
+                namespace.uuid,
+                s.uuid,
+                "01DHNAWKYD0000000000000002"
+            ))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"updated_at": "2019-08-07T06:05:04"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_unfurl() {
+    run_test(|client, conn, config, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let stream_id = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .returning(model::stream::streams::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let dt = Utc.ymd(2019, 8, 7).and_hms(6, 5, 4);
+        let m = model::message::Message {
+            id: "01DHNAWKYD0000000000000001".to_string(),
+            agent_id: user.id,
+            agent_type: model::message::AgentType::Person,
+            stream_id,
+            code: None,
+            lang: "en".to_string(),
+            level: model::message::LogLevel::Error,
+            format: model::message::LogFormat::TOML,
+            title: "Connection timeout".to_string(),
+            content: Some("timed out\nafter 30s".to_string()),
+            created_at: dt.naive_utc(),
+            updated_at: dt.naive_utc(),
+            tags: vec![],
+            deleted_at: None,
+            acknowledged_at: None,
+            resolved_at: None,
+            body_id: None,
+            occurrences_count: 1,
+            trace_id: None,
+            span_id: None,
+            hostname: None,
+            service: None,
+            environment: None,
+        };
+        let _ = diesel::insert_into(model::message::messages::table)
+            .values(&m)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", m));
+
+        let url = format!(
+            "/v1/message/{}/unfurl/{}/{}",
+            namespace.uuid, s.uuid, m.id
+        );
+
+        // not authenticated
+        let res = client
+            .get(url.clone())
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+
+        // not a member yet
+        let res = client
+            .get(url.clone())
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut res = client
+            .get(url)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let permalink = format!(
+            "{}/message/{}/{}/{}",
+            config.application_url.trim_end_matches('/'),
+            namespace.uuid,
+            s.uuid,
+            m.id
+        );
+        assert_eq!(result["url"], permalink);
+        assert_eq!(result["title"], "Connection timeout");
+        assert_eq!(
+            result["description"],
+            "error at 2019-08-07 06:05:04 UTC: timed out after 30s"
+        );
+        assert_eq!(result["oembed"]["type"], "link");
+        assert_eq!(result["open_graph"]["og:url"], permalink);
+
+        // in another stream
+        let res = client
+            .get(format!(
+                "/v1/message/{}/unfurl/{}/{}",
+                namespace.uuid,
+                Uuid::new_v4(),
+                m.id
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_share() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&ns).insert(conn.db);
+        let now = Utc::now();
+        let ids = factory::message()
+            .stream(&stream)
+            .agent(&user)
+            .between(now - chrono::Duration::minutes(5), now)
+            .count(5)
+            .insert(conn.db);
+
+        let url = format!(
+            "/v1/message/{}/share/{}/{}",
+            ns.uuid, stream.uuid, ids[2]
+        );
+
+        let res = client
+            .post(url.clone())
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"context": 11}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let mut res = client
+            .post(url)
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"expires_in": 3600, "context": 1}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let path = result["share"]["path"].as_str().unwrap();
+
+        // without any authentication
+        let mut res = client.get(format!("/v1{}", path)).dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["message"]["id"], ids[2].as_str());
+        assert_eq!(result["context"]["before"][0]["id"], ids[1].as_str());
+        assert_eq!(result["context"]["after"][0]["id"], ids[3].as_str());
+        assert_eq!(result["context"]["after"].as_array().unwrap().len(), 1);
+
+        // tampered
+        let res = client.get(format!("/v1{}x", path)).dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
     });
 }
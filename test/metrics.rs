@@ -0,0 +1,108 @@
+use rocket::http::{Header, Status};
+use serde_json::Value;
+
+use crate::{admin_token, run_test};
+
+#[test]
+fn test_metrics_from_remote() {
+    run_test(|client, _, _, _| {
+        let res = client
+            .get("/_/metrics")
+            .remote("192.0.2.1:8000".parse().unwrap())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_metrics_without_admin_token() {
+    run_test(|client, _, _, _| {
+        // e.g. via a reverse proxy on the same host
+        let res = client
+            .get("/_/metrics")
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+
+        let res = client
+            .get("/_/metrics")
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .header(Header::new("X-Admin-Token", "invalid"))
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_metrics_pools() {
+    run_test(|client, _, config, _| {
+        let res = client.get("/_/health").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let mut res = client
+            .get("/_/metrics")
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let pools = &result["pools"];
+
+        let db = &pools["database"];
+        assert_eq!(db["max_size"], config.database_max_pool_size);
+        assert!(db["connections"].as_u64().unwrap() >= 1);
+        assert!(db["waits"].is_u64());
+        assert!(db["wait_time_avg"].is_f64());
+
+        let mq = &pools["message_queue"];
+        assert_eq!(mq["max_size"], config.message_queue_max_pool_size);
+        assert!(pools["session_store"]["idle"].is_u64());
+        if config.database_replica_url.is_empty() {
+            assert!(pools["database_replica"].is_null());
+        }
+    });
+}
+
+#[test]
+fn test_metrics_render() {
+    run_test(|client, conn, _, _| {
+        // 80us, 3ms and 200ms (see `service::render_metrics`)
+        let _: () = redis::cmd("HSET")
+            .arg("rt-payload")
+            .arg(&["100", "1", "5000", "1", "inf", "1"])
+            .arg(&["count", "3", "sum", "203080"])
+            .query(conn.ss)
+            .unwrap();
+
+        let mut res = client
+            .get("/_/metrics")
+            .remote("127.0.0.1:8000".parse().unwrap())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let render = &result["render"];
+
+        let template = &render["template"];
+        assert_eq!(template["count"], 0);
+        assert_eq!(template["buckets"][0]["count"], 0);
+
+        let payload = &render["payload"];
+        assert_eq!(payload["count"], 3);
+        assert!((payload["sum"].as_f64().unwrap() - 203.08).abs() < 1e-9);
+        let buckets = payload["buckets"].as_array().unwrap();
+        assert_eq!(buckets.len(), 10);
+        assert!((buckets[0]["le"].as_f64().unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(buckets[0]["count"], 1);
+        assert!((buckets[4]["le"].as_f64().unwrap() - 2.5).abs() < 1e-9);
+        assert_eq!(buckets[4]["count"], 1);
+        assert_eq!(buckets[5]["count"], 2);
+        // the one over the last bound (100ms) is only in the total
+        assert_eq!(buckets[9]["count"], 2);
+    });
+}
@@ -1,20 +1,18 @@
+use chrono::NaiveDate;
 use diesel::{self, prelude::*};
 use rocket::http::{ContentType, Header, Status};
 use serde_json::Value;
 
+use eloquentlog_console_api::factory;
 use eloquentlog_console_api::model;
 
-use crate::{
-    minify, run_test, load_user, make_raw_password, MEMBERSHIPS, NAMESPACES,
-    USERS,
-};
+use crate::{minify, run_test, MEMBERSHIPS, NAMESPACES};
 
 #[test]
 fn test_hget_none_namespace() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -54,9 +52,8 @@ fn test_hget_none_namespace() {
 #[test]
 fn test_hget_namespace() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -94,6 +91,7 @@ fn test_hget_namespace() {
             .unwrap()
             .clone();
         ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
         let _ = diesel::insert_into(model::membership::memberships::table)
             .values(&ms)
             .returning(model::membership::memberships::id)
@@ -113,9 +111,13 @@ fn test_hget_namespace() {
             minify(format!(
                 r#"{{
 "namespace": {{
+  "anomaly_sensitivity": "Medium",
   "archived_at": null,
+  "badge_public": false,
   "created_at": "2019-07-07T07:20:15",
+  "default_tags": [],
   "description": "description",
+  "min_level": null,
   "name": "piano",
   "streams_count": 0,
   "updated_at": "2019-07-07T07:20:15",
@@ -128,12 +130,81 @@ fn test_hget_namespace() {
     });
 }
 
+#[test]
+fn test_hget_namespace_by_legacy_id() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let res = client
+            .get(format!("/v1/namespace/hget/{}", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.headers().get_one("Deprecation"), None);
+
+        // deprecated
+        let res = client
+            .get(format!("/v1/namespace/hget/{}", namespace.id))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.headers().get_one("Deprecation"), Some("@1624752000"));
+    });
+}
+
 #[test]
 fn test_hgetall_no_namespace() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -166,16 +237,15 @@ fn test_hgetall_no_namespace() {
             .dispatch();
 
         assert_eq!(res.status(), Status::Ok);
-        assert!(res.body_string().unwrap().contains("[]"));
+        assert!(res.body_string().unwrap().contains(r#""data":[]"#));
     });
 }
 
 #[test]
 fn test_hgetall_namespaces() {
     run_test(|client, conn, _, _| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let password = make_raw_password(&u);
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
 
         let _ = client
             .head("/_/login/")
@@ -213,6 +283,7 @@ fn test_hgetall_namespaces() {
             .unwrap()
             .clone();
         ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
         let _ = diesel::insert_into(model::membership::memberships::table)
             .values(&ms)
             .returning(model::membership::memberships::id)
@@ -230,19 +301,1156 @@ fn test_hgetall_namespaces() {
         assert_eq!(
             res.body_string().unwrap(),
             minify(format!(
-                r#"[{{
+                r#"{{"data": [{{
 "namespace": {{
+  "anomaly_sensitivity": "Medium",
   "archived_at": null,
+  "badge_public": false,
   "created_at": "2019-07-07T07:20:15",
+  "default_tags": [],
   "description": "description",
+  "min_level": null,
   "name": "piano",
   "streams_count": 0,
   "updated_at": "2019-07-07T07:20:15",
   "uuid": "{}"
 }}
-}}]"#,
+}}],
+"next_cursor": null,
+"total_estimate": 1
+}}"#,
                 namespace.uuid,
             ))
         );
     });
 }
+
+#[test]
+fn test_hgetall_not_modified() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let res = client
+            .get("/v1/namespace/hgetall")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let etag = res.headers().get_one("ETag").unwrap().to_string();
+
+        let mut res = client
+            .get("/v1/namespace/hgetall")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotModified);
+        assert_eq!(res.headers().get_one("ETag"), Some(etag.as_str()));
+        assert!(res.body_string().is_none());
+
+        // updated
+        let _ = diesel::update(model::namespace::namespaces::table)
+            .set(
+                model::namespace::namespaces::updated_at
+                    .eq(NaiveDate::from_ymd(2019, 8, 1).and_hms(0, 0, 0)),
+            )
+            .execute(conn.db)
+            .unwrap();
+
+        let res = client
+            .get("/v1/namespace/hgetall")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert_ne!(res.headers().get_one("ETag"), Some(etag.as_str()));
+    });
+}
+
+#[test]
+fn test_hgetall_paginated() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        for _ in 0..3 {
+            let _ = factory::namespace().with_owner(&user).insert(conn.db);
+        }
+
+        let mut res = client
+            .get("/v1/namespace/hgetall?limit=2")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let etag = res.headers().get_one("ETag").unwrap().to_string();
+        let link = res.headers().get_one("Link").unwrap().to_string();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["data"].as_array().unwrap().len(), 2);
+        assert_eq!(result["total_estimate"], 3);
+        let cursor = result["next_cursor"].as_str().unwrap();
+        assert_eq!(
+            link,
+            format!(
+                r#"</v1/namespace/hgetall?limit=2&cursor={}>; rel="next""#,
+                cursor
+            )
+        );
+
+        // the entity tag of another page doesn't match
+        let mut res = client
+            .get(format!("/v1/namespace/hgetall?limit=2&cursor={}", cursor))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("If-None-Match", etag))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert!(res.headers().get_one("Link").is_none());
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["data"].as_array().unwrap().len(), 1);
+        assert!(result["next_cursor"].is_null());
+
+        for query in &["cursor=***", "limit=0"] {
+            let res = client
+                .get(format!("/v1/namespace/hgetall?{}", query))
+                .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", token),
+                ))
+                .dispatch();
+
+            assert_eq!(res.status(), Status::UnprocessableEntity);
+        }
+    });
+}
+
+#[test]
+fn test_memberships() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let member = factory::user().name("Member").insert(conn.db);
+        let ns = factory::namespace()
+            .with_owner(&user)
+            .with_member(&member)
+            .insert(conn.db);
+
+        let mut res = client
+            .get(format!("/v1/namespace/hget/{}/memberships", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let data = result["data"].as_array().unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0]["membership"]["role"], "primary_owner");
+        assert_eq!(
+            data[0]["membership"]["user"]["uuid"],
+            user.uuid.to_string()
+        );
+        assert_eq!(data[1]["membership"]["role"], "member");
+        assert_eq!(data[1]["membership"]["user"]["name"], "Member");
+        assert_eq!(result["total_estimate"], 2);
+
+        // not a member
+        let other = factory::namespace().insert(conn.db);
+        let res = client
+            .get(format!("/v1/namespace/hget/{}/memberships", other.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_usage() {
+    run_test(|client, conn, _, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let date = NaiveDate::from_ymd(2021, 6, 1);
+        let _ = model::namespace_usage::NamespaceUsage::upsert(
+            namespace.id,
+            date,
+            1,
+            2,
+            3,
+            conn.db,
+            logger,
+        )
+        .unwrap();
+        let _ = model::usage_record::UsageRecord::aggregate(
+            date, conn.db, logger,
+        )
+        .unwrap();
+
+        let mut res = client
+            .get(format!("/v1/namespace/hget/{}/usage", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let record = &result["usage_records"][0];
+        assert_eq!(record["month"], "2021-06-01");
+        assert_eq!(record["api_calls_count"], 1);
+        assert_eq!(record["messages_count"], 2);
+        assert_eq!(record["bytes_count"], 3);
+    });
+}
+
+#[test]
+fn test_usage_by_member() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        ms.role = model::membership::MembershipRole::Member;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let res = client
+            .get(format!("/v1/namespace/hget/{}/usage", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+    });
+}
+
+#[test]
+fn test_retention_preview() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let res = client
+            .get(format!(
+                "/v1/namespace/hget/{}/retention/preview?days=0",
+                ns.uuid
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let mut res = client
+            .get(format!(
+                "/v1/namespace/hget/{}/retention/preview?days=30",
+                ns.uuid
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let preview = &result["retention_preview"];
+        assert_eq!(preview["days"], 30);
+        assert_eq!(preview["messages_count"], 0);
+        assert_eq!(preview["bytes_count"], 0);
+        assert!(preview["oldest_created_at"].is_null());
+    });
+}
+
+#[test]
+fn test_stats() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let res = client
+            .get(format!(
+                "/v1/namespace/hget/{}/stats?interval=week",
+                ns.uuid
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let res = client
+            .get(format!(
+                "/v1/namespace/hget/{}/stats?from=2019-01-01T00:00:00&\
+                 to=2021-01-01T00:00:00",
+                ns.uuid
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let mut res = client
+            .get(format!(
+                "/v1/namespace/hget/{}/stats?from=2019-07-07T00:00:00&\
+                 to=2019-07-09T00:00:00&interval=day",
+                ns.uuid
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let stats = &result["stats"];
+        assert_eq!(stats["interval"], "day");
+        let buckets = stats["buckets"].as_array().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0]["time"], "2019-07-07T00:00:00");
+        assert_eq!(buckets[0]["total"], 0);
+        assert_eq!(buckets[0]["levels"]["error"], 0);
+    });
+}
+
+#[test]
+fn test_del_and_restore() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        // not deleted yet
+        let res = client
+            .post(format!("/v1/namespace/restore/{}", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+
+        let uri = format!("/v1/namespace/del/{}", ns.uuid);
+
+        // not confirmed
+        let mut res = client
+            .delete(&uri)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["field_errors"][0]["field"], "confirmation_token");
+
+        let mut res = client
+            .post(format!("{}/confirm", uri))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let confirmation = result["confirmation_token"]["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let mut res = client
+            .delete(&uri)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Confirmation-Token", confirmation))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["namespace"]["uuid"], ns.uuid.to_string());
+        assert!(!result["namespace"]["archived_at"].is_null());
+        assert!(!result["namespace"]["restorable_until"].is_null());
+
+        let res = client
+            .get(format!("/v1/namespace/hget/{}", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+
+        let mut res = client
+            .post(format!("/v1/namespace/restore/{}", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert!(result["namespace"]["archived_at"].is_null());
+
+        let res = client
+            .get(format!("/v1/namespace/hget/{}", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+    });
+}
+
+#[test]
+fn test_update_min_level() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"min_level": "verbose"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let mut res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"min_level": "warning"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["namespace"]["min_level"], "Warning");
+
+        // not changed
+        let mut res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body("{}")
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["namespace"]["min_level"], "Warning");
+
+        let mut res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"min_level": ""}"#)
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert!(result["namespace"]["min_level"].is_null());
+    });
+}
+
+#[test]
+fn test_update_default_tags() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(format!(r#"{{"default_tags": ["{}"]}}"#, "a".repeat(65)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let mut res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(
+                r#"{"default_tags": [
+                    " environment=staging ", "", "team=payments",
+                    "environment=staging"
+                ]}"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            result["namespace"]["default_tags"],
+            serde_json::json!(["environment=staging", "team=payments"])
+        );
+        assert!(result["namespace"]["min_level"].is_null());
+
+        let mut res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"default_tags": []}"#)
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["namespace"]["default_tags"], serde_json::json!([]));
+    });
+}
+
+#[test]
+fn test_update_anomaly_sensitivity() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = factory::namespace().with_owner(&user).insert(conn.db);
+
+        let res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"anomaly_sensitivity": "extreme"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let mut res = client
+            .patch(format!("/v1/namespace/hset/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"anomaly_sensitivity": "off"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["namespace"]["anomaly_sensitivity"], "Off");
+        assert!(result["namespace"]["min_level"].is_null());
+    });
+}
+
+#[test]
+fn test_transfer_to_non_member() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        // to self
+        let mut res = client
+            .post(format!("/v1/namespace/transfer/{}", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(format!(r#"{{"user": "{}"}}"#, user.uuid))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["field_errors"][0]["field"], "user");
+        assert_eq!(
+            result["field_errors"][0]["messages"][0],
+            "Must be another member of the namespace"
+        );
+
+        // invalid confirmation token
+        let res = client
+            .post(format!("/v1/namespace/transfer/{}/accept", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Confirmation-Token", "invalid"))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::PreconditionRequired);
+    });
+}
+
+#[test]
+fn test_transfer_with_confirmation() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let member = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let namespace = factory::namespace()
+            .with_owner(&user)
+            .with_member(&member)
+            .insert(conn.db);
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let uri = format!("/v1/namespace/transfer/{}", namespace.uuid);
+        let data = format!(r#"{{"user": "{}"}}"#, member.uuid);
+
+        // not confirmed
+        let mut res = client
+            .post(&uri)
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(&data)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["field_errors"][0]["field"], "confirmation_token");
+
+        let mut res = client
+            .post(format!("{}/confirm", uri))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let confirmation = result["confirmation_token"]["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let mut res = client
+            .post(&uri)
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Confirmation-Token", confirmation.clone()))
+            .body(&data)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Accepted);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            result["namespace_transfer"]["user"],
+            member.uuid.to_string()
+        );
+
+        // the token can be used only once
+        let res = client
+            .post(&uri)
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .header(Header::new("X-Confirmation-Token", confirmation))
+            .body(&data)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+    });
+}
@@ -0,0 +1,41 @@
+use rocket::http::{ContentType, Status};
+
+use crate::run_test;
+
+#[test]
+fn test_authorize_with_unknown_provider() {
+    run_test(|client, _, _, _| {
+        let res = client
+            .get("/_/oauth/unknown")
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_authorize_in_community_edition() {
+    run_test(|client, _, config, _| {
+        assert!(config.license_file.is_empty());
+
+        let mut res = client
+            .get("/_/oauth/github")
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+        assert!(res
+            .body_string()
+            .unwrap()
+            .contains("only available in the enterprise edition"));
+
+        let res = client
+            .post("/_/oauth/github/callback")
+            .header(ContentType::JSON)
+            .body(r#"{"code": "code", "state": "state"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Forbidden);
+    });
+}
@@ -2,10 +2,11 @@ use fourche::queue::Queue;
 use rocket::http::{ContentType, Header, Status};
 use rocket::local::Client;
 
+use eloquentlog_console_api::factory;
 use eloquentlog_console_api::model;
 use eloquentlog_console_api::job;
 
-use crate::{run_test, load_user, USERS};
+use crate::run_test;
 
 fn password_reset_request_by(
     user: &model::user::User,
@@ -38,8 +39,7 @@ fn password_reset_request_by(
 #[test]
 fn test_password_reset_with_invalid_token() {
     run_test(|client, conn, _, logger| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
 
         let request = password_reset_request_by(&user, &client);
         assert!(request.is_ok());
@@ -90,8 +90,7 @@ fn test_password_reset_with_invalid_token() {
 #[test]
 fn test_password_reset_with_invalid_session_id() {
     run_test(|client, conn, _, logger| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
 
         let request = password_reset_request_by(&user, &client);
         assert!(request.is_ok());
@@ -143,8 +142,7 @@ fn test_password_reset_with_invalid_session_id() {
 #[test]
 fn test_password_reset_without_authorization_header() {
     run_test(|client, conn, _, logger| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
 
         let request = password_reset_request_by(&user, &client);
         assert!(request.is_ok());
@@ -192,8 +190,7 @@ fn test_password_reset_without_authorization_header() {
 #[test]
 fn test_password_reset_without_x_requested_with_header() {
     run_test(|client, conn, _, logger| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
 
         let request = password_reset_request_by(&user, &client);
         assert!(request.is_ok());
@@ -242,8 +239,7 @@ fn test_password_reset_without_x_requested_with_header() {
 #[test]
 fn test_password_reset() {
     run_test(|client, conn, _, logger| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
 
         let request = password_reset_request_by(&user, &client);
         assert!(request.is_ok());
@@ -286,6 +282,11 @@ fn test_password_reset() {
 
         assert_eq!(res.status(), Status::Ok);
 
+        let job = queue.dequeue::<job::Job<String>>().ok().unwrap();
+        assert_eq!(job.kind, job::JobKind::SendSecurityNotificationEmail);
+        assert_eq!(job.args[0], user.uuid.to_string());
+        assert_eq!(job.args[1], "password_changed");
+
         let result =
             model::user::User::find_by_email(&user.email, conn.db, logger);
         assert!(result.unwrap().reset_password_token.is_some());
@@ -2,16 +2,16 @@ use fourche::queue::Queue;
 use rocket::http::{ContentType, Header, Status};
 use redis::{Commands, RedisError};
 
+use eloquentlog_console_api::factory;
 use eloquentlog_console_api::model;
 use eloquentlog_console_api::job;
 
-use crate::{run_test, load_user, USERS};
+use crate::run_test;
 
 #[test]
 fn test_password_reset_request_with_validation_error() {
     run_test(|client, conn, _, logger| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
 
         let email = "invalid";
 
@@ -44,8 +44,7 @@ fn test_password_reset_request_with_validation_error() {
 #[test]
 fn test_password_reset_request() {
     run_test(|client, conn, _, logger| {
-        let u = USERS.get("oswald").unwrap().clone();
-        let user = load_user(u, conn.db);
+        let user = factory::user().insert(conn.db);
 
         let email = user.email;
 
@@ -0,0 +1,189 @@
+use chrono::{Utc, TimeZone};
+use diesel::{self, prelude::*};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use eloquentlog_console_api::clock;
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::job;
+use eloquentlog_console_api::model;
+
+use crate::{run_test, MEMBERSHIPS, NAMESPACES, STREAMS};
+
+#[test]
+fn test_append_and_del() {
+    run_test(|client, conn, config, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+        let auth = Header::new("Authorization", format!("Bearer {}", token));
+
+        // not authenticated
+        let res = client
+            .get("/v1/push_device/hgetall")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+
+        let res = client
+            .post("/v1/push_device/append")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth.clone())
+            .body(r#"{"platform": "apns", "token": "xyz", "name": "iPhone"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+
+        let mut res = client
+            .post("/v1/push_device/append")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth.clone())
+            .body(
+                r#"{
+                    "platform": "apns",
+                    "token": "0a1b2c",
+                    "name": "iPhone"
+                }"#,
+            )
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["push_device"]["platform"], "apns");
+        assert!(result["push_device"].get("token").is_none());
+        let uuid = result["push_device"]["uuid"].as_str().unwrap().to_string();
+
+        // a critical alert (APNs is not configured in tests)
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .returning(model::membership::memberships::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let stream_id = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .returning(model::stream::streams::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let dt = Utc.ymd(2019, 8, 7).and_hms(6, 5, 4);
+        let m = model::message::Message {
+            id: "01DHNAWKYD0000000000000001".to_string(),
+            agent_id: user.id,
+            agent_type: model::message::AgentType::Person,
+            stream_id,
+            code: None,
+            lang: "en".to_string(),
+            level: model::message::LogLevel::Critical,
+            format: model::message::LogFormat::TOML,
+            title: "Disk full".to_string(),
+            content: None,
+            created_at: dt.naive_utc(),
+            updated_at: dt.naive_utc(),
+            tags: vec![],
+            deleted_at: None,
+            acknowledged_at: None,
+            resolved_at: None,
+            body_id: None,
+            occurrences_count: 1,
+            trace_id: None,
+            span_id: None,
+            hostname: None,
+            service: None,
+            environment: None,
+        };
+        let _ = diesel::insert_into(model::message::messages::table)
+            .values(&m)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", m));
+
+        let job = job::Job::<String> {
+            kind: job::JobKind::SendPushNotification,
+            args: vec![user.uuid.to_string(), s.uuid.to_string(), m.id],
+        };
+        job.invoke(conn.db, config, &clock::SystemClock, logger);
+
+        let mut res = client
+            .get(format!("/v1/push_device/hget/{}/deliveries", uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth.clone())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let deliveries = result["deliveries"].as_array().unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0]["delivered"].as_bool(), Some(false));
+        assert_eq!(deliveries[0]["error"], "apns is disabled");
+
+        let res = client
+            .delete(format!("/v1/push_device/del/{}", uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth.clone())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let mut res = client
+            .get("/v1/push_device/hgetall")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth.clone())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert!(result.as_array().unwrap().is_empty());
+
+        let res = client
+            .delete(format!("/v1/push_device/del/{}", uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
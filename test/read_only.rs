@@ -0,0 +1,73 @@
+use std::env;
+use std::net::SocketAddr;
+
+use rocket::http::{ContentType, Status};
+use rocket::local::Client;
+use serde_json::Value;
+
+use crate::{admin_token, run_test};
+
+fn localhost() -> SocketAddr {
+    "127.0.0.1:8000".parse().unwrap()
+}
+
+// switches it by reloading the config
+fn reload(client: &Client, read_only: bool) {
+    let key = "TEST_READ_ONLY";
+    let origin = env::var(key);
+    env::set_var(key, read_only.to_string());
+
+    let mut res = client
+        .post("/_/config/reload")
+        .remote(localhost())
+        .header(admin_token())
+        .dispatch();
+
+    match origin {
+        Ok(v) => env::set_var(key, v),
+        Err(_) => env::remove_var(key),
+    }
+    assert_eq!(res.status(), Status::Ok);
+
+    let body = res.body_string().unwrap();
+    let result: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(result["config"]["read_only"], read_only);
+}
+
+#[test]
+fn test_read_only() {
+    run_test(|client, _, _, _| {
+        reload(client, true);
+
+        let join = || {
+            client
+                .post("/_/waitlist")
+                .header(ContentType::JSON)
+                .body(r#"{"email": "johnny@example.org"}"#)
+                .dispatch()
+        };
+
+        let mut res = join();
+        assert_eq!(res.status(), Status::ServiceUnavailable);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["code"], "read_only");
+
+        // reads keep working
+        let res = client.get("/v1/health").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        // login may write (e.g. the rehash of the password)
+        let res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .body(r#"{"username": "johnny", "password": "pa$$w0rD"}"#)
+            .dispatch();
+        assert_eq!(res.status(), Status::ServiceUnavailable);
+
+        reload(client, false);
+
+        assert_eq!(join().status(), Status::Ok);
+    });
+}
@@ -0,0 +1,222 @@
+use std::thread;
+use std::time::Duration;
+
+use diesel::{self, prelude::*};
+use chrono::{Utc, TimeZone};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use eloquentlog_console_api::clock;
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::job;
+use eloquentlog_console_api::model;
+
+use crate::{run_test, MEMBERSHIPS, NAMESPACES, STREAMS};
+
+#[test]
+fn test_lrange_no_recent_view() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let mut res = client
+            .get("/v1/recent_view/lrange/0/9")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.body_string().unwrap(), "[]");
+    });
+}
+
+#[test]
+fn test_lrange_recent_views() {
+    run_test(|client, conn, config, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = NAMESPACES.get("piano").unwrap();
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(ns)
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|_| panic!("Error inserting: {}", ns));
+
+        let mut ms = MEMBERSHIPS
+            .get("oswald as a primary owner")
+            .unwrap()
+            .clone();
+        ms.namespace_id = namespace.id;
+        ms.user_id = user.id;
+        let _ = diesel::insert_into(model::membership::memberships::table)
+            .values(&ms)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", ms));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let stream_id = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .returning(model::stream::streams::id)
+            .get_result::<i64>(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", s));
+
+        let dt = Utc.ymd(2019, 8, 7).and_hms_milli(6, 5, 4, 333);
+        let m = model::message::Message {
+            id: "01DHNAWKYD0000000000000001".to_string(),
+            agent_id: user.id,
+            agent_type: model::message::AgentType::Person,
+            stream_id,
+            code: None,
+            lang: "en".to_string(),
+            level: model::message::LogLevel::Error,
+            format: model::message::LogFormat::TOML,
+            title: "Connection timeout".to_string(),
+            content: None,
+            created_at: dt.naive_utc(),
+            updated_at: dt.naive_utc(),
+            tags: vec![],
+            deleted_at: None,
+            acknowledged_at: None,
+            resolved_at: None,
+            body_id: None,
+            occurrences_count: 1,
+            trace_id: None,
+            span_id: None,
+            hostname: None,
+            service: None,
+            environment: None,
+        };
+
+        let _ = diesel::insert_into(model::message::messages::table)
+            .values(&m)
+            .execute(conn.db)
+            .unwrap_or_else(|_| panic!("Error inserting: {}", m));
+
+        let namespace_key = namespace.uuid.to_string();
+        let stream_slug = s.uuid.to_string();
+
+        let mut res = client
+            .get(format!(
+                "/v1/message/{}/hget/{}/{}",
+                namespace_key, stream_slug, m.id
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["message"]["title"], "Connection timeout");
+
+        // views are ordered in milliseconds
+        thread::sleep(Duration::from_millis(5));
+
+        let res = client
+            .get(format!(
+                "/v1/message/{}/search/{}/0/9?q=timeout",
+                namespace_key, stream_slug
+            ))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        // not flushed yet
+        let mut res = client
+            .get("/v1/recent_view/lrange/0/9")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 2);
+        assert_eq!(result[0]["recent_view"]["kind"], "search");
+        assert_eq!(
+            result[0]["recent_view"]["target"],
+            format!("{}/{}/timeout", namespace_key, stream_slug)
+        );
+        assert_eq!(result[1]["recent_view"]["kind"], "message");
+        assert_eq!(
+            result[1]["recent_view"]["target"],
+            format!("{}/{}/{}", namespace_key, stream_slug, m.id)
+        );
+
+        let job = job::Job::<String> {
+            kind: job::JobKind::FlushRecentViews,
+            args: vec![],
+        };
+        job.invoke(conn.db, config, &clock::SystemClock, logger);
+
+        let views = model::recent_view::RecentView::find_all_by_user_id(
+            user.id, 0, 10, conn.db, logger,
+        )
+        .unwrap();
+        assert_eq!(views.len(), 2);
+
+        let mut res = client
+            .get("/v1/recent_view/lrange/0/0")
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 1);
+        assert_eq!(result[0]["recent_view"]["kind"], "search");
+    });
+}
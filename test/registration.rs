@@ -1,6 +1,7 @@
 use fourche::queue::Queue;
 use rocket::http::{ContentType, Header, Status};
 use redis::{Commands, RedisError};
+use serde_json::Value;
 
 use eloquentlog_console_api::model;
 use eloquentlog_console_api::job;
@@ -83,3 +84,35 @@ fn test_register() {
         assert!(result.is_ok());
     });
 }
+
+#[test]
+fn test_register_with_email_suggestion() {
+    run_test(|client, _, _, _| {
+        let _ = client
+            .head("/_/register")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/register")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(
+                r#"{
+                  "email": "hennry@gamil.com",
+                  "username": "hennry",
+                  "password": "pa$$w0rD"
+                }"#,
+            )
+            .dispatch();
+
+        // it's just a hint
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["suggestion"], "hennry@gmail.com");
+    });
+}
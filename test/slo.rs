@@ -0,0 +1,55 @@
+use std::net::SocketAddr;
+
+use rocket::http::Status;
+use serde_json::Value;
+
+use crate::{admin_token, run_test};
+
+fn localhost() -> SocketAddr {
+    "127.0.0.1:8000".parse().unwrap()
+}
+
+#[test]
+fn test_slo_from_remote() {
+    run_test(|client, _, _, _| {
+        let res = client
+            .get("/_/slo")
+            .remote("192.0.2.1:8000".parse().unwrap())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_slo_burn_rates() {
+    run_test(|client, _, config, _| {
+        for _ in 0..3 {
+            let res = client.get("/_/health").dispatch();
+            assert_eq!(res.status(), Status::Ok);
+        }
+        // preflight requests are not counted
+        let _ = client.options("/v1/health").dispatch();
+
+        let mut res = client
+            .get("/_/slo")
+            .remote(localhost())
+            .header(admin_token())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let rates = result["slo"]["burn_rates"].as_array().unwrap();
+        assert_eq!(rates.len(), config.slo_windows.len());
+        for (rate, window) in rates.iter().zip(config.slo_windows.iter()) {
+            assert_eq!(rate["window"], *window);
+            assert_eq!(rate["requests"], 3);
+            assert_eq!(rate["errors"], 0);
+            let availability = rate["availability"].as_f64().unwrap();
+            assert!(availability.abs() < f64::EPSILON);
+        }
+        assert_eq!(result["slo"]["burning"].as_bool(), Some(false));
+    });
+}
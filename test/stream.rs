@@ -0,0 +1,127 @@
+use std::net::SocketAddr;
+
+use diesel::{self, prelude::*};
+use redis::Commands;
+use rocket::http::Status;
+use serde_json::Value;
+
+use eloquentlog_console_api::model;
+
+use crate::{admin_token, run_test, NAMESPACES, STREAMS};
+
+fn localhost() -> SocketAddr {
+    "127.0.0.1:8000".parse().unwrap()
+}
+
+#[test]
+fn test_stream_buffer_from_remote() {
+    run_test(|client, conn, _, _| {
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let stream = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .get_result::<model::stream::Stream>(conn.db)
+            .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+        let res = client
+            .get(format!("/_/stream/{}/buffer", stream.uuid))
+            .remote("192.0.2.1:8000".parse().unwrap())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+
+        let res = client
+            .post(format!("/_/stream/{}/flush", stream.uuid))
+            .remote("192.0.2.1:8000".parse().unwrap())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_stream_buffer_unknown_stream() {
+    run_test(|client, _, _, _| {
+        let res = client
+            .get("/_/stream/00000000-0000-0000-0000-000000000000/buffer")
+            .remote(localhost())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+    });
+}
+
+#[test]
+fn test_stream_buffer_flush() {
+    run_test(|client, conn, _, _| {
+        let namespace =
+            diesel::insert_into(model::namespace::namespaces::table)
+                .values(NAMESPACES.get("piano").unwrap())
+                .get_result::<model::namespace::Namespace>(conn.db)
+                .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+        let mut s = STREAMS.get("oswald's stream").unwrap().clone();
+        s.namespace_id = namespace.id;
+        let stream = diesel::insert_into(model::stream::streams::table)
+            .values(&s)
+            .get_result::<model::stream::Stream>(conn.db)
+            .unwrap_or_else(|e| panic!("Error at inserting: {}", e));
+
+        // an entry pushed by an ingestion (see service::stream_buffer)
+        let id = "01F9DKJ8M00000000000000001";
+        let entry = serde_json::json!({
+            "id": id,
+            "agent_id": 1,
+            "agent_type": "client",
+            "stream_id": stream.id,
+            "code": null,
+            "lang": "en",
+            "level": "information",
+            "format": "toml",
+            "title": "title",
+            "content": null,
+            "created_at": "2021-06-30T09:00:00",
+        });
+        let _: i64 = conn
+            .ss
+            .rpush(format!("sb-{}", stream.id), entry.to_string())
+            .unwrap();
+
+        let mut res = client
+            .get(format!("/_/stream/{}/buffer", stream.uuid))
+            .remote(localhost())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["buffer"]["pending"], 1);
+        assert!(result["buffer"]["oldest_age"].is_number());
+
+        let mut res = client
+            .post(format!("/_/stream/{}/flush", stream.uuid))
+            .remote(localhost())
+            .header(admin_token())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["buffer"]["flushed"], 1);
+        assert_eq!(result["buffer"]["pending"], 0);
+
+        let count: i64 = model::message::messages::table
+            .filter(model::message::messages::id.eq(id))
+            .count()
+            .first(conn.db)
+            .expect("Failed to count rows");
+        assert_eq!(count, 1);
+    });
+}
@@ -0,0 +1,152 @@
+use diesel::prelude::*;
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+use uuid::Uuid;
+
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::model;
+
+use crate::run_test;
+
+#[test]
+fn test_ingest_with_stream_token() {
+    run_test(|client, conn, _, _| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+
+        let ns = factory::namespace().with_owner(&user).insert(conn.db);
+        let stream = factory::stream().namespace(&ns).insert(conn.db);
+
+        let mut res = client
+            .post(format!("/v1/stream_token/{}/append", ns.uuid))
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body(r#"{"name": "fluent-bit", "service": " api "}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let uuid = result["stream_token"]["uuid"].as_str().unwrap().to_string();
+        let raw = result["stream_token"]["token"].as_str().unwrap().to_string();
+        assert_eq!(result["stream_token"]["service"], "api");
+        assert!(result["stream_token"]["hostname"].is_null());
+
+        let body = r#"{
+            "agent_id": 1,
+            "stream_id": 1,
+            "title": "New message",
+            "content": "Hello, world!"
+        }"#;
+        let ingest_into = |namespace_key: &str, slug: &str, value: &str| {
+            client
+                .post(format!("/v1/message/{}/ingest/{}", namespace_key, slug))
+                .header(ContentType::JSON)
+                .header(Header::new(
+                    "Authorization",
+                    format!("Stream-Token {}", value),
+                ))
+                .body(body)
+                .dispatch()
+                .status()
+        };
+        let slug = stream.uuid.to_string();
+        let ingest = |namespace_key: &str, value: &str| {
+            ingest_into(namespace_key, &slug, value)
+        };
+
+        let key = ns.uuid.to_string();
+        assert_eq!(ingest(&key, &raw), Status::Ok);
+        let service = model::message::messages::table
+            .select(model::message::messages::service)
+            .first::<Option<String>>(conn.db)
+            .unwrap();
+        assert_eq!(service, Some("api".to_string()));
+        let other = Uuid::new_v4().to_string();
+        assert_eq!(ingest(&other, &raw), Status::Forbidden);
+        assert_eq!(ingest(&key, "unknown"), Status::Unauthorized);
+
+        // a stream of another namespace
+        let other_ns = factory::namespace().insert(conn.db);
+        let other_stream =
+            factory::stream().namespace(&other_ns).insert(conn.db);
+        let other_slug = other_stream.uuid.to_string();
+        assert_eq!(ingest_into(&key, &other_slug, &raw), Status::NotFound);
+        let other_key = other_ns.uuid.to_string();
+        assert_eq!(
+            ingest_into(&other_key, &other_slug, &raw),
+            Status::Forbidden
+        );
+        let count = model::message::messages::table
+            .filter(model::message::messages::stream_id.eq(other_stream.id))
+            .count()
+            .get_result::<i64>(conn.db)
+            .unwrap();
+        assert_eq!(count, 0);
+
+        // the raw value is not listed
+        let mut res = client
+            .get(format!("/v1/stream_token/{}/hgetall", ns.uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["data"][0]["stream_token"]["uuid"], uuid);
+        assert!(result["data"][0]["stream_token"]["token"].is_null());
+        assert_eq!(result["total_estimate"], 1);
+
+        let mut res = client
+            .patch(format!("/v1/stream_token/{}/dump/{}", ns.uuid, uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let rotated = result["stream_token"]["token"].as_str().unwrap();
+        assert_ne!(rotated, raw);
+        assert_eq!(ingest(&key, &raw), Status::Unauthorized);
+        assert_eq!(ingest(&key, rotated), Status::Ok);
+
+        let res = client
+            .delete(format!("/v1/stream_token/{}/del/{}", ns.uuid, uuid))
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(ingest(&key, rotated), Status::Unauthorized);
+    });
+}
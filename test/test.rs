@@ -6,9 +6,11 @@ extern crate lazy_static;
 extern crate chrono;
 extern crate diesel;
 extern crate dotenv;
+extern crate flate2;
 extern crate fourche;
 extern crate fnv;
 extern crate parking_lot;
+extern crate prost;
 extern crate redis;
 extern crate rocket;
 extern crate rocket_slog;
@@ -19,46 +21,62 @@ extern crate uuid;
 extern crate eloquentlog_console_api;
 
 mod activation;
+mod app;
 mod authentication;
+mod config_reload;
 mod error;
+mod fault;
+mod flow;
 mod health;
+mod maintenance;
+mod metrics;
+mod oauth;
 mod registration;
 mod password_reset;
 mod password_reset_request;
+mod read_only;
+mod slo;
+mod stream;
+mod waitlist;
 
 mod access_token;
+mod alert_schedule;
+mod analytics;
+mod badge;
+mod bulk_operation;
+mod channel;
+mod incident;
 mod message;
 mod namespace;
+mod push_device;
+mod recent_view;
+mod stream_token;
+mod user;
 
 use std::panic::{self, AssertUnwindSafe};
+use std::sync::RwLock;
 use regex::Regex;
 
 use diesel::PgConnection;
-use diesel::prelude::*;
 use dotenv::dotenv;
 use chrono::{Utc, TimeZone};
 use fnv::FnvHashMap;
-use parking_lot::Mutex;
+use rocket::http::Header;
 use rocket::local::Client;
 use rocket_slog::SlogFairing;
 use uuid::Uuid;
 
 use eloquentlog_console_api::server;
+use eloquentlog_console_api::clock;
 use eloquentlog_console_api::db;
+use eloquentlog_console_api::id;
 use eloquentlog_console_api::mq;
 use eloquentlog_console_api::ss;
 use eloquentlog_console_api::config;
+use eloquentlog_console_api::license;
 use eloquentlog_console_api::logger;
 use eloquentlog_console_api::model;
-
-// NOTE:
-// For now, run tests sequencially :'(
-// The usage of transactions for the same connection between tests and
-// client (server) might fix this issue, but we use connection pool.
-// Find another way.
-lazy_static! {
-    static ref DB_LOCK: Mutex<()> = Mutex::new(());
-}
+use eloquentlog_console_api::testing::{RedisDatabase, TestDatabase};
 
 lazy_static! {
     static ref RE: Regex = Regex::new(r"\n\s{2}|\n|(:)\s").unwrap();
@@ -66,26 +84,13 @@ lazy_static! {
         dotenv().ok();
         config::Config::from("testing").unwrap()
     };
-    static ref DB_POOL_HOLDER: db::DbPoolHolder = {
-        db::init_pool_holder(
-            &CONFIG.database_url,
-            CONFIG.database_max_pool_size,
-        )
-    };
-    static ref MQ_POOL_HOLDER: mq::MqPoolHolder = {
-        mq::init_pool_holder(
-            &CONFIG.message_queue_url,
-            CONFIG.message_queue_max_pool_size,
-        )
-    };
-    static ref SS_POOL_HOLDER: ss::SsPoolHolder = {
-        ss::init_pool_holder(
-            &CONFIG.session_store_url,
-            CONFIG.session_store_max_pool_size,
-        )
-    };
 }
 
+// each test has its own pools (the test itself holds a connection of each)
+const DATABASE_MAX_POOL_SIZE: u32 = 4;
+const MESSAGE_QUEUE_MAX_POOL_SIZE: u32 = 3;
+const SESSION_STORE_MAX_POOL_SIZE: u32 = 3;
+
 pub struct Connection<'a> {
     db: &'a PgConnection,
     mq: &'a mut redis::Connection,
@@ -97,16 +102,46 @@ pub fn minify(s: String) -> String {
     RE.replace_all(&s, "$1").to_string()
 }
 
+/// The header for the local-only endpoints (see `request::local_only`)
+pub fn admin_token() -> Header<'static> {
+    Header::new("X-Admin-Token", CONFIG.server_admin_token.clone())
+}
+
 /// A test runner for integration tests
+///
+/// Each test runs against its own database and Redis db (see
+/// `testing::TestDatabase` and `testing::RedisDatabase`), which are removed
+/// after it, so that tests can run in parallel.
 pub fn run_test<T>(test: T)
 where T: FnOnce(&Client, &mut Connection, &config::Config, &logger::Logger)
         + panic::UnwindSafe {
-    let _lock = DB_LOCK.lock();
-
-    // Use same connection pools across tests
-    let db_conn = get_db_conn(&DB_POOL_HOLDER.clone());
-    let mut mq_conn = get_mq_conn(&MQ_POOL_HOLDER.clone());
-    let mut ss_conn = get_ss_conn(&SS_POOL_HOLDER.clone());
+    let database = TestDatabase::create(&CONFIG.database_connection_url());
+    let redis = RedisDatabase::lease(&[
+        &CONFIG.message_queue_connection_url(),
+        &CONFIG.session_store_connection_url(),
+    ]);
+    let config = config_for(&database, &redis);
+
+    let db_pool_holder = db::init_pool_holder(
+        &config.database_connection_url(),
+        &config.database_pool(),
+    );
+    let db_replica_pool_holder = db::init_replica_pool_holder(
+        &config.database_replica_connection_url(),
+        &config.database_pool(),
+    );
+    let mq_pool_holder = mq::init_pool_holder(
+        &config.message_queue_connection_url(),
+        &config.message_queue_pool(),
+    );
+    let ss_pool_holder = ss::init_pool_holder(
+        &config.session_store_connection_url(),
+        config.session_store_max_pool_size,
+    );
+
+    let db_conn = get_db_conn(&db_pool_holder);
+    let mut mq_conn = get_mq_conn(&mq_pool_holder);
+    let mut ss_conn = get_ss_conn(&ss_pool_holder);
 
     let mut conn = Connection {
         db: &db_conn,
@@ -114,56 +149,44 @@ where T: FnOnce(&Client, &mut Connection, &config::Config, &logger::Logger)
         ss: &mut ss_conn,
     };
 
-    let logger = logger::get_logger(&CONFIG);
-    setup(&mut conn);
+    let logger = logger::get_logger(&config);
 
     let result = panic::catch_unwind(AssertUnwindSafe(|| {
-        let server = server()
+        let server = server(&config)
             .attach(SlogFairing::new(logger.clone()))
-            .manage(DB_POOL_HOLDER.clone())
-            .manage(MQ_POOL_HOLDER.clone())
-            .manage(SS_POOL_HOLDER.clone())
-            .manage(CONFIG.clone());
+            .manage(db_pool_holder.clone())
+            .manage(db_replica_pool_holder.clone())
+            .manage(mq_pool_holder.clone())
+            .manage(ss_pool_holder.clone())
+            .manage(RwLock::new(config::DynamicConfig::from(&config)))
+            .manage(clock::SystemClock::shared())
+            .manage(id::RandomIdGenerator::shared())
+            .manage(config.clone())
+            .manage(license::License::load(&config).unwrap());
         let client = Client::new(server).unwrap();
 
-        test(&client, &mut conn, &CONFIG, &logger)
+        test(&client, &mut conn, &config, &logger)
     }));
     assert!(result.is_ok());
-
-    teardown(&mut conn);
-}
-
-fn setup(conn: &mut Connection) {
-    clean(conn);
-}
-
-fn teardown(conn: &mut Connection) {
-    clean(conn);
 }
 
-fn clean(conn: &mut Connection) {
-    redis::cmd("FLUSHDB").execute(conn.mq);
-    redis::cmd("FLUSHDB").execute(conn.ss);
-
-    // Postgres >= 9.5
-    let q = r#"
-DO $func$
-BEGIN
-  EXECUTE (
-    SELECT
-      'TRUNCATE TABLE ' || string_agg(oid::regclass::text, ', ') || ' CASCADE'
-    FROM
-      pg_class
-    WHERE
-      relkind = 'r' AND
-      relnamespace = 'public'::regnamespace AND
-      oid::regclass::text != '__diesel_schema_migrations'
-  );
-END $func$;
-            "#;
-    let _ = diesel::sql_query(q)
-        .execute(conn.db)
-        .expect("Failed to delete");
+// the testing config with the database and the Redis db of a test
+fn config_for(
+    database: &TestDatabase,
+    redis: &RedisDatabase,
+) -> config::Config {
+    let mut c = CONFIG.clone();
+    c.database_url = database.url.clone();
+    if !c.database_replica_url.is_empty() {
+        c.database_replica_url = database.url.clone();
+    }
+    c.message_queue_url = redis.url(&c.message_queue_url);
+    c.session_store_url = redis.url(&c.session_store_url);
+
+    c.database_max_pool_size = DATABASE_MAX_POOL_SIZE;
+    c.message_queue_max_pool_size = MESSAGE_QUEUE_MAX_POOL_SIZE;
+    c.session_store_max_pool_size = SESSION_STORE_MAX_POOL_SIZE;
+    c
 }
 
 // TODO: move these function into {db,mq,ss}.rs.
@@ -189,7 +212,6 @@ pub fn get_ss_conn(holder: &ss::SsPoolHolder) -> ss::SsConn {
 
 type NamespaceFixture = FnvHashMap<&'static str, model::namespace::Namespace>;
 type StreamFixture = FnvHashMap<&'static str, model::stream::Stream>;
-type UserFixture = FnvHashMap<&'static str, model::user::User>;
 type MembershipFixture =
     FnvHashMap<&'static str, model::membership::Membership>;
 
@@ -216,23 +238,11 @@ lazy_static! {
             archived_at: None,
             created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
             updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
-        }
-    };
-    pub static ref USERS: UserFixture = fnvhashmap! {
-        "oswald" => model::user::User {
-            id: 1,
-            uuid: Uuid::new_v4(),
-            name: Some("Oswald".to_string()),
-            username: "oswald".to_string(),
-            email: "oswald@example.org".to_string(),
-            password: b"Pa$$w0rd".to_vec(),
-            state: model::user::UserState::Active,
-            reset_password_state: model::user::UserResetPasswordState::Never,
-            reset_password_token: None,
-            reset_password_token_expires_at: None,
-            reset_password_token_granted_at: None,
-            created_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
-            updated_at: Utc.ymd(2019, 7, 7).and_hms(7, 20, 15).naive_utc(),
+            deduplicates_messages: false,
+            min_level: None,
+            default_tags: vec![],
+            anomaly_sensitivity: model::namespace::AnomalySensitivity::Medium,
+            badge_public: false,
         }
     };
     pub static ref MEMBERSHIPS: MembershipFixture = fnvhashmap! {
@@ -247,45 +257,3 @@ lazy_static! {
         }
     };
 }
-
-// test utils
-
-fn load_user(
-    mut user: model::user::User,
-    db_conn: &PgConnection,
-) -> model::user::User {
-    user.change_password(&make_raw_password(&user));
-
-    let result: Result<model::user::User, diesel::result::Error> = db_conn
-        .build_transaction()
-        .run::<model::user::User, diesel::result::Error, _>(|| {
-            let q = diesel::insert_into(model::user::users::table).values(user);
-            user = q
-                .get_result::<model::user::User>(db_conn)
-                .unwrap_or_else(|e| panic!("e: {}", e));
-
-            let q = diesel::insert_into(model::user_email::user_emails::table)
-                .values((
-                    model::user_email::user_emails::user_id.eq(&user.id),
-                    Some(model::user_email::user_emails::email.eq(&user.email)),
-                    model::user_email::user_emails::role
-                        .eq(model::user_email::UserEmailRole::Primary),
-                    model::user_email::user_emails::identification_state.eq(
-                        model::user_email::UserEmailIdentificationState::Done,
-                    ),
-                ));
-            let _ = q
-                .get_result::<model::user_email::UserEmail>(db_conn)
-                .unwrap_or_else(|e| panic!("e: {}", e));
-
-            Ok(user)
-        });
-    result.unwrap()
-}
-
-/// Creates raw password string.
-///
-/// It works only in test because USERS has `password` as dummy `Vec<u8>`.
-fn make_raw_password(user: &model::user::User) -> String {
-    user.password.iter().map(|c| *c as char).collect()
-}
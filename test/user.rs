@@ -0,0 +1,114 @@
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use eloquentlog_console_api::factory;
+use eloquentlog_console_api::model::notification_preference::NotificationPreference;
+
+use crate::run_test;
+
+#[test]
+fn test_notifications() {
+    run_test(|client, conn, _, logger| {
+        let user = factory::user().insert(conn.db);
+        let password = factory::PASSWORD;
+
+        let _ = client
+            .head("/_/login/")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body("{}")
+            .dispatch();
+
+        let mut res = client
+            .post("/_/login")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(format!(
+                r#"{{
+                    "username": "{}",
+                    "password": "{}"
+                }}"#,
+                user.email, password,
+            ))
+            .dispatch();
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let token = result["token"].as_str().unwrap();
+        let auth = || {
+            Header::new("Authorization", format!("Bearer {}", token))
+        };
+
+        let data = r#"{
+            "alert": {
+                "channel": "webhook",
+                "webhook_url": "https://example.org/hook"
+            },
+            "security": {
+                "channel": "none"
+            }
+        }"#;
+
+        // not authenticated
+        let res = client
+            .patch("/_api/v1/user/notifications")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .body(data)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::NotFound);
+
+        let mut res = client
+            .get("/_api/v1/user/notifications")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth())
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["notifications"]["alert"]["channel"], "email");
+
+        let mut res = client
+            .patch("/_api/v1/user/notifications")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth())
+            .body(data)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let body = res.body_string().unwrap();
+        let result: Value = serde_json::from_str(&body).unwrap();
+        let notifications = &result["notifications"];
+        assert_eq!(notifications["alert"]["channel"], "webhook");
+        assert_eq!(
+            notifications["alert"]["webhook_url"],
+            "https://example.org/hook"
+        );
+        assert_eq!(notifications["quota"]["channel"], "email");
+        assert_eq!(notifications["security"]["channel"], "none");
+        assert!(notifications["security"]["webhook_url"].is_null());
+
+        let preferences = NotificationPreference::find_all_by_user_id(
+            user.id, conn.db, logger,
+        )
+        .unwrap();
+        assert_eq!(preferences.len(), 2);
+
+        // invalid channel
+        let res = client
+            .patch("/_api/v1/user/notifications")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Requested-With", "XMLHttpRequest"))
+            .header(auth())
+            .body(r#"{"quota": {"channel": "sms"}}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+    });
+}
@@ -0,0 +1,103 @@
+use fourche::queue::Queue;
+use redis::Commands;
+use rocket::http::{ContentType, Status};
+
+use eloquentlog_console_api::job;
+
+use crate::run_test;
+
+#[test]
+fn test_join_with_validation_errors() {
+    run_test(|client, _, _, _| {
+        let mut res = client
+            .post("/_/waitlist")
+            .header(ContentType::JSON)
+            .body(r#"{"email": "this-is-not-email"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+        assert!(res.body_string().unwrap().contains("field_errors"));
+    });
+}
+
+#[test]
+fn test_join_and_confirm() {
+    run_test(|client, conn, _, _| {
+        let res = client
+            .post("/_/waitlist")
+            .header(ContentType::JSON)
+            .body(r#"{"email": "johnny@example.org"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        let mut queue = Queue::new("default", conn.mq);
+        let job = queue.dequeue::<job::Job<String>>().ok().unwrap();
+
+        assert_eq!(job.kind, job::JobKind::SendWaitlistConfirmationEmail);
+        assert_eq!(job.args.len(), 2);
+
+        let token = job.args[1].to_string();
+
+        let res = client
+            .patch(format!("/_/waitlist/confirm/{}", token))
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::Ok);
+
+        // already used
+        let res = client
+            .patch(format!("/_/waitlist/confirm/{}", token))
+            .header(ContentType::JSON)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::BadRequest);
+    });
+}
+
+#[test]
+fn test_join_twice() {
+    run_test(|client, conn, _, _| {
+        for _ in 0..2 {
+            let res = client
+                .post("/_/waitlist")
+                .header(ContentType::JSON)
+                .body(r#"{"email": "johnny@example.org"}"#)
+                .dispatch();
+
+            assert_eq!(res.status(), Status::Ok);
+        }
+
+        // the second one is not sent within the interval
+        let len: i64 = conn.mq.llen("default").unwrap();
+        assert_eq!(len, 1);
+
+        let mut queue = Queue::new("default", conn.mq);
+        let job = queue.dequeue::<job::Job<String>>().ok().unwrap();
+        assert_eq!(job.kind, job::JobKind::SendWaitlistConfirmationEmail);
+    });
+}
+
+#[test]
+fn test_join_rate_limit() {
+    run_test(|client, _, config, _| {
+        for i in 0..config.rate_limit_waitlist_per_minute {
+            let res = client
+                .post("/_/waitlist")
+                .header(ContentType::JSON)
+                .body(format!(r#"{{"email": "johnny{}@example.org"}}"#, i))
+                .dispatch();
+            assert_eq!(res.status(), Status::Ok);
+        }
+
+        let res = client
+            .post("/_/waitlist")
+            .header(ContentType::JSON)
+            .body(r#"{"email": "johnny@example.org"}"#)
+            .dispatch();
+
+        assert_eq!(res.status(), Status::TooManyRequests);
+        assert!(res.headers().get_one("Retry-After").is_some());
+    });
+}